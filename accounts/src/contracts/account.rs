@@ -0,0 +1,201 @@
+//! Account contract: a balance with a stable account ID and a sequence number.
+//!
+//! The account contract carries `[balance, account ID, sequence, account predicate]`.
+//! Its predicate is a predicate tree with an unsignable inner key and a single `spend` program,
+//! so every spend is a state transition:
+//!
+//! 1. `spend` expects the next sequence number below the contract, checks that it equals
+//!    the current sequence plus one, requires the owner's signature and leaves the balance
+//!    on the stack together with a continuation contract `[account ID, sequence, account predicate]`.
+//! 2. The continuation contract is locked by a fixed `relock` program that expects
+//!    the new balance below it and locks it back under the account predicate.
+//!
+//! Since contracts cannot be dropped, the transaction is only valid if the owner re-locks
+//! a new balance under the same account ID with the incremented sequence number.
+//! This lets indexers track the state of the account by its ID, and lets payments
+//! reference a persistent identity instead of one-time keys.
+//!
+//! The program cannot refer to its own predicate, so the contract carries it in the payload.
+
+use curve25519_dalek::scalar::Scalar;
+use merlin::Transcript;
+use serde::{Deserialize, Serialize};
+use zkvm::{
+    Anchor, ClearValue, Commitment, Contract, PortableItem, Predicate, PredicateTree, Program,
+    String, VMError, Value, VerificationKey,
+};
+
+/// Stable identifier of the account.
+#[derive(Copy, Clone, Eq, Hash, Debug, PartialEq, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AccountID([u8; 32]);
+
+/// Parameters of the account.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Account {
+    /// Stable identifier of the account.
+    pub id: AccountID,
+
+    /// Key that signs every state transition of the account.
+    pub owner_key: VerificationKey,
+
+    /// Key that blinds the program in the predicate tree.
+    pub blinding_key: [u8; 32],
+}
+
+/// State of the account at a given sequence number.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct AccountState {
+    /// Sequence number of the state, starting with 0 when the account is opened.
+    pub sequence: u64,
+
+    /// Cleartext balance of the account.
+    pub balance: ClearValue,
+
+    /// Blinding factor for the quantity commitment.
+    pub qty_blinding: Scalar,
+
+    /// Blinding factor for the flavor commitment.
+    pub flv_blinding: Scalar,
+
+    /// Contract's anchor necessary to compute the contract ID.
+    pub anchor: Anchor,
+}
+
+impl AccountID {
+    /// Derives the account ID from the owner's key and a unique nonce.
+    pub fn new(owner_key: &VerificationKey, nonce: &[u8]) -> Self {
+        let mut t = Transcript::new(b"ZkVM.accounts.account");
+        t.append_message(b"owner_key", owner_key.as_bytes());
+        t.append_message(b"nonce", nonce);
+        let mut id = AccountID([0u8; 32]);
+        t.challenge_bytes(b"account_id", &mut id.0);
+        id
+    }
+
+    /// Returns the bytes of the account ID.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl Account {
+    /// Creates a new account with the given owner key and a unique nonce.
+    pub fn new(owner_key: VerificationKey, nonce: &[u8], blinding_key: [u8; 32]) -> Self {
+        Account {
+            id: AccountID::new(&owner_key, nonce),
+            owner_key,
+            blinding_key,
+        }
+    }
+
+    /// Returns the predicate that locks the account's balance.
+    pub fn predicate(&self) -> Predicate {
+        Predicate::tree(self.tree())
+    }
+
+    /// Creates a contract for the account state.
+    pub fn contract(&self, state: &AccountState) -> Contract {
+        Contract {
+            predicate: self.predicate(),
+            payload: vec![
+                PortableItem::Value(state.blinded_balance()),
+                PortableItem::String(self.id_string()),
+                PortableItem::String(state.sequence.into()),
+                PortableItem::String(self.predicate().as_opaque().into()),
+            ],
+            anchor: state.anchor,
+        }
+    }
+
+    /// Adds instructions that open the account with the initial balance from the top of the stack
+    /// and the sequence number 0.
+    pub fn open(&self, program: &mut Program) {
+        let predicate = self.predicate().as_opaque();
+        program
+            .push(self.id_string())
+            .push(0u64)
+            .push(predicate.clone())
+            .push(predicate)
+            .output(4);
+    }
+
+    /// Adds instructions that spend the account and leave the continuation contract
+    /// and the balance on top of the stack. The transaction must be signed with the owner key
+    /// and must re-lock the new balance using `relock`.
+    pub fn spend(&self, program: &mut Program, state: &AccountState) -> Result<(), VMError> {
+        program
+            .push(state.sequence + 1)
+            .push(self.contract(state))
+            .input()
+            .choose_call(self.tree(), 0)?;
+        Ok(())
+    }
+
+    /// Adds instructions that lock the new balance from the top of the stack
+    /// under the account, using the continuation contract right below it.
+    pub fn relock(&self, program: &mut Program) -> Result<(), VMError> {
+        program.roll(1).choose_call(relock_tree(), 0)?;
+        Ok(())
+    }
+
+    fn id_string(&self) -> String {
+        String::Opaque(self.id.0.to_vec().into())
+    }
+
+    fn tree(&self) -> PredicateTree {
+        // There is only one program, so the tree is always well-formed.
+        PredicateTree::new(None, vec![self.spend_program()], self.blinding_key)
+            .expect("Account predicate tree is well-formed.")
+    }
+
+    /// Expects `next_seq, balance, id, seq, account_pred` on the stack,
+    /// checks `next_seq == seq + 1`, requires the owner's signature
+    /// and leaves `continuation, balance` on the stack:
+    /// `dup:1 scalar 1 scalar add dup:5 scalar eq verify roll:1 drop roll:3 roll:1
+    ///  <owner> contract:3 signtx <relock pred> contract:3 roll:1`.
+    fn spend_program(&self) -> Program {
+        Program::build(|p| {
+            p.dup(1)
+                .scalar()
+                .push(1u64)
+                .scalar()
+                .add()
+                .dup(5)
+                .scalar()
+                .eq()
+                .verify();
+            p.roll(1).drop().roll(3).roll(1);
+            p.require_signature(Predicate::new(self.owner_key), 3)
+                .push(relock_predicate())
+                .contract(3)
+                .roll(1);
+        })
+    }
+}
+
+impl AccountState {
+    /// Returns the balance with its blinding factors.
+    pub fn blinded_balance(&self) -> Value {
+        Value {
+            qty: Commitment::blinded_with_factor(self.balance.qty, self.qty_blinding),
+            flv: Commitment::blinded_with_factor(self.balance.flv, self.flv_blinding),
+        }
+    }
+}
+
+/// Predicate of the continuation contract that re-locks the new balance under the account.
+fn relock_predicate() -> Predicate {
+    Predicate::tree(relock_tree()).as_opaque()
+}
+
+/// Predicate tree with an unsignable inner key and a single program
+/// that expects `balance, id, seq, account_pred` on the stack and locks them
+/// under the account predicate: `dup:0 output:4`.
+fn relock_tree() -> PredicateTree {
+    let program = Program::build(|p| {
+        p.dup(0).output(4);
+    });
+    PredicateTree::new(None, vec![program], [0u8; 32])
+        .expect("Relock predicate tree is well-formed.")
+}
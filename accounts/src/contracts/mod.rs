@@ -0,0 +1,6 @@
+//! Higher-level contract templates built on top of ZkVM programs and predicate trees.
+//! Each template describes how to lock funds and which transactions may unlock them.
+
+pub mod account;
+pub mod order;
+pub mod vault;
@@ -0,0 +1,304 @@
+//! Order contract: a partially-fillable limit order that sells one asset for another.
+//!
+//! The maker locks the offered value under a predicate tree with the maker's key as the inner key,
+//! so the maker can cancel the order at any time with a plain `signtx`.
+//! Anyone can fill the order using one of the two program branches:
+//!
+//! 1. `fill` buys a part of the offered value and re-locks the remainder under the same predicate,
+//! 2. `fill_all` buys the entire offered value.
+//!
+//! Both branches require the taker to pay the maker at least `ask_qty/offer_qty`
+//! units of the asked asset per unit of the offered asset.
+//!
+//! The program cannot refer to its own predicate, so the contract carries it in the payload:
+//! `[offered value, order predicate]`. The maker is the only one harmed if the payload
+//! does not match the predicate, so the taker does not need to check it.
+//!
+//! The offered quantity, remainders and payments are unblinded (checked with `unblind`),
+//! so the maker can spend the payment and any taker can fill the remainder.
+
+use core::cmp::Ordering;
+use curve25519_dalek::scalar::Scalar;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zkvm::{
+    Anchor, Commitment, Contract, PortableItem, Predicate, PredicateTree, Program, VMError, Value,
+    VerificationKey,
+};
+
+/// Parameters of the order.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Order {
+    /// Key that receives the payments and can cancel the order.
+    pub maker_key: VerificationKey,
+
+    /// Flavor of the offered asset.
+    pub offer_flavor: Scalar,
+
+    /// Flavor of the asked asset.
+    pub ask_flavor: Scalar,
+
+    /// Quantity of the offered asset in the price ratio.
+    pub offer_qty: u64,
+
+    /// Quantity of the asked asset in the price ratio.
+    pub ask_qty: u64,
+
+    /// Key that blinds the fill programs in the predicate tree.
+    pub blinding_key: [u8; 32],
+}
+
+/// Unfilled part of the order.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OrderUtxo {
+    /// Remaining quantity of the offered asset.
+    pub qty: u64,
+
+    /// Contract's anchor necessary to compute the contract ID.
+    pub anchor: Anchor,
+}
+
+/// Errors that may occur when filling an order.
+#[derive(Clone, Error, Debug)]
+pub enum OrderError {
+    /// Fill quantity is zero or exceeds the remaining quantity of the order.
+    #[error("Fill quantity must be positive and not exceed the remaining quantity.")]
+    InvalidFillQuantity,
+
+    /// Price ratio is invalid or the payment overflows.
+    #[error("Order price is invalid.")]
+    InvalidPrice,
+
+    /// Failed to create the program for the predicate tree.
+    #[error("Failed to create the order program: {0}")]
+    VMError(#[from] VMError),
+}
+
+impl Order {
+    /// Creates a new order selling `offer_qty` units of `offer_flavor`
+    /// for every `ask_qty` units of `ask_flavor`.
+    pub fn new(
+        maker_key: VerificationKey,
+        offer_flavor: Scalar,
+        ask_flavor: Scalar,
+        offer_qty: u64,
+        ask_qty: u64,
+        blinding_key: [u8; 32],
+    ) -> Self {
+        Order {
+            maker_key,
+            offer_flavor,
+            ask_flavor,
+            offer_qty,
+            ask_qty,
+            blinding_key,
+        }
+    }
+
+    /// Returns the predicate that locks the offered funds.
+    pub fn predicate(&self) -> Predicate {
+        Predicate::tree(self.tree())
+    }
+
+    /// Returns the predicate that receives the payments.
+    pub fn maker_predicate(&self) -> Predicate {
+        Predicate::new(self.maker_key)
+    }
+
+    /// Returns the payment for buying `fill_qty` units of the offered asset,
+    /// rounded up in favor of the maker.
+    pub fn payment_for(&self, fill_qty: u64) -> Result<u64, OrderError> {
+        if self.offer_qty == 0 {
+            return Err(OrderError::InvalidPrice);
+        }
+        let (num, den) = (self.ask_qty as u128, self.offer_qty as u128);
+        let payment = (fill_qty as u128 * num + den - 1) / den;
+        if payment > u64::max_value() as u128 {
+            return Err(OrderError::InvalidPrice);
+        }
+        Ok(payment as u64)
+    }
+
+    /// Compares the prices of two orders: the order with the lower price
+    /// sells more of the offered asset per unit of the asked asset.
+    pub fn cmp_price(&self, other: &Order) -> Ordering {
+        let a = self.ask_qty as u128 * other.offer_qty as u128;
+        let b = other.ask_qty as u128 * self.offer_qty as u128;
+        a.cmp(&b)
+    }
+
+    /// Creates a contract for the unfilled part of the order,
+    /// keeping the predicate tree as a witness so the maker can cancel the order.
+    pub fn contract(&self, utxo: &OrderUtxo) -> Contract {
+        Contract {
+            predicate: self.predicate(),
+            payload: vec![
+                PortableItem::Value(self.offered_value(utxo.qty)),
+                PortableItem::String(self.predicate().as_opaque().into()),
+            ],
+            anchor: utxo.anchor,
+        }
+    }
+
+    /// Adds instructions that lock the offered value from the top of the stack under the order.
+    /// The value must be unblinded, so it can be filled by anyone.
+    pub fn place(&self, program: &mut Program) {
+        let predicate = self.predicate().as_opaque();
+        program.push(predicate.clone()).push(predicate).output(2);
+    }
+
+    /// Adds instructions that buy `fill_qty` units of the offered asset and leave them on the stack.
+    /// Expects the payment of `payment_for(fill_qty)` units of the asked asset on the stack.
+    /// Returns the remaining quantity of the order. If the order is partially filled,
+    /// the remainder is re-locked under the order predicate.
+    pub fn fill(
+        &self,
+        program: &mut Program,
+        utxo: &OrderUtxo,
+        fill_qty: u64,
+    ) -> Result<u64, OrderError> {
+        if fill_qty == 0 || fill_qty > utxo.qty {
+            return Err(OrderError::InvalidFillQuantity);
+        }
+        let payment = self.payment_for(fill_qty)?;
+        let remainder = utxo.qty - fill_qty;
+
+        program
+            .push(Commitment::unblinded(fill_qty))
+            .push(Commitment::unblinded(payment))
+            .push(payment);
+        if remainder > 0 {
+            program
+                .push(Commitment::unblinded(remainder))
+                .push(remainder)
+                .push(self.contract(utxo))
+                .input()
+                .choose_call(self.tree(), 0)?;
+        } else {
+            program
+                .push(self.contract(utxo))
+                .input()
+                .choose_call(self.tree(), 1)?;
+        }
+        Ok(remainder)
+    }
+
+    /// Adds instructions that cancel the order with the maker key
+    /// and leave the unfilled value on the stack.
+    pub fn cancel(&self, program: &mut Program, utxo: &OrderUtxo) {
+        program.push(self.contract(utxo)).input().signtx().drop();
+    }
+
+    /// Returns the factor to be added to the maker's private key
+    /// in order to sign for the order.
+    pub fn maker_adjustment_factor(&self) -> Scalar {
+        self.tree().adjustment_factor()
+    }
+
+    /// Unblinded value of the offered asset.
+    fn offered_value(&self, qty: u64) -> Value {
+        Value {
+            qty: Commitment::unblinded(qty),
+            flv: Commitment::unblinded(self.offer_flavor),
+        }
+    }
+
+    fn tree(&self) -> PredicateTree {
+        // The maker key is a valid point and there are only two programs,
+        // so the tree is always well-formed.
+        PredicateTree::new(
+            Some(self.maker_predicate()),
+            vec![self.fill_program(), self.fill_all_program()],
+            self.blinding_key,
+        )
+        .expect("Order predicate tree is well-formed.")
+    }
+
+    /// Expects `payment, fill_C, payment_C, payment_qty, rem_C, rem_qty, offered, order_pred`
+    /// on the stack, checks that the payment and the remainder are unblinded
+    /// and the price is respected, then splits the offered value with `cloak:2:3`,
+    /// pays the maker, re-locks the remainder and leaves the bought value on the stack.
+    fn fill_program(&self) -> Program {
+        Program::build(|p| {
+            // remainder and payment are unblinded
+            p.dup(3).dup(3).unblind().drop();
+            p.dup(5).dup(5).unblind().drop();
+            self.check_price(p, 5);
+            // drop the cleartext remainder and payment
+            p.roll(2).drop().roll(3).drop();
+            // order_pred payment offered payment_C ask_flv rem_C offer_flv fill_C offer_flv
+            p.roll(5).roll(2).roll(4);
+            p.push(Commitment::unblinded(self.ask_flavor)).roll(5);
+            p.push(Commitment::unblinded(self.offer_flavor)).roll(7);
+            p.push(Commitment::unblinded(self.offer_flavor));
+            p.cloak(2, 3);
+            // pay the maker and re-lock the remainder
+            p.push(self.maker_predicate()).output(1);
+            p.roll(2).dup(0).output(2);
+        })
+    }
+
+    /// Expects `payment, fill_C, payment_C, payment_qty, offered, order_pred` on the stack,
+    /// checks that the payment is unblinded and the price is respected,
+    /// then pays the maker with `cloak:2:2` and leaves the bought value on the stack.
+    fn fill_all_program(&self) -> Program {
+        Program::build(|p| {
+            p.drop();
+            // payment is unblinded
+            p.dup(2).dup(2).unblind().drop();
+            self.check_price(p, 2);
+            // drop the cleartext payment
+            p.roll(1).drop();
+            // payment offered payment_C ask_flv fill_C offer_flv
+            p.roll(2).roll(2);
+            p.push(Commitment::unblinded(self.ask_flavor)).roll(2);
+            p.push(Commitment::unblinded(self.offer_flavor));
+            p.cloak(2, 2);
+            // pay the maker
+            p.push(self.maker_predicate()).output(1);
+        })
+    }
+
+    /// Checks `payment * offer_qty - fill_qty * ask_qty >= 0`,
+    /// where the payment commitment is at depth `k` and the fill quantity commitment
+    /// right below it.
+    fn check_price(&self, p: &mut Program, k: usize) {
+        p.dup(k)
+            .commit()
+            .expr()
+            .push(self.offer_qty)
+            .scalar()
+            .mul()
+            .dup(k + 2)
+            .commit()
+            .expr()
+            .push(self.ask_qty)
+            .scalar()
+            .mul()
+            .neg()
+            .add()
+            .range()
+            .drop();
+    }
+}
+
+/// Selects the fills buying up to `qty` units of the offered asset, best price first.
+/// All orders are expected to trade the same pair of assets.
+/// Returns the indices of the orders with the fill quantities.
+pub fn match_orders(orders: &[(Order, OrderUtxo)], mut qty: u64) -> Vec<(usize, u64)> {
+    let mut sorted = (0..orders.len()).collect::<Vec<_>>();
+    sorted.sort_by(|&a, &b| orders[a].0.cmp_price(&orders[b].0));
+
+    let mut fills = Vec::new();
+    for i in sorted {
+        if qty == 0 {
+            break;
+        }
+        let fill_qty = qty.min(orders[i].1.qty);
+        if fill_qty > 0 {
+            fills.push((i, fill_qty));
+            qty -= fill_qty;
+        }
+    }
+    fills
+}
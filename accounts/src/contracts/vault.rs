@@ -0,0 +1,267 @@
+//! Vault contract: funds that the owner can withdraw only after a waiting period,
+//! during which a separate recovery key can claw them back.
+//!
+//! Lifecycle of the vaulted funds:
+//!
+//! ```ascii
+//! deposit --> Locked --(unvault: owner key)--> Unvaulting{t} --(withdraw: owner key, mintime >= t)--> owner
+//!               |                                   |
+//!               +----(recover: recovery key)--------+------------------------------------------------> recovery
+//! ```
+//!
+//! Both stages are predicate trees with the recovery key as the inner key,
+//! so the recovery key can spend them at any time with a plain `signtx`.
+//! The owner can only use the program branches:
+//!
+//! 1. `unvault` expects the unlock time `t` and the value on the stack, checks that
+//!    `t >= tx.maxtime + delay` and re-locks both items under the unvaulting predicate.
+//! 2. `withdraw` checks that `tx.mintime >= t` and leaves the value on the stack.
+//!
+//! Since `t` is bounded by the maxtime of the unvaulting transaction, the recovery key
+//! always has at least `delay` milliseconds to notice the withdrawal and claw the funds back.
+
+use curve25519_dalek::scalar::Scalar;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zkvm::{
+    Anchor, ClearValue, Contract, PortableItem, Predicate, PredicateTree, Program, VMError,
+    VerificationKey,
+};
+
+use crate::Receiver;
+
+/// Parameters of the vault.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Vault {
+    /// Key that initiates and completes withdrawals.
+    pub owner_key: VerificationKey,
+
+    /// Key that can spend the vaulted funds at any time.
+    pub recovery_key: VerificationKey,
+
+    /// Minimum waiting period between initiating and completing a withdrawal, in milliseconds.
+    pub delay_ms: u64,
+
+    /// Key that blinds the owner's program branches in the predicate trees.
+    pub blinding_key: [u8; 32],
+}
+
+/// Stage of the vaulted funds.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VaultStage {
+    /// Funds are deposited into the vault.
+    Locked,
+
+    /// Withdrawal is initiated and can be completed at or after the unlock time.
+    Unvaulting {
+        /// Earliest time at which the owner can withdraw the funds.
+        unlock_time_ms: u64,
+    },
+}
+
+/// Vaulted funds: value, its blinding factors and the anchor of the contract.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct VaultUtxo {
+    /// Receiver for the vault deposit that specifies the value and its blinding factors.
+    /// The value is carried over unchanged through the unvaulting transaction.
+    pub receiver: Receiver,
+
+    /// Contract's anchor necessary to compute the contract ID.
+    pub anchor: Anchor,
+
+    /// Current stage of the vault contract.
+    pub stage: VaultStage,
+}
+
+/// Errors that may occur when spending vaulted funds.
+#[derive(Clone, Error, Debug)]
+pub enum VaultError {
+    /// The requested operation is not allowed at the current stage of the vault.
+    #[error("The operation is not allowed at the current stage of the vault.")]
+    InvalidStage,
+
+    /// Unlock time is earlier than the vault's waiting period permits.
+    #[error("Unlock time must be at least the vault's delay after the transaction maxtime.")]
+    UnlockTimeTooEarly,
+
+    /// Failed to create the program for the predicate tree.
+    #[error("Failed to create the vault program: {0}")]
+    VMError(#[from] VMError),
+}
+
+impl Vault {
+    /// Creates a new vault.
+    pub fn new(
+        owner_key: VerificationKey,
+        recovery_key: VerificationKey,
+        delay_ms: u64,
+        blinding_key: [u8; 32],
+    ) -> Self {
+        Vault {
+            owner_key,
+            recovery_key,
+            delay_ms,
+            blinding_key,
+        }
+    }
+
+    /// Returns the predicate that locks the deposited funds.
+    pub fn predicate(&self) -> Predicate {
+        Predicate::tree(self.locked_tree())
+    }
+
+    /// Returns the predicate that locks the funds during the waiting period.
+    pub fn unvaulting_predicate(&self) -> Predicate {
+        Predicate::tree(self.unvaulting_tree())
+    }
+
+    /// Creates a receiver for depositing a given value into the vault.
+    pub fn receiver(
+        &self,
+        value: ClearValue,
+        qty_blinding: Scalar,
+        flv_blinding: Scalar,
+    ) -> Receiver {
+        Receiver {
+            opaque_predicate: self.predicate().to_point(),
+            value,
+            qty_blinding,
+            flv_blinding,
+        }
+    }
+
+    /// Creates a contract for the vaulted funds, keeping the predicate tree
+    /// as a witness so it can be spent by the prover.
+    pub fn contract(&self, utxo: &VaultUtxo) -> Contract {
+        let value = PortableItem::Value(utxo.receiver.blinded_value());
+        let (predicate, payload) = match utxo.stage {
+            VaultStage::Locked => (self.predicate(), vec![value]),
+            VaultStage::Unvaulting { unlock_time_ms } => (
+                self.unvaulting_predicate(),
+                vec![PortableItem::String(unlock_time_ms.into()), value],
+            ),
+        };
+        Contract {
+            predicate,
+            payload,
+            anchor: utxo.anchor,
+        }
+    }
+
+    /// Adds instructions that spend the locked funds and re-lock them under the unvaulting
+    /// predicate with a given unlock time. The transaction must be signed with the owner key
+    /// and its maxtime must be at least `delay_ms` before the unlock time.
+    pub fn unvault(
+        &self,
+        program: &mut Program,
+        utxo: &VaultUtxo,
+        unlock_time_ms: u64,
+        tx_maxtime_ms: u64,
+    ) -> Result<(), VaultError> {
+        if utxo.stage != VaultStage::Locked {
+            return Err(VaultError::InvalidStage);
+        }
+        if tx_maxtime_ms.saturating_add(self.delay_ms) > unlock_time_ms {
+            return Err(VaultError::UnlockTimeTooEarly);
+        }
+        program
+            .push(unlock_time_ms)
+            .push(self.contract(utxo))
+            .input()
+            .choose_call(self.locked_tree(), 0)?;
+        Ok(())
+    }
+
+    /// Adds instructions that spend the unvaulting funds and leave the value on the stack.
+    /// The transaction must be signed with the owner key and its mintime must be
+    /// at or after the unlock time.
+    pub fn withdraw(&self, program: &mut Program, utxo: &VaultUtxo) -> Result<(), VaultError> {
+        match utxo.stage {
+            VaultStage::Unvaulting { .. } => {}
+            VaultStage::Locked => return Err(VaultError::InvalidStage),
+        }
+        program
+            .push(self.contract(utxo))
+            .input()
+            .choose_call(self.unvaulting_tree(), 0)?;
+        Ok(())
+    }
+
+    /// Adds instructions that spend the vaulted funds at any stage with the recovery key
+    /// and leave the value on the stack.
+    pub fn recover(&self, program: &mut Program, utxo: &VaultUtxo) {
+        program.push(self.contract(utxo)).input().signtx();
+        if let VaultStage::Unvaulting { .. } = utxo.stage {
+            program.roll(1).drop();
+        }
+    }
+
+    /// Returns the factor to be added to the recovery private key in order to sign
+    /// for the vaulted funds at a given stage.
+    pub fn recovery_adjustment_factor(&self, stage: VaultStage) -> Scalar {
+        match stage {
+            VaultStage::Locked => self.locked_tree().adjustment_factor(),
+            VaultStage::Unvaulting { .. } => self.unvaulting_tree().adjustment_factor(),
+        }
+    }
+
+    /// Predicate tree for the deposited funds.
+    fn locked_tree(&self) -> PredicateTree {
+        self.tree(self.unvault_program())
+    }
+
+    /// Predicate tree for the funds in the waiting period.
+    fn unvaulting_tree(&self) -> PredicateTree {
+        self.tree(self.withdraw_program())
+    }
+
+    fn tree(&self, prog: Program) -> PredicateTree {
+        // The recovery key is a valid point and there is only one program,
+        // so the tree is always well-formed.
+        PredicateTree::new(
+            Some(Predicate::new(self.recovery_key)),
+            vec![prog],
+            self.blinding_key,
+        )
+        .expect("Vault predicate tree is well-formed.")
+    }
+
+    /// Expects `t, value` on the stack, checks `t - (maxtime + delay) >= 0`
+    /// and locks both items under the unvaulting predicate:
+    /// `dup:1 scalar maxtime <delay> scalar add neg add range drop
+    ///  <owner> contract:2 signtx <unvaulting predicate> output:2`.
+    fn unvault_program(&self) -> Program {
+        let unvaulting_predicate = self.unvaulting_predicate().as_opaque();
+        Program::build(|p| {
+            p.dup(1)
+                .scalar()
+                .maxtime()
+                .push(self.delay_ms)
+                .scalar()
+                .add()
+                .neg()
+                .add()
+                .range()
+                .drop()
+                .require_signature(Predicate::new(self.owner_key), 2)
+                .push(unvaulting_predicate)
+                .output(2);
+        })
+    }
+
+    /// Expects `t, value` on the stack, checks `mintime - t >= 0`
+    /// and leaves the value on the stack:
+    /// `roll:1 scalar neg mintime add range drop <owner> contract:1 signtx`.
+    fn withdraw_program(&self) -> Program {
+        Program::build(|p| {
+            p.roll(1)
+                .scalar()
+                .neg()
+                .mintime()
+                .add()
+                .range()
+                .drop()
+                .require_signature(Predicate::new(self.owner_key), 1);
+        })
+    }
+}
@@ -0,0 +1,267 @@
+//! Invoice: a signed payment request.
+//!
+//! Invoice wraps a receiver with the expiration time and a memo.
+//! The amount and the flavor are those of the receiver.
+//! Invoice is signed with the receiver's one-time key, so the payer can check
+//! that the request comes from the owner of the receiver and was not modified on the way.
+//!
+//! Invoice is encoded as a payment request URI: `slingshot:<bech32>`,
+//! where the bech32 string uses the ledger's address label as its prefix
+//! and contains the binary encoding of the invoice:
+//!
+//! ```ascii
+//! predicate (32) || qty (8, LE) || flavor (32) || qty blinding (32) || flavor blinding (32) ||
+//! expiration (8, LE) || memo length (1) || memo || signature (64)
+//! ```
+use core::convert::TryInto;
+use std::fmt;
+
+use bech32::{self, FromBase32, ToBase32};
+use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::scalar::Scalar;
+use merlin::Transcript;
+use musig::{Signature, VerificationKey};
+use thiserror::Error;
+use zkvm::ClearValue;
+
+use super::{AddressLabel, Receiver};
+
+/// Scheme of the payment request URI.
+pub const INVOICE_URI_SCHEME: &str = "slingshot";
+
+/// Maximum length of the memo in bytes.
+pub const MAX_INVOICE_MEMO_LEN: usize = 140;
+
+/// Offset of the memo length in the encoded invoice.
+const MEMO_LEN_OFFSET: usize = 32 + 8 + 32 + 32 + 32 + 8;
+
+/// Length of the encoded invoice without the memo.
+const INVOICE_FIXED_LEN: usize = MEMO_LEN_OFFSET + 1 + 64;
+
+/// Payment request signed by the owner of the receiver.
+#[derive(Clone, Debug)]
+pub struct Invoice {
+    /// Label of the ledger on which the payment is requested.
+    pub label: AddressLabel,
+    /// Receiver of the payment.
+    pub receiver: Receiver,
+    /// Time after which the invoice must not be paid, in milliseconds.
+    pub expiration_ms: u64,
+    /// Short description of the payment.
+    pub memo: String,
+    /// Signature with the receiver's key.
+    pub signature: Signature,
+}
+
+/// Errors related to the invoices.
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum InvoiceError {
+    /// Invoice string is malformed.
+    #[error("Invoice is malformed.")]
+    InvalidFormat,
+    /// Memo is longer than `MAX_INVOICE_MEMO_LEN` bytes.
+    #[error("Invoice memo is too long.")]
+    MemoTooLong,
+    /// Signature does not match the receiver's key.
+    #[error("Invoice signature is not valid.")]
+    InvalidSignature,
+    /// Invoice is expired.
+    #[error("Invoice is expired.")]
+    Expired,
+}
+
+impl Invoice {
+    /// Creates an invoice signed with the receiver's private key.
+    pub fn sign(
+        label: AddressLabel,
+        receiver: Receiver,
+        expiration_ms: u64,
+        memo: String,
+        privkey: Scalar,
+    ) -> Result<Self, InvoiceError> {
+        if memo.len() > MAX_INVOICE_MEMO_LEN {
+            return Err(InvoiceError::MemoTooLong);
+        }
+        let signature = Signature::sign(
+            &mut Self::transcript(&label, &receiver, expiration_ms, &memo),
+            privkey,
+        );
+        Ok(Invoice {
+            label,
+            receiver,
+            expiration_ms,
+            memo,
+            signature,
+        })
+    }
+
+    /// Verifies the signature with the receiver's key.
+    pub fn verify(&self) -> Result<(), InvoiceError> {
+        self.signature
+            .verify(
+                &mut Self::transcript(&self.label, &self.receiver, self.expiration_ms, &self.memo),
+                VerificationKey::from_compressed(self.receiver.opaque_predicate),
+            )
+            .map_err(|_| InvoiceError::InvalidSignature)
+    }
+
+    /// Checks that the invoice can be paid at a given time.
+    pub fn check_expiration(&self, time_ms: u64) -> Result<(), InvoiceError> {
+        if time_ms > self.expiration_ms {
+            Err(InvoiceError::Expired)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Decodes the invoice from the payment request URI and verifies its signature.
+    pub fn from_string(string: &str) -> Result<Self, InvoiceError> {
+        let prefix = format!("{}:", INVOICE_URI_SCHEME);
+        if !string.starts_with(&prefix) {
+            return Err(InvoiceError::InvalidFormat);
+        }
+        let data = &string[prefix.len()..];
+        let (label, data) = bech32::decode(data).map_err(|_| InvoiceError::InvalidFormat)?;
+        let label = AddressLabel::new(label).ok_or(InvoiceError::InvalidFormat)?;
+        let buf = Vec::<u8>::from_base32(&data).map_err(|_| InvoiceError::InvalidFormat)?;
+        if buf.len() < INVOICE_FIXED_LEN
+            || buf.len() != INVOICE_FIXED_LEN + buf[MEMO_LEN_OFFSET] as usize
+        {
+            return Err(InvoiceError::InvalidFormat);
+        }
+        let scalar = |range: core::ops::Range<usize>| {
+            Scalar::from_canonical_bytes(buf[range].try_into().expect("32-byte slice"))
+                .ok_or(InvoiceError::InvalidFormat)
+        };
+        let u64_at = |offset: usize| {
+            u64::from_le_bytes(buf[offset..offset + 8].try_into().expect("8-byte slice"))
+        };
+        let memo_start = MEMO_LEN_OFFSET + 1;
+        let memo_end = memo_start + buf[MEMO_LEN_OFFSET] as usize;
+        let receiver = Receiver {
+            opaque_predicate: CompressedRistretto::from_slice(&buf[0..32]),
+            value: ClearValue {
+                qty: u64_at(32),
+                flv: scalar(40..72)?,
+            },
+            qty_blinding: scalar(72..104)?,
+            flv_blinding: scalar(104..136)?,
+        };
+        let memo = String::from_utf8(buf[memo_start..memo_end].to_vec())
+            .map_err(|_| InvoiceError::InvalidFormat)?;
+        if memo.len() > MAX_INVOICE_MEMO_LEN {
+            return Err(InvoiceError::MemoTooLong);
+        }
+        let signature =
+            Signature::from_bytes(&buf[memo_end..]).map_err(|_| InvoiceError::InvalidFormat)?;
+        let invoice = Invoice {
+            label,
+            receiver,
+            expiration_ms: u64_at(136),
+            memo,
+            signature,
+        };
+        invoice.verify()?;
+        Ok(invoice)
+    }
+
+    fn transcript(
+        label: &AddressLabel,
+        receiver: &Receiver,
+        expiration_ms: u64,
+        memo: &str,
+    ) -> Transcript {
+        let mut t = Transcript::new(b"ZkVM.accounts.invoice");
+        t.append_message(b"label", label.as_bytes());
+        t.append_message(b"receiver", receiver.id().as_bytes());
+        t.append_u64(b"expiration_ms", expiration_ms);
+        t.append_message(b"memo", memo.as_bytes());
+        t
+    }
+}
+
+/// Encodes the invoice as a payment request URI.
+impl fmt::Display for Invoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r = &self.receiver;
+        let mut bytes = Vec::with_capacity(INVOICE_FIXED_LEN + self.memo.len());
+        bytes.extend_from_slice(r.opaque_predicate.as_bytes());
+        bytes.extend_from_slice(&r.value.qty.to_le_bytes());
+        bytes.extend_from_slice(r.value.flv.as_bytes());
+        bytes.extend_from_slice(r.qty_blinding.as_bytes());
+        bytes.extend_from_slice(r.flv_blinding.as_bytes());
+        bytes.extend_from_slice(&self.expiration_ms.to_le_bytes());
+        bytes.push(self.memo.len() as u8);
+        bytes.extend_from_slice(self.memo.as_bytes());
+        bytes.extend_from_slice(&self.signature.to_bytes());
+        let data = bech32::encode(&self.label, bytes.to_base32())
+            .expect("Label should be 1 to 83 characters long, printable ASCII, w/o mixing case.");
+        write!(f, "{}:{}", INVOICE_URI_SCHEME, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{XprvDerivation, XpubDerivation};
+    use keytree::Xprv;
+
+    fn make_invoice(memo: &str) -> Result<Invoice, InvoiceError> {
+        let xprv = Xprv::random(rand::thread_rng());
+        let value = ClearValue {
+            qty: 100,
+            flv: Scalar::from(7u64),
+        };
+        let receiver = xprv.as_xpub().receiver_at_sequence(3, value);
+        Invoice::sign(
+            AddressLabel::new("test".to_string()).unwrap(),
+            receiver,
+            1000,
+            memo.to_string(),
+            xprv.key_at_sequence(3),
+        )
+    }
+
+    #[test]
+    fn invoice_roundtrip() {
+        let invoice = make_invoice("Coffee").unwrap();
+        assert!(invoice.verify().is_ok());
+
+        let string = invoice.to_string();
+        assert!(string.starts_with("slingshot:test1"));
+        let decoded = Invoice::from_string(&string).unwrap();
+        assert_eq!(decoded.label, invoice.label);
+        assert_eq!(decoded.receiver.id(), invoice.receiver.id());
+        assert_eq!(decoded.expiration_ms, 1000);
+        assert_eq!(decoded.memo, "Coffee");
+
+        assert_eq!(decoded.check_expiration(1000), Ok(()));
+        assert_eq!(decoded.check_expiration(1001), Err(InvoiceError::Expired));
+    }
+
+    #[test]
+    fn invalid_invoices() {
+        assert_eq!(
+            make_invoice(&"x".repeat(MAX_INVOICE_MEMO_LEN + 1)).unwrap_err(),
+            InvoiceError::MemoTooLong
+        );
+
+        let mut invoice = make_invoice("").unwrap();
+        invoice.receiver.value.qty += 1;
+        assert_eq!(invoice.verify(), Err(InvoiceError::InvalidSignature));
+        assert_eq!(
+            Invoice::from_string(&invoice.to_string()).unwrap_err(),
+            InvoiceError::InvalidSignature
+        );
+
+        let string = make_invoice("").unwrap().to_string();
+        assert_eq!(
+            Invoice::from_string(&string["slingshot:".len()..]).unwrap_err(),
+            InvoiceError::InvalidFormat
+        );
+        assert_eq!(
+            Invoice::from_string(&string[..string.len() - 1]).unwrap_err(),
+            InvoiceError::InvalidFormat
+        );
+    }
+}
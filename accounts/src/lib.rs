@@ -64,11 +64,14 @@ Questions:
        so the sender can avoid publishing it unless recipient acknowledged the payment details.
 */
 mod address;
+pub mod contracts;
 mod derivation;
+mod invoice;
 mod receiver;
 #[cfg(test)]
 mod tests;
 
 pub use address::{Address, AddressLabel};
 pub use derivation::{Sequence, XprvDerivation, XpubDerivation};
+pub use invoice::{Invoice, InvoiceError, INVOICE_URI_SCHEME, MAX_INVOICE_MEMO_LEN};
 pub use receiver::{Receiver, ReceiverID, ReceiverReply, ReceiverWitness};
@@ -75,6 +75,13 @@ impl ReceiverWitness {
     }
 }
 
+impl ReceiverID {
+    /// Returns the bytes of the receiver ID.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
 impl Receiver {
     /// Returns the unique identifier of the receiver.
     pub fn id(&self) -> ReceiverID {
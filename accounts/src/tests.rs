@@ -8,8 +8,14 @@ use musig::{Multisignature, Signature};
 
 use blockchain::{utreexo, BlockHeader, BlockTx, BlockchainState, Mempool};
 use zkvm::bulletproofs::BulletproofGens;
-use zkvm::{Anchor, ClearValue, Contract, ContractID, Program, Prover, TxEntry, TxHeader};
-
+use zkvm::{
+    Anchor, ClearValue, Commitment, Contract, ContractID, PortableItem, Predicate, Program, Prover,
+    Tx, TxEntry, TxHeader, VMError, Value, VerificationKey,
+};
+
+use crate::contracts::account::{Account, AccountState};
+use crate::contracts::order::{match_orders, Order, OrderUtxo};
+use crate::contracts::vault::{Vault, VaultError, VaultStage, VaultUtxo};
 use crate::{ReceiverReply, ReceiverWitness, XprvDerivation, XpubDerivation};
 
 /// The complete state of the user node: their wallet and their blockchain state.
@@ -264,6 +270,342 @@ fn basic_accounts_test() {
     );
 }
 
+#[test]
+fn vault_test() {
+    let bp_gens = BulletproofGens::new(256, 1);
+
+    // Overview:
+    // 1. Alice deposits funds into a vault with a 500ms delay and a recovery key.
+    // 2. Alice cannot unvault with an unlock time earlier than maxtime + delay.
+    // 3. Alice unvaults the funds with an unlock time 1500.
+    // 4. Alice cannot withdraw the funds before the unlock time.
+    // 5. Alice withdraws the funds at the unlock time.
+    // 6. The recovery key claws back the funds at both stages.
+    let owner_privkey = Scalar::from(1u64);
+    let recovery_privkey = Scalar::from(2u64);
+    let output_predicate = Predicate::new(VerificationKey::from_secret(&Scalar::from(3u64)));
+    let vault = Vault::new(
+        VerificationKey::from_secret(&owner_privkey),
+        VerificationKey::from_secret(&recovery_privkey),
+        500,
+        [7u8; 32],
+    );
+
+    // 1. Alice deposits funds into a vault with a 500ms delay and a recovery key.
+    let value = ClearValue {
+        qty: 10,
+        flv: Scalar::from(0u64),
+    };
+    let deposit = VaultUtxo {
+        receiver: vault.receiver(value, Scalar::from(11u64), Scalar::from(12u64)),
+        anchor: Anchor::from_raw_bytes([0; 32]),
+        stage: VaultStage::Locked,
+    };
+    assert_eq!(
+        vault.contract(&deposit).predicate.to_point(),
+        deposit.receiver.opaque_predicate
+    );
+
+    // 2. Alice cannot unvault with an unlock time earlier than maxtime + delay.
+    match vault.unvault(&mut Program::new(), &deposit, 1499, 1000) {
+        Err(VaultError::UnlockTimeTooEarly) => {}
+        _ => panic!("Unvaulting must respect the delay"),
+    }
+
+    // 3. Alice unvaults the funds with an unlock time 1500.
+    let unvault_tx = {
+        let mut program = Program::new();
+        vault.unvault(&mut program, &deposit, 1500, 1000).unwrap();
+        build_signed_tx(program, 0, 1000, &[owner_privkey], &bp_gens).unwrap()
+    };
+    let pending_anchor = unvault_tx
+        .precompute()
+        .unwrap()
+        .log
+        .iter()
+        .find_map(|e| match e {
+            TxEntry::Output(contract) => Some(contract.clone()),
+            _ => None,
+        })
+        .unwrap();
+    let pending = VaultUtxo {
+        anchor: pending_anchor.anchor,
+        stage: VaultStage::Unvaulting {
+            unlock_time_ms: 1500,
+        },
+        ..deposit
+    };
+    assert_eq!(pending_anchor.id(), vault.contract(&pending).id());
+    assert!(vault.withdraw(&mut Program::new(), &deposit).is_err());
+
+    // 4. Alice cannot withdraw the funds before the unlock time.
+    // 5. Alice withdraws the funds at the unlock time.
+    let withdraw = |mintime_ms| {
+        let mut program = Program::new();
+        vault.withdraw(&mut program, &pending).unwrap();
+        program.push(output_predicate.clone()).output(1);
+        build_signed_tx(program, mintime_ms, 2000, &[owner_privkey], &bp_gens)
+    };
+    assert!(withdraw(1499).is_err());
+    assert!(withdraw(1500).is_ok());
+
+    // 6. The recovery key claws back the funds at both stages.
+    for utxo in [deposit, pending].iter() {
+        let mut program = Program::new();
+        vault.recover(&mut program, utxo);
+        program.push(output_predicate.clone()).output(1);
+        let key = recovery_privkey + vault.recovery_adjustment_factor(utxo.stage);
+        assert!(build_signed_tx(program, 0, 1000, &[key], &bp_gens).is_ok());
+    }
+}
+
+#[test]
+fn order_test() {
+    let bp_gens = BulletproofGens::new(256, 1);
+
+    // Overview:
+    // 1. Maker places an order selling 10 units of A for 3 units of B per 2 units of A.
+    // 2. Taker cannot fill 4 units of A paying less than 6 units of B.
+    // 3. Taker fills 4 units of A paying 6 units of B, the remaining 6 units are re-locked.
+    // 4. Taker fills the remaining 6 units of A paying 9 units of B.
+    // 5. Maker cancels the remaining order instead.
+    let maker_privkey = Scalar::from(4u64);
+    let taker_privkey = Scalar::from(5u64);
+    let maker_predicate = Predicate::new(VerificationKey::from_secret(&maker_privkey));
+    let taker_predicate = Predicate::new(VerificationKey::from_secret(&taker_privkey));
+    let (flv_a, flv_b) = (Scalar::from(1u64), Scalar::from(2u64));
+    let order = Order::new(
+        VerificationKey::from_secret(&maker_privkey),
+        flv_a,
+        flv_b,
+        2,
+        3,
+        [8u8; 32],
+    );
+
+    // 1. Maker places an order selling 10 units of A for 3 units of B per 2 units of A.
+    let place_tx = {
+        let program = Program::build(|p| {
+            p.push(make_contract(10, flv_a, maker_predicate.clone()))
+                .input()
+                .signtx()
+                .push(Commitment::unblinded(10u64))
+                .push(Commitment::unblinded(flv_a))
+                .cloak(1, 1);
+            order.place(p);
+        });
+        build_signed_tx(program, 0, 1000, &[maker_privkey], &bp_gens).unwrap()
+    };
+    let placed = tx_outputs(&place_tx)[0].clone();
+    let utxo = OrderUtxo {
+        qty: 10,
+        anchor: placed.anchor,
+    };
+    assert_eq!(placed.id(), order.contract(&utxo).id());
+
+    // Taker pays `payment` units of B from a utxo with 20 units of B and fills `fill_qty` units of A.
+    let fill = |utxo: &OrderUtxo, payment: u64, fill_qty: u64| {
+        let mut program = Program::new();
+        program
+            .push(make_contract(20, flv_b, taker_predicate.clone()))
+            .input()
+            .signtx()
+            .push(Commitment::unblinded(payment))
+            .push(Commitment::unblinded(flv_b))
+            .push(Commitment::blinded(20 - payment))
+            .push(Commitment::blinded(flv_b))
+            .cloak(1, 2);
+        order.fill(&mut program, utxo, fill_qty).unwrap();
+        program
+            .push(taker_predicate.clone())
+            .output(1)
+            .push(taker_predicate.clone())
+            .output(1);
+        build_signed_tx(program, 0, 1000, &[taker_privkey], &bp_gens)
+    };
+
+    // 2. Taker cannot fill 4 units of A paying less than 6 units of B.
+    assert_eq!(order.payment_for(4).unwrap(), 6);
+    assert!(fill(&utxo, 5, 4).is_err());
+
+    // 3. Taker fills 4 units of A paying 6 units of B, the remaining 6 units are re-locked.
+    let fill_tx = fill(&utxo, 6, 4).unwrap();
+    let remainder = tx_outputs(&fill_tx)
+        .into_iter()
+        .map(|c| {
+            (
+                c.id(),
+                OrderUtxo {
+                    qty: 6,
+                    anchor: c.anchor,
+                },
+            )
+        })
+        .find(|(id, u)| id == &order.contract(u).id())
+        .map(|(_, u)| u)
+        .expect("Remainder must be re-locked under the order");
+
+    // 4. Taker fills the remaining 6 units of A paying 9 units of B.
+    assert_eq!(order.payment_for(6).unwrap(), 9);
+    assert!(fill(&remainder, 9, 6).is_ok());
+
+    // 5. Maker cancels the remaining order instead.
+    let program = Program::build(|p| {
+        order.cancel(p, &remainder);
+        p.push(maker_predicate.clone()).output(1);
+    });
+    let key = maker_privkey + order.maker_adjustment_factor();
+    assert!(build_signed_tx(program, 0, 1000, &[key], &bp_gens).is_ok());
+}
+
+#[test]
+fn match_orders_test() {
+    let key = VerificationKey::from_secret(&Scalar::from(4u64));
+    let (flv_a, flv_b) = (Scalar::from(1u64), Scalar::from(2u64));
+    let utxo = |qty| OrderUtxo {
+        qty,
+        anchor: Anchor::from_raw_bytes([0; 32]),
+    };
+    let orders = vec![
+        (Order::new(key, flv_a, flv_b, 1, 3, [0; 32]), utxo(10)),
+        (Order::new(key, flv_a, flv_b, 1, 1, [0; 32]), utxo(5)),
+        (Order::new(key, flv_a, flv_b, 2, 3, [0; 32]), utxo(4)),
+    ];
+    assert_eq!(match_orders(&orders, 7), vec![(1, 5), (2, 2)]);
+    assert_eq!(match_orders(&orders, 12), vec![(1, 5), (2, 4), (0, 3)]);
+    assert_eq!(match_orders(&orders, 100).len(), 3);
+}
+
+#[test]
+fn account_test() {
+    let bp_gens = BulletproofGens::new(256, 1);
+
+    // Overview:
+    // 1. Alice opens an account with 10 units.
+    // 2. Alice pays 3 units to Bob, re-locking 7 units with the sequence number 1.
+    // 3. Alice cannot spend the account without re-locking the balance.
+    let owner_privkey = Scalar::from(6u64);
+    let owner_predicate = Predicate::new(VerificationKey::from_secret(&owner_privkey));
+    let bob_predicate = Predicate::new(VerificationKey::from_secret(&Scalar::from(7u64)));
+    let account = Account::new(
+        VerificationKey::from_secret(&owner_privkey),
+        b"alice",
+        [9u8; 32],
+    );
+    let flv = Scalar::from(0u64);
+    let state_at = |sequence, qty, anchor| AccountState {
+        sequence,
+        balance: ClearValue { qty, flv },
+        qty_blinding: Scalar::from(100 + sequence),
+        flv_blinding: Scalar::from(200 + sequence),
+        anchor,
+    };
+    let find_state = |tx: &Tx, sequence, qty| {
+        tx_outputs(tx)
+            .into_iter()
+            .map(|c| (c.id(), state_at(sequence, qty, c.anchor)))
+            .find(|(id, state)| id == &account.contract(state).id())
+            .map(|(_, state)| state)
+    };
+
+    // 1. Alice opens an account with 10 units.
+    let open_tx = {
+        let initial = state_at(0, 10, Anchor::from_raw_bytes([0; 32])).blinded_balance();
+        let program = Program::build(|p| {
+            p.push(make_contract(10, flv, owner_predicate.clone()))
+                .input()
+                .signtx()
+                .push(initial.qty)
+                .push(initial.flv)
+                .cloak(1, 1);
+            account.open(p);
+        });
+        build_signed_tx(program, 0, 1000, &[owner_privkey], &bp_gens).unwrap()
+    };
+    let state0 = find_state(&open_tx, 0, 10).expect("Account must be opened");
+
+    // 2. Alice pays 3 units to Bob, re-locking 7 units with the sequence number 1.
+    let pay_tx = {
+        let new_balance = state_at(1, 7, state0.anchor).blinded_balance();
+        let mut program = Program::new();
+        account.spend(&mut program, &state0).unwrap();
+        program
+            .push(Commitment::blinded(3u64))
+            .push(Commitment::blinded(flv))
+            .push(new_balance.qty)
+            .push(new_balance.flv)
+            .cloak(1, 2)
+            .push(bob_predicate.clone())
+            .output(1);
+        account.relock(&mut program).unwrap();
+        build_signed_tx(program, 0, 1000, &[owner_privkey], &bp_gens).unwrap()
+    };
+    assert!(find_state(&pay_tx, 1, 7).is_some());
+
+    // 3. Alice cannot spend the account without re-locking the balance.
+    let mut program = Program::new();
+    account.spend(&mut program, &state0).unwrap();
+    program.push(bob_predicate.clone()).output(1);
+    assert!(build_signed_tx(program, 0, 1000, &[owner_privkey], &bp_gens).is_err());
+}
+
+/// Creates a contract with a blinded value.
+fn make_contract(qty: u64, flv: Scalar, predicate: Predicate) -> Contract {
+    Contract {
+        predicate,
+        payload: vec![PortableItem::Value(Value {
+            qty: Commitment::blinded(qty),
+            flv: Commitment::blinded(flv),
+        })],
+        anchor: Anchor::from_raw_bytes([0; 32]),
+    }
+}
+
+/// Returns the outputs of the transaction.
+fn tx_outputs(tx: &Tx) -> Vec<Contract> {
+    tx.precompute()
+        .unwrap()
+        .log
+        .iter()
+        .filter_map(|e| match e {
+            TxEntry::Output(contract) => Some(contract.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Builds, signs and verifies the transaction.
+fn build_signed_tx(
+    program: Program,
+    mintime_ms: u64,
+    maxtime_ms: u64,
+    privkeys: &[Scalar],
+    bp_gens: &BulletproofGens,
+) -> Result<Tx, VMError> {
+    let header = TxHeader {
+        version: 1u64,
+        mintime_ms,
+        maxtime_ms,
+    };
+    let utx = Prover::build_tx(program, header, bp_gens)?;
+
+    let mut signtx_transcript = Transcript::new(b"ZkVM.signtx");
+    signtx_transcript.append_message(b"txid", &utx.txid.0);
+    let sig = Signature::sign_multi(
+        privkeys,
+        utx.signing_instructions
+            .iter()
+            .map(|(p, m)| (p.verification_key(), m))
+            .collect(),
+        &mut signtx_transcript,
+    )
+    .unwrap();
+
+    let tx = utx.sign(sig);
+    tx.verify(bp_gens)?;
+    Ok(tx)
+}
+
 /// Processes a block
 fn process_block(
     node: &mut Node,
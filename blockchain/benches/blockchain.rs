@@ -0,0 +1,163 @@
+#[macro_use]
+extern crate criterion;
+use criterion::Criterion;
+
+use curve25519_dalek::scalar::Scalar;
+use merlin::Transcript;
+use rand::RngCore;
+
+use blockchain::utreexo::{self, Forest};
+use blockchain::{BlockTx, BlockchainState, Mempool};
+use zkvm::bulletproofs::BulletproofGens;
+use zkvm::{
+    Anchor, Commitment, Contract, ContractID, Multisignature, PortableItem, Predicate, Program,
+    Prover, Signature, String, TxHeader, Value, VerificationKey,
+};
+
+fn make_predicate(privkey: u64) -> Predicate {
+    Predicate::new(VerificationKey::from_secret(&Scalar::from(privkey)))
+}
+
+fn make_contract(privkey: u64, qty: u64) -> Contract {
+    let mut anchor_bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut anchor_bytes);
+
+    Contract {
+        predicate: make_predicate(privkey),
+        payload: vec![PortableItem::Value(Value {
+            qty: Commitment::unblinded(qty),
+            flv: Commitment::unblinded(Value::issue_flavor(&make_predicate(0), String::default())),
+        })],
+        anchor: Anchor::from_raw_bytes(anchor_bytes),
+    }
+}
+
+/// Makes a tx that simply moves funds from one utxo to another.
+fn transfer_tx(contract: Contract, proof: utreexo::Proof, bp_gens: &BulletproofGens) -> BlockTx {
+    let privkey = Scalar::from(1u64);
+    let program = Program::build(|p| {
+        p.push(contract)
+            .input()
+            .signtx()
+            .push(make_predicate(1))
+            .output(1);
+    });
+    let header = TxHeader {
+        version: 1u64,
+        mintime_ms: 0u64,
+        maxtime_ms: u64::max_value(),
+    };
+    let utx = Prover::build_tx(program, header, bp_gens).unwrap();
+
+    let mut signtx_transcript = Transcript::new(b"ZkVM.signtx");
+    signtx_transcript.append_message(b"txid", &utx.txid.0);
+    let sig = Signature::sign_multi(
+        &[privkey],
+        utx.signing_instructions
+            .iter()
+            .map(|(p, m)| (p.verification_key(), m))
+            .collect(),
+        &mut signtx_transcript,
+    )
+    .unwrap();
+
+    BlockTx {
+        tx: utx.sign(sig),
+        proofs: vec![proof],
+    }
+}
+
+fn validate_block_helper(n: usize, c: &mut Criterion) {
+    let label = format!("Blockchain: validate block with {} txs", n);
+
+    c.bench_function(&label, move |b| {
+        let bp_gens = BulletproofGens::new(256, 1);
+        let contracts: Vec<_> = (0..n).map(|_| make_contract(1, 100)).collect();
+        let (state, proofs) = BlockchainState::make_initial(
+            0u64,
+            contracts.iter().map(|c| c.id()).collect::<Vec<_>>(),
+        );
+
+        let mut mempool = Mempool::new(state.clone(), 1);
+        let txs: Vec<_> = contracts
+            .into_iter()
+            .zip(proofs.into_iter())
+            .map(|(contract, proof)| transfer_tx(contract, proof, &bp_gens))
+            .collect();
+        for tx in txs.iter() {
+            mempool.append(tx.clone(), &bp_gens).unwrap();
+        }
+        let header = mempool.make_block().header;
+
+        b.iter(|| state.apply_block(header.clone(), &txs, &bp_gens).unwrap())
+    });
+}
+
+fn validate_block_100(c: &mut Criterion) {
+    validate_block_helper(100, c);
+}
+
+fn validate_block_1000(c: &mut Criterion) {
+    validate_block_helper(1000, c);
+}
+
+fn utreexo_update_helper(n: usize, c: &mut Criterion) {
+    let label = format!("Utreexo: delete and insert {} items", n);
+
+    c.bench_function(&label, move |b| {
+        let hasher = utreexo::utreexo_hasher::<ContractID>();
+        let items: Vec<_> = (0..n).map(|_| make_contract(1, 1).id()).collect();
+        let new_items: Vec<_> = (0..n).map(|_| make_contract(1, 1).id()).collect();
+
+        let (forest, catchup) = {
+            let mut wf = Forest::new().work_forest();
+            for item in items.iter() {
+                wf.insert(item, &hasher);
+            }
+            wf.normalize(&hasher)
+        };
+        let proofs: Vec<_> = items
+            .iter()
+            .map(|item| {
+                catchup
+                    .update_proof(item, utreexo::Proof::Transient, &hasher)
+                    .unwrap()
+            })
+            .collect();
+
+        b.iter(|| {
+            let mut wf = forest.work_forest();
+            for (item, proof) in items.iter().zip(proofs.iter()) {
+                wf.delete(item, proof, &hasher).unwrap();
+            }
+            for item in new_items.iter() {
+                wf.insert(item, &hasher);
+            }
+            wf.normalize(&hasher)
+        })
+    });
+}
+
+fn utreexo_update_100(c: &mut Criterion) {
+    utreexo_update_helper(100, c);
+}
+
+fn utreexo_update_1000(c: &mut Criterion) {
+    utreexo_update_helper(1000, c);
+}
+
+criterion_group! {
+    name = validate_block;
+    config = Criterion::default().sample_size(10);
+    targets = validate_block_100,
+        validate_block_1000,
+}
+
+criterion_group! {
+    name = utreexo_update;
+    config = Criterion::default().sample_size(10);
+    targets = utreexo_update_100,
+        utreexo_update_1000,
+}
+
+criterion_main!(validate_block, utreexo_update);
@@ -0,0 +1,214 @@
+//! Index of the issuance history of the assets.
+//!
+//! The index groups the `Issue`, `Retire` and `Burn` entries of the transaction logs
+//! by the flavor commitment, the same way as the supply audit does, and keeps
+//! the running net supply of each flavor.
+//! Transfers are detected from the outputs whose value payload reuses
+//! a flavor commitment already seen in the issuance entries. Values with blinded flavor
+//! commitments cannot be linked to their flavor and are not reported as transfers.
+
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::Identity;
+use std::collections::{HashMap, VecDeque};
+use zkvm::bulletproofs::PedersenGens;
+use zkvm::{ContractID, PortableItem, TxEntry, TxID, TxLog};
+
+use super::audit::FlavorSupply;
+use super::block::VerifiedBlock;
+use super::errors::BlockchainError;
+
+/// Default number of recent transfers kept per flavor.
+pub const DEFAULT_MAX_TRANSFERS: usize = 100;
+
+/// Index of the issuance events and recent transfers per flavor commitment.
+#[derive(Clone, Debug)]
+pub struct AssetIndex {
+    assets: HashMap<[u8; 32], AssetHistory>,
+    max_transfers: usize,
+}
+
+/// Issuance history of a single flavor.
+#[derive(Clone, Debug)]
+pub struct AssetHistory {
+    /// Flavor commitment.
+    pub flavor: CompressedRistretto,
+    /// Issuance, retirement and burn events, in the order of the blockchain.
+    pub events: Vec<AssetEvent>,
+    /// Most recent transfers of the flavor, oldest first.
+    pub transfers: VecDeque<AssetTransfer>,
+    net_qty: RistrettoPoint,
+}
+
+/// Issuance, retirement or burn of a flavor.
+#[derive(Clone, Debug)]
+pub struct AssetEvent {
+    /// Height of the block containing the transaction.
+    pub height: u64,
+    /// ID of the transaction.
+    pub txid: TxID,
+    /// Kind of the event with its quantity.
+    pub kind: AssetEventKind,
+}
+
+/// Kind of the asset event.
+#[derive(Clone, Debug)]
+pub enum AssetEventKind {
+    /// Issuance of a committed quantity.
+    Issue(CompressedRistretto),
+    /// Retirement of a committed quantity.
+    Retire(CompressedRistretto),
+    /// Retirement of a cleartext quantity.
+    Burn(u64),
+}
+
+/// Output carrying a value of the flavor.
+#[derive(Clone, Debug)]
+pub struct AssetTransfer {
+    /// Height of the block containing the transaction.
+    pub height: u64,
+    /// ID of the transaction.
+    pub txid: TxID,
+    /// ID of the output contract.
+    pub contract_id: ContractID,
+    /// Quantity commitment of the value.
+    pub qty: CompressedRistretto,
+}
+
+impl AssetIndex {
+    /// Creates an empty index.
+    pub fn new() -> Self {
+        AssetIndex {
+            assets: HashMap::new(),
+            max_transfers: DEFAULT_MAX_TRANSFERS,
+        }
+    }
+
+    /// Sets the number of recent transfers kept per flavor.
+    pub fn set_max_transfers(mut self, max_transfers: usize) -> Self {
+        self.max_transfers = max_transfers;
+        self
+    }
+
+    /// Indexes all transactions in a block.
+    pub fn index_block(&mut self, block: &VerifiedBlock) -> Result<(), BlockchainError> {
+        for vtx in block.verified_txs.iter() {
+            self.index_tx(block.header.height, vtx.id, &vtx.log)?;
+        }
+        Ok(())
+    }
+
+    /// Indexes the log of a transaction included in a block at a given height.
+    /// Fails if the log contains an invalid quantity commitment.
+    pub fn index_tx(
+        &mut self,
+        height: u64,
+        txid: TxID,
+        log: &TxLog,
+    ) -> Result<(), BlockchainError> {
+        for entry in log.iter() {
+            match entry {
+                TxEntry::Issue(qty, flv) => {
+                    let point = decompress(qty, height)?;
+                    let history = self.history_mut(*flv);
+                    history.net_qty += point;
+                    history.push_event(height, txid, AssetEventKind::Issue(*qty));
+                }
+                TxEntry::Retire(qty, flv) => {
+                    let point = decompress(qty, height)?;
+                    let history = self.history_mut(*flv);
+                    history.net_qty -= point;
+                    history.push_event(height, txid, AssetEventKind::Retire(*qty));
+                }
+                TxEntry::Burn(qty, flv) => {
+                    let gens = PedersenGens::default();
+                    let history = self.history_mut((flv * gens.B).compress());
+                    history.net_qty -= Scalar::from(*qty) * gens.B;
+                    history.push_event(height, txid, AssetEventKind::Burn(*qty));
+                }
+                TxEntry::Output(contract) => {
+                    let max_transfers = self.max_transfers;
+                    for value in contract.payload.iter().filter_map(PortableItem::as_value) {
+                        if let Some(history) = self.assets.get_mut(&value.flv.to_point().to_bytes())
+                        {
+                            if history.transfers.len() >= max_transfers {
+                                history.transfers.pop_front();
+                            }
+                            if max_transfers > 0 {
+                                history.transfers.push_back(AssetTransfer {
+                                    height,
+                                    txid,
+                                    contract_id: contract.id(),
+                                    qty: value.qty.to_point(),
+                                });
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the history of a flavor commitment, if it was ever issued or retired.
+    pub fn history(&self, flavor: &CompressedRistretto) -> Option<&AssetHistory> {
+        self.assets.get(flavor.as_bytes())
+    }
+
+    /// Iterates over the histories of all indexed flavors.
+    pub fn assets(&self) -> impl Iterator<Item = &AssetHistory> {
+        self.assets.values()
+    }
+
+    fn history_mut(&mut self, flavor: CompressedRistretto) -> &mut AssetHistory {
+        self.assets
+            .entry(flavor.to_bytes())
+            .or_insert_with(|| AssetHistory {
+                flavor,
+                events: Vec::new(),
+                transfers: VecDeque::new(),
+                net_qty: RistrettoPoint::identity(),
+            })
+    }
+}
+
+impl Default for AssetIndex {
+    fn default() -> Self {
+        AssetIndex::new()
+    }
+}
+
+impl AssetHistory {
+    /// Returns the current net supply of the flavor.
+    pub fn supply(&self) -> FlavorSupply {
+        let mut supply = FlavorSupply {
+            flavor: self.flavor,
+            issuances: 0,
+            retirements: 0,
+            burned: 0,
+            net_qty: self.net_qty.compress(),
+        };
+        for event in self.events.iter() {
+            match event.kind {
+                AssetEventKind::Issue(_) => supply.issuances += 1,
+                AssetEventKind::Retire(_) => supply.retirements += 1,
+                AssetEventKind::Burn(qty) => {
+                    supply.retirements += 1;
+                    supply.burned = supply.burned.saturating_add(qty);
+                }
+            }
+        }
+        supply
+    }
+
+    fn push_event(&mut self, height: u64, txid: TxID, kind: AssetEventKind) {
+        self.events.push(AssetEvent { height, txid, kind });
+    }
+}
+
+fn decompress(point: &CompressedRistretto, height: u64) -> Result<RistrettoPoint, BlockchainError> {
+    point
+        .decompress()
+        .ok_or(BlockchainError::AuditMismatch(height))
+}
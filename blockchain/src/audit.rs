@@ -0,0 +1,162 @@
+//! Audit of the blockchain state against the transaction logs of its blocks.
+//!
+//! Issuance entries commit to the flavor with an unblinded commitment `flavor·B`,
+//! so the issued quantities can be grouped per flavor. Quantities remain committed:
+//! the audit sums the quantity commitments, and the issuer (or anyone who knows the clear amounts)
+//! can check the net supply against the expected one.
+//! Retirements are grouped by their flavor commitment too: retirements of values
+//! with blinded flavor commitments appear under their own, unique commitments.
+//! Burn entries reveal the retired quantity and the flavor in the clear,
+//! so the burned supply of each flavor is counted without knowing any blinding factors.
+
+use core::borrow::Borrow;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::Identity;
+use std::collections::BTreeMap;
+use zkvm::bulletproofs::PedersenGens;
+use zkvm::TxEntry;
+
+use super::block::VerifiedBlock;
+use super::errors::BlockchainError;
+use super::state::BlockchainState;
+
+/// Result of the blockchain state audit.
+#[derive(Clone, Debug)]
+pub struct SupplyAudit {
+    /// Height of the first audited block.
+    pub from_height: u64,
+    /// Height of the last audited block.
+    pub tip_height: u64,
+    /// Number of utxos, matching the utreexo state.
+    pub utxo_count: u64,
+    /// Net supply per flavor commitment, ordered by the commitment.
+    pub flavors: Vec<FlavorSupply>,
+    /// Total amount of fees paid in the audited blocks.
+    pub fees: u64,
+}
+
+/// Net supply of a single flavor in the audited blocks.
+#[derive(Clone, Debug)]
+pub struct FlavorSupply {
+    /// Flavor commitment.
+    pub flavor: CompressedRistretto,
+    /// Number of issuance entries.
+    pub issuances: usize,
+    /// Number of retirement entries, including the burn entries.
+    pub retirements: usize,
+    /// Total cleartext quantity retired by the burn entries.
+    pub burned: u64,
+    /// Sum of the issued quantity commitments minus the sum of the retired ones.
+    pub net_qty: CompressedRistretto,
+}
+
+impl FlavorSupply {
+    /// Checks that the net supply of the flavor commits to a given clear quantity
+    /// with a given total blinding factor.
+    /// If all issued and retired quantities are unblinded, the blinding factor is zero.
+    pub fn verify_net_qty(&self, qty: u64, blinding: Scalar) -> bool {
+        PedersenGens::default()
+            .commit(Scalar::from(qty), blinding)
+            .compress()
+            == self.net_qty
+    }
+}
+
+impl BlockchainState {
+    /// Audits the state against the transaction logs of the blocks applied since an earlier state.
+    /// Blocks must follow the `from` state up to the current tip.
+    /// Fails if the blocks do not form the chain to the current tip,
+    /// or if the number of utxos in the utreexo state does not match the transaction logs.
+    pub fn audit<I>(
+        &self,
+        from: &BlockchainState,
+        blocks: I,
+    ) -> Result<SupplyAudit, BlockchainError>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<VerifiedBlock>,
+    {
+        let mut prev = from.tip.clone();
+        let mut utxo_count = from.utreexo.count();
+        let mut flavors = BTreeMap::<[u8; 32], (usize, usize, u64, RistrettoPoint)>::new();
+        let mut fees = 0u64;
+
+        for block in blocks {
+            let block = block.borrow();
+            if block.header.height != prev.height + 1 || block.header.prev != prev.id() {
+                return Err(BlockchainError::InconsistentHeader);
+            }
+            for entry in block.verified_txs.iter().flat_map(|vtx| vtx.log.iter()) {
+                match entry {
+                    TxEntry::Input(_) => {
+                        utxo_count = utxo_count
+                            .checked_sub(1)
+                            .ok_or(BlockchainError::AuditMismatch(block.header.height))?;
+                    }
+                    TxEntry::Output(_) => utxo_count += 1,
+                    TxEntry::Issue(qty, flv) => {
+                        let supply = flavors.entry(flv.to_bytes()).or_insert(empty_supply());
+                        supply.0 += 1;
+                        supply.3 += decompress(qty, block.header.height)?;
+                    }
+                    TxEntry::Retire(qty, flv) => {
+                        let supply = flavors.entry(flv.to_bytes()).or_insert(empty_supply());
+                        supply.1 += 1;
+                        supply.3 -= decompress(qty, block.header.height)?;
+                    }
+                    TxEntry::Burn(qty, flv) => {
+                        let gens = PedersenGens::default();
+                        let flv = (flv * gens.B).compress();
+                        let supply = flavors.entry(flv.to_bytes()).or_insert(empty_supply());
+                        supply.1 += 1;
+                        supply.2 = supply.2.saturating_add(*qty);
+                        supply.3 -= Scalar::from(*qty) * gens.B;
+                    }
+                    TxEntry::Fee(fee) => fees += fee,
+                    _ => {}
+                }
+            }
+            if block.utreexo.count() != utxo_count {
+                return Err(BlockchainError::AuditMismatch(block.header.height));
+            }
+            prev = block.header.clone();
+        }
+
+        if prev != self.tip {
+            return Err(BlockchainError::InconsistentHeader);
+        }
+        if self.utreexo.count() != utxo_count {
+            return Err(BlockchainError::AuditMismatch(self.tip.height));
+        }
+
+        Ok(SupplyAudit {
+            from_height: from.tip.height + 1,
+            tip_height: self.tip.height,
+            utxo_count,
+            flavors: flavors
+                .into_iter()
+                .map(
+                    |(flavor, (issuances, retirements, burned, net_qty))| FlavorSupply {
+                        flavor: CompressedRistretto(flavor),
+                        issuances,
+                        retirements,
+                        burned,
+                        net_qty: net_qty.compress(),
+                    },
+                )
+                .collect(),
+            fees,
+        })
+    }
+}
+
+fn empty_supply() -> (usize, usize, u64, RistrettoPoint) {
+    (0, 0, 0, RistrettoPoint::identity())
+}
+
+fn decompress(point: &CompressedRistretto, height: u64) -> Result<RistrettoPoint, BlockchainError> {
+    point
+        .decompress()
+        .ok_or(BlockchainError::AuditMismatch(height))
+}
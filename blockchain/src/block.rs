@@ -1,9 +1,15 @@
 use merlin::Transcript;
-use serde::{Deserialize, Serialize};
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
+use zkvm::bulletproofs::BulletproofGens;
 use zkvm::encoding::*;
-use zkvm::{merkle, Hash, MerkleItem, MerkleTree, Tx, VerifiedTx};
+use zkvm::{
+    merkle, ContractID, Hash, MerkleItem, MerkleTree, Tx, TxEntry, TxID, TxWireHash, VerifiedTx,
+};
 
+use super::errors::BlockchainError;
 use super::state::BlockchainState;
 use super::utreexo::{self, Proof};
 use readerwriter::Encodable;
@@ -11,16 +17,39 @@ use readerwriter::Encodable;
 /// Identifier of the block, computed as a hash of the `BlockHeader`.
 #[derive(Clone, Copy, PartialEq, Default)]
 pub struct BlockID(pub [u8; 32]);
-serialize_bytes32!(BlockID);
+bytes32_id!(BlockID);
 
 /// Witness hash of the transaction that commits to all signatures and proofs.
 #[derive(Clone, Copy, PartialEq, Default)]
 pub struct WitnessHash(pub [u8; 32]);
-serialize_bytes32!(WitnessHash);
+bytes32_id!(WitnessHash);
+
+/// First version of the block header that commits to the auxiliary structures in the `ext` field.
+pub const BLOCK_VERSION_AUX: u64 = 2;
+
+/// First version of the block that requires the canonical order of the transactions
+/// (see `canonical_order`).
+pub const BLOCK_VERSION_ORDERED: u64 = 3;
+
+/// First version of the block header that commits to the transaction IDs in the `txidroot` field.
+pub const BLOCK_VERSION_TXIDROOT: u64 = 4;
+
+/// First version of the block that limits the total weight of the transactions
+/// to `MAX_BLOCK_WEIGHT`.
+pub const BLOCK_VERSION_WEIGHT: u64 = 4;
+
+/// Kind of the auxiliary commitment to the compact block filter (`BlockFilter::hash`).
+pub const AUX_BLOCK_FILTER: u64 = 1;
+
+/// Maximum total weight of the transactions in a block (see `zkvm::Tx::weight`).
+pub const MAX_BLOCK_WEIGHT: u64 = 4_000_000;
 
 /// BlockHeader contains the metadata for the block of transactions,
 /// committing to them, but not containing the actual transactions.
-#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+/// In binary serde formats (such as the bincode files of the node), the `txidroot`
+/// is serialized only since `BLOCK_VERSION_TXIDROOT`, like in the consensus encoding:
+/// the headers stored before the field was added remain readable.
+#[derive(Clone, PartialEq, Debug)]
 pub struct BlockHeader {
     /// Network version.
     pub version: u64,
@@ -33,12 +62,29 @@ pub struct BlockHeader {
     pub timestamp_ms: u64,
     /// 32-byte Merkle root of the transaction witness hashes (`BlockTx::witness_hash`) in the block.
     pub txroot: Hash,
+    /// 32-byte Merkle root of the transaction IDs in the block.
+    /// Allows proving inclusion of a transaction by its ID.
+    /// Since `BLOCK_VERSION_TXIDROOT`; in earlier versions it is all-zero and is neither encoded nor committed to.
+    pub txidroot: Hash,
     /// 32-byte Merkle root of the Utreexo state.
     pub utxoroot: Hash,
     /// Extra data for the future extensions.
+    /// Since `BLOCK_VERSION_AUX`, starts with the 32-byte Merkle root of the auxiliary commitments.
     pub ext: Vec<u8>,
 }
 
+/// Commitment to an auxiliary structure of the block: filter, checkpoint, fee stats etc.
+/// The block carries its commitments ordered by kind, and the header commits to them
+/// under a Merkle root. Nodes check the commitments of the kinds they know and ignore the rest,
+/// so new kinds can be added without changing the header format.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuxCommitment {
+    /// Kind of the committed structure, e.g. `AUX_BLOCK_FILTER`.
+    pub kind: u64,
+    /// Hash of the committed structure.
+    pub hash: Hash,
+}
+
 /// Transaction annotated with Utreexo proofs.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct BlockTx {
@@ -62,6 +108,8 @@ pub struct VerifiedBlock {
     pub raw_txs: Vec<BlockTx>,
     /// List of verified transactions
     pub verified_txs: Vec<VerifiedTx>,
+    /// Auxiliary commitments, ordered by kind
+    pub aux: Vec<AuxCommitment>,
 }
 
 impl BlockHeader {
@@ -73,6 +121,9 @@ impl BlockHeader {
         t.append_message(b"previd", &self.prev.0);
         t.append_u64(b"timestamp_ms", self.timestamp_ms);
         t.append_message(b"txroot", &self.txroot.0);
+        if self.version >= BLOCK_VERSION_TXIDROOT {
+            t.append_message(b"txidroot", &self.txidroot.0);
+        }
         t.append_message(b"utxoroot", &self.utxoroot.0);
         t.append_message(b"ext", &self.ext);
 
@@ -89,10 +140,119 @@ impl BlockHeader {
             prev: BlockID([0; 32]),
             timestamp_ms,
             txroot: MerkleTree::empty_root(b"ZkVM.txroot"),
+            txidroot: Hash::default(),
             utxoroot,
             ext: Vec::new(),
         }
     }
+
+    /// Verifies that the transaction with a given ID is included in the block
+    /// using the Merkle path to the `txidroot`.
+    /// Returns false if the header version precedes `BLOCK_VERSION_TXIDROOT`.
+    pub fn verify_tx_inclusion(&self, txid: &TxID, path: &merkle::Path) -> bool {
+        self.version >= BLOCK_VERSION_TXIDROOT
+            && path.verify_root(&self.txidroot, txid, &txidroot_hasher())
+    }
+
+    /// Returns the Merkle root of the auxiliary commitments,
+    /// or `None` if the header version precedes `BLOCK_VERSION_AUX`.
+    /// The bytes of `ext` after the root are reserved for the future extensions.
+    pub fn auxroot(&self) -> Option<Hash> {
+        if self.version < BLOCK_VERSION_AUX || self.ext.len() < 32 {
+            return None;
+        }
+        let mut root = [0u8; 32];
+        root.copy_from_slice(&self.ext[..32]);
+        Some(Hash(root))
+    }
+
+    /// Verifies that the auxiliary commitment is included in the block
+    /// using the Merkle path to the `auxroot`.
+    pub fn verify_aux_commitment(&self, commitment: &AuxCommitment, path: &merkle::Path) -> bool {
+        match self.auxroot() {
+            Some(root) => path.verify_root(&root, commitment, &auxroot_hasher()),
+            None => false,
+        }
+    }
+}
+
+impl VerifiedBlock {
+    /// Returns the total weight of the transactions in the block.
+    pub fn weight(&self) -> u64 {
+        self.verified_txs.iter().map(|vtx| vtx.weight()).sum()
+    }
+
+    /// Computes the auxiliary commitments of the kinds known to this implementation.
+    pub fn known_aux_commitments(&self) -> Vec<AuxCommitment> {
+        vec![AuxCommitment {
+            kind: AUX_BLOCK_FILTER,
+            hash: self.filter().hash(),
+        }]
+    }
+
+    /// Checks the auxiliary commitments received with the block against its header,
+    /// and attaches them to the block.
+    /// Commitments must be ordered by kind; commitments of the unknown kinds are not checked.
+    pub fn set_aux_commitments(&mut self, aux: Vec<AuxCommitment>) -> Result<(), BlockchainError> {
+        let auxroot = match self.header.auxroot() {
+            Some(root) => root,
+            None if aux.is_empty() => return Ok(()),
+            None => return Err(BlockchainError::InvalidAuxCommitment),
+        };
+        if aux.windows(2).any(|pair| pair[0].kind >= pair[1].kind)
+            || compute_auxroot(&aux) != auxroot
+        {
+            return Err(BlockchainError::InvalidAuxCommitment);
+        }
+        for known in self.known_aux_commitments() {
+            match aux.iter().find(|c| c.kind == known.kind) {
+                Some(c) if c.hash == known.hash => {}
+                _ => return Err(BlockchainError::InvalidAuxCommitment),
+            }
+        }
+        self.aux = aux;
+        Ok(())
+    }
+
+    /// Creates a Merkle path proving inclusion of the auxiliary commitment
+    /// of a given kind in the block's `auxroot`.
+    pub fn aux_commitment_proof(&self, kind: u64) -> Option<merkle::Path> {
+        let index = self.aux.iter().position(|c| c.kind == kind)?;
+        merkle::Path::new(&self.aux, index, &auxroot_hasher())
+    }
+
+    /// Creates a Merkle path proving inclusion of the transaction
+    /// at a given index in the block's `txidroot`.
+    /// Returns `None` if the header version precedes `BLOCK_VERSION_TXIDROOT`.
+    pub fn tx_inclusion_proof(&self, index: usize) -> Option<merkle::Path> {
+        if self.header.version < BLOCK_VERSION_TXIDROOT || index >= self.verified_txs.len() {
+            return None;
+        }
+        let txids = self
+            .verified_txs
+            .iter()
+            .map(|vtx| vtx.id)
+            .collect::<Vec<_>>();
+        merkle::Path::new(&txids, index, &txidroot_hasher())
+    }
+}
+
+/// Computes the Merkle root of the transaction IDs in the block.
+pub(crate) fn compute_txidroot(txids: impl IntoIterator<Item = TxID>) -> Hash {
+    MerkleTree::root(b"ZkVM.txidroot", txids)
+}
+
+pub(crate) fn txidroot_hasher() -> merkle::Hasher<TxID> {
+    merkle::Hasher::new(b"ZkVM.txidroot")
+}
+
+/// Computes the Merkle root of the auxiliary commitments.
+pub(crate) fn compute_auxroot(aux: &[AuxCommitment]) -> Hash {
+    MerkleTree::root(b"ZkVM.auxroot", aux.iter())
+}
+
+fn auxroot_hasher() -> merkle::Hasher<AuxCommitment> {
+    merkle::Hasher::new(b"ZkVM.auxroot")
 }
 
 impl BlockTx {
@@ -104,6 +264,29 @@ impl BlockTx {
         t.challenge_bytes(b"hash", &mut result);
         WitnessHash(result)
     }
+
+    /// Verifies the transaction and checks that all its inputs are present in the utreexo.
+    /// All proofs must be committed: transient proofs are only valid within a block.
+    pub fn verify_against_utreexo(
+        &self,
+        utreexo: &utreexo::Forest,
+        bp_gens: &BulletproofGens,
+    ) -> Result<VerifiedTx, BlockchainError> {
+        let verified_tx = self.tx.verify(bp_gens)?;
+        let hasher = utreexo::utreexo_hasher::<ContractID>();
+        let mut proofs = self.proofs.iter();
+        for entry in verified_tx.log.iter() {
+            if let TxEntry::Input(contract_id) = entry {
+                let path = proofs
+                    .next()
+                    .ok_or(BlockchainError::UtreexoProofMissing)?
+                    .as_path()
+                    .ok_or(utreexo::UtreexoError::InvalidProof)?;
+                utreexo.verify(contract_id, path, &hasher)?;
+            }
+        }
+        Ok(verified_tx)
+    }
 }
 
 impl Encodable for BlockHeader {
@@ -113,6 +296,9 @@ impl Encodable for BlockHeader {
         w.write(b"prev", &self.prev)?;
         w.write_u64(b"timestamp_ms", self.timestamp_ms)?;
         w.write(b"txroot", &self.txroot)?;
+        if self.version >= BLOCK_VERSION_TXIDROOT {
+            w.write(b"txidroot", &self.txidroot)?;
+        }
         w.write(b"utxoroot", &self.utxoroot)?;
         w.write_u32(b"ext_len", self.ext.len() as u32)?;
         w.write(b"ext", &self.ext)?;
@@ -122,18 +308,29 @@ impl Encodable for BlockHeader {
 
 impl ExactSizeEncodable for BlockHeader {
     fn encoded_size(&self) -> usize {
-        8 + 8 + 32 + 8 + 32 + 32 + 4 + self.ext.len()
+        let txidroot_size = if self.version >= BLOCK_VERSION_TXIDROOT {
+            32
+        } else {
+            0
+        };
+        8 + 8 + 32 + 8 + 32 + txidroot_size + 32 + 4 + self.ext.len()
     }
 }
 
 impl Decodable for BlockHeader {
     fn decode(buf: &mut impl Reader) -> Result<Self, ReadError> {
+        let version = buf.read_u64()?;
         Ok(BlockHeader {
-            version: buf.read_u64()?,
+            version,
             height: buf.read_u64()?,
             prev: buf.read_u8x32().map(BlockID)?,
             timestamp_ms: buf.read_u64()?,
             txroot: buf.read_u8x32().map(Hash)?,
+            txidroot: if version >= BLOCK_VERSION_TXIDROOT {
+                buf.read_u8x32().map(Hash)?
+            } else {
+                Hash::default()
+            },
             utxoroot: buf.read_u8x32().map(Hash)?,
             ext: {
                 let n = buf.read_u32()? as usize;
@@ -143,6 +340,117 @@ impl Decodable for BlockHeader {
     }
 }
 
+impl Serialize for BlockHeader {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let with_txidroot =
+            serializer.is_human_readable() || self.version >= BLOCK_VERSION_TXIDROOT;
+        let len = if with_txidroot { 8 } else { 7 };
+        let mut s = serializer.serialize_struct("BlockHeader", len)?;
+        s.serialize_field("version", &self.version)?;
+        s.serialize_field("height", &self.height)?;
+        s.serialize_field("prev", &self.prev)?;
+        s.serialize_field("timestamp_ms", &self.timestamp_ms)?;
+        s.serialize_field("txroot", &self.txroot)?;
+        if with_txidroot {
+            s.serialize_field("txidroot", &self.txidroot)?;
+        } else {
+            s.skip_field("txidroot")?;
+        }
+        s.serialize_field("utxoroot", &self.utxoroot)?;
+        s.serialize_field("ext", &self.ext)?;
+        s.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for BlockHeader {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        const FIELDS: &[&str] = &[
+            "version",
+            "height",
+            "prev",
+            "timestamp_ms",
+            "txroot",
+            "txidroot",
+            "utxoroot",
+            "ext",
+        ];
+
+        struct HeaderVisitor;
+
+        impl<'de> Visitor<'de> for HeaderVisitor {
+            type Value = BlockHeader;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a block header")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<BlockHeader, A::Error> {
+                fn next<'de, A: SeqAccess<'de>, T: Deserialize<'de>>(
+                    seq: &mut A,
+                    index: usize,
+                ) -> Result<T, A::Error> {
+                    seq.next_element()?
+                        .ok_or_else(|| de::Error::invalid_length(index, &"a block header"))
+                }
+                let version: u64 = next(&mut seq, 0)?;
+                Ok(BlockHeader {
+                    version,
+                    height: next(&mut seq, 1)?,
+                    prev: next(&mut seq, 2)?,
+                    timestamp_ms: next(&mut seq, 3)?,
+                    txroot: next(&mut seq, 4)?,
+                    txidroot: if version >= BLOCK_VERSION_TXIDROOT {
+                        next(&mut seq, 5)?
+                    } else {
+                        Hash::default()
+                    },
+                    utxoroot: next(&mut seq, 6)?,
+                    ext: next(&mut seq, 7)?,
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<BlockHeader, A::Error> {
+                let mut version = None;
+                let mut height = None;
+                let mut prev = None;
+                let mut timestamp_ms = None;
+                let mut txroot = None;
+                let mut txidroot = None;
+                let mut utxoroot = None;
+                let mut ext = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "version" => version = Some(map.next_value()?),
+                        "height" => height = Some(map.next_value()?),
+                        "prev" => prev = Some(map.next_value()?),
+                        "timestamp_ms" => timestamp_ms = Some(map.next_value()?),
+                        "txroot" => txroot = Some(map.next_value()?),
+                        "txidroot" => txidroot = Some(map.next_value()?),
+                        "utxoroot" => utxoroot = Some(map.next_value()?),
+                        "ext" => ext = Some(map.next_value()?),
+                        _ => {
+                            map.next_value::<de::IgnoredAny>()?;
+                        }
+                    }
+                }
+                Ok(BlockHeader {
+                    version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+                    height: height.ok_or_else(|| de::Error::missing_field("height"))?,
+                    prev: prev.ok_or_else(|| de::Error::missing_field("prev"))?,
+                    timestamp_ms: timestamp_ms
+                        .ok_or_else(|| de::Error::missing_field("timestamp_ms"))?,
+                    txroot: txroot.ok_or_else(|| de::Error::missing_field("txroot"))?,
+                    txidroot: txidroot.unwrap_or_default(),
+                    utxoroot: utxoroot.ok_or_else(|| de::Error::missing_field("utxoroot"))?,
+                    ext: ext.ok_or_else(|| de::Error::missing_field("ext"))?,
+                })
+            }
+        }
+
+        deserializer.deserialize_struct("BlockHeader", FIELDS, HeaderVisitor)
+    }
+}
+
 impl Encodable for BlockTx {
     fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
         self.tx.encode(w)?;
@@ -197,21 +505,38 @@ impl MerkleItem for WitnessHash {
     }
 }
 
-impl AsRef<[u8]> for WitnessHash {
-    fn as_ref(&self) -> &[u8] {
-        &self.0
+impl MerkleItem for AuxCommitment {
+    fn commit(&self, t: &mut Transcript) {
+        t.append_u64(b"kind", self.kind);
+        t.append_message(b"hash", &self.hash.0);
     }
 }
 
-impl core::ops::Deref for WitnessHash {
-    type Target = [u8];
-    fn deref(&self) -> &Self::Target {
-        &self.0
+impl Encodable for AuxCommitment {
+    fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
+        w.write_u64(b"kind", self.kind)?;
+        w.write(b"hash", &self.hash)
+    }
+}
+
+impl ExactSizeEncodable for AuxCommitment {
+    fn encoded_size(&self) -> usize {
+        8 + 32
     }
 }
 
-impl AsRef<[u8]> for BlockID {
-    fn as_ref(&self) -> &[u8] {
+impl Decodable for AuxCommitment {
+    fn decode(r: &mut impl Reader) -> Result<Self, ReadError> {
+        Ok(AuxCommitment {
+            kind: r.read_u64()?,
+            hash: r.read_u8x32().map(Hash)?,
+        })
+    }
+}
+
+impl core::ops::Deref for WitnessHash {
+    type Target = [u8];
+    fn deref(&self) -> &Self::Target {
         &self.0
     }
 }
@@ -247,4 +572,18 @@ impl VerifiedBlock {
             utreexo: self.utreexo.clone(),
         }
     }
+
+    /// Returns the index of the transaction with a given ID in the block.
+    pub fn tx_index(&self, txid: &TxID) -> Option<usize> {
+        self.verified_txs.iter().position(|vtx| &vtx.id == txid)
+    }
+
+    /// Returns the index of the transaction with a given wire hash in the block.
+    /// The transaction may be included in a different encoding than the one relayed to the wallet,
+    /// so wallets should track their transactions with `tx_index` instead.
+    pub fn tx_index_by_wire_hash(&self, wire_hash: &TxWireHash) -> Option<usize> {
+        self.raw_txs
+            .iter()
+            .position(|btx| &btx.tx.wire_hash() == wire_hash)
+    }
 }
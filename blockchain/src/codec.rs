@@ -1,11 +1,69 @@
+//! Binary encoding of the protocol messages.
+//!
+//! Every message is encoded as `version || message_type || body`, where `version`
+//! is `WIRE_VERSION`, `message_type` is a byte identifying the message and the body
+//! contains the fields of the message in the order of their declaration:
+//!
+//! * integers are encoded as little-endian,
+//! * fixed-size byte arrays (IDs, hashes, signatures) are written as-is,
+//! * variable-length lists are prefixed with their LE32 number of items.
+//!
+//! Blocks of version `BLOCK_VERSION_AUX` and higher are followed by the list of their auxiliary commitments.
+//!
+//! Versions are numbered from `0x80` so they never collide with the message types:
+//! the messages of legacy peers start with the message type and are decoded as such.
+//!
+//! `encode_message` and `decode_message` additionally prefix the message with its LE32 length,
+//! so messages can be delimited in a byte stream.
+//!
+//! Legacy peers reject the versioned messages, so the messages sent to peers
+//! (encoded with `Encodable`) omit the version unless the `wire-version` feature is enabled.
+//! Enabling it is a hard fork of the p2p network: nodes built with it can only
+//! talk to the nodes that decode the versioned messages, i.e. the nodes of this or later releases.
+
 use crate::shortid::ShortIDVec;
 use crate::{
-    Block, BlockHeader, BlockID, BlockTx, GetBlock, GetInventory, GetMempoolTxs, Inventory,
-    MempoolTxs, Message,
+    AuxCommitment, Block, BlockHeader, BlockID, BlockTx, BlockUnavailable, Checkpoint, Compressed,
+    Finality, GetBlock, GetHeaders, GetInventory, GetMempoolTxs, GetTxs, Headers, Inventory,
+    MempoolTxs, Message, ShortIDCollision, BLOCK_VERSION_AUX,
 };
 use readerwriter::{Decodable, Encodable, ReadError, Reader, WriteError, Writer};
 use std::convert::TryFrom;
-use zkvm::{Hash, Signature};
+use zkvm::{Hash, Signature, TxID};
+
+/// Version of the binary encoding of the protocol messages.
+/// Has the high bit set to be distinct from any message type.
+pub const WIRE_VERSION: u8 = 0x80;
+
+/// Bytes below this value are message types sent without the version by legacy peers.
+const MIN_WIRE_VERSION: u8 = 0x80;
+
+/// Encodes the message as `length || version || message_type || body`,
+/// where `length` is the LE32 length of the rest of the encoding.
+pub fn encode_message(message: &Message) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.push(WIRE_VERSION);
+    message
+        .encode_unversioned(&mut body)
+        .expect("Writing to a Vec never fails.");
+    let mut bytes = Vec::with_capacity(4 + body.len());
+    bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&body);
+    bytes
+}
+
+/// Decodes the message encoded with `encode_message`.
+/// Fails if the length prefix does not match the encoding or there are trailing bytes.
+pub fn decode_message(bytes: &[u8]) -> Result<Message, ReadError> {
+    let mut src = bytes;
+    src.read_all(|r| {
+        let len = r.read_u32()? as usize;
+        if len != r.remaining_bytes() {
+            return Err(ReadError::InvalidFormat);
+        }
+        Message::decode(r)
+    })
+}
 
 #[repr(u8)]
 enum MessageType {
@@ -15,6 +73,13 @@ enum MessageType {
     GetInventory = 3,
     MempoolTxs = 4,
     GetMempoolTxs = 5,
+    Finality = 6,
+    ShortIDCollision = 7,
+    GetTxs = 8,
+    BlockUnavailable = 9,
+    Compressed = 10,
+    GetHeaders = 11,
+    Headers = 12,
 }
 
 impl TryFrom<u8> for MessageType {
@@ -28,6 +93,13 @@ impl TryFrom<u8> for MessageType {
             3 => Ok(MessageType::GetInventory),
             4 => Ok(MessageType::MempoolTxs),
             5 => Ok(MessageType::GetMempoolTxs),
+            6 => Ok(MessageType::Finality),
+            7 => Ok(MessageType::ShortIDCollision),
+            8 => Ok(MessageType::GetTxs),
+            9 => Ok(MessageType::BlockUnavailable),
+            10 => Ok(MessageType::Compressed),
+            11 => Ok(MessageType::GetHeaders),
+            12 => Ok(MessageType::Headers),
             _ => Err(ReadError::Custom(
                 format!("unknown message type: {}", value).into(),
             )),
@@ -42,6 +114,7 @@ impl Encodable for Inventory {
         w.write_signature(&self.tip_signature)?;
         w.write_u64(b"shortid_nonce", self.shortid_nonce)?;
         w.write_shortid_vec(b"shortid_list", &self.shortid_list)?;
+        w.write_u64(b"features", self.features)?;
         Ok(())
     }
 }
@@ -54,10 +127,19 @@ impl Decodable for Inventory {
             tip_signature: buf.read_signature()?,
             shortid_nonce: buf.read_u64()?,
             shortid_list: buf.read_shortid_vec()?,
+            features: read_features(buf)?,
         })
     }
 }
 
+/// Reads the trailing features bitmask that legacy peers do not send.
+fn read_features(src: &mut impl Reader) -> Result<u64, ReadError> {
+    if src.remaining_bytes() == 0 {
+        return Ok(0);
+    }
+    src.read_u64()
+}
+
 fn read_block_txs(src: &mut impl Reader) -> Result<Vec<BlockTx>, ReadError> {
     let n = src.read_u32()? as usize;
     src.read_vec(n, BlockTx::decode)
@@ -90,6 +172,11 @@ trait ReaderExt: Reader + Sized {
     fn read_hash(&mut self) -> Result<Hash, ReadError> {
         self.read_u8x32().map(Hash)
     }
+
+    fn read_txid_vec(&mut self) -> Result<Vec<TxID>, ReadError> {
+        let n = self.read_u32()? as usize;
+        self.read_vec(n, |r| r.read_hash().map(TxID))
+    }
 }
 
 trait WriterExt: Writer + Sized {
@@ -122,6 +209,14 @@ trait WriterExt: Writer + Sized {
     fn write_hash(&mut self, label: &'static [u8], hash: &Hash) -> Result<(), WriteError> {
         self.write(label, hash.0.as_ref())
     }
+
+    fn write_txid_vec(&mut self, txids: &[TxID]) -> Result<(), WriteError> {
+        self.write_u32(b"n", txids.len() as u32)?;
+        txids
+            .iter()
+            .map(|txid| self.write_hash(b"txid", &txid.0))
+            .collect()
+    }
 }
 
 impl<R: Reader> ReaderExt for R {}
@@ -133,16 +228,29 @@ impl Message {
         BlockHeader::encode(&b.header, dst)?;
         dst.write_signature(&b.signature)?;
         write_block_txs(&b.txs, dst)?;
+        if b.header.version >= BLOCK_VERSION_AUX {
+            dst.write_u32(b"n", b.aux.len() as u32)?;
+            for commitment in b.aux.iter() {
+                commitment.encode(dst)?;
+            }
+        }
         Ok(())
     }
     fn decode_block(src: &mut impl Reader) -> Result<Self, ReadError> {
         let header = BlockHeader::decode(src)?;
         let signature = src.read_signature()?;
         let txs = read_block_txs(src)?;
+        let aux = if header.version >= BLOCK_VERSION_AUX {
+            let n = src.read_u32()? as usize;
+            src.read_vec(n, AuxCommitment::decode)?
+        } else {
+            Vec::new()
+        };
         Ok(Message::Block(Block {
             header,
             signature,
             txs,
+            aux,
         }))
     }
 
@@ -154,6 +262,22 @@ impl Message {
         Ok(Message::GetBlock(GetBlock { height }))
     }
 
+    fn encode_block_unavailable(
+        b: &BlockUnavailable,
+        dst: &mut impl Writer,
+    ) -> Result<(), WriteError> {
+        dst.write_u64(b"block_height", b.height)?;
+        dst.write_u64(b"pruned_height", b.pruned_height)
+    }
+    fn decode_block_unavailable(src: &mut impl Reader) -> Result<Self, ReadError> {
+        let height = src.read_u64()?;
+        let pruned_height = src.read_u64()?;
+        Ok(Message::BlockUnavailable(BlockUnavailable {
+            height,
+            pruned_height,
+        }))
+    }
+
     fn encode_inventory(inv: &Inventory, dst: &mut impl Writer) -> Result<(), WriteError> {
         Inventory::encode(inv, dst)
     }
@@ -164,17 +288,28 @@ impl Message {
     fn encode_get_inventory(g: &GetInventory, dst: &mut impl Writer) -> Result<(), WriteError> {
         dst.write_u64(b"version", g.version)?;
         dst.write_u64(b"shortid_nonce", g.shortid_nonce)?;
+        dst.write_u64(b"features", g.features)?;
         Ok(())
     }
     fn decode_get_inventory(src: &mut impl Reader) -> Result<Self, ReadError> {
         let version = src.read_u64()?;
         let shortid_nonce = src.read_u64()?;
+        let features = read_features(src)?;
         Ok(Message::GetInventory(GetInventory {
             version,
             shortid_nonce,
+            features,
         }))
     }
 
+    fn encode_compressed(c: &Compressed, dst: &mut impl Writer) -> Result<(), WriteError> {
+        dst.write_u8_vec(b"data", &c.data)
+    }
+    fn decode_compressed(src: &mut impl Reader) -> Result<Self, ReadError> {
+        let data = src.read_u8_vec()?;
+        Ok(Message::Compressed(Compressed { data }))
+    }
+
     fn encode_mempool_txs(mempool: &MempoolTxs, dst: &mut impl Writer) -> Result<(), WriteError> {
         dst.write_blockid(b"tip", &mempool.tip)?;
         write_block_txs(&mempool.txs, dst)?;
@@ -199,6 +334,64 @@ impl Message {
             shortid_list,
         }))
     }
+
+    fn encode_finality(f: &Finality, dst: &mut impl Writer) -> Result<(), WriteError> {
+        dst.write_u64(b"height", f.checkpoint.height)?;
+        dst.write_blockid(b"block_id", &f.checkpoint.block_id)?;
+        dst.write_signature(&f.signature)?;
+        Ok(())
+    }
+    fn decode_finality(src: &mut impl Reader) -> Result<Self, ReadError> {
+        let height = src.read_u64()?;
+        let block_id = src.read_blockid()?;
+        let signature = src.read_signature()?;
+        Ok(Message::Finality(Finality {
+            checkpoint: Checkpoint { height, block_id },
+            signature,
+        }))
+    }
+
+    fn encode_shortid_collision(
+        c: &ShortIDCollision,
+        dst: &mut impl Writer,
+    ) -> Result<(), WriteError> {
+        dst.write_blockid(b"tip", &c.tip)?;
+        dst.write_txid_vec(&c.txids)?;
+        Ok(())
+    }
+    fn decode_shortid_collision(src: &mut impl Reader) -> Result<Self, ReadError> {
+        let tip = src.read_blockid()?;
+        let txids = src.read_txid_vec()?;
+        Ok(Message::ShortIDCollision(ShortIDCollision { tip, txids }))
+    }
+
+    fn encode_get_txs(g: &GetTxs, dst: &mut impl Writer) -> Result<(), WriteError> {
+        dst.write_txid_vec(&g.txids)
+    }
+    fn decode_get_txs(src: &mut impl Reader) -> Result<Self, ReadError> {
+        let txids = src.read_txid_vec()?;
+        Ok(Message::GetTxs(GetTxs { txids }))
+    }
+
+    fn encode_get_headers(g: &GetHeaders, dst: &mut impl Writer) -> Result<(), WriteError> {
+        dst.write_u64(b"from_height", g.from_height)?;
+        dst.write_u64(b"count", g.count)
+    }
+    fn decode_get_headers(src: &mut impl Reader) -> Result<Self, ReadError> {
+        let from_height = src.read_u64()?;
+        let count = src.read_u64()?;
+        Ok(Message::GetHeaders(GetHeaders { from_height, count }))
+    }
+
+    fn encode_headers(h: &Headers, dst: &mut impl Writer) -> Result<(), WriteError> {
+        dst.write_u32(b"n", h.headers.len() as u32)?;
+        h.headers.iter().map(|header| header.encode(dst)).collect()
+    }
+    fn decode_headers(src: &mut impl Reader) -> Result<Self, ReadError> {
+        let n = src.read_u32()? as usize;
+        let headers = src.read_vec(n, BlockHeader::decode)?;
+        Ok(Message::Headers(Headers { headers }))
+    }
 }
 
 impl Decodable for Message {
@@ -206,7 +399,16 @@ impl Decodable for Message {
     where
         Self: Sized,
     {
-        let message_type_byte = src.read_u8()?;
+        let first_byte = src.read_u8()?;
+        let message_type_byte = if first_byte < MIN_WIRE_VERSION {
+            first_byte
+        } else if first_byte == WIRE_VERSION {
+            src.read_u8()?
+        } else {
+            return Err(ReadError::Custom(
+                format!("unsupported wire version: {}", first_byte).into(),
+            ));
+        };
         let message_type = MessageType::try_from(message_type_byte)?;
         match message_type {
             MessageType::Block => Message::decode_block(src),
@@ -215,12 +417,29 @@ impl Decodable for Message {
             MessageType::GetInventory => Message::decode_get_inventory(src),
             MessageType::MempoolTxs => Message::decode_mempool_txs(src),
             MessageType::GetMempoolTxs => Message::decode_get_mempool_txs(src),
+            MessageType::Finality => Message::decode_finality(src),
+            MessageType::ShortIDCollision => Message::decode_shortid_collision(src),
+            MessageType::GetTxs => Message::decode_get_txs(src),
+            MessageType::BlockUnavailable => Message::decode_block_unavailable(src),
+            MessageType::Compressed => Message::decode_compressed(src),
+            MessageType::GetHeaders => Message::decode_get_headers(src),
+            MessageType::Headers => Message::decode_headers(src),
         }
     }
 }
 
 impl Encodable for Message {
     fn encode(&self, dst: &mut impl Writer) -> Result<(), WriteError> {
+        if cfg!(feature = "wire-version") {
+            dst.write_u8(b"version", WIRE_VERSION)?;
+        }
+        self.encode_unversioned(dst)
+    }
+}
+
+impl Message {
+    /// Encodes the message as `message_type || body`, the way legacy peers expect it.
+    fn encode_unversioned(&self, dst: &mut impl Writer) -> Result<(), WriteError> {
         macro_rules! typ {
             ($msg_type:expr) => {
                 dst.write_u8(b"message_type", $msg_type as u8)?;
@@ -251,6 +470,34 @@ impl Encodable for Message {
                 typ!(MessageType::GetMempoolTxs);
                 Self::encode_get_mempool_txs(g, dst)
             }
+            Message::Finality(f) => {
+                typ!(MessageType::Finality);
+                Self::encode_finality(f, dst)
+            }
+            Message::ShortIDCollision(c) => {
+                typ!(MessageType::ShortIDCollision);
+                Self::encode_shortid_collision(c, dst)
+            }
+            Message::GetTxs(g) => {
+                typ!(MessageType::GetTxs);
+                Self::encode_get_txs(g, dst)
+            }
+            Message::BlockUnavailable(b) => {
+                typ!(MessageType::BlockUnavailable);
+                Self::encode_block_unavailable(b, dst)
+            }
+            Message::Compressed(c) => {
+                typ!(MessageType::Compressed);
+                Self::encode_compressed(c, dst)
+            }
+            Message::GetHeaders(g) => {
+                typ!(MessageType::GetHeaders);
+                Self::encode_get_headers(g, dst)
+            }
+            Message::Headers(h) => {
+                typ!(MessageType::Headers);
+                Self::encode_headers(h, dst)
+            }
         }
     }
 }
@@ -258,7 +505,7 @@ impl Encodable for Message {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{utreexo, BlockHeader, BlockID, BlockTx};
+    use crate::{utreexo, BlockHeader, BlockID, BlockTx, BLOCK_VERSION_TXIDROOT};
     use curve25519_dalek::ristretto::CompressedRistretto;
     use curve25519_dalek::scalar::Scalar;
     use zkvm::bulletproofs::r1cs::R1CSProof;
@@ -273,6 +520,7 @@ mod tests {
                 prev: BlockID([2; 32]),
                 timestamp_ms: 3,
                 txroot: Hash([4; 32]),
+                txidroot: Hash::default(),
                 utxoroot: Hash([5; 32]),
                 ext: vec![6; 79],
             },
@@ -297,11 +545,12 @@ mod tests {
                 proofs: vec![
                     utreexo::Proof::Transient,
                     utreexo::Proof::Committed(zkvm::merkle::Path {
-                        position: 15,
+                        position: zkvm::merkle::Position::new(15),
                         neighbors: vec![Hash([16; 32]), Hash([17; 32])],
                     }),
                 ],
             }],
+            aux: Vec::new(),
         });
         let mut bytes = Vec::<u8>::new();
         message.clone().encode(&mut bytes).unwrap();
@@ -318,6 +567,40 @@ mod tests {
         assert_eq!(left, right);
     }
 
+    #[test]
+    fn message_block_with_aux() {
+        let message = Message::Block(Block {
+            header: BlockHeader {
+                version: BLOCK_VERSION_AUX,
+                height: 1,
+                prev: BlockID([2; 32]),
+                timestamp_ms: 3,
+                txroot: Hash([4; 32]),
+                txidroot: Hash::default(),
+                utxoroot: Hash([5; 32]),
+                ext: vec![6; 32],
+            },
+            signature: Signature {
+                s: Scalar::from_bits([7; 32]),
+                R: CompressedRistretto([8; 32]),
+            },
+            txs: Vec::new(),
+            aux: vec![
+                AuxCommitment {
+                    kind: 1,
+                    hash: Hash([9; 32]),
+                },
+                AuxCommitment {
+                    kind: 10,
+                    hash: Hash([11; 32]),
+                },
+            ],
+        });
+        let bytes = encode_message(&message);
+        let res = decode_message(&bytes).unwrap();
+        assert_eq!(format!("{:?}", message), format!("{:?}", res));
+    }
+
     #[test]
     fn message_get_block() {
         let message = Message::GetBlock(GetBlock { height: 30 });
@@ -335,4 +618,252 @@ mod tests {
         let right = format!("{:?}", res);
         assert_eq!(left, right);
     }
+
+    #[test]
+    fn message_block_unavailable() {
+        let message = Message::BlockUnavailable(BlockUnavailable {
+            height: 30,
+            pruned_height: 40,
+        });
+        let mut bytes = Vec::<u8>::new();
+        message.clone().encode(&mut bytes).unwrap();
+        let mut bytes_to_decode = bytes.as_slice();
+        let res = Message::decode(&mut bytes_to_decode).unwrap();
+        assert!(
+            bytes_to_decode.is_empty(),
+            "len = {}",
+            bytes_to_decode.len()
+        );
+
+        let left = format!("{:?}", message);
+        let right = format!("{:?}", res);
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn message_finality() {
+        let message = Message::Finality(Finality {
+            checkpoint: Checkpoint {
+                height: 40,
+                block_id: BlockID([41; 32]),
+            },
+            signature: Signature {
+                s: Scalar::from_bits([42; 32]),
+                R: CompressedRistretto([43; 32]),
+            },
+        });
+        let mut bytes = Vec::<u8>::new();
+        message.clone().encode(&mut bytes).unwrap();
+        let mut bytes_to_decode = bytes.as_slice();
+        let res = Message::decode(&mut bytes_to_decode).unwrap();
+        assert!(
+            bytes_to_decode.is_empty(),
+            "len = {}",
+            bytes_to_decode.len()
+        );
+
+        let left = format!("{:?}", message);
+        let right = format!("{:?}", res);
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn message_shortid_collision() {
+        let message = Message::ShortIDCollision(ShortIDCollision {
+            tip: BlockID([50; 32]),
+            txids: vec![TxID(Hash([51; 32])), TxID(Hash([52; 32]))],
+        });
+        let mut bytes = Vec::<u8>::new();
+        message.clone().encode(&mut bytes).unwrap();
+        let mut bytes_to_decode = bytes.as_slice();
+        let res = Message::decode(&mut bytes_to_decode).unwrap();
+        assert!(
+            bytes_to_decode.is_empty(),
+            "len = {}",
+            bytes_to_decode.len()
+        );
+
+        let left = format!("{:?}", message);
+        let right = format!("{:?}", res);
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn message_get_txs() {
+        let message = Message::GetTxs(GetTxs {
+            txids: vec![TxID(Hash([60; 32])), TxID(Hash([61; 32]))],
+        });
+        let mut bytes = Vec::<u8>::new();
+        message.clone().encode(&mut bytes).unwrap();
+        let mut bytes_to_decode = bytes.as_slice();
+        let res = Message::decode(&mut bytes_to_decode).unwrap();
+        assert!(
+            bytes_to_decode.is_empty(),
+            "len = {}",
+            bytes_to_decode.len()
+        );
+
+        let left = format!("{:?}", message);
+        let right = format!("{:?}", res);
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn message_headers() {
+        let message = Message::Headers(Headers {
+            headers: vec![
+                BlockHeader {
+                    version: 3,
+                    height: 70,
+                    prev: BlockID([71; 32]),
+                    timestamp_ms: 72,
+                    txroot: Hash([73; 32]),
+                    txidroot: Hash::default(),
+                    utxoroot: Hash([75; 32]),
+                    ext: vec![76; 32],
+                },
+                BlockHeader {
+                    version: BLOCK_VERSION_TXIDROOT,
+                    height: 71,
+                    prev: BlockID([77; 32]),
+                    timestamp_ms: 78,
+                    txroot: Hash([79; 32]),
+                    txidroot: Hash([80; 32]),
+                    utxoroot: Hash([81; 32]),
+                    ext: vec![82; 32],
+                },
+            ],
+        });
+        let mut bytes = Vec::<u8>::new();
+        message.clone().encode(&mut bytes).unwrap();
+        let mut bytes_to_decode = bytes.as_slice();
+        let res = Message::decode(&mut bytes_to_decode).unwrap();
+        assert!(
+            bytes_to_decode.is_empty(),
+            "len = {}",
+            bytes_to_decode.len()
+        );
+
+        let left = format!("{:?}", message);
+        let right = format!("{:?}", res);
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn wire_test_vectors() {
+        let vectors = vec![
+            (
+                Message::GetBlock(GetBlock { height: 30 }),
+                "0a000000\
+                 80\
+                 01\
+                 1e00000000000000",
+            ),
+            (
+                Message::GetHeaders(GetHeaders {
+                    from_height: 1,
+                    count: 2000,
+                }),
+                "12000000\
+                 80\
+                 0b\
+                 0100000000000000\
+                 d007000000000000",
+            ),
+            (
+                Message::GetInventory(GetInventory {
+                    version: 0,
+                    shortid_nonce: 0x0102030405060708,
+                    features: 1,
+                }),
+                "1a000000\
+                 80\
+                 03\
+                 0000000000000000\
+                 0807060504030201\
+                 0100000000000000",
+            ),
+            (
+                Message::BlockUnavailable(BlockUnavailable {
+                    height: 5,
+                    pruned_height: 7,
+                }),
+                "12000000\
+                 80\
+                 09\
+                 0500000000000000\
+                 0700000000000000",
+            ),
+            (
+                Message::GetTxs(GetTxs {
+                    txids: vec![TxID(Hash([0xaa; 32]))],
+                }),
+                "26000000\
+                 80\
+                 08\
+                 01000000\
+                 aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            ),
+        ];
+        for (message, expected_hex) in vectors {
+            let bytes = encode_message(&message);
+            assert_eq!(hex::encode(&bytes), expected_hex);
+            let decoded = decode_message(&bytes).unwrap();
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", message));
+        }
+    }
+
+    #[test]
+    fn wire_decodes_legacy_messages() {
+        // Legacy peers send neither the wire version nor the features.
+        let bytes = hex::decode(
+            "11000000\
+             03\
+             0000000000000000\
+             0807060504030201",
+        )
+        .unwrap();
+        match decode_message(&bytes).unwrap() {
+            Message::GetInventory(g) => {
+                assert_eq!(g.shortid_nonce, 0x0102030405060708);
+                assert_eq!(g.features, 0);
+            }
+            m => panic!("unexpected message: {:?}", m),
+        }
+    }
+
+    #[test]
+    fn peer_messages_are_readable_by_legacy_peers() {
+        let message = Message::GetBlock(GetBlock { height: 30 });
+        let bytes = message.encode_to_vec();
+        if cfg!(feature = "wire-version") {
+            assert_eq!(hex::encode(&bytes), "80011e00000000000000");
+        } else {
+            // Legacy peers read the message type first.
+            assert_eq!(hex::encode(&bytes), "011e00000000000000");
+        }
+        let decoded = (&bytes[..]).read_all(|r| Message::decode(r)).unwrap();
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", message));
+    }
+
+    #[test]
+    fn wire_rejects_malformed_messages() {
+        let bytes = encode_message(&Message::GetBlock(GetBlock { height: 30 }));
+
+        // Unsupported version
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = WIRE_VERSION + 1;
+        assert!(decode_message(&wrong_version).is_err());
+
+        // Length prefix does not match the message
+        assert!(decode_message(&bytes[..bytes.len() - 1]).is_err());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(decode_message(&trailing).is_err());
+
+        // Unknown message type
+        let mut wrong_type = bytes.clone();
+        wrong_type[5] = 0xff;
+        assert!(decode_message(&wrong_type).is_err());
+    }
 }
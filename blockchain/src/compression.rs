@@ -0,0 +1,57 @@
+//! Compression of the bulky protocol messages.
+//!
+//! Peers that set `FEATURE_COMPRESSION` in their `GetInventory` or `Inventory` messages
+//! receive `Block` and `MempoolTxs` messages wrapped in the `Compressed` message
+//! that contains the snappy-compressed encoding of the original message.
+//!
+//! Snappy prefixes the compressed data with the length of the decompressed data,
+//! so the receiver rejects the messages that would expand beyond `MAX_DECOMPRESSED_SIZE`
+//! before allocating the buffer.
+
+use readerwriter::{Decodable, Encodable, Reader};
+
+use super::errors::BlockchainError;
+use super::protocol::{Compressed, Message};
+
+/// Maximum size of the decompressed message in bytes.
+pub const MAX_DECOMPRESSED_SIZE: usize = 16 * 1024 * 1024;
+
+impl Compressed {
+    /// Compresses the message.
+    /// Returns `None` if the message is not one of the compressible kinds.
+    pub fn compress(message: &Message) -> Option<Self> {
+        if !Self::is_compressible(message) {
+            return None;
+        }
+        let mut bytes = Vec::new();
+        message.encode(&mut bytes).ok()?;
+        let data = snap::raw::Encoder::new().compress_vec(&bytes).ok()?;
+        Some(Compressed { data })
+    }
+
+    /// Decompresses the message, checking the decompressed size upfront.
+    pub fn decompress(&self) -> Result<Message, BlockchainError> {
+        let len = snap::raw::decompress_len(&self.data)
+            .map_err(|_| BlockchainError::InvalidCompressedMessage)?;
+        if len > MAX_DECOMPRESSED_SIZE {
+            return Err(BlockchainError::DecompressedMessageTooLarge(len));
+        }
+        let bytes = snap::raw::Decoder::new()
+            .decompress_vec(&self.data)
+            .map_err(|_| BlockchainError::InvalidCompressedMessage)?;
+        let message = (&bytes[..])
+            .read_all(|r| Message::decode(r))
+            .map_err(|_| BlockchainError::InvalidCompressedMessage)?;
+        if !Self::is_compressible(&message) {
+            return Err(BlockchainError::InvalidCompressedMessage);
+        }
+        Ok(message)
+    }
+
+    fn is_compressible(message: &Message) -> bool {
+        match message {
+            Message::Block(_) | Message::Headers(_) | Message::MempoolTxs(_) => true,
+            _ => false,
+        }
+    }
+}
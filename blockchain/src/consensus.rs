@@ -0,0 +1,75 @@
+//! Interface between the node and the consensus engine.
+//!
+//! The node assembles the blocks from its mempool, verifies and stores them,
+//! while the consensus engine decides which block is committed at each height.
+//! The engine drives a block through three steps: the node's block is `propose`d,
+//! the participants `prevote` for it, and the engine `commit`s it by producing
+//! the signature that is verified with the network key (e.g. an aggregated signature
+//! of the validators). Storage, mempool and synchronization do not depend on the engine.
+//!
+//! `SingleSigner` implements the stubnet mode where a single network key signs all the blocks.
+
+use starsig::{Signature, SigningKey};
+
+use super::block::BlockHeader;
+use super::protocol::{create_block_signature, BlockTemplate, Checkpoint};
+
+/// Consensus engine to which the node delegates the block production and finality.
+pub trait ConsensusDriver {
+    /// Called with the next block assembled by the node.
+    /// Returns `false` if the node may not propose a block now
+    /// (e.g. it is not the leader of the current round).
+    fn propose(&mut self, template: &BlockTemplate) -> bool;
+
+    /// Votes for the proposed block.
+    /// Returns `false` if the engine refuses the block.
+    fn prevote(&mut self, header: &BlockHeader) -> bool;
+
+    /// Returns the network signature over the block once the engine has agreed on it,
+    /// or `None` if the block is not committed yet.
+    /// Signature of a block committed later is submitted with `BlockchainProtocol::submit_signed_block`.
+    fn commit(&mut self, header: &BlockHeader) -> Option<Signature>;
+
+    /// Returns the network signature over the checkpoint if the engine considers it final.
+    fn finalize(&mut self, checkpoint: &Checkpoint) -> Option<Signature>;
+}
+
+/// Consensus of a single block signer holding the network key.
+/// The signer commits every block it votes for, and never votes for two blocks at the same height.
+#[derive(Clone)]
+pub struct SingleSigner {
+    signing_key: SigningKey,
+    last_height: u64,
+}
+
+impl SingleSigner {
+    /// Creates a signer with the network signing key.
+    pub fn new(signing_key: SigningKey) -> Self {
+        SingleSigner {
+            signing_key,
+            last_height: 0,
+        }
+    }
+}
+
+impl ConsensusDriver for SingleSigner {
+    fn propose(&mut self, _template: &BlockTemplate) -> bool {
+        true
+    }
+
+    fn prevote(&mut self, header: &BlockHeader) -> bool {
+        header.height > self.last_height
+    }
+
+    fn commit(&mut self, header: &BlockHeader) -> Option<Signature> {
+        if !self.prevote(header) {
+            return None;
+        }
+        self.last_height = header.height;
+        Some(create_block_signature(header, self.signing_key))
+    }
+
+    fn finalize(&mut self, checkpoint: &Checkpoint) -> Option<Signature> {
+        Some(checkpoint.sign(self.signing_key))
+    }
+}
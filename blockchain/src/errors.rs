@@ -1,19 +1,26 @@
 use crate::utreexo::UtreexoError;
 use crate::BlockID;
 use thiserror::Error;
-use zkvm::VMError;
+use zkvm::{ContractID, TxID, VMError};
+
+pub use zkvm::FailureClass;
 
 /// Blockchain state machine error conditions.
-#[derive(Debug, Error)]
+#[derive(Clone, Debug, Error)]
 pub enum BlockchainError {
     /// Occurs when the header contains inconsistent data.
     #[error("Inconsistent data in the block header.")]
     InconsistentHeader,
 
-    /// Occurs when extension field is non-empty in v1 blocks.
-    #[error("Extension field must be empty in v1 blocks.")]
+    /// Occurs when extension field is non-empty in v1 blocks,
+    /// or does not contain the auxiliary root in the later versions.
+    #[error("Extension field is malformed for the block version.")]
     IllegalExtension,
 
+    /// Auxiliary commitments do not match the block header or the block contents.
+    #[error("Auxiliary commitments do not match the block.")]
+    InvalidAuxCommitment,
+
     /// Occurs when block timestamp is outside the tx time bounds.
     #[error("Block timestamp is outside the transaction time bounds.")]
     BadTxTimestamp,
@@ -24,7 +31,7 @@ pub enum BlockchainError {
 
     /// Occurs when ZkVM failed executing the transaction.
     #[error("Transaction validation failed in ZkVM.")]
-    VMError(VMError),
+    VMError(#[from] VMError),
 
     /// Occurs when utreexo proof is missing.
     #[error("Utreexo proof is missing.")]
@@ -32,7 +39,7 @@ pub enum BlockchainError {
 
     /// Occurs when utreexo operation failed.
     #[error("Utreexo operation failed.")]
-    UtreexoError(UtreexoError),
+    UtreexoError(#[from] UtreexoError),
 
     /// Block signature is invalid.
     #[error("Block signature is invalid.")]
@@ -50,19 +57,105 @@ pub enum BlockchainError {
     #[error("Block at height {0} is not relevant")]
     BlockNotRelevant(u64),
 
+    /// Checkpoint signature is invalid.
+    #[error("Checkpoint signature is invalid.")]
+    InvalidCheckpointSignature,
+
+    /// Block conflicts with the finalized checkpoint.
+    #[error("Block at height {0} conflicts with the finalized checkpoint")]
+    ConflictingCheckpoint(u64),
+
+    /// Utreexo roots are unknown or do not match the block header.
+    #[error("Utreexo roots do not match the block header")]
+    InconsistentUtreexo,
+
+    /// Merkle path does not prove inclusion of the transaction in the block.
+    #[error("Transaction inclusion proof is invalid")]
+    InvalidInclusionProof,
+
     /// Received block is either too old or an orphan.
     #[error("Received mempool txs at an irrelevant state")]
     StaleMempoolState(BlockID),
-}
 
-impl From<UtreexoError> for BlockchainError {
-    fn from(e: UtreexoError) -> BlockchainError {
-        BlockchainError::UtreexoError(e)
-    }
+    /// Peer requested more transactions by ID than allowed within the rate limit interval.
+    #[error("Peer requested too many transactions")]
+    TooManyTxsRequested,
+
+    /// Compressed message is malformed or contains a message that must not be compressed.
+    #[error("Compressed message is invalid")]
+    InvalidCompressedMessage,
+
+    /// Compressed message expands beyond the allowed size.
+    #[error("Compressed message expands to {0} bytes")]
+    DecompressedMessageTooLarge(usize),
+
+    /// Block template is missing or no longer extends the tip.
+    #[error("Block template is missing or outdated")]
+    StaleBlockTemplate,
+
+    /// Transactions in the block do not follow the canonical order.
+    #[error("Transactions in the block are not in the canonical order")]
+    NonCanonicalTxOrder,
+
+    /// Transaction pays less than the minimum feerate of the mempool.
+    #[error("Transaction feerate is below the mempool minimum of {0} per weight unit")]
+    FeerateTooLow(u64),
+
+    /// Transaction spends a contract already spent by another transaction in the mempool.
+    #[error("Contract {0} is already spent by transaction {1} in the mempool")]
+    DoubleSpend(ContractID, TxID),
+
+    /// Headers do not form a chain that leads to the target tip.
+    #[error("Headers received at height {0} do not lead to the target tip")]
+    InvalidHeaders(u64),
+
+    /// Block does not match the downloaded header at its height.
+    #[error("Block at height {0} does not match the downloaded header")]
+    HeaderMismatch(u64),
+
+    /// Transactions in the block exceed the maximum block weight.
+    #[error("Block weight {0} exceeds the maximum block weight")]
+    BlockTooHeavy(u64),
+
+    /// Utreexo state or a transaction log does not match the audited blocks.
+    #[error("Blockchain state does not match the transaction logs at height {0}")]
+    AuditMismatch(u64),
 }
 
-impl From<VMError> for BlockchainError {
-    fn from(e: VMError) -> BlockchainError {
-        BlockchainError::VMError(e)
+impl BlockchainError {
+    /// Returns the class of the error, so the protocol, mempool and API
+    /// can decide uniformly whether to ban the peer, drop the data or retry.
+    pub fn failure_class(&self) -> FailureClass {
+        match self {
+            BlockchainError::VMError(e) => e.failure_class(),
+            BlockchainError::UtreexoError(UtreexoError::OutdatedProof) => FailureClass::Transient,
+            BlockchainError::InconsistentHeader
+            | BlockchainError::IllegalExtension
+            | BlockchainError::InvalidAuxCommitment
+            | BlockchainError::BadTxTimestamp
+            | BlockchainError::BadTxVersion
+            | BlockchainError::UtreexoProofMissing
+            | BlockchainError::UtreexoError(_)
+            | BlockchainError::InvalidBlockSignature
+            | BlockchainError::InvalidCheckpointSignature
+            | BlockchainError::ConflictingCheckpoint(_)
+            | BlockchainError::InconsistentUtreexo
+            | BlockchainError::InvalidInclusionProof
+            | BlockchainError::NonCanonicalTxOrder
+            | BlockchainError::BlockTooHeavy(_)
+            | BlockchainError::InvalidCompressedMessage
+            | BlockchainError::InvalidHeaders(_)
+            | BlockchainError::HeaderMismatch(_)
+            | BlockchainError::AuditMismatch(_) => FailureClass::Consensus,
+            BlockchainError::IncompatibleVersion
+            | BlockchainError::TooManyTxsRequested
+            | BlockchainError::FeerateTooLow(_)
+            | BlockchainError::DoubleSpend(..)
+            | BlockchainError::DecompressedMessageTooLarge(_) => FailureClass::Policy,
+            BlockchainError::BlockNotFound(_)
+            | BlockchainError::BlockNotRelevant(_)
+            | BlockchainError::StaleMempoolState(_)
+            | BlockchainError::StaleBlockTemplate => FailureClass::Transient,
+        }
     }
 }
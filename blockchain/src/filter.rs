@@ -0,0 +1,314 @@
+//! Compact block filters for light scanning.
+//!
+//! The filter is a Golomb-coded set (as in [BIP-158](https://github.com/bitcoin/bips/blob/master/bip-0158.mediawiki))
+//! of the items in the transaction logs of the block: IDs of the spent and created contracts,
+//! predicates of the created contracts and the data entries produced by the `log` instruction.
+//!
+//! 1. Each item is hashed with SipHash-2-4 keyed by the first 16 bytes of the ID of the previous block
+//!    (so the filter can be committed to in the block header),
+//!    and mapped to the range `[0, N·M)`, where `N` is the number of items.
+//! 2. The values are sorted and the differences between them are Golomb-Rice coded with parameter `P`.
+//!
+//! A light wallet downloads the filter of each block, checks it against the `AUX_BLOCK_FILTER`
+//! commitment in the header and fetches the block only if one of its items matches.
+//! False positives occur at the rate of `1/M`.
+
+use core::hash::Hasher;
+use merlin::Transcript;
+use serde::{Deserialize, Serialize};
+use siphasher::sip::SipHasher;
+use zkvm::encoding::*;
+use zkvm::{Hash, TxEntry};
+
+use super::block::{BlockID, VerifiedBlock};
+
+/// Golomb-Rice parameter of the filter.
+pub const FILTER_P: u8 = 19;
+
+/// Inverse of the false positive rate of the filter.
+pub const FILTER_M: u64 = 784_931;
+
+/// Compact filter over the items of a block.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BlockFilter {
+    n: u32,
+    data: Vec<u8>,
+}
+
+impl BlockFilter {
+    /// Creates a filter over the items of the block following the block with a given ID.
+    /// Duplicate items are counted once.
+    pub fn new<I>(prev_id: &BlockID, items: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut items = items
+            .into_iter()
+            .map(|item| item.as_ref().to_vec())
+            .collect::<Vec<_>>();
+        items.sort();
+        items.dedup();
+
+        let n = items.len() as u32;
+        let mut values = items
+            .iter()
+            .map(|item| hash_to_range(prev_id, item, n))
+            .collect::<Vec<_>>();
+        values.sort();
+
+        let mut writer = BitWriter::default();
+        let mut last = 0u64;
+        for value in values {
+            writer.write_golomb(value - last);
+            last = value;
+        }
+        BlockFilter {
+            n,
+            data: writer.finish(),
+        }
+    }
+
+    /// Creates a filter over the transaction logs of the block.
+    pub fn from_block(block: &VerifiedBlock) -> Self {
+        let mut items = Vec::new();
+        for entry in block.verified_txs.iter().flat_map(|vtx| vtx.log.iter()) {
+            match entry {
+                TxEntry::Input(contract_id) => items.push(contract_id.0.to_vec()),
+                TxEntry::Output(contract) => {
+                    items.push(contract.id().0.to_vec());
+                    items.push(contract.predicate.to_point().to_bytes().to_vec());
+                }
+                TxEntry::Data(data) => items.push(data.clone()),
+                _ => {}
+            }
+        }
+        Self::new(&block.header.prev, items)
+    }
+
+    /// Returns the number of items in the filter.
+    pub fn len(&self) -> usize {
+        self.n as usize
+    }
+
+    /// Returns true if the filter has no items.
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    /// Checks if the item may be in the block following the block with a given ID.
+    pub fn matches(&self, prev_id: &BlockID, item: impl AsRef<[u8]>) -> bool {
+        self.matches_any(prev_id, Some(item))
+    }
+
+    /// Checks if any of the items may be in the block following the block with a given ID.
+    /// Returns false if the filter data is malformed.
+    pub fn matches_any<I>(&self, prev_id: &BlockID, items: I) -> bool
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut targets = items
+            .into_iter()
+            .map(|item| hash_to_range(prev_id, item.as_ref(), self.n))
+            .collect::<Vec<_>>();
+        targets.sort();
+
+        let mut reader = BitReader::new(&self.data);
+        let mut targets = targets.into_iter().peekable();
+        let mut value = 0u64;
+        for _ in 0..self.n {
+            value = match reader.read_golomb() {
+                Some(delta) => value + delta,
+                None => return false,
+            };
+            while let Some(&target) = targets.peek() {
+                if target == value {
+                    return true;
+                }
+                if target > value {
+                    break;
+                }
+                targets.next();
+            }
+            if targets.peek().is_none() {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// Computes the hash of the filter.
+    pub fn hash(&self) -> Hash {
+        let mut t = Transcript::new(b"ZkVM.blockfilter");
+        t.append_u64(b"n", self.n as u64);
+        t.append_message(b"data", &self.data);
+        let mut result = [0u8; 32];
+        t.challenge_bytes(b"hash", &mut result);
+        Hash(result)
+    }
+}
+
+impl VerifiedBlock {
+    /// Computes the compact filter of the block.
+    pub fn filter(&self) -> BlockFilter {
+        BlockFilter::from_block(self)
+    }
+}
+
+impl Encodable for BlockFilter {
+    fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
+        w.write_u32(b"n", self.n)?;
+        w.write_size(b"len", self.data.len())?;
+        w.write(b"data", &self.data)
+    }
+}
+
+impl ExactSizeEncodable for BlockFilter {
+    fn encoded_size(&self) -> usize {
+        4 + 4 + self.data.len()
+    }
+}
+
+impl Decodable for BlockFilter {
+    fn decode(buf: &mut impl Reader) -> Result<Self, ReadError> {
+        let n = buf.read_u32()?;
+        let len = buf.read_size()?;
+        let data = buf.read_bytes(len)?;
+        Ok(BlockFilter { n, data })
+    }
+}
+
+/// Maps the item to the range `[0, n·M)`.
+fn hash_to_range(prev_id: &BlockID, item: &[u8], n: u32) -> u64 {
+    let mut sip =
+        SipHasher::new_with_keys(read_le64(&prev_id.0[0..8]), read_le64(&prev_id.0[8..16]));
+    sip.write(item);
+    let f = (n as u64) * FILTER_M;
+    ((sip.finish() as u128 * f as u128) >> 64) as u64
+}
+
+fn read_le64(slice: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(slice);
+    u64::from_le_bytes(bytes)
+}
+
+/// Writes bits starting with the most significant bit of each byte.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: u8, // number of bits used in the last byte
+}
+
+impl BitWriter {
+    fn write_bit(&mut self, bit: bool) {
+        if self.bits == 0 {
+            self.bytes.push(0);
+        }
+        if bit {
+            *self.bytes.last_mut().expect("byte was pushed") |= 0x80 >> self.bits;
+        }
+        self.bits = (self.bits + 1) % 8;
+    }
+
+    fn write_golomb(&mut self, value: u64) {
+        for _ in 0..(value >> FILTER_P) {
+            self.write_bit(true);
+        }
+        self.write_bit(false);
+        for i in (0..FILTER_P).rev() {
+            self.write_bit((value >> i) & 1 == 1);
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Reads bits written by `BitWriter`.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize, // position of the next bit
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        BitReader { bytes, position: 0 }
+    }
+
+    fn read_bit(&mut self) -> Option<bool> {
+        let byte = self.bytes.get(self.position / 8)?;
+        let bit = byte & (0x80 >> (self.position % 8)) != 0;
+        self.position += 1;
+        Some(bit)
+    }
+
+    fn read_golomb(&mut self) -> Option<u64> {
+        let mut quotient = 0u64;
+        while self.read_bit()? {
+            quotient += 1;
+        }
+        let mut remainder = 0u64;
+        for _ in 0..FILTER_P {
+            remainder = (remainder << 1) | (self.read_bit()? as u64);
+        }
+        Some((quotient << FILTER_P) | remainder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(range: core::ops::Range<u32>) -> Vec<Vec<u8>> {
+        range.map(|i| i.to_le_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn filter_matches_its_items() {
+        let block_id = BlockID([7u8; 32]);
+        let filter = BlockFilter::new(&block_id, items(0..100));
+        assert_eq!(filter.len(), 100);
+        for item in items(0..100) {
+            assert!(filter.matches(&block_id, &item));
+        }
+        assert!(filter.matches_any(
+            &block_id,
+            items(1000..1010).into_iter().chain(items(50..51))
+        ));
+
+        // False positives are rare.
+        let false_positives = items(1000..11000)
+            .iter()
+            .filter(|item| filter.matches(&block_id, item))
+            .count();
+        assert!(false_positives < 3);
+
+        // Items are hashed with the block ID.
+        let other_id = BlockID([8u8; 32]);
+        assert!(!filter.matches_any(&other_id, items(0..10)));
+    }
+
+    #[test]
+    fn empty_filter() {
+        let block_id = BlockID([7u8; 32]);
+        let filter = BlockFilter::new(&block_id, Vec::<Vec<u8>>::new());
+        assert!(filter.is_empty());
+        assert!(!filter.matches(&block_id, b"foo"));
+    }
+
+    #[test]
+    fn filter_roundtrip() {
+        let block_id = BlockID([7u8; 32]);
+        let filter = BlockFilter::new(&block_id, items(0..10).into_iter().chain(items(0..10)));
+        assert_eq!(filter.len(), 10);
+
+        let bytes = filter.encode_to_vec();
+        assert_eq!(bytes.len(), filter.encoded_size());
+        let decoded = (&bytes[..]).read_all(|r| BlockFilter::decode(r)).unwrap();
+        assert_eq!(decoded, filter);
+        assert_eq!(decoded.hash(), filter.hash());
+    }
+}
@@ -7,10 +7,17 @@ extern crate zkvm;
 
 extern crate starsig;
 
+mod asset_index;
+mod audit;
 mod block;
 mod codec;
+mod compression;
+mod consensus;
 mod errors;
+mod filter;
+mod lightclient;
 mod mempool;
+mod ordering;
 mod protocol;
 mod shortid;
 mod state;
@@ -19,8 +26,16 @@ pub mod utreexo;
 #[cfg(test)]
 mod tests;
 
+pub use self::asset_index::*;
+pub use self::audit::*;
 pub use self::block::*;
+pub use self::codec::{decode_message, encode_message, WIRE_VERSION};
+pub use self::compression::MAX_DECOMPRESSED_SIZE;
+pub use self::consensus::*;
 pub use self::errors::*;
+pub use self::filter::*;
+pub use self::lightclient::*;
 pub use self::mempool::*;
+pub use self::ordering::*;
 pub use self::protocol::*;
 pub use self::state::*;
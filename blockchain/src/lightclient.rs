@@ -0,0 +1,199 @@
+//! Light client (SPV) that keeps only the signed block headers and utreexo roots.
+//!
+//! Transactions and utxo proofs are supplied by full nodes or bridges
+//! and verified against the authenticated headers.
+
+use starsig::{Signature, VerificationKey};
+use zkvm::bulletproofs::BulletproofGens;
+use zkvm::merkle::{Hasher, Path};
+use zkvm::{Contract, ContractID, TxEntry, TxID, VerifiedTx};
+
+use super::block::{AuxCommitment, BlockHeader, BlockTx, WitnessHash, AUX_BLOCK_FILTER};
+use super::errors::BlockchainError;
+use super::filter::BlockFilter;
+use super::protocol::verify_block_signature;
+use super::state::check_block_header;
+use super::utreexo::{self, Forest};
+
+/// Light client state: a chain of signed headers and the utreexo roots at the tip.
+#[derive(Clone)]
+pub struct LightClient {
+    network_pubkey: VerificationKey,
+    headers: Vec<BlockHeader>, // i=0 -> starting header
+    utreexo: Option<Forest>,
+    bp_gens: BulletproofGens,
+}
+
+/// Effects of a transaction relevant to the wallet.
+#[derive(Clone, Debug, Default)]
+pub struct TxScan {
+    /// Contracts spent by the transaction.
+    pub spent: Vec<ContractID>,
+    /// Contracts created by the transaction that belong to the wallet.
+    pub received: Vec<Contract>,
+}
+
+impl LightClient {
+    /// Creates a light client starting from a trusted signed header,
+    /// e.g. the initial block or a finalized checkpoint.
+    pub fn new(
+        network_pubkey: VerificationKey,
+        header: BlockHeader,
+        signature: &Signature,
+    ) -> Result<Self, BlockchainError> {
+        if !verify_block_signature(&header, signature, network_pubkey) {
+            return Err(BlockchainError::InvalidBlockSignature);
+        }
+        Ok(LightClient {
+            network_pubkey,
+            headers: vec![header],
+            utreexo: None,
+            bp_gens: BulletproofGens::new(256, 1),
+        })
+    }
+
+    /// Latest known block header.
+    pub fn tip(&self) -> &BlockHeader {
+        self.headers.last().expect("Header chain is never empty")
+    }
+
+    /// Returns the header at a given height, if it is known to the client.
+    pub fn header_at_height(&self, height: u64) -> Option<&BlockHeader> {
+        let start = self.headers[0].height;
+        if height < start {
+            return None;
+        }
+        self.headers.get((height - start) as usize)
+    }
+
+    /// Appends the next signed header to the chain.
+    /// Utreexo roots must be updated for the new tip with `update_utreexo`.
+    pub fn apply_header(
+        &mut self,
+        header: BlockHeader,
+        signature: &Signature,
+    ) -> Result<(), BlockchainError> {
+        check_block_header(&header, self.tip())?;
+        if !verify_block_signature(&header, signature, self.network_pubkey) {
+            return Err(BlockchainError::InvalidBlockSignature);
+        }
+        self.headers.push(header);
+        self.utreexo = None;
+        Ok(())
+    }
+
+    /// Sets the utreexo roots for the current tip, received from a full node or a bridge.
+    pub fn update_utreexo(&mut self, utreexo: Forest) -> Result<(), BlockchainError> {
+        let hasher = utreexo::utreexo_hasher::<ContractID>();
+        if utreexo.root(&hasher) != self.tip().utxoroot {
+            return Err(BlockchainError::InconsistentUtreexo);
+        }
+        self.utreexo = Some(utreexo);
+        Ok(())
+    }
+
+    /// Verifies that the contract is unspent at the current tip.
+    pub fn verify_utxo(
+        &self,
+        contract_id: &ContractID,
+        proof: &utreexo::Proof,
+    ) -> Result<(), BlockchainError> {
+        let utreexo = self
+            .utreexo
+            .as_ref()
+            .ok_or(BlockchainError::InconsistentUtreexo)?;
+        let path = proof.as_path().ok_or(utreexo::UtreexoError::InvalidProof)?;
+        utreexo.verify(contract_id, path, &utreexo::utreexo_hasher())?;
+        Ok(())
+    }
+
+    /// Verifies that the transaction is included in the block at a given height
+    /// using the Merkle path to the block's `txroot`, and returns the verified transaction.
+    pub fn verify_tx_inclusion(
+        &self,
+        height: u64,
+        block_tx: &BlockTx,
+        path: &Path,
+    ) -> Result<VerifiedTx, BlockchainError> {
+        let header = self
+            .header_at_height(height)
+            .ok_or(BlockchainError::BlockNotFound(height))?;
+        if !path.verify_root(&header.txroot, &block_tx.witness_hash(), &txroot_hasher()) {
+            return Err(BlockchainError::InvalidInclusionProof);
+        }
+        Ok(block_tx.tx.verify(&self.bp_gens)?)
+    }
+
+    /// Verifies that the transaction with a given ID is included in the block at a given height
+    /// using the Merkle path to the block's `txidroot`.
+    /// This allows confirming the wallet's own transactions without downloading them.
+    pub fn verify_txid_inclusion(
+        &self,
+        height: u64,
+        txid: &TxID,
+        path: &Path,
+    ) -> Result<(), BlockchainError> {
+        let header = self
+            .header_at_height(height)
+            .ok_or(BlockchainError::BlockNotFound(height))?;
+        if !header.verify_tx_inclusion(txid, path) {
+            return Err(BlockchainError::InvalidInclusionProof);
+        }
+        Ok(())
+    }
+
+    /// Verifies the compact filter of the block at a given height
+    /// using the Merkle path to its commitment in the block's `auxroot`.
+    /// The wallet then matches its items against the filter to decide whether to fetch the block.
+    pub fn verify_block_filter(
+        &self,
+        height: u64,
+        filter: &BlockFilter,
+        path: &Path,
+    ) -> Result<(), BlockchainError> {
+        let header = self
+            .header_at_height(height)
+            .ok_or(BlockchainError::BlockNotFound(height))?;
+        let commitment = AuxCommitment {
+            kind: AUX_BLOCK_FILTER,
+            hash: filter.hash(),
+        };
+        if !header.verify_aux_commitment(&commitment, path) {
+            return Err(BlockchainError::InvalidAuxCommitment);
+        }
+        Ok(())
+    }
+
+    /// Scans the verified transaction for the spent contracts
+    /// and the new contracts accepted by the wallet's filter.
+    pub fn scan_tx(&self, tx: &VerifiedTx, mut is_ours: impl FnMut(&Contract) -> bool) -> TxScan {
+        let mut scan = TxScan::default();
+        for entry in tx.log.iter() {
+            match entry {
+                TxEntry::Input(contract_id) => scan.spent.push(*contract_id),
+                TxEntry::Output(contract) if is_ours(contract) => {
+                    scan.received.push(contract.clone())
+                }
+                _ => {}
+            }
+        }
+        scan
+    }
+}
+
+/// Creates a Merkle path for the transaction at a given index within the block,
+/// to be served by full nodes to light clients.
+pub fn tx_inclusion_path(block_txs: &[BlockTx], index: usize) -> Option<Path> {
+    if index >= block_txs.len() {
+        return None;
+    }
+    let hashes = block_txs
+        .iter()
+        .map(|btx| btx.witness_hash())
+        .collect::<Vec<_>>();
+    Path::new(&hashes, index, &txroot_hasher())
+}
+
+fn txroot_hasher() -> Hasher<WitnessHash> {
+    Hasher::new(b"ZkVM.txroot")
+}
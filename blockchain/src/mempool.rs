@@ -1,15 +1,33 @@
 //! Super-simple mempool implementation.
+//!
+//! Transactions paying less than the minimum feerate are rejected before verification.
+//! The minimum is the configured relay feerate, raised above the feerate of the transactions
+//! evicted when the mempool exceeds its maximum size. The raised feerate halves
+//! every `MIN_FEERATE_HALFLIFE_MS`, so it returns to the relay feerate once the spam flood is over.
+use core::cmp::{self, Reverse};
+use core::convert::TryFrom;
 use core::mem;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::mpsc;
 
 use zkvm::bulletproofs::BulletproofGens;
-use zkvm::{ContractID, MerkleTree, Tx, TxEntry, TxID, TxLog, VerifiedTx};
-
-use super::block::{BlockHeader, BlockTx, VerifiedBlock};
+use zkvm::{
+    ContractID, Hash, MerkleTree, PrecomputedTx, Tx, TxEntry, TxID, TxLog, TxWireHash, VerifiedTx,
+};
+
+use super::block::{
+    compute_auxroot, compute_txidroot, BlockHeader, BlockTx, VerifiedBlock, BLOCK_VERSION_AUX,
+    BLOCK_VERSION_ORDERED, BLOCK_VERSION_TXIDROOT, BLOCK_VERSION_WEIGHT, MAX_BLOCK_WEIGHT,
+};
 use super::errors::BlockchainError;
+use super::ordering::{canonical_order, spends};
 use super::state::{check_tx_header, BlockchainState};
 use super::utreexo::{self, utreexo_hasher, Catchup};
 
+/// Time in milliseconds in which the minimum feerate raised by the evictions halves.
+pub const MIN_FEERATE_HALFLIFE_MS: u64 = 60 * 60 * 1000;
+
 /// Implements a pool of unconfirmed (not-in-the-block) transactions.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Mempool {
@@ -17,6 +35,77 @@ pub struct Mempool {
     timestamp_ms: u64,
     work_utreexo: utreexo::WorkForest,
     entries: Vec<MempoolEntry>,
+    // indices of the entries by TxID, by the wire hash and by the spent contracts
+    by_txid: HashMap<TxID, usize>,
+    by_wire_hash: HashMap<TxWireHash, usize>,
+    by_input: HashMap<ContractID, usize>,
+    // maximum total weight of the transactions
+    max_size: usize,
+    // minimum feerate in fee units per weight unit
+    min_relay_feerate: u64,
+    // minimum feerate raised by the last eviction, and the time of the eviction
+    evicted_feerate: u64,
+    evicted_timestamp_ms: u64,
+    #[serde(skip)]
+    subscribers: Vec<mpsc::Sender<MempoolEvent>>,
+}
+
+/// Changes in the mempool delivered to the subscribers.
+#[derive(Clone, Debug)]
+pub enum MempoolEvent {
+    /// Transaction was verified and added to the mempool.
+    Accepted(TxID),
+    /// Transaction was rejected with a given reason.
+    /// Transactions that cannot be decoded into a `TxID` are not reported.
+    Rejected(TxID, BlockchainError),
+    /// Rejected transaction spends a contract already spent by a transaction in the mempool.
+    /// Reported after the `Rejected` event, so wallets can warn about attempted double-spends
+    /// of the payments they are watching.
+    DoubleSpendDetected {
+        /// ID of the rejected transaction.
+        txid: TxID,
+        /// ID of the transaction in the mempool spending the same contract.
+        conflicting_txid: TxID,
+        /// ID of the contract spent by both transactions.
+        contract_id: ContractID,
+    },
+    /// Transaction was removed from the mempool because it is included in a block,
+    /// conflicts with the new state, its time bounds expired, or the mempool
+    /// exceeded its maximum size and the transaction had the lowest feerate.
+    Evicted(TxID),
+}
+
+/// Dependency graph of the transactions in the mempool, for diagnostics and visualization.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MempoolGraph {
+    /// Transactions in the order they were accepted to the mempool.
+    pub nodes: Vec<MempoolGraphNode>,
+    /// Spends of the outputs created by the transactions in the mempool, one per spent output.
+    pub edges: Vec<MempoolGraphEdge>,
+}
+
+/// Transaction in the mempool dependency graph.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MempoolGraphNode {
+    /// Transaction ID.
+    pub txid: TxID,
+    /// Weight of the transaction (see `zkvm::Tx::weight`).
+    pub size: u64,
+    /// Fee paid by the transaction.
+    pub fee: u64,
+    /// Fee per weight unit, rounded down.
+    pub feerate: u64,
+    /// Mempool timestamp at which the transaction was accepted.
+    pub timestamp_ms: u64,
+}
+
+/// Spend of an output of one transaction in the mempool by another one.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MempoolGraphEdge {
+    /// Transaction creating the output.
+    pub from: TxID,
+    /// Transaction spending the output.
+    pub to: TxID,
 }
 
 /// Tx item stored in the mempool
@@ -24,6 +113,8 @@ pub struct Mempool {
 pub struct MempoolEntry {
     block_tx: BlockTx,
     verified_tx: VerifiedTx,
+    wire_hash: TxWireHash,
+    timestamp_ms: u64,
 }
 
 impl MempoolEntry {
@@ -37,6 +128,16 @@ impl MempoolEntry {
         self.verified_tx.id
     }
 
+    /// Returns the hash of the serialized transaction as it was received.
+    pub fn wire_hash(&self) -> TxWireHash {
+        self.wire_hash
+    }
+
+    /// Returns the mempool timestamp at which the transaction was accepted.
+    pub fn timestamp_ms(&self) -> u64 {
+        self.timestamp_ms
+    }
+
     /// Returns the block tx.
     pub fn block_tx(&self) -> &BlockTx {
         &self.block_tx
@@ -67,19 +168,114 @@ impl Mempool {
             timestamp_ms,
             work_utreexo,
             entries: Vec::new(),
+            by_txid: HashMap::new(),
+            by_wire_hash: HashMap::new(),
+            by_input: HashMap::new(),
+            max_size: usize::max_value(),
+            min_relay_feerate: 0,
+            evicted_feerate: 0,
+            evicted_timestamp_ms: timestamp_ms,
+            subscribers: Vec::new(),
         }
     }
 
+    /// Sets the maximum total weight of the transactions (see `zkvm::Tx::weight`).
+    /// When the mempool grows beyond it, the transactions with the lowest feerate are evicted
+    /// and the minimum feerate is raised above theirs.
+    /// By default, the size is not limited.
+    pub fn set_max_size(mut self, weight: usize) -> Self {
+        self.max_size = weight;
+        self
+    }
+
+    /// Sets the minimum feerate (in fee units per weight unit) of the accepted transactions.
+    /// By default, transactions without fees are accepted.
+    pub fn set_min_relay_feerate(mut self, feerate: u64) -> Self {
+        self.min_relay_feerate = feerate;
+        self
+    }
+
+    /// Returns the current minimum feerate (in fee units per weight unit) of the accepted transactions:
+    /// the minimum relay feerate, or the feerate raised by the recent evictions if it is higher.
+    pub fn min_feerate(&self) -> u64 {
+        cmp::max(self.min_relay_feerate, self.decayed_evicted_feerate())
+    }
+
+    /// Subscribes to the mempool events.
+    /// The subscription is cancelled when the receiver is dropped.
+    /// A clone of the mempool keeps sending the events to the subscribers it was cloned with,
+    /// but the subscriptions made after cloning receive the events of one instance only.
+    /// Subscribers are not serialized.
+    pub fn subscribe(&mut self) -> mpsc::Receiver<MempoolEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
     /// Returns a list of transactions.
     pub fn entries(&self) -> impl Iterator<Item = &MempoolEntry> {
         self.entries.iter()
     }
 
+    /// Returns the entry with a given transaction ID.
+    pub fn get(&self, txid: &TxID) -> Option<&MempoolEntry> {
+        self.by_txid.get(txid).map(|i| &self.entries[*i])
+    }
+
+    /// Returns the entry with a given wire hash.
+    /// A different encoding of the same transaction is not found by its wire hash, use `get` instead.
+    pub fn get_by_wire_hash(&self, wire_hash: &TxWireHash) -> Option<&MempoolEntry> {
+        self.by_wire_hash.get(wire_hash).map(|i| &self.entries[*i])
+    }
+
+    /// Returns the transaction in the mempool that spends a given contract.
+    pub fn spender(&self, contract_id: &ContractID) -> Option<&MempoolEntry> {
+        self.by_input.get(contract_id).map(|i| &self.entries[*i])
+    }
+
+    /// Returns the current timestamp of the mempool.
+    pub fn timestamp_ms(&self) -> u64 {
+        self.timestamp_ms
+    }
+
     /// Returns the size of the mempool in number of transactions.
     pub fn len(&self) -> usize {
         self.entries.len()
     }
 
+    /// Returns the dependency graph of the transactions.
+    pub fn graph(&self) -> MempoolGraph {
+        let txs: Vec<&VerifiedTx> = self.entries.iter().map(|e| &e.verified_tx).collect();
+        MempoolGraph {
+            nodes: self
+                .entries
+                .iter()
+                .map(|e| MempoolGraphNode {
+                    txid: e.txid(),
+                    size: e.verified_tx.feerate.size() as u64,
+                    fee: e.verified_tx.feerate.fee(),
+                    feerate: e.verified_tx.feerate.normalize().fee(),
+                    timestamp_ms: e.timestamp_ms,
+                })
+                .collect(),
+            edges: spends(&txs)
+                .into_iter()
+                .map(|(from, to)| MempoolGraphEdge {
+                    from: txs[from].id,
+                    to: txs[to].id,
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the total weight of the transactions.
+    pub fn size(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|e| e.verified_tx.feerate.size())
+            .sum()
+    }
+
     /// Updates timestamp and re-applies txs to filter out the outdated ones.
     pub fn update_timestamp(&mut self, timestamp_ms: u64) {
         self.timestamp_ms = timestamp_ms;
@@ -96,62 +292,123 @@ impl Mempool {
     /// Adds transaction to the mempool and verifies it.
     /// Returns the reference to the stored mempool entry.
     /// If a duplicate is detected (by TxID), no changes are made and the corresponding entry
-    /// is returned to the caller.
-    /// FIXME: If tx is double-spending, detect it before doing the expensive r1cs validation.
+    /// is returned to the caller. The duplicate may have a different wire hash
+    /// if its proof or signatures were re-encoded or re-created.
+    /// Fails with `FeerateTooLow` if the transaction pays less than the current minimum feerate,
+    /// or has the lowest feerate in the mempool that exceeds its maximum size.
+    /// Fails with `DoubleSpend` before the verification if the transaction spends a contract
+    /// already spent by another transaction in the mempool.
     pub fn append(
         &mut self,
         block_tx: BlockTx,
         bp_gens: &BulletproofGens,
     ) -> Result<&MempoolEntry, BlockchainError> {
-        // 1. Check the header
-        check_tx_header(
-            &block_tx.tx.header,
-            self.timestamp_ms,
-            self.state.tip.version,
-        )?;
+        // 1. Skip the exact duplicate without computing the TxID.
+        let wire_hash = block_tx.tx.wire_hash();
+        if let Some(i) = self.by_wire_hash.get(&wire_hash) {
+            return Ok(&self.entries[*i]);
+        }
 
         // 2. Precompute the transaction
         let precomputed_tx = block_tx.tx.precompute()?;
+        let txid = precomputed_tx.id;
 
         // 3. Check if this transaction already exists in the mempool.
         //    If it does, simply return the reference to its entry.
-        // TODO: use a faster way to index existing transactions and do this check before expensive r1cs verification.
-        if let Some(existing_entry_index) = self
-            .entries
-            .iter()
-            .position(|entry| entry.verified_tx.id == precomputed_tx.id)
-        {
-            return Ok(&self.entries[existing_entry_index]);
+        if let Some(i) = self.by_txid.get(&txid) {
+            return Ok(&self.entries[*i]);
         }
 
-        // 4. TODO: before verifying the transaction, immutably check if it can be applied to the mempool
-        // to prevent double spends before expensive verification happens.
-
-        // 5. Verify the tx
-        let verified_tx = precomputed_tx.verify(bp_gens)?;
-
-        // 6. Apply to the state
-        self.apply_tx(&verified_tx.log, &block_tx.proofs, None)?;
+        // 4. Check the feerate, verify and apply the transaction,
+        //    notifying the subscribers about rejection.
+        let verified_tx = match self.verify_and_apply(&block_tx, precomputed_tx, bp_gens) {
+            Ok(verified_tx) => verified_tx,
+            Err(e) => {
+                self.notify(MempoolEvent::Rejected(txid, e.clone()));
+                if let BlockchainError::DoubleSpend(contract_id, conflicting_txid) = e {
+                    self.notify(MempoolEvent::DoubleSpendDetected {
+                        txid,
+                        conflicting_txid,
+                        contract_id,
+                    });
+                }
+                return Err(e);
+            }
+        };
 
-        // 7. Save in the list
-        self.entries.push(MempoolEntry {
+        // 5. Save in the list
+        self.push_entry(MempoolEntry {
             block_tx,
             verified_tx,
+            wire_hash,
+            timestamp_ms: self.timestamp_ms,
         });
+        self.notify(MempoolEvent::Accepted(txid));
+
+        // 6. Evict the transactions with the lowest feerate if the mempool is too large.
+        self.trim_to_size();
 
-        // 8. Return the reference to the entry we've just added.
-        Ok(self.entries.last().unwrap())
+        // 7. Return the reference to the entry we've just added, unless it was evicted.
+        match self.by_txid.get(&txid) {
+            Some(i) => Ok(&self.entries[*i]),
+            None => Err(BlockchainError::FeerateTooLow(self.min_feerate())),
+        }
     }
 
     /// Creates a new verified block using the current set of transactions.
+    /// Since `BLOCK_VERSION_ORDERED`, the transactions are placed in the canonical order.
+    /// Since `BLOCK_VERSION_WEIGHT`, transactions that do not fit in `MAX_BLOCK_WEIGHT`
+    /// are left in the mempool together with their dependents.
     pub fn make_block(&self) -> VerifiedBlock {
+        let mut entries: Vec<&MempoolEntry> = self.entries.iter().collect();
+        let ordered = self.state.tip.version >= BLOCK_VERSION_ORDERED;
+        if ordered {
+            entries = canonical_order(self.entries.iter().map(|e| &e.verified_tx))
+                .into_iter()
+                .map(|i| &self.entries[i])
+                .collect();
+        }
+        let max_weight = if self.state.tip.version >= BLOCK_VERSION_WEIGHT {
+            MAX_BLOCK_WEIGHT
+        } else {
+            u64::max_value()
+        };
+        let total_weight: u64 = self.entries.iter().map(|e| e.verified_tx.weight()).sum();
+        let mut rebuilt_utreexo = None;
+        if ordered || total_weight > max_weight {
+            // Utreexo depends on the order of insertions, so the txs are re-applied in the new order.
+            // Txs that fail to apply or exceed the block weight are left out together with their dependents,
+            // which keeps the rest in the canonical order.
+            let mut work_utreexo = self.state.utreexo.work_forest();
+            let mut block_weight = 0u64;
+            entries.retain(|e| {
+                let weight = e.verified_tx.weight();
+                if block_weight + weight > max_weight {
+                    return false;
+                }
+                let applied = apply_tx(&mut work_utreexo, e.txlog(), e.utxo_proofs(), None).is_ok();
+                if applied {
+                    block_weight += weight;
+                }
+                applied
+            });
+            rebuilt_utreexo = Some(work_utreexo);
+        }
+        let work_utreexo = rebuilt_utreexo.as_ref().unwrap_or(&self.work_utreexo);
+
         let txroot = MerkleTree::root(
             b"ZkVM.txroot",
-            self.entries.iter().map(|mtx| mtx.block_tx.witness_hash()),
+            entries.iter().map(|mtx| mtx.block_tx.witness_hash()),
         );
 
+        let txidroot = if self.state.tip.version >= BLOCK_VERSION_TXIDROOT {
+            compute_txidroot(entries.iter().map(|mtx| mtx.txid()))
+        } else {
+            Hash::default()
+        };
+
         let hasher = utreexo_hasher::<ContractID>();
-        let (new_forest, new_catchup) = self.work_utreexo.normalize(&hasher);
+        let (new_forest, new_catchup) = work_utreexo.normalize(&hasher);
         let utxoroot = new_forest.root(&hasher);
 
         let new_header = BlockHeader {
@@ -160,17 +417,24 @@ impl Mempool {
             prev: self.state.tip.id(),
             timestamp_ms: self.timestamp_ms,
             txroot,
+            txidroot,
             utxoroot,
             ext: Vec::new(),
         };
 
-        VerifiedBlock {
+        let mut block = VerifiedBlock {
             header: new_header,
             utreexo: new_forest,
             catchup: new_catchup,
-            raw_txs: self.entries().map(|e| e.block_tx()).cloned().collect(),
-            verified_txs: self.entries().map(|e| e.verified_tx()).cloned().collect(),
+            raw_txs: entries.iter().map(|e| e.block_tx()).cloned().collect(),
+            verified_txs: entries.iter().map(|e| e.verified_tx()).cloned().collect(),
+            aux: Vec::new(),
+        };
+        if block.header.version >= BLOCK_VERSION_AUX {
+            block.aux = block.known_aux_commitments();
+            block.header.ext = compute_auxroot(&block.aux).0.to_vec();
         }
+        block
     }
 
     fn update_mempool(&mut self, catchup: Option<&Catchup>) {
@@ -179,6 +443,9 @@ impl Mempool {
 
         // extract old
         let old_entries = mem::replace(&mut self.entries, Vec::new());
+        self.by_txid.clear();
+        self.by_wire_hash.clear();
+        self.by_input.clear();
 
         for entry in old_entries.into_iter() {
             let result = check_tx_header(
@@ -186,54 +453,153 @@ impl Mempool {
                 self.timestamp_ms,
                 self.state.tip.version,
             )
-            .and_then(|_| self.apply_tx(&entry.verified_tx.log, &entry.block_tx.proofs, catchup));
+            .and_then(|_| {
+                apply_tx(
+                    &mut self.work_utreexo,
+                    &entry.verified_tx.log,
+                    &entry.block_tx.proofs,
+                    catchup,
+                )
+            });
             if result.is_ok() {
                 // put the entry back into the mempool if it's still valid
-                self.entries.push(entry);
+                self.push_entry(entry);
+            } else {
+                self.notify(MempoolEvent::Evicted(entry.txid()));
             }
         }
     }
 
-    fn apply_tx(
+    fn verify_and_apply(
         &mut self,
-        txlog: &TxLog,
-        utxo_proofs: &[utreexo::Proof],
-        catchup: Option<&Catchup>,
-    ) -> Result<(), BlockchainError> {
-        // Update block makes sure the that if half of tx fails, all changes are undone.
-        self.work_utreexo
-            .batch(|wf| {
-                let hasher = utreexo_hasher();
-                let mut utxo_proofs = utxo_proofs.iter();
-
-                for logentry in txlog.iter() {
-                    match logentry {
-                        // Remove item from the UTXO set
-                        TxEntry::Input(contract_id) => {
-                            let proof = utxo_proofs
-                                .next()
-                                .ok_or(BlockchainError::UtreexoProofMissing)?;
-
-                            let updated_proof = match catchup {
-                                Some(c) => {
-                                    Some(c.update_proof(contract_id, proof.clone(), &hasher)?)
-                                }
-                                None => None,
-                            };
-                            let proof = updated_proof.as_ref().unwrap_or(proof);
-
-                            wf.delete(contract_id, proof, &hasher)?;
-                        }
-                        // Add item to the UTXO set
-                        TxEntry::Output(contract) => {
-                            wf.insert(&contract.id(), &hasher);
-                        }
-                        // Ignore all other log entries
-                        _ => {}
+        block_tx: &BlockTx,
+        precomputed_tx: PrecomputedTx,
+        bp_gens: &BulletproofGens,
+    ) -> Result<VerifiedTx, BlockchainError> {
+        // 1. Check the header
+        check_tx_header(
+            &block_tx.tx.header,
+            self.timestamp_ms,
+            self.state.tip.version,
+        )?;
+
+        // 2. Check the feerate
+        let min_feerate = self.min_feerate();
+        if precomputed_tx.feerate.normalize().fee() < min_feerate {
+            return Err(BlockchainError::FeerateTooLow(min_feerate));
+        }
+
+        // 3. Check that the inputs are not spent by the mempool transactions
+        //    before the expensive verification happens.
+        for contract_id in precomputed_tx.log.inputs() {
+            if let Some(entry) = self.spender(contract_id) {
+                return Err(BlockchainError::DoubleSpend(*contract_id, entry.txid()));
+            }
+        }
+
+        // 4. Verify the tx
+        let verified_tx = precomputed_tx.verify(bp_gens)?;
+
+        // 5. Apply to the state
+        apply_tx(
+            &mut self.work_utreexo,
+            &verified_tx.log,
+            &block_tx.proofs,
+            None,
+        )?;
+
+        Ok(verified_tx)
+    }
+
+    fn trim_to_size(&mut self) {
+        let mut size = self.size();
+        if size <= self.max_size {
+            return;
+        }
+        while size > self.max_size {
+            // Among the transactions with equal feerates, the most recent one is evicted first.
+            let (i, _) = self
+                .entries
+                .iter()
+                .enumerate()
+                .min_by_key(|(i, e)| (e.verified_tx.feerate, Reverse(*i)))
+                .expect("mempool exceeding its size is not empty");
+            let entry = self.entries.remove(i);
+            size -= entry.verified_tx.feerate.size();
+            self.evicted_feerate = cmp::max(
+                self.decayed_evicted_feerate(),
+                entry.verified_tx.feerate.normalize().fee() + 1,
+            );
+            self.evicted_timestamp_ms = self.timestamp_ms;
+            self.notify(MempoolEvent::Evicted(entry.txid()));
+        }
+        // Re-apply the remaining transactions, evicting the ones spending the evicted outputs.
+        self.update_mempool(None);
+    }
+
+    fn decayed_evicted_feerate(&self) -> u64 {
+        let halvings =
+            self.timestamp_ms.saturating_sub(self.evicted_timestamp_ms) / MIN_FEERATE_HALFLIFE_MS;
+        u32::try_from(halvings)
+            .ok()
+            .and_then(|h| self.evicted_feerate.checked_shr(h))
+            .unwrap_or(0)
+    }
+
+    fn notify(&mut self, event: MempoolEvent) {
+        // Drop the subscribers whose receivers are gone.
+        self.subscribers
+            .retain(|sender| sender.send(event.clone()).is_ok());
+    }
+
+    fn push_entry(&mut self, entry: MempoolEntry) {
+        self.by_txid.insert(entry.txid(), self.entries.len());
+        self.by_wire_hash
+            .insert(entry.wire_hash, self.entries.len());
+        for contract_id in entry.txlog().inputs() {
+            self.by_input.insert(*contract_id, self.entries.len());
+        }
+        self.entries.push(entry);
+    }
+}
+
+fn apply_tx(
+    work_utreexo: &mut utreexo::WorkForest,
+    txlog: &TxLog,
+    utxo_proofs: &[utreexo::Proof],
+    catchup: Option<&Catchup>,
+) -> Result<(), BlockchainError> {
+    // Update block makes sure the that if half of tx fails, all changes are undone.
+    work_utreexo
+        .batch(|wf| {
+            let hasher = utreexo_hasher();
+            let mut utxo_proofs = utxo_proofs.iter();
+
+            for logentry in txlog.iter() {
+                match logentry {
+                    // Remove item from the UTXO set
+                    TxEntry::Input(contract_id) => {
+                        let proof = utxo_proofs
+                            .next()
+                            .ok_or(BlockchainError::UtreexoProofMissing)?;
+
+                        let updated_proof = match catchup {
+                            Some(c) => Some(c.update_proof(contract_id, proof.clone(), &hasher)?),
+                            None => None,
+                        };
+                        let proof = updated_proof.as_ref().unwrap_or(proof);
+
+                        wf.delete(contract_id, proof, &hasher)?;
                     }
+                    // Add item to the UTXO set
+                    TxEntry::Output(contract) => {
+                        wf.insert(&contract.id(), &hasher);
+                    }
+                    // Ignore all other log entries
+                    _ => {}
                 }
-                Ok(())
-            })
-            .map(|_| ())
-    }
+            }
+            Ok(())
+        })
+        .map(|_| ())
 }
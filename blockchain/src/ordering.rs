@@ -0,0 +1,81 @@
+//! Canonical order of the transactions in a block.
+//!
+//! Since `BLOCK_VERSION_ORDERED`, transactions in a block must follow the canonical order,
+//! so that any node builds the same block from the same set of transactions,
+//! and a receiver of the transactions (e.g. via a compact block) restores their order
+//! without extra data.
+//!
+//! The order is topological: a transaction spending an output of another transaction
+//! in the same block is placed after it. Among the transactions whose dependencies are placed,
+//! the one with the highest feerate goes first, and the ties are broken by the lowest `TxID`.
+
+use core::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use zkvm::{ContractID, VerifiedTx};
+
+/// Returns the indices of the transactions in the canonical order.
+pub fn canonical_order<'a, I>(txs: I) -> Vec<usize>
+where
+    I: IntoIterator<Item = &'a VerifiedTx>,
+{
+    let txs: Vec<&VerifiedTx> = txs.into_iter().collect();
+
+    // Number of the unplaced dependencies of each transaction, and its dependents.
+    let mut pending = vec![0usize; txs.len()];
+    let mut dependents = vec![Vec::new(); txs.len()];
+    for (creator, spender) in spends(&txs) {
+        pending[spender] += 1;
+        dependents[creator].push(spender);
+    }
+
+    let priority = |i: usize| (txs[i].feerate, Reverse((txs[i].id.0).0), i);
+    let mut ready: BinaryHeap<_> = (0..txs.len())
+        .filter(|&i| pending[i] == 0)
+        .map(priority)
+        .collect();
+    let mut order = Vec::with_capacity(txs.len());
+    while let Some((_, _, i)) = ready.pop() {
+        order.push(i);
+        for &j in dependents[i].iter() {
+            pending[j] -= 1;
+            if pending[j] == 0 {
+                ready.push(priority(j));
+            }
+        }
+    }
+    // Transactions in a dependency cycle cannot be applied, so they are placed last in the original order.
+    if order.len() < txs.len() {
+        order.extend((0..txs.len()).filter(|&i| pending[i] > 0));
+    }
+    order
+}
+
+/// Returns the pairs of indices `(creator, spender)` of the transactions
+/// where the spender spends an output of the creator, in the order of the spenders' inputs.
+pub(crate) fn spends(txs: &[&VerifiedTx]) -> Vec<(usize, usize)> {
+    // Index of the transaction creating each output.
+    let mut creators = HashMap::<ContractID, usize>::new();
+    for (i, tx) in txs.iter().enumerate() {
+        for contract in tx.log.outputs() {
+            creators.insert(contract.id(), i);
+        }
+    }
+    let mut spends = Vec::new();
+    for (i, tx) in txs.iter().enumerate() {
+        for contract_id in tx.log.inputs() {
+            match creators.get(contract_id) {
+                Some(&j) if j != i => spends.push((j, i)),
+                _ => {}
+            }
+        }
+    }
+    spends
+}
+
+/// Checks that the transactions follow the canonical order.
+pub fn is_canonical_order(txs: &[VerifiedTx]) -> bool {
+    canonical_order(txs)
+        .into_iter()
+        .enumerate()
+        .all(|(position, i)| position == i)
+}
@@ -4,10 +4,13 @@
 
 use core::convert::AsRef;
 use core::hash::Hash;
+use core::mem;
 use std::collections::hash_map::RandomState;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::fmt::Debug;
-use std::time::Instant;
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use merlin::Transcript;
@@ -15,11 +18,15 @@ use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
 use starsig::{Signature, SigningKey, VerificationKey};
 use zkvm::bulletproofs::BulletproofGens;
-use zkvm::ContractID;
-
-use super::block::{BlockHeader, BlockID, BlockTx, VerifiedBlock};
-use super::errors::BlockchainError;
-use super::mempool::Mempool;
+use zkvm::{merkle, ContractID, DeferredVerification, TxID};
+
+use super::block::{
+    txidroot_hasher, AuxCommitment, BlockHeader, BlockID, BlockTx, VerifiedBlock,
+    BLOCK_VERSION_TXIDROOT,
+};
+use super::consensus::{ConsensusDriver, SingleSigner};
+use super::errors::{BlockchainError, FailureClass};
+use super::mempool::{Mempool, MempoolEvent};
 use super::shortid::{self, ShortIDVec};
 use super::state::BlockchainState;
 use super::utreexo;
@@ -27,9 +34,49 @@ use super::utreexo;
 /// Current version of the sync protocol.
 const CURRENT_VERSION: u64 = 0;
 
+/// Feature bit indicating that the node accepts `Compressed` messages.
+pub const FEATURE_COMPRESSION: u64 = 1 << 0;
+
+/// Feature bit indicating that the node serves the block headers with `GetHeaders`.
+pub const FEATURE_HEADERS: u64 = 1 << 1;
+
+/// Feature bit indicating that the node accepts `Finality` messages.
+pub const FEATURE_FINALITY: u64 = 1 << 2;
+
+/// Feature bit indicating that the node accepts `ShortIDCollision` messages.
+pub const FEATURE_SHORTID_COLLISION: u64 = 1 << 3;
+
+/// Feature bit indicating that the node accepts `BlockUnavailable` messages.
+pub const FEATURE_BLOCK_UNAVAILABLE: u64 = 1 << 4;
+
+/// Features supported by this implementation.
+const SUPPORTED_FEATURES: u64 = FEATURE_COMPRESSION
+    | FEATURE_HEADERS
+    | FEATURE_FINALITY
+    | FEATURE_SHORTID_COLLISION
+    | FEATURE_BLOCK_UNAVAILABLE;
+
+/// Maximum number of headers sent in one `Headers` message.
+const MAX_HEADERS_PER_MESSAGE: u64 = 2000;
+
 /// Number of sync cycles after which the ShortID nonce is rotated.
 const SHORTID_NONCE_TTL: usize = 50;
 
+/// Maximum number of txs a peer may request by ID within `TXS_REQUEST_INTERVAL_SECS`.
+const MAX_TXS_REQUESTED_PER_INTERVAL: usize = 1000;
+
+/// Interval (in seconds) over which the number of txs requested by ID is limited.
+const TXS_REQUEST_INTERVAL_SECS: u64 = 60;
+
+/// Maximum factor by which the inventory interval grows while the peer's inventory does not change.
+const MAX_INVENTORY_BACKOFF: u32 = 8;
+
+/// Number of seconds after which the block request is re-assigned to another peer.
+const BLOCK_REQUEST_TIMEOUT_SECS: u64 = 10;
+
+/// Default number of seconds without progress after which the node gives up on the target tip.
+const DEFAULT_TARGET_TIP_TIMEOUT_SECS: u64 = 120;
+
 /// Enumeration of all protocol messages
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Message {
@@ -37,8 +84,15 @@ pub enum Message {
     Inventory(Inventory),
     GetBlock(GetBlock),
     Block(Block),
+    BlockUnavailable(BlockUnavailable),
     GetMempoolTxs(GetMempoolTxs),
     MempoolTxs(MempoolTxs),
+    Finality(Finality),
+    ShortIDCollision(ShortIDCollision),
+    GetTxs(GetTxs),
+    Compressed(Compressed),
+    GetHeaders(GetHeaders),
+    Headers(Headers),
 }
 
 /// Request for the state of the node.
@@ -46,6 +100,7 @@ pub enum Message {
 pub struct GetInventory {
     pub(crate) version: u64,
     pub(crate) shortid_nonce: u64,
+    pub(crate) features: u64,
 }
 
 /// Response with the state of the node.
@@ -56,6 +111,7 @@ pub struct Inventory {
     pub(crate) tip_signature: Signature,
     pub(crate) shortid_nonce: u64,
     pub(crate) shortid_list: ShortIDVec,
+    pub(crate) features: u64,
 }
 
 /// Request of a block
@@ -70,6 +126,27 @@ pub struct Block {
     pub(crate) header: BlockHeader,
     pub(crate) signature: Signature,
     pub(crate) txs: Vec<BlockTx>,
+    pub(crate) aux: Vec<AuxCommitment>,
+}
+
+/// Request of consecutive block headers starting at a given height.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GetHeaders {
+    pub(crate) from_height: u64,
+    pub(crate) count: u64,
+}
+
+/// Response with consecutive block headers, at most `MAX_HEADERS_PER_MESSAGE` of them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Headers {
+    pub(crate) headers: Vec<BlockHeader>,
+}
+
+/// Response to `GetBlock` for a block whose body was discarded by the pruned node.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BlockUnavailable {
+    pub(crate) height: u64,
+    pub(crate) pruned_height: u64,
 }
 
 /// Request for mempool txs
@@ -85,8 +162,85 @@ pub struct MempoolTxs {
     pub(crate) tip: BlockID,
     pub(crate) txs: Vec<BlockTx>,
 }
+
+/// Request for mempool txs by their full IDs.
+/// Response is sent with the `MempoolTxs` message.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GetTxs {
+    pub(crate) txids: Vec<TxID>,
+}
+
+/// List of full IDs of mempool txs whose short IDs collide in the inventory sent to the peer.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShortIDCollision {
+    pub(crate) tip: BlockID,
+    pub(crate) txids: Vec<TxID>,
+}
+
+/// `Block`, `Headers` or `MempoolTxs` message compressed for the peer that supports `FEATURE_COMPRESSION`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Compressed {
+    pub(crate) data: Vec<u8>,
+}
+
+/// Block that is declared final by the network: nodes never reorganize below it.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Height of the finalized block.
+    pub height: u64,
+    /// ID of the finalized block.
+    pub block_id: BlockID,
+}
+
+/// Phase of the initial block download.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SyncPhase {
+    /// Downloading the headers of the chain that leads to the target tip.
+    Headers,
+    /// Downloading the blocks along the downloaded headers.
+    Blocks,
+    /// Our tip is the target tip: the node synchronizes the mempool only.
+    CaughtUp,
+}
+
+/// Progress of the initial block download.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SyncStatus {
+    /// Current phase of the download.
+    pub phase: SyncPhase,
+    /// Height of our tip.
+    pub tip_height: u64,
+    /// Height of the tip the node is synchronizing to.
+    pub target_height: u64,
+    /// Percentage of the headers downloaded towards the target tip.
+    pub headers_percent: u8,
+    /// Percentage of the blocks downloaded towards the target tip.
+    pub blocks_percent: u8,
+}
+
+/// Block assembled from the mempool, to be signed by an external block producer.
+#[derive(Clone)]
+pub struct BlockTemplate {
+    /// Header of the block to be signed.
+    pub header: BlockHeader,
+    /// Transactions included in the block.
+    pub txs: Vec<BlockTx>,
+    /// State of the blockchain after applying the block.
+    pub state: BlockchainState,
+}
+
+/// Checkpoint signed by the network key.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Finality {
+    pub(crate) checkpoint: Checkpoint,
+    pub(crate) signature: Signature,
+}
+
 #[async_trait]
 pub trait Delegate {
+    /// Identifier of the peer, such as its authenticated identity key (`p2p::PeerID`).
+    /// It should not be a transport address: the shortid salts are derived from it,
+    /// and the per-peer state must survive reconnects and address changes.
     type PeerIdentifier: Clone + AsRef<[u8]> + Eq + Hash + Debug;
 
     /// ID of our node.
@@ -95,6 +249,10 @@ pub trait Delegate {
     /// Send a message to a given peer.
     async fn send(&mut self, peer: Self::PeerIdentifier, message: Message);
 
+    /// Disconnects the peer and refuses its connections from now on:
+    /// it sent data that violates the consensus rules.
+    async fn ban_peer(&mut self, peer: Self::PeerIdentifier);
+
     /// Returns current height of the chain.
     /// Default implementation calls `tip().0.height`.
     fn tip_height(&self) -> u64 {
@@ -112,34 +270,147 @@ pub trait Delegate {
     /// Returns a block at a given height
     fn block_at_height(&self, height: u64) -> Option<Block>;
 
+    /// Returns the header of the block at a given height.
+    /// Default implementation calls `block_at_height`: pruned nodes that keep
+    /// the headers of the discarded blocks should override it.
+    fn header_at_height(&self, height: u64) -> Option<BlockHeader> {
+        self.block_at_height(height).map(|block| block.header)
+    }
+
+    /// Returns the height of the latest block whose body was discarded by pruning.
+    /// Headers, utreexo state and the wallet index are retained for all blocks.
+    /// Default implementation returns 0: the node keeps all the blocks.
+    fn pruned_height(&self) -> u64 {
+        0
+    }
+
     /// Blockchain state
     fn blockchain_state(&self) -> &BlockchainState;
 
     /// Stores a new block and an updated state.
     /// Guaranteed to be called monotonically for blocks with height=2, then 3, etc.
     fn store_block(&mut self, verified_block: VerifiedBlock, signature: Signature);
+
+    /// Returns the latest finalized checkpoint with its signature.
+    fn finalized_checkpoint(&self) -> Option<(Checkpoint, Signature)>;
+
+    /// Stores a new finalized checkpoint.
+    /// Guaranteed to be called with monotonically increasing heights.
+    fn store_finalized_checkpoint(&mut self, checkpoint: Checkpoint, signature: Signature);
 }
 
 pub struct BlockchainProtocol<D: Delegate> {
     network_pubkey: VerificationKey,
     delegate: D,
     target_tip: BlockHeader,
+    // tips above ours announced by the peers; the highest one becomes the target tip.
+    candidate_tips: Vec<CandidateTip<D::PeerIdentifier>>,
+    target_tip_timeout_secs: u64,
+    // last time the target tip changed or a block towards it was received.
+    last_sync_progress: Instant,
+    sync_phase: SyncPhase,
+    // headers of the chain towards the target tip, starting with our tip at the start of the download.
+    sync_headers: Vec<BlockHeader>,
+    sync_window: usize,
+    // heights of the blocks requested during the download, with the peers and the time of the request.
+    requested_blocks: HashMap<u64, (D::PeerIdentifier, Instant)>,
+    // blocks that arrived ahead of the preceding ones, with the peers that sent them.
+    received_blocks: BTreeMap<u64, (D::PeerIdentifier, Block)>,
+    // peers that sent data violating the consensus rules, banned once the message is processed.
+    misbehaving_peers: Vec<D::PeerIdentifier>,
     peers: HashMap<D::PeerIdentifier, PeerInfo>,
     shortid_nonce: u64,
     shortid_nonce_ttl: usize,
     mempool: Mempool,
+    // The receiver is not `Sync`: the lock lets the node share the protocol between its tasks.
+    mempool_events: Mutex<mpsc::Receiver<MempoolEvent>>,
+    mempool_revision: u64,
     bp_gens: BulletproofGens,
     inventory_interval_secs: u64,
+    sync_batch_size: usize,
+    // blocks received during the initial sync, with the signatures not verified yet.
+    pending_blocks: Vec<(VerifiedBlock, Signature)>,
+    pending_batch: DeferredVerification,
+    // block prepared for the external block producer, waiting for the signature.
+    block_template: Option<VerifiedBlock>,
 }
 
 /// Status of the peer.
 struct PeerInfo {
     tip: Option<BlockHeader>,
     needs_our_inventory: bool,
+    needs_our_finality: bool,
     their_short_id_nonce: u64,
     shortid_nonce: u64,
     shortid_list: ShortIDVec,
     last_inventory_received: Instant,
+    last_inventory_requested: Instant,
+    // multiplier for the inventory interval, grows while the peer's inventory does not change.
+    inventory_backoff: u32,
+    // our state at the moment we've sent the inventory to the peer.
+    sent_inventory: Option<InventoryState>,
+    // latest height for which the peer reported the block as unavailable.
+    pruned_height: u64,
+    // features advertised by the peer in its inventory messages.
+    features: u64,
+    // number of txs requested by ID since `txs_requested_since`.
+    txs_requested: usize,
+    txs_requested_since: Instant,
+    // tip announced by the peer that the node gave up on: ignored until the peer announces another one.
+    abandoned_tip: Option<BlockID>,
+    // time when the outstanding headers or block request was sent to the peer.
+    request_sent: Option<Instant>,
+    // moving average of the time the peer takes to respond to the headers and block requests.
+    response_time: Option<Duration>,
+}
+
+/// Tip announced by the peers, which the node may synchronize to.
+struct CandidateTip<P> {
+    header: BlockHeader,
+    // peers whose latest inventory announced this tip.
+    peers: HashSet<P>,
+}
+
+impl PeerInfo {
+    /// Expected time for the peer to respond: the average response time,
+    /// or the time its outstanding request is pending, whichever is longer.
+    /// Peers that never responded yet are tried first.
+    fn expected_response_time(&self, now: Instant) -> Duration {
+        let pending = self
+            .request_sent
+            .map(|sent| now.duration_since(sent))
+            .unwrap_or_default();
+        self.response_time.unwrap_or_default().max(pending)
+    }
+
+    /// Updates the average response time once the outstanding request is answered.
+    fn response_received(&mut self, now: Instant) {
+        if let Some(sent) = self.request_sent.take() {
+            let elapsed = now.duration_since(sent);
+            self.response_time = Some(match self.response_time {
+                Some(average) => (average * 3 + elapsed) / 4,
+                None => elapsed,
+            });
+        }
+    }
+}
+
+impl fmt::Display for SyncPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncPhase::Headers => write!(f, "headers"),
+            SyncPhase::Blocks => write!(f, "blocks"),
+            SyncPhase::CaughtUp => write!(f, "caught_up"),
+        }
+    }
+}
+
+/// Snapshot of the node's state reflected in the inventory.
+#[derive(Copy, Clone, PartialEq)]
+struct InventoryState {
+    tip: BlockID,
+    mempool_revision: u64,
+    shortid_nonce: u64,
 }
 
 impl<D: Delegate> BlockchainProtocol<D> {
@@ -147,26 +418,138 @@ impl<D: Delegate> BlockchainProtocol<D> {
     pub fn new(network_pubkey: VerificationKey, delegate: D) -> Self {
         let state = delegate.blockchain_state().clone();
         let tip = state.tip.clone();
+        let mut mempool = Mempool::new(state, tip.timestamp_ms);
+        let mempool_events = mempool.subscribe();
         BlockchainProtocol {
             network_pubkey,
             delegate,
-            mempool: Mempool::new(state, tip.timestamp_ms),
+            mempool,
+            mempool_events: Mutex::new(mempool_events),
+            mempool_revision: 0,
             target_tip: tip,
+            candidate_tips: Vec::new(),
+            target_tip_timeout_secs: DEFAULT_TARGET_TIP_TIMEOUT_SECS,
+            last_sync_progress: Instant::now(),
+            sync_phase: SyncPhase::CaughtUp,
+            sync_headers: Vec::new(),
+            sync_window: 16,
+            requested_blocks: HashMap::new(),
+            received_blocks: BTreeMap::new(),
+            misbehaving_peers: Vec::new(),
             bp_gens: BulletproofGens::new(256, 1),
             peers: HashMap::new(),
             shortid_nonce: thread_rng().gen::<u64>(),
             shortid_nonce_ttl: SHORTID_NONCE_TTL,
             inventory_interval_secs: 60,
+            sync_batch_size: 16,
+            pending_blocks: Vec::new(),
+            pending_batch: DeferredVerification::new(),
+            block_template: None,
         }
     }
 
     /// Sets the interval (in seconds) to request inventory from the peers.
     /// If set to zero, the inventory is requested on every invocation of `synchronize`.
+    /// The interval grows up to 8 times for the peers whose inventory does not change.
     pub fn set_inventory_interval(mut self, secs: u64) -> Self {
         self.inventory_interval_secs = secs;
         self
     }
 
+    /// Sets the number of blocks whose signatures are verified in one batch
+    /// while the node is catching up with the network.
+    /// If the batch fails, the blocks are verified one by one to find the offending block.
+    /// If set to 0 or 1, each block is verified separately.
+    pub fn set_sync_batch_size(mut self, blocks: usize) -> Self {
+        self.sync_batch_size = blocks;
+        self
+    }
+
+    /// Sets the number of blocks above our tip that are requested at once
+    /// while the node is catching up with the network.
+    /// The blocks are spread across the peers that announced the target tip,
+    /// and applied in order as they arrive.
+    /// If set to 0 or 1, the blocks are requested one by one.
+    pub fn set_sync_window(mut self, blocks: usize) -> Self {
+        self.sync_window = blocks;
+        self
+    }
+
+    /// Replaces the mempool, e.g. with one configured with the size and feerate limits.
+    /// The mempool must be created for the current state of the delegate.
+    pub fn set_mempool(mut self, mut mempool: Mempool) -> Self {
+        self.mempool_events = Mutex::new(mempool.subscribe());
+        self.mempool = mempool;
+        self
+    }
+
+    /// Sets the time (in seconds) the node waits for the blocks towards the target tip.
+    /// If the announced chain does not materialize in time, the node falls back
+    /// to the next highest tip announced by the peers, or to its own tip.
+    /// Default is 120 seconds.
+    pub fn set_target_tip_timeout(mut self, secs: u64) -> Self {
+        self.target_tip_timeout_secs = secs;
+        self
+    }
+
+    /// Returns the delegate that stores the blockchain.
+    pub fn delegate(&self) -> &D {
+        &self.delegate
+    }
+
+    /// Returns the mutable delegate that stores the blockchain.
+    pub fn delegate_mut(&mut self) -> &mut D {
+        &mut self.delegate
+    }
+
+    /// Returns the pool of unconfirmed transactions relayed to the peers.
+    pub fn mempool(&self) -> &Mempool {
+        &self.mempool
+    }
+
+    /// Returns the mutable pool of unconfirmed transactions.
+    /// Transactions added to it are announced to the peers on the next `synchronize`.
+    pub fn mempool_mut(&mut self) -> &mut Mempool {
+        &mut self.mempool
+    }
+
+    /// Returns the tip the node is synchronizing to:
+    /// the highest tip announced by the peers, or our own tip if we are up to date.
+    pub fn target_tip(&self) -> &BlockHeader {
+        &self.target_tip
+    }
+
+    /// Returns the progress of the initial block download.
+    pub fn sync_status(&self) -> SyncStatus {
+        let tip_height = self.delegate.tip_height();
+        let target_height = self.target_tip.height;
+        let start_height = self
+            .sync_headers
+            .first()
+            .map(|header| header.height)
+            .unwrap_or(tip_height);
+        let total = target_height.saturating_sub(start_height);
+        let percent = |done: u64| {
+            if total == 0 {
+                100
+            } else {
+                (done.min(total) * 100 / total) as u8
+            }
+        };
+        let headers_done = match self.sync_phase {
+            SyncPhase::Headers => self.next_header_height() - 1 - start_height,
+            _ => total,
+        };
+        let blocks_done = self.sync_tip_height().saturating_sub(start_height);
+        SyncStatus {
+            phase: self.sync_phase,
+            tip_height,
+            target_height,
+            headers_percent: percent(headers_done),
+            blocks_percent: percent(blocks_done),
+        }
+    }
+
     /// Creates a new network.
     pub fn new_network<I>(
         network_signing_key: SigningKey,
@@ -182,61 +565,166 @@ impl<D: Delegate> BlockchainProtocol<D> {
     }
 
     /// Called when a node receives a message from the peer.
+    /// The peer is banned if the message violates the consensus rules
+    /// (see `FailureClass::Consensus`), as are the peers whose earlier blocks
+    /// turned out to be invalid while processing it.
     pub async fn process_message(
         &mut self,
         pid: D::PeerIdentifier,
         message: Message,
     ) -> Result<(), BlockchainError> {
-        // TODO: represent ban scenarios with subcategory of errors and ban here.
+        let result = self.dispatch_message(pid.clone(), message).await;
+        if let Err(e) = &result {
+            if e.failure_class() == FailureClass::Consensus {
+                self.distrust_peer(&pid);
+            }
+        }
+        for pid in core::mem::take(&mut self.misbehaving_peers) {
+            self.delegate.ban_peer(pid).await;
+        }
+        result
+    }
+
+    async fn dispatch_message(
+        &mut self,
+        pid: D::PeerIdentifier,
+        message: Message,
+    ) -> Result<(), BlockchainError> {
+        let message = match message {
+            Message::Compressed(compressed) => compressed.decompress()?,
+            message => message,
+        };
         match message {
             Message::GetInventory(request) => self.process_inventory_request(pid, request).await?,
             Message::Inventory(inventory) => self.receive_inventory(pid, inventory).await?,
             Message::GetBlock(request) => self.send_block(pid, request).await?,
-            Message::Block(block_msg) => self.receive_block(block_msg)?,
+            Message::Block(block_msg) => self.receive_block(pid, block_msg)?,
+            Message::BlockUnavailable(msg) => self.receive_block_unavailable(pid, msg),
             Message::GetMempoolTxs(request) => self.send_txs(pid, request).await,
             Message::MempoolTxs(request) => self.receive_txs(request).await?,
+            Message::Finality(finality) => self.receive_finality(finality)?,
+            Message::ShortIDCollision(collision) => {
+                self.receive_shortid_collision(pid, collision).await?
+            }
+            Message::GetTxs(request) => self.send_txs_by_id(pid, request).await?,
+            Message::GetHeaders(request) => self.send_headers(pid, request).await,
+            Message::Headers(msg) => self.receive_headers(pid, msg)?,
+            Message::Compressed(_) => return Err(BlockchainError::InvalidCompressedMessage),
         }
         Ok(())
     }
 
     /// Called periodically (every 1-2 seconds).
+    /// Inventory is sent only to the peers that have not yet seen our current state:
+    /// either they requested it with a new nonce, or our tip or mempool has changed since.
     pub async fn synchronize(&mut self) {
         self.rotate_shortid_nonce_if_needed();
 
+        let mempool_events = self
+            .mempool_events
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner());
+        if mempool_events.try_iter().count() > 0 {
+            self.mempool_revision += 1;
+        }
+
         let (tip_header, tip_signature) = self.delegate.tip();
+        let tip_id = tip_header.id();
+        let mempool_revision = self.mempool_revision;
 
-        for (pid, peer) in self.peers.iter().filter(|(_, p)| p.needs_our_inventory) {
+        for (pid, peer) in self.peers.iter_mut() {
+            let state = InventoryState {
+                tip: tip_id,
+                mempool_revision,
+                shortid_nonce: peer.their_short_id_nonce,
+            };
+            // Peers that never asked for our inventory do not receive updates.
+            let subscribed = peer.needs_our_inventory || peer.sent_inventory.is_some();
+            if !subscribed || peer.sent_inventory == Some(state) {
+                continue;
+            }
+            let (shortid_list, collisions) = Self::mempool_inventory_for_peer(
+                &self.mempool,
+                pid.clone(),
+                peer.their_short_id_nonce,
+            );
             let msg = Message::Inventory(Inventory {
                 version: CURRENT_VERSION,
+                features: SUPPORTED_FEATURES,
                 tip: tip_header.clone(),
                 tip_signature: tip_signature.clone(),
                 shortid_nonce: peer.their_short_id_nonce,
-                shortid_list: self
-                    .mempool_inventory_for_peer(pid.clone(), peer.their_short_id_nonce),
+                shortid_list,
             });
+            peer.sent_inventory = Some(state);
             self.delegate.send(pid.clone(), msg).await;
+            // The peer cannot tell apart txs with the same short ID,
+            // so we tell it their full IDs to fetch them separately.
+            // Legacy peers cannot decode the message: they receive such txs in the blocks.
+            if !collisions.is_empty() && peer.features & FEATURE_SHORTID_COLLISION != 0 {
+                let msg = Message::ShortIDCollision(ShortIDCollision {
+                    tip: tip_id,
+                    txids: collisions,
+                });
+                self.delegate.send(pid.clone(), msg).await;
+            }
+        }
+
+        // Legacy peers cannot decode the finality: the checkpoint waits
+        // until the peer advertises the feature in its inventory messages.
+        if let Some((checkpoint, signature)) = self.delegate.finalized_checkpoint() {
+            for (pid, peer) in self
+                .peers
+                .iter_mut()
+                .filter(|(_, p)| p.needs_our_finality && p.features & FEATURE_FINALITY != 0)
+            {
+                let msg = Message::Finality(Finality {
+                    checkpoint,
+                    signature,
+                });
+                self.delegate.send(pid.clone(), msg).await;
+                peer.needs_our_finality = false;
+            }
         }
 
         for (_pid, peer) in self.peers.iter_mut() {
             peer.needs_our_inventory = false;
         }
 
-        if self.target_tip.id() != self.delegate.tip_id() {
+        // Give up on the target tip if its chain does not materialize,
+        // and fall back to the next candidate or our own tip.
+        if self.target_tip.id() != self.delegate.tip_id()
+            && self.last_sync_progress.elapsed().as_secs() >= self.target_tip_timeout_secs
+        {
+            self.abandon_tip(self.target_tip.id());
+        }
+        self.retarget();
+
+        // Pending blocks are flushed even if the target tip was abandoned meanwhile.
+        if self.target_tip.id() != self.delegate.tip_id() || !self.pending_blocks.is_empty() {
             self.synchronize_chain().await;
         } else {
             self.synchronize_mempool().await;
         }
 
         // For peers who have not sent inventory for over a minute, we request inventory again.
+        // Peers with unchanging inventory are asked less often.
         let now = Instant::now();
         let interval_secs = self.inventory_interval_secs;
         let invpids: Vec<_> = self
             .peers
-            .iter()
+            .iter_mut()
             .filter(|(_, peer)| {
-                now.duration_since(peer.last_inventory_received).as_secs() >= interval_secs
+                let last_update = peer
+                    .last_inventory_received
+                    .max(peer.last_inventory_requested);
+                now.duration_since(last_update).as_secs()
+                    >= interval_secs * (peer.inventory_backoff as u64)
+            })
+            .map(|(pid, peer)| {
+                peer.last_inventory_requested = now;
+                pid.clone()
             })
-            .map(|(pid, _)| pid.clone())
             .collect();
         for pid in invpids.into_iter() {
             self.request_inventory(pid).await;
@@ -250,19 +738,45 @@ impl<D: Delegate> BlockchainProtocol<D> {
             PeerInfo {
                 tip: None,
                 needs_our_inventory: false,
+                needs_our_finality: true,
                 their_short_id_nonce: 0,
                 shortid_nonce: self.shortid_nonce,
                 shortid_list: ShortIDVec::default(),
                 last_inventory_received: Instant::now(),
+                last_inventory_requested: Instant::now(),
+                inventory_backoff: 1,
+                sent_inventory: None,
+                pruned_height: 0,
+                features: 0,
+                txs_requested: 0,
+                txs_requested_since: Instant::now(),
+                abandoned_tip: None,
+                request_sent: None,
+                response_time: None,
             },
         );
 
         self.request_inventory(pid).await;
     }
 
+    /// Requests mempool txs with the given IDs from the peer.
+    /// Txs received in response are added to the mempool.
+    pub async fn request_txs(&mut self, pid: D::PeerIdentifier, txids: Vec<TxID>) {
+        self.delegate
+            .send(pid, Message::GetTxs(GetTxs { txids }))
+            .await;
+    }
+
     /// Called when a peer disconnects.
     pub async fn peer_disconnected(&mut self, pid: D::PeerIdentifier) {
         self.peers.remove(&pid);
+        for candidate in self.candidate_tips.iter_mut() {
+            candidate.peers.remove(&pid);
+        }
+        // Heights requested from the peer are re-assigned to the others.
+        self.requested_blocks
+            .retain(|_height, (p, _sent)| p != &pid);
+        self.retarget();
     }
 
     /// Adds transaction to the mempool.
@@ -274,62 +788,250 @@ impl<D: Delegate> BlockchainProtocol<D> {
     /// Creates and signs block, and updates the state.
     /// The API makes sure that the node state is updated with the new block,
     /// so the user cannot accidentally sign two conflicting blocks.
-    /// Obviously, a multi-party signing, SCP or any other decentralized consensus algorithm
-    /// would have a different API.
-    pub fn create_block(&mut self, timestamp_ms: u64, signing_key: SigningKey) {
+    /// Fails with `InvalidBlockSignature` and does not store the block
+    /// if the key does not match the network key.
+    /// Multi-party signing, SCP or any other decentralized consensus algorithm
+    /// drives the block production with `produce_block` instead.
+    pub fn create_block(
+        &mut self,
+        timestamp_ms: u64,
+        signing_key: SigningKey,
+    ) -> Result<(), BlockchainError> {
+        self.produce_block(timestamp_ms, &mut SingleSigner::new(signing_key))
+            .map(|_| ())
+    }
+
+    /// Assembles the next block and passes it through the consensus engine.
+    /// Returns `true` if the block was committed and stored.
+    /// If the engine has not committed the block yet, the node keeps it as the block template
+    /// until its signature is submitted with `submit_signed_block`.
+    pub fn produce_block(
+        &mut self,
+        timestamp_ms: u64,
+        driver: &mut impl ConsensusDriver,
+    ) -> Result<bool, BlockchainError> {
+        let template = self.block_template(timestamp_ms);
+        if !driver.propose(&template) || !driver.prevote(&template.header) {
+            self.block_template = None;
+            return Ok(false);
+        }
+        match driver.commit(&template.header) {
+            Some(signature) => self.submit_signed_block(signature).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Assembles the next block from the mempool without signing or storing it,
+    /// so the block production can be driven by an external signer.
+    /// The node keeps the template until its signature is submitted with `submit_signed_block`.
+    /// A new template replaces the previous one.
+    pub fn block_template(&mut self, timestamp_ms: u64) -> BlockTemplate {
+        let verified_block = self.make_block(timestamp_ms);
+        let template = BlockTemplate {
+            header: verified_block.header.clone(),
+            txs: verified_block.raw_txs.clone(),
+            state: verified_block.blockchain_state(),
+        };
+        self.block_template = Some(verified_block);
+        template
+    }
+
+    /// Stores the block prepared by `block_template` with the block producer's signature,
+    /// and updates the state.
+    /// Fails if there is no template, if the template no longer extends the tip,
+    /// or if the signature does not match the network key (the template is kept then).
+    pub fn submit_signed_block(&mut self, signature: Signature) -> Result<(), BlockchainError> {
+        let verified_block = self
+            .block_template
+            .take()
+            .ok_or(BlockchainError::StaleBlockTemplate)?;
+        if verified_block.header.prev != self.delegate.tip_id() {
+            return Err(BlockchainError::StaleBlockTemplate);
+        }
+        if !verify_block_signature(&verified_block.header, &signature, self.network_pubkey) {
+            self.block_template = Some(verified_block);
+            return Err(BlockchainError::InvalidBlockSignature);
+        }
+        self.commit_block(verified_block, signature);
+        Ok(())
+    }
+
+    /// Signs the current tip as a finalized checkpoint and sends it out to the peers.
+    /// Nodes refuse blocks that conflict with the finalized checkpoint.
+    pub fn finalize(&mut self, signing_key: SigningKey) {
+        self.finalize_with(&mut SingleSigner::new(signing_key));
+    }
+
+    /// Asks the consensus engine to finalize the current tip,
+    /// and sends out the checkpoint to the peers if the engine signs it.
+    /// Returns `true` if the tip was finalized.
+    pub fn finalize_with(&mut self, driver: &mut impl ConsensusDriver) -> bool {
+        let tip = self.delegate.tip().0;
+        let checkpoint = Checkpoint {
+            height: tip.height,
+            block_id: tip.id(),
+        };
+        match driver.finalize(&checkpoint) {
+            Some(signature) => {
+                self.store_finality(checkpoint, signature);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the latest finalized checkpoint with the network's signature.
+    /// Light clients use it as a trusted starting point for syncing the headers.
+    pub fn finalized_checkpoint(&self) -> Option<(Checkpoint, Signature)> {
+        self.delegate.finalized_checkpoint()
+    }
+
+    /// Returns the ID of this node.
+    pub fn id(&self) -> D::PeerIdentifier {
+        self.delegate.self_id()
+    }
+}
+
+impl<D: Delegate> BlockchainProtocol<D> {
+    /// Converts the mempool into the next block at a given timestamp.
+    fn make_block(&mut self, timestamp_ms: u64) -> VerifiedBlock {
         // Note: we don't need to do that if all tx.maxtime's are 1-2 blocks away.
         // TODO: rethink whether we actually need the maxtime at all. It is not needed for relative timelocks in paychans,
         // and it is not helping with clearing up the mempool spam.
         let timestamp_ms = core::cmp::max(timestamp_ms, self.delegate.tip().0.timestamp_ms);
         self.mempool.update_timestamp(timestamp_ms);
 
-        // Note: we currently assume that the entire mempool is converted into a block,
-        // so we convert all the entries into the transactions.
-        let verified_block = self.mempool.make_block();
-
-        let signature = create_block_signature(&verified_block.header, signing_key);
+        // Transactions that do not fit in the block weight limit stay in the mempool.
+        self.mempool.make_block()
+    }
 
+    /// Updates the mempool and stores the block signed by the network key.
+    fn commit_block(&mut self, verified_block: VerifiedBlock, signature: Signature) {
         // Update the mempool
         self.mempool
             .update_state(verified_block.blockchain_state(), &verified_block.catchup);
 
-        self.target_tip = verified_block.header.clone();
+        self.block_template = None;
 
         // Store the block
         self.delegate.store_block(verified_block, signature);
+        self.retarget();
     }
 
-    /// Returns the ID of this node.
-    pub fn id(&self) -> D::PeerIdentifier {
-        self.delegate.self_id()
+    async fn synchronize_chain(&mut self) {
+        // Headers and blocks are requested only from the peers that announced the target tip,
+        // so we do not follow another chain.
+        if self.sync_phase == SyncPhase::Headers {
+            self.request_headers().await;
+        } else {
+            self.request_blocks().await;
+        }
     }
-}
 
-impl<D: Delegate> BlockchainProtocol<D> {
-    async fn synchronize_chain(&mut self) {
-        use rand::seq::IteratorRandom;
-
-        // Request the next block from a random peer.
-        // This is highly inefficient from the point of view of the node,
-        // but spreads the load on the network that prioritizes synchronizing
-        // recent transactions and blocks.
-        // TODO: find the peers that may have the block.
-        let height_needed = self.delegate.tip_height() + 1;
-        let relevant_peers = self.peers.iter().filter(|(_pid, peer)| {
-            peer.tip.as_ref().map(|h| h.height).unwrap_or(0) >= height_needed
-        });
-        if let Some((pid, _peer)) = relevant_peers.choose(&mut thread_rng()) {
+    /// Requests the next headers from the fastest peer among those that serve them.
+    async fn request_headers(&mut self) {
+        let now = Instant::now();
+        let fastest_peer = match self.target_supporters() {
+            Some(supporters) => self
+                .peers
+                .iter()
+                .filter(|(pid, peer)| {
+                    supporters.contains(pid) && peer.features & FEATURE_HEADERS != 0
+                })
+                .min_by_key(|(_pid, peer)| peer.expected_response_time(now))
+                .map(|(pid, _peer)| pid.clone()),
+            None => None,
+        };
+        match fastest_peer {
+            Some(pid) => {
+                if let Some(peer) = self.peers.get_mut(&pid) {
+                    peer.request_sent.get_or_insert(now);
+                }
+                let message = Message::GetHeaders(GetHeaders {
+                    from_height: self.next_header_height(),
+                    count: MAX_HEADERS_PER_MESSAGE,
+                });
+                self.delegate.send(pid, message).await;
+            }
+            None => {
+                // Nobody serves the headers: download the blocks right away,
+                // authenticating them by their signatures only.
+                self.sync_phase = SyncPhase::Blocks;
+            }
+        }
+    }
+
+    /// Requests the window of blocks above our tip from several peers at once.
+    /// Each height goes to the peer with the fewest outstanding requests, the fastest one first.
+    /// Heights whose requests time out are re-assigned, and pruned peers that reported
+    /// the block as unavailable are skipped.
+    async fn request_blocks(&mut self) {
+        let now = Instant::now();
+        let timeout = Duration::from_secs(BLOCK_REQUEST_TIMEOUT_SECS);
+        self.requested_blocks
+            .retain(|_height, (_pid, sent)| now.duration_since(*sent) < timeout);
+
+        let next_height = self.sync_tip_height() + 1;
+        let last_height =
+            (next_height + self.sync_window.max(1) as u64 - 1).min(self.target_tip.height);
+        let mut assignments = Vec::new();
+        if let Some(supporters) = self.target_supporters() {
+            let mut outstanding = HashMap::<&D::PeerIdentifier, usize>::new();
+            for (pid, _sent) in self.requested_blocks.values() {
+                *outstanding.entry(pid).or_insert(0) += 1;
+            }
+            for height in next_height..=last_height {
+                if self.requested_blocks.contains_key(&height)
+                    || self.received_blocks.contains_key(&height)
+                {
+                    continue;
+                }
+                let peer = self
+                    .peers
+                    .iter()
+                    .filter(|(pid, peer)| supporters.contains(pid) && peer.pruned_height < height)
+                    .min_by_key(|(pid, peer)| {
+                        (
+                            outstanding.get(pid).copied().unwrap_or(0),
+                            peer.expected_response_time(now),
+                        )
+                    });
+                match peer {
+                    Some((pid, _peer)) => {
+                        *outstanding.entry(pid).or_insert(0) += 1;
+                        assignments.push((height, pid.clone()));
+                    }
+                    None => break,
+                }
+            }
+        }
+
+        if assignments.is_empty() && self.requested_blocks.is_empty() {
+            // Nobody has the next block: do not hold the received blocks any longer.
+            // The offending block, if any, is discarded and requested again later.
+            let _ = self.verify_pending_blocks();
+            return;
+        }
+        for (height, pid) in assignments {
+            if let Some(peer) = self.peers.get_mut(&pid) {
+                peer.request_sent.get_or_insert(now);
+            }
+            self.requested_blocks.insert(height, (pid.clone(), now));
             self.delegate
-                .send(
-                    pid.clone(),
-                    Message::GetBlock(GetBlock {
-                        height: height_needed,
-                    }),
-                )
+                .send(pid, Message::GetBlock(GetBlock { height }))
                 .await;
         }
     }
 
+    /// Returns the peers that announced the target tip.
+    fn target_supporters(&self) -> Option<&HashSet<D::PeerIdentifier>> {
+        let target_id = self.target_tip.id();
+        self.candidate_tips
+            .iter()
+            .find(|c| c.header.id() == target_id)
+            .map(|c| &c.peers)
+    }
+
     async fn synchronize_mempool(&mut self) {
         // **If the target tip is the latest**, the node walks all peers in round-robin and constructs lists of [short IDs](#short-id) to request from each peer,
         // keeping track of already used IDs. Once all requests are constructed, the [`GetMempoolTxs`](#getmempooltxs) messages are sent out to respective peers.
@@ -391,6 +1093,7 @@ impl<D: Delegate> BlockchainProtocol<D> {
         self.peers.get_mut(&pid).map(|peer| {
             peer.needs_our_inventory = true;
             peer.their_short_id_nonce = request.shortid_nonce;
+            peer.features = request.features;
         });
         Ok(())
     }
@@ -401,6 +1104,7 @@ impl<D: Delegate> BlockchainProtocol<D> {
                 pid,
                 Message::GetInventory(GetInventory {
                     version: CURRENT_VERSION,
+                    features: SUPPORTED_FEATURES,
                     shortid_nonce: self.shortid_nonce,
                 }),
             )
@@ -414,6 +1118,7 @@ impl<D: Delegate> BlockchainProtocol<D> {
     ) -> Result<(), BlockchainError> {
         let Inventory {
             version,
+            features,
             tip,
             tip_signature,
             shortid_nonce,
@@ -425,19 +1130,60 @@ impl<D: Delegate> BlockchainProtocol<D> {
             return Err(BlockchainError::IncompatibleVersion);
         }
 
-        if tip.height > self.target_tip.height {
-            // check the signature and update the target tip
-            if !verify_block_signature(&tip, &tip_signature, self.network_pubkey) {
-                return Err(BlockchainError::InvalidBlockSignature);
+        // The peer supports only the tip from its latest inventory.
+        let tip_id = tip.id();
+        for candidate in self.candidate_tips.iter_mut() {
+            candidate.peers.remove(&pid);
+        }
+        let abandoned = self
+            .peers
+            .get(&pid)
+            .map(|peer| peer.abandoned_tip == Some(tip_id))
+            .unwrap_or(true);
+        if tip.height > self.delegate.tip_height() && !abandoned {
+            match self
+                .candidate_tips
+                .iter_mut()
+                .find(|c| c.header.id() == tip_id)
+            {
+                Some(candidate) => {
+                    candidate.peers.insert(pid.clone());
+                }
+                None => {
+                    // check the signature before considering the tip
+                    if !verify_block_signature(&tip, &tip_signature, self.network_pubkey) {
+                        return Err(BlockchainError::InvalidBlockSignature);
+                    }
+                    self.check_finalized_checkpoint(&tip)?;
+                    let mut peers = HashSet::new();
+                    peers.insert(pid.clone());
+                    self.candidate_tips.push(CandidateTip {
+                        header: tip.clone(),
+                        peers,
+                    });
+                }
             }
-            self.target_tip = tip.clone();
         }
+        self.retarget();
 
         // store the inventory until we figure out what we are missing per-peer in `synchronize_mempool`.
         self.peers.get_mut(&pid).map(|peer| {
+            let unchanged = peer.tip.as_ref().map(|t| t.id()) == Some(tip.id())
+                && peer.shortid_nonce == shortid_nonce
+                && peer.shortid_list == shortid_list;
+            peer.inventory_backoff = if unchanged {
+                (peer.inventory_backoff * 2).min(MAX_INVENTORY_BACKOFF)
+            } else {
+                1
+            };
+            if peer.abandoned_tip != Some(tip_id) {
+                peer.abandoned_tip = None;
+            }
             peer.tip = Some(tip);
             peer.shortid_nonce = shortid_nonce;
             peer.shortid_list = shortid_list;
+            peer.features = features;
+            peer.last_inventory_received = Instant::now();
         });
 
         Ok(())
@@ -448,40 +1194,356 @@ impl<D: Delegate> BlockchainProtocol<D> {
         pid: D::PeerIdentifier,
         request: GetBlock,
     ) -> Result<(), BlockchainError> {
-        let block = self
-            .delegate
-            .block_at_height(request.height)
-            .ok_or(BlockchainError::BlockNotFound(request.height))?;
-        self.delegate.send(pid, Message::Block(block)).await;
+        if request.height > self.delegate.tip_height() {
+            return Err(BlockchainError::BlockNotFound(request.height));
+        }
+        let pruned_height = self.delegate.pruned_height();
+        // Legacy peers cannot decode `BlockUnavailable`: they get no response, as before.
+        let accepts_unavailable = self
+            .peers
+            .get(&pid)
+            .map(|peer| peer.features & FEATURE_BLOCK_UNAVAILABLE != 0)
+            .unwrap_or(false);
+        let message = match self.delegate.block_at_height(request.height) {
+            Some(block) => Message::Block(block),
+            None if request.height <= pruned_height && accepts_unavailable => {
+                Message::BlockUnavailable(BlockUnavailable {
+                    height: request.height,
+                    pruned_height,
+                })
+            }
+            None => return Err(BlockchainError::BlockNotFound(request.height)),
+        };
+        self.send_compressible(pid, message).await;
+        Ok(())
+    }
+
+    async fn send_headers(&mut self, pid: D::PeerIdentifier, request: GetHeaders) {
+        let end_height = request
+            .from_height
+            .saturating_add(request.count.min(MAX_HEADERS_PER_MESSAGE))
+            .min(self.delegate.tip_height() + 1);
+        let mut headers = Vec::new();
+        for height in request.from_height..end_height {
+            match self.delegate.header_at_height(height) {
+                Some(header) => headers.push(header),
+                None => break,
+            }
+        }
+        self.send_compressible(pid, Message::Headers(Headers { headers }))
+            .await;
+    }
+
+    fn receive_headers(
+        &mut self,
+        pid: D::PeerIdentifier,
+        msg: Headers,
+    ) -> Result<(), BlockchainError> {
+        if let Some(peer) = self.peers.get_mut(&pid) {
+            peer.response_received(Instant::now());
+        }
+        let result = self.append_headers(msg);
+        if let Err(e) = &result {
+            if e.failure_class() == FailureClass::Consensus {
+                // Drop the headers downloaded so far, as we cannot tell which peer sent the bogus ones.
+                self.sync_headers.truncate(1);
+                self.distrust_peer(&pid);
+            }
+        }
+        result
+    }
+
+    /// Appends the received headers to the chain downloaded towards the target tip.
+    /// Switches to downloading the blocks once the chain reaches the target tip.
+    fn append_headers(&mut self, msg: Headers) -> Result<(), BlockchainError> {
+        // Silently ignore the headers we did not ask for, or received too late.
+        if self.sync_phase != SyncPhase::Headers
+            || msg.headers.first().map(|header| header.height) != Some(self.next_header_height())
+        {
+            return Ok(());
+        }
+        let mut prev = match self.sync_headers.last() {
+            Some(header) => (header.height, header.id()),
+            None => return Ok(()),
+        };
+        let target_height = self.target_tip.height;
+        let target_id = self.target_tip.id();
+        let mut headers = Vec::with_capacity(msg.headers.len());
+        for header in msg.headers.into_iter() {
+            if header.height > target_height {
+                break;
+            }
+            let id = header.id();
+            if header.height != prev.0 + 1
+                || header.prev != prev.1
+                || (header.height == target_height && id != target_id)
+            {
+                return Err(BlockchainError::InvalidHeaders(header.height));
+            }
+            self.check_finalized_checkpoint(&header)?;
+            prev = (header.height, id);
+            headers.push(header);
+        }
+        self.sync_headers.extend(headers);
+        self.last_sync_progress = Instant::now();
+        if prev.1 == target_id {
+            self.sync_phase = SyncPhase::Blocks;
+        }
         Ok(())
     }
 
-    fn receive_block(&mut self, block_msg: Block) -> Result<(), BlockchainError> {
+    /// Height of the next header to download towards the target tip.
+    fn next_header_height(&self) -> u64 {
+        self.sync_headers
+            .last()
+            .map(|header| header.height + 1)
+            .unwrap_or_else(|| self.delegate.tip_height() + 1)
+    }
+
+    /// Returns the downloaded header at a given height.
+    fn sync_header_at(&self, height: u64) -> Option<&BlockHeader> {
+        let start_height = self.sync_headers.first()?.height;
+        if height <= start_height {
+            return None;
+        }
+        self.sync_headers.get((height - start_height) as usize)
+    }
+
+    /// Sends the message compressed if the peer supports compression and the message is compressible.
+    async fn send_compressible(&mut self, pid: D::PeerIdentifier, message: Message) {
+        let supports_compression = self
+            .peers
+            .get(&pid)
+            .map(|peer| peer.features & FEATURE_COMPRESSION != 0)
+            .unwrap_or(false);
+        let message = if supports_compression {
+            Compressed::compress(&message)
+                .map(Message::Compressed)
+                .unwrap_or(message)
+        } else {
+            message
+        };
+        self.delegate.send(pid, message).await;
+    }
+
+    fn receive_block_unavailable(&mut self, pid: D::PeerIdentifier, msg: BlockUnavailable) {
+        // Remember how far the peer is pruned, so the next block is requested from someone else.
+        if let Some(peer) = self.peers.get_mut(&pid) {
+            peer.response_received(Instant::now());
+            peer.pruned_height = peer.pruned_height.max(msg.pruned_height.max(msg.height));
+        }
+        if self.requested_blocks.get(&msg.height).map(|(p, _)| p) == Some(&pid) {
+            self.requested_blocks.remove(&msg.height);
+        }
+    }
+
+    fn receive_block(
+        &mut self,
+        pid: D::PeerIdentifier,
+        block_msg: Block,
+    ) -> Result<(), BlockchainError> {
+        if let Some(peer) = self.peers.get_mut(&pid) {
+            peer.response_received(Instant::now());
+        }
+        let height = block_msg.header.height;
+        let requested = self.requested_blocks.remove(&height).is_some();
+        if requested && height > self.sync_tip_height() + 1 {
+            // The block arrived ahead of the preceding ones: keep it until they arrive.
+            if let Some(header) = self.sync_header_at(height) {
+                if header.id() != block_msg.header.id() {
+                    self.distrust_peer(&pid);
+                    return Err(BlockchainError::HeaderMismatch(height));
+                }
+            }
+            self.received_blocks.insert(height, (pid, block_msg));
+            return Ok(());
+        }
+        self.accept_block(pid, block_msg)?;
+
+        // Apply the blocks that arrived ahead of this one, in order.
+        // Failures are attributed to the peers that sent those blocks.
+        while let Some((pid, block_msg)) =
+            self.received_blocks.remove(&(self.sync_tip_height() + 1))
+        {
+            if self.accept_block(pid, block_msg).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn accept_block(
+        &mut self,
+        pid: D::PeerIdentifier,
+        block_msg: Block,
+    ) -> Result<(), BlockchainError> {
+        let result = self.apply_received_block(block_msg);
+        match &result {
+            Ok(()) => self.last_sync_progress = Instant::now(),
+            Err(e) if e.failure_class() == FailureClass::Consensus => self.distrust_peer(&pid),
+            Err(_) => {}
+        }
+        result
+    }
+
+    /// The peer sent us invalid data: do not follow the tip it announced,
+    /// and ban it once the current message is processed.
+    fn distrust_peer(&mut self, pid: &D::PeerIdentifier) {
+        if !self.misbehaving_peers.contains(pid) {
+            self.misbehaving_peers.push(pid.clone());
+        }
+        if let Some(peer) = self.peers.get_mut(pid) {
+            peer.abandoned_tip = peer.tip.as_ref().map(|tip| tip.id());
+        }
+        for candidate in self.candidate_tips.iter_mut() {
+            candidate.peers.remove(pid);
+        }
+        self.retarget();
+    }
+
+    fn apply_received_block(&mut self, block_msg: Block) -> Result<(), BlockchainError> {
         // Quick check: is this actually a block that we want?
-        if block_msg.header.height != self.delegate.tip_height() + 1 {
+        if block_msg.header.height != self.sync_tip_height() + 1 {
             // Silently ignore the irrelevant block - maybe we received it too late.
             return Err(BlockchainError::BlockNotRelevant(block_msg.header.height));
         }
 
+        // The block must match the downloaded header that leads to the target tip.
+        if let Some(header) = self.sync_header_at(block_msg.header.height) {
+            if header.id() != block_msg.header.id() {
+                return Err(BlockchainError::HeaderMismatch(block_msg.header.height));
+            }
+        }
+
         // Check the block signature.
         if !verify_block_signature(&block_msg.header, &block_msg.signature, self.network_pubkey) {
             return Err(BlockchainError::InvalidBlockSignature);
         }
 
+        // Refuse to reorganize below the finalized checkpoint.
+        self.check_finalized_checkpoint(&block_msg.header)?;
+
+        // While catching up with the network, the signatures are verified
+        // in one batch for several blocks.
+        let catching_up = block_msg.header.height < self.target_tip.height;
+        if self.sync_batch_size > 1 && (catching_up || !self.pending_blocks.is_empty()) {
+            let state = match self.pending_blocks.last() {
+                Some((verified_block, _)) => verified_block.blockchain_state(),
+                None => self.delegate.blockchain_state().clone(),
+            };
+            let mut deferred = DeferredVerification::new();
+            let mut verified_block = state.apply_block_deferred(
+                block_msg.header.clone(),
+                &block_msg.txs,
+                &self.bp_gens,
+                &mut deferred,
+            )?;
+            verified_block.set_aux_commitments(block_msg.aux)?;
+            self.pending_batch.append(deferred);
+            self.pending_blocks
+                .push((verified_block, block_msg.signature));
+
+            if self.pending_blocks.len() >= self.sync_batch_size || !catching_up {
+                self.verify_pending_blocks()?;
+            }
+            return Ok(());
+        }
+
         // Now the block header is authenticated, so we can do a more expensive validation.
         let state = self.delegate.blockchain_state();
-        let verified_block =
+        let mut verified_block =
             state.apply_block(block_msg.header.clone(), &block_msg.txs, &self.bp_gens)?;
+        verified_block.set_aux_commitments(block_msg.aux)?;
+        self.store_verified_block(verified_block, block_msg.signature);
+
+        Ok(())
+    }
+
+    /// Verifies the signatures of the pending blocks in one batch and stores the blocks.
+    /// If the batch fails, the blocks are verified one by one to find the offending block:
+    /// the blocks before it are stored, and the rest are discarded.
+    fn verify_pending_blocks(&mut self) -> Result<(), BlockchainError> {
+        let blocks = mem::take(&mut self.pending_blocks);
+        let batch = mem::replace(&mut self.pending_batch, DeferredVerification::new());
+        if batch.verify().is_ok() {
+            for (verified_block, signature) in blocks {
+                self.store_verified_block(verified_block, signature);
+            }
+            return Ok(());
+        }
+        for (pending_block, signature) in blocks {
+            let state = self.delegate.blockchain_state();
+            let mut verified_block =
+                state.apply_block(pending_block.header, &pending_block.raw_txs, &self.bp_gens)?;
+            verified_block.set_aux_commitments(pending_block.aux)?;
+            self.store_verified_block(verified_block, signature);
+        }
+        Ok(())
+    }
 
-        // Update the mempool.
+    /// Updates the mempool and stores the block.
+    fn store_verified_block(&mut self, verified_block: VerifiedBlock, signature: Signature) {
         self.mempool
             .update_state(verified_block.blockchain_state(), &verified_block.catchup);
+        self.delegate.store_block(verified_block, signature);
+    }
 
-        // Store the block
-        self.delegate
-            .store_block(verified_block, block_msg.signature);
+    /// Stops following the given tip: the peers that announced it
+    /// are not considered for it again until they announce another tip.
+    fn abandon_tip(&mut self, tip_id: BlockID) {
+        self.candidate_tips.retain(|c| c.header.id() != tip_id);
+        for (_pid, peer) in self.peers.iter_mut() {
+            if peer.tip.as_ref().map(|tip| tip.id()) == Some(tip_id) {
+                peer.abandoned_tip = Some(tip_id);
+            }
+        }
+        self.last_sync_progress = Instant::now();
+    }
 
-        Ok(())
+    /// Drops the candidate tips that are reached, no longer announced by any peer,
+    /// or conflicting with the finalized checkpoint, and sets the target tip
+    /// to the highest remaining candidate, or to our own tip.
+    fn retarget(&mut self) {
+        let tip = self.delegate.tip().0;
+        let checkpoint = self
+            .delegate
+            .finalized_checkpoint()
+            .map(|(checkpoint, _)| checkpoint);
+        self.candidate_tips.retain(|c| {
+            let conflicts = checkpoint
+                .as_ref()
+                .map(|cp| cp.height == c.header.height && cp.block_id != c.header.id())
+                .unwrap_or(false);
+            c.header.height > tip.height && !c.peers.is_empty() && !conflicts
+        });
+        let target = self
+            .candidate_tips
+            .iter()
+            .max_by_key(|c| (c.header.height, c.peers.len()))
+            .map(|c| c.header.clone())
+            .unwrap_or_else(|| tip.clone());
+        // Restart the download when the target changes, and finish it once we reach the target.
+        if target.id() == tip.id() {
+            self.sync_phase = SyncPhase::CaughtUp;
+            self.sync_headers.clear();
+            self.requested_blocks.clear();
+            self.received_blocks.clear();
+        } else if target.id() != self.target_tip.id() {
+            self.last_sync_progress = Instant::now();
+            self.sync_phase = SyncPhase::Headers;
+            self.sync_headers = vec![tip];
+            self.requested_blocks.clear();
+            self.received_blocks.clear();
+        }
+        self.target_tip = target;
+    }
+
+    /// Height of the latest received block, including the blocks pending the batch verification.
+    fn sync_tip_height(&self) -> u64 {
+        self.pending_blocks
+            .last()
+            .map(|(verified_block, _)| verified_block.header.height)
+            .unwrap_or_else(|| self.delegate.tip_height())
     }
 
     async fn send_txs(&mut self, pid: D::PeerIdentifier, request: GetMempoolTxs) {
@@ -502,7 +1564,8 @@ impl<D: Delegate> BlockchainProtocol<D> {
             }
         }
 
-        self.delegate.send(pid, Message::MempoolTxs(response)).await;
+        self.send_compressible(pid, Message::MempoolTxs(response))
+            .await;
     }
 
     async fn receive_txs(&mut self, request: MempoolTxs) -> Result<(), BlockchainError> {
@@ -513,7 +1576,7 @@ impl<D: Delegate> BlockchainProtocol<D> {
         for tx in request.txs.into_iter() {
             let result = self.mempool.append(tx, &self.bp_gens);
             if let Err(err) = result {
-                if let BlockchainError::UtreexoError(_) = err {
+                if let BlockchainError::UtreexoError(_) | BlockchainError::DoubleSpend(..) = err {
                     // Two nodes may have sent us double-spends, w/o being aware of them.
                     // that's not their fault.
                 } else {
@@ -526,6 +1589,112 @@ impl<D: Delegate> BlockchainProtocol<D> {
         Ok(())
     }
 
+    async fn receive_shortid_collision(
+        &mut self,
+        pid: D::PeerIdentifier,
+        collision: ShortIDCollision,
+    ) -> Result<(), BlockchainError> {
+        if collision.tip != self.delegate.tip_id() {
+            return Err(BlockchainError::StaleMempoolState(collision.tip));
+        }
+
+        let missing_txids: Vec<_> = collision
+            .txids
+            .into_iter()
+            .filter(|txid| self.mempool.get(txid).is_none())
+            .collect();
+        if missing_txids.is_empty() {
+            return Ok(());
+        }
+
+        self.request_txs(pid, missing_txids).await;
+        Ok(())
+    }
+
+    async fn send_txs_by_id(
+        &mut self,
+        pid: D::PeerIdentifier,
+        request: GetTxs,
+    ) -> Result<(), BlockchainError> {
+        if let Some(peer) = self.peers.get_mut(&pid) {
+            let now = Instant::now();
+            if now.duration_since(peer.txs_requested_since).as_secs() >= TXS_REQUEST_INTERVAL_SECS {
+                peer.txs_requested = 0;
+                peer.txs_requested_since = now;
+            }
+            peer.txs_requested += request.txids.len();
+            if peer.txs_requested > MAX_TXS_REQUESTED_PER_INTERVAL {
+                return Err(BlockchainError::TooManyTxsRequested);
+            }
+        }
+
+        let response = MempoolTxs {
+            tip: self.delegate.tip_id(),
+            txs: request
+                .txids
+                .iter()
+                .filter_map(|txid| self.mempool.get(txid))
+                .map(|entry| entry.block_tx().clone())
+                .collect(),
+        };
+
+        self.send_compressible(pid, Message::MempoolTxs(response))
+            .await;
+        Ok(())
+    }
+
+    fn receive_finality(&mut self, finality: Finality) -> Result<(), BlockchainError> {
+        let Finality {
+            checkpoint,
+            signature,
+        } = finality;
+
+        // Ignore checkpoints that are not newer than ours.
+        if let Some((current, _)) = self.delegate.finalized_checkpoint() {
+            if checkpoint.height <= current.height {
+                return Ok(());
+            }
+        }
+
+        if !checkpoint.verify_signature(&signature, self.network_pubkey) {
+            return Err(BlockchainError::InvalidCheckpointSignature);
+        }
+
+        // If we already have a block at this height, it must be the finalized one.
+        if let Some(block) = self.delegate.block_at_height(checkpoint.height) {
+            if block.header.id() != checkpoint.block_id {
+                return Err(BlockchainError::ConflictingCheckpoint(checkpoint.height));
+            }
+        }
+
+        self.store_finality(checkpoint, signature);
+        Ok(())
+    }
+
+    /// Stores the checkpoint and schedules sending it to all the peers.
+    fn store_finality(&mut self, checkpoint: Checkpoint, signature: Signature) {
+        self.delegate
+            .store_finalized_checkpoint(checkpoint, signature);
+        for (_pid, peer) in self.peers.iter_mut() {
+            peer.needs_our_finality = true;
+        }
+        // Drop the candidate tips that conflict with the new checkpoint.
+        self.retarget();
+    }
+
+    /// Checks that the block header does not conflict with the finalized checkpoint.
+    fn check_finalized_checkpoint(&self, header: &BlockHeader) -> Result<(), BlockchainError> {
+        match self.delegate.finalized_checkpoint() {
+            Some((checkpoint, _)) if checkpoint.height == header.height => {
+                if header.id() != checkpoint.block_id {
+                    return Err(BlockchainError::ConflictingCheckpoint(header.height));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn rotate_shortid_nonce_if_needed(&mut self) {
         self.shortid_nonce_ttl -= 1;
         if self.shortid_nonce_ttl == 0 {
@@ -539,25 +1708,112 @@ impl<D: Delegate> BlockchainProtocol<D> {
         }
     }
 
-    fn mempool_inventory_for_peer(&self, pid: D::PeerIdentifier, nonce: u64) -> ShortIDVec {
-        let mut result = ShortIDVec::with_capacity(self.mempool.len());
+    /// Returns the list of short IDs of the mempool txs for a given peer,
+    /// and the full IDs of the txs whose short IDs collide.
+    fn mempool_inventory_for_peer(
+        mempool: &Mempool,
+        pid: D::PeerIdentifier,
+        nonce: u64,
+    ) -> (ShortIDVec, Vec<TxID>) {
+        let mut result = ShortIDVec::with_capacity(mempool.len());
+        let mut seen = HashMap::with_capacity(mempool.len());
+        let mut collisions = Vec::new();
         let shortener = shortid::Transform::new(nonce, &pid.as_ref());
-        for entry in self.mempool.entries() {
-            let shortid = shortener.apply(&entry.txid());
-            result.push(shortid);
+        for entry in mempool.entries() {
+            let txid = entry.txid();
+            let shortid = shortener.apply(&txid);
+            match seen.insert(shortid, txid) {
+                None => result.push(shortid),
+                Some(prev_txid) => {
+                    if !collisions.contains(&prev_txid) {
+                        collisions.push(prev_txid);
+                    }
+                    collisions.push(txid);
+                }
+            }
         }
-        result
+        (result, collisions)
+    }
+}
+
+impl Block {
+    /// Creates the block message from the stored block and its network signature.
+    pub fn from_verified(verified_block: VerifiedBlock, signature: Signature) -> Self {
+        Block {
+            header: verified_block.header,
+            signature,
+            txs: verified_block.raw_txs,
+            aux: verified_block.aux,
+        }
+    }
+
+    /// Returns the block header.
+    pub fn header(&self) -> &BlockHeader {
+        &self.header
+    }
+
+    /// Returns the network signature of the block.
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    /// Returns the transactions in the block.
+    pub fn txs(&self) -> &[BlockTx] {
+        &self.txs
+    }
+
+    /// Returns the auxiliary commitments of the block, ordered by kind.
+    pub fn aux(&self) -> &[AuxCommitment] {
+        &self.aux
+    }
+
+    /// Creates a Merkle path proving inclusion of the transaction
+    /// at a given index in the block's `txidroot`.
+    /// Returns `None` if the index is out of bounds, the transactions are malformed
+    /// or the header version precedes `BLOCK_VERSION_TXIDROOT`.
+    pub fn tx_inclusion_proof(&self, index: usize) -> Option<merkle::Path> {
+        if self.header.version < BLOCK_VERSION_TXIDROOT || index >= self.txs.len() {
+            return None;
+        }
+        let txids = self
+            .txs
+            .iter()
+            .map(|btx| btx.tx.precompute().map(|ptx| ptx.id))
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        merkle::Path::new(&txids, index, &txidroot_hasher())
+    }
+}
+
+impl Checkpoint {
+    /// Signs the checkpoint with the network key.
+    pub fn sign(&self, privkey: SigningKey) -> Signature {
+        let mut t = Transcript::new(b"ZkVM.checkpoint");
+        self.commit(&mut t);
+        Signature::sign(&mut t, privkey)
+    }
+
+    /// Verifies the network signature over the checkpoint.
+    pub fn verify_signature(&self, signature: &Signature, pubkey: VerificationKey) -> bool {
+        let mut t = Transcript::new(b"ZkVM.checkpoint");
+        self.commit(&mut t);
+        signature.verify(&mut t, pubkey).is_ok()
+    }
+
+    fn commit(&self, t: &mut Transcript) {
+        t.append_u64(b"height", self.height);
+        t.append_message(b"block_id", &self.block_id);
     }
 }
 
 /// Signs a block.
-fn create_block_signature(header: &BlockHeader, privkey: SigningKey) -> Signature {
+pub(crate) fn create_block_signature(header: &BlockHeader, privkey: SigningKey) -> Signature {
     let mut t = Transcript::new(b"ZkVM.stubnet1");
     t.append_message(b"block_id", &header.id());
     Signature::sign(&mut t, privkey)
 }
 
-fn verify_block_signature(
+pub(crate) fn verify_block_signature(
     header: &BlockHeader,
     signature: &Signature,
     pubkey: VerificationKey,
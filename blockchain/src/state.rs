@@ -1,10 +1,14 @@
 use serde::{Deserialize, Serialize};
 
-use super::block::{BlockHeader, BlockTx, VerifiedBlock};
+use super::block::{
+    compute_txidroot, BlockHeader, BlockTx, VerifiedBlock, BLOCK_VERSION_AUX,
+    BLOCK_VERSION_ORDERED, BLOCK_VERSION_TXIDROOT, BLOCK_VERSION_WEIGHT, MAX_BLOCK_WEIGHT,
+};
 use super::errors::BlockchainError;
+use super::ordering::is_canonical_order;
 use crate::utreexo::{self, utreexo_hasher, Forest};
 use zkvm::bulletproofs::BulletproofGens;
-use zkvm::{ContractID, MerkleTree, TxEntry, TxHeader};
+use zkvm::{ContractID, DeferredVerification, MerkleTree, Tx, TxEntry, TxHeader, VerifiedTx};
 
 /// State of the blockchain node.
 #[derive(Clone, Serialize, Deserialize)]
@@ -50,6 +54,30 @@ impl BlockchainState {
         block_header: BlockHeader,
         block_txs: &[BlockTx],
         bp_gens: &BulletproofGens,
+    ) -> Result<VerifiedBlock, BlockchainError> {
+        self.apply_block_with(block_header, block_txs, |tx| Ok(tx.verify(bp_gens)?))
+    }
+
+    /// Applies the block to the current state, deferring the signature checks
+    /// of its transactions to the `deferred` batch, and returns a new state.
+    /// The block is valid only if the batch verifies too.
+    pub fn apply_block_deferred(
+        &self,
+        block_header: BlockHeader,
+        block_txs: &[BlockTx],
+        bp_gens: &BulletproofGens,
+        deferred: &mut DeferredVerification,
+    ) -> Result<VerifiedBlock, BlockchainError> {
+        self.apply_block_with(block_header, block_txs, |tx| {
+            Ok(tx.verify_deferred(bp_gens, deferred)?)
+        })
+    }
+
+    fn apply_block_with(
+        &self,
+        block_header: BlockHeader,
+        block_txs: &[BlockTx],
+        mut verify_tx: impl FnMut(&Tx) -> Result<VerifiedTx, BlockchainError>,
     ) -> Result<VerifiedBlock, BlockchainError> {
         check_block_header(&block_header, &self.tip)?;
 
@@ -76,9 +104,15 @@ impl BlockchainState {
         let mut work_forest = self.utreexo.work_forest();
         let utxo_hasher = utreexo_hasher::<ContractID>();
         let mut verified_txs = Vec::with_capacity(block_txs.len());
+        let mut block_weight = 0u64;
         for block_tx in block_txs.iter() {
-            // TODO: this is a great place to do batch verification of signatures and bulletproofs.
-            let verified_tx = block_tx.tx.verify(bp_gens)?;
+            // TODO: this is a great place to do batch verification of bulletproofs.
+            let verified_tx = verify_tx(&block_tx.tx)?;
+
+            block_weight += verified_tx.weight();
+            if block_header.version >= BLOCK_VERSION_WEIGHT && block_weight > MAX_BLOCK_WEIGHT {
+                return Err(BlockchainError::BlockTooHeavy(block_weight));
+            }
 
             let mut utreexo_proofs = block_tx.proofs.iter();
 
@@ -105,6 +139,18 @@ impl BlockchainState {
             verified_txs.push(verified_tx);
         }
 
+        // Check the order of the txs.
+        if block_header.version >= BLOCK_VERSION_ORDERED && !is_canonical_order(&verified_txs) {
+            return Err(BlockchainError::NonCanonicalTxOrder);
+        }
+
+        // Check the commitment to all txids in a block.
+        if block_header.version >= BLOCK_VERSION_TXIDROOT
+            && block_header.txidroot != compute_txidroot(verified_txs.iter().map(|vtx| vtx.id))
+        {
+            return Err(BlockchainError::InconsistentHeader);
+        }
+
         let (new_forest, new_catchup) = work_forest.normalize(&utxo_hasher);
         let utxoroot = new_forest.root(&utxo_hasher);
 
@@ -119,6 +165,7 @@ impl BlockchainState {
             catchup: new_catchup,
             raw_txs: block_txs.iter().cloned().collect(),
             verified_txs: verified_txs,
+            aux: Vec::new(),
         })
     }
 }
@@ -146,7 +193,7 @@ pub fn check_tx_header(
 }
 
 /// Verifies block header with respect to the previous header.
-fn check_block_header(
+pub(crate) fn check_block_header(
     block_header: &BlockHeader,
     prev_header: &BlockHeader,
 ) -> Result<(), BlockchainError> {
@@ -160,6 +207,12 @@ fn check_block_header(
             BlockchainError::IllegalExtension,
         )?;
     }
+    if block_header.version >= BLOCK_VERSION_AUX {
+        check(
+            block_header.auxroot().is_some(),
+            BlockchainError::IllegalExtension,
+        )?;
+    }
     check(
         block_header.height == prev_header.height + 1,
         BlockchainError::InconsistentHeader,
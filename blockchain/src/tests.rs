@@ -5,8 +5,9 @@ use zkvm::bulletproofs::BulletproofGens;
 
 use super::*;
 use zkvm::{
-    Anchor, Commitment, Contract, ContractID, Multisignature, PortableItem, Predicate, Program,
-    Prover, Signature, String, TxHeader, Value, VerificationKey,
+    Anchor, Commitment, Contract, ContractID, DeferredVerification, Hash, MerkleTree,
+    Multisignature, PortableItem, Predicate, Program, Prover, Signature, String, TxEntry, TxHeader,
+    TxID, Value, VerificationKey,
 };
 
 fn make_predicate(privkey: impl Into<Scalar>) -> Predicate {
@@ -109,17 +110,32 @@ fn test_state_machine() {
     let block_tx = dummy_tx(utxo, &bp_gens).0;
 
     let mut mempool = Mempool::new(state.clone(), 42);
+    let events = mempool.subscribe();
 
-    mempool
+    let txid = mempool
         .append(block_tx.clone(), &bp_gens)
-        .expect("Tx must be valid");
+        .expect("Tx must be valid")
+        .txid();
+    let wire_hash = block_tx.tx.wire_hash();
+    assert_eq!(mempool.get(&txid).unwrap().wire_hash(), wire_hash);
+    assert_eq!(mempool.get_by_wire_hash(&wire_hash).unwrap().txid(), txid);
+    assert!(matches!(events.try_recv(), Ok(MempoolEvent::Accepted(id)) if id == txid));
+
+    // Appending the same tx again is a no-op.
+    mempool.append(block_tx.clone(), &bp_gens).unwrap();
+    assert_eq!(mempool.len(), 1);
+    assert!(events.try_recv().is_err());
 
     let verified_block = mempool.make_block();
+    assert_eq!(verified_block.tx_index(&txid), Some(0));
+    assert_eq!(verified_block.tx_index_by_wire_hash(&wire_hash), Some(0));
+    assert_eq!(verified_block.weight(), block_tx.tx.weight().unwrap());
+    assert_eq!(verified_block.weight(), mempool.size() as u64);
     let future_state = verified_block.blockchain_state();
 
     // Apply the block to the state
     let applied_block = state
-        .apply_block(future_state.tip, &[block_tx], &bp_gens)
+        .apply_block(future_state.tip, &[block_tx.clone()], &bp_gens)
         .expect("Block application should succeed.");
     let new_state = applied_block.blockchain_state();
 
@@ -128,6 +144,630 @@ fn test_state_machine() {
         new_state.utreexo.root(&hasher),
         future_state.utreexo.root(&hasher)
     );
+
+    // Confirmed tx is evicted from the mempool and cannot be added again.
+    mempool.update_state(new_state, &applied_block.catchup);
+    assert_eq!(mempool.len(), 0);
+    assert!(matches!(events.try_recv(), Ok(MempoolEvent::Evicted(id)) if id == txid));
+    assert!(mempool.append(block_tx, &bp_gens).is_err());
+    assert!(matches!(events.try_recv(), Ok(MempoolEvent::Rejected(id, _)) if id == txid));
+}
+
+#[test]
+fn test_deferred_block_verification() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let initial_contract = make_nonce_contract(1u64, 100);
+    let (state, proofs) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
+    let utxo = UTXO {
+        contract: initial_contract,
+        proof: proofs[0].clone(),
+        privkey: Scalar::from(1u64),
+    };
+    let block_tx = dummy_tx(utxo, &bp_gens).0;
+
+    let mut mempool = Mempool::new(state.clone(), 42);
+    mempool.append(block_tx.clone(), &bp_gens).unwrap();
+    let header = mempool.make_block().header;
+
+    // Valid block: the signatures are checked later in a batch.
+    let mut deferred = DeferredVerification::new();
+    state
+        .apply_block_deferred(header.clone(), &[block_tx.clone()], &bp_gens, &mut deferred)
+        .expect("Block application should succeed.");
+    assert!(deferred.verify().is_ok());
+
+    // Block with an invalid tx signature is applied, but fails the batch.
+    let mut bad_tx = block_tx;
+    bad_tx.tx.signature.s += Scalar::one();
+    let mut txroot = MerkleTree::build_root(b"ZkVM.txroot");
+    txroot.append(&bad_tx.witness_hash());
+    let bad_header = BlockHeader {
+        txroot: txroot.root(),
+        ..header
+    };
+    let mut deferred = DeferredVerification::new();
+    state
+        .apply_block_deferred(
+            bad_header.clone(),
+            &[bad_tx.clone()],
+            &bp_gens,
+            &mut deferred,
+        )
+        .expect("Signature check is deferred.");
+    assert!(deferred.verify().is_err());
+
+    // Per-block verification locates the offending block.
+    assert!(state.apply_block(bad_header, &[bad_tx], &bp_gens).is_err());
+}
+
+#[test]
+fn test_mempool_min_feerate() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let contracts = vec![
+        make_nonce_contract(1u64, 100),
+        make_nonce_contract(1u64, 200),
+    ];
+    let (state, proofs) =
+        BlockchainState::make_initial(0u64, contracts.iter().map(|c| c.id()).collect());
+    let mut txs = contracts
+        .into_iter()
+        .zip(proofs.into_iter())
+        .map(|(contract, proof)| {
+            let utxo = UTXO {
+                contract,
+                proof,
+                privkey: Scalar::from(1u64),
+            };
+            dummy_tx(utxo, &bp_gens).0
+        });
+    let tx1 = txs.next().unwrap();
+    let tx2 = txs.next().unwrap();
+    let tx_size = tx1.tx.weight().unwrap() as usize;
+
+    // Transactions without fees are rejected by the minimum relay feerate.
+    let mut mempool = Mempool::new(state.clone(), 42).set_min_relay_feerate(1);
+    assert_eq!(mempool.min_feerate(), 1);
+    assert!(matches!(
+        mempool.append(tx1.clone(), &bp_gens),
+        Err(BlockchainError::FeerateTooLow(1))
+    ));
+    assert_eq!(mempool.len(), 0);
+
+    // Full mempool evicts the most recent of the transactions with the lowest feerate,
+    // and raises the minimum feerate above it.
+    let mut mempool = Mempool::new(state.clone(), 42).set_max_size(tx_size);
+    let events = mempool.subscribe();
+    assert_eq!(mempool.min_feerate(), 0);
+    let txid1 = mempool.append(tx1, &bp_gens).unwrap().txid();
+    assert!(matches!(
+        mempool.append(tx2.clone(), &bp_gens),
+        Err(BlockchainError::FeerateTooLow(1))
+    ));
+    assert_eq!(mempool.len(), 1);
+    assert_eq!(mempool.size(), tx_size);
+    assert!(mempool.get(&txid1).is_some());
+    assert!(matches!(events.try_recv(), Ok(MempoolEvent::Accepted(id)) if id == txid1));
+    assert!(matches!(events.try_recv(), Ok(MempoolEvent::Accepted(_))));
+    assert!(matches!(events.try_recv(), Ok(MempoolEvent::Evicted(_))));
+    assert_eq!(mempool.min_feerate(), 1);
+
+    // Raised feerate is rejected before verification.
+    assert!(matches!(
+        mempool.append(tx2, &bp_gens),
+        Err(BlockchainError::FeerateTooLow(1))
+    ));
+
+    // Raised feerate halves over time.
+    mempool.update_timestamp(42 + MIN_FEERATE_HALFLIFE_MS);
+    assert_eq!(mempool.min_feerate(), 0);
+}
+
+#[test]
+fn test_mempool_double_spend() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let contract = make_nonce_contract(1u64, 100);
+    let (state, proofs) = BlockchainState::make_initial(0u64, vec![contract.id()]);
+    let utxo = UTXO {
+        contract: contract.clone(),
+        proof: proofs[0].clone(),
+        privkey: Scalar::from(1u64),
+    };
+    let tx1 = dummy_tx(utxo, &bp_gens).0;
+
+    // Second transaction spends the same contract to another predicate.
+    let tx2 = {
+        let program = Program::build(|p| {
+            p.push(contract.clone())
+                .input()
+                .signtx()
+                .push(make_predicate(2u64))
+                .output(1);
+        });
+        let header = TxHeader {
+            version: 1u64,
+            mintime_ms: 0u64,
+            maxtime_ms: u64::max_value(),
+        };
+        let utx = Prover::build_tx(program, header, &bp_gens).unwrap();
+        let mut signtx_transcript = Transcript::new(b"ZkVM.signtx");
+        signtx_transcript.append_message(b"txid", &utx.txid.0);
+        let sig = Signature::sign_multi(
+            &[Scalar::from(1u64)],
+            utx.signing_instructions
+                .iter()
+                .map(|(p, m)| (p.verification_key(), m))
+                .collect(),
+            &mut signtx_transcript,
+        )
+        .unwrap();
+        BlockTx {
+            tx: utx.sign(sig),
+            proofs: vec![proofs[0].clone()],
+        }
+    };
+
+    let mut mempool = Mempool::new(state, 42);
+    let events = mempool.subscribe();
+    let txid1 = mempool.append(tx1, &bp_gens).unwrap().txid();
+    assert_eq!(mempool.spender(&contract.id()).unwrap().txid(), txid1);
+    assert!(matches!(events.try_recv(), Ok(MempoolEvent::Accepted(_))));
+
+    match mempool.append(tx2, &bp_gens) {
+        Err(BlockchainError::DoubleSpend(cid, id)) if cid == contract.id() && id == txid1 => {}
+        _ => panic!("Double-spending transaction must be rejected"),
+    }
+    assert_eq!(mempool.len(), 1);
+    let txid2 = match events.try_recv() {
+        Ok(MempoolEvent::Rejected(id, BlockchainError::DoubleSpend(..))) => id,
+        _ => panic!("Rejection must be reported"),
+    };
+    assert!(matches!(
+        events.try_recv(),
+        Ok(MempoolEvent::DoubleSpendDetected { txid, conflicting_txid, contract_id })
+            if txid == txid2 && conflicting_txid == txid1 && contract_id == contract.id()
+    ));
+    assert_eq!(
+        BlockchainError::DoubleSpend(contract.id(), txid1).failure_class(),
+        FailureClass::Policy
+    );
+}
+
+#[test]
+fn test_canonical_tx_order() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let contracts = vec![
+        make_nonce_contract(1u64, 100),
+        make_nonce_contract(1u64, 200),
+    ];
+    let (mut state, proofs) =
+        BlockchainState::make_initial(0u64, contracts.iter().map(|c| c.id()).collect());
+    state.tip.version = BLOCK_VERSION_AUX;
+    state.tip.ext = vec![0; 32];
+    let mut utxos = contracts
+        .into_iter()
+        .zip(proofs.into_iter())
+        .map(|(contract, proof)| UTXO {
+            contract,
+            proof,
+            privkey: Scalar::from(1u64),
+        });
+    let (tx_a, utxo_a) = dummy_tx(utxos.next().unwrap(), &bp_gens);
+    let (tx_b, _) = dummy_tx(utxo_a, &bp_gens);
+    let (tx_c, _) = dummy_tx(utxos.next().unwrap(), &bp_gens);
+    let txid = |block_tx: &BlockTx| block_tx.tx.precompute().unwrap().id;
+
+    // Mempool builds the blocks of the ordered version in the canonical order.
+    let mut ordered_state = state.clone();
+    ordered_state.tip.version = BLOCK_VERSION_ORDERED;
+    let mut mempool = Mempool::new(ordered_state.clone(), 42);
+    for tx in vec![tx_a.clone(), tx_b.clone(), tx_c.clone()] {
+        mempool.append(tx, &bp_gens).unwrap();
+    }
+    let block = mempool.make_block();
+    assert!(is_canonical_order(&block.verified_txs));
+    assert_eq!(block.verified_txs.len(), 3);
+    let index_a = block.tx_index(&txid(&tx_a)).unwrap();
+    let index_b = block.tx_index(&txid(&tx_b)).unwrap();
+    assert!(index_a < index_b);
+    let graph = mempool.graph();
+    assert_eq!(graph.nodes.len(), 3);
+    assert_eq!(graph.nodes[1].txid, txid(&tx_b));
+    assert_eq!(graph.edges.len(), 1);
+    assert_eq!(graph.edges[0].from, txid(&tx_a));
+    assert_eq!(graph.edges[0].to, txid(&tx_b));
+    ordered_state
+        .apply_block(block.header.clone(), &block.raw_txs, &bp_gens)
+        .expect("Block in the canonical order should be valid.");
+
+    // Independent txs with equal feerates are ordered by TxID.
+    let (first, second) = if (txid(&tx_a).0).0 < (txid(&tx_c).0).0 {
+        (tx_c, tx_a)
+    } else {
+        (tx_a, tx_c)
+    };
+    let mut mempool = Mempool::new(state.clone(), 42);
+    mempool.append(first, &bp_gens).unwrap();
+    mempool.append(second, &bp_gens).unwrap();
+    let block = mempool.make_block();
+    assert!(!is_canonical_order(&block.verified_txs));
+    state
+        .apply_block(block.header.clone(), &block.raw_txs, &bp_gens)
+        .expect("Earlier versions do not require the canonical order.");
+    let mut header = block.header;
+    header.version = BLOCK_VERSION_ORDERED;
+    assert!(matches!(
+        state.apply_block(header, &block.raw_txs, &bp_gens),
+        Err(BlockchainError::NonCanonicalTxOrder)
+    ));
+}
+
+#[test]
+fn test_supply_audit() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let initial_contract = make_nonce_contract(1u64, 100);
+    let (state, proofs) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
+    let utxo = UTXO {
+        contract: initial_contract,
+        proof: proofs[0].clone(),
+        privkey: Scalar::from(1u64),
+    };
+    let block_tx = dummy_tx(utxo, &bp_gens).0;
+
+    let mut mempool = Mempool::new(state.clone(), 42);
+    mempool.append(block_tx.clone(), &bp_gens).unwrap();
+    let header = mempool.make_block().header;
+    let block = state
+        .apply_block(header, &[block_tx], &bp_gens)
+        .expect("Block application should succeed.");
+    let new_state = block.blockchain_state();
+
+    let audit = new_state
+        .audit(&state, &[block.clone()])
+        .expect("Audit should succeed.");
+    assert_eq!(audit.from_height, 2);
+    assert_eq!(audit.tip_height, 2);
+    assert_eq!(audit.utxo_count, 1);
+    assert_eq!(audit.fees, 0);
+    assert!(audit.flavors.is_empty());
+
+    // Issued, retired and burned quantities are summed per flavor.
+    let flv = Commitment::unblinded(nonce_flavor()).to_point();
+    let mut block_with_supply = block.clone();
+    let log = &mut block_with_supply.verified_txs[0].log;
+    log.push(TxEntry::Issue(Commitment::unblinded(30u64).to_point(), flv));
+    log.push(TxEntry::Issue(Commitment::unblinded(20u64).to_point(), flv));
+    log.push(TxEntry::Retire(
+        Commitment::unblinded(15u64).to_point(),
+        flv,
+    ));
+    log.push(TxEntry::Burn(5, nonce_flavor()));
+    log.push(TxEntry::Fee(7));
+    let audit = new_state.audit(&state, &[block_with_supply]).unwrap();
+    assert_eq!(audit.fees, 7);
+    assert_eq!(audit.flavors.len(), 1);
+    assert_eq!(audit.flavors[0].flavor, flv);
+    assert_eq!(audit.flavors[0].issuances, 2);
+    assert_eq!(audit.flavors[0].retirements, 2);
+    assert_eq!(audit.flavors[0].burned, 5);
+    assert!(audit.flavors[0].verify_net_qty(30, Scalar::zero()));
+    assert!(!audit.flavors[0].verify_net_qty(35, Scalar::zero()));
+
+    // Extra output in the log does not match the utreexo state.
+    let mut corrupted_block = block.clone();
+    let output = corrupted_block.verified_txs[0]
+        .log
+        .outputs()
+        .next()
+        .unwrap()
+        .clone();
+    corrupted_block.verified_txs[0]
+        .log
+        .push(TxEntry::Output(output));
+    assert!(matches!(
+        new_state.audit(&state, &[corrupted_block]),
+        Err(BlockchainError::AuditMismatch(2))
+    ));
+
+    // Blocks must lead to the audited tip.
+    assert!(matches!(
+        new_state.audit(&state, Vec::<VerifiedBlock>::new()),
+        Err(BlockchainError::InconsistentHeader)
+    ));
+}
+
+#[test]
+fn test_asset_index() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let initial_contract = make_nonce_contract(1u64, 100);
+    let (state, proofs) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
+    let utxo = UTXO {
+        contract: initial_contract,
+        proof: proofs[0].clone(),
+        privkey: Scalar::from(1u64),
+    };
+    let block_tx = dummy_tx(utxo, &bp_gens).0;
+
+    let mut mempool = Mempool::new(state.clone(), 42);
+    mempool.append(block_tx.clone(), &bp_gens).unwrap();
+    let header = mempool.make_block().header;
+    let mut block = state
+        .apply_block(header, &[block_tx], &bp_gens)
+        .expect("Block application should succeed.");
+
+    // Outputs of flavors that were never issued are not indexed.
+    let mut index = AssetIndex::new().set_max_transfers(1);
+    index.index_block(&block).unwrap();
+    assert_eq!(index.assets().count(), 0);
+
+    let flv = Commitment::unblinded(nonce_flavor()).to_point();
+    let txid = block.verified_txs[0].id;
+    let output = block.verified_txs[0].log.outputs().next().unwrap().clone();
+    let log = &mut block.verified_txs[0].log;
+    log.push(TxEntry::Issue(Commitment::unblinded(30u64).to_point(), flv));
+    log.push(TxEntry::Retire(
+        Commitment::unblinded(15u64).to_point(),
+        flv,
+    ));
+    log.push(TxEntry::Burn(5, nonce_flavor()));
+    log.push(TxEntry::Output(output.clone()));
+    log.push(TxEntry::Output(output.clone()));
+    index.index_block(&block).unwrap();
+
+    let history = index.history(&flv).expect("Flavor should be indexed.");
+    assert_eq!(history.events.len(), 3);
+    assert!(history
+        .events
+        .iter()
+        .all(|e| e.height == 2 && e.txid == txid));
+    assert!(matches!(history.events[2].kind, AssetEventKind::Burn(5)));
+    assert_eq!(history.transfers.len(), 1);
+    assert_eq!(history.transfers[0].contract_id, output.id());
+    assert_eq!(
+        history.transfers[0].qty,
+        Commitment::unblinded(100u64).to_point()
+    );
+
+    let supply = history.supply();
+    assert_eq!(supply.issuances, 1);
+    assert_eq!(supply.retirements, 2);
+    assert_eq!(supply.burned, 5);
+    assert!(supply.verify_net_qty(10, Scalar::zero()));
+}
+
+#[test]
+fn test_txidroot_is_versioned() {
+    use zkvm::encoding::ExactSizeEncodable;
+
+    let (state, _) = BlockchainState::make_initial(0u64, Vec::new());
+    let mut header = state.tip.clone();
+    assert_eq!(header.txidroot, Hash::default());
+
+    // Earlier versions neither encode nor commit to the txidroot.
+    let legacy_id = header.id();
+    let legacy_size = header.encoded_size();
+    header.txidroot = Hash([1; 32]);
+    assert_eq!(header.id(), legacy_id);
+    assert_eq!(header.encoded_size(), legacy_size);
+
+    header.version = BLOCK_VERSION_TXIDROOT;
+    let id = header.id();
+    assert_eq!(header.encoded_size(), legacy_size + 32);
+    header.txidroot = Hash([2; 32]);
+    assert_ne!(header.id(), id);
+}
+
+#[test]
+fn test_header_snapshot_compatibility() {
+    use serde::Serialize;
+
+    // Layout of the state stored by the node before the header got the txidroot.
+    #[derive(Serialize)]
+    struct LegacyHeader {
+        version: u64,
+        height: u64,
+        prev: BlockID,
+        timestamp_ms: u64,
+        txroot: Hash,
+        utxoroot: Hash,
+        ext: Vec<u8>,
+    }
+    #[derive(Serialize)]
+    struct LegacyState<'a> {
+        tip: LegacyHeader,
+        utreexo: &'a utreexo::Forest,
+    }
+
+    let (state, _) = BlockchainState::make_initial(0u64, vec![make_nonce_contract(1u64, 100).id()]);
+    let tip = &state.tip;
+    let snapshot = bincode::serialize(&LegacyState {
+        tip: LegacyHeader {
+            version: tip.version,
+            height: tip.height,
+            prev: tip.prev,
+            timestamp_ms: tip.timestamp_ms,
+            txroot: tip.txroot,
+            utxoroot: tip.utxoroot,
+            ext: tip.ext.clone(),
+        },
+        utreexo: &state.utreexo,
+    })
+    .unwrap();
+
+    let restored: BlockchainState = bincode::deserialize(&snapshot).unwrap();
+    assert_eq!(&restored.tip, tip);
+    assert_eq!(bincode::serialize(&restored).unwrap(), snapshot);
+
+    // Headers since `BLOCK_VERSION_TXIDROOT` keep the txidroot.
+    let mut header = tip.clone();
+    header.version = BLOCK_VERSION_TXIDROOT;
+    header.txidroot = Hash([1; 32]);
+    let bytes = bincode::serialize(&header).unwrap();
+    assert_eq!(bytes.len(), bincode::serialize(tip).unwrap().len() + 32);
+    assert_eq!(bincode::deserialize::<BlockHeader>(&bytes).unwrap(), header);
+
+    // Human-readable formats always carry the txidroot.
+    let json = serde_json::to_value(tip).unwrap();
+    assert!(json.get("txidroot").is_some());
+}
+
+#[test]
+fn test_light_client() {
+    use super::protocol::create_block_signature;
+
+    let bp_gens = BulletproofGens::new(256, 1);
+    let network_signing_key = Scalar::from(9000u64);
+    let network_pubkey = VerificationKey::from_secret(&network_signing_key);
+
+    let initial_contract = make_nonce_contract(1u64, 100);
+    let (mut state, proofs) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
+    state.tip.version = BLOCK_VERSION_TXIDROOT;
+    state.tip.ext = vec![0; 32];
+    let initial_sig = create_block_signature(&state.tip, network_signing_key);
+
+    assert!(LightClient::new(
+        VerificationKey::from_secret(&Scalar::from(1u64)),
+        state.tip.clone(),
+        &initial_sig
+    )
+    .is_err());
+
+    let mut client = LightClient::new(network_pubkey, state.tip.clone(), &initial_sig).unwrap();
+    client.update_utreexo(state.utreexo.clone()).unwrap();
+    client
+        .verify_utxo(&initial_contract.id(), &proofs[0])
+        .unwrap();
+
+    // Full node makes a block spending the initial utxo.
+    let utxo = UTXO {
+        contract: initial_contract.clone(),
+        proof: proofs[0].clone(),
+        privkey: Scalar::from(1u64),
+    };
+    let (block_tx, _) = dummy_tx(utxo, &bp_gens);
+    let mut mempool = Mempool::new(state.clone(), 42);
+    mempool.append(block_tx, &bp_gens).unwrap();
+    let block = mempool.make_block();
+    let block_sig = create_block_signature(&block.header, network_signing_key);
+
+    // Light client follows the headers and the utreexo roots.
+    client
+        .apply_header(block.header.clone(), &block_sig)
+        .unwrap();
+    assert_eq!(client.tip().height, 2);
+    assert!(client
+        .apply_header(block.header.clone(), &block_sig)
+        .is_err());
+    assert!(client
+        .verify_utxo(&initial_contract.id(), &proofs[0])
+        .is_err());
+    client.update_utreexo(block.utreexo.clone()).unwrap();
+    assert!(client.update_utreexo(state.utreexo.clone()).is_err());
+
+    // Light client verifies the inclusion of the tx and scans it.
+    let path = tx_inclusion_path(&block.raw_txs, 0).unwrap();
+    let verified_tx = client
+        .verify_tx_inclusion(2, &block.raw_txs[0], &path)
+        .unwrap();
+    assert!(client
+        .verify_tx_inclusion(1, &block.raw_txs[0], &path)
+        .is_err());
+
+    let txid_path = block.tx_inclusion_proof(0).unwrap();
+    client
+        .verify_txid_inclusion(2, &verified_tx.id, &txid_path)
+        .unwrap();
+    assert!(client
+        .verify_txid_inclusion(2, &TxID(Hash([0; 32])), &txid_path)
+        .is_err());
+    assert!(block.tx_inclusion_proof(1).is_none());
+
+    let block_msg = Block {
+        header: block.header.clone(),
+        signature: block_sig,
+        txs: block.raw_txs.clone(),
+        aux: Vec::new(),
+    };
+    assert_eq!(block_msg.tx_inclusion_proof(0), Some(txid_path));
+
+    let scan = client.scan_tx(&verified_tx, |_| true);
+    assert_eq!(scan.spent, vec![initial_contract.id()]);
+    assert_eq!(scan.received.len(), 1);
+    assert!(client.scan_tx(&verified_tx, |_| false).received.is_empty());
+}
+
+#[test]
+fn test_aux_commitments() {
+    use super::protocol::create_block_signature;
+
+    let bp_gens = BulletproofGens::new(256, 1);
+    let network_signing_key = Scalar::from(9000u64);
+    let network_pubkey = VerificationKey::from_secret(&network_signing_key);
+
+    let initial_contract = make_nonce_contract(1u64, 100);
+    let (mut state, proofs) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
+    state.tip.version = BLOCK_VERSION_AUX;
+    state.tip.ext = vec![0; 32];
+    let initial_sig = create_block_signature(&state.tip, network_signing_key);
+    let mut client = LightClient::new(network_pubkey, state.tip.clone(), &initial_sig).unwrap();
+
+    let utxo = UTXO {
+        contract: initial_contract.clone(),
+        proof: proofs[0].clone(),
+        privkey: Scalar::from(1u64),
+    };
+    let (block_tx, _) = dummy_tx(utxo, &bp_gens);
+    let mut mempool = Mempool::new(state.clone(), 42);
+    mempool.append(block_tx, &bp_gens).unwrap();
+    let block = mempool.make_block();
+    assert_eq!(block.aux, block.known_aux_commitments());
+    assert!(block.header.auxroot().is_some());
+
+    // Node checks the commitments received with the block.
+    let mut verified_block = state
+        .apply_block(block.header.clone(), &block.raw_txs, &bp_gens)
+        .unwrap();
+    let mut tampered = block.aux.clone();
+    tampered[0].hash = Hash([0; 32]);
+    assert!(verified_block.set_aux_commitments(tampered).is_err());
+    assert!(verified_block.set_aux_commitments(Vec::new()).is_err());
+    verified_block
+        .set_aux_commitments(block.aux.clone())
+        .unwrap();
+
+    // Commitments of the unknown kinds are ignored.
+    let mut extended = block.aux.clone();
+    extended.push(AuxCommitment {
+        kind: 1000,
+        hash: Hash([1; 32]),
+    });
+    let mut header = block.header.clone();
+    header.ext = MerkleTree::root(b"ZkVM.auxroot", extended.iter())
+        .0
+        .to_vec();
+    let mut verified_block = state
+        .apply_block(header.clone(), &block.raw_txs, &bp_gens)
+        .unwrap();
+    verified_block.set_aux_commitments(extended).unwrap();
+    assert!(verified_block
+        .set_aux_commitments(block.aux.clone())
+        .is_err());
+
+    // Header must commit to the auxiliary structures.
+    header.ext = Vec::new();
+    assert!(state.apply_block(header, &block.raw_txs, &bp_gens).is_err());
+
+    // Light client verifies the filter and matches the wallet's contracts.
+    let block_sig = create_block_signature(&block.header, network_signing_key);
+    client
+        .apply_header(block.header.clone(), &block_sig)
+        .unwrap();
+    let filter = block.filter();
+    let path = block.aux_commitment_proof(AUX_BLOCK_FILTER).unwrap();
+    client.verify_block_filter(2, &filter, &path).unwrap();
+    assert!(client
+        .verify_block_filter(2, &BlockFilter::default(), &path)
+        .is_err());
+    assert!(filter.matches(&block.header.prev, initial_contract.id()));
 }
 
 #[test]
@@ -158,8 +798,11 @@ fn test_p2p_protocol() {
     struct MockNode {
         id: PID,
         state: BlockchainState,
-        blocks: Vec<Block>,                   // i=0 -> height=1, etc
+        blocks: Vec<Block>, // i=0 -> height=1, etc
+        pruned_height: u64,
+        checkpoint: Option<(Checkpoint, Signature)>,
         mailbox: Sender<(PID, PID, Message)>, // from, to, msg
+        banned: Vec<PID>,
     }
 
     #[derive(Debug)]
@@ -204,6 +847,10 @@ fn test_p2p_protocol() {
             self.mailbox.send((self.id, pid_to, message)).unwrap();
         }
 
+        async fn ban_peer(&mut self, pid: Self::PeerIdentifier) {
+            self.banned.push(pid);
+        }
+
         /// Returns the signed tip of the blockchain
         fn tip(&self) -> (BlockHeader, Signature) {
             let last_block = self.blocks.last().unwrap();
@@ -212,12 +859,16 @@ fn test_p2p_protocol() {
 
         /// Returns a block at a given height
         fn block_at_height(&self, height: u64) -> Option<Block> {
-            if height < 1 {
+            if height < 1 || height <= self.pruned_height {
                 return None;
             }
             self.blocks.get((height - 1) as usize).map(|b| b.clone())
         }
 
+        fn pruned_height(&self) -> u64 {
+            self.pruned_height
+        }
+
         /// Blockchain state
         fn blockchain_state(&self) -> &BlockchainState {
             &self.state
@@ -232,8 +883,17 @@ fn test_p2p_protocol() {
                 header: verified_block.header,
                 signature,
                 txs: verified_block.raw_txs,
+                aux: verified_block.aux,
             });
         }
+
+        fn finalized_checkpoint(&self) -> Option<(Checkpoint, Signature)> {
+            self.checkpoint
+        }
+
+        fn store_finalized_checkpoint(&mut self, checkpoint: Checkpoint, signature: Signature) {
+            self.checkpoint = Some((checkpoint, signature));
+        }
     }
 
     let bp_gens = BulletproofGens::new(256, 1);
@@ -265,8 +925,12 @@ fn test_p2p_protocol() {
                 header: state.tip.clone(),
                 signature: block_sig.clone(),
                 txs: Vec::new(),
+                aux: Vec::new(),
             }],
+            pruned_height: 0,
+            checkpoint: None,
             mailbox: mailbox_tx.clone(),
+            banned: Vec::new(),
         })
         .map(|mock| BlockchainProtocol::new(network_pubkey, mock));
 
@@ -325,7 +989,17 @@ fn test_p2p_protocol() {
 
     mailbox.process_must_succeed(&mut [&mut node0, &mut node1, &mut node2]);
 
-    node0.create_block(1u64, network_signing_key);
+    // A block signed by a wrong key is not stored.
+    let tip_height = node0.delegate().tip().0.height;
+    assert!(matches!(
+        node0.create_block(1u64, Scalar::from(1u64)),
+        Err(BlockchainError::InvalidBlockSignature)
+    ));
+    assert_eq!(node0.delegate().tip().0.height, tip_height);
+
+    node0
+        .create_block(1u64, network_signing_key)
+        .expect("should create a block");
 
     dbg!("creating a block 2");
 
@@ -346,4 +1020,413 @@ fn test_p2p_protocol() {
     block_on(node2.synchronize());
 
     mailbox.process_must_succeed(&mut [&mut node0, &mut node1, &mut node2]);
+
+    // Finalize block 2 and propagate the checkpoint.
+    node0.finalize(network_signing_key);
+
+    block_on(node0.synchronize());
+
+    mailbox.process_must_succeed(&mut [&mut node0, &mut node1, &mut node2]);
+
+    let (checkpoint, _) = node0.finalized_checkpoint().unwrap();
+    assert_eq!(checkpoint.height, 2);
+    assert_eq!(node1.finalized_checkpoint().unwrap().0, checkpoint);
+    assert_eq!(node2.finalized_checkpoint().unwrap().0, checkpoint);
+
+    // Legacy peers receive the checkpoint only once they advertise the finality feature.
+    let legacy_peer = PID(7);
+    let finality_sent_to_legacy_peer = || {
+        let mut sent = false;
+        while let Ok((_, pid_to, msg)) = mailbox.rx.try_recv() {
+            sent |= pid_to == legacy_peer && matches!(msg, Message::Finality(_));
+        }
+        sent
+    };
+    block_on(node0.peer_connected(legacy_peer));
+    block_on(node0.synchronize());
+    assert!(!finality_sent_to_legacy_peer());
+    block_on(node0.process_message(
+        legacy_peer,
+        Message::GetInventory(GetInventory {
+            version: 0,
+            shortid_nonce: 0,
+            features: FEATURE_FINALITY,
+        }),
+    ))
+    .unwrap();
+    block_on(node0.synchronize());
+    assert!(finality_sent_to_legacy_peer());
+
+    // Checkpoint signed by a wrong key is rejected.
+    let bogus = Checkpoint {
+        height: 3,
+        block_id: BlockID([0; 32]),
+    };
+    let result = block_on(node1.process_message(
+        node0.id(),
+        Message::Finality(Finality {
+            checkpoint: bogus,
+            signature: bogus.sign(Scalar::from(1u64)),
+        }),
+    ));
+    match result {
+        Err(BlockchainError::InvalidCheckpointSignature) => {}
+        _ => panic!("Checkpoint signed by a wrong key must be rejected"),
+    }
+    // Such checkpoint violates the consensus rules, so the peer that sent it is banned.
+    assert_eq!(node1.delegate().banned, vec![node0.id()]);
+
+    // Checkpoint that conflicts with an existing block is rejected.
+    node0
+        .create_block(2u64, network_signing_key)
+        .expect("should create a block");
+    let result = block_on(node0.process_message(
+        node1.id(),
+        Message::Finality(Finality {
+            checkpoint: bogus,
+            signature: bogus.sign(network_signing_key),
+        }),
+    ));
+    match result {
+        Err(BlockchainError::ConflictingCheckpoint(3)) => {}
+        _ => panic!("Checkpoint conflicting with the chain must be rejected"),
+    }
+    assert_eq!(node0.delegate().banned, vec![node1.id()]);
+    assert_eq!(node0.finalized_checkpoint().unwrap().0, checkpoint);
+
+    // External block producer signs the template prepared by the node.
+    let template = node0.block_template(3u64);
+    assert_eq!(template.state.tip, template.header);
+    let result =
+        node0.submit_signed_block(create_block_signature(&template.header, Scalar::from(1u64)));
+    match result {
+        Err(BlockchainError::InvalidBlockSignature) => {}
+        _ => panic!("Block signed by a wrong key must be rejected"),
+    }
+    node0
+        .submit_signed_block(create_block_signature(
+            &template.header,
+            network_signing_key,
+        ))
+        .unwrap();
+    let result = node0.submit_signed_block(create_block_signature(
+        &template.header,
+        network_signing_key,
+    ));
+    match result {
+        Err(BlockchainError::StaleBlockTemplate) => {}
+        _ => panic!("Template must be submitted only once"),
+    }
+    assert_eq!(node0.block_template(4u64).header.prev, template.header.id());
+
+    // Consensus engine that is not the leader does not produce blocks.
+    struct Follower;
+    impl ConsensusDriver for Follower {
+        fn propose(&mut self, _template: &BlockTemplate) -> bool {
+            false
+        }
+        fn prevote(&mut self, _header: &BlockHeader) -> bool {
+            false
+        }
+        fn commit(&mut self, _header: &BlockHeader) -> Option<Signature> {
+            None
+        }
+        fn finalize(&mut self, _checkpoint: &Checkpoint) -> Option<Signature> {
+            None
+        }
+    }
+    assert!(!node0.produce_block(4u64, &mut Follower).unwrap());
+    assert!(!node0.finalize_with(&mut Follower));
+    let next = node0.block_template(4u64).header;
+    assert_eq!(next.prev, template.header.id());
+
+    // Single signer commits the block and refuses to sign another one at the same height.
+    let mut signer = SingleSigner::new(network_signing_key);
+    assert!(node0.produce_block(4u64, &mut signer).unwrap());
+    assert!(!signer.prevote(&next));
+    assert!(signer.commit(&next).is_none());
+    assert!(node0.finalize_with(&mut signer));
+    assert_eq!(node0.finalized_checkpoint().unwrap().0.height, next.height);
+    assert_eq!(node0.block_template(5u64).header.prev, next.id());
+
+    // Txs requested by ID are rate-limited per peer.
+    let txids = vec![TxID(Hash([0; 32])); 1000];
+    block_on(node0.process_message(node1.id(), Message::GetTxs(GetTxs { txids }))).unwrap();
+    let result = block_on(node0.process_message(
+        node1.id(),
+        Message::GetTxs(GetTxs {
+            txids: vec![TxID(Hash([0; 32]))],
+        }),
+    ));
+    match result {
+        Err(BlockchainError::TooManyTxsRequested) => {}
+        _ => panic!("Requests above the rate limit must be rejected"),
+    }
+    // Exceeding the rate limit is a policy violation: the peer is not banned again.
+    assert_eq!(node0.delegate().banned, vec![node1.id()]);
+
+    // Pruned node responds with `BlockUnavailable` instead of the discarded block.
+    let mut pruned_node = BlockchainProtocol::new(
+        network_pubkey,
+        MockNode {
+            id: PID(3),
+            state: state.clone(),
+            blocks: vec![Block {
+                header: state.tip.clone(),
+                signature: block_sig.clone(),
+                txs: Vec::new(),
+                aux: Vec::new(),
+            }],
+            pruned_height: 1,
+            checkpoint: None,
+            mailbox: mailbox_tx.clone(),
+            banned: Vec::new(),
+        },
+    );
+    // Legacy peers get no response, as before the pruning.
+    let result = block_on(
+        pruned_node.process_message(node0.id(), Message::GetBlock(GetBlock { height: 1 })),
+    );
+    match result {
+        Err(BlockchainError::BlockNotFound(1)) => {}
+        _ => panic!("Pruned block must not be reported to the legacy peers"),
+    }
+    assert!(mailbox.rx.try_recv().is_err());
+    block_on(pruned_node.peer_connected(node0.id()));
+    while mailbox.rx.try_recv().is_ok() {}
+    block_on(pruned_node.process_message(
+        node0.id(),
+        Message::GetInventory(GetInventory {
+            version: 0,
+            shortid_nonce: 0,
+            features: FEATURE_BLOCK_UNAVAILABLE,
+        }),
+    ))
+    .unwrap();
+    block_on(pruned_node.process_message(node0.id(), Message::GetBlock(GetBlock { height: 1 })))
+        .unwrap();
+    match mailbox.rx.try_recv() {
+        Ok((PID(3), PID(0), Message::BlockUnavailable(msg))) => {
+            assert_eq!(msg.height, 1);
+            assert_eq!(msg.pruned_height, 1);
+        }
+        other => panic!("Pruned block must be reported as unavailable: {:?}", other),
+    }
+    let result = block_on(
+        pruned_node.process_message(node0.id(), Message::GetBlock(GetBlock { height: 2 })),
+    );
+    match result {
+        Err(BlockchainError::BlockNotFound(2)) => {}
+        _ => panic!("Blocks above the tip must not be reported as pruned"),
+    }
+
+    // Node follows the tip announced by a peer until the peer goes away.
+    let make_fresh_node = |id: u8| {
+        BlockchainProtocol::new(
+            network_pubkey,
+            MockNode {
+                id: PID(id),
+                state: state.clone(),
+                blocks: vec![Block {
+                    header: state.tip.clone(),
+                    signature: block_sig.clone(),
+                    txs: Vec::new(),
+                    aux: Vec::new(),
+                }],
+                pruned_height: 0,
+                checkpoint: None,
+                mailbox: mailbox_tx.clone(),
+                banned: Vec::new(),
+            },
+        )
+    };
+    let announced_tip = node0.target_tip().clone();
+    let announcement = Inventory {
+        version: 0,
+        features: FEATURE_COMPRESSION | FEATURE_HEADERS,
+        tip: announced_tip.clone(),
+        tip_signature: create_block_signature(&announced_tip, network_signing_key),
+        shortid_nonce: 0,
+        shortid_list: Default::default(),
+    };
+    let mut fresh_node = make_fresh_node(4);
+    block_on(fresh_node.peer_connected(node0.id()));
+    while mailbox.rx.try_recv().is_ok() {}
+    block_on(fresh_node.process_message(node0.id(), Message::Inventory(announcement.clone())))
+        .unwrap();
+    assert_eq!(fresh_node.target_tip(), &announced_tip);
+    block_on(fresh_node.peer_disconnected(node0.id()));
+    assert_eq!(fresh_node.target_tip(), &state.tip);
+
+    // Node downloads the headers up to the announced tip, then the blocks.
+    let relay = |node0: &mut BlockchainProtocol<MockNode>,
+                 node: &mut BlockchainProtocol<MockNode>| {
+        while let Ok((pid_from, pid_to, msg)) = mailbox.rx.try_recv() {
+            let receiver = if pid_to == node0.id() {
+                &mut *node0
+            } else {
+                &mut *node
+            };
+            block_on(receiver.process_message(pid_from, msg)).unwrap();
+        }
+    };
+    block_on(fresh_node.peer_connected(node0.id()));
+    while mailbox.rx.try_recv().is_ok() {}
+    block_on(fresh_node.process_message(node0.id(), Message::Inventory(announcement.clone())))
+        .unwrap();
+    let status = fresh_node.sync_status();
+    assert_eq!(status.phase, SyncPhase::Headers);
+    assert_eq!(status.target_height, announced_tip.height);
+    assert_eq!((status.headers_percent, status.blocks_percent), (0, 0));
+    block_on(fresh_node.synchronize());
+    relay(&mut node0, &mut fresh_node);
+    let status = fresh_node.sync_status();
+    assert_eq!(status.phase, SyncPhase::Blocks);
+    assert_eq!((status.headers_percent, status.blocks_percent), (100, 0));
+    for _ in 0..announced_tip.height {
+        block_on(fresh_node.synchronize());
+        relay(&mut node0, &mut fresh_node);
+    }
+    let status = fresh_node.sync_status();
+    assert_eq!(status.phase, SyncPhase::CaughtUp);
+    assert_eq!(status.tip_height, announced_tip.height);
+    assert_eq!(status.blocks_percent, 100);
+    assert_eq!(fresh_node.target_tip(), &announced_tip);
+
+    // Blocks requested in one window are applied in order, whatever order they arrive in.
+    let mut windowed_node = make_fresh_node(6);
+    block_on(windowed_node.peer_connected(node0.id()));
+    while mailbox.rx.try_recv().is_ok() {}
+    block_on(windowed_node.process_message(node0.id(), Message::Inventory(announcement.clone())))
+        .unwrap();
+    block_on(windowed_node.synchronize());
+    relay(&mut node0, &mut windowed_node);
+    block_on(windowed_node.synchronize());
+    let mut blocks = Vec::new();
+    while let Ok((pid_from, pid_to, msg)) = mailbox.rx.try_recv() {
+        if pid_to == node0.id() {
+            block_on(node0.process_message(pid_from, msg)).unwrap();
+        } else {
+            blocks.push(msg);
+        }
+    }
+    assert_eq!(blocks.len() as u64, announced_tip.height - 1);
+    for msg in blocks.into_iter().rev() {
+        block_on(windowed_node.process_message(node0.id(), msg)).unwrap();
+    }
+    block_on(windowed_node.synchronize());
+    relay(&mut node0, &mut windowed_node);
+    assert_eq!(windowed_node.sync_status().phase, SyncPhase::CaughtUp);
+    assert_eq!(windowed_node.sync_status().tip_height, announced_tip.height);
+
+    // Node gives up on the announced tip if the blocks never arrive,
+    // and does not follow it again until the peer announces another tip.
+    let mut stalled_node = make_fresh_node(5).set_target_tip_timeout(0);
+    block_on(stalled_node.peer_connected(node0.id()));
+    block_on(stalled_node.process_message(node0.id(), Message::Inventory(announcement.clone())))
+        .unwrap();
+    assert_eq!(stalled_node.target_tip(), &announced_tip);
+    block_on(stalled_node.synchronize());
+    assert_eq!(stalled_node.target_tip(), &state.tip);
+    block_on(stalled_node.process_message(node0.id(), Message::Inventory(announcement))).unwrap();
+    assert_eq!(stalled_node.target_tip(), &state.tip);
+    while mailbox.rx.try_recv().is_ok() {}
+}
+
+#[test]
+fn test_error_source_chain() {
+    use std::error::Error;
+
+    let err: BlockchainError = zkvm::VMError::InvalidMerkleProof.into();
+    assert!(matches!(
+        err,
+        BlockchainError::VMError(zkvm::VMError::InvalidMerkleProof)
+    ));
+    assert_eq!(
+        err.source().map(|e| e.to_string()),
+        Some(zkvm::VMError::InvalidMerkleProof.to_string())
+    );
+
+    let err: BlockchainError = utreexo::UtreexoError::InvalidProof.into();
+    assert!(err.source().is_some());
+}
+
+#[test]
+fn test_failure_class() {
+    let class = |e: BlockchainError| e.failure_class();
+
+    assert_eq!(
+        class(BlockchainError::InvalidBlockSignature),
+        FailureClass::Consensus
+    );
+    assert_eq!(
+        class(zkvm::VMError::StackNotClean.into()),
+        FailureClass::Consensus
+    );
+    assert_eq!(
+        class(zkvm::VMError::WitnessMissing.into()),
+        FailureClass::Internal
+    );
+    // Verifier rejects the out-of-range constants.
+    assert_eq!(
+        class(zkvm::VMError::InvalidBitrange.into()),
+        FailureClass::Consensus
+    );
+    assert_eq!(
+        class(BlockchainError::TooManyTxsRequested),
+        FailureClass::Policy
+    );
+    assert_eq!(
+        class(BlockchainError::FeerateTooLow(1)),
+        FailureClass::Policy
+    );
+    assert_eq!(
+        class(BlockchainError::BlockNotFound(1)),
+        FailureClass::Transient
+    );
+    assert_eq!(
+        class(utreexo::UtreexoError::OutdatedProof.into()),
+        FailureClass::Transient
+    );
+    assert_eq!(
+        class(utreexo::UtreexoError::InvalidProof.into()),
+        FailureClass::Consensus
+    );
+}
+
+#[test]
+fn test_compressed_messages() {
+    let message = Message::MempoolTxs(MempoolTxs {
+        tip: BlockID([1; 32]),
+        txs: Vec::new(),
+    });
+    let compressed = Compressed::compress(&message).expect("MempoolTxs is compressible");
+    match compressed.decompress() {
+        Ok(Message::MempoolTxs(txs)) => assert_eq!(txs.tip, BlockID([1; 32])),
+        other => panic!("Unexpected decompression result: {:?}", other),
+    }
+
+    // Only bulky messages are compressed.
+    assert!(Compressed::compress(&Message::GetBlock(GetBlock { height: 1 })).is_none());
+
+    // Decompressed size is checked before decompression.
+    let bomb = Compressed {
+        data: snap::raw::Encoder::new()
+            .compress_vec(&vec![0u8; MAX_DECOMPRESSED_SIZE + 1])
+            .unwrap(),
+    };
+    match bomb.decompress() {
+        Err(BlockchainError::DecompressedMessageTooLarge(len)) => {
+            assert_eq!(len, MAX_DECOMPRESSED_SIZE + 1)
+        }
+        other => panic!("Oversized message must be rejected: {:?}", other),
+    }
+
+    let garbage = Compressed {
+        data: vec![1, 2, 3],
+    };
+    match garbage.decompress() {
+        Err(BlockchainError::InvalidCompressedMessage) => {}
+        other => panic!("Malformed message must be rejected: {:?}", other),
+    }
 }
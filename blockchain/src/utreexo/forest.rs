@@ -1,4 +1,7 @@
 use core::borrow::Borrow;
+use core::convert::TryFrom;
+use merlin::Transcript;
+use readerwriter::{ReadError, Reader, WriteError, Writer};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
@@ -9,15 +12,21 @@ use thiserror::Error;
 use super::heap::{Heap, HeapIndex};
 use zkvm::merkle::{Directions, Hash, Hasher, MerkleItem, MerkleTree, Path, Position};
 
+/// Version of the canonical encoding of `Forest` and `WorkForest`.
+pub const ENCODING_VERSION: u8 = 1;
+
 /// Forest consists of a number of roots of merkle binary trees.
+/// Serialized in the canonical encoding (see `Forest::to_bytes`).
 #[derive(Clone, Serialize, Deserialize)]
+#[serde(into = "Vec<u8>", try_from = "Vec<u8>")]
 pub struct Forest {
-    #[serde(with = "array64")]
     pub(super) roots: [Option<Hash>; 64], // roots of the trees for levels 0 to 63
 }
 
 /// Roots of the perfect merkle trees forming a forest, which itself is an imperfect merkle tree.
+/// Serialized in the canonical encoding (see `WorkForest::to_bytes`).
 #[derive(Clone, Serialize, Deserialize)]
+#[serde(into = "Vec<u8>", try_from = "Vec<u8>")]
 pub struct WorkForest {
     roots: Vec<HeapIndex>, // roots of all the perfect binary trees, including the newly inserted nodes
     heap: Heap<Node>,
@@ -32,6 +41,7 @@ pub struct Catchup {
 
 /// Represents an error in proof creation, verification, or parsing.
 #[derive(Error, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum UtreexoError {
     /// This error occurs when we receive a proof that's outdated and cannot be auto-updated.
     #[error("Item proof is outdated and must be re-created against the new state")]
@@ -41,6 +51,18 @@ pub enum UtreexoError {
     /// to which it should.
     #[error("Merkle proof is invalid")]
     InvalidProof,
+
+    /// This error occurs when the encoded forest has an unknown version.
+    #[error("Unsupported forest encoding version {0}")]
+    UnsupportedVersion(u8),
+
+    /// This error occurs when the checksum of the encoded forest does not match its contents.
+    #[error("Forest checksum mismatch")]
+    ChecksumMismatch,
+
+    /// This error occurs when the encoded forest is malformed.
+    #[error("Forest encoding is malformed")]
+    InvalidEncoding,
 }
 
 /// Node in the merkle tree
@@ -95,6 +117,26 @@ impl Forest {
             .fold(0u64, |total, (level, _)| total + (1 << level))
     }
 
+    /// Restores the forest from the number of items and the roots of its perfect trees,
+    /// ordered from the highest to the lowest level.
+    /// Returns `None` if the number of roots does not match the number of items.
+    pub fn from_roots(count: u64, roots: &[Hash]) -> Option<Self> {
+        if count.count_ones() as usize != roots.len() {
+            return None;
+        }
+        let mut forest = Forest::new();
+        let levels = (0..64).rev().filter(|level| count & (1 << level) != 0);
+        for (level, root) in levels.zip(roots.iter()) {
+            forest.roots[level] = Some(*root);
+        }
+        Some(forest)
+    }
+
+    /// Returns the roots of the perfect trees, ordered from the highest to the lowest level.
+    pub fn roots(&self) -> Vec<Hash> {
+        self.roots_iter().map(|(_, hash)| hash).collect()
+    }
+
     /// Verifies that the given item and a path belong to the forest.
     pub fn verify<M: MerkleItem>(
         &self,
@@ -513,8 +555,7 @@ impl Catchup {
 
         // Adjust the absolute position:
         // keep the lowest (level+1) bits and add the stored position offset for the stored subtree
-        let mask: Position = (1 << midlevel) - 1; // L=0 -> ...00, L=1 -> ...01, L=2 -> ...11
-        path.position = position_offset + (path.position & mask);
+        path.position = position_offset.offset(path.position.within_level(midlevel).to_u64());
 
         // Remove all outdated neighbors
         path.neighbors.truncate(midlevel);
@@ -574,7 +615,7 @@ where
             heap,
             roots,
             stack: Vec::with_capacity(16),
-            root_offset: 0,
+            root_offset: Position::default(),
         }
     }
 }
@@ -590,7 +631,7 @@ where
         while let Some((offset, node_index)) = self.stack.pop() {
             let node = self.heap.get_ref(node_index);
             if let Some((l, r)) = node.children {
-                self.stack.push((offset + (1 << node.level) / 2, r));
+                self.stack.push((offset.offset((1 << node.level) / 2), r));
                 self.stack.push((offset, l));
             } else {
                 return Some((offset, node.clone()));
@@ -601,7 +642,7 @@ where
             let i = *root_index.borrow();
             let root = self.heap.get_ref(i);
             self.stack.push((self.root_offset, i));
-            self.root_offset += 1 << root.level;
+            self.root_offset = self.root_offset.offset(1 << root.level);
             self.next() // this is guaranteed to be 1-level recursion
         } else {
             None
@@ -611,9 +652,9 @@ where
 
 /// Scans the list of roots' levels and returns the index and the level of the root that contains the position in a tree.
 fn find_root(roots: impl IntoIterator<Item = usize>, position: Position) -> Option<(usize, usize)> {
-    let mut offset: Position = 0;
+    let mut offset = Position::default();
     for (i, level) in roots.into_iter().enumerate() {
-        offset += 1u64 << level;
+        offset = offset.offset(1u64 << level);
         if position < offset {
             return Some((i, level));
         }
@@ -621,6 +662,179 @@ fn find_root(roots: impl IntoIterator<Item = usize>, position: Position) -> Opti
     None
 }
 
+/// Canonical encoding of the forests, used for storage and snapshot sync:
+///
+/// ```ascii
+/// Forest:     version (u8) || count (LE64) || roots (32 bytes each, highest level first) || checksum (32 bytes)
+/// WorkForest: version (u8) || n (LE32) || n trees || checksum (32 bytes)
+/// tree:       level (u8) || flags (u8) || hash (32 bytes) || [left tree || right tree]
+/// ```
+///
+/// Flags: bit 0 is set for modified nodes, bit 1 is set for nodes with children.
+/// Trees are encoded in pre-order. Checksum is a transcript hash over the preceding bytes.
+impl Forest {
+    /// Encodes the forest in the canonical format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(1 + 8 + 32 * 64 + 32);
+        write_encoding(&mut buf, |w| {
+            w.write_u64(b"count", self.count())?;
+            for root in self.roots() {
+                w.write(b"root", &root)?;
+            }
+            Ok(())
+        });
+        buf
+    }
+
+    /// Decodes the forest from the canonical format, checking the version and the checksum.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, UtreexoError> {
+        read_encoding(bytes, |r| {
+            let count = r.read_u64()?;
+            let roots = r.read_vec(count.count_ones() as usize, |r| r.read_u8x32().map(Hash))?;
+            Forest::from_roots(count, &roots).ok_or(ReadError::InvalidFormat)
+        })
+    }
+}
+
+impl WorkForest {
+    /// Encodes the work forest in the canonical format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_encoding(&mut buf, |w| {
+            w.write_u32(b"n", self.roots.len() as u32)?;
+            for root in self.roots.iter() {
+                self.heap.get_ref(*root).encode_tree(&self.heap, w)?;
+            }
+            Ok(())
+        });
+        buf
+    }
+
+    /// Decodes the work forest from the canonical format, checking the version and the checksum.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, UtreexoError> {
+        read_encoding(bytes, |r| {
+            let n = r.read_u32()? as usize;
+            let mut heap = Heap::new();
+            let mut roots = Vec::new();
+            for _ in 0..n {
+                roots.push(Node::decode_tree(r, &mut heap, None)?);
+            }
+            Ok(WorkForest { roots, heap })
+        })
+    }
+}
+
+impl Node {
+    fn encode_tree(&self, heap: &Heap<Node>, w: &mut Vec<u8>) -> Result<(), WriteError> {
+        let flags = (self.modified as u8) | ((self.children.is_some() as u8) << 1);
+        w.write_u8(b"level", self.level as u8)?;
+        w.write_u8(b"flags", flags)?;
+        w.write(b"hash", &self.hash)?;
+        if let Some((l, r)) = self.children {
+            heap.get_ref(l).encode_tree(heap, w)?;
+            heap.get_ref(r).encode_tree(heap, w)?;
+        }
+        Ok(())
+    }
+
+    /// Decodes the tree and allocates its nodes in the heap.
+    /// Children must be exactly one level below their parent, which bounds the recursion.
+    fn decode_tree(
+        reader: &mut &[u8],
+        heap: &mut Heap<Node>,
+        expected_level: Option<usize>,
+    ) -> Result<HeapIndex, ReadError> {
+        let level = reader.read_u8()? as usize;
+        let flags = reader.read_u8()?;
+        let hash = Hash(reader.read_u8x32()?);
+        if level >= 64 || flags > 0b11 || expected_level.map_or(false, |l| l != level) {
+            return Err(ReadError::InvalidFormat);
+        }
+        let children = if flags & 0b10 != 0 {
+            if level == 0 {
+                return Err(ReadError::InvalidFormat);
+            }
+            let l = Self::decode_tree(reader, heap, Some(level - 1))?;
+            let r = Self::decode_tree(reader, heap, Some(level - 1))?;
+            Some((l, r))
+        } else {
+            None
+        };
+        Ok(heap.allocate(Node {
+            level,
+            hash,
+            modified: flags & 0b01 != 0,
+            children,
+        }))
+    }
+}
+
+fn write_encoding<F>(buf: &mut Vec<u8>, body: F)
+where
+    F: FnOnce(&mut Vec<u8>) -> Result<(), WriteError>,
+{
+    buf.push(ENCODING_VERSION);
+    body(buf).expect("Writing to Vec never fails.");
+    let checksum = encoding_checksum(&buf[..]);
+    buf.extend_from_slice(&checksum);
+}
+
+fn read_encoding<T, F>(bytes: &[u8], body: F) -> Result<T, UtreexoError>
+where
+    F: FnOnce(&mut &[u8]) -> Result<T, ReadError>,
+{
+    if bytes.len() < 1 + 32 {
+        return Err(UtreexoError::InvalidEncoding);
+    }
+    let (contents, checksum) = bytes.split_at(bytes.len() - 32);
+    if encoding_checksum(contents)[..] != checksum[..] {
+        return Err(UtreexoError::ChecksumMismatch);
+    }
+    if contents[0] != ENCODING_VERSION {
+        return Err(UtreexoError::UnsupportedVersion(contents[0]));
+    }
+    let mut reader = &contents[1..];
+    reader
+        .read_all(body)
+        .map_err(|_| UtreexoError::InvalidEncoding)
+}
+
+pub(super) fn encoding_checksum(bytes: &[u8]) -> [u8; 32] {
+    let mut t = Transcript::new(b"ZkVM.utreexo.checksum");
+    t.append_message(b"bytes", bytes);
+    let mut checksum = [0u8; 32];
+    t.challenge_bytes(b"checksum", &mut checksum);
+    checksum
+}
+
+impl From<Forest> for Vec<u8> {
+    fn from(forest: Forest) -> Self {
+        forest.to_bytes()
+    }
+}
+
+impl TryFrom<Vec<u8>> for Forest {
+    type Error = UtreexoError;
+
+    fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
+        Forest::from_bytes(&bytes)
+    }
+}
+
+impl From<WorkForest> for Vec<u8> {
+    fn from(forest: WorkForest) -> Self {
+        forest.to_bytes()
+    }
+}
+
+impl TryFrom<Vec<u8>> for WorkForest {
+    type Error = UtreexoError;
+
+    fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
+        WorkForest::from_bytes(&bytes)
+    }
+}
+
 impl fmt::Debug for Forest {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "utreexo::Forest{{\n")?;
@@ -678,100 +892,3 @@ impl Node {
         Ok(())
     }
 }
-
-/// Serde adaptor for 64-item array
-mod array64 {
-    use serde::{Deserialize, Deserializer, Serialize, Serializer};
-
-    pub fn serialize<T, S>(value: &[T; 64], serializer: S) -> Result<S::Ok, S::Error>
-    where
-        T: Serialize + Clone,
-        S: Serializer,
-    {
-        value.to_vec().serialize(serializer)
-    }
-
-    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<[T; 64], D::Error>
-    where
-        D: Deserializer<'de>,
-        T: Deserialize<'de> + Default,
-    {
-        let mut vec = Vec::<T>::deserialize(deserializer)?;
-        if vec.len() != 64 {
-            return Err(serde::de::Error::invalid_length(
-                vec.len(),
-                &"a 64-item array",
-            ));
-        }
-        let mut buf: [T; 64] = [
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-            T::default(),
-        ];
-        for i in 0..64 {
-            buf[63 - i] = vec.pop().unwrap();
-        }
-        Ok(buf)
-    }
-}
@@ -7,7 +7,7 @@ mod heap;
 mod tests;
 
 // Public API
-pub use self::forest::{Catchup, Forest, Proof, UtreexoError, WorkForest};
+pub use self::forest::{Catchup, Forest, Proof, UtreexoError, WorkForest, ENCODING_VERSION};
 pub use zkvm::Hasher;
 
 /// Utreexo-labeled hasher for the merkle tree nodes.
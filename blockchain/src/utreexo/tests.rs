@@ -433,3 +433,117 @@ fn insert_and_delete_utreexo() {
         forest.delete(&Item(7), &proof7, &hasher).unwrap();
     });
 }
+
+#[test]
+fn forest_from_roots() {
+    let hasher = utreexo_hasher();
+    let (forest, catchup) = Forest::new()
+        .work_forest()
+        .batch::<_, ()>(|forest| {
+            for i in 0..6 {
+                forest.insert(&Item(i), &hasher);
+            }
+            Ok(())
+        })
+        .unwrap()
+        .normalize(&hasher);
+
+    let roots = forest.roots();
+    assert_eq!(roots.len(), 2);
+    assert!(Forest::from_roots(5, &roots).is_none());
+    assert!(Forest::from_roots(6, &roots[..1]).is_none());
+
+    let restored = Forest::from_roots(forest.count(), &roots).unwrap();
+    assert_eq!(restored.count(), 6);
+    assert_eq!(restored.root(&hasher), forest.root(&hasher));
+
+    let proof = catchup
+        .update_proof(&Item(4), Proof::Transient, &hasher)
+        .unwrap();
+    assert!(restored
+        .verify(&Item(4), proof.as_path().unwrap(), &hasher)
+        .is_ok());
+}
+
+#[test]
+fn forest_encoding_roundtrip() {
+    let hasher = utreexo_hasher();
+    let (forest, catchup) = Forest::new()
+        .work_forest()
+        .batch::<_, ()>(|forest| {
+            for i in 0..6 {
+                forest.insert(&Item(i), &hasher);
+            }
+            Ok(())
+        })
+        .unwrap()
+        .normalize(&hasher);
+
+    let bytes = forest.to_bytes();
+    assert_eq!(bytes[0], ENCODING_VERSION);
+    let restored = Forest::from_bytes(&bytes).unwrap();
+    assert_eq!(restored.count(), forest.count());
+    assert_eq!(restored.root(&hasher), forest.root(&hasher));
+
+    let json = serde_json::to_string(&forest).unwrap();
+    let restored: Forest = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored.root(&hasher), forest.root(&hasher));
+
+    // Work forest keeps the inner nodes and the deletion marks.
+    let proof = catchup
+        .update_proof(&Item(2), Proof::Transient, &hasher)
+        .unwrap();
+    let mut wf = forest.work_forest();
+    wf.delete(&Item(2), &proof, &hasher).unwrap();
+    wf.insert(&Item(6), &hasher);
+
+    let bytes = wf.to_bytes();
+    let restored = WorkForest::from_bytes(&bytes).unwrap();
+    assert_eq!(restored.to_bytes(), bytes);
+    assert_eq!(
+        restored.normalize(&hasher).0.root(&hasher),
+        wf.normalize(&hasher).0.root(&hasher)
+    );
+}
+
+#[test]
+fn forest_encoding_detects_corruption() {
+    let hasher = utreexo_hasher();
+    let (forest, _) = Forest::new()
+        .work_forest()
+        .batch::<_, ()>(|forest| {
+            for i in 0..3 {
+                forest.insert(&Item(i), &hasher);
+            }
+            Ok(())
+        })
+        .unwrap()
+        .normalize(&hasher);
+    let bytes = forest.to_bytes();
+
+    let mut corrupted = bytes.clone();
+    corrupted[10] ^= 1;
+    assert_eq!(
+        Forest::from_bytes(&corrupted).unwrap_err(),
+        UtreexoError::ChecksumMismatch
+    );
+
+    assert_eq!(
+        Forest::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(),
+        UtreexoError::ChecksumMismatch
+    );
+    assert_eq!(
+        Forest::from_bytes(&bytes[..16]).unwrap_err(),
+        UtreexoError::InvalidEncoding
+    );
+
+    // Unknown version with a valid checksum.
+    let mut contents = bytes[..bytes.len() - 32].to_vec();
+    contents[0] = ENCODING_VERSION + 1;
+    let checksum = forest::encoding_checksum(&contents);
+    contents.extend_from_slice(&checksum);
+    assert_eq!(
+        Forest::from_bytes(&contents).unwrap_err(),
+        UtreexoError::UnsupportedVersion(ENCODING_VERSION + 1)
+    );
+}
@@ -24,7 +24,7 @@ impl AssetDefinition {
     }
 
     pub fn metadata(&self) -> zkvm::String {
-        zkvm::String::Opaque(self.alias.as_bytes().to_vec())
+        zkvm::String::Opaque(self.alias.as_bytes().to_vec().into())
     }
 
     pub fn flavor(&self) -> Scalar {
@@ -4,8 +4,9 @@
 use super::schema::*;
 use super::util;
 use blockchain::utreexo;
-use blockchain::{BlockHeader, BlockchainState};
-use zkvm::{Tx, TxEntry};
+use blockchain::{AssetEventKind, AssetIndex, BlockHeader, BlockchainState};
+use curve25519_dalek::scalar::Scalar;
+use zkvm::{Commitment, Tx, TxEntry};
 
 use serde_json::Value as JsonValue;
 
@@ -32,7 +33,7 @@ impl BlockRecord {
     pub fn network_status_summary(&self) -> JsonValue {
         json!({
             "height": self.height,
-            "block_id": hex::encode(self.block_header().id().0),
+            "block_id": self.block_header().id().to_string(),
             "block_header": serde_json::from_str::<JsonValue>(&self.header_json).expect("Block header should be valid JSON."),
             "state": serde_json::from_str::<JsonValue>(&self.state_json).expect("State should be valid JSON."),
             "utxos_count": self.state().utreexo.count(),
@@ -43,7 +44,7 @@ impl BlockRecord {
         let block_header = self.block_header();
         json!({
             "height": self.height,
-            "id": hex::encode(block_header.id().0),
+            "id": block_header.id().to_string(),
             "header": block_header,
             "txs": self.txs().len(),
         })
@@ -53,7 +54,7 @@ impl BlockRecord {
         let block_header = self.block_header();
         json!({
             "height": self.height,
-            "id": hex::encode(block_header.id().0),
+            "id": block_header.id().to_string(),
             "header": &util::to_json_value(&block_header),
             "txs": self.txs().into_iter().map(|tx| {
                 Self::tx_details(&tx)
@@ -86,6 +87,80 @@ impl BlockRecord {
         })
     }
 
+    /// Step-by-step execution of the tx program for the trace viewer:
+    /// the stack after each instruction and the error that stopped the execution, if any.
+    pub fn tx_trace(tx: &Tx) -> JsonValue {
+        let trace = tx.trace();
+        json!({
+            "id": tx.precompute().map(|ptx| ptx.id.to_string()).ok(),
+            "steps": trace.steps.iter().enumerate().map(|(i, step)| json!({
+                "index": i,
+                "instruction": step.instruction,
+                "depth": step.depth,
+                "stack": step.stack,
+                "log_len": step.log_len,
+            })).collect::<Vec<_>>(),
+            "error": trace.error.map(|e| e.to_string()),
+        })
+    }
+
+    /// Issuance events, net supply and recent transfers of a flavor
+    /// in the given blocks, ordered by height.
+    pub fn asset_history(records: &[BlockRecord], flavor: Scalar) -> JsonValue {
+        let mut index = AssetIndex::new();
+        for record in records.iter() {
+            for tx in record.txs() {
+                let ptx = tx
+                    .precompute()
+                    .expect("Our blockchain should not contain invalid transactions.");
+                index
+                    .index_tx(record.height as u64, ptx.id, &ptx.log)
+                    .expect("Our blockchain should not contain invalid commitments.");
+            }
+        }
+
+        let history = match index.history(&Commitment::unblinded(flavor).to_point()) {
+            Some(history) => history,
+            None => {
+                return json!({
+                    "events": [],
+                    "supply": null,
+                    "transfers": [],
+                })
+            }
+        };
+        let supply = history.supply();
+        json!({
+            "events": history.events.iter().map(|event| {
+                let (kind, qty) = match &event.kind {
+                    AssetEventKind::Issue(qty) => ("Issue", hex::encode(qty.as_bytes())),
+                    AssetEventKind::Retire(qty) => ("Retire", hex::encode(qty.as_bytes())),
+                    AssetEventKind::Burn(qty) => ("Burn", qty.to_string()),
+                };
+                json!({
+                    "height": event.height,
+                    "txid": hex::encode(&event.txid),
+                    "kind": kind,
+                    "qty": qty,
+                })
+            }).collect::<Vec<_>>(),
+            "supply": {
+                "issuances": supply.issuances,
+                "retirements": supply.retirements,
+                "burned": supply.burned,
+                "net_qty": hex::encode(supply.net_qty.as_bytes()),
+            },
+            "transfers": history.transfers.iter().rev().map(|transfer| {
+                json!({
+                    "height": transfer.height,
+                    "txid": hex::encode(&transfer.txid),
+                    "contract_id": hex::encode(&transfer.contract_id),
+                    "qty": hex::encode(transfer.qty.as_bytes()),
+                })
+            }).collect::<Vec<_>>(),
+        })
+    }
+
     pub fn block_header(&self) -> BlockHeader {
         util::from_valid_json(&self.header_json)
     }
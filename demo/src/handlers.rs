@@ -6,7 +6,7 @@ use std::sync::Mutex;
 use diesel::prelude::*;
 
 use rocket::request::{Form, FromForm};
-use rocket::response::{status::NotFound, Flash, NamedFile, Redirect};
+use rocket::response::{content, status::NotFound, Flash, NamedFile, Redirect};
 use rocket::{Request, State};
 
 use rocket_contrib::serve::StaticFiles;
@@ -229,6 +229,31 @@ fn network_block_show(
     Ok(Template::render("network/block_show", &context))
 }
 
+#[get("/network/block/<height_param>/tx/<txid_param>/trace")]
+fn network_tx_trace(
+    height_param: i32,
+    txid_param: String,
+    dbconn: DBConnection,
+) -> Result<content::Json<String>, NotFound<String>> {
+    use schema::block_records::dsl::*;
+    let blk_record = block_records
+        .filter(height.eq(&height_param))
+        .first::<BlockRecord>(&dbconn.0)
+        .map_err(|_| NotFound("Block not found".into()))?;
+
+    let tx = blk_record
+        .txs()
+        .into_iter()
+        .find(|tx| {
+            tx.precompute()
+                .map(|ptx| ptx.id.to_string() == txid_param)
+                .unwrap_or(false)
+        })
+        .ok_or_else(|| NotFound("Transaction not found".into()))?;
+
+    Ok(content::Json(BlockRecord::tx_trace(&tx).to_string()))
+}
+
 #[get("/nodes/<alias_param>")]
 fn nodes_show(
     alias_param: String,
@@ -470,14 +495,68 @@ fn assets_show(
         .first::<AssetRecord>(&dbconn.0)
         .map_err(|_| NotFound("Asset not found".into()))?;
 
+    let history = load_asset_history(&asset, &dbconn)?;
     let context = json!({
         "sidebar": sidebar.json,
         "asset": asset.to_json(),
         "asset_is_external": asset.owner_id != sidebar.current_user.id(),
+        "history": history,
     });
     Ok(Template::render("assets/show", &context))
 }
 
+#[get("/assets/<flavor_param>/history")]
+fn assets_history(
+    flavor_param: String,
+    dbconn: DBConnection,
+) -> Result<content::Json<String>, NotFound<String>> {
+    use schema::asset_records::dsl::*;
+
+    let asset = asset_records
+        .filter(flavor_hex.eq(flavor_param))
+        .first::<AssetRecord>(&dbconn.0)
+        .map_err(|_| NotFound("Asset not found".into()))?;
+
+    Ok(content::Json(
+        load_asset_history(&asset, &dbconn)?.to_string(),
+    ))
+}
+
+/// Indexes the issuance history of the asset from all blocks
+/// and adds the circulating supply known to the demo accounts.
+fn load_asset_history(
+    asset: &AssetRecord,
+    dbconn: &DBConnection,
+) -> Result<serde_json::Value, NotFound<String>> {
+    let blk_records = {
+        use schema::block_records::dsl::*;
+        block_records
+            .order(height.asc())
+            .load::<BlockRecord>(&dbconn.0)
+            .map_err(|_| NotFound("Blocks can't be loaded".into()))?
+    };
+    let acc_records = {
+        use schema::account_records::dsl::*;
+        account_records
+            .load::<AccountRecord>(&dbconn.0)
+            .map_err(|_| NotFound("Accounts can't be loaded".into()))?
+    };
+
+    // Issued quantities are blinded on chain, but the demo accounts know
+    // the clear values of their confirmed utxos.
+    let flavor = asset.flavor();
+    let circulating = acc_records
+        .iter()
+        .flat_map(|rec| rec.wallet().utxos)
+        .filter_map(|utxo| utxo.spendable_utxo().map(|u| u.value()))
+        .filter(|value| value.flv == flavor)
+        .fold(0u64, |total, value| total.saturating_add(value.qty));
+
+    let mut history = BlockRecord::asset_history(&blk_records, flavor);
+    history["circulating"] = json!(circulating);
+    Ok(history)
+}
+
 #[derive(FromForm)]
 struct NewAccountForm {
     alias: String,
@@ -729,10 +808,12 @@ pub fn launch_rocket_app(p2p_handle: net::P2PHandle) {
                 network_mempool_makeblock,
                 network_blocks,
                 network_block_show,
+                network_tx_trace,
                 network_connect_peer,
                 nodes_show,
                 nodes_create,
                 assets_show,
+                assets_history,
                 assets_create,
                 pay,
                 favicon
@@ -19,12 +19,19 @@ mod handlers;
 mod mempool;
 mod names;
 mod net;
+mod scenario;
 mod schema;
 mod sidebar;
 mod user;
 mod util;
 
 fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() == 3 && args[1] == "scenario" {
+        let success = scenario::run_path(std::path::Path::new(&args[2]));
+        std::process::exit(if success { 0 } else { 1 });
+    }
+
     db::prepare_db_if_needed();
     let handle = net::launch_p2p();
     handlers::launch_rocket_app(handle);
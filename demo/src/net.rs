@@ -72,6 +72,9 @@ pub fn launch_p2p() -> P2PHandle {
             inbound_limit: 100,
             outbound_limit: 100,
             heartbeat_interval_sec: 3600,
+            ping_interval_sec: 30,
+            ping_timeout_sec: 60,
+            proxy: None,
         };
 
         let mut rt =
@@ -0,0 +1,428 @@
+//! Scripted scenarios for reproducible walkthroughs.
+//!
+//! A scenario is a JSON file with a list of steps (create accounts, issue assets, pay,
+//! make blocks, assert balances) that are executed against an in-memory demo network
+//! seeded the same way as the database in `db::prepare_db_if_needed`.
+//! Running a scenario produces a transcript suitable for the docs,
+//! and fails on the first step that cannot be performed or whose assertion does not hold,
+//! so the scenarios double as end-to-end regression tests:
+//!
+//! ```ascii
+//! cargo run -- scenario scenarios/walkthrough.json
+//! cargo run -- scenario scenarios/
+//! ```
+//!
+//! Transaction IDs depend on the random blinding factors, so the transcript
+//! only mentions the values, balances and block heights.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use bulletproofs::BulletproofGens;
+use serde::Deserialize;
+
+use blockchain::utreexo;
+use blockchain::{BlockTx, BlockchainState};
+use zkvm::{Anchor, ClearValue};
+
+use crate::account::{Utxo, UtxoWithStatus, Wallet};
+use crate::asset::AssetRecord;
+use crate::mempool::Mempool;
+use crate::user::User;
+
+/// Alias of the treasury account that anchors the issuances.
+const TREASURY_ALIAS: &'static str = "Root";
+
+/// Alias of the asset allocated to the treasury in the initial state.
+const TREASURY_ASSET: &'static str = "XLM";
+
+/// Interval between the timestamps of the blocks made by the scenario.
+const BLOCK_INTERVAL_MS: u64 = 1000;
+
+/// Description of the scenario.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Scenario {
+    /// Name of the scenario printed in the transcript.
+    pub name: String,
+
+    /// Seed of the user owning all the accounts and assets.
+    pub seed: String,
+
+    /// Steps to perform in order.
+    pub steps: Vec<Step>,
+}
+
+/// Single step of the scenario.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Step {
+    /// Creates a new account with a given alias.
+    CreateAccount { alias: String },
+
+    /// Issues an asset to the account, creating the asset if needed.
+    Issue {
+        asset: String,
+        qty: u64,
+        recipient: String,
+    },
+
+    /// Pays an amount of the asset from one account to another.
+    Pay {
+        sender: String,
+        recipient: String,
+        asset: String,
+        qty: u64,
+    },
+
+    /// Makes a block out of all the transactions in the mempool.
+    MakeBlock,
+
+    /// Checks the spendable balance of the account.
+    AssertBalance {
+        account: String,
+        asset: String,
+        qty: u64,
+    },
+}
+
+/// In-memory state of the demo network used to execute the scenario.
+struct Network {
+    user: User,
+    bp_gens: BulletproofGens,
+    mempool: Mempool,
+    timestamp_ms: u64,
+    assets: HashMap<String, AssetRecord>,
+    accounts: HashMap<String, Wallet>,
+}
+
+impl Scenario {
+    /// Reads the scenario from a JSON file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let string = fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        serde_json::from_str(&string).map_err(|e| format!("Cannot parse {}: {}", path.display(), e))
+    }
+
+    /// Runs the scenario and returns the transcript.
+    /// On failure, returns the transcript up to the failed step followed by the error.
+    pub fn run(&self) -> Result<Vec<String>, Vec<String>> {
+        let mut network = Network::new(User::from_seed(self.seed.clone()));
+        let mut transcript = vec![format!("# {}", self.name)];
+        for (i, step) in self.steps.iter().enumerate() {
+            match network.perform(step) {
+                Ok(line) => transcript.push(format!("{}. {}", i + 1, line)),
+                Err(e) => {
+                    transcript.push(format!("{}. FAILED: {}", i + 1, e));
+                    return Err(transcript);
+                }
+            }
+        }
+        Ok(transcript)
+    }
+}
+
+impl Network {
+    /// Creates the network with the treasury account holding the initial allocation.
+    fn new(user: User) -> Self {
+        let timestamp_ms = 0;
+        let xlm = AssetRecord::new(&user, TREASURY_ASSET);
+        let mut treasury = Wallet::new(&user, TREASURY_ALIAS);
+
+        let (utxos, _anchor) = treasury.mint_utxos(
+            Anchor::from_raw_bytes([0; 32]),
+            xlm.flavor(),
+            vec![1_000_000_000u64],
+        );
+        let (state, proofs) =
+            BlockchainState::make_initial(timestamp_ms, utxos.iter().map(|u| u.contract_id()));
+        treasury.utxos = utxos
+            .into_iter()
+            .zip(proofs.into_iter())
+            .map(|(mut utxo, proof)| {
+                utxo.proof = proof;
+                utxo.received()
+            })
+            .collect();
+
+        let mut assets = HashMap::new();
+        assets.insert(TREASURY_ASSET.to_string(), xlm);
+        let mut accounts = HashMap::new();
+        accounts.insert(TREASURY_ALIAS.to_string(), treasury);
+
+        Network {
+            user,
+            bp_gens: BulletproofGens::new(256, 1),
+            mempool: Mempool::new(state, timestamp_ms),
+            timestamp_ms,
+            assets,
+            accounts,
+        }
+    }
+
+    /// Performs the step and returns its description for the transcript.
+    fn perform(&mut self, step: &Step) -> Result<String, String> {
+        match step {
+            Step::CreateAccount { alias } => {
+                if self.accounts.contains_key(alias) {
+                    return Err(format!("Account {} already exists", alias));
+                }
+                let wallet = Wallet::new(&self.user, alias.as_str());
+                self.accounts.insert(alias.clone(), wallet);
+                Ok(format!("Created account {}", alias))
+            }
+            Step::Issue {
+                asset,
+                qty,
+                recipient,
+            } => {
+                self.issue(asset, *qty, recipient)?;
+                Ok(format!("Issued {} {} to {}", qty, asset, recipient))
+            }
+            Step::Pay {
+                sender,
+                recipient,
+                asset,
+                qty,
+            } => {
+                self.pay(sender, recipient, asset, *qty)?;
+                Ok(format!(
+                    "{} paid {} {} to {}",
+                    sender, qty, asset, recipient
+                ))
+            }
+            Step::MakeBlock => {
+                let (height, txs) = self.make_block();
+                Ok(format!("Made block {} with {} transactions", height, txs))
+            }
+            Step::AssertBalance {
+                account,
+                asset,
+                qty,
+            } => {
+                let flv = self.asset(asset)?.flavor();
+                let balance = self
+                    .account(account)?
+                    .utxos
+                    .iter()
+                    .filter_map(UtxoWithStatus::spendable_utxo)
+                    .map(|utxo| utxo.value())
+                    .filter(|value| value.flv == flv)
+                    .map(|value| value.qty)
+                    .sum::<u64>();
+                if balance != *qty {
+                    return Err(format!(
+                        "{} has {} {}, expected {}",
+                        account, balance, asset, qty
+                    ));
+                }
+                Ok(format!("{} has {} {}", account, qty, asset))
+            }
+        }
+    }
+
+    fn issue(&mut self, asset: &str, qty: u64, recipient: &str) -> Result<(), String> {
+        if qty == 0 {
+            return Err("Cannot issue zero".to_string());
+        }
+        if recipient == TREASURY_ALIAS {
+            return Err("Cannot issue to the treasury account".to_string());
+        }
+        let user = &self.user;
+        let asset_record = self
+            .assets
+            .entry(asset.to_string())
+            .or_insert_with(|| AssetRecord::new(user, asset));
+        let payment = ClearValue {
+            qty,
+            flv: asset_record.flavor(),
+        };
+        let (issuance_key, metadata) = (asset_record.issuance_key(), asset_record.metadata());
+
+        let mut issuer = self.take_account(TREASURY_ALIAS)?;
+        let result = self.take_account(recipient).and_then(|mut recipient| {
+            let witness = recipient.generate_receiver(payment);
+            let result = issuer
+                .prepare_issuance_tx(issuance_key, metadata, &witness.receiver, &self.bp_gens)
+                .map_err(|e| e.to_string())
+                .and_then(|(tx, _txid, proofs, reply)| {
+                    self.append_tx(BlockTx { tx, proofs })?;
+                    recipient.utxos.push(
+                        Utxo {
+                            receiver: witness.receiver,
+                            sequence: witness.sequence,
+                            anchor: reply.anchor,
+                            proof: utreexo::Proof::Transient,
+                        }
+                        .received(),
+                    );
+                    Ok(())
+                });
+            self.accounts.insert(recipient.alias.clone(), recipient);
+            result
+        });
+        self.accounts.insert(issuer.alias.clone(), issuer);
+        result
+    }
+
+    fn pay(&mut self, sender: &str, recipient: &str, asset: &str, qty: u64) -> Result<(), String> {
+        if qty == 0 {
+            return Err("Cannot transfer zero".to_string());
+        }
+        if sender == recipient {
+            return Err("Cannot pay to the same account".to_string());
+        }
+        let payment = ClearValue {
+            qty,
+            flv: self.asset(asset)?.flavor(),
+        };
+
+        let mut sender = self.take_account(sender)?;
+        let result = self.take_account(recipient).and_then(|mut recipient| {
+            let witness = recipient.generate_receiver(payment);
+            let result = sender
+                .prepare_payment_tx(&witness.receiver, &self.bp_gens)
+                .map_err(|e| e.to_string())
+                .and_then(|(tx, _txid, proofs, reply)| {
+                    self.append_tx(BlockTx { tx, proofs })?;
+                    recipient.utxos.push(
+                        Utxo {
+                            receiver: witness.receiver,
+                            sequence: witness.sequence,
+                            anchor: reply.anchor,
+                            proof: utreexo::Proof::Transient,
+                        }
+                        .received(),
+                    );
+                    Ok(())
+                });
+            self.accounts.insert(recipient.alias.clone(), recipient);
+            result
+        });
+        self.accounts.insert(sender.alias.clone(), sender);
+        result
+    }
+
+    /// Makes a block out of the mempool and lets all the accounts process it.
+    /// Returns the height of the new block and the number of its transactions.
+    fn make_block(&mut self) -> (u64, usize) {
+        let txs = self
+            .mempool
+            .entries()
+            .map(|entry| entry.tx().clone())
+            .collect::<Vec<_>>();
+        let verified_txs = self
+            .mempool
+            .entries()
+            .map(|entry| entry.verified_tx().clone())
+            .collect::<Vec<_>>();
+
+        let verified_block = self.mempool.make_block();
+        let new_state = verified_block.blockchain_state();
+        let height = new_state.tip.height;
+
+        for wallet in self.accounts.values_mut() {
+            wallet.process_block(&verified_txs, &txs, height, &verified_block.catchup);
+        }
+
+        self.timestamp_ms += BLOCK_INTERVAL_MS;
+        self.mempool = Mempool::new(new_state, self.timestamp_ms);
+        (height, txs.len())
+    }
+
+    fn append_tx(&mut self, block_tx: BlockTx) -> Result<(), String> {
+        self.mempool
+            .append(block_tx, &self.bp_gens)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn asset(&self, alias: &str) -> Result<&AssetRecord, String> {
+        self.assets
+            .get(alias)
+            .ok_or_else(|| format!("Asset {} not found", alias))
+    }
+
+    fn account(&self, alias: &str) -> Result<&Wallet, String> {
+        self.accounts
+            .get(alias)
+            .ok_or_else(|| format!("Account {} not found", alias))
+    }
+
+    /// Removes the account for the duration of the update.
+    /// The caller must put it back.
+    fn take_account(&mut self, alias: &str) -> Result<Wallet, String> {
+        self.accounts
+            .remove(alias)
+            .ok_or_else(|| format!("Account {} not found", alias))
+    }
+}
+
+/// Runs the scenario file, or all the `.json` files in a directory,
+/// printing the transcripts. Returns false if any scenario failed.
+pub fn run_path(path: &Path) -> bool {
+    let mut paths = if path.is_dir() {
+        match fs::read_dir(path) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().map(|ext| ext == "json").unwrap_or(false))
+                .collect::<Vec<_>>(),
+            Err(e) => {
+                eprintln!("Cannot read {}: {}", path.display(), e);
+                return false;
+            }
+        }
+    } else {
+        vec![path.to_path_buf()]
+    };
+    paths.sort();
+
+    let mut success = true;
+    for path in paths {
+        let result = Scenario::load(&path)
+            .map_err(|e| vec![e])
+            .and_then(|s| s.run());
+        match result {
+            Ok(transcript) => println!("{}\n", transcript.join("\n")),
+            Err(transcript) => {
+                eprintln!("{}\n", transcript.join("\n"));
+                success = false;
+            }
+        }
+    }
+    success
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walkthrough() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios/walkthrough.json");
+        let scenario = Scenario::load(&path).unwrap();
+        let transcript = scenario
+            .run()
+            .unwrap_or_else(|transcript| panic!("{}", transcript.join("\n")));
+        assert_eq!(transcript.len(), scenario.steps.len() + 1);
+    }
+
+    #[test]
+    fn failed_assertion_stops_the_scenario() {
+        let scenario = Scenario {
+            name: "Wrong balance".to_string(),
+            seed: "wrong balance".to_string(),
+            steps: vec![
+                Step::AssertBalance {
+                    account: TREASURY_ALIAS.to_string(),
+                    asset: TREASURY_ASSET.to_string(),
+                    qty: 1,
+                },
+                Step::MakeBlock,
+            ],
+        };
+        let transcript = scenario.run().unwrap_err();
+        assert_eq!(transcript.len(), 2);
+        assert!(transcript[1].starts_with("1. FAILED"));
+    }
+}
@@ -34,6 +34,11 @@ impl User {
         hex::encode(&id)
     }
 
+    /// Creates a user with a given seed
+    pub fn from_seed(seed: impl Into<String>) -> Self {
+        Self { seed: seed.into() }
+    }
+
     /// Creates a random seed
     pub fn random() -> Self {
         Self {
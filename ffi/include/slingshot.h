@@ -0,0 +1,52 @@
+#ifndef SLINGSHOT_H
+#define SLINGSHOT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum {
+    SLINGSHOT_OK = 0,
+    SLINGSHOT_NULL_POINTER = 1,
+    SLINGSHOT_INVALID_KEY = 2,
+    SLINGSHOT_INVALID_FORMAT = 3,
+    SLINGSHOT_INVALID_UTREEXO = 4,
+    SLINGSHOT_INVALID_TX = 5,
+    SLINGSHOT_PANIC = 6,
+} SlingshotStatus;
+
+/* Verifies a serialized tx with utreexo proofs against the utreexo state
+   given by the number of items and the concatenated 32-byte roots
+   (from the highest to the lowest level). */
+SlingshotStatus slingshot_verify_tx(const uint8_t *tx,
+                                    size_t tx_len,
+                                    uint64_t utxo_count,
+                                    const uint8_t *roots,
+                                    size_t roots_len);
+
+/* Derives a 32-byte verification key from a 64-byte xpub. */
+SlingshotStatus slingshot_receiver_key(const uint8_t *xpub,
+                                       uint64_t sequence,
+                                       uint8_t *out);
+
+/* Derives 32-byte quantity and flavor blinding factors from a 64-byte xpub. */
+SlingshotStatus slingshot_receiver_blinding_factors(const uint8_t *xpub,
+                                                    uint64_t sequence,
+                                                    uint64_t qty,
+                                                    const uint8_t *flv,
+                                                    uint8_t *out_qty_blinding,
+                                                    uint8_t *out_flv_blinding);
+
+/* Derives a 32-byte signing key from a 64-byte xprv. */
+SlingshotStatus slingshot_signing_key(const uint8_t *xprv,
+                                      uint64_t sequence,
+                                      uint8_t *out);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SLINGSHOT_H */
@@ -0,0 +1,378 @@
+//! C API for verifying transactions and deriving receiver keys.
+//!
+//! All functions take raw pointers to caller-owned buffers and never allocate
+//! memory that the caller has to release. Each function returns a `SlingshotStatus`
+//! code; output buffers are written only when the status is `Ok`.
+//! Panics are caught at the boundary and reported as `Panic`,
+//! and the intermediate secrets are erased before returning.
+//!
+//! See `include/slingshot.h` for the C declarations.
+#![deny(missing_docs)]
+
+use core::slice;
+use std::panic::{self, AssertUnwindSafe};
+
+use curve25519_dalek::scalar::Scalar;
+use keytree::{Xprv, Xpub};
+use readerwriter::{Decodable, Reader};
+use zeroize::Zeroizing;
+
+use accounts::{XprvDerivation, XpubDerivation};
+use blockchain::utreexo::Forest;
+use blockchain::BlockTx;
+use zkvm::bulletproofs::BulletproofGens;
+use zkvm::{ClearValue, Hash};
+
+/// Status code returned by all functions.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SlingshotStatus {
+    /// Operation succeeded.
+    Ok = 0,
+    /// One of the required pointers is null.
+    NullPointer = 1,
+    /// Extended key or scalar is malformed.
+    InvalidKey = 2,
+    /// Transaction could not be decoded.
+    InvalidFormat = 3,
+    /// Utreexo roots do not match the number of items.
+    InvalidUtreexo = 4,
+    /// Transaction is invalid or spends outputs missing from the utreexo.
+    InvalidTx = 5,
+    /// Operation failed due to an internal error.
+    Panic = 6,
+}
+
+/// Runs the function, catching a panic that must not unwind into the caller's frames.
+/// Nothing is observed after the panic except the returned status,
+/// so the state captured by the closure need not be unwind-safe.
+fn catch_panic(f: impl FnOnce() -> SlingshotStatus) -> SlingshotStatus {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(SlingshotStatus::Panic)
+}
+
+/// Verifies a serialized block transaction (tx with utreexo proofs)
+/// against the utreexo state given by the number of items and
+/// the concatenated 32-byte roots ordered from the highest to the lowest level.
+///
+/// # Safety
+///
+/// `tx` must point to `tx_len` readable bytes and `roots` must point to `roots_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn slingshot_verify_tx(
+    tx: *const u8,
+    tx_len: usize,
+    utxo_count: u64,
+    roots: *const u8,
+    roots_len: usize,
+) -> SlingshotStatus {
+    if tx.is_null() || (roots.is_null() && roots_len > 0) {
+        return SlingshotStatus::NullPointer;
+    }
+    let tx = slice::from_raw_parts(tx, tx_len);
+    let roots = if roots_len == 0 {
+        &[][..]
+    } else {
+        slice::from_raw_parts(roots, roots_len)
+    };
+    catch_panic(|| verify_tx(tx, utxo_count, roots))
+}
+
+/// Derives the 32-byte compressed verification key at a given sequence number
+/// from a 64-byte xpub.
+///
+/// # Safety
+///
+/// `xpub` must point to 64 readable bytes and `out` must point to 32 writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn slingshot_receiver_key(
+    xpub: *const u8,
+    sequence: u64,
+    out: *mut u8,
+) -> SlingshotStatus {
+    if xpub.is_null() || out.is_null() {
+        return SlingshotStatus::NullPointer;
+    }
+    let xpub = slice::from_raw_parts(xpub, 64);
+    let out = slice::from_raw_parts_mut(out, 32);
+    catch_panic(|| match receiver_key(xpub, sequence) {
+        Some(key) => {
+            out.copy_from_slice(&key);
+            SlingshotStatus::Ok
+        }
+        None => SlingshotStatus::InvalidKey,
+    })
+}
+
+/// Derives the 32-byte quantity and flavor blinding factors for a receiver
+/// at a given sequence number from a 64-byte xpub, a quantity and a 32-byte flavor.
+///
+/// # Safety
+///
+/// `xpub` must point to 64 readable bytes, `flv` must point to 32 readable bytes,
+/// `out_qty_blinding` and `out_flv_blinding` must point to 32 writable bytes each.
+#[no_mangle]
+pub unsafe extern "C" fn slingshot_receiver_blinding_factors(
+    xpub: *const u8,
+    sequence: u64,
+    qty: u64,
+    flv: *const u8,
+    out_qty_blinding: *mut u8,
+    out_flv_blinding: *mut u8,
+) -> SlingshotStatus {
+    if xpub.is_null() || flv.is_null() || out_qty_blinding.is_null() || out_flv_blinding.is_null() {
+        return SlingshotStatus::NullPointer;
+    }
+    let xpub = slice::from_raw_parts(xpub, 64);
+    let flv = slice::from_raw_parts(flv, 32);
+    let out_qty_blinding = slice::from_raw_parts_mut(out_qty_blinding, 32);
+    let out_flv_blinding = slice::from_raw_parts_mut(out_flv_blinding, 32);
+    catch_panic(
+        || match receiver_blinding_factors(xpub, sequence, qty, flv) {
+            Some((q, f)) => {
+                out_qty_blinding.copy_from_slice(q.as_bytes());
+                out_flv_blinding.copy_from_slice(f.as_bytes());
+                SlingshotStatus::Ok
+            }
+            None => SlingshotStatus::InvalidKey,
+        },
+    )
+}
+
+/// Derives the 32-byte signing key at a given sequence number from a 64-byte xprv.
+///
+/// # Safety
+///
+/// `xprv` must point to 64 readable bytes and `out` must point to 32 writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn slingshot_signing_key(
+    xprv: *const u8,
+    sequence: u64,
+    out: *mut u8,
+) -> SlingshotStatus {
+    if xprv.is_null() || out.is_null() {
+        return SlingshotStatus::NullPointer;
+    }
+    let xprv = slice::from_raw_parts(xprv, 64);
+    let out = slice::from_raw_parts_mut(out, 32);
+    catch_panic(|| match signing_key(xprv, sequence) {
+        Some(key) => {
+            out.copy_from_slice(key.as_bytes());
+            SlingshotStatus::Ok
+        }
+        None => SlingshotStatus::InvalidKey,
+    })
+}
+
+fn verify_tx(mut tx: &[u8], utxo_count: u64, roots: &[u8]) -> SlingshotStatus {
+    let block_tx = match tx.read_all(|r| BlockTx::decode(r)) {
+        Ok(block_tx) => block_tx,
+        Err(_) => return SlingshotStatus::InvalidFormat,
+    };
+    if roots.len() % 32 != 0 {
+        return SlingshotStatus::InvalidUtreexo;
+    }
+    let roots = roots
+        .chunks(32)
+        .map(|chunk| {
+            let mut hash = [0u8; 32];
+            hash.copy_from_slice(chunk);
+            Hash(hash)
+        })
+        .collect::<Vec<_>>();
+    let forest = match Forest::from_roots(utxo_count, &roots) {
+        Some(forest) => forest,
+        None => return SlingshotStatus::InvalidUtreexo,
+    };
+
+    // Capacity of the generators is the same as the one used by the node.
+    let bp_gens = BulletproofGens::new(256, 1);
+    match block_tx.verify_against_utreexo(&forest, &bp_gens) {
+        Ok(_) => SlingshotStatus::Ok,
+        Err(_) => SlingshotStatus::InvalidTx,
+    }
+}
+
+fn receiver_key(xpub: &[u8], sequence: u64) -> Option<[u8; 32]> {
+    let xpub = Xpub::from_bytes(xpub)?;
+    Some(xpub.key_at_sequence(sequence).into_point().to_bytes())
+}
+
+/// Blinding factors are erased when dropped, as they would reveal the value of the output.
+fn receiver_blinding_factors(
+    xpub: &[u8],
+    sequence: u64,
+    qty: u64,
+    flv: &[u8],
+) -> Option<(Zeroizing<Scalar>, Zeroizing<Scalar>)> {
+    let xpub = Xpub::from_bytes(xpub)?;
+    let mut flv_bytes = [0u8; 32];
+    flv_bytes.copy_from_slice(flv);
+    let flv = Scalar::from_canonical_bytes(flv_bytes)?;
+    let (q, f) = xpub.value_blinding_factors(sequence, &ClearValue { qty, flv });
+    Some((Zeroizing::new(q), Zeroizing::new(f)))
+}
+
+/// The parsed xprv and the derived key are erased when dropped.
+fn signing_key(xprv: &[u8], sequence: u64) -> Option<Zeroizing<Scalar>> {
+    let xprv = Zeroizing::new(Xprv::from_bytes(xprv)?);
+    Some(Zeroizing::new(xprv.key_at_sequence(sequence)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use blockchain::BlockchainState;
+    use merlin::Transcript;
+    use musig::{Multisignature, Signature};
+    use readerwriter::Encodable;
+    use zkvm::{
+        Anchor, Commitment, Contract, PortableItem, Predicate, Program, Prover, TxHeader, Value,
+    };
+
+    fn make_contract(key: Scalar, qty: u64) -> Contract {
+        Contract {
+            predicate: Predicate::with_witness(key),
+            payload: vec![PortableItem::Value(Value {
+                qty: Commitment::unblinded(qty),
+                flv: Commitment::unblinded(Scalar::zero()),
+            })],
+            anchor: Anchor::from_raw_bytes([0u8; 32]),
+        }
+    }
+
+    fn make_block_tx(key: Scalar) -> (BlockTx, Forest) {
+        let contract = make_contract(key, 10);
+        let (state, proofs) = BlockchainState::make_initial(0u64, vec![contract.id()]);
+
+        let program = Program::build(|p| {
+            p.push(contract)
+                .input()
+                .signtx()
+                .push(Predicate::with_witness(key))
+                .output(1);
+        });
+        let header = TxHeader {
+            version: 1u64,
+            mintime_ms: 0u64,
+            maxtime_ms: u64::max_value(),
+        };
+        let utx = Prover::build_tx(program, header, &BulletproofGens::new(256, 1)).unwrap();
+
+        let mut signtx_transcript = Transcript::new(b"ZkVM.signtx");
+        signtx_transcript.append_message(b"txid", &utx.txid.0);
+        let sig = Signature::sign_multi(
+            &[key],
+            utx.signing_instructions
+                .iter()
+                .map(|(p, m)| (p.verification_key(), m))
+                .collect(),
+            &mut signtx_transcript,
+        )
+        .unwrap();
+
+        let block_tx = BlockTx {
+            tx: utx.sign(sig),
+            proofs,
+        };
+        (block_tx, state.utreexo)
+    }
+
+    #[test]
+    fn verify_tx_against_roots() {
+        let (block_tx, forest) = make_block_tx(Scalar::from(1u64));
+        let tx_bytes = block_tx.encode_to_vec();
+        let roots = forest
+            .roots()
+            .iter()
+            .flat_map(|h| h.0.to_vec())
+            .collect::<Vec<_>>();
+
+        let status = unsafe {
+            slingshot_verify_tx(
+                tx_bytes.as_ptr(),
+                tx_bytes.len(),
+                forest.count(),
+                roots.as_ptr(),
+                roots.len(),
+            )
+        };
+        assert_eq!(status, SlingshotStatus::Ok);
+
+        // Utreexo that does not contain the spent output.
+        let (_, other_forest) = make_block_tx(Scalar::from(2u64));
+        assert_eq!(
+            verify_tx(&tx_bytes, other_forest.count(), &other_forest.roots()[0].0),
+            SlingshotStatus::InvalidTx
+        );
+        assert_eq!(
+            verify_tx(&tx_bytes, 2, &roots),
+            SlingshotStatus::InvalidUtreexo
+        );
+        assert_eq!(
+            verify_tx(&tx_bytes[..tx_bytes.len() - 1], forest.count(), &roots),
+            SlingshotStatus::InvalidFormat
+        );
+    }
+
+    #[test]
+    fn panics_are_caught() {
+        assert_eq!(catch_panic(|| panic!("unexpected")), SlingshotStatus::Panic);
+        assert_eq!(catch_panic(|| SlingshotStatus::Ok), SlingshotStatus::Ok);
+    }
+
+    #[test]
+    fn derive_keys() {
+        let xprv = Xprv::from_seed(b"seed");
+        let xprv_bytes = xprv.to_bytes();
+        let xpub_bytes = xprv.to_xpub().to_bytes();
+
+        let mut pubkey = [0u8; 32];
+        let mut privkey = [0u8; 32];
+        unsafe {
+            assert_eq!(
+                slingshot_receiver_key(xpub_bytes.as_ptr(), 7, pubkey.as_mut_ptr()),
+                SlingshotStatus::Ok
+            );
+            assert_eq!(
+                slingshot_signing_key(xprv_bytes.as_ptr(), 7, privkey.as_mut_ptr()),
+                SlingshotStatus::Ok
+            );
+            assert_eq!(
+                slingshot_signing_key(core::ptr::null(), 7, privkey.as_mut_ptr()),
+                SlingshotStatus::NullPointer
+            );
+        }
+
+        let privkey = Scalar::from_canonical_bytes(privkey).unwrap();
+        assert_eq!(
+            musig::VerificationKey::from_secret(&privkey)
+                .into_point()
+                .to_bytes(),
+            pubkey
+        );
+
+        let flv = Scalar::zero().to_bytes();
+        let (mut q, mut f) = ([0u8; 32], [0u8; 32]);
+        let status = unsafe {
+            slingshot_receiver_blinding_factors(
+                xpub_bytes.as_ptr(),
+                7,
+                10,
+                flv.as_ptr(),
+                q.as_mut_ptr(),
+                f.as_mut_ptr(),
+            )
+        };
+        assert_eq!(status, SlingshotStatus::Ok);
+        let receiver = xprv.to_xpub().receiver_at_sequence(
+            7,
+            ClearValue {
+                qty: 10,
+                flv: Scalar::zero(),
+            },
+        );
+        assert_eq!(q, receiver.qty_blinding.to_bytes());
+        assert_eq!(f, receiver.flv_blinding.to_bytes());
+    }
+}
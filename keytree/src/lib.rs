@@ -7,12 +7,16 @@ use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
 use rand::{CryptoRng, RngCore};
 use starsig::VerificationKey;
+use zeroize::Zeroize;
 
 use crate::transcript::TranscriptProtocol;
 
+mod mnemonic;
 mod serialization;
 mod transcript;
 
+pub use self::mnemonic::{Mnemonic, MnemonicVersion};
+
 #[cfg(test)]
 mod tests;
 
@@ -130,6 +134,15 @@ impl Xprv {
     }
 }
 
+impl Zeroize for Xprv {
+    /// Erases the secret scalar and the derivation key.
+    /// The public key is left as is.
+    fn zeroize(&mut self) {
+        self.scalar.zeroize();
+        self.xpub.dk.zeroize();
+    }
+}
+
 impl Xpub {
     /// Returns an intermediate Xpub derived using a PRF customized with a user-provided closure.
     pub fn derive_intermediate_key(&self, customize: impl FnOnce(&mut Transcript)) -> Xpub {
@@ -0,0 +1,115 @@
+//! Mnemonic backup of the root key material.
+//!
+//! The mnemonic encodes 128 or 256 bits of entropy as 12 or 24 words from the BIP39
+//! English wordlist with a BIP39 checksum. Unlike BIP39, the keys are not derived via
+//! PBKDF2 and secp256k1: the entropy is fed into a Merlin transcript
+//! and the Ristretto keys are squeezed out of it according to the `MnemonicVersion`.
+//! The version is not encoded in the words, so it must be stored with the backup.
+
+use curve25519_dalek::scalar::Scalar;
+use merlin::Transcript;
+use rand::{CryptoRng, RngCore};
+
+use crate::transcript::TranscriptProtocol;
+use crate::Xprv;
+
+/// Mnemonic phrase encoding the root entropy.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Mnemonic {
+    inner: bip39::Mnemonic,
+}
+
+/// Version of the derivation scheme from the mnemonic entropy to the keys.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MnemonicVersion {
+    /// Initial derivation scheme.
+    V1,
+}
+
+impl Mnemonic {
+    /// Generates a new 24-word mnemonic using the provided random number generator `rng`.
+    pub fn random<T: RngCore + CryptoRng>(mut rng: T) -> Self {
+        let mut entropy = [0u8; 32];
+        rng.fill_bytes(&mut entropy);
+        Self::from_entropy(&entropy).expect("32 bytes of entropy are always valid")
+    }
+
+    /// Creates a mnemonic from 16 or 32 bytes of entropy.
+    pub fn from_entropy(entropy: &[u8]) -> Option<Self> {
+        if entropy.len() != 16 && entropy.len() != 32 {
+            return None;
+        }
+        let inner = bip39::Mnemonic::from_entropy(entropy).ok()?;
+        Some(Mnemonic { inner })
+    }
+
+    /// Parses a 12- or 24-word phrase, and fails if the words are unknown
+    /// or the checksum does not match.
+    pub fn from_phrase(phrase: &str) -> Option<Self> {
+        let inner = bip39::Mnemonic::parse(phrase).ok()?;
+        match inner.word_count() {
+            12 | 24 => Some(Mnemonic { inner }),
+            _ => None,
+        }
+    }
+
+    /// Returns the words separated by single spaces.
+    pub fn phrase(&self) -> String {
+        self.inner.to_string()
+    }
+
+    /// Returns the entropy encoded by the mnemonic.
+    pub fn entropy(&self) -> Vec<u8> {
+        self.inner.to_entropy()
+    }
+
+    /// Derives the root Xprv of the wallet.
+    pub fn to_xprv(&self, version: MnemonicVersion) -> Xprv {
+        let mut t = self.prepare_prf(version, b"xprv");
+        let scalar = t.challenge_scalar(b"scalar");
+        let mut dk = [0u8; 32];
+        t.challenge_bytes(b"dk", &mut dk);
+        Xprv::from_raw_parts(scalar, dk)
+    }
+
+    /// Derives the secret scalar of the node's p2p identity.
+    pub fn to_identity_key(&self, version: MnemonicVersion) -> Scalar {
+        self.prepare_prf(version, b"identity")
+            .challenge_scalar(b"scalar")
+    }
+
+    fn prepare_prf(&self, version: MnemonicVersion, purpose: &'static [u8]) -> Transcript {
+        let mut t = Transcript::new(b"Keytree.mnemonic");
+        t.append_u64(b"version", version.to_u64());
+        t.append_message(b"entropy", &self.entropy());
+        t.append_message(b"purpose", purpose);
+        t
+    }
+}
+
+impl MnemonicVersion {
+    /// Latest version of the derivation scheme used for the new mnemonics.
+    pub const LATEST: MnemonicVersion = MnemonicVersion::V1;
+
+    /// Returns the numeric encoding of the version.
+    pub fn to_u64(self) -> u64 {
+        match self {
+            MnemonicVersion::V1 => 1,
+        }
+    }
+
+    /// Decodes the version, and fails if the version is not supported.
+    pub fn from_u64(version: u64) -> Option<Self> {
+        match version {
+            1 => Some(MnemonicVersion::V1),
+            _ => None,
+        }
+    }
+}
+
+impl core::fmt::Debug for Mnemonic {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // Do not leak the secret words into the logs.
+        write!(f, "Mnemonic({} words)", self.inner.word_count())
+    }
+}
@@ -251,6 +251,73 @@ fn random_xpub_leaf_test() {
     );
 }
 
+#[test]
+fn mnemonic_roundtrip() {
+    let mut rng = ChaChaRng::from_seed([0u8; 32]);
+    let mnemonic = Mnemonic::random(&mut rng);
+    let phrase = mnemonic.phrase();
+    assert_eq!(phrase.split(' ').count(), 24);
+
+    let restored = Mnemonic::from_phrase(&phrase).unwrap();
+    assert_eq!(restored, mnemonic);
+    assert_eq!(restored.entropy(), mnemonic.entropy());
+    assert_eq!(
+        restored.to_xprv(MnemonicVersion::V1),
+        mnemonic.to_xprv(MnemonicVersion::V1)
+    );
+}
+
+#[test]
+fn mnemonic_bip39_vectors() {
+    let mnemonic = Mnemonic::from_entropy(&[0u8; 16]).unwrap();
+    assert_eq!(
+        mnemonic.phrase(),
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about"
+    );
+    let mnemonic = Mnemonic::from_entropy(&[0xffu8; 32]).unwrap();
+    assert_eq!(
+        mnemonic.phrase(),
+        "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo vote"
+    );
+}
+
+#[test]
+fn mnemonic_rejects_invalid_phrases() {
+    // bad checksum
+    assert!(Mnemonic::from_phrase(
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon"
+    )
+    .is_none());
+    // unknown word
+    assert!(Mnemonic::from_phrase(
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon slingshot"
+    )
+    .is_none());
+    // unsupported length
+    assert!(Mnemonic::from_entropy(&[0u8; 20]).is_none());
+}
+
+#[test]
+fn mnemonic_derivation_is_domain_separated() {
+    let mnemonic = Mnemonic::from_entropy(&[7u8; 32]).unwrap();
+    let xprv = mnemonic.to_xprv(MnemonicVersion::V1);
+    let identity = mnemonic.to_identity_key(MnemonicVersion::V1);
+    assert_ne!(xprv.to_bytes()[..32], identity.to_bytes()[..]);
+    assert_ne!(xprv, Xprv::from_seed(mnemonic.entropy()));
+    assert_eq!(
+        MnemonicVersion::from_u64(MnemonicVersion::LATEST.to_u64()),
+        Some(MnemonicVersion::LATEST)
+    );
+    assert_eq!(MnemonicVersion::from_u64(0), None);
+}
+
+#[test]
+fn xprv_zeroize() {
+    let mut xprv = Xprv::from_seed(b"seed");
+    xprv.zeroize();
+    assert_eq!(&xprv.to_bytes()[..], &[0u8; 64][..]);
+}
+
 fn to_hex_32(input: [u8; 32]) -> String {
     return hex::encode(&input[..]);
 }
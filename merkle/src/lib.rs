@@ -42,6 +42,58 @@ impl fmt::Debug for Hash {
     }
 }
 
+impl fmt::Display for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(&self.0))
+    }
+}
+
+impl Hash {
+    /// Creates a hash from raw bytes.
+    pub const fn from_bytes(bytes: [u8; 32]) -> Self {
+        Hash(bytes)
+    }
+
+    /// Creates a hash from a slice. Returns None if the slice is not 32 bytes long.
+    pub fn from_slice(slice: &[u8]) -> Option<Self> {
+        if slice.len() != 32 {
+            return None;
+        }
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(slice);
+        Some(Hash(bytes))
+    }
+
+    /// Decodes a hash from a hex string. Returns None if the string is not a 32-byte hex string.
+    pub fn from_hex(string: &str) -> Option<Self> {
+        hex::decode(string)
+            .ok()
+            .and_then(|bytes| Self::from_slice(&bytes))
+    }
+
+    /// Returns a reference to the raw bytes of the hash.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Returns the raw bytes of the hash.
+    pub fn to_bytes(self) -> [u8; 32] {
+        self.0
+    }
+}
+
+impl From<[u8; 32]> for Hash {
+    fn from(bytes: [u8; 32]) -> Self {
+        Hash(bytes)
+    }
+}
+
+impl From<Hash> for [u8; 32] {
+    fn from(hash: Hash) -> Self {
+        hash.0
+    }
+}
+
 impl MerkleTree {
     /// Builds and returns the root hash of a Merkle tree constructed from
     /// the supplied list.
@@ -212,7 +264,69 @@ impl<M: MerkleItem> Hasher<M> {
 }
 
 /// Absolute position of an item in the tree.
-pub type Position = u64;
+/// Bits of the position, from the lowest to the highest, determine the sides
+/// of the neighbors in the merkle path (see `Path::position`).
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Position(u64);
+
+impl Position {
+    /// Creates a position from its integer representation.
+    pub const fn new(position: u64) -> Self {
+        Position(position)
+    }
+
+    /// Returns the integer representation of the position.
+    pub const fn to_u64(self) -> u64 {
+        self.0
+    }
+
+    /// Returns the position relative to the subtree of a given level
+    /// by keeping only the lowest `level` bits.
+    pub fn within_level(self, level: usize) -> Self {
+        Position(self.0 & ((1u64 << level) - 1))
+    }
+
+    /// Returns the position shifted by a given number of items.
+    pub fn offset(self, items: u64) -> Self {
+        Position(self.0 + items)
+    }
+
+    /// Appends a lower bit to the position when descending into a subtree:
+    /// `true` for the right subtree, `false` for the left one.
+    fn descend(self, right: bool) -> Self {
+        Position((self.0 << 1) | (right as u64))
+    }
+
+    /// Returns the side of the item at a given depth.
+    fn side_at(self, depth: usize) -> Side {
+        Side::from_bit(((self.0 >> depth) & 1) as u8)
+    }
+}
+
+impl From<u64> for Position {
+    fn from(position: u64) -> Self {
+        Position(position)
+    }
+}
+
+impl From<Position> for u64 {
+    fn from(position: Position) -> Self {
+        position.0
+    }
+}
+
+impl fmt::Debug for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Position({})", self.0)
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
 
 /// Merkle proof of inclusion of a node in a `Forest`.
 /// The exact tree is determined by the `position`, an absolute position of the item
@@ -277,7 +391,7 @@ impl Side {
 impl Default for Path {
     fn default() -> Path {
         Path {
-            position: 0,
+            position: Position::default(),
             neighbors: Vec::new(),
         }
     }
@@ -322,9 +436,8 @@ impl Path {
             let k = list.len().next_power_of_two() / 2;
             // Note: path.position is not necessarily the same as the global index.
             // See documentation for `Path::position`.
-            path.position = path.position << 1;
+            path.position = path.position.descend(index >= k);
             if index >= k {
-                path.position = path.position | 1;
                 path.neighbors.insert(0, root(&list[..k], builder));
                 fill_neighbors(&list[k..], index - k, path, hasher, builder);
             } else {
@@ -361,7 +474,7 @@ impl Path {
 
 impl Encodable for Path {
     fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
-        w.write_u64(b"position", self.position)?;
+        w.write_u64(b"position", self.position.to_u64())?;
         w.write_u32(b"n", self.neighbors.len() as u32)?;
         for hash in self.neighbors.iter() {
             w.write(b"hash", &hash[..])?;
@@ -378,7 +491,7 @@ impl ExactSizeEncodable for Path {
 
 impl Decodable for Path {
     fn decode(reader: &mut impl Reader) -> Result<Self, ReadError> {
-        let position = reader.read_u64()?;
+        let position = Position::new(reader.read_u64()?);
         let n = reader.read_u32()? as usize;
         let neighbors = reader.read_vec(n, |r| r.read_u8x32().map(Hash))?;
         Ok(Path {
@@ -415,9 +528,9 @@ impl Iterator for Directions {
         if self.depth == 0 {
             return None;
         }
-        let side = Side::from_bit((self.position & 1) as u8);
+        let side = self.position.side_at(0);
         // kick out the lowest bit and shrink the depth
-        self.position >>= 1;
+        self.position = Position(self.position.0 >> 1);
         self.depth -= 1;
         Some(side)
     }
@@ -434,7 +547,7 @@ impl DoubleEndedIterator for Directions {
         self.depth -= 1;
         // Note: we do not mask out the bit in `position` because we don't expose it.
         // The bit is ignored implicitly by having the depth decremented.
-        let side = Side::from_bit(((self.position >> self.depth) & 1) as u8);
+        let side = self.position.side_at(self.depth);
         Some(side)
     }
 }
@@ -548,4 +661,26 @@ mod tests {
             assert_proof_err!(num, idx, wrong_idx);
         }
     }
+
+    #[test]
+    fn hash_hex_roundtrip() {
+        let hash = Hasher::<TestItem>::new(b"test").leaf(&TestItem(1));
+        let hex = hash.to_string();
+        assert_eq!(hex.len(), 64);
+        assert_eq!(Hash::from_hex(&hex), Some(hash));
+        assert_eq!(Hash::from_hex(&hex[..62]), None);
+        assert_eq!(Hash::from(hash.to_bytes()), hash);
+    }
+
+    #[test]
+    fn position_directions() {
+        let position = Position::new(0b1101);
+        assert_eq!(position.within_level(2), Position::new(0b01));
+        assert_eq!(position.offset(3).to_u64(), 0b10000);
+        let sides: Vec<_> = Directions::new(position, 4).collect();
+        assert_eq!(
+            sides,
+            vec![Side::Right, Side::Left, Side::Right, Side::Right]
+        );
+    }
 }
@@ -31,6 +31,28 @@ impl NonceCommitment {
     }
 }
 
+/// Pair of nonce commitments for the two-round protocol.
+/// The effective nonce commitment is `R_1 + b·R_2`, where `b` is the binding factor.
+#[derive(Copy, Clone, Debug)]
+pub struct NoncePair(RistrettoPoint, RistrettoPoint);
+
+impl NoncePair {
+    pub(super) fn new(first: RistrettoPoint, second: RistrettoPoint) -> Self {
+        NoncePair(first, second)
+    }
+
+    pub(super) fn sum(pairs: &Vec<Self>) -> (RistrettoPoint, RistrettoPoint) {
+        pairs.iter().fold(
+            (RistrettoPoint::default(), RistrettoPoint::default()),
+            |(R_1, R_2), pair| (R_1 + pair.0, R_2 + pair.1),
+        )
+    }
+
+    pub(super) fn bind(&self, binding_factor: Scalar) -> NonceCommitment {
+        NonceCommitment(self.0 + binding_factor * self.1)
+    }
+}
+
 pub struct Counterparty {
     position: usize,
     pubkey: VerificationKey,
@@ -63,6 +85,18 @@ impl Counterparty {
             pubkey: self.pubkey,
         }
     }
+
+    pub(super) fn commit_nonce_pair(
+        self,
+        pair: NoncePair,
+        binding_factor: Scalar,
+    ) -> CounterpartyCommitted {
+        CounterpartyCommitted {
+            commitment: pair.bind(binding_factor),
+            position: self.position,
+            pubkey: self.pubkey,
+        }
+    }
 }
 
 impl CounterpartyPrecommitted {
@@ -2,6 +2,7 @@ use thiserror::Error;
 
 /// Represents an error in key aggregation, signing, or verification.
 #[derive(Error, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum MusigError {
     /// This error occurs when a point is not a valid compressed Ristretto point
     #[error("Point decoding failed")]
@@ -20,9 +20,11 @@ pub use starsig::{
 };
 
 pub use self::context::{Multikey, Multimessage, MusigContext};
+pub use self::counterparty::NoncePair;
 pub use self::errors::MusigError;
 pub use self::multisignature::Multisignature;
 pub use self::signer::{
-    Signer, SignerAwaitingCommitments, SignerAwaitingPrecommitments, SignerAwaitingShares,
+    Signer, SignerAwaitingCommitments, SignerAwaitingNonces, SignerAwaitingPrecommitments,
+    SignerAwaitingShares,
 };
 pub use self::transcript::TranscriptProtocol;
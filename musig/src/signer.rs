@@ -33,6 +33,16 @@ pub struct SignerAwaitingCommitments<'t, C: MusigContext> {
     counterparties: Vec<CounterpartyPrecommitted>,
 }
 
+/// State of the party when awaiting nonce pairs from other parties in the two-round protocol.
+pub struct SignerAwaitingNonces<'t, C: MusigContext> {
+    transcript: &'t mut Transcript,
+    context: C,
+    position: usize,
+    x_i: Scalar,
+    r_i: (Scalar, Scalar),
+    counterparties: Vec<Counterparty>,
+}
+
 /// State of the party when awaiting signature shares from other parties.
 pub struct SignerAwaitingShares<C: MusigContext> {
     transcript: Transcript,
@@ -81,6 +91,105 @@ impl Signer {
     }
 }
 
+impl Signer {
+    /// Create new signing party for the two-round protocol for a given transcript.
+    /// Instead of the precommitment round, each party commits to a pair of nonces,
+    /// and the effective nonce is bound to the message and all the commitments.
+    pub fn new_two_round<'t, C: MusigContext>(
+        // The message `m` has already been fed into the transcript
+        transcript: &'t mut Transcript,
+        position: usize,
+        x_i: Scalar,
+        context: C,
+    ) -> (SignerAwaitingNonces<'t, C>, NoncePair) {
+        let mut rng = transcript
+            .build_rng()
+            .rekey_with_witness_bytes(b"x_i", &x_i.to_bytes())
+            .finalize(&mut rand::thread_rng());
+
+        // Generate two ephemeral keypairs (r_i1, R_i1) and (r_i2, R_i2).
+        let r_i = (Scalar::random(&mut rng), Scalar::random(&mut rng));
+        let nonces = NoncePair::new(
+            RISTRETTO_BASEPOINT_POINT * r_i.0,
+            RISTRETTO_BASEPOINT_POINT * r_i.1,
+        );
+
+        let counterparties = (0..context.len())
+            .map(|i| Counterparty::new(i, context.key(i)))
+            .collect();
+
+        (
+            SignerAwaitingNonces {
+                transcript,
+                context,
+                position,
+                x_i,
+                r_i,
+                counterparties,
+            },
+            nonces,
+        )
+    }
+}
+
+impl<'t, C: MusigContext> SignerAwaitingNonces<'t, C> {
+    /// Provide nonce pairs to the party and transition to the shares round.
+    pub fn receive_nonces(
+        mut self,
+        nonce_pairs: Vec<NoncePair>,
+    ) -> Result<(SignerAwaitingShares<C>, Scalar), MusigError> {
+        if nonce_pairs.len() != self.counterparties.len() {
+            return Err(MusigError::BadArguments);
+        }
+
+        // Make R_1 = sum_i(R_i1) and R_2 = sum_i(R_i2).
+        let (R_1, R_2) = NoncePair::sum(&nonce_pairs);
+
+        // Commit the context with label "X".
+        self.context.commit(&mut self.transcript);
+
+        // Make the binding factor b = H(X, m, R_1, R_2) using a copy of the transcript,
+        // so the challenge is computed the same way as in the three-round protocol.
+        let b = {
+            let mut t = self.transcript.clone();
+            t.append_message(b"dom-sep", b"musig-two-round v1");
+            t.append_point(b"R_1", &R_1.compress());
+            t.append_point(b"R_2", &R_2.compress());
+            t.challenge_scalar(b"b")
+        };
+
+        // Make R = R_1 + b * R_2 and commit it with label "R".
+        let R = R_1 + b * R_2;
+        self.transcript.append_point(b"R", &R.compress());
+
+        let counterparties = self
+            .counterparties
+            .into_iter()
+            .zip(nonce_pairs)
+            .map(|(counterparty, pair)| counterparty.commit_nonce_pair(pair, b))
+            .collect();
+
+        // Make a copy of the transcript for extracting the challenge c_i.
+        let transcript = self.transcript.clone();
+
+        // Get per-party challenge c_i
+        let c_i = self.context.challenge(self.position, &mut self.transcript);
+
+        // Generate share: s_i = r_i1 + b * r_i2 + c_i * x_i
+        let s_i = self.r_i.0 + b * self.r_i.1 + c_i * self.x_i;
+
+        Ok((
+            SignerAwaitingShares {
+                transcript,
+                context: self.context,
+                R,
+                counterparties,
+            },
+            s_i,
+        ))
+    }
+}
+
 impl<'t, C: MusigContext> SignerAwaitingPrecommitments<'t, C> {
     /// Provide nonce precommitments to the party and transition to the next round.
     pub fn receive_precommitments(
@@ -4,7 +4,7 @@ use merlin::Transcript;
 
 use starsig::{Signature, TranscriptProtocol, VerificationKey};
 
-use crate::{Multikey, Multimessage, Multisignature, MusigContext, MusigError, Signer};
+use crate::{Multikey, Multimessage, Multisignature, MusigContext, MusigError, NoncePair, Signer};
 
 #[test]
 fn sign_verify_single_multikey() {
@@ -302,3 +302,131 @@ fn check_transcripts_multimessage() {
     // Test that prover and verifier transcript states are the same after running protocol
     assert_eq!(prover_challenge, verifier_challenge);
 }
+
+fn sign_with_two_round_mpc<C: MusigContext + Clone>(
+    privkeys: &Vec<Scalar>,
+    context: C,
+    transcript: Transcript,
+) -> Result<Signature, MusigError> {
+    let mut transcripts: Vec<_> = privkeys.iter().map(|_| transcript.clone()).collect();
+
+    let (parties, nonces): (Vec<_>, Vec<NoncePair>) = privkeys
+        .clone()
+        .into_iter()
+        .zip(transcripts.iter_mut())
+        .enumerate()
+        .map(|(i, (x_i, transcript))| Signer::new_two_round(transcript, i, x_i, context.clone()))
+        .unzip();
+
+    let (parties, shares): (Vec<_>, Vec<_>) = parties
+        .into_iter()
+        .map(|p| p.receive_nonces(nonces.clone()).unwrap())
+        .unzip();
+
+    let signatures = parties
+        .into_iter()
+        .map(|p| p.receive_shares(shares.clone()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Check that signatures from all parties are the same
+    let cmp = &signatures[0];
+    for sig in &signatures {
+        assert_eq!(cmp.s, sig.s);
+        assert_eq!(cmp.R, sig.R)
+    }
+
+    // Check that all party transcripts are in sync at end of the protocol
+    let cmp_challenge = transcripts[0].clone().challenge_scalar(b"test");
+    for mut transcript in transcripts {
+        assert_eq!(cmp_challenge, transcript.challenge_scalar(b"test"));
+    }
+
+    Ok(signatures[0].clone())
+}
+
+#[test]
+fn verify_two_round_multikey() {
+    // super secret, sshhh!
+    let priv_keys = vec![
+        Scalar::from(1u64),
+        Scalar::from(2u64),
+        Scalar::from(3u64),
+        Scalar::from(4u64),
+    ];
+    let multikey = multikey_helper(&priv_keys);
+
+    let signature = sign_with_two_round_mpc(
+        &priv_keys,
+        multikey.clone(),
+        Transcript::new(b"example transcript"),
+    )
+    .unwrap();
+
+    assert!(signature
+        .verify(
+            &mut Transcript::new(b"example transcript"),
+            multikey.aggregated_key()
+        )
+        .is_ok());
+}
+
+#[test]
+fn verify_two_round_multimessage() {
+    // super secret, sshhh!
+    let priv_keys = vec![
+        Scalar::from(1u64),
+        Scalar::from(2u64),
+        Scalar::from(3u64),
+        Scalar::from(4u64),
+    ];
+    let messages = vec![b"message1", b"message2", b"message3", b"message4"];
+    let multimessage = Multimessage::new(multimessage_helper(&priv_keys, messages.clone()));
+
+    let signature = sign_with_two_round_mpc(
+        &priv_keys,
+        multimessage,
+        Transcript::new(b"example transcript"),
+    )
+    .unwrap();
+
+    assert!(signature
+        .verify_multi(
+            &mut Transcript::new(b"example transcript"),
+            multimessage_helper(&priv_keys, messages)
+        )
+        .is_ok());
+}
+
+#[test]
+fn two_round_bad_share() {
+    let priv_keys = vec![Scalar::from(1u64), Scalar::from(2u64)];
+    let multikey = multikey_helper(&priv_keys);
+    let mut transcripts = vec![
+        Transcript::new(b"example transcript"),
+        Transcript::new(b"example transcript"),
+    ];
+    let (t0, t1) = transcripts.split_at_mut(1);
+
+    let (p0, n0) = Signer::new_two_round(&mut t0[0], 0, priv_keys[0], multikey.clone());
+    let (p1, n1) = Signer::new_two_round(&mut t1[0], 1, priv_keys[1], multikey.clone());
+
+    let (p0, s0) = p0.receive_nonces(vec![n0, n1]).unwrap();
+    let (_, s1) = p1.receive_nonces(vec![n0, n1]).unwrap();
+
+    // Share of the second party is corrupted.
+    let result = p0.receive_shares(vec![s0, s1 + Scalar::one()]);
+    assert_eq!(
+        result.unwrap_err(),
+        MusigError::ShareError {
+            pubkey: multikey.key(1).to_bytes()
+        }
+    );
+
+    // Wrong number of nonce pairs is rejected.
+    let mut t = Transcript::new(b"example transcript");
+    let (p, n) = Signer::new_two_round(&mut t, 0, priv_keys[0], multikey);
+    assert_eq!(
+        p.receive_nonces(vec![n]).err(),
+        Some(MusigError::BadArguments)
+    );
+}
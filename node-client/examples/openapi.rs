@@ -0,0 +1,9 @@
+//! Prints the OpenAPI document of the node API.
+
+fn main() {
+    let spec = node_client::openapi();
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&spec).expect("JSON value should serialize")
+    );
+}
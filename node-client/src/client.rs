@@ -0,0 +1,82 @@
+use hyper::client::HttpConnector;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Method, Request};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::errors::Error;
+use crate::types::ErrorResponse;
+
+/// Client of the node API.
+/// The methods for each endpoint are listed in the `endpoints` module.
+#[derive(Clone, Debug)]
+pub struct Client {
+    base_url: String,
+    http: hyper::Client<HttpConnector>,
+    idempotency_key: Option<String>,
+}
+
+impl Client {
+    /// Creates a client for the API at a given URL, e.g. `http://127.0.0.1:3001`.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Client {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            http: hyper::Client::new(),
+            idempotency_key: None,
+        }
+    }
+
+    /// Returns a copy of the client that sends the `Idempotency-Key` header with the requests,
+    /// so the retried state-changing requests are not performed twice.
+    pub fn with_idempotency_key(&self, key: impl Into<String>) -> Self {
+        Client {
+            idempotency_key: Some(key.into()),
+            ..self.clone()
+        }
+    }
+
+    /// Sends the request and decodes the response.
+    pub(crate) async fn call<Q, B, R>(
+        &self,
+        method: Method,
+        path: &str,
+        query: Option<&Q>,
+        body: Option<&B>,
+    ) -> Result<R, Error>
+    where
+        Q: Serialize,
+        B: Serialize,
+        R: DeserializeOwned,
+    {
+        let mut uri = format!("{}{}", self.base_url, path);
+        if let Some(query) = query {
+            uri.push('?');
+            uri.push_str(&serde_urlencoded::to_string(query)?);
+        }
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(key) = &self.idempotency_key {
+            request = request.header("Idempotency-Key", key.as_str());
+        }
+        let request = match body {
+            Some(body) => request
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_vec(body)?))?,
+            None => request.body(Body::empty())?,
+        };
+
+        let response = self.http.request(request).await?;
+        let status = response.status();
+        let bytes = hyper::body::to_bytes(response.into_body()).await?;
+        let text = String::from_utf8_lossy(&bytes);
+        if !status.is_success() {
+            let message = serde_json::from_str::<ErrorResponse>(&text)
+                .map(|response| response.error)
+                .unwrap_or_else(|_| text.to_string());
+            return Err(Error::ApiError(status.as_u16(), message));
+        }
+        Ok(serde_json::de::from_str_with_binary_mode(
+            &text,
+            serde_json::BinaryMode::Hex,
+        )?)
+    }
+}
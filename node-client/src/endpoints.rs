@@ -0,0 +1,181 @@
+//! List of the node API endpoints.
+//!
+//! Each endpoint is declared once and produces a method of the `Client`
+//! and an entry of the OpenAPI document.
+
+use accounts::Receiver;
+use serde_json::Value;
+
+use crate::client::Client;
+use crate::errors::Error;
+use crate::schema::Schema;
+use crate::types::*;
+
+/// Description of the API endpoint.
+#[derive(Clone, Debug)]
+pub struct Endpoint {
+    /// Name of the endpoint, also the name of the client method.
+    pub name: &'static str,
+
+    /// HTTP method.
+    pub method: &'static str,
+
+    /// Path of the endpoint, starting with the API version.
+    pub path: &'static str,
+
+    /// Short description of the endpoint.
+    pub summary: String,
+
+    /// Schema of the request body.
+    pub body: Option<Value>,
+
+    /// Schema of the query string.
+    pub query: Option<Value>,
+
+    /// Schema of the successful response.
+    pub response: Value,
+
+    /// Whether the endpoint accepts the `Idempotency-Key` header.
+    pub idempotent: bool,
+}
+
+/// Wraps an optional argument of the client method.
+macro_rules! optional {
+    () => {
+        None::<&()>
+    };
+    ($arg:expr) => {
+        Some($arg)
+    };
+}
+
+macro_rules! endpoints {
+    ($(
+        $(#[doc = $doc:expr])*
+        $name:ident: $method:ident $path:literal
+            $(body($body:ty))? $(query($query:ty))? => $response:ty $(, $flag:ident)?;
+    )*) => {
+        /// Returns the list of the node API endpoints.
+        pub fn endpoints() -> Vec<Endpoint> {
+            vec![$(
+                Endpoint {
+                    name: stringify!($name),
+                    method: stringify!($method),
+                    path: $path,
+                    summary: vec![$($doc.trim()),*].join(" "),
+                    body: optional!($(<$body as Schema>::schema())?),
+                    query: optional!($(<$query as Schema>::schema())?),
+                    response: <$response as Schema>::schema(),
+                    idempotent: false $(|| stringify!($flag) == "idempotent")?,
+                },
+            )*]
+        }
+
+        impl Client {
+            $(
+                $(#[doc = $doc])*
+                pub async fn $name(&self $(, body: &$body)? $(, query: &$query)?) -> Result<$response, Error> {
+                    self.call(
+                        hyper::Method::$method,
+                        $path,
+                        optional!($({ let query: &$query = query; query })?),
+                        optional!($({ let body: &$body = body; body })?),
+                    )
+                    .await
+                }
+            )*
+        }
+    };
+}
+
+endpoints! {
+    /// Returns the node's identity in the p2p network.
+    identity: GET "/v1/identity" => IdentityResponse;
+
+    /// Lists the transactions in the mempool.
+    mempool: GET "/v1/mempool" => MempoolResponse;
+
+    /// Returns the dependency graph of the transactions in the mempool.
+    mempool_graph: GET "/v1/mempool/graph" => MempoolGraphResponse;
+
+    /// Returns the current state of the blockchain.
+    network_state: GET "/v1/network/state" => NetworkStateResponse;
+
+    /// Lists the peers connected to the node.
+    network_peers: GET "/v1/network/peers" => NetworkPeersResponse;
+
+    /// Returns the progress of the initial block download.
+    sync_status: GET "/v1/network/sync_status" => SyncStatusResponse;
+
+    /// Returns the value issued by the faucet to each receiver.
+    faucet_info: GET "/v1/faucet" => FaucetInfoResponse;
+
+    /// Requests the faucet's asset to the receiver.
+    faucet_request: POST "/v1/faucet" body(Receiver) => FaucetResponse;
+
+    /// Exports the mnemonic of the wallet, encrypted with a passphrase.
+    backup_export: POST "/v1/wallet/backup/export" body(BackupExportRequest) => BackupExportResponse;
+
+    /// Restores the wallet keys and the node's p2p identity from the encrypted backup.
+    backup_import: POST "/v1/wallet/backup/import" body(BackupImportRequest) => BackupImportResponse, idempotent;
+
+    /// Exports the confirmed utxos of the wallet, encrypted with a passphrase.
+    utxos_export: POST "/v1/wallet/utxos/export" body(UtxoExportRequest) => UtxoExportResponse;
+
+    /// Imports the utxos exported from another node.
+    utxos_import: POST "/v1/wallet/utxos/import" body(UtxoImportRequest) => UtxoImportResponse, idempotent;
+
+    /// Starts the rescan of the stored blocks from a given height.
+    rescan: POST "/v1/wallet/rescan" query(RescanQuery) => RescanResponse;
+
+    /// Lists the contacts of the wallet with the payments sent to them.
+    contacts: GET "/v1/wallet/contacts" => ContactsResponse;
+
+    /// Adds a contact or replaces its address and receiver.
+    contact_set: POST "/v1/wallet/contacts" body(ContactRequest) => ContactResponse;
+
+    /// Removes a contact.
+    contact_remove: DELETE "/v1/wallet/contacts" query(ContactQuery) => ContactRemoveResponse;
+
+    /// Builds, signs and submits the payment to a contact or an address.
+    buildtx: POST "/v1/wallet/buildtx" body(BuildTxRequest) => BuildTxResponse, idempotent;
+
+    /// Creates a signed invoice for a new receiver of the wallet.
+    invoice_create: POST "/v1/wallet/invoice" body(InvoiceRequest) => InvoiceResponse;
+
+    /// Pays the invoice before it expires.
+    invoice_pay: POST "/v1/wallet/invoice/pay" body(InvoicePayRequest) => InvoicePayResponse, idempotent;
+
+    /// Creates a vault owned by the wallet and deposits the value into it.
+    vault_create: POST "/v1/wallet/vault" body(VaultCreateRequest) => VaultCreateResponse, idempotent;
+
+    /// Creates a new key of the wallet for recovering the funds from the vaults.
+    vault_recovery_key: POST "/v1/wallet/vault/recovery_key" => VaultRecoveryKeyResponse;
+
+    /// Initiates the withdrawal of the vaulted funds, to be completed after the unlock time.
+    vault_unvault: POST "/v1/wallet/vault/unvault" body(VaultUnvaultRequest) => VaultUnvaultResponse, idempotent;
+
+    /// Completes the withdrawal of the vaulted funds into the wallet.
+    vault_withdraw: POST "/v1/wallet/vault/withdraw" body(VaultSpendRequest) => VaultSpendResponse, idempotent;
+
+    /// Claws back the vaulted funds into the wallet with the recovery key.
+    vault_recover: POST "/v1/wallet/vault/recover" body(VaultSpendRequest) => VaultSpendResponse, idempotent;
+
+    /// Exports the proof of payment to the receiver.
+    payment_proof_export: POST "/v1/payment_proof/export" body(PaymentProofRequest) => PaymentProof;
+
+    /// Verifies the proof of payment against the stored block header.
+    payment_proof_verify: POST "/v1/payment_proof/verify" body(PaymentProof) => PaymentProofVerifyResponse;
+
+    /// Starts the blind signing of a voucher for the wallet's asset.
+    voucher_nonce: POST "/v1/wallet/voucher/nonce" body(VoucherNonceRequest) => VoucherNonceResponse;
+
+    /// Signs the blinded challenge of a voucher.
+    voucher_sign: POST "/v1/wallet/voucher/sign" body(VoucherSignRequest) => VoucherSignResponse;
+
+    /// Redeems the voucher to the receiver.
+    voucher_redeem: POST "/v1/wallet/voucher/redeem" body(VoucherRedeemRequest) => VoucherRedeemResponse, idempotent;
+
+    /// Audits the supply of the assets in the stored blocks.
+    admin_audit: GET "/v1/admin/audit" => AuditResponse;
+}
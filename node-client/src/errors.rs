@@ -0,0 +1,44 @@
+use thiserror::Error as ThisError;
+
+/// Errors returned by the API client.
+#[derive(ThisError, Debug)]
+pub enum Error {
+    #[error("HTTP error: {0}")]
+    HttpError(hyper::Error),
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(hyper::http::Error),
+
+    #[error("Query encoding error: {0}")]
+    QueryError(serde_urlencoded::ser::Error),
+
+    #[error("JSON error: {0}")]
+    JsonError(serde_json::Error),
+
+    #[error("Node responded with status {0}: {1}")]
+    ApiError(u16, String),
+}
+
+impl From<hyper::Error> for Error {
+    fn from(err: hyper::Error) -> Self {
+        Error::HttpError(err)
+    }
+}
+
+impl From<hyper::http::Error> for Error {
+    fn from(err: hyper::http::Error) -> Self {
+        Error::InvalidRequest(err)
+    }
+}
+
+impl From<serde_urlencoded::ser::Error> for Error {
+    fn from(err: serde_urlencoded::ser::Error) -> Self {
+        Error::QueryError(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::JsonError(err)
+    }
+}
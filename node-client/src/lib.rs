@@ -0,0 +1,19 @@
+//! Typed client for the node API and the OpenAPI description of it.
+//!
+//! The request and response types are shared with the node's API server,
+//! and the client methods and the OpenAPI document are generated from a single list
+//! of the endpoints, so the server, the client and the document stay in sync.
+
+#[macro_use]
+mod schema;
+mod client;
+mod endpoints;
+mod errors;
+mod openapi;
+pub mod types;
+
+pub use self::client::Client;
+pub use self::endpoints::{endpoints, Endpoint};
+pub use self::errors::Error;
+pub use self::openapi::openapi;
+pub use self::schema::Schema;
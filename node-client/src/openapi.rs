@@ -0,0 +1,133 @@
+use serde_json::{json, Map, Value};
+
+use crate::endpoints::endpoints;
+use crate::schema::Schema;
+use crate::types::ErrorResponse;
+
+/// Returns the OpenAPI 3 document describing the node API.
+pub fn openapi() -> Value {
+    let mut paths = Map::new();
+    for endpoint in endpoints() {
+        let mut parameters = Vec::new();
+        if let Some(query) = &endpoint.query {
+            let required = query["required"].as_array().cloned().unwrap_or_default();
+            if let Some(properties) = query["properties"].as_object() {
+                for (name, schema) in properties.iter() {
+                    parameters.push(json!({
+                        "name": name,
+                        "in": "query",
+                        "required": required.contains(&json!(name)),
+                        "schema": schema,
+                    }));
+                }
+            }
+        }
+        if endpoint.idempotent {
+            parameters.push(json!({
+                "name": "Idempotency-Key",
+                "in": "header",
+                "required": false,
+                "schema": { "type": "string", "minLength": 1, "maxLength": 255 },
+            }));
+        }
+
+        let mut operation = json!({
+            "operationId": endpoint.name,
+            "summary": endpoint.summary,
+            "parameters": parameters,
+            "responses": {
+                "200": {
+                    "description": "Successful response",
+                    "content": { "application/json": { "schema": endpoint.response } },
+                },
+                "default": {
+                    "description": "Error",
+                    "content": { "application/json": { "schema": ErrorResponse::schema() } },
+                },
+            },
+        });
+        if let Some(body) = endpoint.body {
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": { "application/json": { "schema": body } },
+            });
+        }
+
+        paths
+            .entry(endpoint.path)
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .expect("Path item is an object")
+            .insert(endpoint.method.to_lowercase(), operation);
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Slingshot node API",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoints_are_unique() {
+        let all = endpoints();
+        for (i, a) in all.iter().enumerate() {
+            for b in all[i + 1..].iter() {
+                assert_ne!(a.name, b.name);
+                assert!(a.method != b.method || a.path != b.path);
+            }
+        }
+    }
+
+    #[test]
+    fn document_lists_all_operations() {
+        let doc = openapi();
+        let operations: usize = doc["paths"]
+            .as_object()
+            .unwrap()
+            .values()
+            .map(|item| item.as_object().unwrap().len())
+            .sum();
+        assert_eq!(operations, endpoints().len());
+
+        let faucet = &doc["paths"]["/v1/faucet"];
+        assert!(faucet["get"].is_object());
+        assert!(faucet["post"]["requestBody"].is_object());
+
+        let rescan = &doc["paths"]["/v1/wallet/rescan"]["post"];
+        assert_eq!(rescan["parameters"][0]["name"], "from_height");
+        assert_eq!(rescan["parameters"][0]["required"], true);
+
+        let redeem = &doc["paths"]["/v1/wallet/voucher/redeem"]["post"];
+        assert_eq!(redeem["parameters"][0]["name"], "Idempotency-Key");
+    }
+
+    #[test]
+    fn vault_operations_take_the_vault_and_its_funds() {
+        let doc = openapi();
+        for path in &[
+            "/v1/wallet/vault/unvault",
+            "/v1/wallet/vault/withdraw",
+            "/v1/wallet/vault/recover",
+        ] {
+            let operation = &doc["paths"][path]["post"];
+            assert_eq!(operation["parameters"][0]["name"], "Idempotency-Key");
+            let body = &operation["requestBody"]["content"]["application/json"]["schema"];
+            assert_eq!(body["properties"]["vault"]["required"][0], "owner_key");
+            assert_eq!(body["properties"]["utxo"]["required"][2], "stage");
+        }
+
+        let create = &doc["paths"]["/v1/wallet/vault"]["post"];
+        let response = &create["responses"]["200"]["content"]["application/json"]["schema"];
+        assert!(response["properties"]["utxo"]["properties"]["stage"]["oneOf"].is_array());
+        let recovery_key = &doc["paths"]["/v1/wallet/vault/recovery_key"]["post"];
+        assert!(recovery_key["requestBody"].is_null());
+    }
+}
@@ -0,0 +1,256 @@
+//! JSON schemas of the API types for the OpenAPI document.
+
+use accounts::contracts::vault::{Vault, VaultStage, VaultUtxo};
+use accounts::Receiver;
+use blockchain::BlockHeader;
+use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::scalar::Scalar;
+use serde_json::{json, Value};
+use zkvm::{Anchor, ClearValue, Hash, TxID, VerificationKey};
+
+/// Type with a JSON schema.
+pub trait Schema {
+    /// Whether the field of this type must be present in the object.
+    const REQUIRED: bool = true;
+
+    /// Returns the JSON schema of the type.
+    fn schema() -> Value;
+}
+
+/// Defines the API types with their schemas derived from the types of the fields.
+macro_rules! api_types {
+    ($(
+        $(#[$meta:meta])*
+        pub struct $name:ident {
+            $(
+                $(#[$field_meta:meta])*
+                pub $field:ident: $ty:ty,
+            )*
+        }
+    )*) => {
+        $(
+            $(#[$meta])*
+            #[derive(Clone, Debug, Serialize, Deserialize)]
+            pub struct $name {
+                $(
+                    $(#[$field_meta])*
+                    pub $field: $ty,
+                )*
+            }
+
+            impl $crate::Schema for $name {
+                fn schema() -> serde_json::Value {
+                    let mut properties = serde_json::Map::new();
+                    let mut required = Vec::<&str>::new();
+                    $(
+                        properties.insert(
+                            stringify!($field).to_string(),
+                            <$ty as $crate::Schema>::schema(),
+                        );
+                        if <$ty as $crate::Schema>::REQUIRED {
+                            required.push(stringify!($field));
+                        }
+                    )*
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": properties,
+                        "required": required,
+                    })
+                }
+            }
+        )*
+    };
+}
+
+impl Schema for () {
+    fn schema() -> Value {
+        json!({})
+    }
+}
+
+impl Schema for bool {
+    fn schema() -> Value {
+        json!({ "type": "boolean" })
+    }
+}
+
+impl Schema for u64 {
+    fn schema() -> Value {
+        json!({ "type": "integer", "format": "int64", "minimum": 0 })
+    }
+}
+
+impl Schema for String {
+    fn schema() -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl Schema for Value {
+    fn schema() -> Value {
+        json!({ "type": "object" })
+    }
+}
+
+impl<T: Schema> Schema for Option<T> {
+    const REQUIRED: bool = false;
+
+    fn schema() -> Value {
+        T::schema()
+    }
+}
+
+impl<T: Schema> Schema for Vec<T> {
+    fn schema() -> Value {
+        json!({ "type": "array", "items": T::schema() })
+    }
+}
+
+/// Identifiers are encoded as 64-character hex strings.
+fn hex32_schema() -> Value {
+    json!({ "type": "string", "pattern": "^[0-9a-f]{64}$" })
+}
+
+/// Scalars and points are encoded as arrays of 32 bytes.
+fn array32_schema() -> Value {
+    json!({
+        "type": "array",
+        "items": { "type": "integer", "minimum": 0, "maximum": 255 },
+        "minItems": 32,
+        "maxItems": 32,
+    })
+}
+
+impl Schema for TxID {
+    fn schema() -> Value {
+        hex32_schema()
+    }
+}
+
+impl Schema for Hash {
+    fn schema() -> Value {
+        hex32_schema()
+    }
+}
+
+impl Schema for Anchor {
+    fn schema() -> Value {
+        hex32_schema()
+    }
+}
+
+impl Schema for Scalar {
+    fn schema() -> Value {
+        array32_schema()
+    }
+}
+
+impl Schema for CompressedRistretto {
+    fn schema() -> Value {
+        array32_schema()
+    }
+}
+
+impl Schema for VerificationKey {
+    fn schema() -> Value {
+        array32_schema()
+    }
+}
+
+impl Schema for ClearValue {
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "qty": u64::schema(),
+                "flv": Scalar::schema(),
+            },
+            "required": ["qty", "flv"],
+        })
+    }
+}
+
+impl Schema for Receiver {
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "opaque_predicate": CompressedRistretto::schema(),
+                "value": ClearValue::schema(),
+                "qty_blinding": Scalar::schema(),
+                "flv_blinding": Scalar::schema(),
+            },
+            "required": ["opaque_predicate", "value", "qty_blinding", "flv_blinding"],
+        })
+    }
+}
+
+impl Schema for Vault {
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "owner_key": VerificationKey::schema(),
+                "recovery_key": VerificationKey::schema(),
+                "delay_ms": u64::schema(),
+                "blinding_key": array32_schema(),
+            },
+            "required": ["owner_key", "recovery_key", "delay_ms", "blinding_key"],
+        })
+    }
+}
+
+/// Stage is either `"Locked"` or `{ "Unvaulting": { "unlock_time_ms": u64 } }`.
+impl Schema for VaultStage {
+    fn schema() -> Value {
+        json!({
+            "oneOf": [
+                { "type": "string", "enum": ["Locked"] },
+                {
+                    "type": "object",
+                    "properties": {
+                        "Unvaulting": {
+                            "type": "object",
+                            "properties": { "unlock_time_ms": u64::schema() },
+                            "required": ["unlock_time_ms"],
+                        },
+                    },
+                    "required": ["Unvaulting"],
+                },
+            ],
+        })
+    }
+}
+
+impl Schema for VaultUtxo {
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "receiver": Receiver::schema(),
+                "anchor": Anchor::schema(),
+                "stage": VaultStage::schema(),
+            },
+            "required": ["receiver", "anchor", "stage"],
+        })
+    }
+}
+
+impl Schema for BlockHeader {
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "version": u64::schema(),
+                "height": u64::schema(),
+                "prev": hex32_schema(),
+                "timestamp_ms": u64::schema(),
+                "txroot": hex32_schema(),
+                "txidroot": hex32_schema(),
+                "utxoroot": hex32_schema(),
+                "ext": { "type": "array", "items": { "type": "integer", "minimum": 0, "maximum": 255 } },
+            },
+            "required": ["version", "height", "prev", "timestamp_ms", "txroot", "txidroot", "utxoroot", "ext"],
+        })
+    }
+}
@@ -0,0 +1,371 @@
+//! Requests and responses of the node API.
+//!
+//! Responses are encoded by the node with binary strings in hex
+//! (see `serde_json::BinaryMode::Hex`), so the identifiers such as `TxID`
+//! appear as hex strings. Requests are decoded by the node in the default binary mode.
+
+use accounts::contracts::vault::{Vault, VaultUtxo};
+use accounts::Receiver;
+use blockchain::BlockHeader;
+use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::scalar::Scalar;
+use serde::{Deserialize, Serialize};
+use zkvm::{Hash, TxID, VerificationKey};
+
+/// Proof of payment produced by `/v1/payment_proof/export`.
+/// The client passes it back to `/v1/payment_proof/verify` as is.
+pub type PaymentProof = serde_json::Value;
+
+api_types! {
+    /// Error returned by the node with a non-successful status.
+    pub struct ErrorResponse {
+        pub error: String,
+    }
+
+    /// Identity of the node in the p2p network.
+    pub struct IdentityResponse {
+        pub peer_id: String,
+    }
+
+    /// Transaction in the mempool.
+    pub struct MempoolTx {
+        pub id: TxID,
+        pub size: u64,
+        pub fee: u64,
+        pub feerate: u64,
+        pub age_ms: u64,
+    }
+
+    /// Transactions in the mempool and the minimum feerate of the new ones.
+    pub struct MempoolResponse {
+        pub count: u64,
+        pub size: u64,
+        pub min_feerate: u64,
+        pub txs: Vec<MempoolTx>,
+    }
+
+    /// Transaction in the mempool dependency graph.
+    pub struct MempoolGraphNode {
+        pub id: TxID,
+        pub size: u64,
+        pub fee: u64,
+        pub feerate: u64,
+        pub age_ms: u64,
+    }
+
+    /// Spend of an output of one transaction in the mempool by another one.
+    pub struct MempoolGraphEdge {
+        pub from: TxID,
+        pub to: TxID,
+    }
+
+    /// Dependency graph of the transactions in the mempool.
+    pub struct MempoolGraphResponse {
+        pub nodes: Vec<MempoolGraphNode>,
+        pub edges: Vec<MempoolGraphEdge>,
+    }
+
+    /// Current state of the blockchain.
+    pub struct NetworkStateResponse {
+        pub tip: BlockHeader,
+        pub utreexo: Vec<Hash>,
+        pub utxo_count: u64,
+        pub tx_count: u64,
+    }
+
+    /// Peer connected to the node.
+    pub struct NetworkPeer {
+        pub id: String,
+        pub address: String,
+        pub public: bool,
+        pub inbound: bool,
+        pub latency_ms: Option<u64>,
+        pub frames_sent: u64,
+        pub frames_received: u64,
+        pub decrypt_failures: u64,
+    }
+
+    /// Peers connected to the node.
+    pub struct NetworkPeersResponse {
+        pub count: u64,
+        pub peers: Vec<NetworkPeer>,
+    }
+
+    /// Progress of the initial block download.
+    /// Phase is one of `headers`, `blocks` or `caught_up`.
+    pub struct SyncStatusResponse {
+        pub phase: String,
+        pub tip_height: u64,
+        pub target_height: u64,
+        pub headers_percent: u64,
+        pub blocks_percent: u64,
+    }
+
+    /// Value issued by the faucet to each receiver.
+    pub struct FaucetInfoResponse {
+        pub qty: u64,
+        pub flv: Scalar,
+    }
+
+    /// Position of the accepted faucet request in the queue.
+    pub struct FaucetResponse {
+        pub position: u64,
+    }
+
+    /// Request to export the encrypted wallet backup.
+    pub struct BackupExportRequest {
+        pub passphrase: String,
+    }
+
+    /// Encrypted wallet backup, hex-encoded.
+    pub struct BackupExportResponse {
+        pub backup: String,
+    }
+
+    /// Request to restore the wallet from the encrypted backup.
+    pub struct BackupImportRequest {
+        pub backup: String,
+        pub passphrase: String,
+        pub overwrite: Option<bool>,
+    }
+
+    /// Address label of the restored wallet.
+    pub struct BackupImportResponse {
+        pub address_label: String,
+    }
+
+    /// Request to export the encrypted wallet utxos.
+    pub struct UtxoExportRequest {
+        pub passphrase: String,
+    }
+
+    /// Encrypted wallet utxos, hex-encoded.
+    pub struct UtxoExportResponse {
+        pub utxos: String,
+    }
+
+    /// Request to import the encrypted wallet utxos exported from another node.
+    pub struct UtxoImportRequest {
+        pub utxos: String,
+        pub passphrase: String,
+    }
+
+    /// Number of the wallet utxos added and removed by the import.
+    pub struct UtxoImportResponse {
+        pub added_utxos: u64,
+        pub removed_utxos: u64,
+    }
+
+    /// Query of the wallet rescan request.
+    pub struct RescanQuery {
+        pub from_height: u64,
+    }
+
+    /// Range of the blocks being rescanned.
+    pub struct RescanResponse {
+        pub from_height: u64,
+        pub tip_height: u64,
+    }
+
+    /// Request to export the proof of payment to the receiver.
+    pub struct PaymentProofRequest {
+        pub height: u64,
+        pub txid: TxID,
+        pub receiver: Receiver,
+    }
+
+    /// Result of the payment proof verification: the paid value.
+    pub struct PaymentProofVerifyResponse {
+        pub valid: bool,
+        pub height: u64,
+        pub txid: TxID,
+        pub qty: u64,
+        pub flv: Scalar,
+    }
+
+    /// Request to start the blind signing of a voucher.
+    pub struct VoucherNonceRequest {
+        pub flv: Scalar,
+        pub qty: u64,
+    }
+
+    /// Voucher key and the nonce commitment of the signing session.
+    pub struct VoucherNonceResponse {
+        pub voucher_key: VerificationKey,
+        pub nonce_commitment: CompressedRistretto,
+    }
+
+    /// Request to sign the blinded challenge of a voucher.
+    pub struct VoucherSignRequest {
+        pub voucher_key: VerificationKey,
+        pub challenge: Scalar,
+    }
+
+    /// Blind signature of the voucher.
+    pub struct VoucherSignResponse {
+        pub signature: Scalar,
+    }
+
+    /// Request to redeem the voucher.
+    pub struct VoucherRedeemRequest {
+        pub voucher: String,
+        pub receiver: Receiver,
+    }
+
+    /// ID of the transaction redeeming the voucher.
+    pub struct VoucherRedeemResponse {
+        pub id: TxID,
+    }
+
+    /// Payment sent to a contact.
+    pub struct ContactPayment {
+        pub txid: TxID,
+        pub qty: u64,
+        pub flv: Scalar,
+    }
+
+    /// Contact in the wallet's contact book.
+    /// The address is bech32-encoded.
+    pub struct ContactInfo {
+        pub name: String,
+        pub address: Option<String>,
+        pub receiver: Option<Receiver>,
+        pub payments: Vec<ContactPayment>,
+    }
+
+    /// Contacts ordered by name.
+    pub struct ContactsResponse {
+        pub contacts: Vec<ContactInfo>,
+    }
+
+    /// Request to add a contact or to replace the payment details of an existing one.
+    pub struct ContactRequest {
+        pub name: String,
+        pub address: Option<String>,
+        pub receiver: Option<Receiver>,
+    }
+
+    /// Stored contact and the warnings about the receiver reuse.
+    pub struct ContactResponse {
+        pub contact: ContactInfo,
+        pub warnings: Vec<String>,
+    }
+
+    /// Query of the contact removal request.
+    pub struct ContactQuery {
+        pub name: String,
+    }
+
+    /// Whether the contact was removed.
+    pub struct ContactRemoveResponse {
+        pub removed: bool,
+    }
+
+    /// Request to pay a value to a contact name or to an address.
+    pub struct BuildTxRequest {
+        pub to: String,
+        pub qty: u64,
+        pub flv: Scalar,
+    }
+
+    /// ID of the submitted transaction, the paid contact and the warnings about the receiver reuse.
+    pub struct BuildTxResponse {
+        pub id: TxID,
+        pub contact: Option<String>,
+        pub warnings: Vec<String>,
+    }
+
+    /// Request to create an invoice for a new receiver of the wallet.
+    pub struct InvoiceRequest {
+        pub qty: u64,
+        pub flv: Scalar,
+        pub expiration_ms: u64,
+        pub memo: Option<String>,
+    }
+
+    /// Invoice encoded as a payment request URI.
+    pub struct InvoiceResponse {
+        pub invoice: String,
+    }
+
+    /// Request to pay the invoice.
+    pub struct InvoicePayRequest {
+        pub invoice: String,
+    }
+
+    /// ID of the transaction paying the invoice, with the paid value and the memo.
+    pub struct InvoicePayResponse {
+        pub id: TxID,
+        pub qty: u64,
+        pub flv: Scalar,
+        pub memo: String,
+    }
+
+    /// Request to create a vault owned by the wallet and to deposit a value into it.
+    pub struct VaultCreateRequest {
+        pub recovery_key: VerificationKey,
+        pub delay_ms: u64,
+        pub qty: u64,
+        pub flv: Scalar,
+    }
+
+    /// ID of the deposit transaction, the vault with the sequence of its owner key
+    /// and the deposited funds.
+    pub struct VaultCreateResponse {
+        pub id: TxID,
+        pub vault: Vault,
+        pub sequence: u64,
+        pub utxo: VaultUtxo,
+    }
+
+    /// New recovery key of the wallet and its sequence.
+    pub struct VaultRecoveryKeyResponse {
+        pub recovery_key: VerificationKey,
+        pub sequence: u64,
+    }
+
+    /// Request to initiate the withdrawal of the vaulted funds with the owner key at a given sequence.
+    pub struct VaultUnvaultRequest {
+        pub vault: Vault,
+        pub sequence: u64,
+        pub utxo: VaultUtxo,
+        pub unlock_time_ms: u64,
+    }
+
+    /// ID of the unvaulting transaction and the funds in the waiting period.
+    pub struct VaultUnvaultResponse {
+        pub id: TxID,
+        pub utxo: VaultUtxo,
+    }
+
+    /// Request to move the vaulted funds into the wallet with the key at a given sequence:
+    /// the owner key for the withdrawal, the recovery key for the recovery.
+    pub struct VaultSpendRequest {
+        pub vault: Vault,
+        pub sequence: u64,
+        pub utxo: VaultUtxo,
+    }
+
+    /// ID of the transaction spending the vaulted funds.
+    pub struct VaultSpendResponse {
+        pub id: TxID,
+    }
+
+    /// Net supply of a flavor, with the commitments hex-encoded.
+    pub struct AuditFlavor {
+        pub flavor: String,
+        pub issuances: u64,
+        pub retirements: u64,
+        pub burned: u64,
+        pub net_qty: String,
+    }
+
+    /// Result of the supply audit of the stored blocks.
+    pub struct AuditResponse {
+        pub from_height: u64,
+        pub tip_height: u64,
+        pub utxo_count: u64,
+        pub fees: u64,
+        pub flavors: Vec<AuditFlavor>,
+    }
+}
@@ -1,24 +1,1203 @@
 use std::net::SocketAddr;
+use std::sync::Arc;
+use warp::http::StatusCode;
 use warp::Filter;
 
-use crate::bc::BlockchainRef;
+use accounts::{Address, Invoice, Receiver};
+use node_client::types::*;
+use token::Voucher;
+use zkvm::bulletproofs::BulletproofGens;
+use zkvm::ClearValue;
+
+use crate::auth::{self, ApiToken};
+use crate::bc::{self, BlockchainRef};
 use crate::config::Config;
-use crate::wallet_manager::WalletRef;
+use crate::contacts::{self, Contact};
+use crate::errors::Error;
+use crate::faucet::FaucetRef;
+use crate::idempotency::{self, IdempotencyCache};
+use crate::invoice;
+use crate::json::to_json_value;
+use crate::payment_proof;
+use crate::vault;
+use crate::voucher::{self, VoucherIssuer};
+use crate::wallet::WalletError;
+use crate::wallet_manager::{self, WalletRef};
 
 /// Launches the API server.
-pub async fn launch(config: Config, bc: BlockchainRef, wallet: WalletRef) {
+pub async fn launch(
+    config: Config,
+    bc: BlockchainRef,
+    wallet: WalletRef,
+    faucet: Option<FaucetRef>,
+) {
     let conf = &config.data.api;
     if conf.disabled {
         return;
     }
+    let idempotency = Arc::new(std::sync::Mutex::new(IdempotencyCache::new(&config)));
+    let api_token = ApiToken::from_env();
+
     let echo =
         warp::path!("v1" / "echo" / String).map(|thingy| format!("API v1 echo: {}!", thingy));
 
+    let bc_ref = bc.clone();
+    let identity = warp::path!("v1" / "identity").and_then(move || {
+        let bc = bc_ref.clone();
+        async move {
+            let peer_id = bc.read().await.peer_id();
+            Ok::<_, std::convert::Infallible>(warp::reply::json(&to_json_value(
+                &IdentityResponse {
+                    peer_id: peer_id.to_string(),
+                },
+            )))
+        }
+    });
+
+    let bc_ref = bc.clone();
+    let mempool = warp::path!("v1" / "mempool").and_then(move || {
+        let bc = bc_ref.clone();
+        async move {
+            let bc = bc.read().await;
+            let now_ms = crate::current_timestamp_ms();
+            let txs: Vec<_> = bc
+                .mempool()
+                .entries()
+                .map(|entry| {
+                    let feerate = entry.verified_tx().feerate;
+                    MempoolTx {
+                        id: entry.txid(),
+                        size: feerate.size() as u64,
+                        fee: feerate.fee(),
+                        feerate: feerate.normalize().fee(),
+                        age_ms: now_ms.saturating_sub(entry.timestamp_ms()),
+                    }
+                })
+                .collect();
+            Ok::<_, std::convert::Infallible>(warp::reply::json(&to_json_value(&MempoolResponse {
+                count: txs.len() as u64,
+                size: bc.mempool().size() as u64,
+                min_feerate: bc.mempool().min_feerate(),
+                txs,
+            })))
+        }
+    });
+
+    let bc_ref = bc.clone();
+    let mempool_graph = warp::path!("v1" / "mempool" / "graph").and_then(move || {
+        let bc = bc_ref.clone();
+        async move {
+            let bc = bc.read().await;
+            let now_ms = crate::current_timestamp_ms();
+            let graph = bc.mempool().graph();
+            Ok::<_, std::convert::Infallible>(warp::reply::json(&to_json_value(
+                &MempoolGraphResponse {
+                    nodes: graph
+                        .nodes
+                        .into_iter()
+                        .map(|node| MempoolGraphNode {
+                            id: node.txid,
+                            size: node.size,
+                            fee: node.fee,
+                            feerate: node.feerate,
+                            age_ms: now_ms.saturating_sub(node.timestamp_ms),
+                        })
+                        .collect(),
+                    edges: graph
+                        .edges
+                        .into_iter()
+                        .map(|edge| MempoolGraphEdge {
+                            from: edge.from,
+                            to: edge.to,
+                        })
+                        .collect(),
+                },
+            )))
+        }
+    });
+
+    let bc_ref = bc.clone();
+    let network_state = warp::path!("v1" / "network" / "state").and_then(move || {
+        let bc = bc_ref.clone();
+        async move {
+            let bc = bc.read().await;
+            let state = bc.state();
+            Ok::<_, std::convert::Infallible>(warp::reply::json(&to_json_value(
+                &NetworkStateResponse {
+                    tip: state.tip.clone(),
+                    utreexo: state.utreexo.roots(),
+                    utxo_count: state.utreexo.count(),
+                    tx_count: bc.tx_count(),
+                },
+            )))
+        }
+    });
+
+    let bc_ref = bc.clone();
+    let network_peers = warp::path!("v1" / "network" / "peers").and_then(move || {
+        let bc = bc_ref.clone();
+        async move {
+            let peers: Vec<_> = bc
+                .read()
+                .await
+                .peers()
+                .await
+                .into_iter()
+                .map(|peer| NetworkPeer {
+                    id: peer.id.to_string(),
+                    address: peer.address.to_string(),
+                    public: peer.public,
+                    inbound: peer.direction == p2p::Direction::Inbound,
+                    latency_ms: peer.latency.map(|l| l.as_millis() as u64),
+                    frames_sent: peer.stats.frames_sent(),
+                    frames_received: peer.stats.frames_received(),
+                    decrypt_failures: peer.stats.decrypt_failures(),
+                })
+                .collect();
+            Ok::<_, std::convert::Infallible>(warp::reply::json(&to_json_value(
+                &NetworkPeersResponse {
+                    count: peers.len() as u64,
+                    peers,
+                },
+            )))
+        }
+    });
+
+    let bc_ref = bc.clone();
+    let sync_status = warp::path!("v1" / "network" / "sync_status").and_then(move || {
+        let bc = bc_ref.clone();
+        async move {
+            let status = bc.read().await.sync_status();
+            Ok::<_, std::convert::Infallible>(warp::reply::json(&to_json_value(
+                &SyncStatusResponse {
+                    phase: status.phase.to_string(),
+                    tip_height: status.tip_height,
+                    target_height: status.target_height,
+                    headers_percent: status.headers_percent as u64,
+                    blocks_percent: status.blocks_percent as u64,
+                },
+            )))
+        }
+    });
+
+    let faucet_ref = faucet.clone();
+    let faucet_info = warp::get()
+        .and(warp::path!("v1" / "faucet"))
+        .and_then(move || {
+            let faucet = faucet_ref.clone();
+            async move {
+                let reply = match faucet {
+                    Some(faucet) => {
+                        let value = faucet.lock().await.value();
+                        warp::reply::with_status(
+                            warp::reply::json(&to_json_value(&FaucetInfoResponse {
+                                qty: value.qty,
+                                flv: value.flv,
+                            })),
+                            StatusCode::OK,
+                        )
+                    }
+                    None => faucet_disabled(),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let (token_ref, faucet_ref) = (api_token.clone(), faucet.clone());
+    let require_token = config.data.faucet.require_token;
+    let faucet_request = warp::post()
+        .and(warp::path!("v1" / "faucet"))
+        .and(warp::addr::remote())
+        .and(auth::header())
+        .and(warp::body::content_length_limit(4096))
+        .and(warp::body::json())
+        .and_then(
+            move |addr: Option<SocketAddr>, auth: Option<String>, receiver: Receiver| {
+                let (token, faucet) = (token_ref.clone(), faucet_ref.clone());
+                async move {
+                    if require_token {
+                        if let Err(e) = token.check(auth.as_deref()) {
+                            return Ok::<_, std::convert::Infallible>(faucet_error(e));
+                        }
+                    }
+                    let reply = match (faucet, addr) {
+                        (Some(faucet), Some(addr)) => {
+                            match faucet.lock().await.request(addr.ip(), receiver) {
+                                Ok(position) => warp::reply::with_status(
+                                    warp::reply::json(&to_json_value(&FaucetResponse {
+                                        position: position as u64,
+                                    })),
+                                    StatusCode::ACCEPTED,
+                                ),
+                                Err(e) => faucet_error(e),
+                            }
+                        }
+                        (Some(_), None) => warp::reply::with_status(
+                            warp::reply::json(&to_json_value(&ErrorResponse {
+                                error: "Unknown client address".to_string(),
+                            })),
+                            StatusCode::BAD_REQUEST,
+                        ),
+                        (None, _) => faucet_disabled(),
+                    };
+                    Ok::<_, std::convert::Infallible>(reply)
+                }
+            },
+        );
+
+    let (token_ref, wallet_ref) = (api_token.clone(), wallet.clone());
+    let wallet_backup_export = warp::post()
+        .and(warp::path!("v1" / "wallet" / "backup" / "export"))
+        .and(auth::header())
+        .and(warp::body::content_length_limit(4096))
+        .and(warp::body::json())
+        .and_then(move |auth: Option<String>, req: BackupExportRequest| {
+            let (token, wallet) = (token_ref.clone(), wallet_ref.clone());
+            async move {
+                let result = match token.check(auth.as_deref()) {
+                    Ok(()) => wallet.read().await.export_backup(&req.passphrase),
+                    Err(e) => Err(e),
+                };
+                let reply = match result {
+                    Ok(backup) => warp::reply::with_status(
+                        warp::reply::json(&to_json_value(&BackupExportResponse {
+                            backup: hex::encode(&backup),
+                        })),
+                        StatusCode::OK,
+                    ),
+                    Err(e) => wallet_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let (token_ref, cache_ref, wallet_ref) =
+        (api_token.clone(), idempotency.clone(), wallet.clone());
+    let wallet_backup_import = warp::post()
+        .and(warp::path!("v1" / "wallet" / "backup" / "import"))
+        .and(auth::header())
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(warp::body::content_length_limit(4096))
+        .and(warp::body::json())
+        .and_then(
+            move |auth: Option<String>, key: Option<String>, req: BackupImportRequest| {
+                let (token, cache, wallet) =
+                    (token_ref.clone(), cache_ref.clone(), wallet_ref.clone());
+                async move {
+                    if let Err(e) = token.check(auth.as_deref()) {
+                        return Ok::<_, std::convert::Infallible>(wallet_error(e));
+                    }
+                    let result = idempotency::deduplicate(
+                        &cache,
+                        "backup_import",
+                        key,
+                        req,
+                        move |req| async move {
+                            let bytes = hex::decode(&req.backup)
+                                .map_err(|_| Error::InvalidBackup("invalid hex encoding"))?;
+                            let restored = wallet.write().await.import_backup(
+                                &bytes,
+                                &req.passphrase,
+                                req.overwrite.unwrap_or(false),
+                            )?;
+                            Ok(to_json_value(&BackupImportResponse {
+                                address_label: restored.address_label().as_str().to_string(),
+                            }))
+                        },
+                    )
+                    .await;
+                    let reply = match result {
+                        Ok(response) => {
+                            warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+                        }
+                        Err(e) => wallet_error(e),
+                    };
+                    Ok::<_, std::convert::Infallible>(reply)
+                }
+            },
+        );
+
+    let (token_ref, bc_ref, wallet_ref) = (api_token.clone(), bc.clone(), wallet.clone());
+    let wallet_utxos_export = warp::post()
+        .and(warp::path!("v1" / "wallet" / "utxos" / "export"))
+        .and(auth::header())
+        .and(warp::body::content_length_limit(4096))
+        .and(warp::body::json())
+        .and_then(move |auth: Option<String>, req: UtxoExportRequest| {
+            let (token, bc, wallet) = (token_ref.clone(), bc_ref.clone(), wallet_ref.clone());
+            async move {
+                let result = match token.check(auth.as_deref()) {
+                    Ok(()) => wallet_manager::export_utxos(&wallet, &bc, &req.passphrase).await,
+                    Err(e) => Err(e),
+                };
+                let reply = match result {
+                    Ok(utxos) => warp::reply::with_status(
+                        warp::reply::json(&to_json_value(&UtxoExportResponse {
+                            utxos: hex::encode(&utxos),
+                        })),
+                        StatusCode::OK,
+                    ),
+                    Err(e) => wallet_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let (token_ref, cache_ref, bc_ref, wallet_ref) = (
+        api_token.clone(),
+        idempotency.clone(),
+        bc.clone(),
+        wallet.clone(),
+    );
+    let wallet_utxos_import = warp::post()
+        .and(warp::path!("v1" / "wallet" / "utxos" / "import"))
+        .and(auth::header())
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(warp::body::content_length_limit(4 * 1024 * 1024))
+        .and(warp::body::json())
+        .and_then(
+            move |auth: Option<String>, key: Option<String>, req: UtxoImportRequest| {
+                let (token, cache, bc, wallet) = (
+                    token_ref.clone(),
+                    cache_ref.clone(),
+                    bc_ref.clone(),
+                    wallet_ref.clone(),
+                );
+                async move {
+                    if let Err(e) = token.check(auth.as_deref()) {
+                        return Ok::<_, std::convert::Infallible>(wallet_error(e));
+                    }
+                    let result = idempotency::deduplicate(
+                        &cache,
+                        "utxos_import",
+                        key,
+                        req,
+                        move |req| async move {
+                            let bytes = hex::decode(&req.utxos)
+                                .map_err(|_| Error::InvalidBackup("invalid hex encoding"))?;
+                            let (added_utxos, removed_utxos) =
+                                wallet_manager::import_utxos(wallet, bc, &bytes, &req.passphrase)
+                                    .await?;
+                            Ok(to_json_value(&UtxoImportResponse {
+                                added_utxos: added_utxos as u64,
+                                removed_utxos: removed_utxos as u64,
+                            }))
+                        },
+                    )
+                    .await;
+                    let reply = match result {
+                        Ok(response) => {
+                            warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+                        }
+                        Err(e) => wallet_error(e),
+                    };
+                    Ok::<_, std::convert::Infallible>(reply)
+                }
+            },
+        );
+
+    let (bc_ref, wallet_ref) = (bc.clone(), wallet.clone());
+    let wallet_rescan = warp::post()
+        .and(warp::path!("v1" / "wallet" / "rescan"))
+        .and(warp::query::<RescanQuery>())
+        .and_then(move |query: RescanQuery| {
+            let (bc, wallet) = (bc_ref.clone(), wallet_ref.clone());
+            async move {
+                let from_height = query.from_height;
+                let result = match wallet.read().await.wallet_ref() {
+                    Ok(_) => wallet_manager::check_rescan(&bc, from_height).await,
+                    Err(e) => Err(e),
+                };
+                let reply = match result {
+                    Ok(tip_height) => {
+                        tokio::spawn(async move {
+                            if let Err(e) = wallet_manager::rescan(wallet, bc, from_height).await {
+                                eprintln!("\n=> Wallet rescan failed: {}", e);
+                            }
+                        });
+                        warp::reply::with_status(
+                            warp::reply::json(&to_json_value(&RescanResponse {
+                                from_height,
+                                tip_height,
+                            })),
+                            StatusCode::ACCEPTED,
+                        )
+                    }
+                    Err(e) => wallet_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let bc_ref = bc.clone();
+    let payment_proof_export = warp::post()
+        .and(warp::path!("v1" / "payment_proof" / "export"))
+        .and(warp::body::content_length_limit(4096))
+        .and(warp::body::json())
+        .and_then(move |req: PaymentProofRequest| {
+            let bc = bc_ref.clone();
+            async move {
+                let result = match bc.read().await.load_block(req.height) {
+                    Ok(Some(block)) => {
+                        payment_proof::PaymentProof::new(&block, &req.txid, req.receiver)
+                    }
+                    Ok(None) => Err(Error::BlockNotFound(req.height)),
+                    Err(e) => Err(e),
+                };
+                let reply = match result {
+                    Ok(proof) => warp::reply::with_status(
+                        warp::reply::json(&to_json_value(&proof)),
+                        StatusCode::OK,
+                    ),
+                    Err(e) => payment_proof_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let bc_ref = bc.clone();
+    let payment_proof_verify = warp::post()
+        .and(warp::path!("v1" / "payment_proof" / "verify"))
+        .and(warp::body::content_length_limit(16384))
+        .and(warp::body::json())
+        .and_then(move |proof: payment_proof::PaymentProof| {
+            let bc = bc_ref.clone();
+            async move {
+                let result = match bc.read().await.load_block(proof.height) {
+                    Ok(Some(block)) => proof.verify(&block.header),
+                    Ok(None) => Err(Error::BlockNotFound(proof.height)),
+                    Err(e) => Err(e),
+                };
+                let reply = match result {
+                    Ok(value) => warp::reply::with_status(
+                        warp::reply::json(&to_json_value(&PaymentProofVerifyResponse {
+                            valid: true,
+                            height: proof.height,
+                            txid: proof.txid,
+                            qty: value.qty,
+                            flv: value.flv,
+                        })),
+                        StatusCode::OK,
+                    ),
+                    Err(e) => payment_proof_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let voucher_issuer = Arc::new(tokio::sync::Mutex::new(VoucherIssuer::default()));
+
+    let (issuer_ref, wallet_ref) = (voucher_issuer.clone(), wallet.clone());
+    let voucher_nonce = warp::post()
+        .and(warp::path!("v1" / "wallet" / "voucher" / "nonce"))
+        .and(warp::body::content_length_limit(4096))
+        .and(warp::body::json())
+        .and_then(move |req: VoucherNonceRequest| {
+            let (issuer, wallet) = (issuer_ref.clone(), wallet_ref.clone());
+            async move {
+                let result = issuer
+                    .lock()
+                    .await
+                    .start_signing(&wallet, req.flv, req.qty)
+                    .await;
+                let reply = match result {
+                    Ok((voucher_key, nonce_commitment)) => warp::reply::with_status(
+                        warp::reply::json(&to_json_value(&VoucherNonceResponse {
+                            voucher_key,
+                            nonce_commitment,
+                        })),
+                        StatusCode::OK,
+                    ),
+                    Err(e) => voucher_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let issuer_ref = voucher_issuer.clone();
+    let voucher_sign = warp::post()
+        .and(warp::path!("v1" / "wallet" / "voucher" / "sign"))
+        .and(warp::body::content_length_limit(4096))
+        .and(warp::body::json())
+        .and_then(move |req: VoucherSignRequest| {
+            let issuer = issuer_ref.clone();
+            async move {
+                let result = issuer.lock().await.sign(req.voucher_key, req.challenge);
+                let reply = match result {
+                    Ok(signature) => warp::reply::with_status(
+                        warp::reply::json(&to_json_value(&VoucherSignResponse { signature })),
+                        StatusCode::OK,
+                    ),
+                    Err(e) => voucher_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let bp_gens = Arc::new(BulletproofGens::new(config.data.prover.gens_capacity, 1));
+    let (cache_ref, bc_ref, wallet_ref, gens_ref) = (
+        idempotency.clone(),
+        bc.clone(),
+        wallet.clone(),
+        bp_gens.clone(),
+    );
+    let voucher_redeem = warp::post()
+        .and(warp::path!("v1" / "wallet" / "voucher" / "redeem"))
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(warp::body::content_length_limit(4096))
+        .and(warp::body::json())
+        .and_then(move |key: Option<String>, req: VoucherRedeemRequest| {
+            let (cache, bc, wallet, bp_gens) = (
+                cache_ref.clone(),
+                bc_ref.clone(),
+                wallet_ref.clone(),
+                gens_ref.clone(),
+            );
+            async move {
+                let result = idempotency::deduplicate(
+                    &cache,
+                    "voucher_redeem",
+                    key,
+                    req,
+                    move |req| async move {
+                        let voucher = hex::decode(&req.voucher)
+                            .ok()
+                            .and_then(|bytes| Voucher::from_bytes(&bytes).ok())
+                            .ok_or(Error::InvalidVoucher)?;
+                        let txid =
+                            voucher::redeem(voucher, req.receiver, &bp_gens, &bc, &wallet).await?;
+                        Ok(to_json_value(&VoucherRedeemResponse { id: txid }))
+                    },
+                )
+                .await;
+                let reply = match result {
+                    Ok(response) => {
+                        warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+                    }
+                    Err(e) => voucher_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let wallet_ref = wallet.clone();
+    let contacts_list = warp::get()
+        .and(warp::path!("v1" / "wallet" / "contacts"))
+        .and_then(move || {
+            let wallet = wallet_ref.clone();
+            async move {
+                let result = wallet.read().await.wallet_ref().map(|w| {
+                    w.contacts()
+                        .iter()
+                        .map(|(name, contact)| contact_info(name, contact))
+                        .collect::<Vec<_>>()
+                });
+                let reply = match result {
+                    Ok(contacts) => warp::reply::with_status(
+                        warp::reply::json(&to_json_value(&ContactsResponse { contacts })),
+                        StatusCode::OK,
+                    ),
+                    Err(e) => wallet_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let wallet_ref = wallet.clone();
+    let contact_set = warp::post()
+        .and(warp::path!("v1" / "wallet" / "contacts"))
+        .and(warp::body::content_length_limit(4096))
+        .and(warp::body::json())
+        .and_then(move |req: ContactRequest| {
+            let wallet = wallet_ref.clone();
+            async move {
+                let result = wallet.write().await.update_wallet(|w| {
+                    let address = match &req.address {
+                        Some(string) => Some(
+                            Address::from_string(string)
+                                .ok_or(Error::InvalidContact("address is malformed"))?,
+                        ),
+                        None => None,
+                    };
+                    let warnings = w
+                        .contacts_mut()
+                        .set(req.name.clone(), address, req.receiver)?;
+                    let contact = w
+                        .contacts()
+                        .get(&req.name)
+                        .expect("Contact was just stored.");
+                    Ok(ContactResponse {
+                        contact: contact_info(&req.name, contact),
+                        warnings,
+                    })
+                });
+                let reply = match result {
+                    Ok(response) => warp::reply::with_status(
+                        warp::reply::json(&to_json_value(&response)),
+                        StatusCode::OK,
+                    ),
+                    Err(e) => wallet_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let wallet_ref = wallet.clone();
+    let contact_remove = warp::delete()
+        .and(warp::path!("v1" / "wallet" / "contacts"))
+        .and(warp::query::<ContactQuery>())
+        .and_then(move |query: ContactQuery| {
+            let wallet = wallet_ref.clone();
+            async move {
+                let result = wallet
+                    .write()
+                    .await
+                    .update_wallet(|w| Ok(w.contacts_mut().remove(&query.name).is_some()));
+                let reply = match result {
+                    Ok(removed) => warp::reply::with_status(
+                        warp::reply::json(&to_json_value(&ContactRemoveResponse { removed })),
+                        StatusCode::OK,
+                    ),
+                    Err(e) => wallet_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let (cache_ref, bc_ref, wallet_ref, gens_ref) = (
+        idempotency.clone(),
+        bc.clone(),
+        wallet.clone(),
+        bp_gens.clone(),
+    );
+    let buildtx = warp::post()
+        .and(warp::path!("v1" / "wallet" / "buildtx"))
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(warp::body::content_length_limit(4096))
+        .and(warp::body::json())
+        .and_then(move |key: Option<String>, req: BuildTxRequest| {
+            let (cache, bc, wallet, bp_gens) = (
+                cache_ref.clone(),
+                bc_ref.clone(),
+                wallet_ref.clone(),
+                gens_ref.clone(),
+            );
+            async move {
+                let result =
+                    idempotency::deduplicate(&cache, "buildtx", key, req, move |req| async move {
+                        let value = ClearValue {
+                            qty: req.qty,
+                            flv: req.flv,
+                        };
+                        let (id, contact, warnings) =
+                            contacts::pay(&req.to, value, &bp_gens, &bc, &wallet).await?;
+                        Ok(to_json_value(&BuildTxResponse {
+                            id,
+                            contact,
+                            warnings,
+                        }))
+                    })
+                    .await;
+                let reply = match result {
+                    Ok(response) => {
+                        warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+                    }
+                    Err(e) => wallet_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let wallet_ref = wallet.clone();
+    let invoice_create = warp::post()
+        .and(warp::path!("v1" / "wallet" / "invoice"))
+        .and(warp::body::content_length_limit(4096))
+        .and(warp::body::json())
+        .and_then(move |req: InvoiceRequest| {
+            let wallet = wallet_ref.clone();
+            async move {
+                let value = ClearValue {
+                    qty: req.qty,
+                    flv: req.flv,
+                };
+                let memo = req.memo.unwrap_or_default();
+                let result = invoice::create(value, req.expiration_ms, memo, &wallet).await;
+                let reply = match result {
+                    Ok(invoice) => warp::reply::with_status(
+                        warp::reply::json(&to_json_value(&InvoiceResponse {
+                            invoice: invoice.to_string(),
+                        })),
+                        StatusCode::OK,
+                    ),
+                    Err(e) => wallet_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let (cache_ref, bc_ref, wallet_ref, gens_ref) = (
+        idempotency.clone(),
+        bc.clone(),
+        wallet.clone(),
+        bp_gens.clone(),
+    );
+    let invoice_pay = warp::post()
+        .and(warp::path!("v1" / "wallet" / "invoice" / "pay"))
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(warp::body::content_length_limit(4096))
+        .and(warp::body::json())
+        .and_then(move |key: Option<String>, req: InvoicePayRequest| {
+            let (cache, bc, wallet, bp_gens) = (
+                cache_ref.clone(),
+                bc_ref.clone(),
+                wallet_ref.clone(),
+                gens_ref.clone(),
+            );
+            async move {
+                let result = idempotency::deduplicate(
+                    &cache,
+                    "invoice_pay",
+                    key,
+                    req,
+                    move |req| async move {
+                        let invoice = Invoice::from_string(&req.invoice)
+                            .map_err(WalletError::InvoiceError)?;
+                        let id = invoice::pay(&invoice, &bp_gens, &bc, &wallet).await?;
+                        Ok(to_json_value(&InvoicePayResponse {
+                            id,
+                            qty: invoice.receiver.value.qty,
+                            flv: invoice.receiver.value.flv,
+                            memo: invoice.memo,
+                        }))
+                    },
+                )
+                .await;
+                let reply = match result {
+                    Ok(response) => {
+                        warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+                    }
+                    Err(e) => wallet_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let (cache_ref, bc_ref, wallet_ref, gens_ref) = (
+        idempotency.clone(),
+        bc.clone(),
+        wallet.clone(),
+        bp_gens.clone(),
+    );
+    let vault_create = warp::post()
+        .and(warp::path!("v1" / "wallet" / "vault"))
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(warp::body::content_length_limit(4096))
+        .and(warp::body::json())
+        .and_then(move |key: Option<String>, req: VaultCreateRequest| {
+            let (cache, bc, wallet, bp_gens) = (
+                cache_ref.clone(),
+                bc_ref.clone(),
+                wallet_ref.clone(),
+                gens_ref.clone(),
+            );
+            async move {
+                let result = idempotency::deduplicate(
+                    &cache,
+                    "vault_create",
+                    key,
+                    req,
+                    move |req| async move {
+                        let value = ClearValue {
+                            qty: req.qty,
+                            flv: req.flv,
+                        };
+                        let (id, sequence, vault, utxo) = vault::create(
+                            req.recovery_key,
+                            req.delay_ms,
+                            value,
+                            &bp_gens,
+                            &bc,
+                            &wallet,
+                        )
+                        .await?;
+                        Ok(to_json_value(&VaultCreateResponse {
+                            id,
+                            vault,
+                            sequence,
+                            utxo,
+                        }))
+                    },
+                )
+                .await;
+                let reply = match result {
+                    Ok(response) => {
+                        warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+                    }
+                    Err(e) => wallet_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let wallet_ref = wallet.clone();
+    let vault_recovery_key = warp::post()
+        .and(warp::path!("v1" / "wallet" / "vault" / "recovery_key"))
+        .and_then(move || {
+            let wallet = wallet_ref.clone();
+            async move {
+                let reply = match vault::create_recovery_key(&wallet).await {
+                    Ok((sequence, recovery_key)) => warp::reply::with_status(
+                        warp::reply::json(&to_json_value(&VaultRecoveryKeyResponse {
+                            recovery_key,
+                            sequence,
+                        })),
+                        StatusCode::OK,
+                    ),
+                    Err(e) => wallet_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let (cache_ref, bc_ref, wallet_ref, gens_ref) = (
+        idempotency.clone(),
+        bc.clone(),
+        wallet.clone(),
+        bp_gens.clone(),
+    );
+    let vault_unvault = warp::post()
+        .and(warp::path!("v1" / "wallet" / "vault" / "unvault"))
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(warp::body::content_length_limit(4096))
+        .and(warp::body::json())
+        .and_then(move |key: Option<String>, req: VaultUnvaultRequest| {
+            let (cache, bc, wallet, bp_gens) = (
+                cache_ref.clone(),
+                bc_ref.clone(),
+                wallet_ref.clone(),
+                gens_ref.clone(),
+            );
+            async move {
+                let result = idempotency::deduplicate(
+                    &cache,
+                    "vault_unvault",
+                    key,
+                    req,
+                    move |req| async move {
+                        let (id, utxo) = vault::unvault(
+                            req.vault,
+                            req.sequence,
+                            req.utxo,
+                            req.unlock_time_ms,
+                            &bp_gens,
+                            &bc,
+                            &wallet,
+                        )
+                        .await?;
+                        Ok(to_json_value(&VaultUnvaultResponse { id, utxo }))
+                    },
+                )
+                .await;
+                let reply = match result {
+                    Ok(response) => {
+                        warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+                    }
+                    Err(e) => wallet_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let (cache_ref, bc_ref, wallet_ref, gens_ref) = (
+        idempotency.clone(),
+        bc.clone(),
+        wallet.clone(),
+        bp_gens.clone(),
+    );
+    let vault_withdraw = warp::post()
+        .and(warp::path!("v1" / "wallet" / "vault" / "withdraw"))
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(warp::body::content_length_limit(4096))
+        .and(warp::body::json())
+        .and_then(move |key: Option<String>, req: VaultSpendRequest| {
+            let (cache, bc, wallet, bp_gens) = (
+                cache_ref.clone(),
+                bc_ref.clone(),
+                wallet_ref.clone(),
+                gens_ref.clone(),
+            );
+            async move {
+                let result = idempotency::deduplicate(
+                    &cache,
+                    "vault_withdraw",
+                    key,
+                    req,
+                    move |req| async move {
+                        let id = vault::withdraw(
+                            req.vault,
+                            req.sequence,
+                            req.utxo,
+                            &bp_gens,
+                            &bc,
+                            &wallet,
+                        )
+                        .await?;
+                        Ok(to_json_value(&VaultSpendResponse { id }))
+                    },
+                )
+                .await;
+                let reply = match result {
+                    Ok(response) => {
+                        warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+                    }
+                    Err(e) => wallet_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let (cache_ref, bc_ref, wallet_ref, gens_ref) = (
+        idempotency.clone(),
+        bc.clone(),
+        wallet.clone(),
+        bp_gens.clone(),
+    );
+    let vault_recover = warp::post()
+        .and(warp::path!("v1" / "wallet" / "vault" / "recover"))
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(warp::body::content_length_limit(4096))
+        .and(warp::body::json())
+        .and_then(move |key: Option<String>, req: VaultSpendRequest| {
+            let (cache, bc, wallet, bp_gens) = (
+                cache_ref.clone(),
+                bc_ref.clone(),
+                wallet_ref.clone(),
+                gens_ref.clone(),
+            );
+            async move {
+                let result = idempotency::deduplicate(
+                    &cache,
+                    "vault_recover",
+                    key,
+                    req,
+                    move |req| async move {
+                        let id = vault::recover(
+                            req.vault,
+                            req.sequence,
+                            req.utxo,
+                            &bp_gens,
+                            &bc,
+                            &wallet,
+                        )
+                        .await?;
+                        Ok(to_json_value(&VaultSpendResponse { id }))
+                    },
+                )
+                .await;
+                let reply = match result {
+                    Ok(response) => {
+                        warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+                    }
+                    Err(e) => wallet_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
+    let (token_ref, bc_ref) = (api_token.clone(), bc.clone());
+    let admin_audit = warp::get()
+        .and(warp::path!("v1" / "admin" / "audit"))
+        .and(auth::header())
+        .and_then(move |auth: Option<String>| {
+            let (token, bc) = (token_ref.clone(), bc_ref.clone());
+            async move {
+                let result = match token.check(auth.as_deref()) {
+                    Ok(()) => bc::audit(&bc).await,
+                    Err(e) => Err(e),
+                };
+                let reply = match result {
+                    Ok(audit) => {
+                        let flavors: Vec<_> = audit
+                            .flavors
+                            .iter()
+                            .map(|supply| AuditFlavor {
+                                flavor: hex::encode(supply.flavor.as_bytes()),
+                                issuances: supply.issuances as u64,
+                                retirements: supply.retirements as u64,
+                                burned: supply.burned,
+                                net_qty: hex::encode(supply.net_qty.as_bytes()),
+                            })
+                            .collect();
+                        warp::reply::with_status(
+                            warp::reply::json(&to_json_value(&AuditResponse {
+                                from_height: audit.from_height,
+                                tip_height: audit.tip_height,
+                                utxo_count: audit.utxo_count,
+                                fees: audit.fees,
+                                flavors,
+                            })),
+                            StatusCode::OK,
+                        )
+                    }
+                    Err(e) => audit_error(e),
+                };
+                Ok::<_, std::convert::Infallible>(reply)
+            }
+        });
+
     let not_found = warp::any()
         .map(|| warp::reply::with_status("Not found.", warp::http::StatusCode::NOT_FOUND));
 
-    let routes = echo.or(not_found);
+    let routes = echo
+        .or(identity)
+        .or(mempool)
+        .or(mempool_graph)
+        .or(network_state)
+        .or(network_peers)
+        .or(sync_status)
+        .or(faucet_info)
+        .or(faucet_request)
+        .or(wallet_backup_export)
+        .or(wallet_backup_import)
+        .or(wallet_utxos_export)
+        .or(wallet_utxos_import)
+        .or(wallet_rescan)
+        .or(payment_proof_export)
+        .or(payment_proof_verify)
+        .or(voucher_nonce)
+        .or(voucher_sign)
+        .or(voucher_redeem)
+        .or(contacts_list)
+        .or(contact_set)
+        .or(contact_remove)
+        .or(buildtx)
+        .or(invoice_create)
+        .or(invoice_pay)
+        .or(vault_create)
+        .or(vault_recovery_key)
+        .or(vault_unvault)
+        .or(vault_withdraw)
+        .or(vault_recover)
+        .or(admin_audit)
+        .or(not_found);
 
     eprintln!("API: http://{}", &conf.listen);
     warp::serve(routes).run(conf.listen).await;
 }
+
+fn faucet_disabled() -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(
+        warp::reply::json(&to_json_value(&ErrorResponse {
+            error: "Faucet is disabled".to_string(),
+        })),
+        StatusCode::NOT_FOUND,
+    )
+}
+
+fn faucet_error(err: Error) -> warp::reply::WithStatus<warp::reply::Json> {
+    let status = match err {
+        Error::FaucetRateLimited(_) | Error::FaucetQueueFull => StatusCode::TOO_MANY_REQUESTS,
+        Error::Unauthorized => StatusCode::UNAUTHORIZED,
+        Error::ApiTokenNotSet => StatusCode::FORBIDDEN,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    warp::reply::with_status(
+        warp::reply::json(&to_json_value(&ErrorResponse {
+            error: err.to_string(),
+        })),
+        status,
+    )
+}
+
+fn wallet_error(err: Error) -> warp::reply::WithStatus<warp::reply::Json> {
+    let status = match err {
+        Error::WalletNotInitialized | Error::MnemonicNotFound => StatusCode::NOT_FOUND,
+        Error::WalletAlreadyExists => StatusCode::CONFLICT,
+        Error::InvalidBackup(_)
+        | Error::BlockNotFound(_)
+        | Error::WalletError(_)
+        | Error::InvalidContact(_)
+        | Error::TxRejected(_)
+        | Error::InvalidIdempotencyKey => StatusCode::BAD_REQUEST,
+        Error::ContactNotFound(_) => StatusCode::NOT_FOUND,
+        Error::IdempotencyKeyInUse => StatusCode::CONFLICT,
+        Error::IdempotencyKeyMismatch => StatusCode::UNPROCESSABLE_ENTITY,
+        Error::IdempotencyCacheFull => StatusCode::TOO_MANY_REQUESTS,
+        Error::Unauthorized => StatusCode::UNAUTHORIZED,
+        Error::ApiTokenNotSet => StatusCode::FORBIDDEN,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    warp::reply::with_status(
+        warp::reply::json(&to_json_value(&ErrorResponse {
+            error: err.to_string(),
+        })),
+        status,
+    )
+}
+
+fn contact_info(name: &str, contact: &Contact) -> ContactInfo {
+    ContactInfo {
+        name: name.to_string(),
+        address: contact.address.as_ref().map(|address| address.to_string()),
+        receiver: contact.receiver,
+        payments: contact
+            .payments
+            .iter()
+            .map(|payment| ContactPayment {
+                txid: payment.txid,
+                qty: payment.value.qty,
+                flv: payment.value.flv,
+            })
+            .collect(),
+    }
+}
+
+fn payment_proof_error(err: Error) -> warp::reply::WithStatus<warp::reply::Json> {
+    let status = match err {
+        Error::BlockNotFound(_) => StatusCode::NOT_FOUND,
+        Error::InvalidPaymentProof(_) => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    warp::reply::with_status(
+        warp::reply::json(&to_json_value(&ErrorResponse {
+            error: err.to_string(),
+        })),
+        status,
+    )
+}
+
+fn audit_error(err: Error) -> warp::reply::WithStatus<warp::reply::Json> {
+    let status = match err {
+        Error::BlockNotFound(_) => StatusCode::NOT_FOUND,
+        Error::TxRejected(_) => StatusCode::CONFLICT,
+        Error::Unauthorized => StatusCode::UNAUTHORIZED,
+        Error::ApiTokenNotSet => StatusCode::FORBIDDEN,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    warp::reply::with_status(
+        warp::reply::json(&to_json_value(&ErrorResponse {
+            error: err.to_string(),
+        })),
+        status,
+    )
+}
+
+fn voucher_error(err: Error) -> warp::reply::WithStatus<warp::reply::Json> {
+    let status = match err {
+        Error::WalletNotInitialized | Error::VoucherSessionNotFound => StatusCode::NOT_FOUND,
+        Error::InvalidVoucher
+        | Error::WalletError(_)
+        | Error::TxRejected(_)
+        | Error::InvalidIdempotencyKey => StatusCode::BAD_REQUEST,
+        Error::IdempotencyKeyInUse => StatusCode::CONFLICT,
+        Error::IdempotencyKeyMismatch => StatusCode::UNPROCESSABLE_ENTITY,
+        Error::IdempotencyCacheFull => StatusCode::TOO_MANY_REQUESTS,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    warp::reply::with_status(
+        warp::reply::json(&to_json_value(&ErrorResponse {
+            error: err.to_string(),
+        })),
+        status,
+    )
+}
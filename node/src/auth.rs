@@ -0,0 +1,55 @@
+//! Authorization of the sensitive API endpoints.
+//!
+//! Endpoints that reveal or replace the wallet secrets require the header
+//! `Authorization: Bearer <token>` with the token set in the `SLINGSHOT_API_TOKEN`
+//! environment variable. If the variable is not set, these endpoints are disabled.
+
+use std::sync::Arc;
+use warp::Filter;
+
+use crate::errors::Error;
+
+/// Environment variable that holds the token for the sensitive API endpoints.
+const API_TOKEN_VAR: &'static str = "SLINGSHOT_API_TOKEN";
+
+/// Token that authorizes the requests to the sensitive API endpoints.
+#[derive(Clone)]
+pub struct ApiToken {
+    token: Option<Arc<String>>,
+}
+
+impl ApiToken {
+    /// Reads the token from the environment.
+    /// Empty token is treated as missing.
+    pub fn from_env() -> Self {
+        ApiToken {
+            token: std::env::var(API_TOKEN_VAR)
+                .ok()
+                .filter(|t| !t.is_empty())
+                .map(Arc::new),
+        }
+    }
+
+    /// Checks the value of the `Authorization` header of the request.
+    pub fn check(&self, header: Option<&str>) -> Result<(), Error> {
+        let token = self.token.as_ref().ok_or(Error::ApiTokenNotSet)?;
+        let provided = header
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .ok_or(Error::Unauthorized)?;
+        if constant_time_eq(provided.as_bytes(), token.as_bytes()) {
+            Ok(())
+        } else {
+            Err(Error::Unauthorized)
+        }
+    }
+}
+
+/// Extracts the `Authorization` header of the request, if any.
+pub fn header() -> impl Filter<Extract = (Option<String>,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+}
+
+/// Compares the byte strings without leaking the position of the first difference.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,200 @@
+//! Encrypted backup of the wallet's mnemonic and export of the wallet's UTXOs.
+//!
+//! The backup contains the mnemonic entropy, the version of the derivation scheme
+//! and the address label of the wallet, which is enough to restore
+//! the wallet keys and the node's p2p identity. The wallet's UTXO index is not included
+//! and has to be rebuilt from the blockchain, or imported from the UTXO export.
+//!
+//! The UTXO export contains the confirmed UTXOs of the wallet (contracts, blinding factors,
+//! derivation sequences and utreexo proofs) and the height and ID of the block
+//! at which the proofs were valid, so another node on the same chain can refresh the proofs
+//! up to its tip without rescanning the blocks before that height.
+//!
+//! Format:
+//!
+//! ```ascii
+//! [version] [derivation version] [salt]    [tag]     [encrypted payload]
+//!  1 byte    1 byte               16 bytes  16 bytes  variable
+//!
+//! backup payload (version 2):
+//! [entropy length] [entropy]      [address label]
+//!  1 byte           16/32 bytes    variable
+//!
+//! UTXO export payload (version 3, derivation version is 0):
+//! [height]  [block ID]  [utxos]
+//!  8 bytes   32 bytes    bincode-encoded list
+//! ```
+//!
+//! The payload is encrypted with AES-SIV-PMAC-128 under a key derived from the passphrase
+//! and the salt with scrypt, with the first two bytes authenticated as associated data.
+//! Legacy UTXO exports of version 1 are rejected because they do not identify the block.
+
+use miscreant::{generic_array::GenericArray, Aes128PmacSiv};
+use rand::{CryptoRng, RngCore};
+
+use accounts::AddressLabel;
+use blockchain::BlockID;
+use keytree::{Mnemonic, MnemonicVersion};
+
+use crate::errors::Error;
+use crate::wallet::Utxo;
+
+const BACKUP_VERSION: u8 = 2;
+const UTXO_EXPORT_VERSION: u8 = 3;
+const SALT_LEN: usize = 16;
+const TAG_LEN: usize = 16;
+const HEADER_LEN: usize = 2 + SALT_LEN + TAG_LEN;
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+/// Decrypted contents of the wallet backup.
+#[derive(Clone, Debug)]
+pub struct WalletBackup {
+    /// Mnemonic encoding the root entropy.
+    pub mnemonic: Mnemonic,
+
+    /// Version of the derivation scheme from the mnemonic to the keys.
+    pub version: MnemonicVersion,
+
+    /// Address label of the wallet.
+    pub address_label: AddressLabel,
+}
+
+/// Decrypted contents of the UTXO export.
+#[derive(Clone, Debug)]
+pub struct UtxoExport {
+    /// Height of the block at which the utreexo proofs of the UTXOs are valid.
+    pub height: u64,
+
+    /// ID of the block at which the utreexo proofs of the UTXOs are valid.
+    pub block_id: BlockID,
+
+    /// Confirmed UTXOs of the wallet.
+    pub utxos: Vec<Utxo>,
+}
+
+impl WalletBackup {
+    /// Encrypts the backup with a passphrase.
+    pub fn encrypt<R: RngCore + CryptoRng>(&self, passphrase: &str, rng: &mut R) -> Vec<u8> {
+        let entropy = self.mnemonic.entropy();
+        let mut payload = Vec::with_capacity(1 + entropy.len() + 83);
+        payload.push(entropy.len() as u8);
+        payload.extend_from_slice(&entropy);
+        payload.extend_from_slice(self.address_label.as_str().as_bytes());
+
+        let header = [BACKUP_VERSION, self.version.to_u64() as u8];
+        seal(header, payload, passphrase, rng)
+    }
+
+    /// Decrypts the backup.
+    /// Fails if the passphrase is incorrect or the backup is malformed.
+    pub fn decrypt(bytes: &[u8], passphrase: &str) -> Result<Self, Error> {
+        if bytes.len() < HEADER_LEN || bytes[0] != BACKUP_VERSION {
+            return Err(Error::InvalidBackup("unsupported backup format"));
+        }
+        let version = MnemonicVersion::from_u64(bytes[1] as u64)
+            .ok_or(Error::InvalidBackup("unsupported derivation version"))?;
+        let payload = open(bytes, passphrase)?;
+
+        let entropy_len = *payload
+            .get(0)
+            .ok_or(Error::InvalidBackup("missing entropy"))? as usize;
+        if payload.len() < 1 + entropy_len {
+            return Err(Error::InvalidBackup("missing entropy"));
+        }
+        let mnemonic = Mnemonic::from_entropy(&payload[1..1 + entropy_len])
+            .ok_or(Error::InvalidBackup("invalid entropy"))?;
+        let address_label = String::from_utf8(payload[1 + entropy_len..].to_vec())
+            .ok()
+            .and_then(AddressLabel::new)
+            .ok_or(Error::InvalidBackup("invalid address label"))?;
+
+        Ok(WalletBackup {
+            mnemonic,
+            version,
+            address_label,
+        })
+    }
+}
+
+impl UtxoExport {
+    /// Encrypts the export with a passphrase.
+    pub fn encrypt<R: RngCore + CryptoRng>(&self, passphrase: &str, rng: &mut R) -> Vec<u8> {
+        let mut payload = self.height.to_le_bytes().to_vec();
+        payload.extend_from_slice(&self.block_id.0);
+        bincode::serialize_into(&mut payload, &self.utxos)
+            .expect("serialization into a vector never fails");
+        seal([UTXO_EXPORT_VERSION, 0], payload, passphrase, rng)
+    }
+
+    /// Decrypts the export.
+    /// Fails if the passphrase is incorrect or the export is malformed.
+    pub fn decrypt(bytes: &[u8], passphrase: &str) -> Result<Self, Error> {
+        if bytes.len() < HEADER_LEN || bytes[0] != UTXO_EXPORT_VERSION || bytes[1] != 0 {
+            return Err(Error::InvalidBackup("unsupported export format"));
+        }
+        let payload = open(bytes, passphrase)?;
+        if payload.len() < 8 + 32 {
+            return Err(Error::InvalidBackup("missing block"));
+        }
+        let mut height = [0u8; 8];
+        height.copy_from_slice(&payload[..8]);
+        let mut block_id = [0u8; 32];
+        block_id.copy_from_slice(&payload[8..8 + 32]);
+        let utxos = bincode::deserialize(&payload[8 + 32..])
+            .map_err(|_| Error::InvalidBackup("invalid utxos"))?;
+        Ok(UtxoExport {
+            height: u64::from_le_bytes(height),
+            block_id: BlockID(block_id),
+            utxos,
+        })
+    }
+}
+
+/// Encrypts the payload, authenticating the header.
+fn seal<R: RngCore + CryptoRng>(
+    header: [u8; 2],
+    mut payload: Vec<u8>,
+    passphrase: &str,
+    rng: &mut R,
+) -> Vec<u8> {
+    let mut salt = [0u8; SALT_LEN];
+    rng.fill_bytes(&mut salt);
+    let tag = Aes128PmacSiv::new(GenericArray::clone_from_slice(&passphrase_key(
+        passphrase, &salt,
+    )))
+    .encrypt_in_place_detached(&[&header[..]], &mut payload)
+    .expect("never fails because we have just one header");
+
+    let mut buf = Vec::with_capacity(HEADER_LEN + payload.len());
+    buf.extend_from_slice(&header);
+    buf.extend_from_slice(&salt);
+    buf.extend_from_slice(tag.as_slice());
+    buf.extend_from_slice(&payload);
+    buf
+}
+
+/// Decrypts the payload. The length of the bytes must be checked by the caller.
+fn open(bytes: &[u8], passphrase: &str) -> Result<Vec<u8>, Error> {
+    let salt = &bytes[2..2 + SALT_LEN];
+    let tag = GenericArray::clone_from_slice(&bytes[2 + SALT_LEN..HEADER_LEN]);
+    let mut payload = bytes[HEADER_LEN..].to_vec();
+
+    Aes128PmacSiv::new(GenericArray::clone_from_slice(&passphrase_key(
+        passphrase, salt,
+    )))
+    .decrypt_in_place_detached(&[&bytes[..2]], &mut payload, &tag)
+    .map_err(|_| Error::InvalidBackup("incorrect passphrase"))?;
+    Ok(payload)
+}
+
+/// Derives the symmetric key for encrypting the backup from a passphrase.
+fn passphrase_key(passphrase: &str, salt: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    let params = scrypt::ScryptParams::new(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)
+        .expect("never fails because the parameters are valid");
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key)
+        .expect("never fails because the key length is valid");
+    key
+}
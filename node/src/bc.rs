@@ -1,25 +1,45 @@
+use std::fmt;
 use std::fs::{self, File};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
+use async_trait::async_trait;
 use tokio::io;
 use tokio::prelude::*;
-use tokio::sync::broadcast;
 use tokio::sync::RwLock;
 use tokio::task;
+use tokio::time;
 
-use curve25519_dalek::scalar::Scalar;
 use rand::thread_rng;
-
-use blockchain::{self, BlockchainState};
-use p2p::{cybershake, PeerID};
-
-use crate::config::Config;
+use serde::{Deserialize, Serialize};
+
+use blockchain::{
+    self, Block, BlockHeader, BlockTx, BlockchainProtocol, BlockchainState, Checkpoint,
+    ConsensusDriver, Delegate, Mempool, SingleSigner, SupplyAudit, SyncStatus, VerifiedBlock,
+};
+use keytree::Xprv;
+use p2p::{KeyProtection, NodeIdentity, PeerID};
+use starsig::{Signature, SigningKey, VerificationKey};
+use zkvm::bulletproofs::BulletproofGens;
+use zkvm::TxID;
+
+use crate::config::{Config, KeyStorage};
 use crate::errors::Error;
+use crate::events::{BlockEvent, EventBus, MempoolEvent, PeerEvent};
 
 const BC_STATE_FILENAME: &'static str = "blockchain_state";
 
+/// Environment variable that holds the passphrase for the node's identity key.
+const PEER_KEY_PASSPHRASE_VAR: &'static str = "SLINGSHOT_PEER_KEY_PASSPHRASE";
+
+/// Service name of the identity key's encryption key in the OS keyring.
+const PEER_KEY_KEYRING_SERVICE: &'static str = "slingshot-node";
+
+/// Interval between the synchronizations with the peers.
+const SYNC_INTERVAL_SECS: u64 = 1;
+
 /// Interface for initializing and launching blockchain state machine.
 pub struct Blockchain;
 
@@ -27,26 +47,59 @@ pub struct Blockchain;
 pub struct BlockchainIdle {
     config: Config,
     state: Option<BlockchainState>,
+    network: Option<NetworkSignatures>,
 }
 
-#[derive(Debug)]
+/// Running blockchain, synchronized with the peers by the blockchain protocol.
 pub struct BlockchainRunning {
+    /// Sync protocol with the storage of the blockchain
+    protocol: BlockchainProtocol<NodeDelegate>,
+}
+
+/// Storage of the blockchain and the connection to the peers used by the protocol.
+/// Peers are addressed by their authenticated identity keys, so the per-peer state
+/// of the protocol survives reconnects and address changes.
+pub struct NodeDelegate {
     /// Configuration
     config: Config,
 
-    /// Sender end of the notification channel
-    notifications_sender: broadcast::Sender<BlockchainEvent>,
+    /// Peer ID of this node
+    peer_id: PeerID,
+
+    /// Handle to the p2p stack
+    p2p: p2p::NodeHandle<blockchain::Message>,
+
+    /// Current blockchain state
+    state: BlockchainState,
+
+    /// Network key and the signatures over the tip and the finalized checkpoint
+    network: NetworkSignatures,
+
+    /// Number of transactions in the blocks applied by this node, saved next to the blockchain state
+    tx_count: u64,
+
+    /// Event bus shared with the API, UI and wallet
+    events: EventBus,
 }
 
-/// Reference to the Blockchain instance
-pub type BlockchainRef = Arc<RwLock<BlockchainRunning>>;
+/// Network key of the blockchain and the signatures over the current tip
+/// and the finalized checkpoint, stored next to the blockchain state.
+#[derive(Clone, Serialize, Deserialize)]
+struct NetworkSignatures {
+    network_pubkey: VerificationKey,
+    tip_signature: Signature,
+    finality: Option<(Checkpoint, Signature)>,
+}
 
-/// Receiver of the blockchain events.
-pub type BlockchainEventReceiver = broadcast::Receiver<BlockchainEvent>;
+/// Block stored with its network signature, so it can be served to the peers.
+#[derive(Serialize, Deserialize)]
+struct StoredBlock {
+    block: VerifiedBlock,
+    signature: Signature,
+}
 
-/// Type for all events about the BC state into the UI.
-#[derive(Clone, Debug)]
-pub enum BlockchainEvent {}
+/// Reference to the Blockchain instance
+pub type BlockchainRef = Arc<RwLock<BlockchainRunning>>;
 
 impl Blockchain {
     /// Sets up a blockchain instance, initialized or not.
@@ -57,9 +110,16 @@ impl Blockchain {
         } else {
             None
         };
+        let path = config.network_filepath();
+        let maybe_network = if path.exists() {
+            Some(bincode::deserialize_from(File::open(&path)?)?)
+        } else {
+            None
+        };
         Ok(BlockchainIdle {
             config,
             state: maybe_state,
+            network: maybe_network,
         })
     }
 }
@@ -70,8 +130,8 @@ impl BlockchainIdle {
         self.state.is_some()
     }
 
-    /// Initializes blockchain
-    pub fn init(mut self, state: BlockchainState) -> Result<Self, Error> {
+    /// Initializes blockchain with the initial block signed by the network key.
+    pub fn init(mut self, state: BlockchainState, network_key: SigningKey) -> Result<Self, Error> {
         if self.is_initialized() {
             return Err(Error::BlockchainAlreadyExists);
         }
@@ -80,30 +140,49 @@ impl BlockchainIdle {
             fs::create_dir_all(folder)?;
         }
         bincode::serialize_into(File::create(path)?, &state)?;
+        bincode::serialize_into(File::create(self.config.genesis_state_filepath())?, &state)?;
+
+        let network = NetworkSignatures {
+            network_pubkey: VerificationKey::from_secret(&network_key),
+            tip_signature: SingleSigner::new(network_key)
+                .commit(&state.tip)
+                .expect("Initial block is above the zero height."),
+            finality: None,
+        };
+        bincode::serialize_into(File::create(self.config.network_filepath())?, &network)?;
 
-        // TODO: store the newly generated p2p privkey if it does not exist.
+        // Store the newly generated p2p privkey if it does not exist.
+        self.load_identity()?;
 
         self.state = Some(state);
+        self.network = Some(network);
         Ok(self)
     }
 
     /// Launches the blockchain p2p stack and returns the communication reference to it.
     pub async fn launch(self) -> Result<BlockchainRef, Error> {
-        // TODO: make this channel capacity a config option
-        let (notifications_sender, _recv) = broadcast::channel(1000);
+        let events = EventBus::new(self.config.data.events.queue_size);
 
         // Launch p2p stack
 
-        // TBD: load the peer privkey from disk instead of picking a random one.
-        let host_privkey = cybershake::PrivateKey::from(Scalar::random(&mut thread_rng()));
+        let identity = self.load_identity()?;
+        let peer_id = identity.peer_id();
+        let state = self.state.ok_or(Error::BlockchainNotInitialized)?;
+        let network = self.network.ok_or(Error::NetworkKeyNotFound)?;
 
         let (node, mut p2p_channel) = p2p::Node::<blockchain::Message>::spawn(
-            host_privkey,
+            *identity.private_key(),
             p2p::NodeConfig {
                 listen_addr: self.config.data.p2p.listen_addr,
                 inbound_limit: self.config.data.p2p.inbound_limit,
                 outbound_limit: self.config.data.p2p.outbound_limit,
                 heartbeat_interval_sec: self.config.data.p2p.heartbeat_interval_sec,
+                ping_interval_sec: self.config.data.p2p.ping_interval_sec,
+                ping_timeout_sec: self.config.data.p2p.ping_timeout_sec,
+                proxy: self.config.data.p2p.proxy.map(|addr| p2p::ProxyConfig {
+                    addr,
+                    isolate_streams: self.config.data.p2p.proxy_isolate_streams,
+                }),
             },
         )
         .await?;
@@ -114,93 +193,467 @@ impl BlockchainIdle {
             node.id()
         );
 
+        let onion_service = match self.config.data.p2p.tor_control {
+            Some(control_addr) => {
+                let service = p2p::OnionService::publish(
+                    control_addr,
+                    self.config.data.p2p.tor_control_password.as_deref(),
+                    node.socket_address().port(),
+                    node.socket_address(),
+                )
+                .await?;
+                println!(
+                    "Onion service: {}:{}",
+                    service.hostname(),
+                    node.socket_address().port()
+                );
+                Some(service)
+            }
+            None => None,
+        };
+
         // Handle to a shared blockchain state machine instance.
-        let bc = Arc::new(RwLock::new(BlockchainRunning {
+        let mempool = Mempool::new(state.clone(), state.tip.timestamp_ms)
+            .set_max_size(self.config.data.blockchain.mempool_max_size)
+            .set_min_relay_feerate(self.config.data.blockchain.mempool_min_feerate);
+        let network_pubkey = network.network_pubkey;
+        let tx_count = load_tx_count(&self.config, &state)?;
+        let delegate = NodeDelegate {
             config: self.config,
-            notifications_sender,
+            peer_id,
+            p2p: node,
+            state,
+            network,
+            tx_count,
+            events: events.clone(),
+        };
+        let bc = Arc::new(RwLock::new(BlockchainRunning {
+            protocol: BlockchainProtocol::new(network_pubkey, delegate).set_mempool(mempool),
         }));
 
-        let notifications_loop = {
-            task::spawn_local(async move {
-                while let Some(notif) = p2p_channel.recv().await {
-                    match notif {
-                        p2p::NodeNotification::PeerAdded(pid) => {
-                            println!("\n=>    Peer connected: {}", pid);
-                        }
-                        p2p::NodeNotification::PeerDisconnected(pid) => {
-                            println!("\n=> Peer disconnected: {}", pid)
-                        }
-                        p2p::NodeNotification::MessageReceived(pid, msg) => {
-                            println!("\n=> Received: `{:?}` from {}", &msg, pid)
-                        }
-                        p2p::NodeNotification::InboundConnectionFailure(err) => {
-                            println!("\n=> Inbound connection failure: {:?}", err)
-                        }
-                        p2p::NodeNotification::OutboundConnectionFailure(err) => {
-                            println!("\n=> Outbound connection failure: {:?}", err)
-                        }
-                        p2p::NodeNotification::Shutdown => {
-                            println!("\n=> Node did shutdown.");
-                            break;
+        let bc_ref = bc.clone();
+        task::spawn(async move {
+            let mut interval = time::interval(Duration::from_secs(SYNC_INTERVAL_SECS));
+            loop {
+                interval.tick().await;
+                bc_ref.write().await.protocol.synchronize().await;
+            }
+        });
+
+        let bc_ref = bc.clone();
+        task::spawn(async move {
+            // Tor removes the onion service when the control connection is closed.
+            let _onion_service = onion_service;
+            while let Some(notif) = p2p_channel.recv().await {
+                match notif {
+                    p2p::NodeNotification::PeerAdded(pid) => {
+                        println!("\n=>    Peer connected: {}", pid);
+                        events.publish(PeerEvent::PeerConnected {
+                            peer_id: pid.to_string(),
+                        });
+                        bc_ref.write().await.protocol.peer_connected(pid).await;
+                    }
+                    p2p::NodeNotification::PeerDisconnected(pid) => {
+                        println!("\n=> Peer disconnected: {}", pid);
+                        events.publish(PeerEvent::PeerDisconnected {
+                            peer_id: pid.to_string(),
+                        });
+                        bc_ref.write().await.protocol.peer_disconnected(pid).await;
+                    }
+                    p2p::NodeNotification::MessageReceived(pid, msg) => {
+                        let result = bc_ref
+                            .write()
+                            .await
+                            .protocol
+                            .process_message(pid, msg)
+                            .await;
+                        if let Err(e) = result {
+                            println!("\n=> Failed to process a message from {}: {}", pid, e);
                         }
                     }
+                    p2p::NodeNotification::InboundConnectionFailure(err) => {
+                        println!("\n=> Inbound connection failure: {:?}", err)
+                    }
+                    p2p::NodeNotification::OutboundConnectionFailure(err) => {
+                        println!("\n=> Outbound connection failure: {:?}", err)
+                    }
+                    p2p::NodeNotification::Shutdown => {
+                        println!("\n=> Node did shutdown.");
+                        break;
+                    }
                 }
-                Result::<(), Error>::Ok(())
-            })
-        };
-
-        notifications_loop.await.expect("panic on JoinError")?;
+            }
+        });
 
         Ok(bc)
     }
+
+    /// Loads the identity key of the node, or generates a new one on first run.
+    fn load_identity(&self) -> Result<NodeIdentity, Error> {
+        load_identity(&self.config)
+    }
+}
+
+/// Saves the identity key of the node, replacing the existing one.
+pub fn save_identity(config: &Config, identity: &NodeIdentity) -> Result<(), Error> {
+    identity.save(
+        config.p2p_key_path(),
+        &key_protection(config),
+        &mut thread_rng(),
+    )?;
+    Ok(())
+}
+
+/// Loads the stored block with its network signature at a given height.
+/// Returns `None` if the block is not stored or was pruned.
+fn load_signed_block(config: &Config, height: u64) -> Result<Option<StoredBlock>, Error> {
+    let path = config.block_filepath(height);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(bincode::deserialize_from(File::open(path)?)?))
+}
+
+/// Loads the number of transactions in the blocks applied by the node.
+/// If it was not saved yet (e.g. by an older version of the node),
+/// counts the transactions in the stored blocks up to the tip: pruned blocks are not counted.
+fn load_tx_count(config: &Config, state: &BlockchainState) -> Result<u64, Error> {
+    let path = config.tx_count_filepath();
+    if path.exists() {
+        return Ok(bincode::deserialize_from(File::open(path)?)?);
+    }
+    let mut tx_count = 0;
+    for height in 1..=state.tip.height {
+        if let Some(block) = load_block(config, height)? {
+            tx_count += block.verified_txs.len() as u64;
+        }
+    }
+    Ok(tx_count)
+}
+
+/// Loads the stored block at a given height.
+/// Returns `None` if the block is not stored or was pruned.
+fn load_block(config: &Config, height: u64) -> Result<Option<VerifiedBlock>, Error> {
+    Ok(load_signed_block(config, height)?.map(|stored| stored.block))
+}
+
+/// Audits the current state against the transaction logs of the stored blocks
+/// since the initial state: checks the number of utxos and sums up the issued and retired
+/// quantities per flavor.
+/// The blockchain is locked only to take a snapshot of the state, which is then audited
+/// while the node keeps applying new blocks.
+/// Fails if any block is not available (e.g. in pruned mode).
+pub async fn audit(bc: &BlockchainRef) -> Result<SupplyAudit, Error> {
+    let (config, state) = {
+        let bc = bc.read().await;
+        let delegate = bc.protocol.delegate();
+        (delegate.config.clone(), delegate.state.clone())
+    };
+    // Reading the blocks and replaying them is slow, so it should not block the runtime.
+    task::spawn_blocking(move || audit_blocks(&config, &state))
+        .await
+        .expect("panic on JoinError")
+}
+
+/// Audits the state against the stored blocks since the initial state.
+fn audit_blocks(config: &Config, state: &BlockchainState) -> Result<SupplyAudit, Error> {
+    let path = config.genesis_state_filepath();
+    if !path.exists() {
+        return Err(Error::BlockNotFound(1));
+    }
+    let genesis: BlockchainState = bincode::deserialize_from(File::open(path)?)?;
+
+    // Load the blocks one by one, stopping at the first missing block.
+    let mut load_error = None;
+    let blocks =
+        (genesis.tip.height + 1..=state.tip.height).scan(&mut load_error, |load_error, height| {
+            match load_block(config, height) {
+                Ok(Some(block)) => Some(block),
+                Ok(None) => {
+                    **load_error = Some(Error::BlockNotFound(height));
+                    None
+                }
+                Err(e) => {
+                    **load_error = Some(e);
+                    None
+                }
+            }
+        });
+    let result = state.audit(&genesis, blocks);
+    if let Some(e) = load_error {
+        return Err(e);
+    }
+    Ok(result?)
+}
+
+/// Derives the network signing key of a new blockchain from the wallet's root key.
+pub fn network_signing_key(xprv: &Xprv) -> SigningKey {
+    xprv.derive_key(|t| t.append_message(b"purpose", b"network"))
+}
+
+/// Loads the identity key of the node, or generates a new one on first run.
+pub fn load_identity(config: &Config) -> Result<NodeIdentity, Error> {
+    let identity = NodeIdentity::load_or_generate(
+        config.p2p_key_path(),
+        &key_protection(config),
+        &mut thread_rng(),
+    )?;
+    Ok(identity)
+}
+
+/// Returns the protection of the identity key requested by the user:
+/// the passphrase if it is set, or the configured key storage.
+fn key_protection(config: &Config) -> KeyProtection {
+    if let Ok(passphrase) = std::env::var(PEER_KEY_PASSPHRASE_VAR) {
+        return KeyProtection::Passphrase(passphrase);
+    }
+    match config.data.p2p.key_storage {
+        KeyStorage::Keyring => KeyProtection::Keyring {
+            service: PEER_KEY_KEYRING_SERVICE.to_string(),
+            user: config.p2p_key_path().display().to_string(),
+        },
+        KeyStorage::Plaintext => {
+            eprintln!(
+                "Warning: the identity key is stored in the clear at {}. Set {} or use the OS keyring to encrypt it.",
+                config.p2p_key_path().display(),
+                PEER_KEY_PASSPHRASE_VAR
+            );
+            KeyProtection::Plaintext
+        }
+    }
 }
 
 impl BlockchainRunning {
-    /// Creates a subscription for notifications and returns a receiving end of a broadcast channel.
-    pub async fn subscribe(&self) -> BlockchainEventReceiver {
-        self.notifications_sender.subscribe()
+    /// Returns the peer ID of this node.
+    pub fn peer_id(&self) -> PeerID {
+        self.protocol.delegate().peer_id
+    }
+
+    /// Returns the list of connected peers with their latencies.
+    pub async fn peers(&self) -> Vec<p2p::PeerInfo> {
+        self.protocol.delegate().p2p.clone().list_peers().await
+    }
+
+    /// Returns the current blockchain state.
+    pub fn state(&self) -> &BlockchainState {
+        &self.protocol.delegate().state
+    }
+
+    /// Returns the pool of unconfirmed transactions.
+    pub fn mempool(&self) -> &Mempool {
+        self.protocol.mempool()
+    }
+
+    /// Verifies the transaction and adds it to the mempool.
+    /// Publishes `MempoolEvent::DoubleSpendDetected` if the transaction is rejected
+    /// because it spends a contract already spent in the mempool.
+    pub fn submit_tx(
+        &mut self,
+        block_tx: BlockTx,
+        bp_gens: &BulletproofGens,
+    ) -> Result<TxID, Error> {
+        let events = self.protocol.delegate().events.clone();
+        let mempool = self.protocol.mempool_mut();
+        // The subscription is dropped at the end of the call and pruned by the next event.
+        let mempool_events = mempool.subscribe();
+        let result = mempool.append(block_tx, bp_gens).map(|entry| entry.txid());
+        for event in mempool_events.try_iter() {
+            if let blockchain::MempoolEvent::DoubleSpendDetected {
+                txid,
+                conflicting_txid,
+                contract_id,
+            } = event
+            {
+                events.publish(MempoolEvent::DoubleSpendDetected {
+                    id: txid,
+                    conflicting_id: conflicting_txid,
+                    contract_id,
+                });
+            }
+        }
+        let txid = result?;
+        events.publish(MempoolEvent::TxAdded {
+            id: txid,
+            mempool_len: mempool.len(),
+        });
+        Ok(txid)
+    }
+
+    /// Returns the progress of the initial block download
+    /// towards the highest tip announced by the peers.
+    pub fn sync_status(&self) -> SyncStatus {
+        self.protocol.sync_status()
+    }
+
+    /// Returns the number of transactions in the blocks applied by this node.
+    pub fn tx_count(&self) -> u64 {
+        self.protocol.delegate().tx_count
+    }
+
+    /// Returns the height of the latest block whose body is discarded in pruned mode,
+    /// or 0 if the node keeps all the blocks.
+    pub fn pruned_height(&self) -> u64 {
+        self.protocol.delegate().pruned_height()
+    }
+
+    /// Loads the stored block at a given height.
+    /// Returns `None` if the block is not stored or was pruned.
+    pub fn load_block(&self, height: u64) -> Result<Option<VerifiedBlock>, Error> {
+        load_block(&self.protocol.delegate().config, height)
+    }
+
+    /// Returns the event bus of the node.
+    /// Clone it to publish or subscribe without holding the lock on the blockchain.
+    pub fn events(&self) -> &EventBus {
+        &self.protocol.delegate().events
     }
 
     /// Stops the blockchain stack
     pub async fn stop(&self) {}
 }
 
-/*
-impl protocol::Delegate for BlockchainRunning {
-    type PeerIdentifier = p2p::PeerID;
+impl NodeDelegate {
+    /// Applies the verified block on top of the current tip: updates and saves the state
+    /// with the signature of the new tip, and stores the block body.
+    /// Blocks must be committed in the order of their heights.
+    fn commit_block(
+        &mut self,
+        verified_block: VerifiedBlock,
+        signature: Signature,
+    ) -> Result<(), Error> {
+        if verified_block.header.height != self.state.tip.height + 1 {
+            return Err(blockchain::BlockchainError::BlockNotRelevant(
+                verified_block.header.height,
+            )
+            .into());
+        }
+        self.state = verified_block.blockchain_state();
+        self.tx_count += verified_block.verified_txs.len() as u64;
+        bincode::serialize_into(
+            File::create(self.config.blockchain_state_filepath())?,
+            &self.state,
+        )?;
+        bincode::serialize_into(
+            File::create(self.config.tx_count_filepath())?,
+            &self.tx_count,
+        )?;
+        self.network.tip_signature = signature;
+        self.save_network()?;
+        self.store_block(StoredBlock {
+            block: verified_block,
+            signature,
+        })
+    }
 
-    /// ID of our node.
-    fn self_id(&self) -> Self::PeerIdentifier;
+    /// Stores the block body, so it can be served to the peers and replayed by the wallet.
+    /// In pruned mode, discards the body of the block that falls out of the window.
+    fn store_block(&self, stored: StoredBlock) -> Result<(), Error> {
+        let block = &stored.block;
+        let path = self.config.block_filepath(block.header.height);
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder)?;
+        }
+        bincode::serialize_into(File::create(path)?, &stored)?;
+        self.events.publish(BlockEvent::BlockStored {
+            height: block.header.height,
+            id: block.header.id().to_string(),
+            tx_count: block.verified_txs.len(),
+        });
+
+        if let Some(keep) = self.config.data.blockchain.keep_blocks {
+            if block.header.height > keep {
+                let height = block.header.height - keep;
+                let pruned = self.config.block_filepath(height);
+                if pruned.exists() {
+                    fs::remove_file(pruned)?;
+                    self.events.publish(BlockEvent::BlockPruned { height });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves the network key and signatures next to the blockchain state.
+    fn save_network(&self) -> Result<(), Error> {
+        bincode::serialize_into(File::create(self.config.network_filepath())?, &self.network)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Delegate for NodeDelegate {
+    type PeerIdentifier = PeerID;
+
+    fn self_id(&self) -> PeerID {
+        self.peer_id
+    }
 
-    /// Send a message to a given peer.
-    async fn send(&mut self, peer: Self::PeerIdentifier, message: Message);
+    async fn send(&mut self, peer: PeerID, message: blockchain::Message) {
+        self.p2p.send_to_peer(peer, message).await
+    }
 
-    /// Returns current height of the chain.
-    /// Default implementation calls `tip().0.height`.
-    fn tip_height(&self) -> u64 {
-        self.tip().0.height
+    async fn ban_peer(&mut self, peer: PeerID) {
+        println!(
+            "\n=> Banning the peer {} for violating the consensus rules",
+            peer
+        );
+        self.p2p.ban_peer(peer).await
     }
 
-    /// Returns ID of the current tip.
-    fn tip_id(&self) -> BlockID {
-        self.tip().0.id()
+    fn tip(&self) -> (BlockHeader, Signature) {
+        (self.state.tip.clone(), self.network.tip_signature)
     }
 
-    /// Returns the signed tip of the blockchain
-    fn tip(&self) -> (BlockHeader, Signature);
+    fn block_at_height(&self, height: u64) -> Option<Block> {
+        match load_signed_block(&self.config, height) {
+            Ok(stored) => stored.map(|stored| Block::from_verified(stored.block, stored.signature)),
+            Err(e) => {
+                eprintln!("Failed to load the block at height {}: {}", height, e);
+                None
+            }
+        }
+    }
 
-    /// Returns a block at a given height
-    fn block_at_height(&self, height: u64) -> Option<Block>;
+    fn pruned_height(&self) -> u64 {
+        self.config
+            .data
+            .blockchain
+            .keep_blocks
+            .map(|keep| self.state.tip.height.saturating_sub(keep))
+            .unwrap_or(0)
+    }
 
-    /// Blockchain state
     fn blockchain_state(&self) -> &BlockchainState {
-
+        &self.state
     }
 
-    /// Stores a new block and an updated state.
-    /// Guaranteed to be called monotonically for blocks with height=2, then 3, etc.
     fn store_block(&mut self, verified_block: VerifiedBlock, signature: Signature) {
+        if let Err(e) = self.commit_block(verified_block, signature) {
+            eprintln!("Failed to commit the block: {}", e);
+        }
+    }
+
+    fn finalized_checkpoint(&self) -> Option<(Checkpoint, Signature)> {
+        self.network.finality
+    }
+
+    fn store_finalized_checkpoint(&mut self, checkpoint: Checkpoint, signature: Signature) {
+        self.network.finality = Some((checkpoint, signature));
+        if let Err(e) = self.save_network() {
+            eprintln!("Failed to store the finalized checkpoint: {}", e);
+        }
+    }
+}
 
+impl fmt::Debug for BlockchainRunning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let delegate = self.protocol.delegate();
+        f.debug_struct("BlockchainRunning")
+            .field("config", &delegate.config)
+            .field("peer_id", &delegate.peer_id)
+            .field("tip_height", &delegate.state.tip.height)
+            .field("mempool_len", &self.protocol.mempool().len())
+            .field("tx_count", &delegate.tx_count)
+            .finish()
     }
 }
-*/
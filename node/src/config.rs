@@ -8,6 +8,10 @@ use crate::errors::Error;
 /// Default config location
 pub const DEFAULT_CONFIG_LOCATION: &'static str = "~/.slingshot/config.toml";
 const BC_STATE_FILENAME: &'static str = "blockchain_state";
+const BC_GENESIS_STATE_FILENAME: &'static str = "genesis_state";
+const BC_NETWORK_FILENAME: &'static str = "network";
+const BC_TX_COUNT_FILENAME: &'static str = "tx_count";
+const BC_BLOCKS_DIRNAME: &'static str = "blocks";
 
 #[derive(Clone, Debug)]
 pub struct Config {
@@ -39,6 +43,18 @@ pub struct ConfigData {
     /// Wallet storage location
     #[serde(default)]
     pub wallet: Wallet,
+
+    /// Delegated proving service options
+    #[serde(default)]
+    pub prover: Prover,
+
+    /// Test asset faucet options
+    #[serde(default)]
+    pub faucet: Faucet,
+
+    /// Event bus options
+    #[serde(default)]
+    pub events: Events,
 }
 
 /// UI configuration options
@@ -63,6 +79,14 @@ pub struct API {
     /// Disable API by setting api.disabled=true. Default is false (enabled).
     #[serde(default)]
     pub disabled: bool,
+
+    /// Time for which the responses to the requests with an `Idempotency-Key` are kept.
+    #[serde(default = "API::default_idempotency_ttl_sec")]
+    pub idempotency_ttl_sec: u64,
+
+    /// Maximum number of the cached responses to the requests with an `Idempotency-Key`.
+    #[serde(default = "API::default_idempotency_capacity")]
+    pub idempotency_capacity: usize,
 }
 
 /// P2P configuration options
@@ -72,10 +96,16 @@ pub struct P2P {
     #[serde(default = "P2P::default_listen_addr")]
     pub listen_addr: SocketAddr,
 
-    /// Listening address for the P2P webserver.
+    /// Location of the node's identity key.
+    /// (if relative, resolved based on the config file location)
     #[serde(default = "P2P::default_key_path")]
     pub key_path: PathBuf,
 
+    /// Protection of the node's identity key at rest,
+    /// unless the `SLINGSHOT_PEER_KEY_PASSPHRASE` environment variable is set.
+    #[serde(default = "P2P::default_key_storage")]
+    pub key_storage: KeyStorage,
+
     /// List of initial peers.
     #[serde(default)]
     pub peers: Vec<SocketAddr>,
@@ -91,6 +121,40 @@ pub struct P2P {
     /// Ping frequency of the other nodes.
     #[serde(default = "P2P::default_heartbeat_interval_sec")]
     pub heartbeat_interval_sec: u64,
+
+    /// Interval between the liveness pings sent to each peer. Must be positive.
+    #[serde(default = "P2P::default_ping_interval_sec")]
+    pub ping_interval_sec: u64,
+
+    /// Peers that do not respond to a ping within this window are disconnected.
+    #[serde(default = "P2P::default_ping_timeout_sec")]
+    pub ping_timeout_sec: u64,
+
+    /// SOCKS5 proxy for the outbound connections (e.g. Tor at 127.0.0.1:9050).
+    #[serde(default)]
+    pub proxy: Option<SocketAddr>,
+
+    /// Dial each peer through a separate proxy circuit.
+    #[serde(default = "P2P::default_proxy_isolate_streams")]
+    pub proxy_isolate_streams: bool,
+
+    /// Tor control port for publishing the listening address as an onion service.
+    #[serde(default)]
+    pub tor_control: Option<SocketAddr>,
+
+    /// Password for the Tor control port, if it requires one.
+    #[serde(default)]
+    pub tor_control_password: Option<String>,
+}
+
+/// Storage of the node's identity key.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyStorage {
+    /// The key is encrypted with a random key stored in the OS keyring.
+    Keyring,
+    /// The key is stored in the clear and is protected only by the permissions of the file.
+    Plaintext,
 }
 
 /// P2P configuration options
@@ -100,13 +164,20 @@ pub struct Blockchain {
     #[serde(default = "Blockchain::default_storage_path")]
     pub storage_path: PathBuf,
 
-    /// Maximum size of the mempool in bytes.
+    /// Maximum total weight of the mempool transactions.
     #[serde(default = "Blockchain::default_mempool_max_size")]
     pub mempool_max_size: usize,
 
-    /// Minimum feerate in units/byte.
+    /// Minimum relay feerate in units/weight unit.
+    /// The effective minimum rises above it when the full mempool evicts transactions.
     #[serde(default)]
-    pub mempool_min_feerate: f32,
+    pub mempool_min_feerate: u64,
+
+    /// Number of recent blocks whose bodies are kept in pruned mode.
+    /// Headers, utreexo state and wallet index are kept for all blocks.
+    /// If not set, all blocks are kept.
+    #[serde(default)]
+    pub keep_blocks: Option<u64>,
 }
 
 /// P2P configuration options
@@ -117,6 +188,86 @@ pub struct Wallet {
     pub storage_path: PathBuf,
 }
 
+/// Delegated proving service configuration options
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Prover {
+    /// Listening address for the proving service.
+    #[serde(default = "Prover::default_listen_addr")]
+    pub listen: SocketAddr,
+
+    /// Enable the proving service by setting prover.disabled=false. Default is true (disabled).
+    #[serde(default = "Prover::default_disabled")]
+    pub disabled: bool,
+
+    /// Peer IDs of the clients allowed to use the service. If empty, any client is allowed.
+    #[serde(default)]
+    pub allowed_clients: Vec<String>,
+
+    /// Maximum size of the witness bundle in bytes.
+    #[serde(default = "Prover::default_max_bundle_size")]
+    pub max_bundle_size: usize,
+
+    /// Number of bulletproofs generators, which limits the size of the transactions.
+    #[serde(default = "Prover::default_gens_capacity")]
+    pub gens_capacity: usize,
+}
+
+/// Faucet configuration options
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Faucet {
+    /// Enable the faucet by setting faucet.disabled=false. Default is true (disabled).
+    /// The faucet is served by the API server.
+    #[serde(default = "Faucet::default_disabled")]
+    pub disabled: bool,
+
+    /// Alias of the test asset issued by the node's wallet.
+    #[serde(default = "Faucet::default_asset_alias")]
+    pub asset_alias: String,
+
+    /// Quantity of the asset issued to each receiver.
+    #[serde(default = "Faucet::default_amount")]
+    pub amount: u64,
+
+    /// Minimum interval between the requests from the same IP address.
+    #[serde(default = "Faucet::default_per_ip_interval_sec")]
+    pub per_ip_interval_sec: u64,
+
+    /// Minimum interval between the requests for the same receiver key.
+    #[serde(default = "Faucet::default_per_key_interval_sec")]
+    pub per_key_interval_sec: u64,
+
+    /// Maximum number of requests accepted from all clients within an hour.
+    /// Limits the issuance to clients that rotate their IP addresses and keys.
+    #[serde(default = "Faucet::default_hourly_limit")]
+    pub hourly_limit: usize,
+
+    /// Whether the requests require the API token (see `SLINGSHOT_API_TOKEN`),
+    /// e.g. when the faucet is used only by the operator's onboarding service.
+    #[serde(default = "Faucet::default_require_token")]
+    pub require_token: bool,
+
+    /// Maximum number of pending requests.
+    #[serde(default = "Faucet::default_queue_size")]
+    pub queue_size: usize,
+
+    /// Interval between the issuance transactions paying the pending requests.
+    #[serde(default = "Faucet::default_batch_interval_sec")]
+    pub batch_interval_sec: u64,
+
+    /// Number of bulletproofs generators, which limits the number of receivers in a batch.
+    #[serde(default = "Faucet::default_gens_capacity")]
+    pub gens_capacity: usize,
+}
+
+/// Event bus configuration options
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Events {
+    /// Capacity of the queue of each topic.
+    /// Subscribers that fall behind by more than this number of events miss the oldest ones.
+    #[serde(default = "Events::default_queue_size")]
+    pub queue_size: usize,
+}
+
 impl Config {
     /// Returns a documentation for the config file.
     pub fn description() -> &'static str {
@@ -128,22 +279,60 @@ impl Config {
     [api]
     listen = "127.0.0.1:3001"      # socket address for the webserver running the API
     disabled = false               # whether the API server should be disabled
+    idempotency_ttl_sec = 86400    # time for which the responses to requests with an Idempotency-Key are kept
+    idempotency_capacity = 10_000  # maximum number of the cached responses
 
     [p2p]
     listen = "0.0.0.0:0"           # socket address to listen in the peer-to-peer network
     peers = ["127.0.0.0:4000"]     # list of initial peers to connect to
+    key_path = "./peer.key"        # location of the node's identity key
+                                   # (generated on first run; encrypted with
+                                   #  SLINGSHOT_PEER_KEY_PASSPHRASE if it is set)
+    key_storage = "keyring"        # otherwise the key is encrypted with a key in the OS keyring,
+                                   # or stored in the clear with "plaintext"
+    ping_interval_sec = 30         # interval between the liveness pings sent to each peer
+    ping_timeout_sec = 60          # peers that do not respond to a ping in time are disconnected
+    # proxy = "127.0.0.1:9050"     # SOCKS5 proxy for the outbound connections (e.g. Tor)
+    proxy_isolate_streams = true   # dial each peer through a separate proxy circuit
+    # tor_control = "127.0.0.1:9051"  # Tor control port to publish the listening address as an onion service
+    # tor_control_password = ""    # password for the Tor control port, if required
     
     [blockchain]
     storage_path = "./storage"     # location of the stored data 
                                    # (if relative, resolved based on the config file location,
                                    #  which is ~/.slingshot/config.toml by default)
-    mempool_max_size = 10_000_000  # maximum size in bytes for the mempool transactions
-    mempool_min_feerate = 0        # minimum feerate for the transactions to be included in mempool
+    mempool_max_size = 10_000_000  # maximum total weight of the mempool transactions
+    mempool_min_feerate = 0        # minimum relay feerate (units/weight unit) for the transactions to be included in mempool
+    # keep_blocks = 1000           # enables pruned mode: only the bodies of the most recent blocks are kept
+                                   # (headers and utreexo state are kept for the entire chain)
 
     [wallet]
     storage_path = "./wallet"      # location of the wallet keys and account data
                                    # (if relative, resolved based on the config file location,
                                    #  which is ~/.slingshot/wallet by default)
+
+    [prover]
+    listen = "127.0.0.1:3002"      # socket address of the delegated proving service
+    disabled = true                # whether the proving service should be disabled
+    allowed_clients = []           # peer IDs of the allowed clients (any client if empty)
+    max_bundle_size = 1_000_000    # maximum size in bytes of the witness bundle
+    gens_capacity = 4096           # number of bulletproofs generators
+
+    [faucet]
+    disabled = true                # whether the test asset faucet should be disabled
+    asset_alias = "faucet"         # alias of the asset issued by the node's wallet
+    amount = 100                   # quantity issued to each receiver
+    per_ip_interval_sec = 3600     # minimum interval between requests from the same IP address
+    per_key_interval_sec = 86400   # minimum interval between requests for the same receiver key
+    hourly_limit = 100             # maximum number of requests from all clients within an hour
+    require_token = false          # whether requests require the API token
+    queue_size = 32                # maximum number of pending requests
+    batch_interval_sec = 10        # interval between the issuance transactions
+    gens_capacity = 4096           # number of bulletproofs generators
+
+    [events]
+    queue_size = 1000              # capacity of each topic's queue (blocks, mempool, peers, wallet);
+                                   # slow subscribers miss the oldest events and receive a lag report
 "##
     }
 
@@ -185,12 +374,49 @@ impl Config {
         path
     }
 
+    /// Absolute path to the node's identity key
+    pub fn p2p_key_path(&self) -> PathBuf {
+        let mut path = self.path.clone();
+        path.pop(); // remove the filename (config.toml)
+        path.push(&self.data.p2p.key_path); // push the relative key path (if absolute, it'll replace the whole path)
+        path
+    }
+
     /// Path to the blockchain state file
     pub fn blockchain_state_filepath(&self) -> PathBuf {
         let mut path = self.blockchain_path();
         path.push(BC_STATE_FILENAME);
         path
     }
+
+    /// Path to the initial blockchain state file, used as a starting point for the audit
+    pub fn genesis_state_filepath(&self) -> PathBuf {
+        let mut path = self.blockchain_path();
+        path.push(BC_GENESIS_STATE_FILENAME);
+        path
+    }
+
+    /// Path to the network key and the signatures over the tip and the finalized checkpoint
+    pub fn network_filepath(&self) -> PathBuf {
+        let mut path = self.blockchain_path();
+        path.push(BC_NETWORK_FILENAME);
+        path
+    }
+
+    /// Path to the number of transactions in the blocks applied by the node
+    pub fn tx_count_filepath(&self) -> PathBuf {
+        let mut path = self.blockchain_path();
+        path.push(BC_TX_COUNT_FILENAME);
+        path
+    }
+
+    /// Path to the stored block at a given height
+    pub fn block_filepath(&self, height: u64) -> PathBuf {
+        let mut path = self.blockchain_path();
+        path.push(BC_BLOCKS_DIRNAME);
+        path.push(height.to_string());
+        path
+    }
 }
 
 impl UI {
@@ -214,6 +440,14 @@ impl API {
     pub fn default_listen_addr() -> SocketAddr {
         ([127, 0, 0, 1], 3001).into()
     }
+
+    pub fn default_idempotency_ttl_sec() -> u64 {
+        24 * 3600
+    }
+
+    pub fn default_idempotency_capacity() -> usize {
+        10_000
+    }
 }
 
 impl Default for API {
@@ -221,6 +455,8 @@ impl Default for API {
         API {
             listen: Self::default_listen_addr(),
             disabled: false,
+            idempotency_ttl_sec: Self::default_idempotency_ttl_sec(),
+            idempotency_capacity: Self::default_idempotency_capacity(),
         }
     }
 }
@@ -234,6 +470,10 @@ impl P2P {
     pub fn default_key_path() -> PathBuf {
         PathBuf::from("./peer.key")
     }
+    /// Peer key is encrypted with a key in the OS keyring by default.
+    pub fn default_key_storage() -> KeyStorage {
+        KeyStorage::Keyring
+    }
 
     pub fn default_inbound_limit() -> usize {
         100
@@ -245,6 +485,15 @@ impl P2P {
     pub fn default_heartbeat_interval_sec() -> u64 {
         3600
     }
+    pub fn default_ping_interval_sec() -> u64 {
+        30
+    }
+    pub fn default_ping_timeout_sec() -> u64 {
+        60
+    }
+    pub fn default_proxy_isolate_streams() -> bool {
+        true
+    }
 }
 
 impl Default for P2P {
@@ -252,10 +501,17 @@ impl Default for P2P {
         P2P {
             listen_addr: Self::default_listen_addr(),
             key_path: Self::default_key_path(),
+            key_storage: Self::default_key_storage(),
             peers: Vec::new(),
             inbound_limit: Self::default_inbound_limit(),
             outbound_limit: Self::default_outbound_limit(),
             heartbeat_interval_sec: Self::default_heartbeat_interval_sec(),
+            ping_interval_sec: Self::default_ping_interval_sec(),
+            ping_timeout_sec: Self::default_ping_timeout_sec(),
+            proxy: None,
+            proxy_isolate_streams: Self::default_proxy_isolate_streams(),
+            tor_control: None,
+            tor_control_password: None,
         }
     }
 }
@@ -265,7 +521,7 @@ impl Blockchain {
     pub fn default_storage_path() -> PathBuf {
         PathBuf::from("./storage")
     }
-    /// Default maximum size of the mempool (1M bytes).
+    /// Default maximum weight of the mempool (1M weight units).
     pub fn default_mempool_max_size() -> usize {
         1_000_000
     }
@@ -276,7 +532,8 @@ impl Default for Blockchain {
         Blockchain {
             storage_path: Self::default_storage_path(),
             mempool_max_size: Self::default_mempool_max_size(),
-            mempool_min_feerate: 0.0,
+            mempool_min_feerate: 0,
+            keep_blocks: None,
         }
     }
 }
@@ -296,6 +553,125 @@ impl Default for Wallet {
     }
 }
 
+impl Prover {
+    /// Default address for the proving service is only accessible from the localhost.
+    /// Set it to a public address to serve the wallets on other hosts.
+    pub fn default_listen_addr() -> SocketAddr {
+        ([127, 0, 0, 1], 3002).into()
+    }
+
+    /// Proving service is disabled by default.
+    pub fn default_disabled() -> bool {
+        true
+    }
+
+    /// Default maximum size of the witness bundle (1M bytes).
+    pub fn default_max_bundle_size() -> usize {
+        1_000_000
+    }
+
+    /// Default number of bulletproofs generators.
+    pub fn default_gens_capacity() -> usize {
+        4096
+    }
+}
+
+impl Default for Prover {
+    fn default() -> Self {
+        Prover {
+            listen: Self::default_listen_addr(),
+            disabled: Self::default_disabled(),
+            allowed_clients: Vec::new(),
+            max_bundle_size: Self::default_max_bundle_size(),
+            gens_capacity: Self::default_gens_capacity(),
+        }
+    }
+}
+
+impl Faucet {
+    /// Faucet is disabled by default.
+    pub fn default_disabled() -> bool {
+        true
+    }
+
+    /// Default alias of the test asset.
+    pub fn default_asset_alias() -> String {
+        "faucet".to_string()
+    }
+
+    /// Default quantity issued to each receiver.
+    pub fn default_amount() -> u64 {
+        100
+    }
+
+    /// Default interval between requests from the same IP address (1 hour).
+    pub fn default_per_ip_interval_sec() -> u64 {
+        3600
+    }
+
+    /// Default interval between requests for the same receiver key (1 day).
+    pub fn default_per_key_interval_sec() -> u64 {
+        86400
+    }
+
+    /// Default maximum number of requests within an hour.
+    pub fn default_hourly_limit() -> usize {
+        100
+    }
+
+    /// Faucet is public by default.
+    pub fn default_require_token() -> bool {
+        false
+    }
+
+    /// Default maximum number of pending requests.
+    pub fn default_queue_size() -> usize {
+        32
+    }
+
+    /// Default interval between the issuance transactions.
+    pub fn default_batch_interval_sec() -> u64 {
+        10
+    }
+
+    /// Default number of bulletproofs generators.
+    pub fn default_gens_capacity() -> usize {
+        4096
+    }
+}
+
+impl Default for Faucet {
+    fn default() -> Self {
+        Faucet {
+            disabled: Self::default_disabled(),
+            asset_alias: Self::default_asset_alias(),
+            amount: Self::default_amount(),
+            per_ip_interval_sec: Self::default_per_ip_interval_sec(),
+            per_key_interval_sec: Self::default_per_key_interval_sec(),
+            hourly_limit: Self::default_hourly_limit(),
+            require_token: Self::default_require_token(),
+            queue_size: Self::default_queue_size(),
+            batch_interval_sec: Self::default_batch_interval_sec(),
+            gens_capacity: Self::default_gens_capacity(),
+        }
+    }
+}
+
+impl Events {
+    /// Default capacity of the topic queue.
+    pub fn default_queue_size() -> usize {
+        1000
+    }
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Events {
+            queue_size: Self::default_queue_size(),
+        }
+    }
+}
+
 fn expand_path(path: impl Into<PathBuf>) -> PathBuf {
     let mut path = path.into();
     if let Ok(p) = path.strip_prefix("~/") {
@@ -0,0 +1,211 @@
+//! Contact book of the node's wallet.
+//!
+//! Contacts are named counterparties with a reusable address and/or a one-time receiver,
+//! so the payments can be sent `to: "alice"` instead of pasting the address or the receiver JSON.
+//! Payments to a contact address are preferred: each of them gets a fresh output key.
+//! A receiver commits to a single value and predicate, and paying it twice
+//! links the payments on chain, so the wallet warns when a paid receiver is used again.
+
+use std::collections::BTreeMap;
+
+use curve25519_dalek::ristretto::CompressedRistretto;
+use serde::{Deserialize, Serialize};
+
+use accounts::{Address, Receiver};
+use zkvm::bulletproofs::BulletproofGens;
+use zkvm::{ClearValue, TxID};
+
+use crate::bc::BlockchainRef;
+use crate::errors::Error;
+use crate::wallet_manager::WalletRef;
+
+/// Maximum length of the contact name.
+pub const MAX_CONTACT_NAME_LEN: usize = 64;
+
+/// Named counterparties of the wallet, ordered by name.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ContactBook {
+    contacts: BTreeMap<String, Contact>,
+}
+
+/// Counterparty's payment details and the history of payments to it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Contact {
+    /// Reusable address of the counterparty.
+    pub address: Option<Address>,
+    /// One-time receiver of the counterparty.
+    pub receiver: Option<Receiver>,
+    /// Payments sent to the contact, oldest first.
+    pub payments: Vec<Payment>,
+}
+
+/// Payment sent to a contact.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Payment {
+    /// ID of the transaction.
+    pub txid: TxID,
+    /// Paid value.
+    pub value: ClearValue,
+    /// Predicate of the paid receiver, or the control key of the paid address.
+    pub predicate: CompressedRistretto,
+}
+
+/// Destination of the payment resolved from a contact name or an address string.
+enum Destination {
+    Address(Address),
+    Receiver(Receiver),
+}
+
+impl ContactBook {
+    /// Adds a contact or replaces the payment details of an existing one, keeping its payments.
+    /// Returns the warnings about the receiver reuse.
+    pub fn set(
+        &mut self,
+        name: String,
+        address: Option<Address>,
+        receiver: Option<Receiver>,
+    ) -> Result<Vec<String>, Error> {
+        if name.is_empty() || name.len() > MAX_CONTACT_NAME_LEN {
+            return Err(Error::InvalidContact(
+                "name must be 1 to 64 characters long",
+            ));
+        }
+        if Address::from_string(&name).is_some() {
+            return Err(Error::InvalidContact("name must not be an address"));
+        }
+        if address.is_none() && receiver.is_none() {
+            return Err(Error::InvalidContact("address or receiver is required"));
+        }
+
+        let mut warnings = Vec::new();
+        if let Some(receiver) = &receiver {
+            for (other, contact) in self.contacts.iter() {
+                let same_receiver = contact
+                    .receiver
+                    .map(|r| r.opaque_predicate == receiver.opaque_predicate)
+                    .unwrap_or(false);
+                if other != &name && same_receiver {
+                    warnings.push(format!("Receiver is already used by contact {}.", other));
+                }
+            }
+            warnings.extend(self.receiver_warnings(receiver));
+        }
+
+        let contact = self.contacts.entry(name).or_insert_with(|| Contact {
+            address: None,
+            receiver: None,
+            payments: Vec::new(),
+        });
+        contact.address = address;
+        contact.receiver = receiver;
+        Ok(warnings)
+    }
+
+    /// Removes the contact.
+    pub fn remove(&mut self, name: &str) -> Option<Contact> {
+        self.contacts.remove(name)
+    }
+
+    /// Returns the contact with a given name.
+    pub fn get(&self, name: &str) -> Option<&Contact> {
+        self.contacts.get(name)
+    }
+
+    /// Iterates over the contacts ordered by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Contact)> {
+        self.contacts
+            .iter()
+            .map(|(name, contact)| (name.as_str(), contact))
+    }
+
+    /// Returns the warnings if the receiver was already paid.
+    fn receiver_warnings(&self, receiver: &Receiver) -> Vec<String> {
+        self.contacts
+            .iter()
+            .flat_map(|(name, contact)| contact.payments.iter().map(move |p| (name, p)))
+            .filter(|(_, payment)| payment.predicate == receiver.opaque_predicate)
+            .map(|(name, payment)| {
+                format!(
+                    "Receiver of contact {} was already paid in transaction {}: ask for a new receiver.",
+                    name, payment.txid
+                )
+            })
+            .collect()
+    }
+
+    /// Resolves the contact name or the address string.
+    /// Returns the name of the contact, if any, and the destination of the payment.
+    fn resolve(&self, to: &str) -> Result<(Option<String>, Destination), Error> {
+        if let Some(contact) = self.contacts.get(to) {
+            let destination = match (&contact.address, contact.receiver) {
+                (Some(address), _) => Destination::Address(address.clone()),
+                (None, Some(receiver)) => Destination::Receiver(receiver),
+                (None, None) => {
+                    return Err(Error::InvalidContact("address or receiver is required"))
+                }
+            };
+            return Ok((Some(to.to_string()), destination));
+        }
+        Address::from_string(to)
+            .map(|address| (None, Destination::Address(address)))
+            .ok_or_else(|| Error::ContactNotFound(to.to_string()))
+    }
+
+    fn record_payment(&mut self, name: &str, payment: Payment) {
+        if let Some(contact) = self.contacts.get_mut(name) {
+            contact.payments.push(payment);
+        }
+    }
+}
+
+/// Pays the value to a contact or to an address, and submits the transaction.
+/// Payment to a contact's receiver must match the receiver's value.
+/// Returns the transaction ID, the name of the paid contact and the warnings about the receiver reuse.
+pub async fn pay(
+    to: &str,
+    value: ClearValue,
+    bp_gens: &BulletproofGens,
+    bc: &BlockchainRef,
+    wallet: &WalletRef,
+) -> Result<(TxID, Option<String>, Vec<String>), Error> {
+    let mut wm = wallet.write().await;
+    let xprv = wm.read_xprv()?;
+    let (block_tx, name, predicate, warnings) = wm.update_wallet(|w| {
+        let (name, destination) = w.contacts().resolve(to)?;
+        let (warnings, predicate) = match &destination {
+            Destination::Address(address) => (Vec::new(), *address.control_key()),
+            Destination::Receiver(receiver) => {
+                if receiver.value != value {
+                    return Err(Error::InvalidContact("receiver is for a different value"));
+                }
+                (
+                    w.contacts().receiver_warnings(receiver),
+                    receiver.opaque_predicate,
+                )
+            }
+        };
+        let tx = w
+            .build_tx(bp_gens, |b| match destination {
+                Destination::Address(address) => b.transfer_to_address(value, address),
+                Destination::Receiver(receiver) => b.transfer_to_receiver(receiver),
+            })?
+            .sign(&xprv)?;
+        Ok((tx, name, predicate, warnings))
+    })?;
+    let txid = bc.write().await.submit_tx(block_tx, bp_gens)?;
+
+    if let Some(name) = &name {
+        wm.update_wallet(|w| {
+            w.contacts_mut().record_payment(
+                name,
+                Payment {
+                    txid,
+                    value,
+                    predicate,
+                },
+            );
+            Ok(())
+        })?;
+    }
+    Ok((txid, name, warnings))
+}
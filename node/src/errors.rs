@@ -2,6 +2,9 @@ use std;
 use std::path::PathBuf;
 use thiserror::Error as ThisError;
 
+use crate::wallet::WalletError;
+use blockchain::BlockchainError;
+
 /// All error types in the node implementation
 #[derive(ThisError, Debug)]
 pub enum Error {
@@ -20,11 +23,86 @@ pub enum Error {
     #[error("Blockchain is already initialized")]
     BlockchainAlreadyExists,
 
+    #[error("Blockchain is not initialized")]
+    BlockchainNotInitialized,
+
+    #[error("Blockchain has no network key: create it again with the `new` command")]
+    NetworkKeyNotFound,
+
     #[error("Configuration file does not exist")]
     ConfigNotFound(PathBuf),
 
     #[error("Configuration error: {0}")]
     ConfigError(toml::de::Error),
+
+    #[error("Invalid peer ID: {0}")]
+    InvalidPeerID(String),
+
+    #[error("Proving service failed: {0}")]
+    ProvingFailed(String),
+
+    #[error("Wallet key is malformed")]
+    InvalidWalletKey,
+
+    #[error("Wallet mnemonic is not available")]
+    MnemonicNotFound,
+
+    #[error("Invalid wallet backup: {0}")]
+    InvalidBackup(&'static str),
+
+    #[error("Wallet error: {0}")]
+    WalletError(WalletError),
+
+    #[error("Transaction rejected: {0}")]
+    TxRejected(BlockchainError),
+
+    #[error("Faucet request does not match the faucet's asset and amount")]
+    FaucetInvalidReceiver,
+
+    #[error("Too many faucet requests, retry in {0} seconds")]
+    FaucetRateLimited(u64),
+
+    #[error("Faucet queue is full")]
+    FaucetQueueFull,
+
+    #[error("Block at height {0} is not available")]
+    BlockNotFound(u64),
+
+    #[error("Invalid payment proof: {0}")]
+    InvalidPaymentProof(&'static str),
+
+    #[error("Voucher is malformed")]
+    InvalidVoucher,
+
+    #[error("Voucher signing session is not found")]
+    VoucherSessionNotFound,
+
+    #[error("Invalid contact: {0}")]
+    InvalidContact(&'static str),
+
+    #[error("Contact or address is not found: {0}")]
+    ContactNotFound(String),
+
+    #[error("Idempotency key must be 1 to 255 characters long")]
+    InvalidIdempotencyKey,
+
+    #[error("Request with the same idempotency key is in progress")]
+    IdempotencyKeyInUse,
+
+    #[error("Idempotency key was used with another request")]
+    IdempotencyKeyMismatch,
+
+    #[error("Too many idempotency keys, retry later")]
+    IdempotencyCacheFull,
+
+    #[error("Request handler failed")]
+    RequestHandlerFailed,
+
+    #[error("Authorization required")]
+    Unauthorized,
+
+    #[error("Endpoint is disabled because the API token is not set")]
+    ApiTokenNotSet,
 }
 
 impl From<std::io::Error> for Error {
@@ -38,3 +116,15 @@ impl From<bincode::Error> for Error {
         Error::BincodeError(err)
     }
 }
+
+impl From<WalletError> for Error {
+    fn from(err: WalletError) -> Self {
+        Error::WalletError(err)
+    }
+}
+
+impl From<BlockchainError> for Error {
+    fn from(err: BlockchainError) -> Self {
+        Error::TxRejected(err)
+    }
+}
@@ -0,0 +1,200 @@
+//! Event bus of the node.
+//!
+//! Events are published to the typed topics: blocks, mempool, peers and wallet.
+//! Each topic is a bounded broadcast queue, so the API, UI, indexer and wallet
+//! can consume the events independently, at their own pace.
+//! A subscriber that falls behind by more than the queue size loses the oldest events
+//! and is notified about the number of the events it has missed.
+
+use serde::Serialize;
+use std::fmt;
+use tokio::sync::broadcast;
+use zkvm::{ContractID, TxID};
+
+/// Event bus with a bounded queue per topic.
+/// Cloning the bus is cheap: clones publish to and subscribe to the same topics.
+#[derive(Clone)]
+pub struct EventBus {
+    blocks: broadcast::Sender<BlockEvent>,
+    mempool: broadcast::Sender<MempoolEvent>,
+    peers: broadcast::Sender<PeerEvent>,
+    wallet: broadcast::Sender<WalletEvent>,
+}
+
+/// Topic of the event bus, identified by the type of its events.
+pub trait Topic: Clone + Send + 'static {
+    /// Name of the topic.
+    const NAME: &'static str;
+
+    /// Returns the queue of the topic in the bus.
+    fn queue(bus: &EventBus) -> &broadcast::Sender<Self>;
+}
+
+/// Subscription to a single topic.
+pub struct Subscription<T: Topic> {
+    receiver: broadcast::Receiver<T>,
+    missed: u64,
+}
+
+/// Item received from the subscription.
+#[derive(Clone, Debug)]
+pub enum Received<T> {
+    /// Next event in the topic.
+    Event(T),
+
+    /// The subscriber has fallen behind and missed a number of the oldest events.
+    Lagged(u64),
+}
+
+/// Events about the blocks stored by the node.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BlockEvent {
+    /// New block is stored.
+    BlockStored {
+        height: u64,
+        id: String,
+        tx_count: usize,
+    },
+
+    /// The body of the block is discarded in pruned mode.
+    BlockPruned { height: u64 },
+}
+
+/// Events about the unconfirmed transactions.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MempoolEvent {
+    /// Transaction is verified and added to the mempool.
+    TxAdded { id: TxID, mempool_len: usize },
+
+    /// Transaction is rejected because it spends a contract already spent in the mempool.
+    DoubleSpendDetected {
+        id: TxID,
+        conflicting_id: TxID,
+        contract_id: ContractID,
+    },
+}
+
+/// Events about the peer-to-peer connections.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PeerEvent {
+    /// Peer is connected.
+    PeerConnected { peer_id: String },
+
+    /// Peer is disconnected.
+    PeerDisconnected { peer_id: String },
+}
+
+/// Events about the wallet.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WalletEvent {
+    /// Wallet rescan has processed the block at a given height.
+    RescanProgress { height: u64, tip_height: u64 },
+
+    /// Wallet rescan has finished and reconciled the utxo set.
+    RescanFinished {
+        tip_height: u64,
+        added_utxos: usize,
+        removed_utxos: usize,
+    },
+
+    /// Wallet rescan has stopped due to an error.
+    RescanFailed { height: u64, error: String },
+
+    /// Transaction in the mempool that spends the wallet's utxo or pays the wallet
+    /// is contested by a double-spending transaction, which was rejected.
+    DoubleSpendDetected {
+        id: TxID,
+        conflicting_id: TxID,
+        contract_id: ContractID,
+    },
+}
+
+impl EventBus {
+    /// Creates a new event bus with a given capacity of each topic's queue.
+    pub fn new(queue_size: usize) -> Self {
+        EventBus {
+            blocks: broadcast::channel(queue_size).0,
+            mempool: broadcast::channel(queue_size).0,
+            peers: broadcast::channel(queue_size).0,
+            wallet: broadcast::channel(queue_size).0,
+        }
+    }
+
+    /// Sends the event to all the subscribers of its topic.
+    /// Never blocks: if the queue is full, the oldest event is dropped
+    /// and the lagging subscribers are notified when they catch up.
+    pub fn publish<T: Topic>(&self, event: T) {
+        // Sending fails only if there are no subscribers, which is fine.
+        let _ = T::queue(self).send(event);
+    }
+
+    /// Subscribes to the events published to the topic from now on.
+    pub fn subscribe<T: Topic>(&self) -> Subscription<T> {
+        Subscription {
+            receiver: T::queue(self).subscribe(),
+            missed: 0,
+        }
+    }
+}
+
+impl<T: Topic> Subscription<T> {
+    /// Waits for the next event or a lag report.
+    /// Returns `None` when the event bus is dropped.
+    pub async fn recv(&mut self) -> Option<Received<T>> {
+        match self.receiver.recv().await {
+            Ok(event) => Some(Received::Event(event)),
+            Err(broadcast::RecvError::Lagged(n)) => {
+                self.missed += n;
+                Some(Received::Lagged(n))
+            }
+            Err(broadcast::RecvError::Closed) => None,
+        }
+    }
+
+    /// Returns the total number of the events missed by this subscriber.
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+}
+
+impl Topic for BlockEvent {
+    const NAME: &'static str = "blocks";
+
+    fn queue(bus: &EventBus) -> &broadcast::Sender<Self> {
+        &bus.blocks
+    }
+}
+
+impl Topic for MempoolEvent {
+    const NAME: &'static str = "mempool";
+
+    fn queue(bus: &EventBus) -> &broadcast::Sender<Self> {
+        &bus.mempool
+    }
+}
+
+impl Topic for PeerEvent {
+    const NAME: &'static str = "peers";
+
+    fn queue(bus: &EventBus) -> &broadcast::Sender<Self> {
+        &bus.peers
+    }
+}
+
+impl Topic for WalletEvent {
+    const NAME: &'static str = "wallet";
+
+    fn queue(bus: &EventBus) -> &broadcast::Sender<Self> {
+        &bus.wallet
+    }
+}
+
+impl fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventBus").finish()
+    }
+}
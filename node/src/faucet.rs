@@ -0,0 +1,315 @@
+//! Faucet service for the test networks.
+//!
+//! Users submit a receiver for the faucet's test asset and the node issues the configured
+//! amount to it, so new users can be onboarded without the operator's involvement.
+//! Requests are rate-limited per IP address, per receiver's predicate key
+//! and in total per hour, and placed in a bounded queue. The queue is drained periodically
+//! and all pending receivers are paid with a single issuance transaction.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::scalar::Scalar;
+use tokio::sync::Mutex;
+use tokio::time;
+
+use accounts::Receiver;
+use zkvm::bulletproofs::BulletproofGens;
+use zkvm::ClearValue;
+
+use crate::bc::BlockchainRef;
+use crate::config::Config;
+use crate::errors::Error;
+use crate::wallet_manager::WalletRef;
+
+/// Window of the total limit on the requests.
+const HOUR: Duration = Duration::from_secs(3600);
+
+/// Reference to the faucet instance.
+pub type FaucetRef = Arc<Mutex<Faucet>>;
+
+/// Queue of the faucet requests with the rate limits.
+#[derive(Debug)]
+pub struct Faucet {
+    /// Value issued to each receiver.
+    value: ClearValue,
+
+    /// Minimum interval between the requests from the same IP address.
+    per_ip_interval: Duration,
+
+    /// Minimum interval between the requests for the same predicate key.
+    per_key_interval: Duration,
+
+    /// Maximum number of requests accepted within an hour.
+    hourly_limit: usize,
+
+    /// Maximum number of pending requests.
+    queue_size: usize,
+
+    /// Time of the last accepted request from each IP address.
+    last_by_ip: HashMap<IpAddr, Instant>,
+
+    /// Time of the last accepted request for each predicate key.
+    last_by_key: HashMap<CompressedRistretto, Instant>,
+
+    /// Times of the requests accepted within the last hour, oldest first.
+    last_hour: VecDeque<Instant>,
+
+    /// Receivers awaiting the payment.
+    queue: VecDeque<Receiver>,
+}
+
+impl Faucet {
+    /// Creates a faucet issuing the asset with a given flavor.
+    pub fn new(config: &Config, flavor: Scalar) -> Self {
+        let conf = &config.data.faucet;
+        Faucet {
+            value: ClearValue {
+                qty: conf.amount,
+                flv: flavor,
+            },
+            per_ip_interval: Duration::from_secs(conf.per_ip_interval_sec),
+            per_key_interval: Duration::from_secs(conf.per_key_interval_sec),
+            hourly_limit: conf.hourly_limit,
+            queue_size: conf.queue_size,
+            last_by_ip: HashMap::new(),
+            last_by_key: HashMap::new(),
+            last_hour: VecDeque::with_capacity(conf.hourly_limit),
+            queue: VecDeque::with_capacity(conf.queue_size),
+        }
+    }
+
+    /// Value that the receivers must request.
+    pub fn value(&self) -> ClearValue {
+        self.value
+    }
+
+    /// Places the receiver in the queue.
+    /// Returns the number of requests pending before this one.
+    pub fn request(&mut self, ip: IpAddr, receiver: Receiver) -> Result<usize, Error> {
+        self.request_at(Instant::now(), ip, receiver)
+    }
+
+    /// Places the receiver in the queue at a given time.
+    fn request_at(&mut self, now: Instant, ip: IpAddr, receiver: Receiver) -> Result<usize, Error> {
+        if receiver.value.qty != self.value.qty || receiver.value.flv != self.value.flv {
+            return Err(Error::FaucetInvalidReceiver);
+        }
+        self.forget_expired(now);
+
+        let hourly_wait = if self.last_hour.len() >= self.hourly_limit {
+            // With zero limit there are no accepted requests to wait for.
+            Some(
+                self.last_hour
+                    .front()
+                    .map(|t| HOUR - now.duration_since(*t))
+                    .unwrap_or(HOUR),
+            )
+        } else {
+            None
+        };
+        let key = receiver.opaque_predicate;
+        let wait = self
+            .last_by_ip
+            .get(&ip)
+            .map(|t| self.per_ip_interval - now.duration_since(*t))
+            .into_iter()
+            .chain(
+                self.last_by_key
+                    .get(&key)
+                    .map(|t| self.per_key_interval - now.duration_since(*t)),
+            )
+            .chain(hourly_wait)
+            .max();
+        if let Some(wait) = wait {
+            return Err(Error::FaucetRateLimited(wait.as_secs() + 1));
+        }
+        if self.queue.len() >= self.queue_size {
+            return Err(Error::FaucetQueueFull);
+        }
+
+        self.last_by_ip.insert(ip, now);
+        self.last_by_key.insert(key, now);
+        self.last_hour.push_back(now);
+        self.queue.push_back(receiver);
+        Ok(self.queue.len() - 1)
+    }
+
+    /// Removes all pending receivers from the queue.
+    fn take_pending(&mut self) -> Vec<Receiver> {
+        self.queue.drain(..).collect()
+    }
+
+    /// Removes the records that no longer limit the requests.
+    fn forget_expired(&mut self, now: Instant) {
+        let (ip_interval, key_interval) = (self.per_ip_interval, self.per_key_interval);
+        self.last_by_ip
+            .retain(|_, t| now.duration_since(*t) < ip_interval);
+        self.last_by_key
+            .retain(|_, t| now.duration_since(*t) < key_interval);
+        while let Some(t) = self.last_hour.front() {
+            if now.duration_since(*t) < HOUR {
+                break;
+            }
+            self.last_hour.pop_front();
+        }
+    }
+}
+
+/// Periodically pays the pending receivers from the node's wallet.
+pub async fn launch(config: Config, faucet: FaucetRef, bc: BlockchainRef, wallet: WalletRef) {
+    let bp_gens = BulletproofGens::new(config.data.faucet.gens_capacity, 1);
+    let mut interval = time::interval(Duration::from_secs(config.data.faucet.batch_interval_sec));
+    loop {
+        interval.tick().await;
+        let receivers = faucet.lock().await.take_pending();
+        if receivers.is_empty() {
+            continue;
+        }
+        let count = receivers.len();
+        match pay_receivers(receivers, &bp_gens, &bc, &wallet).await {
+            Ok(()) => println!("\n=> Faucet paid {} receivers.", count),
+            Err(e) => println!("\n=> Faucet failed to pay {} receivers: {}", count, e),
+        }
+    }
+}
+
+async fn pay_receivers(
+    receivers: Vec<Receiver>,
+    bp_gens: &BulletproofGens,
+    bc: &BlockchainRef,
+    wallet: &WalletRef,
+) -> Result<(), Error> {
+    let mut wm = wallet.write().await;
+    let xprv = wm.read_xprv()?;
+    let block_tx = wm.update_wallet(|w| {
+        let tx = w
+            .build_tx(bp_gens, |b| {
+                for receiver in receivers {
+                    b.issue_to_receiver(receiver);
+                }
+            })?
+            .sign(&xprv)?;
+        Ok(tx)
+    })?;
+    bc.write().await.submit_tx(block_tx, bp_gens)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+    use std::path::PathBuf;
+
+    fn faucet(configure: impl FnOnce(&mut crate::config::Faucet)) -> Faucet {
+        let mut config = Config {
+            data: Default::default(),
+            path: PathBuf::new(),
+        };
+        configure(&mut config.data.faucet);
+        Faucet::new(&config, Scalar::from(1u64))
+    }
+
+    fn receiver(faucet: &Faucet, key: u64) -> Receiver {
+        Receiver {
+            opaque_predicate: (Scalar::from(key) * RISTRETTO_BASEPOINT_POINT).compress(),
+            value: faucet.value(),
+            qty_blinding: Scalar::zero(),
+            flv_blinding: Scalar::zero(),
+        }
+    }
+
+    fn ip(n: u8) -> IpAddr {
+        IpAddr::from([10, 0, 0, n])
+    }
+
+    #[test]
+    fn rejects_another_value() {
+        let mut f = faucet(|_| {});
+        let mut r = receiver(&f, 1);
+        r.value.qty += 1;
+        assert!(matches!(
+            f.request(ip(1), r),
+            Err(Error::FaucetInvalidReceiver)
+        ));
+    }
+
+    #[test]
+    fn limits_requests_per_ip() {
+        let mut f = faucet(|c| c.per_ip_interval_sec = 60);
+        let now = Instant::now();
+        assert_eq!(f.request_at(now, ip(1), receiver(&f, 1)).unwrap(), 0);
+        assert!(matches!(
+            f.request_at(now + Duration::from_secs(10), ip(1), receiver(&f, 2)),
+            Err(Error::FaucetRateLimited(51))
+        ));
+        assert_eq!(
+            f.request_at(now + Duration::from_secs(10), ip(2), receiver(&f, 2))
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            f.request_at(now + Duration::from_secs(60), ip(1), receiver(&f, 3))
+                .unwrap(),
+            2
+        );
+    }
+
+    #[test]
+    fn limits_requests_per_key() {
+        let mut f = faucet(|c| c.per_key_interval_sec = 600);
+        let now = Instant::now();
+        f.request_at(now, ip(1), receiver(&f, 1)).unwrap();
+        assert!(matches!(
+            f.request_at(now, ip(2), receiver(&f, 1)),
+            Err(Error::FaucetRateLimited(_))
+        ));
+        f.request_at(now + Duration::from_secs(600), ip(2), receiver(&f, 1))
+            .unwrap();
+    }
+
+    #[test]
+    fn limits_requests_per_hour() {
+        let mut f = faucet(|c| c.hourly_limit = 2);
+        let now = Instant::now();
+        f.request_at(now, ip(1), receiver(&f, 1)).unwrap();
+        f.request_at(now + Duration::from_secs(1800), ip(2), receiver(&f, 2))
+            .unwrap();
+        assert!(matches!(
+            f.request_at(now + Duration::from_secs(1800), ip(3), receiver(&f, 3)),
+            Err(Error::FaucetRateLimited(1801))
+        ));
+        // The first request leaves the window.
+        f.request_at(now + HOUR, ip(3), receiver(&f, 3)).unwrap();
+        assert!(matches!(
+            f.request_at(now + HOUR, ip(4), receiver(&f, 4)),
+            Err(Error::FaucetRateLimited(_))
+        ));
+    }
+
+    #[test]
+    fn zero_hourly_limit_rejects_all_requests() {
+        let mut f = faucet(|c| c.hourly_limit = 0);
+        assert!(matches!(
+            f.request(ip(1), receiver(&f, 1)),
+            Err(Error::FaucetRateLimited(_))
+        ));
+    }
+
+    #[test]
+    fn limits_pending_requests() {
+        let mut f = faucet(|c| c.queue_size = 2);
+        f.request(ip(1), receiver(&f, 1)).unwrap();
+        f.request(ip(2), receiver(&f, 2)).unwrap();
+        assert!(matches!(
+            f.request(ip(3), receiver(&f, 3)),
+            Err(Error::FaucetQueueFull)
+        ));
+        assert_eq!(f.take_pending().len(), 2);
+        assert_eq!(f.request(ip(3), receiver(&f, 3)).unwrap(), 0);
+    }
+}
@@ -0,0 +1,222 @@
+//! Deduplication of the retried API requests.
+//!
+//! State-changing wallet endpoints accept an optional `Idempotency-Key` header.
+//! The successful response is cached under the key for a configured period,
+//! so a request retried after a network failure receives the original response
+//! instead of redeeming a voucher or importing the utxos twice.
+//! A request with a key that is still being processed is rejected with 409 Conflict.
+//! The key is bound to the hash of the request body: reusing it with a different body
+//! is rejected with 422 Unprocessable Entity.
+//! Failed requests are not cached, so they can be retried with the same key.
+//! The request with a key runs to completion even if the client disconnects,
+//! so its response is available to the retried request.
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use merlin::Transcript;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::errors::Error;
+
+/// Maximum length of the idempotency key.
+pub const MAX_KEY_LEN: usize = 255;
+
+/// Reference to the idempotency cache.
+/// The lock is never held across an await point, so a blocking mutex is used:
+/// it can be released from the `Drop` impl of a pending request.
+pub type IdempotencyRef = Arc<Mutex<IdempotencyCache>>;
+
+/// Hash of the request body.
+type BodyHash = [u8; 32];
+
+/// Cache of the responses to the requests with idempotency keys.
+#[derive(Debug)]
+pub struct IdempotencyCache {
+    /// Time in milliseconds for which a response is kept.
+    ttl_ms: u64,
+
+    /// Maximum number of the cached responses.
+    capacity: usize,
+
+    /// Entries by endpoint and key.
+    entries: HashMap<(&'static str, String), Entry>,
+
+    /// Completed entries in the order of completion, used to expire them
+    /// without scanning the whole cache.
+    completed: VecDeque<(u64, &'static str, String)>,
+}
+
+#[derive(Debug)]
+enum Entry {
+    /// The request with a given body is being processed.
+    Pending(BodyHash),
+    /// The request with a given body was completed at a given time.
+    Done(BodyHash, serde_json::Value, u64),
+}
+
+impl IdempotencyCache {
+    /// Creates an empty cache.
+    pub fn new(config: &Config) -> Self {
+        let conf = &config.data.api;
+        IdempotencyCache {
+            ttl_ms: conf.idempotency_ttl_sec * 1000,
+            capacity: conf.idempotency_capacity,
+            entries: HashMap::new(),
+            completed: VecDeque::new(),
+        }
+    }
+
+    /// Returns the cached response for the key, or marks the key as pending
+    /// if it is not known yet.
+    fn begin(
+        &mut self,
+        endpoint: &'static str,
+        key: &str,
+        body_hash: BodyHash,
+        now_ms: u64,
+    ) -> Result<Option<serde_json::Value>, Error> {
+        self.expire(now_ms);
+        match self.entries.get(&(endpoint, key.to_string())) {
+            Some(Entry::Pending(hash)) | Some(Entry::Done(hash, _, _)) if hash != &body_hash => {
+                Err(Error::IdempotencyKeyMismatch)
+            }
+            Some(Entry::Pending(_)) => Err(Error::IdempotencyKeyInUse),
+            Some(Entry::Done(_, response, _)) => Ok(Some(response.clone())),
+            None if self.entries.len() >= self.capacity => Err(Error::IdempotencyCacheFull),
+            None => {
+                self.entries
+                    .insert((endpoint, key.to_string()), Entry::Pending(body_hash));
+                Ok(None)
+            }
+        }
+    }
+
+    /// Stores the response to the pending request, or releases the key if the request failed.
+    fn finish(
+        &mut self,
+        endpoint: &'static str,
+        key: String,
+        response: Option<serde_json::Value>,
+        now_ms: u64,
+    ) {
+        let body_hash = match self.entries.remove(&(endpoint, key.clone())) {
+            Some(Entry::Pending(hash)) => hash,
+            _ => return,
+        };
+        if let Some(response) = response {
+            self.completed.push_back((now_ms, endpoint, key.clone()));
+            self.entries
+                .insert((endpoint, key), Entry::Done(body_hash, response, now_ms));
+        }
+    }
+
+    /// Removes the responses older than the TTL.
+    /// Each completed entry is visited once, when it expires.
+    fn expire(&mut self, now_ms: u64) {
+        while let Some((time_ms, _, _)) = self.completed.front() {
+            if now_ms.saturating_sub(*time_ms) < self.ttl_ms {
+                break;
+            }
+            let (time_ms, endpoint, key) = self.completed.pop_front().expect("checked above");
+            let map_key = (endpoint, key);
+            // The key may have been reused since, in which case the entry is newer.
+            if let Some(Entry::Done(_, _, t)) = self.entries.get(&map_key) {
+                if *t == time_ms {
+                    self.entries.remove(&map_key);
+                }
+            }
+        }
+    }
+}
+
+/// Pending request that releases its key when dropped without a response,
+/// e.g. if the handler panics.
+struct PendingKey {
+    cache: IdempotencyRef,
+    endpoint: &'static str,
+    key: Option<String>,
+}
+
+impl PendingKey {
+    /// Stores the response to the request, or releases the key if the request failed.
+    fn finish(mut self, response: Option<serde_json::Value>) {
+        if let Some(key) = self.key.take() {
+            lock(&self.cache).finish(self.endpoint, key, response, crate::current_timestamp_ms());
+        }
+    }
+}
+
+impl Drop for PendingKey {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            lock(&self.cache).finish(self.endpoint, key, None, crate::current_timestamp_ms());
+        }
+    }
+}
+
+/// Locks the cache. A panic while the lock is held cannot leave the cache
+/// in an inconsistent state, so the poisoned lock is still usable.
+fn lock(cache: &IdempotencyRef) -> MutexGuard<IdempotencyCache> {
+    cache.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Hashes the request body, so the key cannot be reused for another request.
+fn hash_body<B: Serialize>(endpoint: &'static str, body: &B) -> BodyHash {
+    let mut t = Transcript::new(b"Node.idempotency");
+    t.append_message(b"endpoint", endpoint.as_bytes());
+    t.append_message(
+        b"body",
+        &bincode::serialize(body).expect("Request body should be serializable."),
+    );
+    let mut hash = [0u8; 32];
+    t.challenge_bytes(b"hash", &mut hash);
+    hash
+}
+
+/// Runs the request handler, unless the request with the same key was already completed,
+/// in which case the cached response is returned.
+/// Requests without a key are always handled.
+pub async fn deduplicate<B, H, F>(
+    cache: &IdempotencyRef,
+    endpoint: &'static str,
+    key: Option<String>,
+    request: B,
+    handler: H,
+) -> Result<serde_json::Value, Error>
+where
+    B: Serialize,
+    H: FnOnce(B) -> F,
+    F: Future<Output = Result<serde_json::Value, Error>> + Send + 'static,
+{
+    let key = match key {
+        Some(key) if key.is_empty() || key.len() > MAX_KEY_LEN => {
+            return Err(Error::InvalidIdempotencyKey)
+        }
+        Some(key) => key,
+        None => return handler(request).await,
+    };
+    let body_hash = hash_body(endpoint, &request);
+    if let Some(response) =
+        lock(cache).begin(endpoint, &key, body_hash, crate::current_timestamp_ms())?
+    {
+        return Ok(response);
+    }
+    let pending = PendingKey {
+        cache: cache.clone(),
+        endpoint,
+        key: Some(key),
+    };
+    let handler = handler(request);
+    tokio::spawn(async move {
+        let result = handler.await;
+        pending.finish(result.as_ref().ok().cloned());
+        result
+    })
+    .await
+    // The pending key is released when the panicked task drops it,
+    // and the request is answered with 500 Internal Server Error.
+    .unwrap_or(Err(Error::RequestHandlerFailed))
+}
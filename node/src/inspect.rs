@@ -0,0 +1,118 @@
+//! Offline inspection and verification of raw transactions and blocks.
+//!
+//! Transactions are accepted either as a bare ZkVM `Tx` encoding
+//! or as a `BlockTx` encoding that includes the utreexo proofs for the inputs.
+//! Blocks are accepted as the length-prefixed `Block` protocol message
+//! (see `blockchain::encode_message`).
+//! Verification is performed against a blockchain state snapshot
+//! in the same format as the node's `blockchain_state` file.
+
+use std::fs::File;
+use std::path::Path;
+
+use readerwriter::{Decodable, Reader};
+use serde_json::Value as JsonValue;
+
+use blockchain::{BlockTx, BlockchainState, Mempool, Message};
+use zkvm::bulletproofs::BulletproofGens;
+use zkvm::{Program, Tx};
+
+use crate::json::to_json_value;
+
+/// Number of bulletproofs generators used for offline verification.
+const GENS_CAPACITY: usize = 4096;
+
+/// Decodes a hex-encoded transaction and describes it without verifying.
+pub fn decode_tx(hex_string: &str) -> Result<JsonValue, String> {
+    let block_tx = parse_tx(hex_string)?;
+    let tx = &block_tx.tx;
+    let program = Program::parse(&tx.program)
+        .map_err(|e| format!("Transaction program is malformed: {}", e))?;
+    let precomputed = tx
+        .precompute()
+        .map_err(|e| format!("Transaction cannot be precomputed: {}", e))?;
+    Ok(json!({
+        "id": to_json_value(&precomputed.id),
+        "wire_hash": to_json_value(&tx.wire_hash()),
+        "header": to_json_value(&tx.header),
+        "program_hex": hex::encode(&tx.program),
+        "program_asm": format!("{:?}", program),
+        "log": to_json_value(&precomputed.log),
+        "utxo_proofs": block_tx.proofs.len(),
+    }))
+}
+
+/// Verifies a hex-encoded transaction against the blockchain state snapshot
+/// the same way the mempool does: checks the signatures, the R1CS proof
+/// and the utreexo proofs of the inputs.
+pub fn verify_tx(state_path: &Path, hex_string: &str) -> Result<JsonValue, String> {
+    let block_tx = parse_tx(hex_string)?;
+    let state = load_state(state_path)?;
+    let tip = state.tip.clone();
+    let bp_gens = BulletproofGens::new(GENS_CAPACITY, 1);
+
+    let mut mempool = Mempool::new(state, tip.timestamp_ms);
+    let entry = mempool
+        .append(block_tx, &bp_gens)
+        .map_err(|e| format!("Transaction is invalid: {}", e))?;
+    let feerate = entry.verified_tx().feerate;
+    Ok(json!({
+        "valid": true,
+        "id": to_json_value(&entry.txid()),
+        "size": feerate.size(),
+        "fee": feerate.fee(),
+        "tip": to_json_value(&tip.id()),
+        "tip_height": tip.height,
+    }))
+}
+
+/// Verifies a hex-encoded block message as the next block after the blockchain state snapshot.
+/// The network signature of the block is not checked.
+pub fn verify_block(state_path: &Path, hex_string: &str) -> Result<JsonValue, String> {
+    let bytes = parse_hex(hex_string)?;
+    let block = match blockchain::decode_message(&bytes) {
+        Ok(Message::Block(block)) => block,
+        Ok(_) => return Err("Message is not a block".to_string()),
+        Err(e) => return Err(format!("Block message is malformed: {}", e)),
+    };
+    let state = load_state(state_path)?;
+    let bp_gens = BulletproofGens::new(GENS_CAPACITY, 1);
+
+    let verified_block = state
+        .apply_block(block.header().clone(), block.txs(), &bp_gens)
+        .map_err(|e| format!("Block is invalid: {}", e))?;
+    let new_state = verified_block.blockchain_state();
+    Ok(json!({
+        "valid": true,
+        "id": to_json_value(&verified_block.header.id()),
+        "height": verified_block.header.height,
+        "tx_count": verified_block.verified_txs.len(),
+        "utreexo": to_json_value(&new_state.utreexo.roots()),
+        "utxo_count": new_state.utreexo.count(),
+    }))
+}
+
+/// Parses the transaction as `Tx` or, if there are trailing bytes, as `BlockTx`.
+fn parse_tx(hex_string: &str) -> Result<BlockTx, String> {
+    let bytes = parse_hex(hex_string)?;
+    if let Ok(tx) = (&bytes[..]).read_all(|r| Tx::decode(r)) {
+        return Ok(BlockTx {
+            tx,
+            proofs: Vec::new(),
+        });
+    }
+    (&bytes[..])
+        .read_all(|r| BlockTx::decode(r))
+        .map_err(|e| format!("Transaction is malformed: {}", e))
+}
+
+fn parse_hex(hex_string: &str) -> Result<Vec<u8>, String> {
+    hex::decode(hex_string.trim()).map_err(|e| format!("Invalid hex string: {}", e))
+}
+
+fn load_state(path: &Path) -> Result<BlockchainState, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("Cannot open state snapshot {}: {}", path.display(), e))?;
+    bincode::deserialize_from(file)
+        .map_err(|e| format!("Cannot read state snapshot {}: {}", path.display(), e))
+}
@@ -0,0 +1,52 @@
+//! Invoices: payment requests created and paid by the node's wallet.
+//!
+//! The payee creates an invoice for a new receiver and shares its string encoding
+//! (see `accounts::Invoice`) instead of the raw receiver. The payer checks the signature
+//! and the expiration time, and pays the receiver with a transaction
+//! that cannot be published after the invoice expires.
+
+use accounts::Invoice;
+use zkvm::bulletproofs::BulletproofGens;
+use zkvm::{ClearValue, TxID};
+
+use crate::bc::BlockchainRef;
+use crate::errors::Error;
+use crate::wallet::WalletError;
+use crate::wallet_manager::WalletRef;
+
+/// Creates an invoice for a given value, payable until the expiration time.
+pub async fn create(
+    value: ClearValue,
+    expiration_ms: u64,
+    memo: String,
+    wallet: &WalletRef,
+) -> Result<Invoice, Error> {
+    let mut wm = wallet.write().await;
+    let xprv = wm.read_xprv()?;
+    wm.update_wallet(|w| Ok(w.create_invoice(value, expiration_ms, memo, &xprv)?))
+}
+
+/// Pays the invoice and submits the transaction.
+/// Fails if the invoice is expired or is issued on another ledger.
+pub async fn pay(
+    invoice: &Invoice,
+    bp_gens: &BulletproofGens,
+    bc: &BlockchainRef,
+    wallet: &WalletRef,
+) -> Result<TxID, Error> {
+    invoice
+        .check_expiration(crate::current_timestamp_ms())
+        .map_err(WalletError::InvoiceError)?;
+    let mut wm = wallet.write().await;
+    let xprv = wm.read_xprv()?;
+    let block_tx = wm.update_wallet(|w| {
+        if &invoice.label != w.address_label() {
+            return Err(WalletError::AddressLabelMismatch.into());
+        }
+        let tx = w
+            .build_tx(bp_gens, |b| b.pay_invoice(invoice))?
+            .sign(&xprv)?;
+        Ok(tx)
+    })?;
+    bc.write().await.submit_tx(block_tx, bp_gens)
+}
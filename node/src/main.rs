@@ -2,25 +2,44 @@
 extern crate serde_json;
 
 mod api;
+mod auth;
+mod backup;
 mod bc;
 mod config;
+mod contacts;
 mod errors;
+mod events;
+mod faucet;
+mod idempotency;
+mod inspect;
+mod invoice;
 mod json;
+mod payment_proof;
+mod prover_service;
 mod ui;
+mod vault;
+mod voucher;
 mod wallet;
 mod wallet_manager;
 
+use backup::WalletBackup;
 use bc::{Blockchain, BlockchainIdle};
 use config::Config;
 use errors::Error;
+use faucet::Faucet;
+use prover_service::ProverService;
 use ui::UI;
 use wallet::Wallet;
 use wallet_manager::WalletManager;
 
 use accounts::AddressLabel;
-use keytree::Xprv;
+use keytree::{Mnemonic, MnemonicVersion};
+use p2p::cybershake::PrivateKey;
+use p2p::NodeIdentity;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::SystemTime;
+use tokio::sync::Mutex;
 use zkvm::curve25519_dalek::scalar::Scalar;
 use zkvm::ClearValue;
 
@@ -35,6 +54,12 @@ async fn main() {
 async fn launch() -> Result<(), String> {
     use clap::{self, App, Arg, SubCommand};
 
+    let state_arg = Arg::with_name("state")
+        .long("state")
+        .takes_value(true)
+        .value_name("SNAPSHOT")
+        .help("Blockchain state snapshot (the node's own state by default)");
+
     let cli_matches = App::new("Slingshot node")
         .setting(clap::AppSettings::SubcommandRequiredElseHelp)
         .version("1.0")
@@ -59,6 +84,13 @@ async fn launch() -> Result<(), String> {
                         .takes_value(true)
                         .required(true)
                         .help("Prefix for addresses (1-83 alphanumeric lowercase characters)"),
+                )
+                .arg(
+                    Arg::with_name("mnemonic")
+                        .long("mnemonic")
+                        .value_name("PHRASE")
+                        .takes_value(true)
+                        .help("Restores the wallet and identity keys from a 12- or 24-word mnemonic"),
                 ),
         )
         .subcommand(
@@ -69,6 +101,33 @@ async fn launch() -> Result<(), String> {
                 .about("Performs wallet operations")
                 .subcommand(SubCommand::with_name("new").about("Creates a new wallet")),
         )
+        .subcommand(
+            SubCommand::with_name("tx")
+                .about("Inspects raw transactions without a running node")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("decode")
+                        .about("Decodes a hex-encoded transaction")
+                        .arg(Arg::with_name("hex").required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name("verify")
+                        .about("Verifies a hex-encoded transaction against a state snapshot")
+                        .arg(state_arg.clone())
+                        .arg(Arg::with_name("hex").required(true)),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("block")
+                .about("Inspects raw blocks without a running node")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("verify")
+                        .about("Verifies a hex-encoded block message as the next block after a state snapshot")
+                        .arg(state_arg.clone())
+                        .arg(Arg::with_name("hex").required(true)),
+                ),
+        )
         .get_matches();
     let config_path = cli_matches.value_of("config").map(|s| PathBuf::from(s));
 
@@ -88,11 +147,35 @@ async fn launch() -> Result<(), String> {
                 "Address prefix must be 1-83 alphanumeric characters long, US-ASCII lowercase."
                     .to_string(),
             )?;
-            create_new_blockchain(config, addr_label)
+            let mnemonic = match sm.value_of("mnemonic") {
+                Some(phrase) => Mnemonic::from_phrase(phrase)
+                    .ok_or("Mnemonic must be 12 or 24 valid words.".to_string())?,
+                None => Mnemonic::random(rand::thread_rng()),
+            };
+            create_new_blockchain(config, addr_label, mnemonic)
                 .await
                 .map_err(|e| format!("Failed to create a new blockchain {:?}", e))?;
         }
         ("wallet", Some(wallet)) => {}
+        ("tx", Some(sm)) => {
+            let result = match sm.subcommand() {
+                ("decode", Some(args)) => inspect::decode_tx(required(args, "hex")),
+                ("verify", Some(args)) => {
+                    inspect::verify_tx(&state_path(&config, args), required(args, "hex"))
+                }
+                _ => unreachable!("Subcommand is required"),
+            }?;
+            println!("{}", json::to_json(&result));
+        }
+        ("block", Some(sm)) => {
+            let result = match sm.subcommand() {
+                ("verify", Some(args)) => {
+                    inspect::verify_block(&state_path(&config, args), required(args, "hex"))
+                }
+                _ => unreachable!("Subcommand is required"),
+            }?;
+            println!("{}", json::to_json(&result));
+        }
         ("run", Some(sm)) => {
             run(config)
                 .await
@@ -111,12 +194,31 @@ async fn launch() -> Result<(), String> {
 async fn create_new_blockchain(
     config: Config,
     addr_label: AddressLabel,
+    mnemonic: Mnemonic,
 ) -> Result<BlockchainIdle, Error> {
-    let xprv = Xprv::random(rand::thread_rng());
+    let version = MnemonicVersion::LATEST;
+    let xprv = mnemonic.to_xprv(version);
     let xpub = xprv.to_xpub();
-    let wallet = Wallet::new(addr_label, xpub);
+    let network_key = bc::network_signing_key(&xprv);
+    let wallet = Wallet::new(addr_label.clone(), xpub);
     let wallet_manager = WalletManager::new(config.clone())?;
     wallet_manager.read().await.save_xprv(xprv)?;
+    let saved = wallet_manager.read().await.save_mnemonic(&WalletBackup {
+        mnemonic: mnemonic.clone(),
+        version,
+        address_label: addr_label,
+    })?;
+    if !saved {
+        eprintln!(
+            "Mnemonic is not stored: set {} to keep it encrypted for the wallet backup.",
+            wallet_manager::WALLET_PASSPHRASE_VAR
+        );
+    }
+
+    // Derive the p2p identity from the same mnemonic.
+    let identity =
+        NodeIdentity::from_private_key(PrivateKey::from(mnemonic.to_identity_key(version)));
+    bc::save_identity(&config, &identity)?;
     wallet_manager.write().await.initialize_wallet(wallet)?;
 
     // Initialize blockchain.
@@ -132,7 +234,7 @@ async fn create_new_blockchain(
     })?;
 
     // Save the blockchain state.
-    let bc = Blockchain::new(config)?.init(bc_state)?;
+    let bc = Blockchain::new(config)?.init(bc_state, network_key)?;
     Ok(bc)
 }
 
@@ -143,13 +245,41 @@ async fn run(config: Config) -> Result<(), Error> {
     // 2. Create a wallet
     let wallet = WalletManager::new(config.clone())?;
 
+    // 2. Set up the faucet that issues the test asset via the API
+    let faucet_ref = if !config.data.faucet.disabled {
+        let alias = config.data.faucet.asset_alias.clone();
+        let token = wallet
+            .write()
+            .await
+            .update_wallet(|w| Ok(w.create_asset(alias)))?;
+        Some(Arc::new(Mutex::new(Faucet::new(&config, token.flavor()))))
+    } else {
+        None
+    };
+    let faucet_process = faucet_ref.clone().map(|faucet| {
+        let conf = config.clone();
+        let bc = bc_ref.clone();
+        let wm = wallet.clone();
+        tokio::spawn(async move { faucet::launch(conf, faucet, bc, wm).await })
+    });
+
+    // 2. Report the double-spends of the wallet's pending payments
+    {
+        let bc = bc_ref.clone();
+        let wm = wallet.clone();
+        tokio::spawn(async move { wallet_manager::watch_double_spends(wm, bc).await });
+    }
+
     // 2. Spawn the API server
     let addr = config.data.api.listen;
     let api_process = if !config.data.api.disabled {
         let conf = config.clone();
         let bc = bc_ref.clone();
         let wm = wallet.clone();
-        Some(tokio::spawn(async move { api::launch(conf, bc, wm).await }))
+        let faucet = faucet_ref.clone();
+        Some(tokio::spawn(async move {
+            api::launch(conf, bc, wm, faucet).await
+        }))
     } else {
         None
     };
@@ -167,6 +297,16 @@ async fn run(config: Config) -> Result<(), Error> {
         None
     };
 
+    // 4. Spawn the delegated proving service
+    let prover_process = if !config.data.prover.disabled {
+        let identity = bc::load_identity(&config)?;
+        let service = ProverService::new(&config, *identity.private_key())?;
+        let addr = config.data.prover.listen;
+        Some(tokio::spawn(async move { service.launch(addr).await }))
+    } else {
+        None
+    };
+
     // Join all the tasks.
     if let Some(handle) = ui_process {
         handle.await.unwrap();
@@ -174,12 +314,30 @@ async fn run(config: Config) -> Result<(), Error> {
     if let Some(handle) = api_process {
         handle.await.unwrap();
     }
+    if let Some(handle) = prover_process {
+        handle.await.unwrap()?;
+    }
+    if let Some(handle) = faucet_process {
+        handle.await.unwrap();
+    }
 
     // Shut down blockchain stack
     bc_ref.as_ref().read().await.stop().await;
     Ok(())
 }
 
+/// Returns the value of the required argument.
+fn required<'a>(args: &'a clap::ArgMatches, name: &str) -> &'a str {
+    args.value_of(name).expect("This is a required argument")
+}
+
+/// Returns the path to the state snapshot, defaulting to the node's own state.
+fn state_path(config: &Config, args: &clap::ArgMatches) -> PathBuf {
+    args.value_of("state")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| config.blockchain_state_filepath())
+}
+
 fn show_config(config: &Config) {
     println!("Using {}\n", config.path.display());
     println!("Resolved configuration:\n");
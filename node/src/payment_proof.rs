@@ -0,0 +1,127 @@
+//! Payment proofs for resolving disputes about the payments.
+//!
+//! A payment proof shows that a transaction included in a given block
+//! created an output paying the value specified by the receiver.
+//! The proof consists of two Merkle paths: from the transaction ID to the block's `txidroot`,
+//! and from the output entry of the transaction log to the transaction ID.
+//! The receiver's blinding factors open the value commitments of the output,
+//! so the verifier learns the paid value, but nothing else about the payer's or the payee's wallet.
+//!
+//! The verifier must check the proof against the block header it trusts,
+//! e.g. the one stored by its own node.
+
+use serde::{Deserialize, Serialize};
+
+use accounts::Receiver;
+use blockchain::{BlockHeader, VerifiedBlock};
+use zkvm::merkle;
+use zkvm::{ClearValue, Contract, PortableItem, TxEntry, TxID};
+
+use crate::errors::Error;
+
+/// Proof that the transaction in a block paid to the receiver.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PaymentProof {
+    /// Height of the block that includes the transaction.
+    pub height: u64,
+
+    /// ID of the transaction.
+    pub txid: TxID,
+
+    /// Merkle path from the transaction ID to the block's `txidroot`.
+    pub tx_path: merkle::Path,
+
+    /// Index of the output entry in the transaction log.
+    pub output_index: usize,
+
+    /// Output contract paying to the receiver.
+    pub output: Contract,
+
+    /// Merkle path from the output entry to the transaction ID.
+    pub output_path: merkle::Path,
+
+    /// Receiver with the blinding factors that open the output's value.
+    pub receiver: Receiver,
+}
+
+impl PaymentProof {
+    /// Creates the proof for the output of the transaction in the block that pays to the receiver.
+    pub fn new(block: &VerifiedBlock, txid: &TxID, receiver: Receiver) -> Result<Self, Error> {
+        let tx_index = block.tx_index(txid).ok_or(Error::InvalidPaymentProof(
+            "transaction is not in the block",
+        ))?;
+        let tx_path = block
+            .tx_inclusion_proof(tx_index)
+            .ok_or(Error::InvalidPaymentProof(
+                "transaction is not in the block",
+            ))?;
+        let log = &block.verified_txs[tx_index].log;
+        let (output_index, output) = log
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| match entry {
+                TxEntry::Output(contract) => Some((i, contract)),
+                _ => None,
+            })
+            .find(|(_, contract)| pays_to(contract, &receiver))
+            .ok_or(Error::InvalidPaymentProof(
+                "transaction does not pay to the receiver",
+            ))?;
+        let output_path = merkle::Path::new(&log[..], output_index, &txid_hasher())
+            .expect("index of the existing entry is always valid");
+
+        Ok(PaymentProof {
+            height: block.header.height,
+            txid: *txid,
+            tx_path,
+            output_index,
+            output: output.clone(),
+            output_path,
+            receiver,
+        })
+    }
+
+    /// Verifies the proof against the trusted block header at the proof's height.
+    /// Returns the paid value.
+    pub fn verify(&self, header: &BlockHeader) -> Result<ClearValue, Error> {
+        if header.height != self.height {
+            return Err(Error::InvalidPaymentProof("block height mismatch"));
+        }
+        if !header.verify_tx_inclusion(&self.txid, &self.tx_path) {
+            return Err(Error::InvalidPaymentProof(
+                "transaction is not in the block",
+            ));
+        }
+        let entry = TxEntry::Output(self.output.clone());
+        if !self
+            .output_path
+            .verify_root(&self.txid.0, &entry, &txid_hasher())
+        {
+            return Err(Error::InvalidPaymentProof(
+                "output is not in the transaction",
+            ));
+        }
+        if !pays_to(&self.output, &self.receiver) {
+            return Err(Error::InvalidPaymentProof(
+                "output does not pay to the receiver",
+            ));
+        }
+        Ok(self.receiver.value)
+    }
+}
+
+/// Checks that the contract is locked by the receiver's predicate
+/// and holds exactly the receiver's value.
+fn pays_to(contract: &Contract, receiver: &Receiver) -> bool {
+    if contract.predicate.to_point() != receiver.opaque_predicate {
+        return false;
+    }
+    match &contract.payload[..] {
+        [PortableItem::Value(value)] => receiver.verify_value(value),
+        _ => false,
+    }
+}
+
+fn txid_hasher() -> merkle::Hasher<TxEntry> {
+    merkle::Hasher::new(b"ZkVM.txid")
+}
@@ -0,0 +1,214 @@
+//! Delegated proving service.
+//!
+//! Wallets that cannot afford to create R1CS proofs locally (e.g. on mobile devices)
+//! send witness bundles to a node over an authenticated cybershake channel.
+//! The node creates the proof and returns the unsigned transaction.
+//! Predicate witnesses are never serialized, so the node never learns the signing keys:
+//! the client re-attaches its predicate trees and signs the transaction itself.
+//! Note that the node does learn the openings of the commitments in the bundle.
+//!
+//! Each message is a bincode-encoded `WitnessBundle` (request) or `ProvingResponse` (reply)
+//! prefixed with its length encoded as LE32. Requests are limited by the service's
+//! `max_bundle_size`, and responses by `MAX_RESPONSE_SIZE`.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use rand::thread_rng;
+use serde::{Deserialize, Serialize};
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task;
+
+use p2p::cybershake;
+use p2p::PeerID;
+use zkvm::bulletproofs::BulletproofGens;
+use zkvm::{Prover, UnsignedTx, WitnessBundle};
+
+use crate::config::Config;
+use crate::errors::Error;
+
+/// Maximum size of the response accepted by the client.
+/// The proven transaction contains the program of the bundle and the proof,
+/// which fit into twice the service's default bundle limit.
+pub const MAX_RESPONSE_SIZE: usize = 2_000_000;
+
+/// Response of the proving service to a witness bundle.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ProvingResponse {
+    /// Transaction is proven and awaits the client's signature.
+    Proved(UnsignedTx),
+
+    /// Bundle was rejected or the proof could not be created.
+    Failed(String),
+}
+
+/// Proving service accepting witness bundles from the clients.
+#[derive(Clone)]
+pub struct ProverService {
+    identity: cybershake::PrivateKey,
+    allowed_clients: Vec<PeerID>,
+    max_bundle_size: usize,
+    bp_gens: Arc<BulletproofGens>,
+}
+
+impl ProverService {
+    /// Creates the proving service with the node's identity key.
+    pub fn new(config: &Config, identity: cybershake::PrivateKey) -> Result<Self, Error> {
+        let allowed_clients = config
+            .data
+            .prover
+            .allowed_clients
+            .iter()
+            .map(|id| PeerID::from_string(id).ok_or_else(|| Error::InvalidPeerID(id.clone())))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ProverService {
+            identity,
+            allowed_clients,
+            max_bundle_size: config.data.prover.max_bundle_size,
+            bp_gens: Arc::new(BulletproofGens::new(config.data.prover.gens_capacity, 1)),
+        })
+    }
+
+    /// Listens for the clients on a given address and serves each one in a separate task.
+    pub async fn launch(self, addr: SocketAddr) -> Result<(), Error> {
+        let mut listener = TcpListener::bind(addr).await?;
+        println!(
+            "Proving service is listening on {} with peer ID: {}",
+            listener.local_addr()?,
+            PeerID::from(self.identity.to_public_key())
+        );
+        loop {
+            let (stream, addr) = listener.accept().await?;
+            let service = self.clone();
+            task::spawn(async move {
+                if let Err(e) = service.serve_client(stream).await {
+                    println!("\n=> Proving service client {} failed: {}", addr, e);
+                }
+            });
+        }
+    }
+
+    async fn serve_client(self, stream: TcpStream) -> Result<(), io::Error> {
+        let (r, w) = io::split(stream);
+        let (client_key, mut outgoing, mut incoming) =
+            cybershake::cybershake(&self.identity, Box::pin(r), Box::pin(w), thread_rng()).await?;
+
+        let client_id = PeerID::from(client_key);
+        if !self.allowed_clients.is_empty() && !self.allowed_clients.contains(&client_id) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "Client {} is not allowed to use the proving service",
+                    client_id
+                ),
+            ));
+        }
+
+        while let Some(bytes) = read_frame(&mut incoming, self.max_bundle_size).await? {
+            let response = match bincode::deserialize::<WitnessBundle>(&bytes) {
+                Ok(bundle) => self.prove(bundle).await,
+                Err(e) => ProvingResponse::Failed(format!("Malformed witness bundle: {}", e)),
+            };
+            let bytes = bincode::serialize(&response).map_err(invalid_data)?;
+            write_frame(&mut outgoing, &bytes).await?;
+        }
+        Ok(())
+    }
+
+    async fn prove(&self, bundle: WitnessBundle) -> ProvingResponse {
+        let multipliers = bundle
+            .program
+            .estimated_circuit_size_for_version(bundle.header.version)
+            .multipliers;
+        if multipliers > self.bp_gens.gens_capacity {
+            return ProvingResponse::Failed(format!(
+                "Transaction needs {} multipliers, the service supports up to {}",
+                multipliers, self.bp_gens.gens_capacity
+            ));
+        }
+        // Proving is CPU-heavy, so it should not block the other clients.
+        let bp_gens = self.bp_gens.clone();
+        let result = task::spawn_blocking(move || Prover::build_tx_from_bundle(bundle, &bp_gens))
+            .await
+            .expect("panic on JoinError");
+        match result {
+            Ok(utx) => ProvingResponse::Proved(utx),
+            Err(e) => ProvingResponse::Failed(e.to_string()),
+        }
+    }
+}
+
+/// Sends the witness bundle to a proving service with a given identity
+/// and returns the proven transaction with the predicate trees re-attached, ready to be signed.
+pub async fn request_proof(
+    addr: SocketAddr,
+    service_id: PeerID,
+    local_identity: &cybershake::PrivateKey,
+    bundle: &WitnessBundle,
+) -> Result<UnsignedTx, Error> {
+    let stream = TcpStream::connect(addr).await?;
+    let (r, w) = io::split(stream);
+    let (_, mut outgoing, mut incoming) = cybershake::cybershake_with_expected_peer(
+        local_identity,
+        Some(service_id.0),
+        Box::pin(r),
+        Box::pin(w),
+        thread_rng(),
+    )
+    .await
+    .map_err(io::Error::from)?;
+
+    write_frame(&mut outgoing, &bincode::serialize(bundle)?).await?;
+    let bytes = read_frame(&mut incoming, MAX_RESPONSE_SIZE)
+        .await?
+        .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+    match bincode::deserialize(&bytes)? {
+        ProvingResponse::Proved(mut utx) => {
+            bundle.attach_predicate_trees(&mut utx);
+            Ok(utx)
+        }
+        ProvingResponse::Failed(msg) => Err(Error::ProvingFailed(msg)),
+    }
+}
+
+/// Reads a length-prefixed frame of at most `max_len` bytes.
+/// Returns `None` if the remote party closed the connection.
+/// The buffer grows as the bytes arrive, so the remote party cannot make the reader
+/// allocate the declared length without sending it.
+async fn read_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    max_len: usize,
+) -> Result<Option<Vec<u8>>, io::Error> {
+    let mut len_buf = [0u8; 4];
+    match reader.read_exact(&mut len_buf).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_le_bytes(len_buf) as usize;
+    if len > max_len {
+        return Err(invalid_data(format!(
+            "Message of {} bytes exceeds the limit of {} bytes",
+            len, max_len
+        )));
+    }
+    let mut buf = Vec::new();
+    reader.take(len as u64).read_to_end(&mut buf).await?;
+    if buf.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(Some(buf))
+}
+
+async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, bytes: &[u8]) -> Result<(), io::Error> {
+    writer
+        .write_all(&(bytes.len() as u32).to_le_bytes())
+        .await?;
+    writer.write_all(bytes).await?;
+    writer.flush().await
+}
+
+fn invalid_data<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
@@ -13,11 +13,13 @@ use std::time::SystemTime;
 
 use std::collections::HashMap;
 
+use serde::Serialize;
 use tera::Tera;
 use warp::Filter;
 use warp::{filters::BoxedFilter, reply::Reply};
 
 use crate::bc::BlockchainRef;
+use crate::events::{BlockEvent, EventBus, MempoolEvent, PeerEvent, Received, Topic, WalletEvent};
 
 /// UI controller for each request.
 #[derive(Clone, Debug)]
@@ -40,9 +42,15 @@ impl UI {
     /// /mempool        -> List mempool txs
     /// /tx/:id         -> Tx details and status (confirmed, mempool, dropped)
     ///
-    /// /ws             -> websocket notifications
+    /// /ws             -> websocket notifications (events of all topics as JSON objects)
     pub async fn launch(config: Config, bc: BlockchainRef, wm: WalletRef) {
         let conf = &config.data.ui;
+        let ws_pool = Arc::new(ws::WebsocketPool::default());
+        let events = bc.read().await.events().clone();
+        forward_events::<BlockEvent>(&events, ws_pool.clone());
+        forward_events::<MempoolEvent>(&events, ws_pool.clone());
+        forward_events::<PeerEvent>(&events, ws_pool.clone());
+        forward_events::<WalletEvent>(&events, ws_pool.clone());
         let ui = UI {
             bc,
             wm,
@@ -50,17 +58,16 @@ impl UI {
         };
 
         eprintln!("UI:  http://{}", &conf.listen);
-        warp::serve(ui.into_routes()).run(conf.listen).await;
+        warp::serve(ui.into_routes(ws_pool)).run(conf.listen).await;
     }
 
     /// Converts the UI controller into the warp filter.
-    pub fn into_routes(self) -> BoxedFilter<(impl Reply,)> {
+    pub fn into_routes(self, ws_pool: Arc<ws::WebsocketPool>) -> BoxedFilter<(impl Reply,)> {
         let index = warp::get()
             .and(warp::path::end())
             .and(self.as_filter())
             .and_then(|ui: UI| async move { ui.render("index.html") });
 
-        let ws_pool = Arc::new(ws::WebsocketPool::default());
         let ws_route = warp::path("ws")
             .and(warp::any().map(move || ws_pool.clone()))
             .and(warp::ws())
@@ -141,3 +148,22 @@ impl UI {
         }
     }
 }
+
+/// Forwards the events of a topic to all the websockets as JSON objects
+/// tagged with the topic name.
+/// If the UI falls behind, the websockets receive a `lagged` event with the number of missed events.
+fn forward_events<T: Topic + Serialize>(events: &EventBus, ws_pool: Arc<ws::WebsocketPool>) {
+    let mut subscription = events.subscribe::<T>();
+    tokio::task::spawn(async move {
+        while let Some(received) = subscription.recv().await {
+            let mut json = match received {
+                Received::Event(event) => serde_json::to_value(&event).unwrap_or_default(),
+                Received::Lagged(missed) => json!({ "type": "lagged", "missed": missed }),
+            };
+            if let Some(object) = json.as_object_mut() {
+                object.insert("topic".to_string(), T::NAME.into());
+                ws_pool.broadcast(json.to_string()).await;
+            }
+        }
+    });
+}
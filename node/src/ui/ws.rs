@@ -66,4 +66,13 @@ impl WebsocketPool {
         // Connect is closed, so we remove it from the map.
         self.conn_map.write().await.remove(&id);
     }
+
+    /// Sends the text message to all connected websockets.
+    pub async fn broadcast(&self, text: String) {
+        for tx in self.conn_map.read().await.values() {
+            if let Err(_disconnected) = tx.send(Ok(Message::text(text.clone()))) {
+                // If we cannot send, it means the connection is dropped and dealt with in its own task.
+            }
+        }
+    }
 }
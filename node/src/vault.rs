@@ -0,0 +1,181 @@
+//! Vaults of the node's wallet: funds that the owner can withdraw only after a waiting period,
+//! during which the recovery key can claw them back (see `accounts::contracts::vault`).
+//!
+//! The node does not track the vaulted funds: each operation returns the `VaultUtxo`
+//! that the client passes to the next one, together with the vault and the key sequence.
+
+use accounts::contracts::vault::{Vault, VaultStage, VaultUtxo};
+use accounts::{Receiver, Sequence};
+use musig::VerificationKey;
+use zkvm::bulletproofs::BulletproofGens;
+use zkvm::{ClearValue, Predicate, TxID, TxLog};
+
+use crate::bc::BlockchainRef;
+use crate::errors::Error;
+use crate::wallet_manager::WalletRef;
+
+/// Creates a vault owned by the wallet, deposits the value into it and submits the transaction.
+/// Returns the deposit transaction ID, the sequence of the owner key, the vault and the deposited funds.
+pub async fn create(
+    recovery_key: VerificationKey,
+    delay_ms: u64,
+    value: ClearValue,
+    bp_gens: &BulletproofGens,
+    bc: &BlockchainRef,
+    wallet: &WalletRef,
+) -> Result<(TxID, Sequence, Vault, VaultUtxo), Error> {
+    let mut wm = wallet.write().await;
+    let xprv = wm.read_xprv()?;
+    let (block_tx, seq, vault, utxo) = wm.update_wallet(|w| {
+        let (seq, vault) = w.create_vault(recovery_key, delay_ms);
+        let mut receiver = None;
+        let built = w.build_tx(bp_gens, |b| {
+            receiver = Some(b.deposit_to_vault(value, &vault));
+        })?;
+        let receiver = receiver.expect("Deposit receiver is created by the tx builder.");
+        let utxo = vault_utxo(
+            &built.unsigned_tx.txlog,
+            &vault.predicate(),
+            receiver,
+            VaultStage::Locked,
+        );
+        Ok((built.sign(&xprv)?, seq, vault, utxo))
+    })?;
+    let txid = bc.write().await.submit_tx(block_tx, bp_gens)?;
+    Ok((txid, seq, vault, utxo))
+}
+
+/// Creates a new key of the wallet for recovering the funds from the vaults.
+pub async fn create_recovery_key(wallet: &WalletRef) -> Result<(Sequence, VerificationKey), Error> {
+    wallet
+        .write()
+        .await
+        .update_wallet(|w| Ok(w.create_recovery_key()))
+}
+
+/// Initiates the withdrawal of the vaulted funds with the owner key at a given sequence,
+/// and submits the transaction. Returns the transaction ID and the funds in the waiting period.
+pub async fn unvault(
+    vault: Vault,
+    seq: Sequence,
+    utxo: VaultUtxo,
+    unlock_time_ms: u64,
+    bp_gens: &BulletproofGens,
+    bc: &BlockchainRef,
+    wallet: &WalletRef,
+) -> Result<(TxID, VaultUtxo), Error> {
+    let mut wm = wallet.write().await;
+    let xprv = wm.read_xprv()?;
+    let (block_tx, pending) = wm.update_wallet(|w| {
+        let built = w.build_tx(bp_gens, |b| b.unvault(vault, seq, utxo, unlock_time_ms))?;
+        let pending = vault_utxo(
+            &built.unsigned_tx.txlog,
+            &vault.unvaulting_predicate(),
+            utxo.receiver,
+            VaultStage::Unvaulting { unlock_time_ms },
+        );
+        Ok((built.sign(&xprv)?, pending))
+    })?;
+    let txid = bc.write().await.submit_tx(block_tx, bp_gens)?;
+    Ok((txid, pending))
+}
+
+/// Completes the withdrawal of the vaulted funds into the wallet with the owner key
+/// at a given sequence, and submits the transaction.
+pub async fn withdraw(
+    vault: Vault,
+    seq: Sequence,
+    utxo: VaultUtxo,
+    bp_gens: &BulletproofGens,
+    bc: &BlockchainRef,
+    wallet: &WalletRef,
+) -> Result<TxID, Error> {
+    let mut wm = wallet.write().await;
+    let xprv = wm.read_xprv()?;
+    let block_tx = wm.update_wallet(|w| {
+        let tx = w
+            .build_tx(bp_gens, |b| b.withdraw_from_vault(vault, seq, utxo))?
+            .sign(&xprv)?;
+        Ok(tx)
+    })?;
+    bc.write().await.submit_tx(block_tx, bp_gens)
+}
+
+/// Claws back the vaulted funds into the wallet with the recovery key
+/// at a given sequence, and submits the transaction.
+pub async fn recover(
+    vault: Vault,
+    seq: Sequence,
+    utxo: VaultUtxo,
+    bp_gens: &BulletproofGens,
+    bc: &BlockchainRef,
+    wallet: &WalletRef,
+) -> Result<TxID, Error> {
+    let mut wm = wallet.write().await;
+    let xprv = wm.read_xprv()?;
+    let block_tx = wm.update_wallet(|w| {
+        let tx = w
+            .build_tx(bp_gens, |b| b.recover_from_vault(vault, seq, utxo))?
+            .sign(&xprv)?;
+        Ok(tx)
+    })?;
+    bc.write().await.submit_tx(block_tx, bp_gens)
+}
+
+/// Describes the vaulted funds locked by the transaction under a given predicate.
+fn vault_utxo(
+    txlog: &TxLog,
+    predicate: &Predicate,
+    receiver: Receiver,
+    stage: VaultStage,
+) -> VaultUtxo {
+    let output = txlog
+        .outputs()
+        .find(|contract| contract.predicate.to_point() == predicate.to_point())
+        .expect("Vault output is created by the transaction.");
+    VaultUtxo {
+        receiver,
+        anchor: output.anchor,
+        stage,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use curve25519_dalek::scalar::Scalar;
+    use zkvm::{Anchor, Contract, PortableItem, TxEntry};
+
+    #[test]
+    fn finds_the_vault_output() {
+        let key = |n: u64| VerificationKey::from_secret(&Scalar::from(n));
+        let vault = Vault::new(key(1), key(2), 500, [7u8; 32]);
+        let value = ClearValue {
+            qty: 10,
+            flv: Scalar::from(3u64),
+        };
+        let receiver = vault.receiver(value, Scalar::from(4u64), Scalar::from(5u64));
+        let output = |predicate: Predicate, anchor: u8| {
+            TxEntry::Output(Contract {
+                predicate,
+                payload: vec![PortableItem::Value(receiver.blinded_value())],
+                anchor: Anchor([anchor; 32]),
+            })
+        };
+        let txlog = TxLog::from(vec![
+            output(Predicate::new(key(3)), 1),
+            output(vault.predicate(), 2),
+        ]);
+
+        let utxo = vault_utxo(&txlog, &vault.predicate(), receiver, VaultStage::Locked);
+        assert_eq!(utxo.anchor, Anchor([2; 32]));
+        assert_eq!(utxo.stage, VaultStage::Locked);
+        assert_eq!(
+            vault.contract(&utxo).id(),
+            match &txlog[1] {
+                TxEntry::Output(contract) => contract.id(),
+                _ => unreachable!(),
+            }
+        );
+    }
+}
@@ -0,0 +1,88 @@
+//! Blind issuance of vouchers by the node's wallet.
+//!
+//! The wallet acts as the issuer of the vouchers for its assets (see `token::Voucher`).
+//! Each value has its own voucher key derived from the asset alias and the quantity,
+//! so the signature alone determines what the voucher can be redeemed for.
+//! Only one signing session per voucher key is kept open at a time:
+//! starting a new session discards the pending one, which protects the issuer
+//! from the forgery via concurrent sessions.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::scalar::Scalar;
+use rand::thread_rng;
+use tokio::sync::Mutex;
+
+use accounts::Receiver;
+use musig::VerificationKey;
+use token::{BlindSigner, Voucher, XprvDerivation};
+use zkvm::bulletproofs::BulletproofGens;
+use zkvm::TxID;
+
+use crate::bc::BlockchainRef;
+use crate::errors::Error;
+use crate::wallet::WalletError;
+use crate::wallet_manager::WalletRef;
+
+/// Reference to the voucher issuer.
+pub type VoucherIssuerRef = Arc<Mutex<VoucherIssuer>>;
+
+/// Pending blind signing sessions of the wallet's voucher keys.
+#[derive(Default)]
+pub struct VoucherIssuer {
+    sessions: HashMap<CompressedRistretto, BlindSigner>,
+}
+
+impl VoucherIssuer {
+    /// Starts the signing session for the voucher for a given value of the wallet's asset.
+    /// Returns the voucher key and the nonce commitment.
+    pub async fn start_signing(
+        &mut self,
+        wallet: &WalletRef,
+        flv: Scalar,
+        qty: u64,
+    ) -> Result<(VerificationKey, CompressedRistretto), Error> {
+        let wm = wallet.read().await;
+        let alias = wm
+            .wallet_ref()?
+            .find_asset(flv)
+            .map(|(alias, _)| alias.to_string())
+            .ok_or(WalletError::AssetNotFound)?;
+        let privkey = wm.read_xprv()?.voucher_key(&alias, qty);
+        let pubkey = VerificationKey::from_secret(&privkey);
+
+        let (signer, nonce_commitment) = BlindSigner::new(privkey, &mut thread_rng());
+        self.sessions.insert(pubkey.into_point(), signer);
+        Ok((pubkey, nonce_commitment))
+    }
+
+    /// Signs the blinded challenge, closing the session for the voucher key.
+    pub fn sign(&mut self, pubkey: VerificationKey, challenge: Scalar) -> Result<Scalar, Error> {
+        let signer = self
+            .sessions
+            .remove(pubkey.as_point())
+            .ok_or(Error::VoucherSessionNotFound)?;
+        Ok(signer.sign(challenge))
+    }
+}
+
+/// Issues the voucher's value to the receiver and submits the transaction.
+pub async fn redeem(
+    voucher: Voucher,
+    receiver: Receiver,
+    bp_gens: &BulletproofGens,
+    bc: &BlockchainRef,
+    wallet: &WalletRef,
+) -> Result<TxID, Error> {
+    let mut wm = wallet.write().await;
+    let xprv = wm.read_xprv()?;
+    let block_tx = wm.update_wallet(|w| {
+        let tx = w
+            .build_tx(bp_gens, |b| b.redeem_voucher(voucher, receiver))?
+            .sign(&xprv)?;
+        Ok(tx)
+    })?;
+    bc.write().await.submit_tx(block_tx, bp_gens)
+}
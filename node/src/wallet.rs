@@ -1,5 +1,5 @@
 use core::borrow::Borrow;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::mem;
 use thiserror::Error;
 
@@ -8,20 +8,27 @@ use curve25519_dalek::scalar::Scalar;
 use serde::{Deserialize, Serialize};
 use zkvm::bulletproofs::BulletproofGens;
 
-use accounts::{Address, AddressLabel, Receiver, Sequence, XprvDerivation, XpubDerivation};
+use accounts::contracts::order::{match_orders, Order, OrderError, OrderUtxo};
+use accounts::contracts::vault::{Vault, VaultError, VaultStage, VaultUtxo};
+use accounts::{
+    Address, AddressLabel, Invoice, InvoiceError, Receiver, Sequence, XprvDerivation,
+    XpubDerivation, MAX_INVOICE_MEMO_LEN,
+};
 use keytree::{Xprv, Xpub};
 use musig::{Multisignature, VerificationKey};
-use token::{Token, XprvDerivation as TKXprvDeriv, XpubDerivation as TKXpubDeriv};
+use token::{Token, Voucher, XprvDerivation as TKXprvDeriv, XpubDerivation as TKXpubDeriv};
 
 use blockchain::utreexo;
 use blockchain::{BlockTx, BlockchainState};
 use zkvm::{
-    self, Anchor, ClearValue, Contract, ContractID, PortableItem, Predicate, Program, TxLog,
-    UnsignedTx, VerifiedTx,
+    self, Anchor, ClearValue, Commitment, Contract, ContractID, PortableItem, Predicate,
+    PredicateTree, Program, TxLog, UnsignedTx, VerifiedTx,
 };
 
 use rand::{thread_rng, RngCore};
 
+use crate::contacts::ContactBook;
+
 /// Simple wallet implementation that keeps all data in a single serializable structure.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Wallet {
@@ -45,6 +52,25 @@ pub struct Wallet {
 
     /// List of registered assets mapped from the flavor to the asset alias.
     assets: HashMap<Scalar, String>,
+
+    /// Serial numbers of the vouchers redeemed by this wallet as the issuer.
+    #[serde(default)]
+    redeemed_vouchers: HashSet<[u8; 32]>,
+
+    /// Named counterparties and the payments sent to them.
+    #[serde(default)]
+    contacts: ContactBook,
+}
+
+/// State of the wallet rescan: the utxos collected while replaying the blocks.
+/// Created with `Wallet::begin_rescan` and applied with `Wallet::finish_rescan`.
+#[derive(Clone, Debug)]
+pub struct Rescan {
+    /// Confirmed utxos known to the wallet before the rescan, minus the ones spent in the replayed blocks.
+    known: HashMap<ContractID, Utxo>,
+
+    /// Utxos found in the replayed blocks, minus the ones spent afterwards.
+    found: HashMap<ContractID, Utxo>,
 }
 
 /// Balance of a certain asset that consists of a number of spendable UTXOs.
@@ -96,6 +122,30 @@ pub enum WalletError {
     /// to receive funds from another ledger.
     #[error("Address label is not expected by this wallet.")]
     AddressLabelMismatch,
+    /// Vault key does not match the key derived by this wallet at a given sequence.
+    #[error("Vault key does not match the wallet's key.")]
+    VaultKeyMismatch,
+    /// Imported utxo is not derived from the wallet's public key.
+    #[error("Imported utxo does not belong to this wallet.")]
+    ForeignUtxo,
+    /// Vault operation cannot be performed.
+    #[error("Vault operation failed: {0}")]
+    VaultError(VaultError),
+    /// Order cannot be filled.
+    #[error("Order operation failed: {0}")]
+    OrderError(OrderError),
+    /// Voucher is not signed by the wallet's voucher key for the requested value.
+    #[error("Voucher is not valid for the requested value.")]
+    InvalidVoucher,
+    /// Voucher with the same serial number was already redeemed.
+    #[error("Voucher was already redeemed.")]
+    VoucherAlreadyRedeemed,
+    /// Invoice cannot be created or paid.
+    #[error("Invoice error: {0}")]
+    InvoiceError(InvoiceError),
+    /// Receiver's predicate is not a valid key and cannot be made refundable.
+    #[error("Receiver's predicate is not a valid key.")]
+    InvalidReceiver,
 }
 
 /// Single-account tx builder API.
@@ -123,6 +173,8 @@ pub enum SigntxInstruction {
     Issue(Xpub, String),
     /// The key for input.
     Input(Xpub, Sequence),
+    /// The key for input locked by a predicate tree: the inner key and the tree's adjustment factor.
+    AdjustedInput(Xpub, Sequence, Scalar),
 }
 
 /// A high-level description of the tx action that
@@ -131,9 +183,16 @@ pub enum SigntxInstruction {
 enum TxAction {
     IssueToAddress(ClearValue, Address),
     IssueToReceiver(Receiver),
+    RedeemVoucher(Voucher, Receiver),
     TransferToAddress(ClearValue, Address),
     TransferToReceiver(Receiver),
+    Deadline(u64),
     Memo(Vec<u8>),
+    Unvault(Vault, Sequence, VaultUtxo, u64),
+    WithdrawFromVault(Vault, Sequence, VaultUtxo),
+    RecoverFromVault(Vault, Sequence, VaultUtxo),
+    PlaceOrder(Order, u64),
+    FillOrder(Order, OrderUtxo, u64),
 }
 
 /// Kind of the output: is it an incoming payment ("theirs") or a change ("ours")
@@ -160,9 +219,26 @@ impl Wallet {
             addresses: Default::default(),
             utxos: Default::default(),
             assets: Default::default(),
+            redeemed_vouchers: Default::default(),
+            contacts: Default::default(),
         }
     }
 
+    /// Returns the prefix used by addresses in this wallet.
+    pub fn address_label(&self) -> &AddressLabel {
+        &self.address_label
+    }
+
+    /// Returns the contact book of the wallet.
+    pub fn contacts(&self) -> &ContactBook {
+        &self.contacts
+    }
+
+    /// Returns the contact book of the wallet for modification.
+    pub fn contacts_mut(&mut self) -> &mut ContactBook {
+        &mut self.contacts
+    }
+
     /// Creates a new asset.
     pub fn create_asset(&mut self, alias: String) -> Token {
         let token = self.xpub.derive_token(&alias);
@@ -208,6 +284,59 @@ impl Wallet {
         (seq, recvr)
     }
 
+    /// Creates an invoice for a new receiver of this wallet, signed with the receiver's key.
+    /// Xprv must match the wallet's xprv.
+    pub fn create_invoice(
+        &mut self,
+        value: ClearValue,
+        expiration_ms: u64,
+        memo: String,
+        xprv: &Xprv,
+    ) -> Result<Invoice, WalletError> {
+        if xprv.as_xpub() != &self.xpub {
+            return Err(WalletError::XprvMismatch);
+        }
+        if memo.len() > MAX_INVOICE_MEMO_LEN {
+            return Err(WalletError::InvoiceError(InvoiceError::MemoTooLong));
+        }
+        let (seq, receiver) = self.create_receiver(value);
+        Invoice::sign(
+            self.address_label.clone(),
+            receiver,
+            expiration_ms,
+            memo,
+            xprv.key_at_sequence(seq),
+        )
+        .map_err(WalletError::InvoiceError)
+    }
+
+    /// Creates a new vault owned by this wallet, with a given recovery key and a waiting period.
+    /// Returns the sequence number of the owner key needed to unvault and withdraw the funds.
+    pub fn create_vault(
+        &mut self,
+        recovery_key: VerificationKey,
+        delay_ms: u64,
+    ) -> (Sequence, Vault) {
+        let seq = self.sequence;
+        self.sequence += 1;
+        let mut blinding_key = [0u8; 32];
+        thread_rng().fill_bytes(&mut blinding_key);
+        let vault = Vault::new(
+            self.xpub.key_at_sequence(seq),
+            recovery_key,
+            delay_ms,
+            blinding_key,
+        );
+        (seq, vault)
+    }
+
+    /// Creates a new key that can be used to recover funds from vaults.
+    pub fn create_recovery_key(&mut self) -> (Sequence, VerificationKey) {
+        let seq = self.sequence;
+        self.sequence += 1;
+        (seq, self.xpub.key_at_sequence(seq))
+    }
+
     /// Creates a blockchain seeded with the given values.
     pub fn seed_blockchain(
         &mut self,
@@ -301,6 +430,152 @@ impl Wallet {
         }
     }
 
+    /// Starts the rescan of the blocks, collecting the currently confirmed utxos.
+    pub fn begin_rescan(&self) -> Rescan {
+        let known = self
+            .utxos
+            .iter()
+            .filter(|(_, utxo)| utxo.confirmed || utxo.spent == Some(true))
+            .map(|(cid, utxo)| {
+                let mut utxo = utxo.clone();
+                utxo.confirmed = true;
+                utxo.spent = None;
+                (*cid, utxo)
+            })
+            .collect();
+        Rescan {
+            known,
+            found: HashMap::new(),
+        }
+    }
+
+    /// Returns the confirmed utxos for the export to another node.
+    /// Utxos spent by unconfirmed transactions are included, since they are still unspent on chain.
+    pub fn export_utxos(&self) -> Vec<Utxo> {
+        self.begin_rescan()
+            .known
+            .into_iter()
+            .map(|(_, utxo)| utxo)
+            .collect()
+    }
+
+    /// Starts the import of the utxos exported from another node.
+    /// The proofs of the imported utxos are updated with `rescan_block`
+    /// starting from the block that follows the export, and applied with `finish_import`.
+    /// Fails if any utxo is not derived from this wallet's public key.
+    pub fn begin_import(&self, utxos: Vec<Utxo>) -> Result<Rescan, WalletError> {
+        let mut rescan = self.begin_rescan();
+        for utxo in utxos.into_iter() {
+            if self.xpub.key_at_sequence(utxo.sequence).into_point()
+                != utxo.receiver.opaque_predicate
+            {
+                return Err(WalletError::ForeignUtxo);
+            }
+            let cid = utxo.contract_id();
+            rescan.known.remove(&cid);
+            rescan.found.insert(
+                cid,
+                Utxo {
+                    confirmed: true,
+                    spent: None,
+                    ..utxo
+                },
+            );
+        }
+        Ok(rescan)
+    }
+
+    /// Applies the imported utxos, dropping the ones that are not in the utreexo of the tip,
+    /// and advances the sequence number past the imported utxos.
+    /// Returns the number of added and removed utxos.
+    pub fn finish_import(
+        &mut self,
+        mut rescan: Rescan,
+        utreexo: &utreexo::Forest,
+    ) -> (usize, usize) {
+        let hasher = utreexo::utreexo_hasher();
+        rescan.found.retain(|cid, utxo| match &utxo.proof {
+            utreexo::Proof::Committed(path) => utreexo.verify(cid, path, &hasher).is_ok(),
+            utreexo::Proof::Transient => false,
+        });
+        if let Some(seq) = rescan.found.values().map(|utxo| utxo.sequence).max() {
+            self.sequence = self.sequence.max(seq + 1);
+        }
+        self.finish_rescan(rescan, utreexo)
+    }
+
+    /// Replays the transactions of the block, which must follow the previously replayed block.
+    pub fn rescan_block<T>(&self, rescan: &mut Rescan, txs: T, catchup: &utreexo::Catchup)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<VerifiedTx>,
+    {
+        for tx in txs.into_iter() {
+            let tx = tx.borrow();
+            for cid in tx.log.inputs() {
+                rescan.known.remove(cid);
+                rescan.found.remove(cid);
+            }
+            for c in tx.log.outputs() {
+                if let Some((seq, recvr, kind)) = self.receiver_for_output(c, &tx.log) {
+                    rescan.found.insert(
+                        c.id(),
+                        Utxo {
+                            receiver: recvr,
+                            sequence: seq,
+                            anchor: c.anchor,
+                            proof: utreexo::Proof::Transient,
+                            kind,
+                            confirmed: true,
+                            spent: None,
+                        },
+                    );
+                }
+            }
+        }
+
+        // Only the found utxos are tracked from block to block:
+        // the proofs of the known utxos are already up to date with the tip.
+        let hasher = utreexo::utreexo_hasher();
+        for (cid, utxo) in rescan.found.iter_mut() {
+            let mut current_proof = utreexo::Proof::Transient;
+            mem::swap(&mut utxo.proof, &mut current_proof);
+            utxo.proof = catchup
+                .update_proof(cid, current_proof, &hasher)
+                .unwrap_or(utreexo::Proof::Transient);
+        }
+    }
+
+    /// Replaces the confirmed utxos with the result of the rescan,
+    /// dropping the known utxos that are not in the utreexo of the tip.
+    /// Unconfirmed utxos are cleared and must be re-added with `add_unconfirmed_tx`.
+    /// Returns the number of added and removed utxos.
+    pub fn finish_rescan(&mut self, rescan: Rescan, utreexo: &utreexo::Forest) -> (usize, usize) {
+        let hasher = utreexo::utreexo_hasher();
+        let Rescan { known, found } = rescan;
+        let mut utxos = known
+            .into_iter()
+            .filter(|(cid, utxo)| match &utxo.proof {
+                utreexo::Proof::Committed(path) => utreexo.verify(cid, path, &hasher).is_ok(),
+                utreexo::Proof::Transient => false,
+            })
+            .collect::<HashMap<_, _>>();
+        utxos.extend(found);
+
+        self.clear_unconfirmed_utxos();
+        let added = utxos
+            .keys()
+            .filter(|cid| !self.utxos.contains_key(cid))
+            .count();
+        let removed = self
+            .utxos
+            .keys()
+            .filter(|cid| !utxos.contains_key(cid))
+            .count();
+        self.utxos = utxos;
+        (added, removed)
+    }
+
     /// Removes all unconfirmed utxos, so they can be re-created anew with `add_unconfirmed_tx` call.
     pub fn clear_unconfirmed_utxos(&mut self) {
         self.utxos.retain(|_, utxo| {
@@ -345,6 +620,16 @@ impl Wallet {
         }
     }
 
+    /// Returns true if a double-spend of the contract concerns this wallet: either the contract
+    /// is the wallet's utxo, or the contested transaction spending it pays this wallet.
+    pub fn is_watching(&self, contract_id: &ContractID, contested_tx: &VerifiedTx) -> bool {
+        self.utxos.contains_key(contract_id)
+            || contested_tx
+                .log
+                .outputs()
+                .any(|c| self.receiver_for_output(c, &contested_tx.log).is_some())
+    }
+
     /// Removes an unconfirmed transaction, which reverses the spent/unspent states of pending utxos.
     /// Important: the caller is responsible to call this method in reverse topological order (children removed before parents).
     pub fn remove_unconfirmed_tx(&mut self, tx: &VerifiedTx) {
@@ -408,7 +693,7 @@ impl Wallet {
             .iter()
             .filter_map(|action| match action {
                 TxAction::IssueToAddress(v, _a) => Some(*v),
-                TxAction::IssueToReceiver(r) => Some(r.value),
+                TxAction::IssueToReceiver(r) | TxAction::RedeemVoucher(_, r) => Some(r.value),
                 _ => None,
             })
             .try_fold(
@@ -426,6 +711,54 @@ impl Wallet {
                 },
             )?;
 
+        // Check the redeemed vouchers against the voucher keys for their values.
+        let mut vouchers = Vec::<(Voucher, VerificationKey)>::new();
+        for action in builder.actions.iter() {
+            if let TxAction::RedeemVoucher(voucher, receiver) = action {
+                let (alias, _) = self
+                    .find_asset(receiver.value.flv)
+                    .ok_or(WalletError::AssetNotFound)?;
+                let key = self.xpub.voucher_key(alias, receiver.value.qty);
+                voucher
+                    .verify(key)
+                    .map_err(|_| WalletError::InvalidVoucher)?;
+                if self.redeemed_vouchers.contains(&voucher.serial)
+                    || vouchers.iter().any(|(v, _)| v.serial == voucher.serial)
+                {
+                    return Err(WalletError::VoucherAlreadyRedeemed);
+                }
+                vouchers.push((*voucher, key));
+            }
+        }
+
+        // Collect placed orders and order fills with their payments.
+        let placed_orders = builder
+            .actions
+            .iter()
+            .filter_map(|action| match action {
+                TxAction::PlaceOrder(order, qty) => Some((*order, *qty)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let order_fills = builder
+            .actions
+            .iter()
+            .filter_map(|action| match action {
+                TxAction::FillOrder(order, utxo, fill_qty) => {
+                    if *fill_qty == 0 || *fill_qty > utxo.qty {
+                        return Some(Err(OrderError::InvalidFillQuantity));
+                    }
+                    Some(
+                        order
+                            .payment_for(*fill_qty)
+                            .map(|payment| (*order, *utxo, *fill_qty, payment)),
+                    )
+                }
+                _ => None,
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(WalletError::OrderError)?;
+
         // Collect transfers of each asset
         let grouped_transfers = builder
             .actions
@@ -435,6 +768,14 @@ impl Wallet {
                 TxAction::TransferToReceiver(r) => Some(r.value),
                 _ => None,
             })
+            .chain(placed_orders.iter().map(|(order, qty)| ClearValue {
+                qty: *qty,
+                flv: order.offer_flavor,
+            }))
+            .chain(order_fills.iter().map(|(order, _, _, payment)| ClearValue {
+                qty: *payment,
+                flv: order.ask_flavor,
+            }))
             .fold(HashMap::new(), |mut hm: HashMap<Scalar, u64>, value| {
                 *(hm.entry(value.flv).or_default()) += value.qty;
                 hm
@@ -459,6 +800,68 @@ impl Wallet {
             },
         )?;
 
+        // Collect vault operations together with the time bounds they need.
+        // Withdrawn and recovered values are sent to new receivers of this wallet.
+        let mut header = zkvm::TxHeader {
+            version: 1u64,
+            mintime_ms: 0u64,
+            maxtime_ms: u64::max_value(),
+        };
+        let mut vault_actions = Vec::<TxAction>::new();
+        let mut vault_items = Vec::<SigntxInstruction>::new();
+        for action in builder.actions.iter() {
+            match action {
+                TxAction::Unvault(vault, seq, utxo, unlock_time_ms) => {
+                    self.check_key_at_sequence(&vault.owner_key, *seq)?;
+                    if utxo.stage != VaultStage::Locked {
+                        return Err(WalletError::VaultError(VaultError::InvalidStage));
+                    }
+                    if *unlock_time_ms < vault.delay_ms {
+                        return Err(WalletError::VaultError(VaultError::UnlockTimeTooEarly));
+                    }
+                    header.maxtime_ms = header
+                        .maxtime_ms
+                        .min(unlock_time_ms.saturating_sub(vault.delay_ms));
+                    vault_items.push(SigntxInstruction::Input(self.xpub, *seq));
+                }
+                TxAction::WithdrawFromVault(vault, seq, utxo) => {
+                    self.check_key_at_sequence(&vault.owner_key, *seq)?;
+                    match utxo.stage {
+                        VaultStage::Unvaulting { unlock_time_ms } => {
+                            header.mintime_ms = header.mintime_ms.max(unlock_time_ms);
+                        }
+                        VaultStage::Locked => {
+                            return Err(WalletError::VaultError(VaultError::InvalidStage));
+                        }
+                    }
+                    vault_items.push(SigntxInstruction::Input(self.xpub, *seq));
+                }
+                TxAction::RecoverFromVault(vault, seq, utxo) => {
+                    self.check_key_at_sequence(&vault.recovery_key, *seq)?;
+                    vault_items.push(SigntxInstruction::AdjustedInput(
+                        self.xpub,
+                        *seq,
+                        vault.recovery_adjustment_factor(utxo.stage),
+                    ));
+                }
+                TxAction::Deadline(time_ms) => {
+                    header.maxtime_ms = header.maxtime_ms.min(*time_ms);
+                    continue;
+                }
+                _ => continue,
+            }
+            vault_actions.push(action.clone());
+        }
+        if header.mintime_ms > header.maxtime_ms {
+            // Unvaulting cannot be combined with a withdrawal that unlocks
+            // later than the unvaulted funds' unlock time minus the delay.
+            return Err(WalletError::VaultError(VaultError::UnlockTimeTooEarly));
+        }
+        let vault_inputs_count = vault_actions
+            .iter()
+            .filter(|action| !matches!(action, TxAction::Unvault(..)))
+            .count();
+
         let mut memos = Vec::<Vec<u8>>::new();
 
         // Collect all outputs, so we can shuffle them.
@@ -476,17 +879,40 @@ impl Wallet {
                         outs.push(recvr);
                         memos.push(ct);
                     }
-                    TxAction::IssueToReceiver(recvr) | TxAction::TransferToReceiver(recvr) => {
+                    TxAction::IssueToReceiver(recvr)
+                    | TxAction::TransferToReceiver(recvr)
+                    | TxAction::RedeemVoucher(_, recvr) => {
                         outs.push(recvr);
                     }
                     TxAction::Memo(buf) => {
                         memos.push(buf);
                     }
+                    TxAction::Unvault(..)
+                    | TxAction::PlaceOrder(..)
+                    | TxAction::FillOrder(..)
+                    | TxAction::Deadline(..) => {}
+                    TxAction::WithdrawFromVault(_, _, utxo)
+                    | TxAction::RecoverFromVault(_, _, utxo) => {
+                        let (_seq, recvr) = self.create_receiver(utxo.receiver.value);
+                        outs.push(recvr);
+                    }
                 }
                 Ok((outs, memos))
             },
         )?;
 
+        // Receive the values bought from the orders.
+        let bought_outputs = order_fills
+            .iter()
+            .map(|(order, _, fill_qty, _)| {
+                self.create_receiver(ClearValue {
+                    qty: *fill_qty,
+                    flv: order.offer_flavor,
+                })
+                .1
+            })
+            .collect::<Vec<_>>();
+
         // Canonically order memos and outputs so we do not leak the order of operations.
         memos.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
         outputs.sort_by(|a, b| {
@@ -502,7 +928,11 @@ impl Wallet {
         let program = zkvm::Program::build(|p| {
             // issue all the assets
             for (_flv, (_alias, token, qty)) in grouped_issuances.iter() {
-                token.issue(p, *qty);
+                token.issue(p, *qty, &mut rng);
+            }
+            // check the redeemed vouchers and log their serial numbers
+            for (voucher, key) in vouchers.iter() {
+                voucher.redeem(p, *key);
             }
             // spend all the selected utxos
             for utxo in inputs.iter() {
@@ -511,6 +941,20 @@ impl Wallet {
                 p.signtx();
             }
 
+            // spend and re-lock the vaulted funds
+            for action in vault_actions.iter() {
+                match action {
+                    TxAction::Unvault(vault, _, utxo, unlock_time_ms) => vault
+                        .unvault(p, utxo, *unlock_time_ms, header.maxtime_ms)
+                        .expect("Vault operations are checked above."),
+                    TxAction::WithdrawFromVault(vault, _, utxo) => vault
+                        .withdraw(p, utxo)
+                        .expect("Vault operations are checked above."),
+                    TxAction::RecoverFromVault(vault, _, utxo) => vault.recover(p, utxo),
+                    _ => {}
+                }
+            }
+
             // prepare outputs for cloak mixer
             for recvr in outputs.iter() {
                 let v = recvr.blinded_value();
@@ -518,8 +962,25 @@ impl Wallet {
                 p.push(v.flv);
             }
 
+            // prepare unblinded values for placing orders and paying for order fills
+            let order_values = placed_orders
+                .iter()
+                .map(|(order, qty)| (*qty, order.offer_flavor))
+                .chain(
+                    order_fills
+                        .iter()
+                        .map(|(order, _, _, payment)| (*payment, order.ask_flavor)),
+                );
+            for (qty, flv) in order_values {
+                p.push(Commitment::unblinded(qty));
+                p.push(Commitment::unblinded(flv));
+            }
+
             // merge/split assets
-            p.cloak(inputs.len(), outputs.len());
+            p.cloak(
+                inputs.len() + vault_inputs_count,
+                outputs.len() + placed_orders.len() + order_fills.len(),
+            );
 
             // lock outputs under new predicates
             for recvr in outputs.iter() {
@@ -527,23 +988,49 @@ impl Wallet {
                 p.output(1);
             }
 
+            // place orders
+            for (order, _) in placed_orders.iter() {
+                order.place(p);
+            }
+
+            // fill orders, keeping the bought values on the stack
+            for (i, (order, utxo, fill_qty, _)) in order_fills.iter().enumerate() {
+                if i > 0 {
+                    p.roll(i);
+                }
+                order
+                    .fill(p, utxo, *fill_qty)
+                    .expect("Order fills are checked above.");
+            }
+
+            // re-blind the bought values and lock them under new predicates
+            if !bought_outputs.is_empty() {
+                for recvr in bought_outputs.iter() {
+                    let v = recvr.blinded_value();
+                    p.push(v.qty);
+                    p.push(v.flv);
+                }
+                p.cloak(bought_outputs.len(), bought_outputs.len());
+                for recvr in bought_outputs.iter() {
+                    p.push(recvr.predicate());
+                    p.output(1);
+                }
+            }
+
             // write all the memos (including ciphertexts from spend-to-address)
             for memo in memos.into_iter() {
-                p.push(zkvm::String::Opaque(memo));
+                p.push(zkvm::String::Opaque(memo.into()));
                 p.log();
             }
         });
 
-        let header = zkvm::TxHeader {
-            version: 1u64,
-            mintime_ms: 0u64,
-            maxtime_ms: u64::max_value(),
-        };
-
         // Build the UnverifiedTx
         let unsigned_tx = zkvm::Prover::build_tx(program, header, &bp_gens)
             .expect("We are supposed to compose the program correctly.");
 
+        self.redeemed_vouchers
+            .extend(vouchers.iter().map(|(voucher, _)| voucher.serial));
+
         let issuing_items = grouped_issuances
             .iter()
             .map(|(_flv, (alias, _, _))| SigntxInstruction::Issue(self.xpub, alias.clone()));
@@ -551,7 +1038,10 @@ impl Wallet {
             .iter()
             .map(|utxo| SigntxInstruction::Input(self.xpub, utxo.sequence));
 
-        let signtx_items = issuing_items.chain(spending_items).collect::<Vec<_>>();
+        let signtx_items = issuing_items
+            .chain(spending_items)
+            .chain(vault_items.into_iter())
+            .collect::<Vec<_>>();
         let utreexo_proofs = inputs.into_iter().map(|utxo| utxo.proof).collect();
 
         Ok(BuiltTx {
@@ -596,6 +1086,19 @@ impl Wallet {
             .sign(xprv)
     }
 
+    /// Checks that the key is derived by this wallet at a given sequence number.
+    fn check_key_at_sequence(
+        &self,
+        key: &VerificationKey,
+        seq: Sequence,
+    ) -> Result<(), WalletError> {
+        if &self.xpub.key_at_sequence(seq) == key {
+            Ok(())
+        } else {
+            Err(WalletError::VaultKeyMismatch)
+        }
+    }
+
     /// Returns a pair of a sequence number and a receiver
     fn receiver_for_output(
         &self,
@@ -643,6 +1146,12 @@ impl TxBuilder {
     pub fn issue_to_receiver(&mut self, receiver: Receiver) {
         self.actions.push(TxAction::IssueToReceiver(receiver));
     }
+    /// Issues the voucher's value to the receiver.
+    /// The voucher must be signed by this wallet's voucher key for the receiver's value.
+    pub fn redeem_voucher(&mut self, voucher: Voucher, receiver: Receiver) {
+        self.actions
+            .push(TxAction::RedeemVoucher(voucher, receiver));
+    }
     /// Transfers the requested amount to the address.
     pub fn transfer_to_address(&mut self, value: ClearValue, address: Address) {
         self.actions
@@ -652,6 +1161,75 @@ impl TxBuilder {
     pub fn transfer_to_receiver(&mut self, receiver: Receiver) {
         self.actions.push(TxAction::TransferToReceiver(receiver));
     }
+    /// Pays the invoice's receiver. The transaction expires together with the invoice.
+    pub fn pay_invoice(&mut self, invoice: &Invoice) {
+        self.actions
+            .push(TxAction::TransferToReceiver(invoice.receiver));
+        self.actions.push(TxAction::Deadline(invoice.expiration_ms));
+    }
+    /// Pays the receiver with an output that this wallet can take back with the refund key
+    /// at or after the refund time, unless the receiver spends it first.
+    /// Returns the receiver of the refundable output, locked by the tree from `refundable_tree`.
+    pub fn refundable_output(
+        &mut self,
+        receiver: Receiver,
+        refund_key: VerificationKey,
+        refund_after_ms: u64,
+    ) -> Result<Receiver, WalletError> {
+        let tree = refundable_tree(&receiver, refund_key, refund_after_ms)?;
+        let receiver = Receiver {
+            opaque_predicate: tree.outer_key().into_point(),
+            ..receiver
+        };
+        self.actions.push(TxAction::TransferToReceiver(receiver));
+        Ok(receiver)
+    }
+    /// Deposits the requested amount into the vault.
+    /// Returns the receiver that, together with the output's anchor, describes the vaulted funds.
+    pub fn deposit_to_vault(&mut self, value: ClearValue, vault: &Vault) -> Receiver {
+        let mut rng = thread_rng();
+        let receiver = vault.receiver(value, Scalar::random(&mut rng), Scalar::random(&mut rng));
+        self.actions.push(TxAction::TransferToReceiver(receiver));
+        receiver
+    }
+    /// Initiates withdrawal from the vault that can be completed at the unlock time.
+    /// The vault's owner key must be derived by this wallet at the given sequence number.
+    pub fn unvault(&mut self, vault: Vault, seq: Sequence, utxo: VaultUtxo, unlock_time_ms: u64) {
+        self.actions
+            .push(TxAction::Unvault(vault, seq, utxo, unlock_time_ms));
+    }
+    /// Completes withdrawal from the vault into this wallet.
+    /// The vault's owner key must be derived by this wallet at the given sequence number.
+    pub fn withdraw_from_vault(&mut self, vault: Vault, seq: Sequence, utxo: VaultUtxo) {
+        self.actions
+            .push(TxAction::WithdrawFromVault(vault, seq, utxo));
+    }
+    /// Claws back the vaulted funds into this wallet.
+    /// The vault's recovery key must be derived by this wallet at the given sequence number.
+    pub fn recover_from_vault(&mut self, vault: Vault, seq: Sequence, utxo: VaultUtxo) {
+        self.actions
+            .push(TxAction::RecoverFromVault(vault, seq, utxo));
+    }
+    /// Places an order selling the requested quantity of the offered asset.
+    pub fn place_order(&mut self, order: Order, qty: u64) {
+        self.actions.push(TxAction::PlaceOrder(order, qty));
+    }
+    /// Buys the requested quantity from the order, paying with the asked asset.
+    pub fn fill_order(&mut self, order: Order, utxo: OrderUtxo, fill_qty: u64) {
+        self.actions
+            .push(TxAction::FillOrder(order, utxo, fill_qty));
+    }
+    /// Buys up to the requested quantity from the orders, best price first.
+    /// Returns the quantity that could not be matched.
+    pub fn buy_from_orders(&mut self, orders: &[(Order, OrderUtxo)], qty: u64) -> u64 {
+        let fills = match_orders(orders, qty);
+        let filled: u64 = fills.iter().map(|(_, fill_qty)| fill_qty).sum();
+        for (i, fill_qty) in fills {
+            let (order, utxo) = orders[i];
+            self.fill_order(order, utxo, fill_qty);
+        }
+        qty - filled
+    }
     /// Attaches free-form textual memo.
     pub fn memo(&mut self, memo: Vec<u8>) {
         self.actions.push(TxAction::Memo(memo));
@@ -675,6 +1253,9 @@ impl BuiltTx {
                         (xpub, xprv.issuing_key(alias.as_str()))
                     }
                     SigntxInstruction::Input(xpub, seq) => (xpub, xprv.key_at_sequence(seq)),
+                    SigntxInstruction::AdjustedInput(xpub, seq, factor) => {
+                        (xpub, xprv.key_at_sequence(seq) + factor)
+                    }
                 };
                 if &xpub != xprv.as_xpub() {
                     Err(WalletError::XprvMismatch)
@@ -743,3 +1324,21 @@ impl Utxo {
         self.receiver.value
     }
 }
+
+/// Creates the predicate tree of a refundable payment to the receiver:
+/// the receiver spends it with its key adjusted by the tree's adjustment factor,
+/// and the refund key can spend it at or after the refund time.
+/// The receiver ID is used as the blinding key, so both parties can recreate the tree.
+pub fn refundable_tree(
+    receiver: &Receiver,
+    refund_key: VerificationKey,
+    refund_after_ms: u64,
+) -> Result<PredicateTree, WalletError> {
+    PredicateTree::refundable(
+        Predicate::new(VerificationKey::from_compressed(receiver.opaque_predicate)),
+        Predicate::new(refund_key),
+        refund_after_ms,
+        *receiver.id().as_bytes(),
+    )
+    .map_err(|_| WalletError::InvalidReceiver)
+}
@@ -1,13 +1,22 @@
+use super::backup::{UtxoExport, WalletBackup};
+use super::bc::{self, BlockchainRef, BlockchainRunning};
 use super::config::Config;
 use super::errors::Error;
-use super::wallet::Wallet;
+use super::events::{MempoolEvent, Received, WalletEvent};
+use super::wallet::{Rescan, Wallet};
 use keytree::Xprv;
+use p2p::cybershake::PrivateKey;
+use p2p::NodeIdentity;
+use rand::thread_rng;
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+/// Environment variable that holds the passphrase for the stored mnemonic.
+pub const WALLET_PASSPHRASE_VAR: &'static str = "SLINGSHOT_WALLET_PASSPHRASE";
+
 /// Reference to the Blockchain instance
 pub type WalletRef = Arc<RwLock<WalletManager>>;
 
@@ -55,6 +64,13 @@ impl WalletManager {
         p
     }
 
+    /// Path to the mnemonic file
+    pub fn wallet_mnemonic_path(&self) -> PathBuf {
+        let mut p = self.config.wallet_path();
+        p.push("wallet.mnemonic");
+        p
+    }
+
     /// Returns a read-only reference to the wallet
     pub fn wallet_ref(&self) -> Result<&Wallet, Error> {
         self.wallet.as_ref().ok_or(Error::WalletNotInitialized)
@@ -72,6 +88,88 @@ impl WalletManager {
         Ok(())
     }
 
+    /// Reads the signing key of the wallet
+    pub fn read_xprv(&self) -> Result<Xprv, Error> {
+        let bytes = fs::read(self.wallet_keypath())?;
+        Xprv::from_bytes(&bytes).ok_or(Error::InvalidWalletKey)
+    }
+
+    /// Saves the mnemonic from which the wallet key is derived, encrypted in the backup format
+    /// with the passphrase from `SLINGSHOT_WALLET_PASSPHRASE`.
+    /// The mnemonic is never stored in the clear: returns false without saving it
+    /// if the passphrase is not set.
+    pub fn save_mnemonic(&self, backup: &WalletBackup) -> Result<bool, Error> {
+        let passphrase = match wallet_passphrase() {
+            Some(passphrase) => passphrase,
+            None => return Ok(false),
+        };
+        let path = self.wallet_mnemonic_path();
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder)?;
+        }
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(path)?
+            .write_all(&backup.encrypt(&passphrase, &mut thread_rng()))?;
+        Ok(true)
+    }
+
+    /// Reads the mnemonic from which the wallet key is derived.
+    /// Fails if the mnemonic is not stored or the passphrase is not set.
+    pub fn read_mnemonic(&self) -> Result<WalletBackup, Error> {
+        let path = self.wallet_mnemonic_path();
+        let passphrase = wallet_passphrase().ok_or(Error::MnemonicNotFound)?;
+        if !path.exists() {
+            return Err(Error::MnemonicNotFound);
+        }
+        WalletBackup::decrypt(&fs::read(path)?, &passphrase)
+    }
+
+    /// Exports the mnemonic and the address label of the wallet encrypted with a passphrase.
+    pub fn export_backup(&self, passphrase: &str) -> Result<Vec<u8>, Error> {
+        let mut backup = self.read_mnemonic()?;
+        backup.address_label = self.wallet_ref()?.address_label().clone();
+        Ok(backup.encrypt(passphrase, &mut thread_rng()))
+    }
+
+    /// Restores the wallet keys and the node's p2p identity from the encrypted backup.
+    /// The restored wallet starts with an empty UTXO index.
+    /// The restored identity takes effect after the node restarts.
+    /// Fails if the wallet already exists, unless `overwrite` is set.
+    pub fn import_backup(
+        &mut self,
+        bytes: &[u8],
+        passphrase: &str,
+        overwrite: bool,
+    ) -> Result<Wallet, Error> {
+        let backup = WalletBackup::decrypt(bytes, passphrase)?;
+        if self.wallet.is_some() {
+            if !overwrite {
+                return Err(Error::WalletAlreadyExists);
+            }
+            self.clear_wallet()?;
+        }
+
+        let xprv = backup.mnemonic.to_xprv(backup.version);
+        let identity = NodeIdentity::from_private_key(PrivateKey::from(
+            backup.mnemonic.to_identity_key(backup.version),
+        ));
+        let wallet = Wallet::new(backup.address_label, xprv.to_xpub());
+
+        self.save_xprv(xprv)?;
+        self.save_mnemonic(&backup)?;
+        bc::save_identity(&self.config, &identity)?;
+        self.initialize_wallet(wallet.clone())?;
+        Ok(wallet)
+    }
+
     /// Removes the wallet
     pub fn clear_wallet(&mut self) -> Result<(), Error> {
         fs::remove_file(self.wallet_filepath())?;
@@ -110,3 +208,183 @@ impl WalletManager {
             .unwrap_or(Err(Error::WalletNotInitialized))
     }
 }
+
+/// Checks that the blocks from a given height up to the tip are available for the rescan.
+/// Returns the height of the tip.
+pub async fn check_rescan(bc: &BlockchainRef, from_height: u64) -> Result<u64, Error> {
+    let bc = bc.read().await;
+    let tip_height = bc.state().tip.height;
+    if from_height == 0 || from_height > tip_height || from_height <= bc.pruned_height() {
+        return Err(Error::BlockNotFound(from_height));
+    }
+    Ok(tip_height)
+}
+
+/// Replays the stored blocks from a given height through the wallet
+/// and reconciles the wallet's utxos with the ones found in the blocks.
+/// Progress is reported via the wallet topic of the event bus.
+pub async fn rescan(wallet: WalletRef, bc: BlockchainRef, from_height: u64) -> Result<(), Error> {
+    // Lock the wallet first, in the same order as the other users of both locks.
+    // Holding the blockchain lock ensures that no blocks are applied in the middle of the rescan.
+    let mut wm = wallet.write().await;
+    let bc = bc.read().await;
+    let tip_height = bc.state().tip.height;
+
+    let mut rescan = wm.wallet_ref()?.begin_rescan();
+    replay_blocks(wm.wallet_ref()?, &bc, &mut rescan, from_height, tip_height)?;
+
+    let (added_utxos, removed_utxos) = wm.update_wallet(|w| {
+        let result = w.finish_rescan(rescan, &bc.state().utreexo);
+        for entry in bc.mempool().entries() {
+            w.add_unconfirmed_tx(entry.verified_tx());
+        }
+        Ok(result)
+    })?;
+    bc.events().publish(WalletEvent::RescanFinished {
+        tip_height,
+        added_utxos,
+        removed_utxos,
+    });
+    Ok(())
+}
+
+/// Watches the mempool for the rejected double-spends and reports the ones
+/// concerning the wallet via the wallet topic of the event bus, so the user can be warned
+/// about an attempted double-spend of a pending payment.
+pub async fn watch_double_spends(wallet: WalletRef, bc: BlockchainRef) {
+    let events = bc.read().await.events().clone();
+    let mut subscription = events.subscribe::<MempoolEvent>();
+    while let Some(received) = subscription.recv().await {
+        let (id, conflicting_id, contract_id) = match received {
+            Received::Event(MempoolEvent::DoubleSpendDetected {
+                id,
+                conflicting_id,
+                contract_id,
+            }) => (id, conflicting_id, contract_id),
+            _ => continue,
+        };
+        // Lock the wallet first, in the same order as the other users of both locks.
+        let wm = wallet.read().await;
+        let bc = bc.read().await;
+        let watching = match (wm.wallet_ref(), bc.mempool().get(&conflicting_id)) {
+            (Ok(w), Some(entry)) => w.is_watching(&contract_id, entry.verified_tx()),
+            _ => false,
+        };
+        if watching {
+            events.publish(WalletEvent::DoubleSpendDetected {
+                id,
+                conflicting_id,
+                contract_id,
+            });
+        }
+    }
+}
+
+/// Exports the confirmed utxos of the wallet with their proofs at the current tip,
+/// encrypted with a passphrase.
+pub async fn export_utxos(
+    wallet: &WalletRef,
+    bc: &BlockchainRef,
+    passphrase: &str,
+) -> Result<Vec<u8>, Error> {
+    // Holding both locks ensures that the proofs are valid at the exported height.
+    let wm = wallet.read().await;
+    let bc = bc.read().await;
+    let export = UtxoExport {
+        height: bc.state().tip.height,
+        block_id: bc.state().tip.id(),
+        utxos: wm.wallet_ref()?.export_utxos(),
+    };
+    Ok(export.encrypt(passphrase, &mut thread_rng()))
+}
+
+/// Imports the utxos exported from another node, refreshing their proofs
+/// by replaying the blocks that follow the exported height up to the tip.
+/// Returns the number of added and removed utxos.
+pub async fn import_utxos(
+    wallet: WalletRef,
+    bc: BlockchainRef,
+    bytes: &[u8],
+    passphrase: &str,
+) -> Result<(usize, usize), Error> {
+    let export = UtxoExport::decrypt(bytes, passphrase)?;
+
+    let mut wm = wallet.write().await;
+    let bc = bc.read().await;
+    let tip_height = bc.state().tip.height;
+    if export.height > tip_height || export.height < bc.pruned_height() {
+        return Err(Error::BlockNotFound(export.height + 1));
+    }
+
+    // The proofs can be refreshed only if the exported block is on our chain.
+    let block_id = if export.height == tip_height {
+        bc.state().tip.id()
+    } else {
+        bc.load_block(export.height + 1)?
+            .ok_or(Error::BlockNotFound(export.height + 1))?
+            .header
+            .prev
+    };
+    if block_id != export.block_id {
+        return Err(Error::InvalidBackup(
+            "utxos are exported from another chain",
+        ));
+    }
+
+    let mut rescan = wm.wallet_ref()?.begin_import(export.utxos)?;
+    replay_blocks(
+        wm.wallet_ref()?,
+        &bc,
+        &mut rescan,
+        export.height + 1,
+        tip_height,
+    )?;
+
+    let (added_utxos, removed_utxos) = wm.update_wallet(|w| {
+        let result = w.finish_import(rescan, &bc.state().utreexo);
+        for entry in bc.mempool().entries() {
+            w.add_unconfirmed_tx(entry.verified_tx());
+        }
+        Ok(result)
+    })?;
+    bc.events().publish(WalletEvent::RescanFinished {
+        tip_height,
+        added_utxos,
+        removed_utxos,
+    });
+    Ok((added_utxos, removed_utxos))
+}
+
+/// Returns the passphrase for the stored mnemonic, if it is set.
+fn wallet_passphrase() -> Option<String> {
+    std::env::var(WALLET_PASSPHRASE_VAR)
+        .ok()
+        .filter(|p| !p.is_empty())
+}
+
+/// Replays the stored blocks in a given range of heights into the rescan.
+fn replay_blocks(
+    wallet: &Wallet,
+    bc: &BlockchainRunning,
+    rescan: &mut Rescan,
+    from_height: u64,
+    tip_height: u64,
+) -> Result<(), Error> {
+    for height in from_height..=tip_height {
+        let block = match bc.load_block(height)? {
+            Some(block) => block,
+            None => {
+                let err = Error::BlockNotFound(height);
+                bc.events().publish(WalletEvent::RescanFailed {
+                    height,
+                    error: err.to_string(),
+                });
+                return Err(err);
+            }
+        };
+        wallet.rescan_block(rescan, &block.verified_txs, &block.catchup);
+        bc.events()
+            .publish(WalletEvent::RescanProgress { height, tip_height });
+    }
+    Ok(())
+}
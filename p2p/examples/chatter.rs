@@ -23,6 +23,9 @@ fn main() {
                 inbound_limit: 100,
                 outbound_limit: 100,
                 heartbeat_interval_sec: 3600,
+                ping_interval_sec: 30,
+                ping_timeout_sec: 60,
+                proxy: None,
             };
 
             let (node, mut notifications_channel) = Node::<Message>::spawn(host_privkey, config)
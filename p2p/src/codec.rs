@@ -9,6 +9,15 @@ use std::marker::PhantomData;
 use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 use tokio_util::codec::{Decoder, Encoder};
 
+/// Length of the ping/pong nonce.
+const NONCE_LEN: usize = 8;
+
+/// Length of the hello message: legacy peers reject the hello of any other length.
+const HELLO_LEN: usize = 2;
+
+/// Length of the features bitmask.
+const FEATURES_LEN: usize = 8;
+
 pub struct MessageEncoder<T: Codable> {
     marker: PhantomData<T>,
 }
@@ -18,11 +27,10 @@ impl<T: Codable> Encoder<PeerMessage<T>> for MessageEncoder<T> {
 
     fn encode(&mut self, item: PeerMessage<T>, dst: &mut BytesMut) -> Result<(), Self::Error> {
         match item {
-            PeerMessage::Hello(u) => {
+            PeerMessage::Hello(port) => {
                 dst.put_u8(0); // Message type
-                const HELLO_MESSAGE_LEN: u32 = 2;
-                dst.put_u32_le(HELLO_MESSAGE_LEN);
-                dst.put_u16_le(u);
+                dst.put_u32_le(HELLO_LEN as u32);
+                dst.put_u16_le(port);
             }
             PeerMessage::Peers(p) => {
                 dst.put_u8(1); // Message type
@@ -45,6 +53,21 @@ impl<T: Codable> Encoder<PeerMessage<T>> for MessageEncoder<T> {
                 let body_len = (dst.len() - 5) as u32;
                 dst[1..5].copy_from_slice(&body_len.to_le_bytes()[..]);
             }
+            PeerMessage::Ping(nonce) => {
+                dst.put_u8(3); // Message type
+                dst.put_u32_le(NONCE_LEN as u32);
+                dst.put_u64_le(nonce);
+            }
+            PeerMessage::Pong(nonce) => {
+                dst.put_u8(4); // Message type
+                dst.put_u32_le(NONCE_LEN as u32);
+                dst.put_u64_le(nonce);
+            }
+            PeerMessage::Features(features) => {
+                dst.put_u8(5); // Message type
+                dst.put_u32_le(FEATURES_LEN as u32);
+                dst.put_u64_le(features);
+            }
         }
         Ok(())
     }
@@ -91,7 +114,7 @@ impl<T: Codable> Decoder for MessageDecoder<T> {
                 }
                 let command_type = src.get_u8();
                 match command_type {
-                    0..=2 => {}
+                    0..=5 => {}
                     _ => {
                         return Err(io::Error::new(
                             io::ErrorKind::InvalidData,
@@ -128,14 +151,14 @@ fn read_message_body<T: Codable>(
 ) -> Result<PeerMessage<T>, io::Error> {
     match message_type {
         0 => {
-            if len != 2 {
+            if len != HELLO_LEN {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidData,
                     format!("Invalid length for hello message: {}", len),
                 ));
             }
-            let data = src.get_u16_le();
-            Ok(PeerMessage::Hello(data))
+            let port = src.get_u16_le();
+            Ok(PeerMessage::Hello(port))
         }
         1 => {
             let mut peers = vec![];
@@ -156,6 +179,29 @@ fn read_message_body<T: Codable>(
                 )),
             }
         }
+        3 | 4 => {
+            if len != NONCE_LEN {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid length for ping message: {}", len),
+                ));
+            }
+            let nonce = src.get_u64_le();
+            if message_type == 3 {
+                Ok(PeerMessage::Ping(nonce))
+            } else {
+                Ok(PeerMessage::Pong(nonce))
+            }
+        }
+        5 => {
+            if len != FEATURES_LEN {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid length for features message: {}", len),
+                ));
+            }
+            Ok(PeerMessage::Features(src.get_u64_le()))
+        }
         m => Err(io::Error::new(
             io::ErrorKind::InvalidData,
             format!("Unknown message type: {}", m),
@@ -249,6 +295,7 @@ fn check_length(buf: &mut BytesMut, len: usize, label: &str) -> Result<(), io::E
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::FEATURE_PING;
     use bytes::BytesMut;
     use readerwriter::{Decodable, Encodable, ReadError, Reader, WriteError, Writer};
     use std::ops::Deref;
@@ -276,6 +323,21 @@ mod tests {
         }
     }
 
+    /// Decodes the hello the way the legacy peers do: they accept only the 2-byte body.
+    fn legacy_decode_hello(bytes: &mut BytesMut) -> Result<u16, io::Error> {
+        if bytes.get_u8() != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a hello"));
+        }
+        let len = bytes.get_u32_le() as usize;
+        if len != 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid length for hello message: {}", len),
+            ));
+        }
+        Ok(bytes.get_u16_le())
+    }
+
     #[test]
     fn code_hello() {
         let msg = PeerMessage::<Message>::Hello(20);
@@ -283,6 +345,7 @@ mod tests {
         MessageEncoder::new()
             .encode(msg.clone(), &mut bytes)
             .expect("Must be encoded");
+        assert_eq!(&bytes[..], &[0u8, 2, 0, 0, 0, 20, 0][..]);
         let res = MessageDecoder::new()
             .decode(&mut bytes)
             .expect("Message must be decoded without errors")
@@ -291,6 +354,37 @@ mod tests {
         assert_eq!(msg, res);
     }
 
+    #[test]
+    fn code_hello_for_legacy_peers() {
+        let mut bytes = BytesMut::new();
+        MessageEncoder::<Message>::new()
+            .encode(PeerMessage::Hello(20), &mut bytes)
+            .expect("Must be encoded");
+        assert_eq!(legacy_decode_hello(&mut bytes).unwrap(), 20);
+        assert!(bytes.is_empty());
+
+        // Hello with the trailing features would be rejected by the legacy peers.
+        let mut bytes = BytesMut::from(&[0u8, 10, 0, 0, 0, 20, 0, 1, 0, 0, 0, 0, 0, 0, 0][..]);
+        assert!(legacy_decode_hello(&mut bytes.clone()).is_err());
+        assert!(MessageDecoder::<Message>::new().decode(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn code_features() {
+        let msg = PeerMessage::<Message>::Features(FEATURE_PING);
+        let mut bytes = BytesMut::new();
+        MessageEncoder::new()
+            .encode(msg.clone(), &mut bytes)
+            .expect("Must be encoded");
+        let res = MessageDecoder::new()
+            .decode(&mut bytes)
+            .expect("Message must be decoded without errors")
+            .expect("message must be encoded to end");
+
+        assert_eq!(msg, res);
+        assert!(bytes.is_empty())
+    }
+
     #[test]
     fn code_peers() {
         let msg = PeerMessage::<Message>::Peers(vec![
@@ -315,6 +409,27 @@ mod tests {
         assert_eq!(msg, res);
     }
 
+    #[test]
+    fn code_ping_pong() {
+        let mut bytes = BytesMut::new();
+        let mut encoder = MessageEncoder::new();
+        let mut decoder = MessageDecoder::new();
+        for msg in vec![
+            PeerMessage::<Message>::Ping(0x0102030405060708),
+            PeerMessage::<Message>::Pong(u64::max_value()),
+        ] {
+            encoder
+                .encode(msg.clone(), &mut bytes)
+                .expect("Must be encoded");
+            let res = decoder
+                .decode(&mut bytes)
+                .expect("Message must be decoded without errors")
+                .expect("message must be encoded to end");
+            assert_eq!(msg, res);
+        }
+        assert!(bytes.is_empty())
+    }
+
     #[test]
     fn code_custom() {
         let msg = PeerMessage::Data(Message(vec![1, 2, 3, 4, 5, 6]));
@@ -13,11 +13,25 @@
 //!
 //! You start with a local private key, remote public key (optional),
 //! and a pair of `AsyncRead` and `AsyncWrite` interfaces.
+//! The tokio traits are supported with the default `tokio-runtime` feature,
+//! and the `futures::io` traits used by async-std and smol are supported
+//! via the functions in the `futures_io` module.
+//! Use `cybershake_with_expected_peer` to pin the remote public key:
+//! the handshake fails with `Error::PeerMismatch` if the remote party has a different identity.
 //!
 //! The protocol performs mutual authentication and, if it succeeded,
 //! returns a pair of wrappers around these interfaces,
 //! that keep track of the encryption keys.
 //!
+//! The async functions are thin wrappers around a sans-IO core:
+//! `Handshake` consumes and produces bytes without performing any I/O,
+//! and yields a `Session` with the `Encryptor` and `Decryptor` for the framed ciphertext.
+//! This allows testing the protocol deterministically and driving it with any I/O model.
+//!
+//! Both ends of the session share the `SessionStats` counters of the frames, bytes and ratchet steps.
+//! A non-zero count of the decryption failures indicates a corrupted session:
+//! its keys are out of sync with the remote party, so the session should be re-established.
+//!
 //! ## Features
 //!
 //! * **Symmetric and low-latency.** Handshake is performed by both ends simultaneously.
@@ -25,13 +39,25 @@
 //! * **Key blinding.** Long-term identity keys are never transmitted in the clear.
 //! * **Foward secrecy.** Keys are rotated on each sent message.
 //! * **Robust encryption.** cipher AES-SIV-PMAC-128 provides high speed and resistance to nonce-misuse.
+//! * **Pluggable ciphers.** Frames are encrypted with an `AeadSuite`: AES-PMAC-SIV by default,
+//!   or ChaCha20-Poly1305 for the environments without hardware AES.
+//! * **Version negotiation.** Parties exchange bitmasks of supported versions and select
+//!   the highest mutual one, which is then bound into the key derivation.
+//!   The upper half of the bitmask lists the supported cipher suites, and the mutual suite
+//!   with the lowest ID is selected. Parties that do not list any suites support only AES-PMAC-SIV.
+//!   A party that supports only version 0 and AES-PMAC-SIV sends the legacy hello
+//!   with a zero bitmask, as the peers that predate the negotiation do.
+//!   If either hello is legacy, the session uses AES-PMAC-SIV and the bitmasks
+//!   are not bound into the key derivation, so that the keys match the legacy peer's ones.
 //!
 //! ## TODO
 //!
 //! * Add custom header to be sent in the first encrypted frame:
-//!   users can put the protocol version there, certificate info etc.
+//!   users can put certificate info etc.
 
 use byteorder::{ByteOrder, LittleEndian};
+use chacha20poly1305::aead::generic_array::GenericArray as AeadArray;
+use chacha20poly1305::aead::{AeadInPlace, NewAead};
 use core::marker::Unpin;
 use miscreant::{generic_array::GenericArray, Aes128PmacSiv};
 use rand_core::{CryptoRng, RngCore};
@@ -39,23 +65,33 @@ use rand_core::{CryptoRng, RngCore};
 use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
 use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
 use curve25519_dalek::scalar::Scalar;
-use curve25519_dalek::traits::VartimeMultiscalarMul;
 use merlin::Transcript; // TODO: change for raw Strobe.
 
-use tokio::io;
-use tokio::prelude::*;
-
+use futures::future::poll_fn;
 use futures::task::{Context, Poll};
+use std::fmt;
+use std::io;
+use std::mem;
 use std::pin::Pin;
-
-/// The current version of the protocol is 0.
-/// In the future we may add more versions, version bits or whatever.
-const ONLY_SUPPORTED_VERSION: u64 = 0;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Bitmask of the supported versions of the protocol: bit `i` is set if version `i` is supported.
+/// Currently only version 0 is supported.
+/// An empty bitmask is sent by legacy peers and is treated as version 0.
+const SUPPORTED_VERSIONS: u64 = 1 << 0;
+/// Hello bitmask of the legacy peers: they send their only version 0 and reject any other value.
+const LEGACY_HELLO: u64 = 0;
+const VERSIONS_MASK: u64 = 0xffff_ffff; // lower half of the hello bitmask
+const SUITES_SHIFT: u32 = 32; // cipher suites are listed in the upper half of the hello bitmask
 const PT_BUF_SIZE: usize = 4096;
 const CT_LEN_SIZE: usize = 2; // 16-bit length prefix for ciphertext chunks
 const CT_TAG_SIZE: usize = 16; // 128-bit auth tag
 const CT_SIZE: usize = CT_TAG_SIZE + PT_BUF_SIZE;
 const PT_OFFSET: usize = CT_LEN_SIZE + CT_TAG_SIZE; // offset of the plaintext in the outgoing buffer
+const SALT_LEN: usize = 16; // salt for blinding the identity key
+const HELLO_LEN: usize = 8 + 32; // versions bitmask and blinded identity pubkey
+const AUTH_LEN: usize = SALT_LEN + 32; // salt and identity pubkey
 
 /// Private key for encrypting and authenticating connection.
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -72,10 +108,9 @@ pub struct PublicKey {
 
 /// An endpoint for sending messages to remote party.
 /// All messages are ordered and encryption key is ratcheted after each sent message.
-pub struct Outgoing<W: io::AsyncWrite + Unpin> {
+pub struct Outgoing<W: Unpin> {
     writer: Pin<Box<W>>,
-    seq: u64,
-    kdf: Transcript,
+    encryptor: Encryptor,
     buf: Vec<u8>,
     flushing: bool,
     ciphertext_sent: usize,
@@ -83,13 +118,138 @@ pub struct Outgoing<W: io::AsyncWrite + Unpin> {
 
 /// An endpoint for receiving messages from a remote party.
 /// All messages are ordered and encryption key is ratcheted after each received message.
-/// Recipient's incoming.seq corresponds to the sender's outgoing.seq.
-pub struct Incoming<R: io::AsyncRead + Unpin> {
+/// Recipient's decryptor sequence number corresponds to the sender's encryptor sequence number.
+pub struct Incoming<R: Unpin> {
     reader: Pin<Box<R>>,
+    decryptor: Decryptor,
+    buf: Vec<u8>,
+}
+
+/// Sans-IO state machine of the handshake.
+/// Send the bytes from `take_output` to the remote party and `feed` the bytes received from it,
+/// reading no more than `bytes_needed` at a time, until the handshake `is_complete`.
+/// Then `into_session` returns the remote identity and the ciphers for the established session.
+pub struct Handshake {
+    local_identity: PrivateKey,
+    expected: Option<PublicKey>,
+    local_suites: u64,
+    local_bitmask: u64,
+    local_salt: [u8; SALT_LEN],
+    local_blinded_identity: PrivateKey,
+    output: Vec<u8>,
+    state: HandshakeState,
+}
+
+/// Authenticated session established by the `Handshake`.
+pub struct Session {
+    /// Identity key of the remote party.
+    pub remote_identity: PublicKey,
+    /// Cipher for the outgoing frames.
+    pub encryptor: Encryptor,
+    /// Cipher for the incoming frames.
+    pub decryptor: Decryptor,
+}
+
+/// Sans-IO encryptor of the outgoing frames.
+/// Encryption key is ratcheted after each frame.
+pub struct Encryptor {
+    version: u8,
+    suite: CipherSuite,
+    seq: u64,
+    kdf: Transcript,
+    stats: SessionStats,
+}
+
+/// Sans-IO decryptor of the incoming frames.
+/// Decryption key is ratcheted after each frame.
+pub struct Decryptor {
+    version: u8,
+    suite: CipherSuite,
     seq: u64,
     kdf: Transcript,
     buf: Vec<u8>,
     state: ReadState,
+    stats: SessionStats,
+}
+
+/// Authenticated encryption of the frames.
+/// Each frame is encrypted with a fresh key produced by the ratchet,
+/// and the sequence number of the frame is authenticated along with it.
+pub trait AeadSuite {
+    /// Encrypts the buffer in place and returns the authentication tag.
+    fn seal(&self, key: &[u8; 32], seq: u64, buf: &mut [u8]) -> [u8; CT_TAG_SIZE];
+
+    /// Decrypts the buffer in place. Fails if the tag does not authenticate the ciphertext.
+    fn open(
+        &self,
+        key: &[u8; 32],
+        seq: u64,
+        buf: &mut [u8],
+        tag: &[u8; CT_TAG_SIZE],
+    ) -> Result<(), io::Error>;
+}
+
+/// AES-128 in the SIV mode with PMAC (RFC 5297), resistant to nonce misuse.
+#[derive(Copy, Clone, Debug)]
+pub struct AesPmacSiv;
+
+/// ChaCha20-Poly1305 (RFC 8439), fast without the hardware AES support.
+#[derive(Copy, Clone, Debug)]
+pub struct ChaCha20Poly1305;
+
+/// Cipher suites negotiated during the handshake.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CipherSuite {
+    /// AES-PMAC-SIV, the default suite (ID 0).
+    AesPmacSiv,
+    /// ChaCha20-Poly1305 (ID 1).
+    ChaCha20Poly1305,
+}
+
+/// Handle to the counters of the session, shared by its encryptor and decryptor.
+/// The handle is cheap to clone and remains valid after the session is closed.
+#[derive(Clone, Debug, Default)]
+pub struct SessionStats {
+    counters: Arc<SessionCounters>,
+}
+
+#[derive(Debug, Default)]
+struct SessionCounters {
+    frames_sent: AtomicU64,
+    frames_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    ratchet_steps: AtomicU64,
+    decrypt_failures: AtomicU64,
+}
+
+/// Errors that may occur during the handshake.
+#[derive(Debug)]
+pub enum Error {
+    /// Underlying I/O failure or malformed data from the remote party.
+    Io(io::Error),
+    /// Remote party's identity does not match the expected public key.
+    PeerMismatch,
+    /// Remote party does not support any of our versions.
+    /// Contains the bitmask of versions supported by the remote party.
+    IncompatibleVersion(u64),
+    /// Remote party does not support any of our cipher suites.
+    /// Contains the bitmask of suites supported by the remote party.
+    IncompatibleCipherSuite(u64),
+}
+
+enum HandshakeState {
+    ReadHello {
+        buf: Vec<u8>,
+    },
+    ReadAuth {
+        remote_blinded_identity: PublicKey,
+        encryptor: Encryptor,
+        decryptor: Decryptor,
+        buf: Vec<u8>,
+    },
+    Complete(Session),
+    Failed,
 }
 
 enum ReadState {
@@ -103,323 +263,1134 @@ enum ReadState {
 /// Returns the identity key of the remote peer, along with read- and write- interfaces
 /// that perform encryption and authentication behind the scenes.
 /// If you need to verify the identity per local policy or certificates, use the returned public key.
+#[cfg(feature = "tokio-runtime")]
 pub async fn cybershake<R, W, RNG>(
     local_identity: &PrivateKey,
+    reader: Pin<Box<R>>,
+    writer: Pin<Box<W>>,
+    rng: RNG,
+) -> Result<(PublicKey, Outgoing<W>, Incoming<R>), io::Error>
+where
+    R: tokio::io::AsyncRead + Unpin,
+    W: tokio::io::AsyncWrite + Unpin,
+    RNG: RngCore + CryptoRng,
+{
+    cybershake_with_expected_peer(local_identity, None, reader, writer, rng)
+        .await
+        .map_err(io::Error::from)
+}
+
+/// Performs the key exchange like `cybershake`, but optionally pins the identity of the remote peer.
+/// If the expected key is provided and the remote party's identity does not match it,
+/// returns `Error::PeerMismatch`.
+/// If the remote blinded identity cannot possibly match the expected key,
+/// the handshake is aborted before our own identity is sent in the authenticated frame.
+#[cfg(feature = "tokio-runtime")]
+pub async fn cybershake_with_expected_peer<R, W, RNG>(
+    local_identity: &PrivateKey,
+    expected: Option<PublicKey>,
+    reader: Pin<Box<R>>,
+    writer: Pin<Box<W>>,
+    rng: RNG,
+) -> Result<(PublicKey, Outgoing<W>, Incoming<R>), Error>
+where
+    R: tokio::io::AsyncRead + Unpin,
+    W: tokio::io::AsyncWrite + Unpin,
+    RNG: RngCore + CryptoRng,
+{
+    perform_handshake::<TokioDriver, _, _, _>(local_identity, expected, reader, writer, rng).await
+}
+
+/// Handshake over the `futures-io` traits, for the runtimes other than tokio
+/// (e.g. async-std or smol).
+/// The returned `Outgoing` and `Incoming` implement `futures::io::AsyncWrite` and `futures::io::AsyncRead`.
+pub mod futures_io {
+    use super::*;
+
+    /// Performs the key exchange like `super::cybershake`,
+    /// using `futures::io::AsyncRead` and `futures::io::AsyncWrite` interfaces.
+    pub async fn cybershake<R, W, RNG>(
+        local_identity: &PrivateKey,
+        reader: Pin<Box<R>>,
+        writer: Pin<Box<W>>,
+        rng: RNG,
+    ) -> Result<(PublicKey, Outgoing<W>, Incoming<R>), io::Error>
+    where
+        R: futures::io::AsyncRead + Unpin,
+        W: futures::io::AsyncWrite + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        cybershake_with_expected_peer(local_identity, None, reader, writer, rng)
+            .await
+            .map_err(io::Error::from)
+    }
+
+    /// Performs the key exchange like `super::cybershake_with_expected_peer`,
+    /// using `futures::io::AsyncRead` and `futures::io::AsyncWrite` interfaces.
+    pub async fn cybershake_with_expected_peer<R, W, RNG>(
+        local_identity: &PrivateKey,
+        expected: Option<PublicKey>,
+        reader: Pin<Box<R>>,
+        writer: Pin<Box<W>>,
+        rng: RNG,
+    ) -> Result<(PublicKey, Outgoing<W>, Incoming<R>), Error>
+    where
+        R: futures::io::AsyncRead + Unpin,
+        W: futures::io::AsyncWrite + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        perform_handshake::<FuturesDriver, _, _, _>(local_identity, expected, reader, writer, rng)
+            .await
+    }
+}
+
+/// Drives the sans-IO handshake over the I/O traits selected by the driver `D`.
+async fn perform_handshake<D, R, W, RNG>(
+    local_identity: &PrivateKey,
+    expected: Option<PublicKey>,
     mut reader: Pin<Box<R>>,
     mut writer: Pin<Box<W>>,
-    mut rng: RNG,
-) -> Result<(PublicKey, Outgoing<W>, Incoming<R>), io::Error>
+    rng: RNG,
+) -> Result<(PublicKey, Outgoing<W>, Incoming<R>), Error>
 where
-    R: io::AsyncRead + Unpin,
-    W: io::AsyncWrite + Unpin,
+    D: ReadDriver<R> + WriteDriver<W>,
+    R: Unpin,
+    W: Unpin,
     RNG: RngCore + CryptoRng,
 {
-    // We are going to need an additional ephemeral D-H key,
-    // and a salt for blinding the reusable identity key.
-
-    let mut keygen_rng = Transcript::new(b"Cybershake.randomness")
-        .build_rng()
-        .rekey_with_witness_bytes(b"local_privkey", local_identity.as_secret_bytes())
-        .finalize(&mut rng);
-
-    const SALT_LEN: usize = 16;
-    let mut local_salt = [0u8; SALT_LEN];
-    keygen_rng.fill_bytes(&mut local_salt[..]);
-    let local_blinded_identity = local_identity.blind(&local_salt[..]);
-
-    // Now we send our first, unencrypted, message:
-    //
-    // [version] [blinded local identity pubkey] [local ephemeral pubkey]
-    // u64-le     32 bytes                        32 bytes
-    writer
-        .write(&encode_u64le(ONLY_SUPPORTED_VERSION)[..])
-        .await?;
-    writer
-        .write(local_blinded_identity.pubkey.as_bytes())
-        .await?;
-    writer.flush().await?;
-
-    // Receive the similar message from the other end (that was sent simultaneously).
-    let mut remote_version_buf = [0u8; 8];
-    reader.read_exact(&mut remote_version_buf[..]).await?;
-    let remote_version = LittleEndian::read_u64(&remote_version_buf);
-    if remote_version != ONLY_SUPPORTED_VERSION {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Incompatible cybershake version",
-        ));
-    }
-    let remote_blinded_identity = PublicKey::read_from(&mut reader).await?;
-
-    // Now, perform a triple Diffie-Hellman shared key generation.
-    let t = cybershake_dh(&local_blinded_identity, &remote_blinded_identity)?;
-
-    // We will have two independent derivations of the shared key:
-    // one for the outgoing messages, and another one for incoming messages.
-    let mut kdf_outgoing = t.clone();
-    let mut kdf_incoming = t;
-    kdf_outgoing.append_message(b"src", local_blinded_identity.pubkey.as_bytes());
-    kdf_incoming.append_message(b"src", remote_blinded_identity.as_bytes());
-
-    // Now we prepare endpoints for reading and writing messages,
-    // but don't give them to the user until we authenticate the connection.
-    let mut out_buf = Vec::with_capacity(CT_SIZE as usize + CT_LEN_SIZE);
-    out_buf.extend_from_slice(&[0; PT_OFFSET]);
-    let mut outgoing = Outgoing {
-        writer,
-        seq: 0,
-        kdf: kdf_outgoing,
-        buf: out_buf,
-        flushing: false,
-        ciphertext_sent: 0,
-    };
-    let mut incoming = Incoming {
-        reader,
-        seq: 0,
-        kdf: kdf_incoming,
-        buf: vec![0u8; CT_SIZE as usize], // TODO: allow user redefine this parameter
-        state: ReadState::Len(0),
-    };
+    let mut handshake = Handshake::new(local_identity, expected, rng);
+    let mut buf = Vec::new();
+    loop {
+        let output = handshake.take_output();
+        if !output.is_empty() {
+            write_all::<D, _>(&mut writer, &output).await?;
+        }
+        if handshake.is_complete() {
+            break;
+        }
+        // Read exactly as much as the handshake needs, so the bytes
+        // following the handshake are left for the `Incoming` endpoint.
+        buf.resize(handshake.bytes_needed(), 0);
+        read_exact::<D, _>(&mut reader, &mut buf[..]).await?;
+        handshake.feed(&buf)?;
+    }
+
+    let session = handshake
+        .into_session()
+        .expect("handshake is complete at this point");
+    Ok((
+        session.remote_identity,
+        Outgoing::new(writer, session.encryptor),
+        Incoming::new(reader, session.decryptor),
+    ))
+}
+
+impl Handshake {
+    /// Starts the handshake and prepares the first message to be sent to the remote party.
+    /// If the expected identity is provided, the handshake fails with `Error::PeerMismatch`
+    /// when the remote party has a different identity.
+    /// Only AES-PMAC-SIV is supported, so the legacy hello is sent
+    /// and the legacy peers can connect.
+    pub fn new<RNG: RngCore + CryptoRng>(
+        local_identity: &PrivateKey,
+        expected: Option<PublicKey>,
+        rng: RNG,
+    ) -> Self {
+        Self::with_cipher_suites(local_identity, expected, &[CipherSuite::AesPmacSiv], rng)
+    }
+
+    /// Starts the handshake like `new`, supporting only the given cipher suites.
+    /// The handshake fails with `Error::IncompatibleCipherSuite` if the remote party
+    /// supports none of them.
+    /// Unless the suites are limited to AES-PMAC-SIV, the hello lists them,
+    /// and the legacy peers reject it.
+    pub fn with_cipher_suites<RNG: RngCore + CryptoRng>(
+        local_identity: &PrivateKey,
+        expected: Option<PublicKey>,
+        suites: &[CipherSuite],
+        mut rng: RNG,
+    ) -> Self {
+        // We are going to need an additional ephemeral D-H key,
+        // and a salt for blinding the reusable identity key.
+        let mut keygen_rng = Transcript::new(b"Cybershake.randomness")
+            .build_rng()
+            .rekey_with_witness_bytes(b"local_privkey", local_identity.as_secret_bytes())
+            .finalize(&mut rng);
+
+        let mut local_salt = [0u8; SALT_LEN];
+        keygen_rng.fill_bytes(&mut local_salt[..]);
+        let local_blinded_identity = local_identity.blind(&local_salt[..]);
+
+        let local_suites = suites
+            .iter()
+            .fold(0u64, |mask, suite| mask | (1 << suite.id()));
+        // While version 0 is the only one, parties limited to AES-PMAC-SIV
+        // have nothing to negotiate and send the legacy hello.
+        let local_bitmask = if local_suites == 1 << CipherSuite::AesPmacSiv.id() {
+            LEGACY_HELLO
+        } else {
+            (local_suites << SUITES_SHIFT) | SUPPORTED_VERSIONS
+        };
+
+        // Our first, unencrypted, message:
+        //
+        // [supported suites and versions] [blinded local identity pubkey]
+        // u64-le bitmask                  32 bytes
+        let mut output = Vec::with_capacity(HELLO_LEN);
+        output.extend_from_slice(&encode_u64le(local_bitmask)[..]);
+        output.extend_from_slice(local_blinded_identity.pubkey.as_bytes());
+
+        Handshake {
+            local_identity: *local_identity,
+            expected,
+            local_suites,
+            local_bitmask,
+            local_salt,
+            local_blinded_identity,
+            output,
+            state: HandshakeState::ReadHello {
+                buf: Vec::with_capacity(HELLO_LEN),
+            },
+        }
+    }
+
+    /// Returns the bytes to be sent to the remote party.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        mem::replace(&mut self.output, Vec::new())
+    }
+
+    /// Returns the number of bytes needed to make progress.
+    /// Reading no more than that leaves the bytes following the handshake in the stream.
+    pub fn bytes_needed(&self) -> usize {
+        match &self.state {
+            HandshakeState::ReadHello { buf } => HELLO_LEN - buf.len(),
+            HandshakeState::ReadAuth { decryptor, .. } => decryptor.bytes_needed(),
+            HandshakeState::Complete(_) | HandshakeState::Failed => 0,
+        }
+    }
+
+    /// Returns true when the session is established.
+    pub fn is_complete(&self) -> bool {
+        match self.state {
+            HandshakeState::Complete(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the established session, or `None` if the handshake is not complete.
+    pub fn into_session(self) -> Option<Session> {
+        match self.state {
+            HandshakeState::Complete(session) => Some(session),
+            _ => None,
+        }
+    }
+
+    /// Processes the bytes received from the remote party.
+    /// Returns the number of bytes consumed: the bytes following the handshake are not consumed.
+    /// After an error the handshake cannot continue.
+    pub fn feed(&mut self, mut bytes: &[u8]) -> Result<usize, Error> {
+        let total = bytes.len();
+        while !bytes.is_empty() && self.bytes_needed() > 0 {
+            let state = mem::replace(&mut self.state, HandshakeState::Failed);
+            let (n, state) = self.step(state, bytes)?;
+            self.state = state;
+            bytes = &bytes[n..];
+        }
+        Ok(total - bytes.len())
+    }
+
+    fn step(
+        &mut self,
+        state: HandshakeState,
+        bytes: &[u8],
+    ) -> Result<(usize, HandshakeState), Error> {
+        match state {
+            HandshakeState::ReadHello { mut buf } => {
+                let n = usize::min(HELLO_LEN - buf.len(), bytes.len());
+                buf.extend_from_slice(&bytes[..n]);
+                if buf.len() < HELLO_LEN {
+                    return Ok((n, HandshakeState::ReadHello { buf }));
+                }
+                Ok((n, self.receive_hello(&buf)?))
+            }
+            HandshakeState::ReadAuth {
+                remote_blinded_identity,
+                encryptor,
+                mut decryptor,
+                mut buf,
+            } => {
+                let n = decryptor.feed(bytes)?;
+                let mut chunk = [0u8; AUTH_LEN];
+                while buf.len() < AUTH_LEN && decryptor.has_plaintext() {
+                    let m = decryptor.read_plaintext(&mut chunk[..AUTH_LEN - buf.len()]);
+                    buf.extend_from_slice(&chunk[..m]);
+                }
+                if buf.len() < AUTH_LEN {
+                    let state = HandshakeState::ReadAuth {
+                        remote_blinded_identity,
+                        encryptor,
+                        decryptor,
+                        buf,
+                    };
+                    return Ok((n, state));
+                }
+                let remote_identity = self.receive_auth(&buf, &remote_blinded_identity)?;
+                let session = Session {
+                    remote_identity,
+                    encryptor,
+                    decryptor,
+                };
+                Ok((n, HandshakeState::Complete(session)))
+            }
+            state => Ok((0, state)),
+        }
+    }
+
+    /// Processes the remote party's first message, performs the key exchange
+    /// and prepares the authentication frame.
+    fn receive_hello(&mut self, hello: &[u8]) -> Result<HandshakeState, Error> {
+        let remote_bitmask = LittleEndian::read_u64(&hello[..8]);
+        // Legacy peers send their only version 0 instead of the bitmask.
+        let remote_versions = match remote_bitmask & VERSIONS_MASK {
+            0 => 1 << 0,
+            versions => versions,
+        };
+        let version = negotiate_version(SUPPORTED_VERSIONS, remote_versions)
+            .ok_or(Error::IncompatibleVersion(remote_versions))?;
+        let remote_suites = match remote_bitmask >> SUITES_SHIFT {
+            0 => 1 << CipherSuite::AesPmacSiv.id(),
+            suites => suites,
+        };
+        let suite = negotiate_cipher_suite(self.local_suites, remote_suites)
+            .ok_or(Error::IncompatibleCipherSuite(remote_suites))?;
+        let local_bitmask = self.local_bitmask;
+        // Legacy hello lists only version 0 and AES-PMAC-SIV, so the negotiation above
+        // has selected them, and the legacy party does not bind anything to its keys.
+        let legacy = local_bitmask == LEGACY_HELLO || remote_bitmask == LEGACY_HELLO;
+        let remote_blinded_identity = PublicKey::from(CompressedRistretto::from_slice(&hello[8..]));
+        let local_blinded_identity = &self.local_blinded_identity;
+
+        // When the remote identity is pinned, do not reveal our identity to a party
+        // whose blinded key cannot be a blinding of the expected key:
+        // an invalid point, or our own blinded key reflected back to us.
+        if self.expected.is_some()
+            && (remote_blinded_identity.as_point().decompress().is_none()
+                || remote_blinded_identity == local_blinded_identity.pubkey)
+        {
+            return Err(Error::PeerMismatch);
+        }
+
+        // Now, perform a triple Diffie-Hellman shared key generation.
+        let mut t = cybershake_dh(local_blinded_identity, &remote_blinded_identity)?;
+
+        // Bind the advertised versions and the selected one to the shared key,
+        // so that a MitM cannot silently downgrade the protocol version.
+        // The cipher suite is selected from the same bitmasks, so it is bound too.
+        // The legacy sessions are left unbound, as the legacy peers derive their keys
+        // from the Diffie-Hellman transcript alone.
+        if !legacy {
+            let (versions1, versions2) =
+                if local_blinded_identity.pubkey.as_bytes() < remote_blinded_identity.as_bytes() {
+                    (local_bitmask, remote_bitmask)
+                } else {
+                    (remote_bitmask, local_bitmask)
+                };
+            t.append_u64(b"versions1", versions1);
+            t.append_u64(b"versions2", versions2);
+            t.append_u64(b"version", version as u64);
+        }
+
+        // We will have two independent derivations of the shared key:
+        // one for the outgoing messages, and another one for incoming messages.
+        let mut kdf_outgoing = t.clone();
+        let mut kdf_incoming = t;
+        kdf_outgoing.append_message(b"src", local_blinded_identity.pubkey.as_bytes());
+        kdf_incoming.append_message(b"src", remote_blinded_identity.as_bytes());
+
+        let stats = SessionStats::default();
+        let mut encryptor = Encryptor::new(version, suite, kdf_outgoing, stats.clone());
+        let decryptor = Decryptor::new(version, suite, kdf_incoming, stats);
+
+        // In order to authenticate the session, we send our first encrypted message
+        // in which we show the salt and the root pubkey.
+        // If the transmission was successful (authenticated decryption succeeded),
+        // we check the blinded key and then let user continue using the session.
+        let mut auth = Vec::with_capacity(AUTH_LEN);
+        auth.extend_from_slice(&self.local_salt[..]);
+        auth.extend_from_slice(self.local_identity.pubkey.as_bytes());
+        encryptor.encrypt(&auth, &mut self.output);
+
+        Ok(HandshakeState::ReadAuth {
+            remote_blinded_identity,
+            encryptor,
+            decryptor,
+            buf: Vec::with_capacity(AUTH_LEN),
+        })
+    }
+
+    /// Checks the remote party's salt and identity against its blinded key.
+    fn receive_auth(
+        &self,
+        auth: &[u8],
+        remote_blinded_identity: &PublicKey,
+    ) -> Result<PublicKey, Error> {
+        let received_remote_identity =
+            PublicKey::from(CompressedRistretto::from_slice(&auth[SALT_LEN..]));
+
+        // Blinded key is also a secure commitment to the underlying key.
+        // Here we check that the remote party has sent us the correct identity key
+        // matching the blinded key they used for X3DH.
+        let received_remote_id_blinded = received_remote_identity
+            .blind(&auth[0..SALT_LEN])
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Failed to decode Ristretto point",
+                )
+            })?;
+
+        if &received_remote_id_blinded != remote_blinded_identity {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidData, "Remote identity key mismatch").into(),
+            );
+        }
+
+        if let Some(expected) = self.expected {
+            if received_remote_identity != expected {
+                return Err(Error::PeerMismatch);
+            }
+        }
+
+        Ok(received_remote_identity)
+    }
+}
+
+impl Encryptor {
+    fn new(version: u8, suite: CipherSuite, kdf: Transcript, stats: SessionStats) -> Self {
+        Encryptor {
+            version,
+            suite,
+            seq: 0,
+            kdf,
+            stats,
+        }
+    }
+
+    /// Returns the protocol version negotiated during the handshake.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Returns the cipher suite negotiated during the handshake.
+    pub fn cipher_suite(&self) -> CipherSuite {
+        self.suite
+    }
+
+    /// Returns the handle to the counters of the session.
+    pub fn stats(&self) -> SessionStats {
+        self.stats.clone()
+    }
+
+    /// Encrypts the plaintext and appends the resulting frames to `out`.
+    /// Plaintext longer than the maximum frame size is split in several frames.
+    pub fn encrypt(&mut self, plaintext: &[u8], out: &mut Vec<u8>) {
+        // Empty plaintext still produces a frame: the remote party reads it as the end of stream.
+        let mut chunks = plaintext.chunks(PT_BUF_SIZE - CT_TAG_SIZE);
+        let first = chunks.next().unwrap_or(&[]);
+        for chunk in core::iter::once(first).chain(chunks) {
+            let offset = out.len();
+            out.extend_from_slice(&[0u8; PT_OFFSET]);
+            out.extend_from_slice(chunk);
+            self.seal_frame(&mut out[offset..]);
+        }
+    }
+
+    /// Encrypts the frame in place: the frame consists of the space
+    /// for the length prefix and the tag, followed by the plaintext.
+    fn seal_frame(&mut self, frame: &mut [u8]) {
+        self.kdf.append_u64(b"seq", self.seq);
+        let mut key = [0u8; 32];
+        self.kdf.challenge_bytes(b"key", &mut key);
+
+        let tag = self
+            .suite
+            .aead()
+            .seal(&key, self.seq, &mut frame[PT_OFFSET..]);
+
+        let ct_len = (frame.len() - 2) as u16;
+        LittleEndian::write_u16(&mut frame[..2], ct_len);
+        frame[CT_LEN_SIZE..PT_OFFSET].copy_from_slice(&tag);
+
+        self.seq += 1;
+        self.stats.record_sent(frame.len());
+    }
+}
+
+impl Decryptor {
+    fn new(version: u8, suite: CipherSuite, kdf: Transcript, stats: SessionStats) -> Self {
+        Decryptor {
+            version,
+            suite,
+            seq: 0,
+            kdf,
+            buf: vec![0u8; CT_SIZE as usize], // TODO: allow user redefine this parameter
+            state: ReadState::Len(0),
+            stats,
+        }
+    }
+
+    /// Returns the protocol version negotiated during the handshake.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Returns the cipher suite negotiated during the handshake.
+    pub fn cipher_suite(&self) -> CipherSuite {
+        self.suite
+    }
+
+    /// Returns the handle to the counters of the session.
+    pub fn stats(&self) -> SessionStats {
+        self.stats.clone()
+    }
+
+    /// Returns the number of bytes needed to make progress:
+    /// the rest of the length prefix or of the ciphertext.
+    /// Returns zero if the decrypted plaintext must be read first.
+    pub fn bytes_needed(&self) -> usize {
+        match self.state {
+            ReadState::Len(already_read) => CT_LEN_SIZE - already_read,
+            ReadState::ReadCt(len, already_read) => len - already_read,
+            ReadState::ReadPt(..) => 0,
+        }
+    }
+
+    /// Returns true if a decrypted frame is available to `read_plaintext`.
+    /// Empty frame is reported as available too: the reader treats it as the end of stream.
+    pub fn has_plaintext(&self) -> bool {
+        match self.state {
+            ReadState::ReadPt(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Processes the received bytes up to the end of the current frame:
+    /// the following frames are not consumed until the plaintext is read.
+    /// Returns the number of bytes consumed.
+    /// Fails if the frame is malformed or cannot be authenticated.
+    pub fn feed(&mut self, mut bytes: &[u8]) -> Result<usize, io::Error> {
+        let total = bytes.len();
+        while !bytes.is_empty() && self.bytes_needed() > 0 {
+            let n = self.feed_step(bytes)?;
+            bytes = &bytes[n..];
+        }
+        Ok(total - bytes.len())
+    }
+
+    fn feed_step(&mut self, bytes: &[u8]) -> Result<usize, io::Error> {
+        let n = usize::min(self.bytes_needed(), bytes.len());
+        match self.state {
+            ReadState::Len(already_read) => {
+                self.buf[already_read..already_read + n].copy_from_slice(&bytes[..n]);
+                let already_read = already_read + n;
+                self.state = ReadState::Len(already_read);
+                if already_read == CT_LEN_SIZE {
+                    let length = LittleEndian::read_u16(&self.buf[..2]) as usize;
+                    if length < CT_TAG_SIZE {
+                        self.stats.record_failure();
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("length prefix: {} < 16", length),
+                        ));
+                    }
+                    self.buf.resize(length, 0);
+                    self.state = ReadState::ReadCt(length, 0);
+                }
+            }
+            ReadState::ReadCt(len, already_read) => {
+                self.buf[already_read..already_read + n].copy_from_slice(&bytes[..n]);
+                let already_read = already_read + n;
+                if already_read == len {
+                    let pt_len = self.decipher_buf(len)?;
+                    self.state = ReadState::ReadPt(pt_len, 0);
+                } else {
+                    self.state = ReadState::ReadCt(len, already_read);
+                }
+            }
+            ReadState::ReadPt(..) => {}
+        }
+        Ok(n)
+    }
+
+    /// Copies the decrypted plaintext into `buf` and returns the number of bytes copied.
+    /// Returns zero if there is no plaintext, or if the frame was empty.
+    pub fn read_plaintext(&mut self, buf: &mut [u8]) -> usize {
+        if let ReadState::ReadPt(pt_len, already_read) = self.state {
+            let read_now = usize::min(buf.len(), pt_len - already_read);
+            buf[..read_now].copy_from_slice(&self.buf[CT_TAG_SIZE + already_read..][..read_now]);
+            self.state = ReadState::ReadPt(pt_len, already_read + read_now);
+            if already_read + read_now == pt_len {
+                self.state = ReadState::Len(0);
+            }
+            read_now
+        } else {
+            0
+        }
+    }
+
+    fn decipher_buf(&mut self, ciphertext_length: usize) -> Result<usize, io::Error> {
+        let seq = self.seq;
+        self.seq += 1;
+
+        self.kdf.append_u64(b"seq", seq);
+        let mut key = [0u8; 32];
+        self.kdf.challenge_bytes(b"key", &mut key);
+
+        let mut tag = [0u8; CT_TAG_SIZE];
+        tag.copy_from_slice(&self.buf[..CT_TAG_SIZE]);
+        let stats = &self.stats;
+        self.suite
+            .aead()
+            .open(&key, seq, &mut self.buf[16..ciphertext_length], &tag)
+            .map_err(|e| {
+                stats.record_failure();
+                e
+            })?;
+
+        let pt_len = ciphertext_length - 16;
+        self.stats.record_received(CT_LEN_SIZE + ciphertext_length);
+
+        Ok(pt_len)
+    }
+}
+
+impl AeadSuite for AesPmacSiv {
+    fn seal(&self, key: &[u8; 32], seq: u64, buf: &mut [u8]) -> [u8; CT_TAG_SIZE] {
+        let ad = encode_u64le(seq);
+        let tag = Aes128PmacSiv::new(GenericArray::clone_from_slice(key))
+            .encrypt_in_place_detached(&[&ad], buf)
+            .expect("never fails because we have just one header");
+        let mut out = [0u8; CT_TAG_SIZE];
+        out.copy_from_slice(tag.as_slice());
+        out
+    }
+
+    fn open(
+        &self,
+        key: &[u8; 32],
+        seq: u64,
+        buf: &mut [u8],
+        tag: &[u8; CT_TAG_SIZE],
+    ) -> Result<(), io::Error> {
+        let ad = encode_u64le(seq);
+        Aes128PmacSiv::new(GenericArray::clone_from_slice(key))
+            .decrypt_in_place_detached(&[&ad], buf, GenericArray::from_slice(tag))
+            .map_err(|_| decrypt_error())
+    }
+}
+
+impl AeadSuite for ChaCha20Poly1305 {
+    fn seal(&self, key: &[u8; 32], seq: u64, buf: &mut [u8]) -> [u8; CT_TAG_SIZE] {
+        let tag = chacha20poly1305::ChaCha20Poly1305::new(AeadArray::from_slice(key))
+            .encrypt_in_place_detached(AeadArray::from_slice(&chacha_nonce(seq)), &[], buf)
+            .expect("never fails because the frames are shorter than 256 GiB");
+        let mut out = [0u8; CT_TAG_SIZE];
+        out.copy_from_slice(tag.as_slice());
+        out
+    }
 
-    // In order to authenticate the session, we send our first encrypted message
-    // in which we show the salt and the root pubkey.
-    // If the transmission was successful (authenticated decryption succeeded),
-    // we check the blinded key and then let user continue using the session.
-
-    // Prepare and send the message: salt and local identity pubkey.
-    outgoing.write_all(&local_salt[..]).await?;
-    outgoing.write_all(local_identity.pubkey.as_bytes()).await?;
-    outgoing.flush().await?;
-
-    // Receive the message from another end: their salt and their identity pubkey.
-    let mut remote_salt_and_id = [0u8; SALT_LEN + 32];
-    incoming.read_exact(&mut remote_salt_and_id).await?;
-    let received_remote_identity =
-        PublicKey::read_from(&mut &remote_salt_and_id[SALT_LEN..]).await?;
-
-    // Blinded key is also a secure commitment to the underlying key.
-    // Here we check that the remote party has sent us the correct identity key
-    // matching the blinded key they used for X3DH.
-    let received_remote_id_blinded = received_remote_identity
-        .blind(&remote_salt_and_id[0..SALT_LEN])
-        .ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Failed to decode Ristretto point",
+    fn open(
+        &self,
+        key: &[u8; 32],
+        seq: u64,
+        buf: &mut [u8],
+        tag: &[u8; CT_TAG_SIZE],
+    ) -> Result<(), io::Error> {
+        chacha20poly1305::ChaCha20Poly1305::new(AeadArray::from_slice(key))
+            .decrypt_in_place_detached(
+                AeadArray::from_slice(&chacha_nonce(seq)),
+                &[],
+                buf,
+                AeadArray::from_slice(tag),
             )
-        })?;
+            .map_err(|_| decrypt_error())
+    }
+}
+
+impl CipherSuite {
+    /// Returns all the suites in the order of preference.
+    pub fn all() -> &'static [CipherSuite] {
+        &[CipherSuite::AesPmacSiv, CipherSuite::ChaCha20Poly1305]
+    }
+
+    /// Returns the ID of the suite: its bit in the bitmask of the supported suites.
+    pub fn id(self) -> u8 {
+        match self {
+            CipherSuite::AesPmacSiv => 0,
+            CipherSuite::ChaCha20Poly1305 => 1,
+        }
+    }
+
+    /// Returns the suite with a given ID.
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::all().iter().copied().find(|suite| suite.id() == id)
+    }
+
+    /// Returns the implementation of the suite.
+    pub fn aead(self) -> &'static dyn AeadSuite {
+        match self {
+            CipherSuite::AesPmacSiv => &AesPmacSiv,
+            CipherSuite::ChaCha20Poly1305 => &ChaCha20Poly1305,
+        }
+    }
+}
+
+impl SessionStats {
+    /// Number of the frames encrypted and sent to the remote party.
+    pub fn frames_sent(&self) -> u64 {
+        self.counters.frames_sent.load(Ordering::Relaxed)
+    }
+
+    /// Number of the frames received and successfully decrypted.
+    pub fn frames_received(&self) -> u64 {
+        self.counters.frames_received.load(Ordering::Relaxed)
+    }
+
+    /// Number of the ciphertext bytes sent, including the length prefixes and the tags.
+    pub fn bytes_sent(&self) -> u64 {
+        self.counters.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    /// Number of the ciphertext bytes received, including the length prefixes and the tags.
+    pub fn bytes_received(&self) -> u64 {
+        self.counters.bytes_received.load(Ordering::Relaxed)
+    }
+
+    /// Number of the key ratchet steps in both directions,
+    /// including the ones for the frames that failed to decrypt.
+    pub fn ratchet_steps(&self) -> u64 {
+        self.counters.ratchet_steps.load(Ordering::Relaxed)
+    }
+
+    /// Number of the received frames that were malformed or could not be authenticated.
+    pub fn decrypt_failures(&self) -> u64 {
+        self.counters.decrypt_failures.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if a received frame failed to decrypt.
+    /// The ratchet cannot recover from that, so the session must be re-established.
+    pub fn is_corrupted(&self) -> bool {
+        self.decrypt_failures() > 0
+    }
+
+    fn record_sent(&self, frame_len: usize) {
+        let c = &self.counters;
+        c.frames_sent.fetch_add(1, Ordering::Relaxed);
+        c.bytes_sent.fetch_add(frame_len as u64, Ordering::Relaxed);
+        c.ratchet_steps.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_received(&self, frame_len: usize) {
+        let c = &self.counters;
+        c.frames_received.fetch_add(1, Ordering::Relaxed);
+        c.bytes_received
+            .fetch_add(frame_len as u64, Ordering::Relaxed);
+        c.ratchet_steps.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_failure(&self) {
+        self.counters
+            .decrypt_failures
+            .fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(e) => e,
+            Error::PeerMismatch
+            | Error::IncompatibleVersion(_)
+            | Error::IncompatibleCipherSuite(_) => {
+                io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "Cybershake I/O error: {}", e),
+            Error::PeerMismatch => write!(f, "Remote identity does not match the expected peer"),
+            Error::IncompatibleVersion(versions) => write!(
+                f,
+                "Incompatible cybershake version: remote supports {:#b}, local supports {:#b}",
+                versions, SUPPORTED_VERSIONS
+            ),
+            Error::IncompatibleCipherSuite(suites) => write!(
+                f,
+                "Incompatible cybershake cipher suites: remote supports {:#b}",
+                suites
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+macro_rules! ready {
+    ($($tokens:tt)*) => {
+        match $($tokens)* {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(Ok(n)) => { n }
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+        }
+    };
+}
+
+/// Polling interface of the byte-oriented reader of a particular async runtime.
+trait ReadDriver<R> {
+    fn poll_read(
+        reader: Pin<&mut R>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>>;
+}
+
+/// Polling interface of the byte-oriented writer of a particular async runtime.
+trait WriteDriver<W> {
+    fn poll_write(
+        writer: Pin<&mut W>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>>;
+    fn poll_flush(writer: Pin<&mut W>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>>;
+    fn poll_close(writer: Pin<&mut W>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>>;
+}
+
+/// Driver for the `tokio::io` traits.
+#[cfg(feature = "tokio-runtime")]
+enum TokioDriver {}
+
+/// Driver for the `futures::io` traits.
+enum FuturesDriver {}
+
+#[cfg(feature = "tokio-runtime")]
+impl<R: tokio::io::AsyncRead> ReadDriver<R> for TokioDriver {
+    fn poll_read(
+        reader: Pin<&mut R>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        tokio::io::AsyncRead::poll_read(reader, cx, buf)
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+impl<W: tokio::io::AsyncWrite> WriteDriver<W> for TokioDriver {
+    fn poll_write(
+        writer: Pin<&mut W>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        tokio::io::AsyncWrite::poll_write(writer, cx, buf)
+    }
+
+    fn poll_flush(writer: Pin<&mut W>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        tokio::io::AsyncWrite::poll_flush(writer, cx)
+    }
+
+    fn poll_close(writer: Pin<&mut W>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        tokio::io::AsyncWrite::poll_shutdown(writer, cx)
+    }
+}
+
+impl<R: futures::io::AsyncRead> ReadDriver<R> for FuturesDriver {
+    fn poll_read(
+        reader: Pin<&mut R>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        futures::io::AsyncRead::poll_read(reader, cx, buf)
+    }
+}
+
+impl<W: futures::io::AsyncWrite> WriteDriver<W> for FuturesDriver {
+    fn poll_write(
+        writer: Pin<&mut W>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        futures::io::AsyncWrite::poll_write(writer, cx, buf)
+    }
+
+    fn poll_flush(writer: Pin<&mut W>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        futures::io::AsyncWrite::poll_flush(writer, cx)
+    }
 
-    if received_remote_id_blinded != remote_blinded_identity {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Remote identity key mismatch",
-        ));
+    fn poll_close(writer: Pin<&mut W>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        futures::io::AsyncWrite::poll_close(writer, cx)
     }
+}
 
-    Ok((received_remote_identity, outgoing, incoming))
+/// Writes and flushes the entire buffer.
+async fn write_all<D: WriteDriver<W>, W: Unpin>(
+    writer: &mut Pin<Box<W>>,
+    buf: &[u8],
+) -> Result<(), io::Error> {
+    let mut written = 0;
+    while written < buf.len() {
+        let n = poll_fn(|cx| D::poll_write(writer.as_mut(), cx, &buf[written..])).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        written += n;
+    }
+    poll_fn(|cx| D::poll_flush(writer.as_mut(), cx)).await
 }
 
-macro_rules! ready {
-    ($($tokens:tt)*) => {
-        match $($tokens)* {
-            Poll::Pending => return Poll::Pending,
-            Poll::Ready(Ok(n)) => { n }
-            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+/// Fills the entire buffer.
+async fn read_exact<D: ReadDriver<R>, R: Unpin>(
+    reader: &mut Pin<Box<R>>,
+    buf: &mut [u8],
+) -> Result<(), io::Error> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = poll_fn(|cx| D::poll_read(reader.as_mut(), cx, &mut buf[filled..])).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
         }
-    };
+        filled += n;
+    }
+    Ok(())
 }
 
-impl<W: AsyncWrite + Unpin> Outgoing<W> {
-    fn cipher_buf(&mut self) {
-        self.kdf.append_u64(b"seq", self.seq);
-        let mut key = [0u8; 32];
-        self.kdf.challenge_bytes(b"key", &mut key);
+impl<W: Unpin> Outgoing<W> {
+    fn new(writer: Pin<Box<W>>, encryptor: Encryptor) -> Self {
+        let mut buf = Vec::with_capacity(CT_SIZE as usize + CT_LEN_SIZE);
+        buf.extend_from_slice(&[0; PT_OFFSET]);
+        Outgoing {
+            writer,
+            encryptor,
+            buf,
+            flushing: false,
+            ciphertext_sent: 0,
+        }
+    }
 
-        let ad = encode_u64le(self.seq);
+    /// Returns the protocol version negotiated during the handshake.
+    pub fn version(&self) -> u8 {
+        self.encryptor.version()
+    }
 
-        let tag = Aes128PmacSiv::new(GenericArray::clone_from_slice(&key))
-            .encrypt_in_place_detached(&[&ad], &mut self.buf[PT_OFFSET..])
-            .expect("never fails because we have just one header");
+    /// Returns the cipher suite negotiated during the handshake.
+    pub fn cipher_suite(&self) -> CipherSuite {
+        self.encryptor.cipher_suite()
+    }
 
-        let ct_len = (self.buf.len() - 2) as u16;
-        LittleEndian::write_u16(&mut self.buf[..2], ct_len);
-        self.buf.as_mut_slice()[CT_LEN_SIZE..PT_OFFSET].copy_from_slice(tag.as_slice());
+    /// Returns the handle to the counters of the session.
+    pub fn stats(&self) -> SessionStats {
+        self.encryptor.stats()
+    }
 
-        self.seq += 1;
+    fn cipher_buf(&mut self) {
+        self.encryptor.seal_frame(&mut self.buf[..]);
         self.flushing = true;
     }
 
-    fn flush_pending_ciphertext(&mut self, cx: &mut Context) -> Poll<Result<(), io::Error>> {
+    fn flush_pending_ciphertext<D: WriteDriver<W>>(
+        &mut self,
+        cx: &mut Context,
+    ) -> Poll<Result<(), io::Error>> {
         if !self.flushing {
             return Poll::Ready(Ok(()));
         }
         while self.ciphertext_sent < self.buf.len() {
-            let poll = self
-                .writer
-                .as_mut()
-                .poll_write(cx, &self.buf[self.ciphertext_sent..]);
+            let poll = D::poll_write(self.writer.as_mut(), cx, &self.buf[self.ciphertext_sent..]);
             let n = ready!(poll);
             self.ciphertext_sent += n;
         }
-        ready!(self.writer.as_mut().poll_flush(cx));
+        ready!(D::poll_flush(self.writer.as_mut(), cx));
         self.ciphertext_sent = 0;
         self.flushing = false;
         self.buf.truncate(PT_OFFSET);
         Poll::Ready(Ok(()))
     }
-}
 
-impl<W: AsyncWrite + Unpin> AsyncWrite for Outgoing<W> {
-    fn poll_write(
-        self: Pin<&mut Self>,
+    fn poll_write_with<D: WriteDriver<W>>(
+        &mut self,
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<Result<usize, io::Error>> {
-        let me = self.get_mut();
+        ready!(self.flush_pending_ciphertext::<D>(cx));
 
-        ready!(me.flush_pending_ciphertext(cx));
-
-        if me.buf.len() + buf.len() >= PT_BUF_SIZE + CT_LEN_SIZE {
+        if self.buf.len() + buf.len() >= PT_BUF_SIZE + CT_LEN_SIZE {
             // plaintext_buf has BUF_SIZE size, so subtract with overflow will be never.
-            let size_to_write = PT_BUF_SIZE + CT_LEN_SIZE - me.buf.len();
-            me.buf.extend_from_slice(&buf[..size_to_write]);
-            me.cipher_buf();
-            ready!(me.flush_pending_ciphertext(cx));
+            let size_to_write = PT_BUF_SIZE + CT_LEN_SIZE - self.buf.len();
+            self.buf.extend_from_slice(&buf[..size_to_write]);
+            self.cipher_buf();
+            ready!(self.flush_pending_ciphertext::<D>(cx));
             Poll::Ready(Ok(size_to_write))
         } else {
-            me.buf.extend_from_slice(buf);
+            self.buf.extend_from_slice(buf);
             Poll::Ready(Ok(buf.len()))
         }
     }
 
-    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
-        let me = self.get_mut();
-        if !me.flushing {
-            if me.buf.is_empty() {
+    fn poll_flush_with<D: WriteDriver<W>>(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        if !self.flushing {
+            if self.buf.is_empty() {
                 return Poll::Ready(Ok(()));
             }
-            me.cipher_buf();
+            self.cipher_buf();
         }
-        me.flush_pending_ciphertext(cx)
+        self.flush_pending_ciphertext::<D>(cx)
     }
 
-    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
-        let me = self.get_mut();
-        if !me.flushing {
-            me.cipher_buf();
+    fn poll_close_with<D: WriteDriver<W>>(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        if !self.flushing {
+            self.cipher_buf();
         }
-        ready!(me.flush_pending_ciphertext(cx));
-        me.writer.as_mut().poll_shutdown(cx)
+        ready!(self.flush_pending_ciphertext::<D>(cx));
+        D::poll_close(self.writer.as_mut(), cx)
     }
 }
 
-impl<W: AsyncWrite + Unpin> Outgoing<W> {
+#[cfg(feature = "tokio-runtime")]
+impl<W: tokio::io::AsyncWrite + Unpin> Outgoing<W> {
     /// Send a message of any length.
     /// This is a temporary. We'll replace this with Tokio Codecs.
     pub async fn send_message(&mut self, msg: &[u8]) -> Result<(), io::Error> {
+        use tokio::io::AsyncWriteExt;
         self.write_all(&msg).await?;
         self.flush().await?;
         Ok(())
     }
 }
 
-impl<R: AsyncRead + Unpin> Incoming<R> {
-    fn decipher_buf(&mut self, ciphertext_length: usize) -> Result<usize, io::Error> {
-        let seq = self.seq;
-        self.seq += 1;
+#[cfg(feature = "tokio-runtime")]
+impl<W: tokio::io::AsyncWrite + Unpin> tokio::io::AsyncWrite for Outgoing<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.get_mut().poll_write_with::<TokioDriver>(cx, buf)
+    }
 
-        self.kdf.append_u64(b"seq", seq);
-        let mut key = [0u8; 32];
-        self.kdf.challenge_bytes(b"key", &mut key);
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.get_mut().poll_flush_with::<TokioDriver>(cx)
+    }
 
-        let ad = encode_u64le(seq);
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.get_mut().poll_close_with::<TokioDriver>(cx)
+    }
+}
 
-        let siv_tag = GenericArray::clone_from_slice(&self.buf[..16]);
-        Aes128PmacSiv::new(GenericArray::clone_from_slice(&key))
-            .decrypt_in_place_detached(&[&ad], &mut self.buf[16..ciphertext_length], &siv_tag)
-            .map_err(|_| {
-                io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "An error was occured when try to decipher data.",
-                )
-            })?;
+impl<W: futures::io::AsyncWrite + Unpin> futures::io::AsyncWrite for Outgoing<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.get_mut().poll_write_with::<FuturesDriver>(cx, buf)
+    }
 
-        let pt_len = ciphertext_length - 16;
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.get_mut().poll_flush_with::<FuturesDriver>(cx)
+    }
 
-        Ok(pt_len)
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.get_mut().poll_close_with::<FuturesDriver>(cx)
     }
 }
 
-impl<R: AsyncRead + Unpin> AsyncRead for Incoming<R> {
-    fn poll_read(
-        self: Pin<&mut Self>,
+impl<R: Unpin> Incoming<R> {
+    fn new(reader: Pin<Box<R>>, decryptor: Decryptor) -> Self {
+        Incoming {
+            reader,
+            decryptor,
+            buf: vec![0u8; CT_SIZE as usize],
+        }
+    }
+
+    /// Returns the protocol version negotiated during the handshake.
+    pub fn version(&self) -> u8 {
+        self.decryptor.version()
+    }
+
+    /// Returns the cipher suite negotiated during the handshake.
+    pub fn cipher_suite(&self) -> CipherSuite {
+        self.decryptor.cipher_suite()
+    }
+
+    /// Returns the handle to the counters of the session.
+    pub fn stats(&self) -> SessionStats {
+        self.decryptor.stats()
+    }
+
+    fn poll_read_with<D: ReadDriver<R>>(
+        &mut self,
         cx: &mut Context<'_>,
         buf: &mut [u8],
     ) -> Poll<Result<usize, io::Error>> {
-        let me = self.get_mut();
-
         loop {
-            match me.state {
-                ReadState::Len(mut already_read) => {
-                    let poll = me
-                        .reader
-                        .as_mut()
-                        .poll_read(cx, &mut me.buf[already_read..2]);
-                    let n = ready!(poll);
-                    if n == 0 {
-                        return Poll::Ready(Ok(0));
-                    }
-                    already_read += n;
-                    me.state = ReadState::Len(already_read);
-                    if already_read == 2 {
-                        let length = LittleEndian::read_u16(&me.buf[..2]) as usize;
-                        if length < 16 {
-                            return Poll::Ready(Err(io::Error::new(
-                                io::ErrorKind::InvalidData,
-                                format!("length prefix: {} < 16", length),
-                            )));
-                        }
-                        me.buf.resize(length, 0);
-                        me.state = ReadState::ReadCt(length, 0);
-                    }
-                }
-                ReadState::ReadCt(len, mut already_read) => {
-                    let poll = me
-                        .reader
-                        .as_mut()
-                        .poll_read(cx, &mut me.buf[already_read..len]);
-                    let n = ready!(poll);
-                    if n == 0 {
-                        return Poll::Ready(Err(io::Error::new(
-                            io::ErrorKind::WouldBlock,
-                            format!("Expected length {}, but found {}.", len, already_read),
-                        )));
-                    }
-                    already_read += n;
-                    if already_read == len {
-                        match me.decipher_buf(len) {
-                            Ok(pt_len) => me.state = ReadState::ReadPt(pt_len, 0),
-                            Err(e) => return Poll::Ready(Err(e)),
-                        }
-                    } else {
-                        me.state = ReadState::ReadCt(len, already_read);
-                    }
-                }
-                ReadState::ReadPt(pt_len, already_read) => {
-                    let read_now = usize::min(buf.len(), pt_len - already_read);
-                    buf[..read_now]
-                        .copy_from_slice(&me.buf[CT_TAG_SIZE + already_read..][..read_now]);
-                    me.state = ReadState::ReadPt(pt_len, already_read + read_now);
-                    if already_read + read_now == pt_len {
-                        me.state = ReadState::Len(0);
-                    }
-                    return Poll::Ready(Ok(read_now));
-                }
+            if self.decryptor.has_plaintext() {
+                return Poll::Ready(Ok(self.decryptor.read_plaintext(buf)));
+            }
+            let needed = usize::min(self.decryptor.bytes_needed(), self.buf.len());
+            let poll = D::poll_read(self.reader.as_mut(), cx, &mut self.buf[..needed]);
+            let n = ready!(poll);
+            if n == 0 {
+                return match self.decryptor.state {
+                    ReadState::ReadCt(len, already_read) => Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::WouldBlock,
+                        format!("Expected length {}, but found {}.", len, already_read),
+                    ))),
+                    _ => Poll::Ready(Ok(0)),
+                };
+            }
+            if let Err(e) = self.decryptor.feed(&self.buf[..n]) {
+                return Poll::Ready(Err(e));
             }
         }
     }
 }
 
+#[cfg(feature = "tokio-runtime")]
+impl<R: tokio::io::AsyncRead + Unpin> tokio::io::AsyncRead for Incoming<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.get_mut().poll_read_with::<TokioDriver>(cx, buf)
+    }
+}
+
+impl<R: futures::io::AsyncRead + Unpin> futures::io::AsyncRead for Incoming<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.get_mut().poll_read_with::<FuturesDriver>(cx, buf)
+    }
+}
+
 fn cybershake_dh(id1: &PrivateKey, id2: &PublicKey) -> Result<Transcript, io::Error> {
     let mut t = Transcript::new(b"Cybershake.DH");
     let keep_order = id1.pubkey.as_bytes() < id2.as_bytes();
@@ -506,13 +1477,6 @@ impl PublicKey {
             PublicKey::from(p + keyblinding_factor(&self.point, salt) * RISTRETTO_BASEPOINT_POINT)
         })
     }
-
-    /// Reads pubkey from a reader.
-    async fn read_from<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self, io::Error> {
-        let mut buf = [0u8; 32];
-        reader.read_exact(&mut buf[..]).await?;
-        Ok(Self::from(CompressedRistretto(buf)))
-    }
 }
 
 fn keyblinding_factor(pubkey: &CompressedRistretto, salt: &[u8]) -> Scalar {
@@ -528,6 +1492,40 @@ fn challenge_scalar(label: &'static [u8], transcript: &mut Transcript) -> Scalar
     Scalar::from_bytes_mod_order_wide(&buf)
 }
 
+/// Selects the highest version present in both bitmasks.
+fn negotiate_version(local_versions: u64, remote_versions: u64) -> Option<u8> {
+    let mutual = local_versions & remote_versions;
+    if mutual == 0 {
+        return None;
+    }
+    Some(63 - mutual.leading_zeros() as u8)
+}
+
+/// Selects the mutual cipher suite with the lowest ID,
+/// so both parties arrive at the same suite.
+fn negotiate_cipher_suite(local_suites: u64, remote_suites: u64) -> Option<CipherSuite> {
+    let mutual = local_suites & remote_suites;
+    if mutual == 0 {
+        return None;
+    }
+    CipherSuite::from_id(mutual.trailing_zeros() as u8)
+}
+
+/// Nonce for ChaCha20-Poly1305: the sequence number of the frame, padded to 96 bits.
+/// Each frame has its own key, so the nonce does not have to be secret or random.
+fn chacha_nonce(seq: u64) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    LittleEndian::write_u64(&mut nonce[..8], seq);
+    nonce
+}
+
+fn decrypt_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "An error was occured when try to decipher data.",
+    )
+}
+
 fn encode_u64le(i: u64) -> [u8; 8] {
     let mut buf = [0u8; 8];
     LittleEndian::write_u64(&mut buf, i);
@@ -537,12 +1535,464 @@ fn encode_u64le(i: u64) -> [u8; 8] {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use futures::channel::mpsc;
+    use futures::stream::TryStreamExt;
     use rand::rngs::StdRng;
     use rand::SeedableRng;
+    #[cfg(feature = "tokio-runtime")]
     use tokio::net::{TcpListener, TcpStream};
 
+    #[test]
+    fn version_negotiation() {
+        assert_eq!(negotiate_version(0b1, 0b1), Some(0));
+        assert_eq!(negotiate_version(0b111, 0b110), Some(2));
+        assert_eq!(negotiate_version(0b011, 0b110), Some(1));
+        assert_eq!(negotiate_version(1 << 63, u64::max_value()), Some(63));
+        assert_eq!(negotiate_version(0b01, 0b10), None);
+        assert_eq!(negotiate_version(0b01, 0), None);
+    }
+
+    #[test]
+    fn cipher_suite_negotiation() {
+        assert_eq!(
+            negotiate_cipher_suite(0b11, 0b11),
+            Some(CipherSuite::AesPmacSiv)
+        );
+        assert_eq!(
+            negotiate_cipher_suite(0b11, 0b10),
+            Some(CipherSuite::ChaCha20Poly1305)
+        );
+        assert_eq!(
+            negotiate_cipher_suite(0b10, 0b11),
+            Some(CipherSuite::ChaCha20Poly1305)
+        );
+        assert_eq!(negotiate_cipher_suite(0b01, 0b10), None);
+        assert_eq!(negotiate_cipher_suite(0b100, 0b100), None);
+    }
+
+    /// Runs the handshake between two parties, feeding the bytes one at a time.
+    fn handshake_pair(
+        alice: Handshake,
+        bob: Handshake,
+    ) -> (Result<Session, Error>, Result<Session, Error>) {
+        let mut alice = Ok(alice);
+        let mut bob = Ok(bob);
+        loop {
+            let to_bob = alice.as_mut().map(|h| h.take_output()).unwrap_or_default();
+            let to_alice = bob.as_mut().map(|h| h.take_output()).unwrap_or_default();
+            if to_bob.is_empty() && to_alice.is_empty() {
+                break;
+            }
+            for byte in to_bob.iter() {
+                bob = bob.and_then(|mut h| h.feed(&[*byte]).map(|_| h));
+            }
+            for byte in to_alice.iter() {
+                alice = alice.and_then(|mut h| h.feed(&[*byte]).map(|_| h));
+            }
+        }
+        let finish = |h: Result<Handshake, Error>| {
+            h.map(|h| h.into_session().expect("handshake should be complete"))
+        };
+        (finish(alice), finish(bob))
+    }
+
+    #[test]
+    fn sans_io_handshake() {
+        let alice_private_key = PrivateKey::from(Scalar::from(1u64));
+        let bob_private_key = PrivateKey::from(Scalar::from(2u64));
+
+        let alice = Handshake::new(&alice_private_key, None, StdRng::seed_from_u64(1));
+        let bob = Handshake::new(
+            &bob_private_key,
+            Some(alice_private_key.to_public_key()),
+            StdRng::seed_from_u64(2),
+        );
+        assert!(!alice.is_complete());
+        assert_eq!(alice.bytes_needed(), HELLO_LEN);
+
+        let (alice, bob) = handshake_pair(alice, bob);
+        let mut alice = alice.expect("alice: should handshake correctly");
+        let mut bob = bob.expect("bob: should handshake correctly");
+        assert_eq!(alice.remote_identity, bob_private_key.to_public_key());
+        assert_eq!(bob.remote_identity, alice_private_key.to_public_key());
+        assert_eq!(alice.encryptor.version(), 0);
+        assert_eq!(bob.decryptor.version(), 0);
+
+        // Alice sends two messages, Bob receives them in arbitrary chunks.
+        let mut frames = Vec::new();
+        alice.encryptor.encrypt(b"Hello, Bob", &mut frames);
+        alice.encryptor.encrypt(&vec![10u8; 6000], &mut frames);
+
+        let mut received = Vec::new();
+        let mut buf = [0u8; 100];
+        for chunk in frames.chunks(7) {
+            let mut chunk = &chunk[..];
+            while !chunk.is_empty() {
+                let n = bob.decryptor.feed(chunk).expect("bob: should decrypt");
+                chunk = &chunk[n..];
+                while bob.decryptor.has_plaintext() {
+                    let m = bob.decryptor.read_plaintext(&mut buf);
+                    received.extend_from_slice(&buf[..m]);
+                }
+            }
+        }
+        assert_eq!(&received[..10], b"Hello, Bob");
+        assert_eq!(&received[10..], &vec![10u8; 6000][..]);
+        assert_eq!(bob.decryptor.bytes_needed(), CT_LEN_SIZE);
+
+        // Both parties count the same frames, including the ones of the handshake.
+        let (alice_stats, bob_stats) = (alice.encryptor.stats(), bob.decryptor.stats());
+        assert_eq!(alice_stats.frames_sent(), bob_stats.frames_received());
+        assert_eq!(alice_stats.bytes_sent(), bob_stats.bytes_received());
+        assert!(!bob_stats.is_corrupted());
+
+        // Empty message is an empty frame, read as the end of stream.
+        let mut frames = Vec::new();
+        bob.encryptor.encrypt(&[], &mut frames);
+        assert_eq!(alice.decryptor.feed(&frames).unwrap(), frames.len());
+        assert!(alice.decryptor.has_plaintext());
+        assert_eq!(alice.decryptor.read_plaintext(&mut buf), 0);
+        assert!(!alice.decryptor.has_plaintext());
+    }
+
+    #[test]
+    fn sans_io_cipher_suites() {
+        let alice_private_key = PrivateKey::from(Scalar::from(1u64));
+        let bob_private_key = PrivateKey::from(Scalar::from(2u64));
+
+        // Bob supports only ChaCha20-Poly1305, so both parties select it.
+        let alice = Handshake::with_cipher_suites(
+            &alice_private_key,
+            None,
+            CipherSuite::all(),
+            StdRng::seed_from_u64(1),
+        );
+        let bob = Handshake::with_cipher_suites(
+            &bob_private_key,
+            None,
+            &[CipherSuite::ChaCha20Poly1305],
+            StdRng::seed_from_u64(2),
+        );
+        let (alice, bob) = handshake_pair(alice, bob);
+        let mut alice = alice.expect("alice: should handshake correctly");
+        let mut bob = bob.expect("bob: should handshake correctly");
+        assert_eq!(
+            alice.encryptor.cipher_suite(),
+            CipherSuite::ChaCha20Poly1305
+        );
+        assert_eq!(bob.decryptor.cipher_suite(), CipherSuite::ChaCha20Poly1305);
+
+        let mut frames = Vec::new();
+        alice.encryptor.encrypt(b"Hello, Bob", &mut frames);
+        bob.decryptor.feed(&frames).expect("bob: should decrypt");
+        let mut buf = [0u8; 100];
+        let n = bob.decryptor.read_plaintext(&mut buf);
+        assert_eq!(&buf[..n], b"Hello, Bob");
+
+        // Hello without the suites is treated as supporting only AES-PMAC-SIV.
+        let mut carol = Handshake::with_cipher_suites(
+            &alice_private_key,
+            None,
+            &[CipherSuite::ChaCha20Poly1305],
+            StdRng::seed_from_u64(3),
+        );
+        let mut hello = encode_u64le(SUPPORTED_VERSIONS).to_vec();
+        hello.extend_from_slice(bob_private_key.to_public_key().as_bytes());
+        match carol.feed(&hello) {
+            Err(Error::IncompatibleCipherSuite(0b1)) => {}
+            _ => panic!("carol: should fail with IncompatibleCipherSuite"),
+        }
+    }
+
+    #[test]
+    fn sans_io_pinned_peer_mismatch() {
+        let alice_private_key = PrivateKey::from(Scalar::from(1u64));
+        let bob_private_key = PrivateKey::from(Scalar::from(2u64));
+        let eve_public_key = PrivateKey::from(Scalar::from(3u64)).to_public_key();
+
+        let alice = Handshake::new(
+            &alice_private_key,
+            Some(eve_public_key),
+            StdRng::seed_from_u64(1),
+        );
+        let bob = Handshake::new(&bob_private_key, None, StdRng::seed_from_u64(2));
+
+        match handshake_pair(alice, bob) {
+            (Err(Error::PeerMismatch), Ok(bob)) => {
+                assert_eq!(bob.remote_identity, alice_private_key.to_public_key())
+            }
+            _ => panic!("alice: should fail with PeerMismatch"),
+        }
+    }
+
+    #[test]
+    fn sans_io_incompatible_version() {
+        let alice_private_key = PrivateKey::from(Scalar::from(1u64));
+        let mut alice = Handshake::new(&alice_private_key, None, StdRng::seed_from_u64(1));
+
+        let mut hello = encode_u64le(0b10).to_vec();
+        hello.extend_from_slice(alice_private_key.to_public_key().as_bytes());
+        match alice.feed(&hello) {
+            Err(Error::IncompatibleVersion(0b10)) => {}
+            _ => panic!("alice: should fail with IncompatibleVersion"),
+        }
+    }
+
+    /// Encrypts a frame the way the legacy peers do.
+    fn legacy_seal(kdf: &mut Transcript, seq: u64, plaintext: &[u8]) -> Vec<u8> {
+        kdf.append_u64(b"seq", seq);
+        let mut key = [0u8; 32];
+        kdf.challenge_bytes(b"key", &mut key);
+        let mut frame = vec![0u8; PT_OFFSET];
+        frame.extend_from_slice(plaintext);
+        let tag = Aes128PmacSiv::new(GenericArray::clone_from_slice(&key))
+            .encrypt_in_place_detached(&[&encode_u64le(seq)], &mut frame[PT_OFFSET..])
+            .unwrap();
+        let ct_len = (frame.len() - CT_LEN_SIZE) as u16;
+        LittleEndian::write_u16(&mut frame[..CT_LEN_SIZE], ct_len);
+        frame[CT_LEN_SIZE..PT_OFFSET].copy_from_slice(tag.as_slice());
+        frame
+    }
+
+    /// Decrypts a frame the way the legacy peers do.
+    fn legacy_open(kdf: &mut Transcript, seq: u64, frame: &[u8]) -> Vec<u8> {
+        kdf.append_u64(b"seq", seq);
+        let mut key = [0u8; 32];
+        kdf.challenge_bytes(b"key", &mut key);
+        let ct_len = LittleEndian::read_u16(&frame[..CT_LEN_SIZE]) as usize;
+        assert_eq!(frame.len(), CT_LEN_SIZE + ct_len);
+        let mut buf = frame[PT_OFFSET..].to_vec();
+        Aes128PmacSiv::new(GenericArray::clone_from_slice(&key))
+            .decrypt_in_place_detached(
+                &[&encode_u64le(seq)],
+                &mut buf,
+                GenericArray::from_slice(&frame[CT_LEN_SIZE..PT_OFFSET]),
+            )
+            .expect("legacy peer: should decrypt");
+        buf
+    }
+
+    #[test]
+    fn sans_io_legacy_peer() {
+        let alice_private_key = PrivateKey::from(Scalar::from(1u64));
+        let bob_private_key = PrivateKey::from(Scalar::from(2u64));
+        let mut alice = Handshake::new(
+            &alice_private_key,
+            Some(bob_private_key.to_public_key()),
+            StdRng::seed_from_u64(1),
+        );
+
+        // Legacy peers accept only the zero version in the hello.
+        let alice_hello = alice.take_output();
+        assert_eq!(alice_hello.len(), HELLO_LEN);
+        assert_eq!(LittleEndian::read_u64(&alice_hello[..8]), 0);
+        let alice_blinded_identity =
+            PublicKey::from(CompressedRistretto::from_slice(&alice_hello[8..]));
+
+        // Bob is a legacy peer: he sends the zero version and his blinded identity,
+        // and derives the keys from the Diffie-Hellman transcript alone.
+        let bob_salt = [7u8; SALT_LEN];
+        let bob_blinded_identity = bob_private_key.blind(&bob_salt);
+        let mut bob_hello = encode_u64le(0).to_vec();
+        bob_hello.extend_from_slice(bob_blinded_identity.pubkey.as_bytes());
+        let t = cybershake_dh(&bob_blinded_identity, &alice_blinded_identity).unwrap();
+        let mut bob_outgoing = t.clone();
+        let mut bob_incoming = t;
+        bob_outgoing.append_message(b"src", bob_blinded_identity.pubkey.as_bytes());
+        bob_incoming.append_message(b"src", alice_blinded_identity.as_bytes());
+
+        assert_eq!(
+            alice.feed(&bob_hello).expect("alice: should accept"),
+            HELLO_LEN
+        );
+
+        // Bob authenticates Alice's frame with his keys.
+        let alice_auth = alice.take_output();
+        let auth = legacy_open(&mut bob_incoming, 0, &alice_auth);
+        assert_eq!(
+            &auth[SALT_LEN..],
+            alice_private_key.to_public_key().as_bytes()
+        );
+        assert_eq!(
+            alice_private_key.to_public_key().blind(&auth[..SALT_LEN]),
+            Some(alice_blinded_identity)
+        );
+
+        // Alice authenticates Bob's frame and completes the session.
+        let mut bob_auth = bob_salt.to_vec();
+        bob_auth.extend_from_slice(bob_private_key.to_public_key().as_bytes());
+        let frame = legacy_seal(&mut bob_outgoing, 0, &bob_auth);
+        assert_eq!(
+            alice.feed(&frame).expect("alice: should authenticate"),
+            frame.len()
+        );
+        let mut alice = alice.into_session().expect("alice: should complete");
+        assert_eq!(alice.remote_identity, bob_private_key.to_public_key());
+        assert_eq!(alice.encryptor.version(), 0);
+        assert_eq!(alice.encryptor.cipher_suite(), CipherSuite::AesPmacSiv);
+
+        // The session continues in both directions.
+        let frame = legacy_seal(&mut bob_outgoing, 1, b"Hello, Alice");
+        alice.decryptor.feed(&frame).expect("alice: should decrypt");
+        let mut buf = [0u8; 100];
+        let n = alice.decryptor.read_plaintext(&mut buf);
+        assert_eq!(&buf[..n], b"Hello, Alice");
+
+        let mut frame = Vec::new();
+        alice.encryptor.encrypt(b"Hello, Bob", &mut frame);
+        assert_eq!(legacy_open(&mut bob_incoming, 1, &frame), b"Hello, Bob");
+    }
+
+    #[test]
+    fn sans_io_legacy_hello_from_negotiating_peer() {
+        let alice_private_key = PrivateKey::from(Scalar::from(1u64));
+        let bob_private_key = PrivateKey::from(Scalar::from(2u64));
+
+        // Alice lists all the suites, Bob sends the legacy hello:
+        // both select AES-PMAC-SIV without binding the bitmasks.
+        let alice = Handshake::with_cipher_suites(
+            &alice_private_key,
+            None,
+            CipherSuite::all(),
+            StdRng::seed_from_u64(1),
+        );
+        let bob = Handshake::new(&bob_private_key, None, StdRng::seed_from_u64(2));
+        let (alice, bob) = handshake_pair(alice, bob);
+        let mut alice = alice.expect("alice: should handshake correctly");
+        let mut bob = bob.expect("bob: should handshake correctly");
+        assert_eq!(alice.encryptor.cipher_suite(), CipherSuite::AesPmacSiv);
+        assert_eq!(bob.decryptor.cipher_suite(), CipherSuite::AesPmacSiv);
+
+        let mut frames = Vec::new();
+        alice.encryptor.encrypt(b"Hello, Bob", &mut frames);
+        bob.decryptor.feed(&frames).expect("bob: should decrypt");
+        let mut buf = [0u8; 100];
+        let n = bob.decryptor.read_plaintext(&mut buf);
+        assert_eq!(&buf[..n], b"Hello, Bob");
+    }
+
+    #[test]
+    fn sans_io_tampered_frame() {
+        let alice = Handshake::new(
+            &PrivateKey::from(Scalar::from(1u64)),
+            None,
+            StdRng::seed_from_u64(1),
+        );
+        let bob = Handshake::new(
+            &PrivateKey::from(Scalar::from(2u64)),
+            None,
+            StdRng::seed_from_u64(2),
+        );
+        let (alice, bob) = handshake_pair(alice, bob);
+        let mut alice = alice.unwrap();
+        let mut bob = bob.unwrap();
+
+        let mut frames = Vec::new();
+        alice.encryptor.encrypt(b"Hello, Bob", &mut frames);
+        let last = frames.len() - 1;
+        frames[last] ^= 1;
+        let stats = bob.decryptor.stats();
+        let received = stats.frames_received();
+        assert!(bob.decryptor.feed(&frames).is_err());
+        assert!(stats.is_corrupted());
+        assert_eq!(stats.frames_received(), received);
+
+        // Frame shorter than the authentication tag is rejected.
+        let mut bob_len_only = Decryptor::new(
+            0,
+            CipherSuite::AesPmacSiv,
+            Transcript::new(b"test"),
+            SessionStats::default(),
+        );
+        assert!(bob_len_only.feed(&[15, 0]).is_err());
+        assert_eq!(bob_len_only.stats().decrypt_failures(), 1);
+    }
+
+    /// In-memory writer that sends the written bytes to a channel.
+    struct ChannelWriter(mpsc::UnboundedSender<Result<Vec<u8>, io::Error>>);
+
+    impl futures::io::AsyncWrite for ChannelWriter {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<Result<usize, io::Error>> {
+            self.0
+                .unbounded_send(Ok(buf.to_vec()))
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+            self.0.close_channel();
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn futures_io_message() {
+        use futures::io::{AsyncReadExt, AsyncWriteExt};
+
+        let alice_private_key = PrivateKey::from(Scalar::from(1u64));
+        let bob_private_key = PrivateKey::from(Scalar::from(2u64));
+
+        let (alice_writer, bob_reader) = mpsc::unbounded();
+        let (bob_writer, alice_reader) = mpsc::unbounded();
+
+        let alice = async {
+            let (received_key, mut alice_out, _) = futures_io::cybershake(
+                &alice_private_key,
+                Box::pin(alice_reader.into_async_read()),
+                Box::pin(ChannelWriter(alice_writer)),
+                StdRng::seed_from_u64(1),
+            )
+            .await
+            .expect("alice: should handshake correctly");
+            assert_eq!(received_key, bob_private_key.to_public_key());
+
+            alice_out.write_all(&vec![10u8; 6000]).await.unwrap();
+            alice_out.close().await.unwrap();
+        };
+
+        let bob = async {
+            let (received_key, _, mut bob_inc) = futures_io::cybershake_with_expected_peer(
+                &bob_private_key,
+                Some(alice_private_key.to_public_key()),
+                Box::pin(bob_reader.into_async_read()),
+                Box::pin(ChannelWriter(bob_writer)),
+                StdRng::seed_from_u64(2),
+            )
+            .await
+            .expect("bob: should handshake correctly");
+            assert_eq!(received_key, alice_private_key.to_public_key());
+
+            let mut received = Vec::new();
+            let mut buf = vec![0u8; 4096];
+            loop {
+                let n = bob_inc
+                    .read(&mut buf)
+                    .await
+                    .expect("bob: should receive msg");
+                if n == 0 {
+                    break;
+                }
+                received.extend_from_slice(&buf[..n]);
+            }
+            assert_eq!(received, vec![10u8; 6000]);
+        };
+
+        futures::executor::block_on(futures::future::join(alice, bob));
+    }
+
+    #[cfg(feature = "tokio-runtime")]
     #[tokio::test]
     async fn light_message_poll_function() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
         let alice_private_key = PrivateKey::from(Scalar::from(1u8));
         let bob_private_key = PrivateKey::from(Scalar::from(2u8));
 
@@ -564,6 +2014,8 @@ mod tests {
             .unwrap();
 
             assert_eq!(received_key, bob_private_key.to_public_key());
+            assert_eq!(alice_out.version(), 0);
+            assert_eq!(alice_inc.version(), 0);
 
             // Alice send message to bob
             let alice_message: Vec<u8> = "Hello, Bob".bytes().collect();
@@ -607,8 +2059,61 @@ mod tests {
         assert!(bob.await.is_ok());
     }
 
+    #[cfg(feature = "tokio-runtime")]
+    #[tokio::test]
+    async fn pinned_peer_mismatch() {
+        let alice_private_key = PrivateKey::from(Scalar::from(1u64));
+        let bob_private_key = PrivateKey::from(Scalar::from(2u64));
+        let eve_public_key = PrivateKey::from(Scalar::from(3u64)).to_public_key();
+
+        let mut alice_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut bob_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let alice_addr = alice_listener.local_addr().unwrap();
+        let bob_addr = bob_listener.local_addr().unwrap();
+
+        let alice = tokio::spawn(async move {
+            let (alice_reader, _) = alice_listener.accept().await.unwrap();
+            let alice_writer = TcpStream::connect(bob_addr).await.unwrap();
+            let result = cybershake_with_expected_peer(
+                &alice_private_key,
+                Some(eve_public_key),
+                Box::pin(alice_reader),
+                Box::pin(alice_writer),
+                StdRng::from_entropy(),
+            )
+            .await;
+
+            match result {
+                Err(Error::PeerMismatch) => {}
+                _ => panic!("alice: should fail with PeerMismatch"),
+            }
+        });
+
+        let bob = tokio::spawn(async move {
+            let bob_writer = TcpStream::connect(alice_addr).await.unwrap();
+            let (bob_reader, _) = bob_listener.accept().await.unwrap();
+            let (received_key, _, _) = cybershake_with_expected_peer(
+                &bob_private_key,
+                Some(alice_private_key.to_public_key()),
+                Box::pin(bob_reader),
+                Box::pin(bob_writer),
+                StdRng::from_entropy(),
+            )
+            .await
+            .expect("bob: should handshake correctly");
+
+            assert_eq!(received_key, alice_private_key.to_public_key());
+        });
+
+        assert!(alice.await.is_ok());
+        assert!(bob.await.is_ok());
+    }
+
+    #[cfg(feature = "tokio-runtime")]
     #[tokio::test]
     async fn large_message() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
         let alice_private_key = PrivateKey::from(Scalar::from(1u64));
         let bob_private_key = PrivateKey::from(Scalar::from(2u64));
 
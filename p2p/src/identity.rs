@@ -0,0 +1,396 @@
+//! Persistent identity of the node.
+//!
+//! `NodeIdentity` owns the long-term cybershake private key of the node.
+//! The key is generated on first run and stored on disk, encrypted
+//! with a user-provided passphrase or with a random key kept in the OS keyring.
+//! Storing the key in the clear must be requested explicitly with `KeyProtection::Plaintext`.
+//!
+//! Key file format:
+//!
+//! ```ascii
+//! [version] [flags] [salt]    [tag]     [secret key]
+//!  1 byte    1 byte  16 bytes  16 bytes  32 bytes
+//! ```
+//!
+//! If the `flags` byte is 0, the key is stored in the clear and salt and tag are zero.
+//! If the `flags` byte is 1, the key is encrypted with AES-SIV-PMAC-128
+//! under a key derived from the passphrase and the salt with scrypt.
+//! If the `flags` byte is 2, the key is encrypted with AES-SIV-PMAC-128
+//! under a random key stored in the OS keyring, and the salt is zero.
+//!
+//! The key file is readable and writable only by its owner.
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use curve25519_dalek::scalar::Scalar;
+use miscreant::{generic_array::GenericArray, Aes128PmacSiv};
+use rand_core::{CryptoRng, RngCore};
+
+use crate::cybershake::{PrivateKey, PublicKey};
+#[cfg(feature = "tokio-runtime")]
+use crate::peer::PeerID;
+
+const KEYFILE_VERSION: u8 = 1;
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+const FLAG_PLAINTEXT: u8 = 0;
+const FLAG_ENCRYPTED: u8 = 1;
+const FLAG_KEYRING: u8 = 2;
+const SALT_LEN: usize = 16;
+const TAG_LEN: usize = 16;
+const KEYFILE_LEN: usize = 2 + SALT_LEN + TAG_LEN + 32;
+
+/// Protection of the identity key at rest.
+#[derive(Clone, Debug)]
+pub enum KeyProtection {
+    /// The key is encrypted under a key derived from the passphrase with scrypt.
+    Passphrase(String),
+    /// The key is encrypted under a random key stored in the OS keyring
+    /// as the password of a given service and user.
+    #[cfg(feature = "os-keyring")]
+    Keyring {
+        /// Name of the service in the keyring.
+        service: String,
+        /// Name of the user in the keyring.
+        user: String,
+    },
+    /// The key is stored in the clear and is protected only by the permissions of the file.
+    Plaintext,
+}
+
+/// Long-term identity of the node that can be stored on disk.
+#[derive(Clone)]
+pub struct NodeIdentity {
+    privkey: PrivateKey,
+}
+
+impl NodeIdentity {
+    /// Generates a new random identity.
+    pub fn random<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        NodeIdentity {
+            privkey: PrivateKey::from(Scalar::random(rng)),
+        }
+    }
+
+    /// Creates the identity from a given private key.
+    pub fn from_private_key(privkey: PrivateKey) -> Self {
+        NodeIdentity { privkey }
+    }
+
+    /// Loads the identity from a file, or generates a new one and saves it to the file
+    /// if the file does not exist yet.
+    /// A key stored in the clear is encrypted with the requested protection.
+    pub fn load_or_generate<R: RngCore + CryptoRng>(
+        path: impl AsRef<Path>,
+        protection: &KeyProtection,
+        rng: &mut R,
+    ) -> Result<Self, io::Error> {
+        let path = path.as_ref();
+        if !path.exists() {
+            let id = Self::random(rng);
+            id.save(path, protection, rng)?;
+            return Ok(id);
+        }
+        let bytes = fs::read(path)?;
+        match protection {
+            KeyProtection::Plaintext => Self::from_bytes(&bytes, protection),
+            _ if bytes.get(1) == Some(&FLAG_PLAINTEXT) => {
+                let id = Self::from_bytes(&bytes, &KeyProtection::Plaintext)?;
+                id.save(path, protection, rng)?;
+                Ok(id)
+            }
+            _ => Self::from_bytes(&bytes, protection),
+        }
+    }
+
+    /// Loads the identity from a file.
+    pub fn load(path: impl AsRef<Path>, protection: &KeyProtection) -> Result<Self, io::Error> {
+        Self::from_bytes(&fs::read(path)?, protection)
+    }
+
+    /// Saves the identity to a file, creating the parent directories if needed.
+    pub fn save<R: RngCore + CryptoRng>(
+        &self,
+        path: impl AsRef<Path>,
+        protection: &KeyProtection,
+        rng: &mut R,
+    ) -> Result<(), io::Error> {
+        let path = path.as_ref();
+        let bytes = self.to_bytes(protection, rng)?;
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder)?;
+        }
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(path)?;
+        // The mode above applies only to a new file: restrict an existing one
+        // before the key is written to it.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        }
+        file.write_all(&bytes)
+    }
+
+    /// Encodes the identity in the key file format.
+    /// With the `Keyring` protection, replaces the key stored in the OS keyring.
+    pub fn to_bytes<R: RngCore + CryptoRng>(
+        &self,
+        protection: &KeyProtection,
+        rng: &mut R,
+    ) -> Result<Vec<u8>, io::Error> {
+        let mut secret = [0u8; 32];
+        secret.copy_from_slice(self.privkey.as_secret_bytes());
+        let mut salt = [0u8; SALT_LEN];
+        let mut tag = [0u8; TAG_LEN];
+        let flag = match protection {
+            KeyProtection::Plaintext => FLAG_PLAINTEXT,
+            KeyProtection::Passphrase(passphrase) => {
+                rng.fill_bytes(&mut salt);
+                tag = encrypt(&passphrase_key(passphrase, &salt), &mut secret);
+                FLAG_ENCRYPTED
+            }
+            #[cfg(feature = "os-keyring")]
+            KeyProtection::Keyring { service, user } => {
+                let mut key = [0u8; 32];
+                rng.fill_bytes(&mut key);
+                keyring::Keyring::new(service, user)
+                    .set_password(&hex::encode(&key))
+                    .map_err(keyring_error)?;
+                tag = encrypt(&key, &mut secret);
+                FLAG_KEYRING
+            }
+        };
+        let mut buf = Vec::with_capacity(KEYFILE_LEN);
+        buf.push(KEYFILE_VERSION);
+        buf.push(flag);
+        buf.extend_from_slice(&salt);
+        buf.extend_from_slice(&tag);
+        buf.extend_from_slice(&secret);
+        Ok(buf)
+    }
+
+    /// Decodes the identity from the key file format.
+    /// Fails if the file is protected differently than requested,
+    /// or the passphrase or the key in the OS keyring is incorrect.
+    pub fn from_bytes(bytes: &[u8], protection: &KeyProtection) -> Result<Self, io::Error> {
+        if bytes.len() != KEYFILE_LEN || bytes[0] != KEYFILE_VERSION {
+            return Err(invalid_data("Unsupported identity key file format"));
+        }
+        let salt = &bytes[2..2 + SALT_LEN];
+        let tag = &bytes[2 + SALT_LEN..2 + SALT_LEN + TAG_LEN];
+        let mut secret = [0u8; 32];
+        secret.copy_from_slice(&bytes[2 + SALT_LEN + TAG_LEN..]);
+
+        match (bytes[1], protection) {
+            (FLAG_PLAINTEXT, KeyProtection::Plaintext) => {}
+            (FLAG_PLAINTEXT, _) => {
+                return Err(invalid_data(
+                    "Identity key is stored in the clear, plaintext storage must be requested",
+                ))
+            }
+            (FLAG_ENCRYPTED, KeyProtection::Passphrase(passphrase)) => {
+                decrypt(&passphrase_key(passphrase, salt), tag, &mut secret)
+                    .map_err(|_| invalid_data("Incorrect passphrase for the identity key"))?;
+            }
+            (FLAG_ENCRYPTED, _) => {
+                return Err(invalid_data(
+                    "Identity key is encrypted, passphrase required",
+                ))
+            }
+            #[cfg(feature = "os-keyring")]
+            (FLAG_KEYRING, KeyProtection::Keyring { service, user }) => {
+                let key = keyring::Keyring::new(service, user)
+                    .get_password()
+                    .map_err(keyring_error)?;
+                let key = hex::decode(&key)
+                    .ok()
+                    .filter(|key| key.len() == 32)
+                    .ok_or_else(|| {
+                        invalid_data("Invalid identity key encryption key in the OS keyring")
+                    })?;
+                let mut buf = [0u8; 32];
+                buf.copy_from_slice(&key);
+                decrypt(&buf, tag, &mut secret).map_err(|_| {
+                    invalid_data("Incorrect identity key encryption key in the OS keyring")
+                })?;
+            }
+            (FLAG_KEYRING, _) => {
+                return Err(invalid_data(
+                    "Identity key is encrypted with a key in the OS keyring",
+                ))
+            }
+            _ => return Err(invalid_data("Unsupported identity key file format")),
+        }
+
+        let scalar = Scalar::from_canonical_bytes(secret)
+            .ok_or_else(|| invalid_data("Identity key is not a canonical scalar"))?;
+        Ok(NodeIdentity {
+            privkey: PrivateKey::from(scalar),
+        })
+    }
+
+    /// Returns the private key for performing the cybershake.
+    pub fn private_key(&self) -> &PrivateKey {
+        &self.privkey
+    }
+
+    /// Returns the public key of the node.
+    pub fn public_key(&self) -> PublicKey {
+        self.privkey.to_public_key()
+    }
+
+    /// Returns the peer ID of the node.
+    #[cfg(feature = "tokio-runtime")]
+    pub fn peer_id(&self) -> PeerID {
+        PeerID::from(self.public_key())
+    }
+}
+
+/// Derives the symmetric key for encrypting the identity key from a passphrase.
+fn passphrase_key(passphrase: &str, salt: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    let params = scrypt::ScryptParams::new(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)
+        .expect("never fails because the parameters are valid");
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key)
+        .expect("never fails because the key length is valid");
+    key
+}
+
+/// Encrypts the secret key in place and returns the authentication tag.
+fn encrypt(key: &[u8; 32], secret: &mut [u8; 32]) -> [u8; TAG_LEN] {
+    let tag = Aes128PmacSiv::new(GenericArray::clone_from_slice(key))
+        .encrypt_in_place_detached(&[&[KEYFILE_VERSION]], secret)
+        .expect("never fails because we have just one header");
+    let mut buf = [0u8; TAG_LEN];
+    buf.copy_from_slice(tag.as_slice());
+    buf
+}
+
+/// Decrypts the secret key in place, checking the authentication tag.
+fn decrypt(key: &[u8; 32], tag: &[u8], secret: &mut [u8; 32]) -> Result<(), miscreant::Error> {
+    let tag = GenericArray::clone_from_slice(tag);
+    Aes128PmacSiv::new(GenericArray::clone_from_slice(key)).decrypt_in_place_detached(
+        &[&[KEYFILE_VERSION]],
+        secret,
+        &tag,
+    )
+}
+
+#[cfg(feature = "os-keyring")]
+fn keyring_error(e: keyring::KeyringError) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("OS keyring: {}", e))
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+
+    fn passphrase(p: &str) -> KeyProtection {
+        KeyProtection::Passphrase(p.to_string())
+    }
+
+    #[test]
+    fn plaintext_roundtrip() {
+        let id = NodeIdentity::random(&mut thread_rng());
+        let bytes = id
+            .to_bytes(&KeyProtection::Plaintext, &mut thread_rng())
+            .unwrap();
+        let restored = NodeIdentity::from_bytes(&bytes, &KeyProtection::Plaintext).unwrap();
+        assert_eq!(restored.public_key(), id.public_key());
+
+        // Plaintext storage must be requested explicitly.
+        assert!(NodeIdentity::from_bytes(&bytes, &passphrase("correct horse")).is_err());
+    }
+
+    #[test]
+    fn encrypted_roundtrip() {
+        let id = NodeIdentity::random(&mut thread_rng());
+        let bytes = id
+            .to_bytes(&passphrase("correct horse"), &mut thread_rng())
+            .unwrap();
+        assert_ne!(
+            &bytes[2 + SALT_LEN + TAG_LEN..],
+            id.private_key().as_secret_bytes()
+        );
+
+        let restored = NodeIdentity::from_bytes(&bytes, &passphrase("correct horse")).unwrap();
+        assert_eq!(restored.public_key(), id.public_key());
+
+        assert!(NodeIdentity::from_bytes(&bytes, &passphrase("battery staple")).is_err());
+        assert!(NodeIdentity::from_bytes(&bytes, &KeyProtection::Plaintext).is_err());
+    }
+
+    #[test]
+    fn keyring_file_requires_the_keyring() {
+        let id = NodeIdentity::random(&mut thread_rng());
+        let mut bytes = id
+            .to_bytes(&passphrase("correct horse"), &mut thread_rng())
+            .unwrap();
+        bytes[1] = FLAG_KEYRING;
+        assert!(NodeIdentity::from_bytes(&bytes, &passphrase("correct horse")).is_err());
+        assert!(NodeIdentity::from_bytes(&bytes, &KeyProtection::Plaintext).is_err());
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let id = NodeIdentity::random(&mut thread_rng());
+        let mut bytes = id
+            .to_bytes(&passphrase("correct horse"), &mut thread_rng())
+            .unwrap();
+        bytes[0] = 0;
+        assert!(NodeIdentity::from_bytes(&bytes, &passphrase("correct horse")).is_err());
+    }
+
+    #[test]
+    fn plaintext_key_file_is_encrypted_on_load() {
+        let path = std::env::temp_dir().join(format!("p2p-identity-plain-{}", std::process::id()));
+        let id = NodeIdentity::random(&mut thread_rng());
+        id.save(&path, &KeyProtection::Plaintext, &mut thread_rng())
+            .unwrap();
+
+        let loaded =
+            NodeIdentity::load_or_generate(&path, &passphrase("correct horse"), &mut thread_rng())
+                .unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.public_key(), id.public_key());
+        assert_eq!(bytes[1], FLAG_ENCRYPTED);
+        let restored = NodeIdentity::from_bytes(&bytes, &passphrase("correct horse")).unwrap();
+        assert_eq!(restored.public_key(), id.public_key());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn key_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("p2p-identity-{}", std::process::id()));
+        let id = NodeIdentity::random(&mut thread_rng());
+        id.save(&path, &KeyProtection::Plaintext, &mut thread_rng())
+            .unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // An existing file readable by others is restricted when the key is saved over it.
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        id.save(&path, &KeyProtection::Plaintext, &mut thread_rng())
+            .unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
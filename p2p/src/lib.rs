@@ -2,14 +2,26 @@ extern crate curve25519_dalek;
 extern crate futures;
 extern crate merlin;
 extern crate rand_core;
+#[cfg(feature = "tokio-runtime")]
 extern crate tokio;
 
+#[cfg(feature = "tokio-runtime")]
 mod codec;
 pub mod cybershake;
+mod identity;
+#[cfg(feature = "tokio-runtime")]
 mod node;
+#[cfg(feature = "tokio-runtime")]
 mod peer;
 mod priority;
+#[cfg(feature = "tokio-runtime")]
+pub mod proxy;
 
+pub use self::identity::{KeyProtection, NodeIdentity};
+#[cfg(feature = "tokio-runtime")]
 pub use self::node::{Direction, Node, NodeConfig, NodeHandle, NodeNotification, PeerInfo};
-pub use self::peer::{PeerID, PeerLink, PeerMessage, PeerNotification};
+#[cfg(feature = "tokio-runtime")]
+pub use self::peer::{PeerID, PeerLink, PeerMessage, PeerNotification, FEATURE_PING};
 pub use self::priority::Priority;
+#[cfg(feature = "tokio-runtime")]
+pub use self::proxy::{Dialer, OnionService, ProxyConfig, TargetAddr};
@@ -1,8 +1,21 @@
 //! Node manages its own state and the state of its peers, and orchestrates messages between them.
+//!
+//! The handshake authenticates the identity key of the remote node, which serves as the nonce
+//! for detecting the connections to ourselves (or to another node running with our key).
+//! If two nodes end up with several connections to each other (e.g. dialing each other simultaneously),
+//! both keep the connection dialed by the node with the lower peer ID and close the others.
+//! Outbound connections are opened directly or through a SOCKS5 proxy (see [`crate::proxy`]).
+//! A node dialing through the proxy does not announce its listening port to the peers.
+//! Nodes send the hello on the accepted connections too, which legacy nodes never do:
+//! the dialer that receives it replies with its features, and the acceptor replies with its own,
+//! so the features are never sent to the legacy nodes that would drop the connection on them.
+//! A peer whose session failed to decrypt a frame is redialed immediately after it disconnects.
+use core::mem;
 use core::time::Duration;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Instant;
 
 use futures::future::FutureExt;
 use futures::select;
@@ -14,12 +27,15 @@ use tokio::sync;
 use tokio::task;
 use tokio::time;
 
-use rand::thread_rng;
+use rand::{thread_rng, Rng};
 
 use crate::codec::{MessageDecoder, MessageEncoder};
 use crate::cybershake;
-use crate::peer::{PeerAddr, PeerID, PeerLink, PeerMessage, PeerNotification};
+use crate::peer::{
+    PeerAddr, PeerID, PeerLink, PeerMessage, PeerNotification, FEATURE_PING, SUPPORTED_FEATURES,
+};
 use crate::priority::{Priority, PriorityTable, HIGH_PRIORITY, LOW_PRIORITY};
+use crate::proxy::{Dialer, ProxyConfig, TargetAddr};
 use readerwriter::Codable;
 
 type Reply<T> = sync::oneshot::Sender<T>;
@@ -31,6 +47,7 @@ type Reply<T> = sync::oneshot::Sender<T>;
 pub struct NodeHandle<Custom: Codable> {
     peer_id: PeerID,
     socket_address: SocketAddr,
+    dialer: Dialer,
     channel: sync::mpsc::Sender<NodeMessage<Custom>>,
 }
 
@@ -40,6 +57,13 @@ pub struct NodeConfig {
     pub inbound_limit: usize,
     pub outbound_limit: usize,
     pub heartbeat_interval_sec: u64,
+    /// Interval between the pings sent to each peer that advertised `FEATURE_PING`.
+    /// Must be positive: `Node::spawn` fails otherwise.
+    pub ping_interval_sec: u64,
+    /// Peers that do not respond to a ping within this window are disconnected.
+    pub ping_timeout_sec: u64,
+    /// SOCKS5 proxy for the outbound connections (e.g. Tor), if any.
+    pub proxy: Option<ProxyConfig>,
 }
 
 pub struct Node<Custom: Codable> {
@@ -47,7 +71,9 @@ pub struct Node<Custom: Codable> {
     cybershake_identity: cybershake::PrivateKey,
     peer_notification_channel: sync::mpsc::Sender<PeerNotification<Custom>>,
     peers: HashMap<PeerID, PeerState<Custom>>,
+    banned: HashSet<PeerID>, // identities refused regardless of their addresses
     config: NodeConfig,
+    dialer: Dialer,
     inbound_semaphore: sync::Semaphore,
     peer_priorities: PriorityTable<PeerID>, // priorities of peers
     notifications_channel: sync::mpsc::Sender<NodeNotification<Custom>>,
@@ -64,10 +90,14 @@ pub enum Direction {
 struct PeerState<T: Codable> {
     link: PeerLink<T>,
     listening_addr: Option<SocketAddr>,
-    socket_addr: SocketAddr,
+    socket_addr: TargetAddr, // dialed address of the outbound peer, or the remote address of the socket
     direction: Direction,
     duplicates: usize,
-    peer_addrs: Vec<PeerAddr>, // addresses of all the peers
+    peer_addrs: Vec<PeerAddr>,            // addresses of all the peers
+    features: u64,                        // features advertised by the peer
+    features_sent: bool,                  // whether we told the peer our features
+    pending_ping: Option<(u64, Instant)>, // nonce and time of the ping awaiting a pong
+    latency: Option<Duration>,            // smoothed round-trip time
 }
 
 #[derive(Debug)]
@@ -84,16 +114,24 @@ pub enum NodeNotification<Custom: Codable> {
 #[derive(Debug)]
 pub struct PeerInfo {
     pub id: PeerID,
-    pub address: SocketAddr,
+    /// Listening address of the peer, if known, or the address of the connection.
+    /// A peer dialed by a host name through the proxy is shown with its host name.
+    pub address: TargetAddr,
     pub public: bool,
     pub priority: Priority,
     pub direction: Direction,
+    /// Smoothed round-trip time, if the peer has responded to a ping.
+    pub latency: Option<Duration>,
+    /// Counters of the encrypted session with the peer.
+    pub stats: cybershake::SessionStats,
 }
 
 /// Internal representation of messages sent by `NodeHandle` to `Node`.
 enum NodeMessage<Custom: Codable> {
-    ConnectPeer(net::TcpStream, Option<PeerID>),
+    ConnectPeer(net::TcpStream, Option<PeerID>, TargetAddr),
     RemovePeer(PeerID),
+    BanPeer(PeerID),
+    SendToPeer(PeerID, Custom),
     Broadcast(Custom),
     CountPeers(Reply<usize>),
     ListPeers(Reply<Vec<PeerInfo>>),
@@ -115,6 +153,13 @@ where
         ),
         io::Error,
     > {
+        if config.ping_interval_sec == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Ping interval must be positive.",
+            ));
+        }
+
         // Prepare listening socket.
         let listener = net::TcpListener::bind(config.listen_addr).await?;
         let mut local_addr = listener.local_addr()?;
@@ -128,12 +173,15 @@ where
         let (peer_sender, mut peer_receiver) = sync::mpsc::channel::<PeerNotification<Custom>>(100);
         let (notif_sender, notif_receiver) = sync::mpsc::channel::<NodeNotification<Custom>>(100);
 
+        let dialer = Dialer::new(config.proxy.clone());
         let mut node = Node {
             cybershake_identity,
             peer_notification_channel: peer_sender,
             peers: HashMap::new(),
+            banned: HashSet::new(),
             listener,
             config,
+            dialer: dialer.clone(),
             inbound_semaphore,
             peer_priorities: PriorityTable::new(1000),
             notifications_channel: notif_sender,
@@ -143,11 +191,13 @@ where
             peer_id: node.peer_id(),
             channel: cmd_sender,
             socket_address: local_addr,
+            dialer,
         };
 
         task::spawn_local(async move {
             let mut heartbeat =
                 time::interval(Duration::from_secs(node.config.heartbeat_interval_sec));
+            let mut ping = time::interval(Duration::from_secs(node.config.ping_interval_sec));
             loop {
                 select! {
                     maybe_cmd = cmd_receiver.next().fuse() => {
@@ -169,6 +219,9 @@ where
                     _ = heartbeat.tick().fuse() => {
                         node.heartbeat_tick().await
                     },
+                    _ = ping.tick().fuse() => {
+                        node.ping_tick().await
+                    },
                     _ = node.try_accept().fuse() => {}
                 }
             }
@@ -185,14 +238,35 @@ impl<Custom: Codable> NodeHandle<Custom> {
     /// If connection is established, returns Ok(), but can fail later to perform handshake -
     /// in which case you will receive a `NodeNotification::OutboundConnectionFailure` notification.
     ///
+    /// Host names are resolved locally, use `connect_to_target` to let the proxy resolve them.
+    ///
     /// TODO: maybe pass `Box<dyn ToSocketAddrs>` to the node, so all errors are handled in one place?
     pub async fn connect_to_peer(
         &mut self,
         addr: impl net::ToSocketAddrs,
         expected_pid: Option<PeerID>,
     ) -> Result<(), io::Error> {
-        let stream = net::TcpStream::connect(&addr).await?;
-        self.send_internal(NodeMessage::ConnectPeer(stream, expected_pid))
+        let addr = net::lookup_host(addr).await?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::Other, "Address could not be resolved.")
+        })?;
+        self.connect_to_target(TargetAddr::Ip(addr), expected_pid)
+            .await
+    }
+
+    /// Attempts to open a connection to a peer at a given address or host name,
+    /// through the proxy if one is configured. Host names (e.g. `.onion` addresses)
+    /// are resolved by the proxy.
+    pub async fn connect_to_target(
+        &mut self,
+        target: TargetAddr,
+        expected_pid: Option<PeerID>,
+    ) -> Result<(), io::Error> {
+        let isolation_key = expected_pid.map(|pid| pid.to_string());
+        let stream = self
+            .dialer
+            .connect(&target, isolation_key.as_deref())
+            .await?;
+        self.send_internal(NodeMessage::ConnectPeer(stream, expected_pid, target))
             .await;
         Ok(())
     }
@@ -202,6 +276,19 @@ impl<Custom: Codable> NodeHandle<Custom> {
         self.send_internal(NodeMessage::RemovePeer(peer_id)).await
     }
 
+    /// Disconnects from a peer with a given ID and refuses its connections from now on,
+    /// whichever address it connects from.
+    pub async fn ban_peer(&mut self, peer_id: PeerID) {
+        self.send_internal(NodeMessage::BanPeer(peer_id)).await
+    }
+
+    /// Sends a message to a peer with a given ID over its current connection.
+    /// The message is dropped if the peer is not connected.
+    pub async fn send_to_peer(&mut self, peer_id: PeerID, msg: Custom) {
+        self.send_internal(NodeMessage::SendToPeer(peer_id, msg))
+            .await
+    }
+
     /// Returns the PeerID of the node.
     pub fn id(&self) -> PeerID {
         self.peer_id
@@ -248,11 +335,15 @@ where
     /// Handles the command and returns false if it needs to shutdown.
     async fn handle_command(&mut self, msg: NodeMessage<Custom>) {
         match msg {
-            NodeMessage::ConnectPeer(stream, expected_pid) => {
-                self.connect_peer_or_notify(stream, expected_pid, HIGH_PRIORITY)
+            NodeMessage::ConnectPeer(stream, expected_pid, addr) => {
+                self.connect_peer_or_notify(stream, expected_pid, addr, HIGH_PRIORITY)
                     .await
             }
             NodeMessage::RemovePeer(peer_id) => self.remove_peer(&peer_id).await,
+            NodeMessage::BanPeer(peer_id) => self.ban_peer(peer_id).await,
+            NodeMessage::SendToPeer(peer_id, msg) => {
+                self.send_to_peer(&peer_id, PeerMessage::Data(msg)).await
+            }
             NodeMessage::Broadcast(msg) => self.broadcast(msg).await,
             NodeMessage::CountPeers(reply) => self.count_peers(reply).await,
             NodeMessage::ListPeers(reply) => self.list_peers(reply).await,
@@ -275,6 +366,36 @@ where
         }
     }
 
+    /// Pings the peers and disconnects the ones that did not respond to the previous ping in time.
+    async fn ping_tick(&mut self) {
+        let timeout = Duration::from_secs(self.config.ping_timeout_sec);
+        let dead_peers = self
+            .peers
+            .iter()
+            .filter(|(_pid, peer)| match peer.pending_ping {
+                Some((_nonce, sent_at)) => sent_at.elapsed() > timeout,
+                None => false,
+            })
+            .map(|(pid, _peer)| *pid)
+            .collect::<Vec<_>>();
+        for pid in dead_peers.iter() {
+            // All connections to the peer are dead, so we drop it regardless of duplicates.
+            if let Some(peer) = self.peers.get_mut(pid) {
+                peer.duplicates = 0;
+            }
+            self.remove_peer(pid).await;
+        }
+
+        // Legacy peers that did not advertise the pings would drop the connection on them.
+        for (_pid, peer) in self.peers.iter_mut() {
+            if peer.features & FEATURE_PING != 0 && peer.pending_ping.is_none() {
+                let nonce = thread_rng().gen::<u64>();
+                peer.pending_ping = Some((nonce, Instant::now()));
+                peer.link.send(PeerMessage::Ping(nonce)).await;
+            }
+        }
+    }
+
     async fn try_accept(&mut self) {
         let result = async {
             let permit = self.inbound_semaphore.acquire().await;
@@ -297,10 +418,14 @@ where
             // removing the peer, then we'll add a new permit to the semaphore.
             permit.forget();
 
-            self.register_peer(peer_link, addr, Direction::Inbound, LOW_PRIORITY)
-                .await;
-
-            Ok(())
+            self.register_peer(
+                peer_link,
+                addr.into(),
+                None,
+                Direction::Inbound,
+                LOW_PRIORITY,
+            )
+            .await
         }
         .await;
         self.notify_on_error(result, |e| NodeNotification::InboundConnectionFailure(e))
@@ -311,20 +436,32 @@ where
         &mut self,
         stream: net::TcpStream,
         expected_pid: Option<PeerID>,
+        target: TargetAddr,
         min_priority: Priority,
     ) {
-        let result = self.connect_peer(stream, expected_pid, min_priority).await;
+        let result = self
+            .connect_peer(stream, expected_pid, target, min_priority)
+            .await;
         self.notify_on_error(result, |e| NodeNotification::OutboundConnectionFailure(e))
             .await;
     }
 
+    /// Connects the peer over the outbound stream opened to a given target.
+    /// The listening address of the peer is unknown if it was dialed by a host name.
     async fn connect_peer(
         &mut self,
         stream: net::TcpStream,
         expected_pid: Option<PeerID>,
+        target: TargetAddr,
         min_priority: Priority,
     ) -> Result<(), io::Error> {
-        let addr = stream.peer_addr()?;
+        // Behind the proxy, the socket is connected to the proxy itself,
+        // so the peer dialed by a host name is known by that name.
+        let (addr, listening_addr) = match target {
+            TargetAddr::Ip(addr) => (target, Some(addr)),
+            TargetAddr::Domain(..) if self.dialer.proxy().is_some() => (target, None),
+            TargetAddr::Domain(..) => (stream.peer_addr()?.into(), None),
+        };
 
         let peer_link = PeerLink::spawn(
             &self.cybershake_identity,
@@ -337,10 +474,14 @@ where
         )
         .await?;
 
-        self.register_peer(peer_link, addr, Direction::Outbound, min_priority)
-            .await;
-
-        Ok(())
+        self.register_peer(
+            peer_link,
+            addr,
+            listening_addr,
+            Direction::Outbound,
+            min_priority,
+        )
+        .await
     }
 
     async fn connect_to_peer_addr(&mut self, peer_addr: &PeerAddr) -> Result<(), io::Error> {
@@ -352,69 +493,121 @@ where
             ));
         }
         // TODO: add short timeout to avoid hanging for too long waiting to be accepted.
-        let stream = net::TcpStream::connect(&peer_addr.addr).await?;
+        let stream = self
+            .dialer
+            .connect(
+                &TargetAddr::Ip(peer_addr.addr),
+                Some(&peer_addr.id.to_string()),
+            )
+            .await?;
 
         // We are connecting to some discovered address, so minimum priority is zero,
         // so we don't bump up whatever known priority is there.
-        self.connect_peer(stream, Some(peer_addr.id), LOW_PRIORITY)
-            .await
+        self.connect_peer(
+            stream,
+            Some(peer_addr.id),
+            TargetAddr::Ip(peer_addr.addr),
+            LOW_PRIORITY,
+        )
+        .await
     }
 
     async fn register_peer(
         &mut self,
         peer_link: PeerLink<Custom>,
-        addr: SocketAddr,
+        addr: TargetAddr,
+        listening_addr: Option<SocketAddr>,
         direction: Direction,
         min_priority: Priority,
-    ) {
+    ) -> Result<(), io::Error> {
         let id = *peer_link.id();
+        let self_id = self.peer_id();
+
+        if id == self_id {
+            // Dropping the link closes the connection.
+            if direction == Direction::Inbound {
+                self.inbound_semaphore.add_permits(1);
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("Connected to self ({}).", id),
+            ));
+        }
+
+        if self.banned.contains(&id) {
+            if direction == Direction::Inbound {
+                self.inbound_semaphore.add_permits(1);
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("Peer {} is banned.", id),
+            ));
+        }
 
         self.peer_priorities.insert(id, min_priority);
 
-        if let Some(mut existing_peer) = self.peers.get_mut(&id) {
+        if let Some(existing_peer) = self.peers.get_mut(&id) {
             // mark the existing peer as having duplicates,
-            // so when the current peer is dropped, we don't remove it.
+            // so when the closed connection reports disconnection, we don't remove the peer.
             existing_peer.duplicates += 1;
 
-            // if the duplicate connection is outbound, upgrade the status of the existing one.
-            if direction == Direction::Outbound && existing_peer.direction == Direction::Inbound {
-                existing_peer.direction = Direction::Outbound;
-                existing_peer.listening_addr = Some(addr);
-                // restore the semaphore since we have just overriden the direction.
+            // keep the connection dialed by the node with the lower ID, so both sides close the same one.
+            let replace = direction != existing_peer.direction
+                && direction == preferred_direction(&self_id, &id);
+            let closed_direction = if replace {
+                // dropping the old link closes its connection.
+                let _ = mem::replace(&mut existing_peer.link, peer_link);
+                let old_direction = existing_peer.direction;
+                existing_peer.direction = direction;
+                existing_peer.socket_addr = addr;
+                if listening_addr.is_some() {
+                    existing_peer.listening_addr = listening_addr;
+                }
+                existing_peer.pending_ping = None;
+                old_direction
+            } else {
+                direction
+            };
+
+            // restore the permit consumed by the closed inbound connection.
+            if closed_direction == Direction::Inbound {
                 self.inbound_semaphore.add_permits(1);
             }
-
-            return;
+            if replace {
+                if let Some(hello) = self.hello(direction) {
+                    self.send_to_peer(&id, hello).await
+                }
+            }
+            return Ok(());
         }
 
         let peer = PeerState {
             link: peer_link,
-            listening_addr: match &direction {
-                &Direction::Inbound => None,
-                &Direction::Outbound => Some(addr),
-            },
+            listening_addr,
             socket_addr: addr,
             direction,
             duplicates: 0,
             peer_addrs: Vec::new(),
+            features: 0,
+            features_sent: false,
+            pending_ping: None,
+            latency: None,
         };
         // The peer did not exist - simply add it.
         let _ = self.peers.insert(id, peer);
 
         self.notify(NodeNotification::PeerAdded(id)).await;
 
-        // If this is an outbound connection, tell our port.
-        if direction == Direction::Outbound {
-            self.send_to_peer(
-                &id,
-                PeerMessage::Hello(self.listener.local_addr().unwrap().port()),
-            )
-            .await
+        // Tell our port (unless we dialed through the proxy).
+        if let Some(hello) = self.hello(direction) {
+            self.send_to_peer(&id, hello).await
         }
 
         // Then, tell about our surrounding peers.
         self.send_to_peer(&id, PeerMessage::Peers(self.sorted_peers()))
-            .await
+            .await;
+
+        Ok(())
     }
 
     async fn remove_peer(&mut self, peer_id: &PeerID) {
@@ -438,6 +631,15 @@ where
         self.connect_to_more_peers_if_needed().await;
     }
 
+    async fn ban_peer(&mut self, peer_id: PeerID) {
+        self.banned.insert(peer_id);
+        // Drop all connections to the peer, including the duplicates.
+        if let Some(peer) = self.peers.get_mut(&peer_id) {
+            peer.duplicates = 0;
+        }
+        self.remove_peer(&peer_id).await;
+    }
+
     fn count_peers_with_direction(&self, direction: Direction) -> usize {
         self.peers
             .iter()
@@ -459,6 +661,29 @@ where
         reply.send(self.peer_infos()).unwrap_or(())
     }
 
+    /// Returns the hello for a new connection: the dialer tells its listening port unless it hides
+    /// behind the proxy, and the acceptor tells it too, showing that it is not a legacy node.
+    fn hello(&self, direction: Direction) -> Option<PeerMessage<Custom>> {
+        if direction == Direction::Outbound && self.dialer.proxy().is_some() {
+            return None;
+        }
+        Some(PeerMessage::Hello(
+            self.listener.local_addr().unwrap().port(),
+        ))
+    }
+
+    /// Tells the peer our features, once per peer.
+    async fn send_features(&mut self, pid: &PeerID) {
+        if let Some(peer) = self.peers.get_mut(pid) {
+            if !peer.features_sent {
+                peer.features_sent = true;
+                peer.link
+                    .send(PeerMessage::Features(SUPPORTED_FEATURES))
+                    .await;
+            }
+        }
+    }
+
     async fn send_to_peer(&mut self, pid: &PeerID, msg: PeerMessage<Custom>) {
         if let Some(peer) = self.peers.get_mut(&pid) {
             peer.link.send(msg).await;
@@ -469,19 +694,50 @@ where
         let (id, peermsg) = match notif {
             PeerNotification::Received(id, peermsg) => (id, peermsg),
             PeerNotification::Disconnected(id) => {
+                // The session failed to decrypt a frame: redial the peer right away
+                // instead of waiting for the next round of connections.
+                let redial = self
+                    .peers
+                    .get(&id)
+                    .filter(|peer| peer.duplicates == 0 && peer.link.stats().is_corrupted())
+                    .and_then(|peer| peer.listening_addr)
+                    .map(|addr| PeerAddr { id, addr });
                 self.remove_peer(&id).await;
+                if let Some(peer_addr) = redial {
+                    if !self.peers.contains_key(&peer_addr.id) {
+                        let result = self.connect_to_peer_addr(&peer_addr).await;
+                        self.notify_on_error(result, |e| {
+                            NodeNotification::OutboundConnectionFailure(e)
+                        })
+                        .await;
+                    }
+                }
                 return;
             }
         };
 
         match peermsg {
             PeerMessage::Hello(port) => {
+                let mut upgraded = false;
                 if let Some(peer) = self.peers.get_mut(&id) {
-                    let mut addr = peer.socket_addr;
-                    addr.set_port(port);
-                    peer.listening_addr = Some(addr);
+                    // The IP address of a peer dialed by a host name through the proxy is unknown.
+                    if let TargetAddr::Ip(mut addr) = peer.socket_addr {
+                        addr.set_port(port);
+                        peer.listening_addr = Some(addr);
+                    }
+                    // Legacy nodes do not send the hello on the connections they accepted.
+                    upgraded = peer.direction == Direction::Outbound;
+                }
+                if upgraded {
+                    self.send_features(&id).await
                 }
             }
+            PeerMessage::Features(features) => {
+                if let Some(peer) = self.peers.get_mut(&id) {
+                    peer.features = features;
+                }
+                self.send_features(&id).await
+            }
             PeerMessage::Data(msg) => {
                 self.notify(NodeNotification::MessageReceived(id, msg))
                     .await
@@ -496,6 +752,18 @@ where
 
                 self.connect_to_more_peers_if_needed().await;
             }
+            PeerMessage::Ping(nonce) => {
+                // The peer that pings supports the pings, even if it did not send us its features.
+                if let Some(peer) = self.peers.get_mut(&id) {
+                    peer.features |= FEATURE_PING;
+                }
+                self.send_to_peer(&id, PeerMessage::Pong(nonce)).await
+            }
+            PeerMessage::Pong(nonce) => {
+                if let Some(peer) = self.peers.get_mut(&id) {
+                    peer.receive_pong(nonce);
+                }
+            }
         }
     }
 
@@ -515,8 +783,10 @@ where
                     .peer_addrs
                     .iter()
                     .filter(|peer_addr| {
-                        // ignore all addresses to which we are already connected.
-                        self.peers.get(&peer_addr.id).is_none() && peer_addr.id != self_pid
+                        // ignore all addresses to which we are already connected, and the banned peers.
+                        self.peers.get(&peer_addr.id).is_none()
+                            && peer_addr.id != self_pid
+                            && !self.banned.contains(&peer_addr.id)
                     })
                     .map(|peer_addr| {
                         let priority = self
@@ -633,10 +903,15 @@ where
             .iter()
             .map(|(pid, peerstate)| PeerInfo {
                 id: *pid,
-                address: peerstate.listening_addr.unwrap_or(peerstate.socket_addr),
+                address: peerstate
+                    .listening_addr
+                    .map(TargetAddr::Ip)
+                    .unwrap_or_else(|| peerstate.socket_addr.clone()),
                 public: peerstate.listening_addr.is_some(),
                 direction: peerstate.direction,
                 priority: self.peer_priorities.get(pid).unwrap_or(LOW_PRIORITY),
+                latency: peerstate.latency,
+                stats: peerstate.link.stats().clone(),
             })
             .collect::<Vec<_>>()
     }
@@ -651,11 +926,40 @@ where
     }
 }
 
+/// Returns the direction of the connection to keep among the duplicate connections to the remote node:
+/// the one dialed by the node with the lower peer ID.
+fn preferred_direction(local: &PeerID, remote: &PeerID) -> Direction {
+    if local.0.as_bytes() < remote.0.as_bytes() {
+        Direction::Outbound
+    } else {
+        Direction::Inbound
+    }
+}
+
+impl<T: Codable> PeerState<T> {
+    /// Updates the latency estimate if the pong matches the pending ping.
+    /// Unsolicited or stale pongs are ignored.
+    fn receive_pong(&mut self, nonce: u64) {
+        match self.pending_ping {
+            Some((expected_nonce, sent_at)) if expected_nonce == nonce => {
+                let rtt = sent_at.elapsed();
+                // Exponential moving average with the weight of 1/8 for the new sample.
+                self.latency = Some(match self.latency {
+                    Some(latency) => (latency * 7 + rtt) / 8,
+                    None => rtt,
+                });
+                self.pending_ping = None;
+            }
+            _ => {}
+        }
+    }
+}
+
 impl fmt::Display for PeerInfo {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "{} {} {}   priority: {}   public: {}",
+            "{} {} {}   priority: {}   public: {}   latency: {}   frames: {} sent, {} received",
             match self.direction {
                 Direction::Inbound => " [in]",
                 Direction::Outbound => "[out]",
@@ -663,7 +967,30 @@ impl fmt::Display for PeerInfo {
             self.address,
             self.id,
             self.priority,
-            self.public
+            self.public,
+            self.latency
+                .map(|l| format!("{} ms", l.as_millis()))
+                .unwrap_or_else(|| "n/a".to_string()),
+            self.stats.frames_sent(),
+            self.stats.frames_received()
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use curve25519_dalek::scalar::Scalar;
+
+    #[test]
+    fn duplicate_connections_resolve_to_the_same_one() {
+        let a = PeerID::from(cybershake::PrivateKey::from(Scalar::from(1u64)).to_public_key());
+        let b = PeerID::from(cybershake::PrivateKey::from(Scalar::from(2u64)).to_public_key());
+
+        // The connection a→b is outbound for a and inbound for b, so both must keep it or both close it.
+        let kept_by_a = preferred_direction(&a, &b) == Direction::Outbound;
+        let kept_by_b = preferred_direction(&b, &a) == Direction::Inbound;
+        assert_eq!(kept_by_a, kept_by_b);
+        assert_ne!(preferred_direction(&a, &b), preferred_direction(&b, &a));
+    }
+}
@@ -1,6 +1,7 @@
 //! APIs for communicating with peers.
 //! - Use PeerLink::spawn() to establish a fully authenticated connection over a given socket stream.
-//! - Use PeerID to identify the peer.
+//! - Use PeerID to identify the peer. It is the authenticated identity key of the peer,
+//!   so it stays the same when the peer reconnects from a different address.
 use core::fmt;
 use futures::stream::StreamExt;
 use std::hash::{Hash, Hasher};
@@ -29,21 +30,37 @@ pub struct PeerAddr {
     pub addr: SocketAddr,
 }
 
+/// Feature bit indicating that the peer responds to the `Ping` messages.
+pub const FEATURE_PING: u64 = 1 << 0;
+
+/// Features supported by this implementation.
+pub(crate) const SUPPORTED_FEATURES: u64 = FEATURE_PING;
+
 /// Various kinds of messages that peers can send and receive between each other.
 #[derive(Clone, Debug, PartialEq)]
 pub enum PeerMessage<T: Codable> {
     // Upon connection, a peer tells its listening port for dialing in, if it's available.
+    // Legacy peers send it only on the connections they dialed,
+    // so the hello on an accepted connection tells that the peer is upgraded.
     Hello(u16),
     // A list of known peers.
     Peers(Vec<PeerAddr>),
     // An underlying message.
     Data(T),
+    // Liveness check with a random nonce.
+    Ping(u64),
+    // Response to the ping with the same nonce.
+    Pong(u64),
+    // Bitmask of the features the peer supports.
+    // Legacy peers reject it, so it is sent only to the peers known to be upgraded.
+    Features(u64),
 }
 
 /// Interface for communication with the peer.
 pub struct PeerLink<Custom: Codable> {
     peer_id: PeerID,
     channel: sync::mpsc::Sender<PeerMessage<Custom>>,
+    stats: cybershake::SessionStats,
 }
 
 /// Notifications that we receive from the peer.
@@ -61,6 +78,11 @@ impl<Custom: Codable + 'static> PeerLink<Custom> {
         &self.peer_id
     }
 
+    /// Returns the counters of the encrypted session with the peer.
+    pub fn stats(&self) -> &cybershake::SessionStats {
+        &self.stats
+    }
+
     /// Sends a message to the peer.
     pub async fn send(&mut self, msg: PeerMessage<Custom>) -> () {
         // We intentionally ignore the error because it's only returned if the recipient has disconnected,
@@ -93,24 +115,22 @@ impl<Custom: Codable + 'static> PeerLink<Custom> {
         let r = Box::pin(io::BufReader::new(r));
         let w = Box::pin(io::BufWriter::new(w));
 
-        let (id_pubkey, outgoing, incoming) =
-            cybershake::cybershake(host_identity, r, w, rng).await?;
+        let (id_pubkey, outgoing, incoming) = cybershake::cybershake_with_expected_peer(
+            host_identity,
+            expected_peer_id.map(|pid| pid.0),
+            r,
+            w,
+            rng,
+        )
+        .await?;
 
+        let stats = outgoing.stats();
         let mut outgoing = FramedWrite::new(outgoing, encoder);
         let incoming = FramedRead::new(incoming, decoder);
 
         let id = PeerID(id_pubkey);
         let retid = id.clone();
 
-        if let Some(expected_pid) = expected_peer_id {
-            if id != expected_pid {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    "Remote and expected id do not match.",
-                ));
-            }
-        }
-
         let (cmd_sender, cmd_receiver) = sync::mpsc::channel::<PeerMessage<Custom>>(100);
 
         enum PeerEvent<Custom: Codable> {
@@ -161,6 +181,7 @@ impl<Custom: Codable + 'static> PeerLink<Custom> {
         Ok(Self {
             peer_id: retid,
             channel: cmd_sender,
+            stats,
         })
     }
 }
@@ -198,6 +219,13 @@ impl fmt::Display for PeerID {
     }
 }
 
+/// Bytes of the identity key, used by the protocols to derive per-peer salts.
+impl AsRef<[u8]> for PeerID {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
 impl Hash for PeerID {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.0.as_bytes().hash(state);
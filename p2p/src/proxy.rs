@@ -0,0 +1,393 @@
+//! Outbound connections through a SOCKS5 proxy, such as Tor.
+//!
+//! The dialer connects to the peers directly or with the SOCKS5 `CONNECT` command (RFC 1928).
+//! Host names (e.g. `.onion` addresses) are resolved by the proxy, not locally.
+//! With stream isolation, each peer is dialed with distinct username/password credentials (RFC 1929),
+//! so Tor (with its default `IsolateSOCKSAuth` option) routes the connections to different peers
+//! through different circuits, and they cannot be linked to each other by the exit relays.
+//!
+//! [`OnionService`] is the integration point with the Tor controller: it publishes
+//! the node's listening address as an onion service, so the node accepts connections
+//! without revealing its IP address.
+use std::fmt;
+use std::net::SocketAddr;
+
+use tokio::io::{self, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+const SOCKS_VERSION: u8 = 5;
+const AUTH_NONE: u8 = 0;
+const AUTH_PASSWORD: u8 = 2;
+const CMD_CONNECT: u8 = 1;
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+/// SOCKS5 proxy for the outbound connections.
+#[derive(Clone, Debug)]
+pub struct ProxyConfig {
+    /// Address of the proxy, e.g. `127.0.0.1:9050` for Tor.
+    pub addr: SocketAddr,
+    /// Dial each peer with distinct credentials, so the proxy isolates their streams.
+    pub isolate_streams: bool,
+}
+
+/// Address of the peer to connect to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TargetAddr {
+    /// IP address and port.
+    Ip(SocketAddr),
+    /// Host name and port. Resolved by the proxy, if one is used.
+    Domain(String, u16),
+}
+
+/// Opens outbound connections to the peers, directly or through the proxy.
+#[derive(Clone, Debug, Default)]
+pub struct Dialer {
+    proxy: Option<ProxyConfig>,
+}
+
+/// Onion service published via the Tor control port.
+/// The service is removed by Tor when this object is dropped.
+pub struct OnionService {
+    hostname: String,
+    _control: BufReader<TcpStream>,
+}
+
+impl TargetAddr {
+    /// Returns the port of the target.
+    pub fn port(&self) -> u16 {
+        match self {
+            TargetAddr::Ip(addr) => addr.port(),
+            TargetAddr::Domain(_, port) => *port,
+        }
+    }
+}
+
+impl fmt::Display for TargetAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TargetAddr::Ip(addr) => write!(f, "{}", addr),
+            TargetAddr::Domain(host, port) => write!(f, "{}:{}", host, port),
+        }
+    }
+}
+
+impl From<SocketAddr> for TargetAddr {
+    fn from(addr: SocketAddr) -> Self {
+        TargetAddr::Ip(addr)
+    }
+}
+
+impl Dialer {
+    /// Creates a dialer that connects through a given proxy, or directly.
+    pub fn new(proxy: Option<ProxyConfig>) -> Self {
+        Dialer { proxy }
+    }
+
+    /// Returns the proxy used by the dialer.
+    pub fn proxy(&self) -> Option<&ProxyConfig> {
+        self.proxy.as_ref()
+    }
+
+    /// Opens a connection to the target.
+    /// With stream isolation, connections with different isolation keys (e.g. peer IDs)
+    /// use different proxy circuits. If the key is not given, the target address is used.
+    pub async fn connect(
+        &self,
+        target: &TargetAddr,
+        isolation_key: Option<&str>,
+    ) -> Result<TcpStream, io::Error> {
+        match (&self.proxy, target) {
+            (None, TargetAddr::Ip(addr)) => TcpStream::connect(addr).await,
+            (None, TargetAddr::Domain(host, port)) => {
+                TcpStream::connect((host.as_str(), *port)).await
+            }
+            (Some(proxy), target) => {
+                let username = if proxy.isolate_streams {
+                    Some(
+                        isolation_key
+                            .map(|key| key.to_string())
+                            .unwrap_or_else(|| target.to_string()),
+                    )
+                } else {
+                    None
+                };
+                socks5_connect(proxy.addr, target, username.as_deref()).await
+            }
+        }
+    }
+}
+
+impl OnionService {
+    /// Publishes an onion service that forwards the `virtual_port` to the local address,
+    /// via the Tor control port at `control_addr`.
+    /// Authenticates with a password (`HashedControlPassword`), or without one if it is not given.
+    pub async fn publish(
+        control_addr: SocketAddr,
+        password: Option<&str>,
+        virtual_port: u16,
+        local_addr: SocketAddr,
+    ) -> Result<Self, io::Error> {
+        let mut control = BufReader::new(TcpStream::connect(control_addr).await?);
+
+        let auth = match password {
+            Some(password) => format!("AUTHENTICATE \"{}\"\r\n", escape(password)),
+            None => "AUTHENTICATE\r\n".to_string(),
+        };
+        control_command(&mut control, &auth).await?;
+
+        // The key is generated by Tor and discarded, so the onion address changes on restart.
+        let reply = control_command(
+            &mut control,
+            &format!(
+                "ADD_ONION NEW:ED25519-V3 Flags=DiscardPK Port={},{}\r\n",
+                virtual_port, local_addr
+            ),
+        )
+        .await?;
+        let service_id = reply
+            .iter()
+            .find_map(|line| line.strip_prefix("ServiceID="))
+            .ok_or_else(|| proxy_error("Tor controller did not return the ServiceID."))?;
+
+        Ok(OnionService {
+            hostname: format!("{}.onion", service_id),
+            _control: control,
+        })
+    }
+
+    /// Returns the onion address of the service, e.g. `<id>.onion`.
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+}
+
+/// Connects to the target through the SOCKS5 proxy, with optional username/password authentication.
+async fn socks5_connect(
+    proxy: SocketAddr,
+    target: &TargetAddr,
+    username: Option<&str>,
+) -> Result<TcpStream, io::Error> {
+    // Tor does not check the credentials, but both fields must be non-empty.
+    if let Some(username) = username {
+        if username.is_empty() || username.len() > 255 {
+            return Err(proxy_error("Proxy username must be 1 to 255 bytes long."));
+        }
+    }
+    let mut stream = TcpStream::connect(proxy).await?;
+
+    // Greeting with a single authentication method.
+    let method = if username.is_some() {
+        AUTH_PASSWORD
+    } else {
+        AUTH_NONE
+    };
+    stream.write_all(&[SOCKS_VERSION, 1, method]).await?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS_VERSION || reply[1] != method {
+        return Err(proxy_error("Proxy rejected the authentication method."));
+    }
+
+    if let Some(username) = username {
+        let username = username.as_bytes();
+        let mut request = vec![1u8, username.len() as u8];
+        request.extend_from_slice(username);
+        request.extend_from_slice(&[1, b'-']);
+        stream.write_all(&request).await?;
+        stream.read_exact(&mut reply).await?;
+        if reply[1] != 0 {
+            return Err(proxy_error("Proxy rejected the credentials."));
+        }
+    }
+
+    let mut request = vec![SOCKS_VERSION, CMD_CONNECT, 0];
+    match target {
+        TargetAddr::Ip(SocketAddr::V4(addr)) => {
+            request.push(ATYP_IPV4);
+            request.extend_from_slice(&addr.ip().octets());
+        }
+        TargetAddr::Ip(SocketAddr::V6(addr)) => {
+            request.push(ATYP_IPV6);
+            request.extend_from_slice(&addr.ip().octets());
+        }
+        TargetAddr::Domain(host, _) => {
+            if host.len() > 255 {
+                return Err(proxy_error("Host name is too long."));
+            }
+            request.push(ATYP_DOMAIN);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&target.port().to_be_bytes());
+    stream.write_all(&request).await?;
+
+    // Reply: version, status, reserved byte and the address bound by the proxy.
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await?;
+    if header[0] != SOCKS_VERSION {
+        return Err(proxy_error("Proxy replied with an unsupported version."));
+    }
+    if header[1] != 0 {
+        return Err(proxy_error(&format!(
+            "Proxy failed to connect to {}: {}.",
+            target,
+            reply_message(header[1])
+        )));
+    }
+    let addr_len = match header[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).await?;
+            len[0] as usize
+        }
+        _ => return Err(proxy_error("Proxy replied with an invalid address.")),
+    };
+    let mut bound_addr = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound_addr).await?;
+
+    Ok(stream)
+}
+
+/// Sends a command to the Tor controller and returns the lines of a successful reply
+/// without the status codes.
+async fn control_command(
+    control: &mut BufReader<TcpStream>,
+    command: &str,
+) -> Result<Vec<String>, io::Error> {
+    control.get_mut().write_all(command.as_bytes()).await?;
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        if control.read_line(&mut line).await? == 0 {
+            return Err(proxy_error("Tor controller closed the connection."));
+        }
+        let line = line.trim_end();
+        if line.len() < 4 || !line.starts_with("250") {
+            return Err(proxy_error(&format!("Tor controller replied: {}", line)));
+        }
+        let text = line
+            .get(4..)
+            .ok_or_else(|| proxy_error("Tor controller replied with a malformed line."))?;
+        lines.push(text.to_string());
+        // The last line of the reply has a space after the status code.
+        if line.as_bytes()[3] == b' ' {
+            return Ok(lines);
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn reply_message(status: u8) -> &'static str {
+    match status {
+        1 => "general failure",
+        2 => "connection not allowed by ruleset",
+        3 => "network unreachable",
+        4 => "host unreachable",
+        5 => "connection refused",
+        6 => "TTL expired",
+        7 => "command not supported",
+        8 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+fn proxy_error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn socks5_connect_with_isolation() {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = listener.local_addr().unwrap();
+
+        // Minimal proxy that accepts the credentials and echoes the bytes after the handshake.
+        let proxy = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [SOCKS_VERSION, 1, AUTH_PASSWORD]);
+            stream
+                .write_all(&[SOCKS_VERSION, AUTH_PASSWORD])
+                .await
+                .unwrap();
+
+            let mut len = [0u8; 2];
+            stream.read_exact(&mut len).await.unwrap();
+            let mut username = vec![0u8; len[1] as usize];
+            stream.read_exact(&mut username).await.unwrap();
+            let mut password = [0u8; 2];
+            stream.read_exact(&mut password).await.unwrap();
+            stream.write_all(&[1, 0]).await.unwrap();
+
+            let mut request = [0u8; 5];
+            stream.read_exact(&mut request).await.unwrap();
+            assert_eq!(request[..4], [SOCKS_VERSION, CMD_CONNECT, 0, ATYP_DOMAIN]);
+            let mut host = vec![0u8; request[4] as usize + 2];
+            stream.read_exact(&mut host).await.unwrap();
+            stream
+                .write_all(&[SOCKS_VERSION, 0, 0, ATYP_IPV4, 0, 0, 0, 0, 0, 0])
+                .await
+                .unwrap();
+
+            let mut byte = [0u8; 1];
+            stream.read_exact(&mut byte).await.unwrap();
+            stream.write_all(&byte).await.unwrap();
+            (String::from_utf8(username).unwrap(), host)
+        });
+
+        let dialer = Dialer::new(Some(ProxyConfig {
+            addr: proxy_addr,
+            isolate_streams: true,
+        }));
+        let target = TargetAddr::Domain("example.onion".to_string(), 4000);
+        let mut stream = dialer.connect(&target, Some("peer-1")).await.unwrap();
+        stream.write_all(&[42]).await.unwrap();
+        let mut byte = [0u8; 1];
+        stream.read_exact(&mut byte).await.unwrap();
+        assert_eq!(byte, [42]);
+
+        let (username, host) = proxy.await.unwrap();
+        assert_eq!(username, "peer-1");
+        assert_eq!(&host[..13], b"example.onion");
+        assert_eq!(host[13..], 4000u16.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn empty_username_is_rejected() {
+        let dialer = Dialer::new(Some(ProxyConfig {
+            addr: "127.0.0.1:9".parse().unwrap(),
+            isolate_streams: true,
+        }));
+        let target = TargetAddr::Domain("example.onion".to_string(), 4000);
+        assert!(dialer.connect(&target, Some("")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn non_ascii_control_reply_is_rejected() {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let control_addr = listener.local_addr().unwrap();
+        let controller = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut command = [0u8; 9];
+            stream.read_exact(&mut command).await.unwrap();
+            stream.write_all("250é\r\n".as_bytes()).await.unwrap();
+        });
+
+        let mut control = BufReader::new(TcpStream::connect(control_addr).await.unwrap());
+        assert!(control_command(&mut control, "GETINFO\r\n").await.is_err());
+        controller.await.unwrap();
+    }
+}
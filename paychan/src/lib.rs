@@ -154,8 +154,8 @@ fn test() {
                     .push(zkvm::String::Commitment(Box::new(Commitment::unblinded(
                         u64::max_value(),
                     ))))
-                    .push(zkvm::String::Opaque(Program::new().to_bytes()))
-                    .push(zkvm::String::Opaque(channel_tag))
+                    .push(zkvm::String::Opaque(Program::new().to_bytes().into()))
+                    .push(zkvm::String::Opaque(channel_tag.into()))
                     .push(zkvm::String::Predicate(Box::new(exit_predicate.clone())))
                     .contract(assets_count + 1 + 1 + 1 + 1);
             })],
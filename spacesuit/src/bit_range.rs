@@ -1,12 +1,15 @@
-/// Represents a usize with value in the range [0,64]
+/// Maximum bit width of a range: the wide quantities take up to 128 bits.
+pub const MAX_BITS: usize = 128;
+
+/// Represents a usize with value in the range [0,MAX_BITS]
 #[derive(Copy, Clone, Debug)]
 pub struct BitRange(usize);
 
 impl BitRange {
-    /// Returns Some(BitRange) if `n` is ≤ 64.
+    /// Returns Some(BitRange) if `n` is ≤ `MAX_BITS`.
     /// Otherwise returns None.
     pub fn new(n: usize) -> Option<Self> {
-        if n > 64 {
+        if n > MAX_BITS {
             None
         } else {
             Some(BitRange(n))
@@ -17,6 +20,11 @@ impl BitRange {
     pub fn max() -> Self {
         BitRange(64)
     }
+
+    /// Returns the 128-bit range of the wide quantities.
+    pub fn wide() -> Self {
+        BitRange(MAX_BITS)
+    }
 }
 
 impl Into<usize> for BitRange {
@@ -0,0 +1,51 @@
+//! Signed values for modeling debts.
+//!
+//! Cloak accepts values with negative quantities among its inputs. A circuit may create
+//! a value out of nothing together with a _debt_: the value with the same flavor and
+//! the negated quantity, which must be cancelled by merging it in a cloak.
+//! Quantities that leave the circuit must be proven non-negative: `borrow` proves it
+//! for the borrowed value, and `nonnegative` does it for any other value.
+
+use bulletproofs::r1cs::{ConstraintSystem, R1CSError};
+
+use crate::bit_range::BitRange;
+use crate::circuit_size::CircuitSize;
+use crate::range_proof::{range_proof, range_proof_size};
+use crate::value::{AllocatedValue, Value};
+
+/// Enforces that the quantity of the value is in the range [0, 2^n)
+/// and returns the debt: a value with the same flavor and the negated quantity.
+pub fn borrow<CS: ConstraintSystem>(
+    cs: &mut CS,
+    value: AllocatedValue,
+    n: BitRange,
+) -> Result<AllocatedValue, R1CSError> {
+    nonnegative(cs, &value, n)?;
+
+    let neg_assignment = value.assignment.map(|v| Value { q: -v.q, f: v.f });
+    let neg_q = cs.allocate(neg_assignment.map(|v| v.q.into()))?;
+
+    // Enforce that the debt cancels out the borrowed quantity.
+    cs.constrain(value.q + neg_q);
+
+    Ok(AllocatedValue {
+        q: neg_q,
+        f: value.f,
+        assignment: neg_assignment,
+    })
+}
+
+/// Enforces that the quantity of the value is in the range [0, 2^n).
+pub fn nonnegative<CS: ConstraintSystem>(
+    cs: &mut CS,
+    value: &AllocatedValue,
+    n: BitRange,
+) -> Result<(), R1CSError> {
+    range_proof(cs, value.q.into(), value.assignment.map(|v| v.q), n)
+}
+
+/// Returns the size of the `borrow` gadget for an `n`-bit range.
+pub fn borrow_size(n: BitRange) -> CircuitSize {
+    // The range proof, plus one variable and one constraint for the debt.
+    range_proof_size(n) + CircuitSize::new(1, 1)
+}
@@ -0,0 +1,43 @@
+use core::ops::{Add, AddAssign};
+
+/// Number of multipliers and constraints allocated by a gadget.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CircuitSize {
+    /// Number of R1CS multipliers.
+    pub multipliers: usize,
+    /// Number of linear constraints.
+    pub constraints: usize,
+}
+
+impl CircuitSize {
+    /// Creates a circuit size with a given number of multipliers and constraints.
+    pub fn new(multipliers: usize, constraints: usize) -> Self {
+        CircuitSize {
+            multipliers,
+            constraints,
+        }
+    }
+
+    /// Returns the number of multipliers padded to a power of two,
+    /// which is the minimum capacity of `BulletproofGens` required to prove the circuit.
+    pub fn padded_multipliers(&self) -> usize {
+        self.multipliers.next_power_of_two()
+    }
+}
+
+impl Add for CircuitSize {
+    type Output = CircuitSize;
+
+    fn add(self, rhs: CircuitSize) -> CircuitSize {
+        CircuitSize {
+            multipliers: self.multipliers + rhs.multipliers,
+            constraints: self.constraints + rhs.constraints,
+        }
+    }
+}
+
+impl AddAssign for CircuitSize {
+    fn add_assign(&mut self, rhs: CircuitSize) {
+        *self = *self + rhs;
+    }
+}
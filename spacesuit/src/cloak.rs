@@ -1,7 +1,9 @@
 use crate::bit_range::BitRange;
-use crate::shuffle::{padded_shuffle, value_shuffle};
+use crate::circuit_size::CircuitSize;
+use crate::mix::{k_mix, k_mix_size};
+use crate::range_proof::{range_proof, range_proof_size};
+use crate::shuffle::{padded_shuffle, padded_shuffle_size, value_shuffle, value_shuffle_size};
 use crate::value::AllocatedValue;
-use crate::{mix::k_mix, range_proof};
 use bulletproofs::r1cs::{R1CSError, RandomizableConstraintSystem};
 
 /// Enforces that the outputs are a valid rearrangement of the inputs, following the
@@ -11,6 +13,26 @@ pub fn cloak<CS: RandomizableConstraintSystem>(
     inputs: Vec<AllocatedValue>,
     outputs: Vec<AllocatedValue>,
 ) -> Result<(), R1CSError> {
+    let bitranges = vec![BitRange::max(); outputs.len()];
+    cloak_with_bitranges(cs, inputs, outputs, &bitranges)
+}
+
+/// Same as `cloak`, but checks the quantity of each output against its own bit range
+/// instead of the full 64-bit range. Smaller ranges for assets with smaller quantity domains
+/// reduce the number of multipliers. Both prover and verifier must use the same bit ranges.
+pub fn cloak_with_bitranges<CS: RandomizableConstraintSystem>(
+    cs: &mut CS,
+    inputs: Vec<AllocatedValue>,
+    outputs: Vec<AllocatedValue>,
+    bitranges: &[BitRange],
+) -> Result<(), R1CSError> {
+    if bitranges.len() != outputs.len() {
+        return Err(R1CSError::GadgetError {
+            description: "Number of bit ranges does not match the number of outputs in cloak"
+                .to_string(),
+        });
+    }
+
     // Merge
     let (merge_in, merge_out) = merge(cs, inputs.clone())?;
 
@@ -33,19 +55,37 @@ pub fn cloak<CS: RandomizableConstraintSystem>(
     value_shuffle(cs, split_out, outputs.clone())?;
 
     // Range Proof
-    // Check that each of the quantities in `outputs` lies in [0, 2^64).
-    for output in outputs {
-        range_proof(
-            cs,
-            output.q.into(),
-            output.assignment.map(|v| v.q),
-            BitRange::max(),
-        )?;
+    // Check that each of the quantities in `outputs` lies in [0, 2^n)
+    // where `n` is the bit range of that output.
+    for (output, n) in outputs.into_iter().zip(bitranges.iter()) {
+        range_proof(cs, output.q.into(), output.assignment.map(|v| v.q), *n)?;
     }
 
     Ok(())
 }
 
+/// Returns the number of multipliers and constraints allocated by the `cloak` gadget
+/// with `m` inputs and `n` outputs.
+/// Use `CircuitSize::padded_multipliers` to pick the size of `BulletproofGens`.
+pub fn cloak_size(m: usize, n: usize) -> CircuitSize {
+    cloak_size_with_bitranges(m, &vec![BitRange::max(); n])
+}
+
+/// Returns the number of multipliers and constraints allocated by the `cloak_with_bitranges` gadget
+/// with `m` inputs and the outputs with given bit ranges.
+pub fn cloak_size_with_bitranges(m: usize, bitranges: &[BitRange]) -> CircuitSize {
+    let n = bitranges.len();
+    let mut size = k_mix_size(m)
+        + k_mix_size(n)
+        + value_shuffle_size(m)
+        + padded_shuffle_size(m, n)
+        + value_shuffle_size(n);
+    for bitrange in bitranges {
+        size += range_proof_size(*bitrange);
+    }
+    size
+}
+
 /// Enforces that the outputs are either a merge of the inputs: `D = A + B && C = 0`,
 /// or the outputs are equal to the inputs `C = A && D = B`. See spec for more details.
 /// Works for `k` inputs and `k` outputs.
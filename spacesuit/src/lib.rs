@@ -6,6 +6,8 @@
 #![deny(missing_docs)]
 
 mod bit_range;
+mod borrow;
+mod circuit_size;
 mod cloak;
 mod mix;
 mod range_proof;
@@ -13,9 +15,11 @@ mod shuffle;
 mod signed_integer;
 mod value;
 
-pub use crate::bit_range::BitRange;
-pub use crate::cloak::cloak;
-pub use crate::range_proof::range_proof;
+pub use crate::bit_range::{BitRange, MAX_BITS};
+pub use crate::borrow::{borrow, borrow_size, nonnegative};
+pub use crate::circuit_size::CircuitSize;
+pub use crate::cloak::{cloak, cloak_size, cloak_size_with_bitranges, cloak_with_bitranges};
+pub use crate::range_proof::{range_proof, range_proof_size};
 pub use crate::signed_integer::SignedInteger;
 pub use crate::value::{AllocatedValue, CommittedValue, Value};
 
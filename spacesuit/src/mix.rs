@@ -1,5 +1,6 @@
 #![allow(non_snake_case)]
 
+use crate::circuit_size::CircuitSize;
 use crate::signed_integer::SignedInteger;
 use crate::value::{AllocatedValue, Value};
 use bulletproofs::r1cs::{
@@ -57,6 +58,16 @@ pub fn k_mix<CS: RandomizableConstraintSystem>(
     Ok((mix_in, mix_out))
 }
 
+/// Returns the size of the `k_mix` gadget for `k` inputs.
+pub fn k_mix_size(k: usize) -> CircuitSize {
+    if k <= 1 {
+        return CircuitSize::default();
+    }
+    // Allocated values: k inputs, k-2 intermediate and k outputs,
+    // plus k-1 `mix` gadgets with one multiplier and one constraint each.
+    CircuitSize::new((3 * k - 2) + (k - 1), k - 1)
+}
+
 // Calls `k` mix gadgets, using mix_in and mix_mid as inputs, and mix_mid and mix_out as outputs.
 fn call_mix_gadget<CS: RandomizableConstraintSystem>(
     cs: &mut CS,
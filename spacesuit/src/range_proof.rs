@@ -1,4 +1,5 @@
 use crate::bit_range::BitRange;
+use crate::circuit_size::CircuitSize;
 use bulletproofs::r1cs::{ConstraintSystem, LinearCombination, R1CSError};
 use curve25519_dalek::scalar::Scalar;
 
@@ -16,8 +17,8 @@ pub fn range_proof<CS: ConstraintSystem>(
     for i in 0..n_usize {
         // Create low-level variables and add them to constraints
         let (a, b, o) = cs.allocate_multiplier(v_assignment.and_then(|q| {
-            q.to_u64().map(|q| {
-                let bit: u64 = (q >> i) & 1;
+            q.to_u128().map(|q| {
+                let bit = ((q >> i) & 1) as u64;
                 ((1 - bit).into(), bit.into())
             })
         }))?;
@@ -42,6 +43,13 @@ pub fn range_proof<CS: ConstraintSystem>(
     Ok(())
 }
 
+/// Returns the size of the `range_proof` gadget for an `n`-bit range.
+pub fn range_proof_size(n: BitRange) -> CircuitSize {
+    let n: usize = n.into();
+    // One multiplier and two constraints per bit, plus the final constraint on `v`.
+    CircuitSize::new(n, 2 * n + 1)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -67,12 +75,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn wide_range_proof_gadget() {
+        let v = SignedInteger::from_u128(1u128 << 100).unwrap();
+        assert!(range_proof_helper(v, 128).is_ok());
+        assert!(range_proof_helper(v, 64).is_err());
+        assert!(range_proof_helper(v, 100).is_err());
+        assert!(range_proof_helper(v, 101).is_ok());
+    }
+
     fn range_proof_helper(v_val: SignedInteger, n: usize) -> Result<(), R1CSError> {
         // Common
         let pc_gens = PedersenGens::default();
         let bp_gens = BulletproofGens::new(128, 1);
         let bit_width = BitRange::new(n).ok_or(R1CSError::GadgetError {
-            description: "Invalid Bitrange; Bitrange must be between 0 and 64".to_string(),
+            description: "Invalid Bitrange; Bitrange must be between 0 and MAX_BITS".to_string(),
         })?;
 
         // Prover's scope
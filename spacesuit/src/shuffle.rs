@@ -1,3 +1,4 @@
+use crate::circuit_size::CircuitSize;
 use crate::value::{AllocatedValue, Value};
 use bulletproofs::r1cs::{
     ConstraintSystem, R1CSError, RandomizableConstraintSystem, RandomizedConstraintSystem, Variable,
@@ -48,6 +49,14 @@ pub fn scalar_shuffle<CS: RandomizedConstraintSystem>(
     Ok(())
 }
 
+/// Returns the size of the `scalar_shuffle` gadget for `k` inputs.
+pub fn scalar_shuffle_size(k: usize) -> CircuitSize {
+    if k <= 1 {
+        return CircuitSize::new(0, 1);
+    }
+    CircuitSize::new(2 * (k - 1), 1)
+}
+
 /// Enforces that the output values `y` are a valid reordering of the inputs values `x`.
 /// The inputs and outputs are all of the `AllocatedValue` type, which contains the fields
 /// quantity, issuer, and tag. Works for `k` inputs and `k` outputs.
@@ -85,6 +94,14 @@ pub fn value_shuffle<CS: RandomizableConstraintSystem>(
     })
 }
 
+/// Returns the size of the `value_shuffle` gadget for `k` inputs.
+pub fn value_shuffle_size(k: usize) -> CircuitSize {
+    if k <= 1 {
+        return CircuitSize::new(0, 2);
+    }
+    CircuitSize::new(k, 0) + scalar_shuffle_size(k)
+}
+
 /// Enforces that the values in `y` are a valid reordering of the values in `x`,
 /// allowing for padding (zero values) in x that can be omitted in y (or the other way around).
 pub fn padded_shuffle<CS: RandomizableConstraintSystem>(
@@ -117,6 +134,12 @@ pub fn padded_shuffle<CS: RandomizableConstraintSystem>(
     value_shuffle(cs, x, y)
 }
 
+/// Returns the size of the `padded_shuffle` gadget for `m` inputs and `n` outputs.
+pub fn padded_shuffle_size(m: usize, n: usize) -> CircuitSize {
+    let pad_count = max(m, n) - min(m, n);
+    CircuitSize::new(pad_count, 2 * pad_count) + value_shuffle_size(max(m, n))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
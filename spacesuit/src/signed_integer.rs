@@ -1,14 +1,23 @@
 //! Range-preserving arithmetic on signed integers with u64 absolute value.
+//! Wide integers (see `SignedInteger::from_u128`) take up to 127 bits,
+//! and the arithmetic involving them stays in that range.
 use core::ops::Neg;
 use curve25519_dalek::scalar::Scalar;
 use serde::{Deserialize, Serialize};
 use std::ops::{Add, Mul};
-use subtle::{Choice, ConditionallySelectable};
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq};
 
-/// Represents a signed integer with absolute value in the 64-bit range.
+/// Represents a signed integer with absolute value in the 64-bit range
+/// (or up to `i128::MAX` for the wide integers).
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Deserialize, Serialize)]
 pub struct SignedInteger(i128);
 
+/// Maximum absolute value of the integers in the 64-bit range.
+const MAX_ABS: i128 = u64::max_value() as i128;
+
+/// Maximum absolute value of the wide integers.
+const MAX_WIDE_ABS: i128 = i128::max_value();
+
 impl SignedInteger {
     /// Returns Some(x) if self is non-negative
     /// Otherwise returns None.
@@ -20,10 +29,52 @@ impl SignedInteger {
         }
     }
 
+    /// Returns Some(x) if self is non-negative
+    /// Otherwise returns None.
+    pub fn to_u128(&self) -> Option<u128> {
+        if self.0 < 0 {
+            None
+        } else {
+            Some(self.0 as u128)
+        }
+    }
+
+    /// Returns Some(SignedInteger) if the integer is below 2^127.
+    /// Otherwise returns None.
+    /// Integers above `u64::MAX` are wide: the arithmetic involving them is checked against
+    /// the 127-bit range instead of the 64-bit one.
+    pub fn from_u128(u: u128) -> Option<SignedInteger> {
+        if u > MAX_WIDE_ABS as u128 {
+            None
+        } else {
+            Some(SignedInteger(u as i128))
+        }
+    }
+
+    /// Returns true if the absolute value does not fit in 64 bits.
+    pub fn is_wide(&self) -> bool {
+        self.0 > MAX_ABS || self.0 < -MAX_ABS
+    }
+
+    /// Returns the maximum absolute value of the result of an operation on two integers:
+    /// the result is wide only if one of the operands is.
+    fn max_abs(self, rhs: SignedInteger) -> i128 {
+        if self.is_wide() || rhs.is_wide() {
+            MAX_WIDE_ABS
+        } else {
+            MAX_ABS
+        }
+    }
+
     /// Converts the integer to Scalar.
     pub fn to_scalar(self) -> Scalar {
         self.into()
     }
+
+    /// Returns 1 if the integer is negative, 0 otherwise.
+    pub fn is_negative(&self) -> Choice {
+        Choice::from(((self.0 >> 127) & 1) as u8)
+    }
 }
 
 impl From<u64> for SignedInteger {
@@ -34,11 +85,11 @@ impl From<u64> for SignedInteger {
 
 impl Into<Scalar> for SignedInteger {
     fn into(self) -> Scalar {
-        if self.0 < 0 {
-            Scalar::zero() - Scalar::from((-self.0) as u64)
-        } else {
-            Scalar::from(self.0 as u64)
-        }
+        // The integer is usually a secret quantity, so the sign is selected in constant time.
+        let negative = self.is_negative();
+        let abs = i128::conditional_select(&self.0, &-self.0, negative);
+        let abs = Scalar::from(abs as u128);
+        Scalar::conditional_select(&abs, &-abs, negative)
     }
 }
 
@@ -46,13 +97,14 @@ impl Add for SignedInteger {
     type Output = Option<SignedInteger>;
 
     fn add(self, rhs: SignedInteger) -> Option<SignedInteger> {
-        let max = u64::max_value() as i128;
-        let s = self.0 + rhs.0;
-        if s <= max && s >= -max {
-            Some(SignedInteger(s))
-        } else {
-            None
-        }
+        let max = self.max_abs(rhs);
+        self.0.checked_add(rhs.0).and_then(|s| {
+            if s <= max && s >= -max {
+                Some(SignedInteger(s))
+            } else {
+                None
+            }
+        })
     }
 }
 
@@ -60,8 +112,8 @@ impl Mul for SignedInteger {
     type Output = Option<SignedInteger>;
 
     fn mul(self, rhs: SignedInteger) -> Option<SignedInteger> {
+        let max = self.max_abs(rhs);
         self.0.checked_mul(rhs.0).and_then(|p| {
-            let max = u64::max_value() as i128;
             if p <= max && p >= -max {
                 Some(SignedInteger(p))
             } else {
@@ -77,6 +129,12 @@ impl ConditionallySelectable for SignedInteger {
     }
 }
 
+impl ConstantTimeEq for SignedInteger {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
 impl Neg for SignedInteger {
     type Output = SignedInteger;
 
@@ -100,6 +158,49 @@ mod tests {
         assert_eq!(a + b, None);
     }
 
+    #[test]
+    fn to_scalar() {
+        let a = SignedInteger::from(u64::max_value());
+        assert_eq!(a.to_scalar(), Scalar::from(u64::max_value()));
+        assert_eq!((-a).to_scalar(), -Scalar::from(u64::max_value()));
+        assert_eq!(SignedInteger::from(0u64).to_scalar(), Scalar::zero());
+        assert_eq!((-SignedInteger::from(0u64)).to_scalar(), Scalar::zero());
+    }
+
+    #[test]
+    fn ct_eq() {
+        let a = SignedInteger::from(5u64);
+        assert!(bool::from(a.ct_eq(&SignedInteger::from(5u64))));
+        assert!(!bool::from(a.ct_eq(&-a)));
+        assert!(bool::from((-a).is_negative()));
+        assert!(!bool::from(a.is_negative()));
+    }
+
+    #[test]
+    fn wide_range() {
+        let a = SignedInteger::from(u64::max_value());
+        let b = SignedInteger::from_u128(1u128 << 64).unwrap();
+        assert!(b.is_wide());
+        assert!(!a.is_wide());
+        assert_eq!(b.to_u128(), Some(1u128 << 64));
+        assert_eq!(b.to_scalar(), Scalar::from(1u128 << 64));
+        assert_eq!((-b).to_scalar(), -Scalar::from(1u128 << 64));
+        assert_eq!(b + -a, Some(SignedInteger::from(1u64)));
+        assert_eq!(a + -b, Some(-SignedInteger::from(1u64)));
+
+        // The arithmetic on the 64-bit integers stays in the 64-bit range,
+        // and the arithmetic involving a wide integer stays in the 127-bit range.
+        let c = SignedInteger::from(1u64 << 40);
+        assert_eq!(c * c, None);
+        assert_eq!(a + SignedInteger::from(1u64), None);
+        assert_eq!((b * c).and_then(|p| p.to_u128()), Some(1u128 << 104));
+        assert_eq!((b + a).and_then(|s| s.to_u128()), Some((1u128 << 65) - 1));
+
+        let max = SignedInteger::from_u128(i128::max_value() as u128).unwrap();
+        assert_eq!(max + SignedInteger::from(1u64), None);
+        assert_eq!(SignedInteger::from_u128(1u128 << 127), None);
+    }
+
     #[test]
     fn mul_overflow() {
         let a = SignedInteger::from(u64::max_value());
@@ -8,7 +8,10 @@ use bulletproofs::{BulletproofGens, PedersenGens};
 use merlin::Transcript;
 use rand::{CryptoRng, Rng};
 
-use spacesuit::{cloak, CommittedValue, ProverCommittable, Value, VerifierCommittable};
+use spacesuit::{
+    borrow, cloak, cloak_size, cloak_size_with_bitranges, cloak_with_bitranges, BitRange,
+    CommittedValue, ProverCommittable, Value, VerifierCommittable,
+};
 
 fn spacesuit_helper(
     bp_gens: &BulletproofGens,
@@ -336,3 +339,141 @@ fn spacesuit_4_4() {
     )
     .is_ok());
 }
+
+#[test]
+fn spacesuit_size() {
+    // Each test case is proven with the minimal generators computed by `cloak_size`
+    // and fails with fewer generators.
+    let cases = vec![
+        (vec![yuan(1)], vec![yuan(1)]),
+        (vec![yuan(3)], vec![yuan(1), yuan(2)]),
+        (vec![yuan(1), yuan(2)], vec![yuan(3)]),
+        (vec![yuan(1), peso(4)], vec![peso(4), yuan(1)]),
+        (
+            vec![yuan(1), peso(4), euro(8)],
+            vec![yuan(1), euro(8), peso(4), zero()],
+        ),
+    ];
+    for (inputs, outputs) in cases {
+        let size = cloak_size(inputs.len(), outputs.len());
+        let bp_gens = BulletproofGens::new(size.padded_multipliers(), 1);
+        assert!(spacesuit_helper(&bp_gens, inputs.clone(), outputs.clone()).is_ok());
+
+        let bp_gens = BulletproofGens::new(size.padded_multipliers() / 2, 1);
+        assert!(spacesuit_helper(&bp_gens, inputs, outputs).is_err());
+    }
+}
+
+fn bitranges_helper(
+    inputs: Vec<Value>,
+    outputs: Vec<Value>,
+    bitranges: Vec<BitRange>,
+) -> Result<(), R1CSError> {
+    let pc_gens = PedersenGens::default();
+    let bp_gens = BulletproofGens::new(1000, 1);
+    let mut rng = rand::thread_rng();
+
+    let (proof, in_com, out_com) = {
+        let mut prover_transcript = Transcript::new(b"TransactionTest");
+        let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+
+        let (in_com, in_vars) = inputs.commit(&mut prover, &mut rng);
+        let (out_com, out_vars) = outputs.commit(&mut prover, &mut rng);
+
+        cloak_with_bitranges(&mut prover, in_vars, out_vars, &bitranges)?;
+        (prover.prove(&bp_gens)?, in_com, out_com)
+    };
+
+    let mut verifier_transcript = Transcript::new(b"TransactionTest");
+    let mut verifier = Verifier::new(&mut verifier_transcript);
+
+    let in_vars = in_com.commit(&mut verifier);
+    let out_vars = out_com.commit(&mut verifier);
+
+    cloak_with_bitranges(&mut verifier, in_vars, out_vars, &bitranges)?;
+    verifier.verify(&proof, &pc_gens, &bp_gens)
+}
+
+#[test]
+fn spacesuit_bitranges() {
+    let b32 = BitRange::new(32).unwrap();
+    let b64 = BitRange::max();
+
+    assert!(bitranges_helper(vec![yuan(3)], vec![yuan(1), yuan(2)], vec![b32, b32]).is_ok());
+    assert!(bitranges_helper(
+        vec![yuan(1 << 40), peso(4)],
+        vec![yuan(1 << 40), peso(4)],
+        vec![b64, b32]
+    )
+    .is_ok());
+
+    // Quantity does not fit in the 32-bit range of its output.
+    assert!(bitranges_helper(
+        vec![yuan(1 << 40), peso(4)],
+        vec![yuan(1 << 40), peso(4)],
+        vec![b32, b32]
+    )
+    .is_err());
+
+    // Number of bit ranges must match the number of outputs.
+    assert!(bitranges_helper(vec![yuan(3)], vec![yuan(3)], vec![b32, b32]).is_err());
+
+    // Smaller ranges reduce the number of multipliers.
+    assert_eq!(
+        cloak_size_with_bitranges(2, &[b32, b32]).multipliers + 64,
+        cloak_size(2, 2).multipliers
+    );
+}
+
+fn borrow_helper(
+    inputs: Vec<Value>,
+    borrowed: Value,
+    outputs: Vec<Value>,
+) -> Result<(), R1CSError> {
+    let pc_gens = PedersenGens::default();
+    let bp_gens = BulletproofGens::new(1000, 1);
+    let mut rng = rand::thread_rng();
+
+    let (proof, in_com, borrowed_com, out_com) = {
+        let mut prover_transcript = Transcript::new(b"BorrowTest");
+        let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+
+        let (in_com, mut in_vars) = inputs.commit(&mut prover, &mut rng);
+        let (borrowed_com, borrowed_var) = borrowed.commit(&mut prover, &mut rng);
+        let (out_com, out_vars) = outputs.commit(&mut prover, &mut rng);
+
+        // The debt is cancelled by the inputs of the cloak.
+        in_vars.push(borrow(&mut prover, borrowed_var, BitRange::max())?);
+        cloak(&mut prover, in_vars, out_vars)?;
+        (prover.prove(&bp_gens)?, in_com, borrowed_com, out_com)
+    };
+
+    let mut verifier_transcript = Transcript::new(b"BorrowTest");
+    let mut verifier = Verifier::new(&mut verifier_transcript);
+
+    let mut in_vars = in_com.commit(&mut verifier);
+    let borrowed_var = borrowed_com.commit(&mut verifier);
+    let out_vars = out_com.commit(&mut verifier);
+
+    in_vars.push(borrow(&mut verifier, borrowed_var, BitRange::max())?);
+    cloak(&mut verifier, in_vars, out_vars)?;
+    verifier.verify(&proof, &pc_gens, &bp_gens)
+}
+
+#[test]
+fn spacesuit_borrow() {
+    // Borrowed 5 yuan are repaid from the input of 7 yuan.
+    assert!(borrow_helper(vec![yuan(7)], yuan(5), vec![yuan(2)]).is_ok());
+    assert!(borrow_helper(vec![yuan(7), peso(4)], yuan(7), vec![peso(4)]).is_ok());
+
+    // The debt must be repaid in full, with the same flavor.
+    assert!(borrow_helper(vec![yuan(7)], yuan(5), vec![yuan(7)]).is_err());
+    assert!(borrow_helper(vec![yuan(7)], peso(5), vec![yuan(2)]).is_err());
+
+    // Negative quantities cannot be borrowed.
+    let negative = Value {
+        q: -spacesuit::SignedInteger::from(5u64),
+        f: 888u64.into(),
+    };
+    assert!(borrow_helper(vec![yuan(7)], negative, vec![yuan(12)]).is_err());
+}
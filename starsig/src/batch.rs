@@ -89,6 +89,29 @@ impl<R: RngCore + CryptoRng> BatchVerifier<R> {
         }
     }
 
+    /// Adds all the statements collected by another batch.
+    /// The statements are already weighted with the other batch's random factors,
+    /// so the batches can be filled independently and verified together.
+    pub fn append_batch<Q: RngCore + CryptoRng>(&mut self, other: BatchVerifier<Q>) {
+        self.basepoint_scalar += other.basepoint_scalar;
+        self.dyn_weights.extend(other.dyn_weights);
+        self.dyn_points.extend(other.dyn_points);
+    }
+
+    /// Adds all the statements collected by another batch, scaled by a given weight.
+    /// Batches weighted without local entropy can be crafted to cancel each other out,
+    /// unless each one is scaled by a weight unpredictable to the authors of the statements.
+    pub fn append_batch_weighted<Q: RngCore + CryptoRng>(
+        &mut self,
+        other: BatchVerifier<Q>,
+        weight: Scalar,
+    ) {
+        self.basepoint_scalar += weight * other.basepoint_scalar;
+        self.dyn_weights
+            .extend(other.dyn_weights.into_iter().map(|w| weight * w));
+        self.dyn_points.extend(other.dyn_points);
+    }
+
     /// Performs the verification and returns the result.
     pub fn verify(self) -> Result<(), StarsigError> {
         let result = RistrettoPoint::optional_multiscalar_mul(
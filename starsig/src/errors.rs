@@ -1,6 +1,7 @@
 use thiserror::Error;
 /// Represents an error in key aggregation, signing, or verification.
 #[derive(Error, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum StarsigError {
     /// This error occurs when a point is not a valid compressed Ristretto point
     #[error("Signature verification failed")]
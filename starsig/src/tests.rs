@@ -28,6 +28,68 @@ fn empty_batch() {
     let batch = BatchVerifier::new(rand::thread_rng());
     assert_eq!(batch.verify(), Ok(()));
 }
+#[test]
+fn merge_batches() {
+    let prv1 = Scalar::from(1u64);
+    let prv2 = Scalar::from(2u64);
+    let sig1 = Signature::sign(&mut Transcript::new(b"example transcript 1"), prv1);
+    let sig2 = Signature::sign(&mut Transcript::new(b"example transcript 2"), prv2);
+    let pub1 = VerificationKey::from_secret(&prv1);
+    let pub2 = VerificationKey::from_secret(&prv2);
+
+    let mut batch = BatchVerifier::new(rand::thread_rng());
+    sig1.verify_batched(
+        &mut Transcript::new(b"example transcript 1"),
+        pub1,
+        &mut batch,
+    );
+    let mut other = BatchVerifier::new(rand::thread_rng());
+    sig2.verify_batched(
+        &mut Transcript::new(b"example transcript 2"),
+        pub2,
+        &mut other,
+    );
+    batch.append_batch(other);
+    assert!(batch.verify().is_ok());
+
+    // Invalid statement in the appended batch fails the whole batch.
+    let mut batch = BatchVerifier::new(rand::thread_rng());
+    sig1.verify_batched(
+        &mut Transcript::new(b"example transcript 1"),
+        pub1,
+        &mut batch,
+    );
+    let mut bad_batch = BatchVerifier::new(rand::thread_rng());
+    sig2.verify_batched(
+        &mut Transcript::new(b"example transcript 1"),
+        pub2,
+        &mut bad_batch,
+    );
+    batch.append_batch(bad_batch);
+    assert!(batch.verify().is_err());
+
+    // Weighted batches verify together, and an invalid one still fails the whole batch.
+    let mut batch = BatchVerifier::new(rand::thread_rng());
+    let mut other = BatchVerifier::new(rand::thread_rng());
+    sig2.verify_batched(
+        &mut Transcript::new(b"example transcript 2"),
+        pub2,
+        &mut other,
+    );
+    batch.append_batch_weighted(other, Scalar::from(7u64));
+    assert!(batch.verify().is_ok());
+
+    let mut bad_batch = BatchVerifier::new(rand::thread_rng());
+    sig2.verify_batched(
+        &mut Transcript::new(b"example transcript 1"),
+        pub2,
+        &mut bad_batch,
+    );
+    let mut batch = BatchVerifier::new(rand::thread_rng());
+    batch.append_batch_weighted(bad_batch, Scalar::from(7u64));
+    assert!(batch.verify().is_err());
+}
+
 #[test]
 fn sign_and_verify_batch() {
     let prv1 = Scalar::from(1u64);
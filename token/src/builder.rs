@@ -0,0 +1,241 @@
+//! Builders of the transactions that issue, transfer and retire a single token.
+//!
+//! The builders assemble the program, derive the flavor of the token and sign the transaction
+//! with the private keys attached to the predicates as witnesses (`Predicate::with_witness`).
+//! The blinding factors of the values are drawn from the caller's RNG,
+//! so the same RNG seed produces the same program.
+
+use bulletproofs::BulletproofGens;
+use curve25519_dalek::scalar::Scalar;
+use merlin::Transcript;
+use musig::{Multisignature, Signature};
+use rand::{CryptoRng, RngCore};
+use thiserror::Error;
+use zkvm::{
+    Commitment, Contract, PortableItem, Predicate, Program, Prover, Tx, TxHeader, TxLog,
+    UnsignedTx, VMError,
+};
+
+use crate::token::Token;
+
+/// Transaction involving a single token, ready to be built and signed.
+#[derive(Clone, Debug)]
+pub struct TokenTx {
+    program: Program,
+    header: TxHeader,
+}
+
+/// Errors related to the token transactions.
+#[derive(Clone, Error, Debug, PartialEq)]
+pub enum TokenError {
+    /// Input contract does not hold a single value of the token with a known quantity.
+    #[error("Input contract does not hold a known quantity of the token.")]
+    InvalidInput,
+
+    /// Inputs do not hold enough of the token.
+    #[error("Insufficient funds: {available} available, {required} required.")]
+    InsufficientFunds {
+        /// Total quantity of the inputs.
+        available: u64,
+        /// Total quantity to be spent.
+        required: u64,
+    },
+
+    /// Predicate to be signed does not carry a matching private key.
+    #[error("Private key is missing for a predicate to be signed.")]
+    MissingKey,
+
+    /// Transaction cannot be built.
+    #[error("Transaction cannot be built: {0}")]
+    VMError(#[from] VMError),
+}
+
+impl Token {
+    /// Creates a transaction that issues a given quantity of the token to a given predicate.
+    /// The issuance is anchored to the `anchor` contract which is spent in the same transaction:
+    /// its payload, if any, is locked back by its predicate.
+    pub fn issue_tx<R: RngCore + CryptoRng>(
+        &self,
+        qty: u64,
+        dest: Predicate,
+        anchor: Contract,
+        rng: &mut R,
+    ) -> TokenTx {
+        let program = Program::build(|p| {
+            let predicate = anchor.predicate.clone();
+            let k = anchor.payload.len();
+            p.push(anchor).input().signtx();
+            if k > 0 {
+                p.push(predicate).output(k);
+            }
+            self.issue_to(p, qty, dest, rng);
+        });
+        TokenTx::new(program)
+    }
+
+    /// Creates a transaction that pays given quantities of the token from the input contracts
+    /// to given predicates, returning the remainder to the `change` predicate.
+    pub fn transfer_tx<R: RngCore + CryptoRng>(
+        &self,
+        inputs: Vec<Contract>,
+        payments: Vec<(u64, Predicate)>,
+        change: Predicate,
+        rng: &mut R,
+    ) -> Result<TokenTx, TokenError> {
+        let required = payments
+            .iter()
+            .try_fold(0u64, |total, (qty, _)| total.checked_add(*qty))
+            .ok_or(TokenError::InvalidInput)?;
+        let available = self.inputs_qty(&inputs)?;
+        let change_qty = available
+            .checked_sub(required)
+            .ok_or(TokenError::InsufficientFunds {
+                available,
+                required,
+            })?;
+
+        let mut outputs = payments;
+        if change_qty > 0 {
+            outputs.push((change_qty, change));
+        }
+        let program = Program::build(|p| {
+            let m = self.spend_inputs(p, inputs);
+            self.cloak_outputs(p, m, outputs.iter().map(|(qty, _)| *qty), rng);
+            for (_, pred) in outputs {
+                p.push(pred).output(1);
+            }
+        });
+        Ok(TokenTx::new(program))
+    }
+
+    /// Creates a transaction that retires a given quantity of the token from the input contracts,
+    /// returning the remainder to the `change` predicate.
+    pub fn retire_tx<R: RngCore + CryptoRng>(
+        &self,
+        inputs: Vec<Contract>,
+        qty: u64,
+        change: Predicate,
+        rng: &mut R,
+    ) -> Result<TokenTx, TokenError> {
+        let available = self.inputs_qty(&inputs)?;
+        let change_qty = available
+            .checked_sub(qty)
+            .ok_or(TokenError::InsufficientFunds {
+                available,
+                required: qty,
+            })?;
+
+        let program = Program::build(|p| {
+            let m = self.spend_inputs(p, inputs);
+            if change_qty > 0 {
+                self.cloak_outputs(p, m, vec![qty, change_qty], rng);
+                p.retire().push(change).output(1);
+            } else {
+                self.cloak_outputs(p, m, vec![qty], rng);
+                p.retire();
+            }
+        });
+        Ok(TokenTx::new(program))
+    }
+
+    /// Returns the total quantity of the token in the contracts.
+    fn inputs_qty(&self, inputs: &[Contract]) -> Result<u64, TokenError> {
+        let flavor = self.flavor();
+        inputs.iter().try_fold(0u64, |total, contract| {
+            let qty = match &contract.payload[..] {
+                [PortableItem::Value(value)] => match value.assignment() {
+                    Some((qty, flv)) if flv == flavor => qty.to_u64(),
+                    _ => None,
+                },
+                _ => None,
+            };
+            qty.and_then(|qty| total.checked_add(qty))
+                .ok_or(TokenError::InvalidInput)
+        })
+    }
+
+    /// Adds instructions to spend the contracts, returns the number of the spent values.
+    fn spend_inputs(&self, program: &mut Program, inputs: Vec<Contract>) -> usize {
+        let m = inputs.len();
+        for contract in inputs {
+            program.push(contract).input().signtx();
+        }
+        m
+    }
+
+    /// Adds instructions to merge `m` values on the stack and split them into the values
+    /// with the given quantities. The first value ends up on top of the stack.
+    fn cloak_outputs<R: RngCore + CryptoRng>(
+        &self,
+        program: &mut Program,
+        m: usize,
+        quantities: impl IntoIterator<Item = u64>,
+        rng: &mut R,
+    ) {
+        let flavor = self.flavor();
+        let mut n = 0;
+        for qty in quantities {
+            program
+                .push(Commitment::blinded_with_rng(qty, rng))
+                .push(Commitment::blinded_with_rng(flavor, rng));
+            n += 1;
+        }
+        program.cloak(m, n);
+    }
+}
+
+impl TokenTx {
+    fn new(program: Program) -> Self {
+        TokenTx {
+            program,
+            header: TxHeader {
+                version: 0,
+                mintime_ms: 0,
+                maxtime_ms: u64::max_value(),
+            },
+        }
+    }
+
+    /// Sets the header of the transaction.
+    pub fn header(mut self, header: TxHeader) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Returns the program of the transaction.
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Builds the unsigned transaction.
+    pub fn build(self, bp_gens: &BulletproofGens) -> Result<UnsignedTx, TokenError> {
+        Ok(Prover::build_tx(self.program, self.header, bp_gens)?)
+    }
+
+    /// Builds the transaction and signs it with the private keys
+    /// attached to the signed predicates.
+    pub fn sign(self, bp_gens: &BulletproofGens) -> Result<(Tx, TxLog), TokenError> {
+        let utx = self.build(bp_gens)?;
+        let privkeys = utx
+            .signing_instructions
+            .iter()
+            .map(|(pred, _)| pred.verification_key_witness::<Scalar>().copied())
+            .collect::<Option<Vec<_>>>()
+            .ok_or(TokenError::MissingKey)?;
+
+        let mut transcript = Transcript::new(b"ZkVM.signtx");
+        transcript.append_message(b"txid", &utx.txid.0);
+        let signature = Signature::sign_multi(
+            privkeys,
+            utx.signing_instructions
+                .iter()
+                .map(|(pred, msg)| (pred.verification_key(), msg))
+                .collect(),
+            &mut transcript,
+        )
+        .map_err(|_| TokenError::MissingKey)?;
+
+        let txlog = utx.txlog.clone();
+        Ok((utx.sign(signature), txlog))
+    }
+}
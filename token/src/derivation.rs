@@ -10,12 +10,22 @@ use super::Token;
 pub trait XprvDerivation {
     /// Derives a key for a given asset alias.
     fn issuing_key(&self, alias: &str) -> Scalar;
+
+    /// Derives a key for signing the vouchers for a given quantity of the asset.
+    fn voucher_key(&self, alias: &str, qty: u64) -> Scalar;
 }
 
 impl XprvDerivation for Xprv {
     fn issuing_key(&self, alias: &str) -> Scalar {
         self.derive_key(|t| t.append_message(b"token.alias", alias.as_bytes()))
     }
+
+    fn voucher_key(&self, alias: &str, qty: u64) -> Scalar {
+        self.derive_key(|t| {
+            t.append_message(b"token.voucher", alias.as_bytes());
+            t.append_u64(b"qty", qty);
+        })
+    }
 }
 
 /// Extension trait for Xprv to derive keys based on sequence number.
@@ -26,6 +36,9 @@ pub trait XpubDerivation {
     /// Derives an Address for a given sequence number.
     fn derive_token(&self, alias: &str) -> Token;
 
+    /// Derives a key for verifying the vouchers for a given quantity of the asset.
+    fn voucher_key(&self, alias: &str, qty: u64) -> VerificationKey;
+
     /// Derives blinding factors for the given value and sequence number.
     /// Q: Why deterministic derivation?
     /// A: Blinding factors are high-entropy, so loss of such data is fatal.
@@ -47,6 +60,13 @@ impl XpubDerivation for Xpub {
         )
     }
 
+    fn voucher_key(&self, alias: &str, qty: u64) -> VerificationKey {
+        self.derive_key(|t| {
+            t.append_message(b"token.voucher", alias.as_bytes());
+            t.append_u64(b"qty", qty);
+        })
+    }
+
     fn value_blinding_factor(&self, alias: &str, qty: u64) -> Scalar {
         // Blinding factors are deterministically derived in order to avoid
         // having to backup secret material.
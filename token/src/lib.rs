@@ -1,8 +1,12 @@
 #![deny(missing_docs)]
 //! Token API for ZkVM
 
+mod builder;
 mod derivation;
 mod token;
+mod voucher;
 
+pub use self::builder::{TokenError, TokenTx};
 pub use self::token::Token;
+pub use self::voucher::{BlindSigner, Voucher, VoucherError, VoucherRequest};
 pub use derivation::{XprvDerivation, XpubDerivation};
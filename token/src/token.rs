@@ -1,4 +1,5 @@
 use curve25519_dalek::scalar::Scalar;
+use rand::{CryptoRng, RngCore};
 use zkvm::{Commitment, Contract, Predicate, Program, String, Value};
 
 /// Represents a ZkVM Token with unique flavor and embedded
@@ -22,19 +23,24 @@ impl Token {
     pub fn flavor(&self) -> Scalar {
         Value::issue_flavor(
             &self.issuance_predicate,
-            String::Opaque(self.metadata.clone()),
+            String::Opaque(self.metadata.clone().into()),
         )
     }
 
     /// Adds instructions to a program to issues a given quantity
-    /// of this Token.
-    pub fn issue<'a>(&self, program: &'a mut Program, qty: u64) -> &'a mut Program {
+    /// of this Token. The quantity is blinded with a factor drawn from `rng`.
+    pub fn issue<'a, R: RngCore + CryptoRng>(
+        &self,
+        program: &'a mut Program,
+        qty: u64,
+        rng: &mut R,
+    ) -> &'a mut Program {
         program
-            .push(Commitment::blinded(qty)) // stack: qty
+            .push(Commitment::blinded_with_rng(qty, rng)) // stack: qty
             .commit() // stack: qty-var
             .push(Commitment::unblinded(self.flavor())) // stack: qty-var, flv
             .commit() // stack: qty-var, flv-var
-            .push(String::Opaque(self.metadata.clone())) // stack: qty-var, flv-var, data
+            .push(String::Opaque(self.metadata.clone().into())) // stack: qty-var, flv-var, data
             .push(self.issuance_predicate.clone()) // stack: qty-var, flv-var, data, flv-pred
             .issue() // stack: issue-contract
             .signtx() // stack: issued-value
@@ -42,13 +48,14 @@ impl Token {
 
     /// Adds instructions to a program to issue a given quantity
     /// of this token to a given destination predicate.
-    pub fn issue_to<'a>(
+    pub fn issue_to<'a, R: RngCore + CryptoRng>(
         &self,
         program: &'a mut Program,
         qty: u64,
         dest: Predicate,
+        rng: &mut R,
     ) -> &'a mut Program {
-        self.issue(program, qty).push(dest).output(1)
+        self.issue(program, qty, rng).push(dest).output(1)
     }
 
     /// Adds instructions to a program to retire a given UTXO.
@@ -91,7 +98,7 @@ mod tests {
 
             let program = Program::build(|p| {
                 add_dummy_input(p, dummy_key);
-                usd.issue_to(p, 10u64, dest.clone());
+                usd.issue_to(p, 10u64, dest.clone(), &mut rand::thread_rng());
             });
             build_tx(program).unwrap()
         };
@@ -112,7 +119,7 @@ mod tests {
             let dest = Predicate::with_witness(dest_key);
             let issue_program = Program::build(|p| {
                 add_dummy_input(p, dummy_key);
-                usd.issue_to(p, 10u64, dest.clone());
+                usd.issue_to(p, 10u64, dest.clone(), &mut rand::thread_rng());
             });
             let (_, _, issue_txlog) = build_tx(issue_program).unwrap();
 
@@ -130,6 +137,182 @@ mod tests {
         assert!(tx.verify(&bp_gens).is_ok());
     }
 
+    #[test]
+    fn blind_voucher_redemption() {
+        use crate::{BlindSigner, VoucherError, VoucherRequest};
+        use musig::VerificationKey;
+
+        let mut rng = rand::thread_rng();
+        let voucher_key = Scalar::from(4u64);
+        let voucher_pubkey = VerificationKey::from_secret(&voucher_key);
+
+        // Issuer signs the voucher without seeing its serial number.
+        let (signer, nonce_commitment) = BlindSigner::new(voucher_key, &mut rng);
+        let (request, blinded_challenge) =
+            VoucherRequest::new(voucher_pubkey, nonce_commitment, &mut rng).unwrap();
+        let voucher = request.finish(signer.sign(blinded_challenge)).unwrap();
+        assert!(voucher.verify(voucher_pubkey).is_ok());
+
+        let mut forged = voucher;
+        forged.serial[0] ^= 1;
+        assert_eq!(
+            forged.verify(voucher_pubkey),
+            Err(VoucherError::InvalidSignature)
+        );
+
+        // Issuer redeems the voucher, logging its serial number.
+        let (tx, _, txlog) = {
+            let issue_key = Scalar::from(1u64);
+            let dest_key = Scalar::from(2u64);
+            let dummy_key = Scalar::from(3u64);
+            let usd = Token::new(Predicate::with_witness(issue_key), b"USD".to_vec());
+            let dest = Predicate::with_witness(dest_key);
+
+            let program = Program::build(|p| {
+                add_dummy_input(p, dummy_key);
+                usd.issue_to(p, 10u64, dest.clone(), &mut rng);
+                voucher.redeem(p, voucher_pubkey);
+            });
+            build_tx(program).unwrap()
+        };
+        assert!(txlog.iter().any(|entry| match entry {
+            TxEntry::Data(data) => data.as_slice() == &voucher.serial[..],
+            _ => false,
+        }));
+
+        let bp_gens = BulletproofGens::new(256, 1);
+        assert!(tx.verify(&bp_gens).is_ok());
+
+        // Voucher signed by another key is rejected by the VM.
+        let (tx, _, _) = {
+            let issue_key = Scalar::from(1u64);
+            let dummy_key = Scalar::from(3u64);
+            let usd = Token::new(Predicate::with_witness(issue_key), b"USD".to_vec());
+            let program = Program::build(|p| {
+                add_dummy_input(p, dummy_key);
+                usd.issue_to(
+                    p,
+                    10u64,
+                    Predicate::with_witness(Scalar::from(2u64)),
+                    &mut rng,
+                );
+                voucher.redeem(p, VerificationKey::from_secret(&Scalar::from(5u64)));
+            });
+            build_tx(program).unwrap()
+        };
+        assert!(tx.verify(&bp_gens).is_err());
+    }
+
+    #[test]
+    fn issue_transfer_retire() {
+        use crate::TokenError;
+
+        let mut rng = rand::thread_rng();
+        let bp_gens = BulletproofGens::new(256, 1);
+        let usd = Token::new(Predicate::with_witness(Scalar::from(1u64)), b"USD".to_vec());
+        let alice = Predicate::with_witness(Scalar::from(2u64));
+        let bob = Predicate::with_witness(Scalar::from(3u64));
+        let anchor = Contract {
+            predicate: Predicate::with_witness(Scalar::from(4u64)),
+            payload: vec![],
+            anchor: Anchor::from_raw_bytes([0u8; 32]),
+        };
+
+        // Issue 10 USD to Alice.
+        let (tx, txlog) = usd
+            .issue_tx(10, alice.clone(), anchor, &mut rng)
+            .sign(&bp_gens)
+            .unwrap();
+        assert!(tx.verify(&bp_gens).is_ok());
+        let alice_utxo = txlog.outputs().next().unwrap().clone();
+
+        // Alice pays 7 USD to Bob.
+        assert_eq!(
+            usd.transfer_tx(
+                vec![alice_utxo.clone()],
+                vec![(11, bob.clone())],
+                alice.clone(),
+                &mut rng
+            )
+            .unwrap_err(),
+            TokenError::InsufficientFunds {
+                available: 10,
+                required: 11
+            }
+        );
+        let (tx, txlog) = usd
+            .transfer_tx(
+                vec![alice_utxo],
+                vec![(7, bob.clone())],
+                alice.clone(),
+                &mut rng,
+            )
+            .unwrap()
+            .sign(&bp_gens)
+            .unwrap();
+        assert!(tx.verify(&bp_gens).is_ok());
+        let outputs: Vec<Contract> = txlog.outputs().cloned().collect();
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0].predicate, bob);
+        assert_eq!(outputs[1].predicate, alice);
+
+        // Bob retires 5 USD and keeps the rest, Alice retires everything.
+        let (tx, _) = usd
+            .retire_tx(vec![outputs[0].clone()], 5, bob.clone(), &mut rng)
+            .unwrap()
+            .sign(&bp_gens)
+            .unwrap();
+        assert!(tx.verify(&bp_gens).is_ok());
+        let (tx, txlog) = usd
+            .retire_tx(vec![outputs[1].clone()], 3, alice, &mut rng)
+            .unwrap()
+            .sign(&bp_gens)
+            .unwrap();
+        assert!(tx.verify(&bp_gens).is_ok());
+        assert_eq!(txlog.outputs().count(), 0);
+
+        // Values of another token are rejected.
+        let eur = Token::new(Predicate::with_witness(Scalar::from(1u64)), b"EUR".to_vec());
+        assert_eq!(
+            eur.retire_tx(vec![outputs[0].clone()], 1, bob, &mut rng)
+                .unwrap_err(),
+            TokenError::InvalidInput
+        );
+    }
+
+    #[test]
+    fn deterministic_transfer() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let bp_gens = BulletproofGens::new(256, 1);
+        let usd = Token::new(Predicate::with_witness(Scalar::from(1u64)), b"USD".to_vec());
+        let alice = Predicate::with_witness(Scalar::from(2u64));
+        let bob = Predicate::with_witness(Scalar::from(3u64));
+        let build = |seed: u64| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let anchor = Contract {
+                predicate: Predicate::with_witness(Scalar::from(4u64)),
+                payload: vec![],
+                anchor: Anchor::from_raw_bytes([0u8; 32]),
+            };
+            let (_, txlog) = usd
+                .issue_tx(10, alice.clone(), anchor, &mut rng)
+                .sign(&bp_gens)
+                .unwrap();
+            let utxo = txlog.outputs().next().unwrap().clone();
+            usd.transfer_tx(vec![utxo], vec![(7, bob.clone())], alice.clone(), &mut rng)
+                .unwrap()
+                .build(&bp_gens)
+                .unwrap()
+        };
+
+        let (utx1, utx2) = (build(1), build(1));
+        assert_eq!(utx1.program, utx2.program);
+        assert_eq!(utx1.txid, utx2.txid);
+        assert_ne!(utx1.txid, build(2).txid);
+    }
+
     // Helper functions
     fn build_tx(program: Program) -> Result<(Tx, TxID, TxLog), VMError> {
         let bp_gens = BulletproofGens::new(256, 1);
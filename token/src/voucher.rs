@@ -0,0 +1,215 @@
+//! Blind issuance of vouchers.
+//!
+//! A voucher is a bearer claim for a fixed amount of a token, signed by the issuer's voucher key.
+//! The issuer signs the voucher blindly: it learns neither the voucher's serial number
+//! nor the signature, so the redemption cannot be linked to the signing session
+//! (e.g. to the payment made for the voucher).
+//!
+//! The voucher signature is a regular Schnorr signature with the `signtag` message
+//! over the voucher's serial number and a fixed redemption program,
+//! so the redemption is checked by the VM with the existing deferred point operations:
+//!
+//! ```ascii
+//! <serial> <voucher key> contract:1 <log> <signature> signtag
+//! ```
+//!
+//! The redemption program logs the serial number, so anyone can check the transaction log
+//! for the vouchers that are redeemed more than once.
+//!
+//! Blind signing protocol between the issuer with the voucher key `X = x·B` and the user:
+//!
+//! 1. Issuer creates a `BlindSigner` with a random nonce `r` and sends `R = r·B`.
+//! 2. User creates a `VoucherRequest` with random blinding factors `α`, `β`,
+//!    computes `R' = R + α·B + β·X`, the challenge `c' = H(X, R', serial)`,
+//!    and sends the blinded challenge `c = c' + β`.
+//! 3. Issuer sends `s = r + c·x`.
+//! 4. User unblinds the signature `(s + α, R')`.
+//!
+//! The issuer must not run concurrent signing sessions with the same key:
+//! parallel sessions allow forging an extra signature (ROS attack).
+
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::scalar::Scalar;
+use merlin::Transcript;
+use musig::{Signature, StarsigTranscriptProtocol, VerificationKey};
+use rand::{CryptoRng, RngCore};
+use thiserror::Error;
+use zkvm::{Predicate, Program, String};
+
+/// Voucher signed by the issuer.
+#[derive(Copy, Clone, Debug)]
+pub struct Voucher {
+    /// Random serial number of the voucher.
+    pub serial: [u8; 32],
+
+    /// Issuer's signature over the serial number.
+    pub signature: Signature,
+}
+
+/// Issuer's state of the blind signing session.
+/// The nonce is used once: `sign` consumes the session.
+pub struct BlindSigner {
+    privkey: Scalar,
+    nonce: Scalar,
+}
+
+/// User's state of the blind signing session.
+pub struct VoucherRequest {
+    pubkey: VerificationKey,
+    serial: [u8; 32],
+    alpha: Scalar,
+    nonce_commitment: CompressedRistretto,
+}
+
+/// Errors related to the vouchers.
+#[derive(Copy, Clone, Error, Debug, Eq, PartialEq)]
+pub enum VoucherError {
+    /// Issuer's nonce commitment is not a valid point.
+    #[error("Nonce commitment is not a valid point.")]
+    InvalidNonceCommitment,
+
+    /// Voucher key is not a valid point.
+    #[error("Voucher key is not a valid point.")]
+    InvalidKey,
+
+    /// Voucher signature is not valid.
+    #[error("Voucher signature is not valid.")]
+    InvalidSignature,
+
+    /// Voucher encoding is not valid.
+    #[error("Voucher encoding is not valid.")]
+    InvalidFormat,
+}
+
+impl BlindSigner {
+    /// Starts the signing session with the issuer's voucher key.
+    /// Returns the session and the nonce commitment to be sent to the user.
+    pub fn new<R: RngCore + CryptoRng>(
+        privkey: Scalar,
+        rng: &mut R,
+    ) -> (Self, CompressedRistretto) {
+        let nonce = Scalar::random(rng);
+        let nonce_commitment = (nonce * RISTRETTO_BASEPOINT_POINT).compress();
+        (BlindSigner { privkey, nonce }, nonce_commitment)
+    }
+
+    /// Signs the user's blinded challenge.
+    /// Returns the blinded signature scalar to be sent to the user.
+    pub fn sign(self, blinded_challenge: Scalar) -> Scalar {
+        self.nonce + blinded_challenge * self.privkey
+    }
+}
+
+impl VoucherRequest {
+    /// Prepares the voucher with a random serial number for the issuer's nonce commitment.
+    /// Returns the request and the blinded challenge to be sent to the issuer.
+    pub fn new<R: RngCore + CryptoRng>(
+        pubkey: VerificationKey,
+        nonce_commitment: CompressedRistretto,
+        rng: &mut R,
+    ) -> Result<(Self, Scalar), VoucherError> {
+        let mut serial = [0u8; 32];
+        rng.fill_bytes(&mut serial);
+        let alpha = Scalar::random(rng);
+        let beta = Scalar::random(rng);
+
+        let nonce_point = nonce_commitment
+            .decompress()
+            .ok_or(VoucherError::InvalidNonceCommitment)?;
+        let key_point = pubkey
+            .as_point()
+            .decompress()
+            .ok_or(VoucherError::InvalidKey)?;
+        let nonce_commitment =
+            (nonce_point + alpha * RISTRETTO_BASEPOINT_POINT + beta * key_point).compress();
+
+        let challenge = {
+            let mut t = Voucher::transcript(&serial);
+            t.starsig_domain_sep();
+            t.append_point(b"X", pubkey.as_point());
+            t.append_point(b"R", &nonce_commitment);
+            t.challenge_scalar(b"c")
+        };
+
+        let request = VoucherRequest {
+            pubkey,
+            serial,
+            alpha,
+            nonce_commitment,
+        };
+        Ok((request, challenge + beta))
+    }
+
+    /// Unblinds the issuer's signature and checks the resulting voucher.
+    pub fn finish(self, blinded_signature: Scalar) -> Result<Voucher, VoucherError> {
+        let voucher = Voucher {
+            serial: self.serial,
+            signature: Signature {
+                s: blinded_signature + self.alpha,
+                R: self.nonce_commitment,
+            },
+        };
+        voucher.verify(self.pubkey)?;
+        Ok(voucher)
+    }
+}
+
+impl Voucher {
+    /// Verifies the voucher signature with the issuer's voucher key.
+    pub fn verify(&self, pubkey: VerificationKey) -> Result<(), VoucherError> {
+        self.signature
+            .verify(&mut Self::transcript(&self.serial), pubkey)
+            .map_err(|_| VoucherError::InvalidSignature)
+    }
+
+    /// Encodes the voucher as 96 bytes: the serial number followed by the signature.
+    pub fn to_bytes(&self) -> [u8; 96] {
+        let mut buf = [0u8; 96];
+        buf[..32].copy_from_slice(&self.serial);
+        buf[32..].copy_from_slice(&self.signature.to_bytes());
+        buf
+    }
+
+    /// Decodes the voucher from 96 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, VoucherError> {
+        if bytes.len() != 96 {
+            return Err(VoucherError::InvalidFormat);
+        }
+        let mut serial = [0u8; 32];
+        serial.copy_from_slice(&bytes[..32]);
+        let signature =
+            Signature::from_bytes(&bytes[32..]).map_err(|_| VoucherError::InvalidFormat)?;
+        Ok(Voucher { serial, signature })
+    }
+
+    /// Adds instructions to a program that check the voucher signature
+    /// and log the serial number of the voucher:
+    /// `<serial> <voucher key> contract:1 <log> <signature> signtag`.
+    /// The instructions leave the stack unchanged, but require the VM's last anchor to be set,
+    /// e.g. by a preceding `issue` or `input`.
+    pub fn redeem<'a>(&self, program: &'a mut Program, pubkey: VerificationKey) -> &'a mut Program {
+        program
+            .push(String::Opaque(self.serial.to_vec().into()))
+            .push(Predicate::new(pubkey))
+            .contract(1)
+            .program(Self::redemption_program())
+            .push(String::Opaque(self.signature.to_bytes().to_vec().into()))
+            .signtag()
+    }
+
+    /// Program that continues the `signtag` with the serial number on the stack.
+    fn redemption_program() -> Program {
+        Program::build(|p| {
+            p.log();
+        })
+    }
+
+    /// Transcript of the `signtag` message, as created by the VM.
+    fn transcript(serial: &[u8; 32]) -> Transcript {
+        let mut t = Transcript::new(b"ZkVM.signtag");
+        t.append_message(b"tag", &serial[..]);
+        t.append_message(b"prog", &Self::redemption_program().to_bytes());
+        t
+    }
+}
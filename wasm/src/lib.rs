@@ -0,0 +1,307 @@
+//! WebAssembly bindings for the wallet primitives: key generation,
+//! receivers, transaction building and signing.
+//!
+//! All data crosses the JS boundary either as hex strings (keys, transactions)
+//! or as JSON-compatible objects (receivers, unsigned transactions).
+//! The bindings are thin wrappers around plain Rust functions,
+//! so the same logic can be tested natively.
+#![deny(missing_docs)]
+
+use curve25519_dalek::scalar::Scalar;
+use keytree::{Xprv, Xpub};
+use merlin::Transcript;
+use musig::{Multisignature, Signature};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use wasm_bindgen::prelude::*;
+
+use accounts::{Receiver, ReceiverWitness, Sequence, XprvDerivation, XpubDerivation};
+use zkvm::bulletproofs::BulletproofGens;
+use zkvm::{Anchor, ClearValue, Program, Prover, TxHeader, UnsignedTx, VMError};
+
+/// Errors returned by the wallet bindings.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// Hex-encoded input could not be decoded.
+    #[error("Invalid hex encoding")]
+    InvalidHex,
+
+    /// Extended key is malformed.
+    #[error("Invalid extended key")]
+    InvalidKey,
+
+    /// Flavor is not a canonical scalar.
+    #[error("Invalid flavor")]
+    InvalidFlavor,
+
+    /// The utxos do not cover the payment and the change.
+    #[error("Utxos do not balance the payment and the change")]
+    UnbalancedTx,
+
+    /// JSON value does not match the expected structure.
+    #[error("Invalid JSON: {0}")]
+    InvalidJSON(String),
+
+    /// Transaction could not be built.
+    #[error("ZkVM error: {0}")]
+    VMError(#[from] VMError),
+
+    /// Transaction could not be signed.
+    #[error("Signing error: {0}")]
+    SigningError(String),
+}
+
+/// Unspent output owned by the wallet, together with its secret details.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Utxo {
+    /// Receiver and sequence number from which the utxo was created.
+    pub receiver_witness: ReceiverWitness,
+
+    /// Anchor of the utxo contract.
+    pub anchor: Anchor,
+}
+
+/// Generates a new random root key and returns it hex-encoded.
+pub fn new_xprv() -> String {
+    hex::encode(&Xprv::random(rand::thread_rng()).to_bytes()[..])
+}
+
+/// Returns a hex-encoded xpub for the hex-encoded xprv.
+pub fn xprv_to_xpub(xprv: &str) -> Result<String, Error> {
+    let xprv = decode_xprv(xprv)?;
+    Ok(hex::encode(&xprv.to_xpub().to_bytes()[..]))
+}
+
+/// Creates a receiver for a given sequence number and value.
+pub fn new_receiver(
+    xpub: &str,
+    sequence: Sequence,
+    qty: u64,
+    flv: &str,
+) -> Result<ReceiverWitness, Error> {
+    let xpub = decode_xpub(xpub)?;
+    let flv = decode_scalar(flv).ok_or(Error::InvalidFlavor)?;
+    Ok(ReceiverWitness::new(
+        &xpub,
+        sequence,
+        ClearValue { qty, flv },
+    ))
+}
+
+/// Builds an unsigned transaction that spends the utxos to the payment receiver
+/// and sends the remainder to the change receiver.
+///
+/// All utxos, the payment and the change must have the same flavor.
+pub fn build_payment_tx(
+    utxos: &[Utxo],
+    payment: &Receiver,
+    change: &ReceiverWitness,
+    header: TxHeader,
+) -> Result<UnsignedTx, Error> {
+    let total = utxos.iter().try_fold(0u64, |total, utxo| {
+        total.checked_add(utxo.receiver_witness.receiver.value.qty)
+    });
+    let required = payment.value.qty.checked_add(change.receiver.value.qty);
+    match (total, required) {
+        (Some(t), Some(r)) if t == r => {}
+        _ => return Err(Error::UnbalancedTx),
+    }
+
+    let program = Program::build(|p| {
+        for utxo in utxos.iter() {
+            p.push(utxo.receiver_witness.contract(utxo.anchor));
+            p.input();
+            p.signtx();
+        }
+
+        let pmnt = payment.blinded_value();
+        p.push(pmnt.qty);
+        p.push(pmnt.flv);
+
+        let change_value = change.receiver.blinded_value();
+        p.push(change_value.qty);
+        p.push(change_value.flv);
+
+        p.cloak(utxos.len(), 2);
+
+        p.push(payment.predicate());
+        p.output(1);
+
+        p.push(change.receiver.predicate());
+        p.output(1);
+    });
+
+    let bp_gens = BulletproofGens::new(256, 1);
+    Ok(Prover::build_tx(program, header, &bp_gens)?)
+}
+
+/// Signs the transaction with the keys derived from the xprv at the given sequence numbers,
+/// and returns the hex-encoded signed transaction.
+///
+/// Sequence numbers must be listed in the same order as the inputs of the transaction.
+pub fn sign_payment_tx(
+    xprv: &str,
+    utx: UnsignedTx,
+    sequences: &[Sequence],
+) -> Result<String, Error> {
+    let xprv = decode_xprv(xprv)?;
+    let signing_keys = sequences
+        .iter()
+        .map(|seq| xprv.key_at_sequence(*seq))
+        .collect::<Vec<_>>();
+
+    let mut signtx_transcript = Transcript::new(b"ZkVM.signtx");
+    signtx_transcript.append_message(b"txid", &utx.txid.0);
+    let sig = Signature::sign_multi(
+        &signing_keys[..],
+        utx.signing_instructions
+            .iter()
+            .map(|(p, m)| (p.verification_key(), m))
+            .collect(),
+        &mut signtx_transcript,
+    )
+    .map_err(|e| Error::SigningError(e.to_string()))?;
+
+    Ok(hex::encode(utx.sign(sig).to_bytes()))
+}
+
+fn decode_xprv(xprv: &str) -> Result<Xprv, Error> {
+    let bytes = hex::decode(xprv).map_err(|_| Error::InvalidHex)?;
+    Xprv::from_bytes(&bytes).ok_or(Error::InvalidKey)
+}
+
+fn decode_xpub(xpub: &str) -> Result<Xpub, Error> {
+    let bytes = hex::decode(xpub).map_err(|_| Error::InvalidHex)?;
+    Xpub::from_bytes(&bytes).ok_or(Error::InvalidKey)
+}
+
+fn decode_scalar(s: &str) -> Option<Scalar> {
+    let bytes = hex::decode(s).ok()?;
+    if bytes.len() != 32 {
+        return None;
+    }
+    let mut buf = [0u8; 32];
+    buf.copy_from_slice(&bytes);
+    Scalar::from_canonical_bytes(buf)
+}
+
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsValue> {
+    JsValue::from_serde(value).map_err(|e| js_error(Error::InvalidJSON(e.to_string())))
+}
+
+fn from_js<T: for<'a> Deserialize<'a>>(value: &JsValue) -> Result<T, JsValue> {
+    value
+        .into_serde()
+        .map_err(|e| js_error(Error::InvalidJSON(e.to_string())))
+}
+
+fn js_error(err: Error) -> JsValue {
+    JsValue::from_str(&err.to_string())
+}
+
+/// Generates a new random hex-encoded xprv.
+#[wasm_bindgen(js_name = generateXprv)]
+pub fn generate_xprv() -> String {
+    new_xprv()
+}
+
+/// Returns a hex-encoded xpub for the hex-encoded xprv.
+#[wasm_bindgen(js_name = xpubFromXprv)]
+pub fn xpub_from_xprv(xprv: &str) -> Result<String, JsValue> {
+    xprv_to_xpub(xprv).map_err(js_error)
+}
+
+/// Creates a receiver witness object for the hex-encoded xpub, sequence number,
+/// quantity and hex-encoded flavor.
+#[wasm_bindgen(js_name = createReceiver)]
+pub fn create_receiver(xpub: &str, sequence: u64, qty: u64, flv: &str) -> Result<JsValue, JsValue> {
+    to_js(&new_receiver(xpub, sequence, qty, flv).map_err(js_error)?)
+}
+
+/// Builds an unsigned transaction object from an array of utxo objects,
+/// a payment receiver object, a change receiver witness object and a time window.
+#[wasm_bindgen(js_name = buildTx)]
+pub fn build_tx(
+    utxos: &JsValue,
+    payment: &JsValue,
+    change: &JsValue,
+    mintime_ms: u64,
+    maxtime_ms: u64,
+) -> Result<JsValue, JsValue> {
+    let utxos: Vec<Utxo> = from_js(utxos)?;
+    let payment: Receiver = from_js(payment)?;
+    let change: ReceiverWitness = from_js(change)?;
+    let header = TxHeader {
+        version: 1u64,
+        mintime_ms,
+        maxtime_ms,
+    };
+    to_js(&build_payment_tx(&utxos, &payment, &change, header).map_err(js_error)?)
+}
+
+/// Signs the unsigned transaction object with keys derived from the hex-encoded xprv
+/// at the given sequence numbers, and returns a hex-encoded transaction.
+#[wasm_bindgen(js_name = signTx)]
+pub fn sign_tx(xprv: &str, utx: &JsValue, sequences: Vec<u64>) -> Result<String, JsValue> {
+    let utx: UnsignedTx = from_js(utx)?;
+    sign_payment_tx(xprv, utx, &sequences).map_err(js_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_and_sign_payment() {
+        let xprv = new_xprv();
+        let xpub = xprv_to_xpub(&xprv).unwrap();
+        let flv = hex::encode(Scalar::zero().as_bytes());
+
+        let utxos = vec![
+            Utxo {
+                receiver_witness: new_receiver(&xpub, 0, 10, &flv).unwrap(),
+                anchor: Anchor::from_raw_bytes([0u8; 32]),
+            },
+            Utxo {
+                receiver_witness: new_receiver(&xpub, 1, 5, &flv).unwrap(),
+                anchor: Anchor::from_raw_bytes([1u8; 32]),
+            },
+        ];
+        let payment = new_receiver(&xpub, 2, 12, &flv).unwrap().receiver;
+        let change = new_receiver(&xpub, 3, 3, &flv).unwrap();
+        let header = TxHeader {
+            version: 1u64,
+            mintime_ms: 0u64,
+            maxtime_ms: u64::max_value(),
+        };
+
+        let utx = build_payment_tx(&utxos, &payment, &change, header).unwrap();
+        let tx = sign_payment_tx(&xprv, utx, &[0, 1]).unwrap();
+        let tx = zkvm::Tx::from_bytes(&hex::decode(tx).unwrap()).unwrap();
+        assert!(tx.verify(&BulletproofGens::new(256, 1)).is_ok());
+    }
+
+    #[test]
+    fn unbalanced_payment() {
+        let xprv = new_xprv();
+        let xpub = xprv_to_xpub(&xprv).unwrap();
+        let flv = hex::encode(Scalar::zero().as_bytes());
+
+        let utxos = vec![Utxo {
+            receiver_witness: new_receiver(&xpub, 0, 10, &flv).unwrap(),
+            anchor: Anchor::from_raw_bytes([0u8; 32]),
+        }];
+        let payment = new_receiver(&xpub, 1, 12, &flv).unwrap().receiver;
+        let change = new_receiver(&xpub, 2, 0, &flv).unwrap();
+        let header = TxHeader {
+            version: 1u64,
+            mintime_ms: 0u64,
+            maxtime_ms: u64::max_value(),
+        };
+
+        match build_payment_tx(&utxos, &payment, &change, header) {
+            Err(Error::UnbalancedTx) => {}
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,203 @@
+#[macro_use]
+extern crate criterion;
+use criterion::Criterion;
+
+use bulletproofs::BulletproofGens;
+use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::Identity;
+use merlin::Transcript;
+use musig::{Multisignature, Signature};
+use zkvm::{
+    Anchor, Commitment, Contract, PortableItem, Predicate, PredicateTree, Program, Prover,
+    ProverContext, Tx, TxHeader, UnsignedTx, Value,
+};
+
+fn make_output(qty: u64, flv: Scalar, predicate: Predicate) -> Contract {
+    Contract {
+        predicate,
+        payload: vec![PortableItem::Value(Value {
+            qty: Commitment::blinded(qty),
+            flv: Commitment::blinded(flv),
+        })],
+        anchor: Anchor::from_raw_bytes([0u8; 32]),
+    }
+}
+
+fn make_predicate(privkey: u64) -> Predicate {
+    Predicate::with_witness(Scalar::from(privkey))
+}
+
+/// Spends two inputs into two outputs of the same flavor.
+fn payment_program() -> Program {
+    let flv = Scalar::from(1u64);
+    Program::build(|p| {
+        p.push(make_output(6, flv, make_predicate(1)))
+            .input()
+            .signtx()
+            .push(make_output(4, flv, make_predicate(2)))
+            .input()
+            .signtx()
+            .push(Commitment::blinded(9u64))
+            .push(Commitment::blinded(flv))
+            .push(Commitment::blinded(1u64))
+            .push(Commitment::blinded(flv))
+            .cloak(2, 2)
+            .push(make_predicate(3))
+            .output(1)
+            .push(make_predicate(4))
+            .output(1);
+    })
+}
+
+/// Spends an input via the program branch of its taproot predicate.
+fn taproot_program() -> Program {
+    let (qty, flv) = (101u64, Scalar::from(1u64));
+    let spend_prog = Program::build(|p| {
+        p.push(Commitment::blinded(qty))
+            .push(Commitment::blinded(flv))
+            .cloak(1, 1)
+            .push(make_predicate(2))
+            .output(1);
+    });
+    let tree = PredicateTree::new(Some(make_predicate(1)), vec![spend_prog], [0u8; 32]).unwrap();
+    let (call_proof, call_prog) = tree.create_callproof(0).unwrap();
+    let prev_output = make_output(qty, flv, Predicate::tree(tree));
+
+    Program::build(|p| {
+        p.push(prev_output)
+            .input()
+            .push(zkvm::String::Opaque(call_proof.to_bytes().into()))
+            .program(call_prog)
+            .call();
+    })
+}
+
+/// Pushes and drops `n` opaque strings before making a payment.
+fn data_program(n: usize) -> Program {
+    let mut instructions = Program::build(|p| {
+        for i in 0..n {
+            p.push(zkvm::String::Opaque(vec![i as u8; 64].into()))
+                .drop();
+        }
+    })
+    .to_vec();
+    instructions.extend(payment_program().to_vec());
+    Program::from_vec(instructions)
+}
+
+fn tx_header() -> TxHeader {
+    TxHeader {
+        version: 0u64,
+        mintime_ms: 0u64,
+        maxtime_ms: 0u64,
+    }
+}
+
+fn build_tx(program: Program, bp_gens: &BulletproofGens) -> Tx {
+    sign_tx(Prover::build_tx(program, tx_header(), bp_gens).unwrap())
+}
+
+fn sign_tx(utx: UnsignedTx) -> Tx {
+    if utx.signing_instructions.is_empty() {
+        let sig = Signature {
+            R: CompressedRistretto::identity(),
+            s: Scalar::zero(),
+        };
+        return utx.sign(sig);
+    }
+
+    let privkeys: Vec<Scalar> = utx
+        .signing_instructions
+        .iter()
+        .map(|(p, _)| *p.verification_key_witness::<Scalar>().unwrap())
+        .collect();
+    let mut signtx_transcript = Transcript::new(b"ZkVM.signtx");
+    signtx_transcript.append_message(b"txid", &utx.txid.0);
+    let sig = Signature::sign_multi(
+        privkeys,
+        utx.signing_instructions
+            .iter()
+            .map(|(p, m)| (p.verification_key(), m))
+            .collect(),
+        &mut signtx_transcript,
+    )
+    .unwrap();
+
+    utx.sign(sig)
+}
+
+fn build_payment_tx(c: &mut Criterion) {
+    c.bench_function("ZkVM: build 2-in-2-out payment", move |b| {
+        let bp_gens = BulletproofGens::new(256, 1);
+        let program = payment_program();
+        b.iter(|| build_tx(program.clone(), &bp_gens))
+    });
+}
+
+fn build_payment_tx_fresh_gens(c: &mut Criterion) {
+    c.bench_function(
+        "ZkVM: build 2-in-2-out payment with new generators",
+        move |b| {
+            let program = payment_program();
+            b.iter(|| build_tx(program.clone(), &BulletproofGens::new(256, 1)))
+        },
+    );
+}
+
+fn build_payment_tx_with_context(c: &mut Criterion) {
+    c.bench_function(
+        "ZkVM: build 2-in-2-out payment with ProverContext",
+        move |b| {
+            let mut ctx = ProverContext::new(256);
+            let program = payment_program();
+            b.iter(|| sign_tx(ctx.build_tx(program.clone(), tx_header()).unwrap()))
+        },
+    );
+}
+
+fn verify_payment_tx(c: &mut Criterion) {
+    c.bench_function("ZkVM: verify 2-in-2-out payment", move |b| {
+        let bp_gens = BulletproofGens::new(256, 1);
+        let tx = build_tx(payment_program(), &bp_gens);
+        b.iter(|| tx.verify(&bp_gens).unwrap())
+    });
+}
+
+fn build_taproot_tx(c: &mut Criterion) {
+    c.bench_function("ZkVM: build taproot call", move |b| {
+        let bp_gens = BulletproofGens::new(256, 1);
+        let program = taproot_program();
+        b.iter(|| build_tx(program.clone(), &bp_gens))
+    });
+}
+
+fn verify_taproot_tx(c: &mut Criterion) {
+    c.bench_function("ZkVM: verify taproot call", move |b| {
+        let bp_gens = BulletproofGens::new(256, 1);
+        let tx = build_tx(taproot_program(), &bp_gens);
+        b.iter(|| tx.verify(&bp_gens).unwrap())
+    });
+}
+
+fn verify_data_tx(c: &mut Criterion) {
+    c.bench_function("ZkVM: verify payment with 1000 dropped strings", move |b| {
+        let bp_gens = BulletproofGens::new(256, 1);
+        let tx = build_tx(data_program(1000), &bp_gens);
+        b.iter(|| tx.verify(&bp_gens).unwrap())
+    });
+}
+
+criterion_group! {
+    name = zkvm_tx;
+    config = Criterion::default().sample_size(10);
+    targets = build_payment_tx,
+        build_payment_tx_fresh_gens,
+        build_payment_tx_with_context,
+        verify_payment_tx,
+        build_taproot_tx,
+        verify_taproot_tx,
+        verify_data_tx,
+}
+
+criterion_main!(zkvm_tx);
@@ -0,0 +1,104 @@
+//! Arena of byte buffers for the programs parsed during verification.
+//!
+//! Pushed strings share the buffer of the transaction program (see `String::Opaque`),
+//! but the `program` payloads are parsed into their own buffers.
+//! The verifier allocates them from the arena, and the VM returns the buffers
+//! of the dropped programs, so most of them reuse a buffer
+//! instead of going to the global allocator.
+//! The arena lives as long as the verification of a single transaction.
+
+use crate::encoding::{ReadError, Reader};
+
+/// Maximum number of free buffers kept by the arena.
+const MAX_FREE_BUFFERS: usize = 64;
+
+/// Pool of recycled byte buffers.
+#[derive(Debug, Default)]
+pub(crate) struct Arena {
+    free: Vec<Vec<u8>>,
+}
+
+impl Arena {
+    /// Creates an empty arena.
+    pub(crate) fn new() -> Self {
+        Arena { free: Vec::new() }
+    }
+
+    /// Reads `len` bytes into a buffer taken from the arena.
+    pub(crate) fn read_bytes(
+        &mut self,
+        reader: &mut impl Reader,
+        len: usize,
+    ) -> Result<Vec<u8>, ReadError> {
+        if reader.remaining_bytes() < len {
+            // do not take a buffer if we don't have enough data.
+            return Err(ReadError::InsufficientBytes);
+        }
+        let mut buf = self.take(len);
+        buf.resize(len, 0u8);
+        reader.read(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Returns the buffer to the arena for reuse.
+    pub(crate) fn recycle(&mut self, mut buf: Vec<u8>) {
+        if buf.capacity() == 0 || self.free.len() >= MAX_FREE_BUFFERS {
+            return;
+        }
+        buf.clear();
+        self.free.push(buf);
+    }
+
+    /// Takes the most recently recycled buffer with enough capacity,
+    /// or allocates a new one.
+    fn take(&mut self, len: usize) -> Vec<u8> {
+        match self.free.iter().rposition(|buf| buf.capacity() >= len) {
+            Some(i) => self.free.swap_remove(i),
+            None => Vec::with_capacity(len),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_recycled_buffers() {
+        let mut arena = Arena::new();
+        let mut data = &[1u8, 2, 3, 4, 5][..];
+
+        let a = arena.read_bytes(&mut data, 3).unwrap();
+        assert_eq!(a, vec![1, 2, 3]);
+        let ptr = a.as_ptr();
+        arena.recycle(a);
+
+        let b = arena.read_bytes(&mut data, 2).unwrap();
+        assert_eq!(b, vec![4, 5]);
+        assert_eq!(b.as_ptr(), ptr);
+        assert_eq!(data.len(), 0);
+    }
+
+    #[test]
+    fn insufficient_bytes() {
+        let mut arena = Arena::new();
+        arena.recycle(vec![0u8; 8]);
+        let mut data = &[1u8, 2][..];
+        match arena.read_bytes(&mut data, 3) {
+            Err(ReadError::InsufficientBytes) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(data.len(), 2);
+        assert_eq!(arena.free.len(), 1);
+    }
+
+    #[test]
+    fn bounded_free_list() {
+        let mut arena = Arena::new();
+        for _ in 0..MAX_FREE_BUFFERS + 10 {
+            arena.recycle(Vec::with_capacity(4));
+        }
+        arena.recycle(Vec::new());
+        assert_eq!(arena.free.len(), MAX_FREE_BUFFERS);
+    }
+}
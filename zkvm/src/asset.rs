@@ -0,0 +1,233 @@
+//! Asset metadata commitments and registry records.
+//!
+//! An issuer may bind human-readable asset metadata (name, decimals, issuer URL)
+//! into the flavor by passing a commitment to the metadata as the `metadata` string
+//! of the `issue` instruction. The metadata itself stays off-chain: registries publish
+//! `AssetRecord`s, and wallets check that the record matches the flavor they hold
+//! before displaying the alias.
+
+use curve25519_dalek::scalar::Scalar;
+use merlin::Transcript;
+use musig::VerificationKey;
+
+use crate::encoding::*;
+use crate::errors::VMError;
+use crate::predicate::Predicate;
+use crate::transcript::TranscriptProtocol;
+use crate::types::{String, Value};
+
+/// Version prefix of the issuance metadata string that carries an asset metadata commitment.
+pub const ASSET_METADATA_VERSION: u8 = 1;
+
+/// Version of the asset registry record format.
+pub const ASSET_RECORD_VERSION: u8 = 1;
+
+/// Human-readable description of an asset.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssetMetadata {
+    /// Name (alias) of the asset, e.g. "USD".
+    pub name: std::string::String,
+    /// Number of decimal places in the display amount.
+    pub decimals: u8,
+    /// Hash of the issuer's URL, see `AssetMetadata::hash_issuer_url`.
+    pub issuer_url_hash: [u8; 32],
+}
+
+/// Registry record that binds the asset metadata to the issuance predicate,
+/// and therefore to the flavor of the asset.
+#[derive(Clone, Debug)]
+pub struct AssetRecord {
+    /// Predicate used in the `issue` instruction.
+    pub issuance_predicate: Predicate,
+    /// Metadata committed to in the `issue` instruction.
+    pub metadata: AssetMetadata,
+}
+
+impl AssetMetadata {
+    /// Creates the asset metadata, hashing the issuer's URL.
+    pub fn new(name: impl Into<std::string::String>, decimals: u8, issuer_url: &str) -> Self {
+        AssetMetadata {
+            name: name.into(),
+            decimals,
+            issuer_url_hash: Self::hash_issuer_url(issuer_url),
+        }
+    }
+
+    /// Hashes the issuer's URL.
+    pub fn hash_issuer_url(url: &str) -> [u8; 32] {
+        let mut t = Transcript::new(b"ZkVM.asset_issuer_url");
+        t.append_message(b"url", url.as_bytes());
+        t.challenge_u8x32(b"hash")
+    }
+
+    /// Computes the commitment to the metadata.
+    pub fn commitment(&self) -> [u8; 32] {
+        let mut t = Transcript::new(b"ZkVM.asset_metadata");
+        t.append_message(b"name", self.name.as_bytes());
+        t.append_u64(b"decimals", self.decimals as u64);
+        t.append_message(b"issuer_url_hash", &self.issuer_url_hash);
+        t.challenge_u8x32(b"commitment")
+    }
+
+    /// Returns the `metadata` string for the `issue` instruction
+    /// that commits to this asset metadata.
+    pub fn to_issue_metadata(&self) -> String {
+        let mut data = Vec::with_capacity(33);
+        data.push(ASSET_METADATA_VERSION);
+        data.extend_from_slice(&self.commitment());
+        String::Opaque(data.into())
+    }
+
+    /// Extracts the asset metadata commitment from the `metadata` string of the `issue` instruction.
+    /// Returns `None` if the string does not carry a commitment.
+    pub fn commitment_from_issue_metadata(metadata: &String) -> Option<[u8; 32]> {
+        let bytes = metadata.encode_to_vec();
+        if bytes.len() != 33 || bytes[0] != ASSET_METADATA_VERSION {
+            return None;
+        }
+        let mut commitment = [0u8; 32];
+        commitment.copy_from_slice(&bytes[1..]);
+        Some(commitment)
+    }
+
+    /// Computes the flavor of the asset issued with this metadata under a given predicate.
+    pub fn flavor(&self, issuance_predicate: &Predicate) -> Scalar {
+        Value::issue_flavor(issuance_predicate, self.to_issue_metadata())
+    }
+}
+
+impl AssetRecord {
+    /// Creates a registry record.
+    pub fn new(issuance_predicate: Predicate, metadata: AssetMetadata) -> Self {
+        AssetRecord {
+            issuance_predicate,
+            metadata,
+        }
+    }
+
+    /// Computes the flavor of the asset described by the record.
+    pub fn flavor(&self) -> Scalar {
+        self.metadata.flavor(&self.issuance_predicate)
+    }
+
+    /// Checks that the record describes the asset with a given flavor.
+    pub fn verify_flavor(&self, flavor: &Scalar) -> bool {
+        &self.flavor() == flavor
+    }
+
+    /// Checks that the claimed alias is the name of the asset with a given flavor.
+    pub fn verify_alias(&self, alias: &str, flavor: &Scalar) -> bool {
+        self.metadata.name == alias && self.verify_flavor(flavor)
+    }
+
+    /// Serializes the record to a byte array.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.encode_to_vec()
+    }
+
+    /// Deserializes the record from a byte slice.
+    pub fn from_bytes(mut slice: &[u8]) -> Result<AssetRecord, VMError> {
+        slice
+            .read_all(|r| Self::decode(r))
+            .map_err(|_| VMError::InvalidFormat)
+    }
+}
+
+impl Encodable for AssetRecord {
+    fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
+        w.write_u8(b"version", ASSET_RECORD_VERSION)?;
+        w.write_point(b"predicate", &self.issuance_predicate.to_point())?;
+        w.write_u8(b"decimals", self.metadata.decimals)?;
+        w.write(b"issuer_url_hash", &self.metadata.issuer_url_hash)?;
+        w.write_size(b"n", self.metadata.name.len())?;
+        w.write(b"name", self.metadata.name.as_bytes())
+    }
+}
+
+impl ExactSizeEncodable for AssetRecord {
+    fn encoded_size(&self) -> usize {
+        1 + 32 + 1 + 32 + 4 + self.metadata.name.len()
+    }
+}
+
+impl Decodable for AssetRecord {
+    fn decode(reader: &mut impl Reader) -> Result<Self, ReadError> {
+        //     Record  =  0x01  ||  Predicate  ||  Decimals  ||  URLHash  ||  LE32(len)  ||  Name
+        //  Predicate  =  <32 bytes>
+        //   Decimals  =  <1 byte>
+        //    URLHash  =  <32 bytes>
+        //       Name  =  <len bytes of UTF-8>
+        if reader.read_u8()? != ASSET_RECORD_VERSION {
+            return Err(ReadError::InvalidFormat);
+        }
+        let predicate = Predicate::new(VerificationKey::from_compressed(reader.read_point()?));
+        let decimals = reader.read_u8()?;
+        let issuer_url_hash = reader.read_u8x32()?;
+        let len = reader.read_size()?;
+        let name = std::string::String::from_utf8(reader.read_bytes(len)?)
+            .map_err(|_| ReadError::InvalidFormat)?;
+        Ok(AssetRecord {
+            issuance_predicate: predicate,
+            metadata: AssetMetadata {
+                name,
+                decimals,
+                issuer_url_hash,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn usd_record() -> AssetRecord {
+        let issuer = Predicate::with_witness(Scalar::from(1u64));
+        AssetRecord::new(issuer, AssetMetadata::new("USD", 2, "https://example.com"))
+    }
+
+    #[test]
+    fn record_roundtrip() {
+        let record = usd_record();
+        let bytes = record.to_bytes();
+        assert_eq!(bytes.len(), record.encoded_size());
+
+        let decoded = AssetRecord::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.metadata, record.metadata);
+        assert_eq!(decoded.flavor(), record.flavor());
+
+        assert!(AssetRecord::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn alias_verification() {
+        let record = usd_record();
+        let flavor = record.flavor();
+        assert!(record.verify_alias("USD", &flavor));
+        assert!(!record.verify_alias("EUR", &flavor));
+
+        let mut fake = record.clone();
+        fake.metadata.decimals = 3;
+        assert!(!fake.verify_alias("USD", &flavor));
+
+        let plain = Value::issue_flavor(&record.issuance_predicate, String::default());
+        assert!(!record.verify_flavor(&plain));
+    }
+
+    #[test]
+    fn issue_metadata_commitment() {
+        let metadata = usd_record().metadata;
+        let string = metadata.to_issue_metadata();
+        assert_eq!(
+            AssetMetadata::commitment_from_issue_metadata(&string),
+            Some(metadata.commitment())
+        );
+        assert_eq!(
+            AssetMetadata::commitment_from_issue_metadata(&String::Opaque(Bytes::from_static(
+                b"USD"
+            ))),
+            None
+        );
+    }
+}
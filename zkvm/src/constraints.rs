@@ -2,12 +2,14 @@
 //! Commitments, Variables, Expressions and Constraints.
 
 use bulletproofs::{r1cs, r1cs::ConstraintSystem, PedersenGens};
-use curve25519_dalek::ristretto::CompressedRistretto;
+use core::iter::FromIterator;
+use core::ops::{Add, Neg};
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoBasepointTable, RistrettoPoint};
 use curve25519_dalek::scalar::Scalar;
+use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
-use std::iter::FromIterator;
-use std::ops::{Add, Neg};
-use subtle::{ConditionallySelectable, ConstantTimeEq};
+use std::collections::hash_map::{Entry, HashMap};
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq};
 
 use crate::encoding::*;
 use crate::errors::VMError;
@@ -24,6 +26,10 @@ pub struct Variable {
 
 /// Expression is a linear combination of high-level variables (`var`),
 /// low-level variables (`alloc`) and constants.
+///
+/// Expressions built with `add` are kept in a normalized form:
+/// each variable (including the constant term) appears at most once,
+/// and terms with zero weights are removed.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Expression {
     /// Represents a constant. Operations on constants produce constants.
@@ -129,6 +135,20 @@ impl Constraint {
         }
     }
 
+    /// Creates an equality constraint like `eq`, and also returns Constraint::Cleartext(bool)
+    /// if the variable terms of the difference of the arguments cancel out.
+    pub fn eq_folded(e1: Expression, e2: Expression) -> Self {
+        match Constraint::eq(e1, e2) {
+            Constraint::Secret(SecretConstraint::Eq(e1, e2)) => {
+                match (e1.clone() + -e2.clone()).cleartext_value() {
+                    Some(diff) => Constraint::Cleartext(diff == Scalar::zero()),
+                    None => Constraint::Secret(SecretConstraint::Eq(e1, e2)),
+                }
+            }
+            constraint => constraint,
+        }
+    }
+
     /// Creates a conjunction constraint.
     ///
     /// Applies _guaranteed optimization_:
@@ -176,14 +196,14 @@ impl Constraint {
     /// based on the assignments to the variables inside the underlying Expressions.
     /// Returns `None` if any underlying variable does not have an assignment.
     pub fn assignment(&self) -> Option<bool> {
-        self.eval()
+        self.eval().map(bool::from)
     }
 
     /// Evaluates the constraint using the optional scalar witness data in the underlying `Expression`s.
     /// Returns None if the witness is missing in any expression.
-    fn eval(&self) -> Option<bool> {
+    fn eval(&self) -> Option<Choice> {
         match self {
-            Constraint::Cleartext(flag) => Some(*flag),
+            Constraint::Cleartext(flag) => Some(Choice::from(*flag as u8)),
             Constraint::Secret(sc) => sc.eval(),
         }
     }
@@ -263,16 +283,67 @@ impl SecretConstraint {
 
     /// Evaluates the constraint using the optional scalar witness data in the underlying `Expression`s.
     /// Returns None if the witness is missing in any expression.
-    fn eval(&self) -> Option<bool> {
+    /// The witness values are secret, so the evaluation does not branch on them.
+    fn eval(&self) -> Option<Choice> {
         match self {
-            SecretConstraint::Eq(e1, e2) => e1.eval().and_then(|x| e2.eval().map(|y| x == y)),
-            SecretConstraint::And(c1, c2) => c1.eval().and_then(|x| c2.eval().map(|y| x && y)),
-            SecretConstraint::Or(c1, c2) => c1.eval().and_then(|x| c2.eval().map(|y| x || y)),
+            SecretConstraint::Eq(e1, e2) => e1.eval().and_then(|x| {
+                e2.eval().map(|y| {
+                    let result = x.ct_eq(&y);
+                    #[cfg(feature = "ct-review")]
+                    debug_assert_eq!(bool::from(result), x.to_scalar() == y.to_scalar());
+                    result
+                })
+            }),
+            SecretConstraint::And(c1, c2) => c1.eval().and_then(|x| c2.eval().map(|y| x & y)),
+            SecretConstraint::Or(c1, c2) => c1.eval().and_then(|x| c2.eval().map(|y| x | y)),
             SecretConstraint::Not(c1) => c1.eval().map(|x| !x),
         }
     }
 }
 
+impl Constraint {
+    /// Returns a byte representation of the structure of the secret constraint,
+    /// without the witness data, so the prover and the verifier
+    /// recognize the same constraints as identical.
+    /// Returns None for a cleartext constraint.
+    pub(crate) fn structural_key(&self) -> Option<Vec<u8>> {
+        match self {
+            Constraint::Cleartext(_) => None,
+            Constraint::Secret(sc) => {
+                let mut key = Vec::new();
+                sc.write_structure(&mut key);
+                Some(key)
+            }
+        }
+    }
+}
+
+impl SecretConstraint {
+    fn write_structure(&self, key: &mut Vec<u8>) {
+        match self {
+            SecretConstraint::Eq(a, b) => {
+                key.push(0);
+                a.write_structure(key);
+                b.write_structure(key);
+            }
+            SecretConstraint::And(a, b) => {
+                key.push(1);
+                a.write_structure(key);
+                b.write_structure(key);
+            }
+            SecretConstraint::Or(a, b) => {
+                key.push(2);
+                a.write_structure(key);
+                b.write_structure(key);
+            }
+            SecretConstraint::Not(a) => {
+                key.push(3);
+                a.write_structure(key);
+            }
+        }
+    }
+}
+
 impl Encodable for Commitment {
     fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
         w.write_point(b"commitment", &self.to_point())
@@ -300,10 +371,21 @@ impl Commitment {
         }))
     }
 
-    /// Creates an open commitment with a random blinding factor.
+    /// Creates an open commitment with a random blinding factor drawn from the thread RNG.
+    /// Use `blinded_with_rng` to build the transactions reproducibly
+    /// or on the targets without the `os-entropy` feature.
+    #[cfg(feature = "os-entropy")]
     pub fn blinded<T: Into<ScalarWitness>>(x: T) -> Self {
+        Self::blinded_with_rng(x, &mut rand::thread_rng())
+    }
+
+    /// Creates an open commitment with a random blinding factor drawn from a given RNG.
+    pub fn blinded_with_rng<T: Into<ScalarWitness>, R: RngCore + CryptoRng>(
+        x: T,
+        rng: &mut R,
+    ) -> Self {
         Commitment::Open(Box::new(CommitmentWitness {
-            blinding: Scalar::random(&mut rand::thread_rng()),
+            blinding: Scalar::random(rng),
             value: x.into(),
         }))
     }
@@ -333,12 +415,131 @@ impl Commitment {
             Commitment::Open(w) => Some(w.value),
         }
     }
+
+    /// Adds two commitments, producing a commitment to the sum of the values
+    /// blinded with the sum of the blinding factors.
+    /// The result is open only if both commitments are open.
+    /// Fails if a closed commitment is not a valid point.
+    pub fn add(&self, other: &Commitment) -> Result<Commitment, VMError> {
+        match (self, other) {
+            (Commitment::Open(a), Commitment::Open(b)) => Ok(a.add(b).into()),
+            (a, b) => Ok(Commitment::Closed(
+                (a.decompress()? + b.decompress()?).compress(),
+            )),
+        }
+    }
+
+    /// Multiplies the commitment by a cleartext scalar, producing a commitment
+    /// to the scaled value blinded with the scaled blinding factor.
+    /// Fails if a closed commitment is not a valid point.
+    pub fn mul<T: Into<ScalarWitness>>(&self, factor: T) -> Result<Commitment, VMError> {
+        let factor = factor.into();
+        match self {
+            Commitment::Open(w) => Ok(w.mul(factor).into()),
+            Commitment::Closed(_) => Ok(Commitment::Closed(
+                (self.decompress()? * factor.to_scalar()).compress(),
+            )),
+        }
+    }
+
+    /// Adds fresh randomness to the blinding factor of the commitment.
+    /// Returns the reblinded commitment and the added blinding factor,
+    /// which is needed to open the closed commitment or to prove that both commitments
+    /// hide the same value.
+    /// Fails if a closed commitment is not a valid point.
+    pub fn reblind<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+    ) -> Result<(Commitment, Scalar), VMError> {
+        let delta = Scalar::random(rng);
+        let reblinded = self.add(&Commitment::blinded_with_factor(0u64, delta))?;
+        Ok((reblinded, delta))
+    }
+
+    fn decompress(&self) -> Result<RistrettoPoint, VMError> {
+        match self {
+            Commitment::Closed(x) => x.decompress().ok_or(VMError::InvalidPoint),
+            Commitment::Open(w) => Ok(w.to_ristretto()),
+        }
+    }
 }
 
 impl CommitmentWitness {
+    /// Creates a witness for a given value and blinding factor.
+    pub fn new<T: Into<ScalarWitness>>(value: T, blinding: Scalar) -> Self {
+        CommitmentWitness {
+            value: value.into(),
+            blinding,
+        }
+    }
+
+    /// Returns the committed value.
+    pub fn value(&self) -> ScalarWitness {
+        self.value
+    }
+
+    /// Returns the blinding factor.
+    pub fn blinding(&self) -> Scalar {
+        self.blinding
+    }
+
+    /// Adds two witnesses, producing a witness for the sum of the commitments.
+    pub fn add(&self, other: &CommitmentWitness) -> CommitmentWitness {
+        CommitmentWitness {
+            value: self.value + other.value,
+            blinding: self.blinding + other.blinding,
+        }
+    }
+
+    /// Multiplies the witness by a cleartext scalar, producing a witness for the scaled commitment.
+    pub fn mul<T: Into<ScalarWitness>>(&self, factor: T) -> CommitmentWitness {
+        let factor = factor.into();
+        CommitmentWitness {
+            value: self.value * factor,
+            blinding: self.blinding * factor.to_scalar(),
+        }
+    }
+
+    /// Adds fresh randomness to the blinding factor.
+    /// Returns the reblinded witness and the added blinding factor.
+    pub fn reblind<R: RngCore + CryptoRng>(&self, rng: &mut R) -> (CommitmentWitness, Scalar) {
+        let delta = Scalar::random(rng);
+        let reblinded = CommitmentWitness {
+            value: self.value,
+            blinding: self.blinding + delta,
+        };
+        (reblinded, delta)
+    }
+
     fn to_point(&self) -> CompressedRistretto {
+        self.to_ristretto().compress()
+    }
+
+    fn to_ristretto(&self) -> RistrettoPoint {
+        let value: Scalar = self.value.into();
+        COMMITMENT_TABLES.with(|tables| &tables.value * &value + &tables.blinding * &self.blinding)
+    }
+}
+
+thread_local! {
+    // Hashing the Pedersen generators to the curve costs more than the commitment itself,
+    // so the open commitments reuse the tables computed once per thread.
+    static COMMITMENT_TABLES: CommitmentTables = CommitmentTables::new();
+}
+
+/// Precomputed tables for the Pedersen generators `B` and `B_blinding`.
+struct CommitmentTables {
+    value: RistrettoBasepointTable,
+    blinding: RistrettoBasepointTable,
+}
+
+impl CommitmentTables {
+    fn new() -> Self {
         let gens = PedersenGens::default();
-        gens.commit(self.value.into(), self.blinding).compress()
+        CommitmentTables {
+            value: RistrettoBasepointTable::create(&gens.B),
+            blinding: RistrettoBasepointTable::create(&gens.B_blinding),
+        }
     }
 }
 
@@ -404,6 +605,50 @@ impl Expression {
         }
     }
 
+    fn write_structure(&self, key: &mut Vec<u8>) {
+        match self {
+            Expression::Constant(a) => {
+                key.push(0);
+                key.extend_from_slice(a.to_scalar().as_bytes());
+            }
+            Expression::LinearCombination(terms, _) => {
+                key.push(1);
+                key.extend_from_slice(&(terms.len() as u64).to_le_bytes());
+                for (var, coeff) in terms.iter() {
+                    let (tag, index) = variable_key(var);
+                    key.push(tag);
+                    key.extend_from_slice(&(index as u64).to_le_bytes());
+                    key.extend_from_slice(coeff.as_bytes());
+                }
+            }
+        }
+    }
+
+    /// Returns the value of the expression if it does not depend on any variables:
+    /// either it is a constant, or the terms with each variable add up to zero.
+    /// The result depends only on the structure of the expression, not on the witness,
+    /// so the prover and the verifier always agree on it.
+    fn cleartext_value(&self) -> Option<Scalar> {
+        match self {
+            Expression::Constant(a) => Some(a.to_scalar()),
+            Expression::LinearCombination(terms, _) => {
+                let mut constant = Scalar::zero();
+                let mut weights: HashMap<(u8, usize), Scalar> = HashMap::new();
+                for (var, coeff) in terms.iter() {
+                    match var {
+                        r1cs::Variable::One() => constant += coeff,
+                        _ => *weights.entry(variable_key(var)).or_insert(Scalar::zero()) += coeff,
+                    }
+                }
+                if weights.values().all(|weight| *weight == Scalar::zero()) {
+                    Some(constant)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
     pub(crate) fn to_r1cs_lc(&self) -> r1cs::LinearCombination {
         match self {
             Expression::Constant(a) => a.to_scalar().into(),
@@ -450,26 +695,28 @@ impl Add for Expression {
             }
             (
                 Expression::Constant(l),
-                Expression::LinearCombination(mut right_terms, right_assignment),
+                Expression::LinearCombination(right_terms, right_assignment),
             ) => {
-                // prepend constant term to `term vector` in non-constant expression
-                right_terms.insert(0, (r1cs::Variable::One(), l.into()));
-                Expression::LinearCombination(right_terms, right_assignment.map(|r| l + r))
+                // put the constant term first and combine it with the constant term of the expression
+                let mut terms = Vec::with_capacity(right_terms.len() + 1);
+                terms.push((r1cs::Variable::One(), l.into()));
+                add_terms(&mut terms, right_terms);
+                Expression::LinearCombination(terms, right_assignment.map(|r| l + r))
             }
             (
                 Expression::LinearCombination(mut left_terms, left_assignment),
                 Expression::Constant(r),
             ) => {
-                // append constant term to term vector in non-constant expression
-                left_terms.push((r1cs::Variable::One(), r.into()));
+                // combine constant term with the constant term of the expression
+                add_terms(&mut left_terms, Some((r1cs::Variable::One(), r.into())));
                 Expression::LinearCombination(left_terms, left_assignment.map(|l| l + r))
             }
             (
                 Expression::LinearCombination(mut left_terms, left_assignment),
                 Expression::LinearCombination(right_terms, right_assignment),
             ) => {
-                // append right terms to left terms in non-constant expression
-                left_terms.extend(right_terms);
+                // combine right terms with the left terms for the same variables
+                add_terms(&mut left_terms, right_terms);
                 Expression::LinearCombination(
                     left_terms,
                     left_assignment.and_then(|l| right_assignment.map(|r| l + r)),
@@ -479,6 +726,41 @@ impl Add for Expression {
     }
 }
 
+/// Adds weighted variables to the list of terms, combining them with the existing terms
+/// for the same variables. Terms whose weights add up to zero are removed.
+fn add_terms(
+    terms: &mut Vec<(r1cs::Variable, Scalar)>,
+    new_terms: impl IntoIterator<Item = (r1cs::Variable, Scalar)>,
+) {
+    let mut index: HashMap<(u8, usize), usize> = terms
+        .iter()
+        .enumerate()
+        .map(|(i, (var, _))| (variable_key(var), i))
+        .collect();
+    for (var, weight) in new_terms {
+        match index.entry(variable_key(&var)) {
+            Entry::Occupied(e) => terms[*e.get()].1 += weight,
+            Entry::Vacant(e) => {
+                e.insert(terms.len());
+                terms.push((var, weight));
+            }
+        }
+    }
+    terms.retain(|(_, weight)| *weight != Scalar::zero());
+}
+
+/// Identifies the low-level variable by its kind and index,
+/// since `r1cs::Variable` cannot be hashed.
+fn variable_key(var: &r1cs::Variable) -> (u8, usize) {
+    match var {
+        r1cs::Variable::Committed(i) => (0, *i),
+        r1cs::Variable::MultiplierLeft(i) => (1, *i),
+        r1cs::Variable::MultiplierRight(i) => (2, *i),
+        r1cs::Variable::MultiplierOutput(i) => (3, *i),
+        r1cs::Variable::One() => (4, 0),
+    }
+}
+
 // Upcasting witness/points into Commitment
 
 impl From<CommitmentWitness> for Commitment {
@@ -504,6 +786,16 @@ mod tests {
     use super::*;
     use merlin::Transcript;
 
+    #[test]
+    fn commitment_tables_match_pedersen_gens() {
+        let gens = PedersenGens::default();
+        let blinding = Scalar::from(42u64);
+        let com = Commitment::blinded_with_factor(1000u64, blinding);
+        let expected = gens.commit(Scalar::from(1000u64), blinding);
+        assert_eq!(com.to_point(), expected.compress());
+        assert_eq!(com.decompress().unwrap(), expected);
+    }
+
     #[test]
     fn expression_arithmetic() {
         // const + const => const
@@ -515,13 +807,13 @@ mod tests {
         assert_eq!(
             Expression::Constant(1u64.into())
                 + Expression::LinearCombination(
-                    vec![(r1cs::Variable::One(), 2u64.into())],
+                    vec![(r1cs::Variable::Committed(0), 2u64.into())],
                     Some(2u64.into())
                 ),
             Expression::LinearCombination(
                 vec![
                     (r1cs::Variable::One(), 1u64.into()),
-                    (r1cs::Variable::One(), 2u64.into())
+                    (r1cs::Variable::Committed(0), 2u64.into())
                 ],
                 Some(3u64.into())
             )
@@ -529,17 +821,53 @@ mod tests {
         // lincomb + const => append to lincomb
         assert_eq!(
             Expression::LinearCombination(
-                vec![(r1cs::Variable::One(), 1u64.into())],
+                vec![(r1cs::Variable::Committed(0), 1u64.into())],
                 Some(1u64.into())
             ) + Expression::Constant(2u64.into()),
             Expression::LinearCombination(
                 vec![
-                    (r1cs::Variable::One(), 1u64.into()),
+                    (r1cs::Variable::Committed(0), 1u64.into()),
                     (r1cs::Variable::One(), 2u64.into())
                 ],
                 Some(3u64.into())
             )
         );
+        // const + lincomb with a constant term => combine constant terms
+        assert_eq!(
+            Expression::Constant(1u64.into())
+                + Expression::LinearCombination(
+                    vec![(r1cs::Variable::One(), 2u64.into())],
+                    Some(2u64.into())
+                ),
+            Expression::LinearCombination(
+                vec![(r1cs::Variable::One(), 3u64.into())],
+                Some(3u64.into())
+            )
+        );
+        // lincomb + lincomb with the same variables => combine weights
+        assert_eq!(
+            Expression::LinearCombination(
+                vec![
+                    (r1cs::Variable::Committed(1), 11u64.into()),
+                    (r1cs::Variable::Committed(2), 1u64.into())
+                ],
+                None
+            ) + Expression::LinearCombination(
+                vec![
+                    (r1cs::Variable::Committed(2), -Scalar::one()),
+                    (r1cs::Variable::Committed(1), 22u64.into()),
+                    (r1cs::Variable::Committed(3), 33u64.into())
+                ],
+                None
+            ),
+            Expression::LinearCombination(
+                vec![
+                    (r1cs::Variable::Committed(1), 33u64.into()),
+                    (r1cs::Variable::Committed(3), 33u64.into())
+                ],
+                None
+            )
+        );
         // lincomb + lincomb => concat
         assert_eq!(
             Expression::LinearCombination(
@@ -638,7 +966,7 @@ mod tests {
         // eq(const, nonconst) => ::Eq
         let e1 = Expression::Constant(1u64.into());
         let e2 = Expression::LinearCombination(
-            vec![(r1cs::Variable::One(), 2u64.into())],
+            vec![(r1cs::Variable::Committed(0), 2u64.into())],
             Some(2u64.into()),
         );
         assert_eq!(
@@ -765,4 +1093,125 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn commitment_arithmetic() {
+        let a = Commitment::blinded(3u64);
+        let b = Commitment::blinded(4u64);
+        let (_, a_blinding) = a.witness().unwrap();
+        let (_, b_blinding) = b.witness().unwrap();
+        let a_closed = Commitment::Closed(a.to_point());
+        let b_closed = Commitment::Closed(b.to_point());
+
+        // open + open => open commitment to the sum
+        let sum = a.add(&b).unwrap();
+        assert_eq!(
+            sum,
+            Commitment::blinded_with_factor(7u64, a_blinding + b_blinding)
+        );
+        // open + closed => closed commitment with the same point
+        assert_eq!(
+            a.add(&b_closed).unwrap(),
+            Commitment::Closed(sum.to_point())
+        );
+        assert_eq!(
+            a_closed.add(&b_closed).unwrap(),
+            Commitment::Closed(sum.to_point())
+        );
+
+        // multiplication by a cleartext scalar
+        let product = a.mul(5u64).unwrap();
+        assert_eq!(
+            product,
+            Commitment::blinded_with_factor(15u64, a_blinding * Scalar::from(5u64))
+        );
+        assert_eq!(a_closed.mul(5u64).unwrap().to_point(), product.to_point());
+
+        // reblinding keeps the value and adds the returned blinding factor
+        let (reblinded, delta) = a_closed.reblind(&mut rand::thread_rng()).unwrap();
+        assert_eq!(
+            reblinded.to_point(),
+            Commitment::blinded_with_factor(3u64, a_blinding + delta).to_point()
+        );
+        let (reblinded, delta) = a.reblind(&mut rand::thread_rng()).unwrap();
+        assert_eq!(
+            reblinded,
+            Commitment::blinded_with_factor(3u64, a_blinding + delta)
+        );
+    }
+
+    #[test]
+    fn structural_key_ignores_witness() {
+        let lc = |assignment: Option<ScalarWitness>| {
+            Expression::LinearCombination(
+                vec![(r1cs::Variable::Committed(0), Scalar::one())],
+                assignment,
+            )
+        };
+        let prover_constraint = Constraint::Secret(SecretConstraint::Eq(
+            lc(Some(5u64.into())),
+            Expression::constant(5u64),
+        ));
+        let verifier_constraint =
+            Constraint::Secret(SecretConstraint::Eq(lc(None), Expression::constant(5u64)));
+        let other_constraint =
+            Constraint::Secret(SecretConstraint::Eq(lc(None), Expression::constant(6u64)));
+
+        assert!(prover_constraint.structural_key().is_some());
+        assert_eq!(
+            prover_constraint.structural_key(),
+            verifier_constraint.structural_key()
+        );
+        assert_ne!(
+            verifier_constraint.structural_key(),
+            other_constraint.structural_key()
+        );
+        assert_eq!(Constraint::Cleartext(true).structural_key(), None);
+    }
+
+    #[test]
+    fn cleartext_constraint_folding() {
+        let var = |i: usize| {
+            Expression::LinearCombination(vec![(r1cs::Variable::Committed(i), Scalar::one())], None)
+        };
+
+        // eq(x, x) => cleartext(true), but only with folding
+        assert_eq!(
+            Constraint::eq_folded(var(0), var(0)),
+            Constraint::Cleartext(true)
+        );
+        assert_eq!(
+            Constraint::eq(var(0), var(0)),
+            Constraint::Secret(SecretConstraint::Eq(var(0), var(0)))
+        );
+        // eq(x + 2, x + 1 + 1) => cleartext(true)
+        assert_eq!(
+            Constraint::eq_folded(
+                var(0) + Expression::constant(2u64),
+                var(0) + Expression::constant(1u64) + Expression::constant(1u64)
+            ),
+            Constraint::Cleartext(true)
+        );
+        // eq(x + 1, x) => cleartext(false)
+        assert_eq!(
+            Constraint::eq_folded(var(0) + Expression::constant(1u64), var(0)),
+            Constraint::Cleartext(false)
+        );
+        // eq(const, lincomb of constant terms) => cleartext
+        assert_eq!(
+            Constraint::eq_folded(
+                Expression::constant(2u64),
+                Expression::LinearCombination(
+                    vec![(r1cs::Variable::One(), 2u64.into())],
+                    Some(2u64.into())
+                )
+            ),
+            Constraint::Cleartext(true)
+        );
+        // eq(x, y) => ::Eq
+        assert_eq!(
+            Constraint::eq_folded(var(0), var(1)),
+            Constraint::Secret(SecretConstraint::Eq(var(0), var(1)))
+        );
+    }
 }
@@ -24,12 +24,12 @@ pub const VALUE_TYPE: u8 = 0x02;
 /// A unique identifier for an anchor
 #[derive(Clone, Copy, PartialEq, Default)]
 pub struct Anchor(pub [u8; 32]);
-serialize_bytes32!(Anchor);
+bytes32_id!(Anchor);
 
 /// A unique identifier for a contract.
 #[derive(Copy, Clone, Eq, Hash, Debug, PartialEq, Default)]
 pub struct ContractID(pub [u8; 32]);
-serialize_bytes32!(ContractID);
+bytes32_id!(ContractID);
 
 /// A ZkVM contract that holds a _payload_ (a list of portable items) protected by a _predicate_.
 #[derive(Clone, PartialEq, Deserialize, Serialize)]
@@ -112,12 +112,6 @@ impl Decodable for Contract {
     }
 }
 
-impl AsRef<[u8]> for ContractID {
-    fn as_ref(&self) -> &[u8] {
-        self.0.as_ref()
-    }
-}
-
 impl Anchor {
     /// Provides a view into the anchor’s bytes.
     pub fn as_bytes(&self) -> &[u8] {
@@ -196,7 +190,7 @@ impl Decodable for PortableItem {
             STRING_TYPE => {
                 let len = reader.read_size()?;
                 let bytes = reader.read_bytes(len)?;
-                Ok(PortableItem::String(String::Opaque(bytes)))
+                Ok(PortableItem::String(String::Opaque(bytes.into())))
             }
             PROG_TYPE => {
                 let len = reader.read_size()?;
@@ -5,8 +5,8 @@
 //! * `[...]` is a sub-program.
 //! * `{...}` is a contract.
 
+use core::fmt;
 use std;
-use std::fmt;
 
 use crate::constraints::Commitment;
 use crate::contract::{Anchor, Contract, PortableItem};
@@ -14,7 +14,7 @@ use crate::encoding::*;
 use crate::ops::Instruction;
 use crate::predicate::Predicate;
 use crate::program::Program;
-use crate::tx::{Tx, TxID, VerifiedTx};
+use crate::tx::{Tx, TxID, TxWireHash, VerifiedTx};
 use crate::types::{String, Value};
 
 impl fmt::Debug for Program {
@@ -41,7 +41,7 @@ impl String {
             String::Opaque(bytes) => {
                 // short strings are usually human-readable, so let's try decode them as utf-8.
                 if bytes.len() < 32 {
-                    match std::string::String::from_utf8(bytes.clone()) {
+                    match core::str::from_utf8(bytes) {
                         Ok(s) => write!(f, "push:\"{}\"", s),
                         Err(_) => write!(f, "push:0x{}", hex::encode(&bytes)),
                     }
@@ -120,11 +120,13 @@ impl Instruction {
             Instruction::Issue => write!(f, "issue"),
             Instruction::Borrow => write!(f, "borrow"),
             Instruction::Retire => write!(f, "retire"),
+            Instruction::Burn => write!(f, "burn"),
             Instruction::Cloak(m, n) => write!(f, "cloak:{}:{}", m, n),
             Instruction::Fee => write!(f, "fee"),
             Instruction::Input => write!(f, "input"),
             Instruction::Output(k) => write!(f, "output:{}", k),
             Instruction::Contract(k) => write!(f, "contract:{}", k),
+            Instruction::Select(n, k) => write!(f, "select:{}:{}", n, k),
             Instruction::Log => write!(f, "log"),
             Instruction::Eval => write!(f, "eval"),
             Instruction::Call => write!(f, "call"),
@@ -181,6 +183,12 @@ impl fmt::Debug for TxID {
     }
 }
 
+impl fmt::Debug for TxWireHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TxWireHash({})", hex::encode(&self.0))
+    }
+}
+
 impl fmt::Debug for Tx {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -0,0 +1,59 @@
+//! Local entropy of the prover and the verifier.
+//!
+//! With the `os-entropy` feature (enabled by default) the entropy comes from the thread RNG.
+//! Targets like `wasm32-unknown-unknown` have no entropy source: without the feature
+//! the RNG produces zeros, so the verifier derives the batch weights from the transactions alone.
+//! Blinding factors are never drawn from this RNG: without the feature,
+//! `Commitment::blinded` is not available and the caller provides the RNG.
+
+#[cfg(not(feature = "os-entropy"))]
+use rand::{CryptoRng, RngCore};
+
+/// RNG used for the batches of deferred statements.
+#[cfg(feature = "os-entropy")]
+pub(crate) type EntropyRng = rand::rngs::ThreadRng;
+
+/// RNG used for the batches of deferred statements.
+#[cfg(not(feature = "os-entropy"))]
+pub(crate) type EntropyRng = NoEntropy;
+
+/// Returns the local entropy.
+#[cfg(feature = "os-entropy")]
+pub(crate) fn entropy_rng() -> EntropyRng {
+    rand::thread_rng()
+}
+
+/// Returns the local entropy.
+#[cfg(not(feature = "os-entropy"))]
+pub(crate) fn entropy_rng() -> EntropyRng {
+    NoEntropy
+}
+
+/// RNG for the targets without an entropy source.
+#[cfg(not(feature = "os-entropy"))]
+pub(crate) struct NoEntropy;
+
+#[cfg(not(feature = "os-entropy"))]
+impl RngCore for NoEntropy {
+    fn next_u32(&mut self) -> u32 {
+        0
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        0
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for b in dest.iter_mut() {
+            *b = 0;
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "os-entropy"))]
+impl CryptoRng for NoEntropy {}
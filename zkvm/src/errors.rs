@@ -3,8 +3,30 @@ use bulletproofs::r1cs::R1CSError;
 
 use thiserror::Error;
 
+/// Classification of an error that tells the caller how to react to it:
+/// whether to ban the peer that sent the data, drop the data, or retry later.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum FailureClass {
+    /// Data violates consensus rules and can never become valid.
+    /// The peer that relayed it should be banned.
+    Consensus,
+
+    /// Data is valid, but is rejected by the local policy (rate limits, versions).
+    /// The data should be dropped without banning the peer.
+    Policy,
+
+    /// Data cannot be processed in the current state, but may be valid later.
+    /// The operation may be retried.
+    Transient,
+
+    /// Error is caused by the local node itself: missing witness data or a bug.
+    /// The remote party is not at fault.
+    Internal,
+}
+
 /// Represents an error in proof creation, verification, or parsing.
 #[derive(Error, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum VMError {
     /// This error occurs when an individual point operation failed.
     #[error("Point operation failed.")]
@@ -98,6 +120,10 @@ pub enum VMError {
     #[error("Item is not a signed integer.")]
     TypeNotSignedInteger,
 
+    /// This error occurs when an integer operation or conversion overflows the integer range.
+    #[error("Integer overflow.")]
+    IntegerOverflow,
+
     /// This error occurs when a prover is supposed to provide a program.
     #[error("Item is not a program")]
     TypeNotProgram,
@@ -118,6 +144,14 @@ pub enum VMError {
     #[error("Stack does not have enough items")]
     StackUnderflow,
 
+    /// This error occurs when VM stack exceeds the maximum depth
+    #[error("Stack exceeds the maximum depth")]
+    StackOverflow,
+
+    /// This error occurs when a string, a program or a contract payload exceeds the maximum size
+    #[error("Item exceeds the maximum size")]
+    ItemTooLarge,
+
     /// This error occurs when VM is left with some items on the stack
     #[error("Stack is not cleared by the program")]
     StackNotClean,
@@ -140,7 +174,7 @@ pub enum VMError {
 
     /// This error occurs when an R1CSError is returned from the ConstraintSystem.
     #[error("R1CSError returned when trying to build R1CS instance")]
-    R1CSError(R1CSError),
+    R1CSError(#[source] R1CSError),
 
     /// This error occurs when a prover expects some witness data, but it is missing.
     #[error("Item misses witness data.")]
@@ -154,6 +188,10 @@ pub enum VMError {
     #[error("Invalid Merkle proof.")]
     InvalidMerkleProof,
 
+    /// This error occurs when the contract's predicate is not a disjunction of the selected predicates.
+    #[error("Predicate is not a disjunction of the given predicates.")]
+    InvalidDisjunction,
+
     /// This error occurs when the predicate tree cannot be constructed.
     #[error("Invalid predicate tree.")]
     InvalidPredicateTree,
@@ -170,7 +208,32 @@ pub enum VMError {
     #[error("Cleartext constraint is false")]
     CleartextConstraintFalse,
 
+    /// This error occurs when a secret constraint is false for the witness data.
+    /// Only reported by `Verifier::verify_program_only`, otherwise the R1CS proof is invalid.
+    #[error("Secret constraint is false")]
+    SecretConstraintFalse,
+
+    /// This error occurs when the cloaked values are not balanced for the witness data.
+    /// Only reported by `Verifier::verify_program_only`, otherwise the R1CS proof is invalid.
+    #[error("Cloaked values are not balanced")]
+    UnbalancedCloak,
+
     /// This error occurs when tx attempts to add a fee beyond the limit.
     #[error("Fee is too high")]
     FeeTooHigh,
 }
+
+impl VMError {
+    /// Returns the class of the error.
+    /// Errors produced by the prover due to insufficient or inconsistent witness data
+    /// are `Internal`, all other errors indicate an invalid transaction.
+    pub fn failure_class(&self) -> FailureClass {
+        match self {
+            VMError::WitnessMissing
+            | VMError::InconsistentWitness
+            | VMError::BadArguments
+            | VMError::InvalidPredicateTree => FailureClass::Internal,
+            _ => FailureClass::Consensus,
+        }
+    }
+}
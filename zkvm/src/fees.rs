@@ -6,6 +6,19 @@ use serde::{Deserialize, Serialize};
 /// Maximum amount of fee, which allows overflow-safe size-by-fee multiplication.
 pub const MAX_FEE: u64 = 1 << 24;
 
+/// Weight of the transaction header, signature and length prefixes.
+pub const TX_BASE_WEIGHT: u64 = 96;
+
+/// Weight of a byte of the transaction program.
+pub const PROGRAM_BYTE_WEIGHT: u64 = 1;
+
+/// Weight of a byte of the payloads of the created outputs,
+/// which are stored in the blocks and kept by the wallets until spent.
+pub const PAYLOAD_BYTE_WEIGHT: u64 = 2;
+
+/// Weight of an R1CS multiplier, accounting for the size and verification cost of the proof.
+pub const MULTIPLIER_WEIGHT: u64 = 4;
+
 /// Fee checked to be less or equal to `MAX_FEE`.
 #[derive(Copy, Clone, Debug)]
 pub struct CheckedFee {
@@ -13,6 +26,7 @@ pub struct CheckedFee {
 }
 
 /// Fee rate is a ratio of the transaction fee to its size.
+/// Size of a transaction is its weight (see `tx_weight`).
 #[derive(Copy, Clone, Default, Debug, Serialize, Deserialize)]
 pub struct FeeRate {
     fee: u64,
@@ -24,6 +38,17 @@ pub fn fee_flavor() -> Scalar {
     Scalar::zero()
 }
 
+/// Computes the consensus weight of a transaction from the length of its program,
+/// the total size of the payloads of its outputs and the number of R1CS multipliers.
+/// The weight is the size component of the transaction feerate
+/// and is limited per block.
+pub fn tx_weight(program_len: usize, payload_bytes: usize, multipliers: usize) -> u64 {
+    TX_BASE_WEIGHT
+        + PROGRAM_BYTE_WEIGHT * program_len as u64
+        + PAYLOAD_BYTE_WEIGHT * payload_bytes as u64
+        + MULTIPLIER_WEIGHT * multipliers as u64
+}
+
 impl FeeRate {
     /// Creates a new zero feerate
     pub fn zero() -> Self {
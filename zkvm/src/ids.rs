@@ -0,0 +1,146 @@
+//! Common representation of the 32-byte identifiers.
+//!
+//! Identifiers such as `ContractID`, `TxID` and `BlockID` are displayed and parsed
+//! as lowercase hex strings and serialized as 32-byte strings, so they look the same
+//! in the logs, the command line and the API payloads.
+//! The [`bytes32_id!`](crate::bytes32_id) macro implements `Display`, `FromStr`, `AsRef<[u8]>`,
+//! `serde::Serialize` and `serde::Deserialize` for such identifiers.
+
+use core::fmt;
+use thiserror::Error;
+
+/// Error parsing a 32-byte identifier from a hex string.
+#[derive(Copy, Clone, Debug, Error, PartialEq)]
+#[error("Identifier must be a 64-character hex string.")]
+pub struct ParseIdError;
+
+/// Implements `Display`, `FromStr`, `AsRef<[u8]>`, `serde::Serialize` and `serde::Deserialize`
+/// for a tuple-struct that wraps `[u8;32]`, or a newtype wrapping `[u8;32]` (such as `Hash`)
+/// passed as the second argument.
+#[macro_export]
+macro_rules! bytes32_id {
+    ($type_name:ident) => {
+        $crate::bytes32_id!(@impl $type_name, id => &id.0, bytes => $type_name(bytes));
+    };
+    ($type_name:ident, $inner:path) => {
+        $crate::bytes32_id!(@impl $type_name, id => &(id.0).0, bytes => $type_name($inner(bytes)));
+    };
+    (@impl $type_name:ident, $id:ident => $as_bytes:expr, $bytes:ident => $from_bytes:expr) => {
+        impl AsRef<[u8]> for $type_name {
+            fn as_ref(&self) -> &[u8] {
+                let $id = self;
+                $as_bytes
+            }
+        }
+
+        impl ::core::fmt::Display for $type_name {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                $crate::ids::fmt_hex(self.as_ref(), f)
+            }
+        }
+
+        impl ::core::str::FromStr for $type_name {
+            type Err = $crate::ids::ParseIdError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let $bytes = $crate::ids::parse_hex32(s)?;
+                Ok($from_bytes)
+            }
+        }
+
+        impl serde::Serialize for $type_name {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                serializer.serialize_bytes(self.as_ref())
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $type_name {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                let $bytes = $crate::ids::deserialize_bytes32(deserializer)?;
+                Ok($from_bytes)
+            }
+        }
+    };
+}
+
+/// Formats the bytes as a lowercase hex string.
+pub fn fmt_hex(bytes: &[u8], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", hex::encode(bytes))
+}
+
+/// Parses a 32-byte array from a hex string.
+pub fn parse_hex32(s: &str) -> Result<[u8; 32], ParseIdError> {
+    let bytes = hex::decode(s).map_err(|_| ParseIdError)?;
+    if bytes.len() != 32 {
+        return Err(ParseIdError);
+    }
+    let mut buf = [0u8; 32];
+    buf.copy_from_slice(&bytes);
+    Ok(buf)
+}
+
+/// Deserializes a 32-byte array from a byte string.
+pub fn deserialize_bytes32<'de, D>(deserializer: D) -> Result<[u8; 32], D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct BytesVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for BytesVisitor {
+        type Value = [u8; 32];
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a valid 32-byte string")
+        }
+
+        fn visit_bytes<E>(self, v: &[u8]) -> Result<[u8; 32], E>
+        where
+            E: serde::de::Error,
+        {
+            if v.len() == 32 {
+                let mut buf = [0u8; 32];
+                buf[0..32].copy_from_slice(v);
+                Ok(buf)
+            } else {
+                Err(serde::de::Error::invalid_length(v.len(), &self))
+            }
+        }
+    }
+
+    deserializer.deserialize_bytes(BytesVisitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ContractID, TxID};
+
+    #[test]
+    fn display_and_parse() {
+        let id = ContractID([0xab; 32]);
+        let string = id.to_string();
+        assert_eq!(string, "ab".repeat(32));
+        assert_eq!(string.parse::<ContractID>(), Ok(id));
+
+        let txid = TxID(crate::Hash([0x01; 32]));
+        assert_eq!(txid.to_string(), "01".repeat(32));
+        assert_eq!("01".repeat(32).parse::<TxID>(), Ok(txid));
+
+        assert!("ab".repeat(31).parse::<ContractID>().is_err());
+        assert!("zz".repeat(32).parse::<ContractID>().is_err());
+    }
+
+    #[test]
+    fn serde_bytes() {
+        let txid = TxID(crate::Hash([0x01; 32]));
+        assert_eq!(
+            serde_json::to_string(&txid).unwrap(),
+            serde_json::to_string(&txid.0).unwrap()
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! Canonical JSON representation of transactions and transaction logs.
+//!
+//! All binary data (points, scalars, hashes, programs, proofs and signatures)
+//! is encoded as lowercase hex strings. Numbers are encoded as JSON numbers.
+//! Log entries and payload items are tagged with a `"type"` field.
+//! Field names are part of the format and must not change.
+use bulletproofs::r1cs::R1CSProof;
+use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::scalar::Scalar;
+use musig::{Signature, VerificationKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::constraints::Commitment;
+use crate::contract::{Anchor, Contract, ContractID, PortableItem};
+use crate::errors::VMError;
+use crate::ids::parse_hex32;
+use crate::predicate::Predicate;
+use crate::program::ProgramItem;
+use crate::tx::{Tx, TxEntry, TxHeader, TxLog};
+use crate::types::{String, Value};
+
+type Hex = std::string::String;
+
+#[derive(Serialize, Deserialize)]
+struct JsonTx {
+    header: JsonHeader,
+    program: Hex,
+    proof: Hex,
+    signature: Hex,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonHeader {
+    version: u64,
+    mintime_ms: u64,
+    maxtime_ms: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum JsonEntry {
+    Header(JsonHeader),
+    Issue { qty: Hex, flv: Hex },
+    Retire { qty: Hex, flv: Hex },
+    Burn { qty: u64, flv: Hex },
+    Input { contract_id: Hex },
+    Output(JsonContract),
+    Fee { qty: u64 },
+    Data { data: Hex },
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonContract {
+    id: Hex,
+    anchor: Hex,
+    predicate: Hex,
+    payload: Vec<JsonItem>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum JsonItem {
+    String { data: Hex },
+    Program { data: Hex },
+    Value { qty: Hex, flv: Hex },
+}
+
+impl Tx {
+    /// Converts the transaction to its canonical JSON representation.
+    pub fn to_json(&self) -> JsonValue {
+        to_value(&JsonTx {
+            header: JsonHeader::from(&self.header),
+            program: hex::encode(&self.program),
+            proof: hex::encode(self.proof.to_bytes()),
+            signature: hex::encode(&self.signature.to_bytes()[..]),
+        })
+    }
+
+    /// Parses the transaction from its canonical JSON representation.
+    pub fn from_json(json: &JsonValue) -> Result<Tx, VMError> {
+        let tx: JsonTx = from_value(json)?;
+        Ok(Tx {
+            header: tx.header.into(),
+            program: decode_hex(&tx.program)?,
+            proof: R1CSProof::from_bytes(&decode_hex(&tx.proof)?)
+                .map_err(|_| VMError::InvalidFormat)?,
+            signature: Signature::from_bytes(&decode_hex(&tx.signature)?[..])
+                .map_err(|_| VMError::InvalidFormat)?,
+        })
+    }
+}
+
+impl TxLog {
+    /// Converts the transaction log to its canonical JSON representation.
+    pub fn to_json(&self) -> JsonValue {
+        to_value(&self.iter().map(JsonEntry::from).collect::<Vec<_>>())
+    }
+
+    /// Parses the transaction log from its canonical JSON representation.
+    pub fn from_json(json: &JsonValue) -> Result<TxLog, VMError> {
+        let entries: Vec<JsonEntry> = from_value(json)?;
+        let entries = entries
+            .into_iter()
+            .map(TxEntry::from_json_entry)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(TxLog::from(entries))
+    }
+}
+
+impl TxEntry {
+    fn from_json_entry(entry: JsonEntry) -> Result<TxEntry, VMError> {
+        Ok(match entry {
+            JsonEntry::Header(h) => TxEntry::Header(h.into()),
+            JsonEntry::Issue { qty, flv } => {
+                TxEntry::Issue(decode_point(&qty)?, decode_point(&flv)?)
+            }
+            JsonEntry::Retire { qty, flv } => {
+                TxEntry::Retire(decode_point(&qty)?, decode_point(&flv)?)
+            }
+            JsonEntry::Burn { qty, flv } => TxEntry::Burn(qty, decode_scalar(&flv)?),
+            JsonEntry::Input { contract_id } => {
+                TxEntry::Input(ContractID(decode_bytes32(&contract_id)?))
+            }
+            JsonEntry::Output(c) => TxEntry::Output(c.into_contract()?),
+            JsonEntry::Fee { qty } => TxEntry::Fee(qty),
+            JsonEntry::Data { data } => TxEntry::Data(decode_hex(&data)?),
+        })
+    }
+}
+
+impl From<&TxEntry> for JsonEntry {
+    fn from(entry: &TxEntry) -> Self {
+        match entry {
+            TxEntry::Header(h) => JsonEntry::Header(h.into()),
+            TxEntry::Issue(qty, flv) => JsonEntry::Issue {
+                qty: hex::encode(qty.as_bytes()),
+                flv: hex::encode(flv.as_bytes()),
+            },
+            TxEntry::Retire(qty, flv) => JsonEntry::Retire {
+                qty: hex::encode(qty.as_bytes()),
+                flv: hex::encode(flv.as_bytes()),
+            },
+            TxEntry::Burn(qty, flv) => JsonEntry::Burn {
+                qty: *qty,
+                flv: hex::encode(flv.as_bytes()),
+            },
+            TxEntry::Input(cid) => JsonEntry::Input {
+                contract_id: cid.to_string(),
+            },
+            TxEntry::Output(c) => JsonEntry::Output(c.into()),
+            TxEntry::Fee(qty) => JsonEntry::Fee { qty: *qty },
+            TxEntry::Data(data) => JsonEntry::Data {
+                data: hex::encode(data),
+            },
+        }
+    }
+}
+
+impl From<&TxHeader> for JsonHeader {
+    fn from(h: &TxHeader) -> Self {
+        JsonHeader {
+            version: h.version,
+            mintime_ms: h.mintime_ms,
+            maxtime_ms: h.maxtime_ms,
+        }
+    }
+}
+
+impl From<JsonHeader> for TxHeader {
+    fn from(h: JsonHeader) -> Self {
+        TxHeader {
+            version: h.version,
+            mintime_ms: h.mintime_ms,
+            maxtime_ms: h.maxtime_ms,
+        }
+    }
+}
+
+impl From<&Contract> for JsonContract {
+    fn from(c: &Contract) -> Self {
+        JsonContract {
+            id: c.id().to_string(),
+            anchor: c.anchor.to_string(),
+            predicate: hex::encode(c.predicate.to_point().as_bytes()),
+            payload: c
+                .payload
+                .iter()
+                .map(|item| match item {
+                    PortableItem::String(s) => JsonItem::String {
+                        data: hex::encode(s.clone().to_bytes()),
+                    },
+                    PortableItem::Program(p) => JsonItem::Program {
+                        data: hex::encode(p.to_bytes()),
+                    },
+                    PortableItem::Value(v) => JsonItem::Value {
+                        qty: hex::encode(v.qty.to_point().as_bytes()),
+                        flv: hex::encode(v.flv.to_point().as_bytes()),
+                    },
+                })
+                .collect(),
+        }
+    }
+}
+
+impl JsonContract {
+    /// Restores the verifier's view of the contract and checks that it matches the declared ID.
+    fn into_contract(self) -> Result<Contract, VMError> {
+        let payload = self
+            .payload
+            .into_iter()
+            .map(|item| {
+                Ok(match item {
+                    JsonItem::String { data } => {
+                        PortableItem::String(String::Opaque(decode_hex(&data)?.into()))
+                    }
+                    JsonItem::Program { data } => {
+                        PortableItem::Program(ProgramItem::Bytecode(decode_hex(&data)?))
+                    }
+                    JsonItem::Value { qty, flv } => PortableItem::Value(Value {
+                        qty: Commitment::Closed(decode_point(&qty)?),
+                        flv: Commitment::Closed(decode_point(&flv)?),
+                    }),
+                })
+            })
+            .collect::<Result<Vec<_>, VMError>>()?;
+        let contract = Contract {
+            predicate: Predicate::new(VerificationKey::from_compressed(decode_point(
+                &self.predicate,
+            )?)),
+            payload,
+            anchor: Anchor(decode_bytes32(&self.anchor)?),
+        };
+        if contract.id().0 != decode_bytes32(&self.id)? {
+            return Err(VMError::InvalidFormat);
+        }
+        Ok(contract)
+    }
+}
+
+fn to_value<T: Serialize>(value: &T) -> JsonValue {
+    serde_json::to_value(value).expect("JSON representation always serializes")
+}
+
+fn from_value<T: for<'de> Deserialize<'de>>(json: &JsonValue) -> Result<T, VMError> {
+    T::deserialize(json).map_err(|_| VMError::InvalidFormat)
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>, VMError> {
+    hex::decode(s).map_err(|_| VMError::InvalidFormat)
+}
+
+fn decode_bytes32(s: &str) -> Result<[u8; 32], VMError> {
+    parse_hex32(s).map_err(|_| VMError::InvalidFormat)
+}
+
+fn decode_point(s: &str) -> Result<CompressedRistretto, VMError> {
+    Ok(CompressedRistretto(decode_bytes32(s)?))
+}
+
+fn decode_scalar(s: &str) -> Result<Scalar, VMError> {
+    Scalar::from_canonical_bytes(decode_bytes32(s)?).ok_or(VMError::InvalidFormat)
+}
@@ -12,38 +12,66 @@ pub extern crate curve25519_dalek;
 pub extern crate merkle;
 extern crate serde;
 
+#[macro_use]
+pub mod ids;
 #[macro_use]
 mod serialization;
+mod arena;
+mod asset;
 mod constraints;
 mod contract;
 mod debug;
 pub mod encoding;
+mod entropy;
 mod errors;
 mod fees;
+#[cfg(feature = "json")]
+mod json;
 mod ops;
 mod predicate;
+mod profile;
 mod program;
 mod prover;
 mod scalar_witness;
+mod sealed;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod trace;
 mod transcript;
 mod tx;
 mod types;
 mod verifier;
 mod vm;
 
+pub use self::asset::{AssetMetadata, AssetRecord, ASSET_METADATA_VERSION, ASSET_RECORD_VERSION};
 pub use self::constraints::{Commitment, CommitmentWitness, Constraint, Expression, Variable};
 pub use self::contract::{Anchor, Contract, ContractID, PortableItem};
-pub use self::errors::VMError;
-pub use self::fees::{fee_flavor, CheckedFee, FeeRate, MAX_FEE};
+pub use self::errors::{FailureClass, VMError};
+pub use self::fees::{
+    fee_flavor, tx_weight, CheckedFee, FeeRate, MAX_FEE, MULTIPLIER_WEIGHT, PAYLOAD_BYTE_WEIGHT,
+    PROGRAM_BYTE_WEIGHT, TX_BASE_WEIGHT,
+};
 pub use self::ops::{Instruction, Opcode};
 pub use self::predicate::{Predicate, PredicateTree, PredicateWitness};
+pub use self::profile::{OpcodeProfile, Profile};
 pub use self::program::{Program, ProgramItem};
-pub use self::prover::Prover;
+pub use self::prover::{Prover, ProverContext, WitnessBundle};
 pub use self::scalar_witness::ScalarWitness;
+pub use self::sealed::SealedContract;
+pub use self::trace::{Trace, TraceStep};
 pub use self::transcript::TranscriptProtocol;
-pub use self::tx::{Tx, TxEntry, TxHeader, TxID, TxLog, UnsignedTx, VerifiedTx};
+pub use self::tx::{
+    PrecomputedTx, SigningMessage, Tx, TxEntry, TxHeader, TxID, TxIDBuilder, TxLog, TxWireHash,
+    UnprovenTx, UnsignedTx, VerifiedTx,
+};
 pub use self::types::{ClearValue, Item, String, Value, WideValue};
-pub use self::verifier::Verifier;
+pub use self::verifier::{DeferredVerification, SimulatedTx, Verifier};
+pub use self::vm::{
+    quantity_range, AGGREGATED_SIGNATURES_VERSION, BURN_VERSION, CLEARTEXT_FOLDING_VERSION,
+    CONSTRAINT_DEDUP_VERSION, CURRENT_VERSION, MAX_ITEM_SIZE, MAX_PAYLOAD_COUNT, MAX_STACK_DEPTH,
+    RESOURCE_LIMITS_VERSION, SELECT_VERSION, WIDE_QUANTITY_VERSION,
+};
 pub use merkle::{Hash, Hasher, MerkleItem, MerkleTree};
 
 pub use musig::{Multikey, Multisignature, Signature, VerificationKey};
+pub use spacesuit::CircuitSize;
@@ -1,8 +1,9 @@
 //! Definition of all instructions in ZkVM,
 //! their codes and decoding/encoding utility functions.
-use core::mem;
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 
+use crate::arena::Arena;
 use crate::encoding::*;
 use crate::errors::VMError;
 use crate::program::ProgramItem;
@@ -308,6 +309,20 @@ pub enum Instruction {
     /// Fails if the value is not a _non-negative value type_.
     Retire,
 
+    /// _value qty metadata pred_ **burn** → _contract_
+    ///
+    /// 1. Pops _point_ `pred`, _string_ `metadata`, 64-bit integer `qty` and a _value_ from the stack.
+    /// 2. Computes the issuance flavor `F` as in **issue**, using `pred` and `metadata`.
+    /// 3. Adds constraints to the _constraint system_ that the value's quantity equals `qty`
+    ///    and its flavor equals `F`.
+    /// 4. Adds a _burn_ entry with the cleartext `qty` and `F` to the _transaction log_.
+    /// 5. Creates a _contract_ with an empty payload, protected by the predicate `pred`,
+    ///    so the burn is authorized by the issuer, and pushes it to the stack.
+    ///
+    /// Fails if `pred` is not a valid _point_, `qty` is not a 64-bit integer,
+    /// or the value is not a _non-negative value type_.
+    Burn,
+
     /// _widevalues commitments_ **cloak:_m_:_n_** → _values_
     ///
     /// Merges and splits `m` _wide values_ into `n` _values_.
@@ -379,6 +394,27 @@ pub enum Instruction {
     /// * payload items are not _portable_.
     Contract(usize),
 
+    /// _contract pred(0) ... pred(n-1)_ **select:_n_:_k_** → _contract'_
+    ///
+    /// 1. Pops `n` _predicates_ `pred(n-1)`, ..., `pred(0)` and a _contract_ from the stack.
+    /// 2. Computes the _disjunction_ `D` of the `n` predicates:
+    ///     ```ascii
+    ///     T = Transcript("ZkVM.select")
+    ///     T.append("n", LE64(n))
+    ///     T.append("predicate", pred(i)) for each i
+    ///     D = B_blinding + T.challenge_scalar("h")·B
+    ///     ```
+    /// 3. Verifies that the contract’s predicate is equal to `D`.
+    /// 4. Replaces the contract’s predicate with `pred(k)` and pushes the contract to the stack.
+    ///
+    /// Immediate data `n` and `k` are encoded as two _LE32_s.
+    ///
+    /// Fails if:
+    /// * `k` is not less than `n`,
+    /// * items are not _predicates_ and a _contract_,
+    /// * contract’s predicate is not the disjunction of the `n` predicates.
+    Select(usize, usize),
+
     /// _data_ **log** → ø
     ///
     /// 1. Pops `data` from the stack.
@@ -457,8 +493,12 @@ pub enum Instruction {
     /// 9. Add the statement to the list of _deferred point operations_.
     /// 10. Set the `prog` as current.
     ///
+    /// If the tx version is 2 or higher and `sig` is an empty _string_,
+    /// steps 7-9 are replaced with adding the predicate to the list of deferred keys
+    /// for the _transaction signature_, with the message `T.challenge_bytes("statement")`.
+    ///
     /// Fails if:
-    /// 1. `sig` is not a 64-byte long _string_,
+    /// 1. `sig` is not a 64-byte long _string_ (or an empty one, since tx version 2),
     /// 2. or `prog` is not a _program_,
     /// 3. or `contract` is not a _contract_.
     Signid,
@@ -494,8 +534,12 @@ pub enum Instruction {
     /// 10. Add the statement to the list of _deferred point operations_.
     /// 11. Set the `prog` as current.
     ///
+    /// If the tx version is 2 or higher and `sig` is an empty _string_,
+    /// steps 8-10 are replaced with adding the predicate to the list of deferred keys
+    /// for the _transaction signature_, with the message `T.challenge_bytes("statement")`.
+    ///
     /// Fails if:
-    /// 1. `sig` is not a 64-byte long _string_,
+    /// 1. `sig` is not a 64-byte long _string_ (or an empty one, since tx version 2),
     /// 2. or `prog` is not a _program_,
     /// 3. or `contract` is not a _contract_,
     /// 4. or last item in the `payload` (`tag`) is not a _string_.
@@ -505,10 +549,34 @@ pub enum Instruction {
     Ext(u8),
 }
 
-/// A bytecode representation of the instruction.
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-#[repr(u8)]
-pub enum Opcode {
+/// Defines the `Opcode` enum together with the table of all opcodes
+/// and the conversion from `u8`, so that the codes do not need to be contiguous.
+macro_rules! define_opcodes {
+    ($($(#[$doc:meta])* $name:ident = $code:literal,)*) => {
+        /// A bytecode representation of the instruction.
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        #[repr(u8)]
+        pub enum Opcode {
+            $($(#[$doc])* $name = $code,)*
+        }
+
+        impl Opcode {
+            /// All assigned opcodes, in the order of their codes.
+            pub const ALL: &'static [Opcode] = &[$(Opcode::$name,)*];
+
+            /// Instantiates the opcode from `u8`.
+            /// Unassigned code is mapped to `None`.
+            pub fn from_u8(code: u8) -> Option<Opcode> {
+                match code {
+                    $($code => Some(Opcode::$name),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+define_opcodes! {
     /// A code for [Instruction::Push].
     Push = 0x00,
     /// A code for [Instruction::Program].
@@ -578,25 +646,17 @@ pub enum Opcode {
     /// A code for [Instruction::Signid]
     Signid = 0x21,
     /// A code for [Instruction::Signtag]
-    Signtag = MAX_OPCODE,
+    Signtag = 0x22,
+    /// A code for [Instruction::Select]
+    Select = 0x23,
+    /// A code for [Instruction::Burn]
+    Burn = 0x24,
 }
 
-const MAX_OPCODE: u8 = 0x22;
-
 impl Opcode {
     /// Converts the opcode to `u8`.
     pub fn to_u8(self) -> u8 {
-        unsafe { mem::transmute(self) }
-    }
-
-    /// Instantiates the opcode from `u8`.
-    /// Unassigned code is mapped to `None`.
-    pub fn from_u8(code: u8) -> Option<Opcode> {
-        if code > MAX_OPCODE {
-            None
-        } else {
-            unsafe { mem::transmute(code) }
-        }
+        self as u8
     }
 }
 
@@ -608,22 +668,22 @@ impl Encodable for Instruction {
         match self {
             Instruction::Push(data) => {
                 write(Opcode::Push)?;
-                w.write_u32(b"n", data.encoded_size() as u32)?;
+                w.write_size(b"n", data.encoded_size())?;
                 data.encode(w)?;
             }
             Instruction::Program(subprog) => {
                 write(Opcode::Program)?;
-                w.write_u32(b"n", subprog.encoded_size() as u32)?;
+                w.write_size(b"n", subprog.encoded_size())?;
                 subprog.encode(w)?;
             }
             Instruction::Drop => write(Opcode::Drop)?,
             Instruction::Dup(idx) => {
                 write(Opcode::Dup)?;
-                w.write_u32(b"k", *idx as u32)?;
+                w.write_size(b"k", *idx)?;
             }
             Instruction::Roll(idx) => {
                 write(Opcode::Roll)?;
-                w.write_u32(b"k", *idx as u32)?;
+                w.write_size(b"k", *idx)?;
             }
             Instruction::Scalar => write(Opcode::Scalar)?,
             Instruction::Commit => write(Opcode::Commit)?,
@@ -644,20 +704,26 @@ impl Encodable for Instruction {
             Instruction::Issue => write(Opcode::Issue)?,
             Instruction::Borrow => write(Opcode::Borrow)?,
             Instruction::Retire => write(Opcode::Retire)?,
+            Instruction::Burn => write(Opcode::Burn)?,
             Instruction::Cloak(m, n) => {
                 write(Opcode::Cloak)?;
-                w.write_u32(b"m", *m as u32)?;
-                w.write_u32(b"n", *n as u32)?;
+                w.write_size(b"m", *m)?;
+                w.write_size(b"n", *n)?;
             }
             Instruction::Fee => write(Opcode::Fee)?,
             Instruction::Input => write(Opcode::Input)?,
             Instruction::Output(k) => {
                 write(Opcode::Output)?;
-                w.write_u32(b"k", *k as u32)?;
+                w.write_size(b"k", *k)?;
             }
             Instruction::Contract(k) => {
                 write(Opcode::Contract)?;
-                w.write_u32(b"k", *k as u32)?;
+                w.write_size(b"k", *k)?;
+            }
+            Instruction::Select(n, k) => {
+                write(Opcode::Select)?;
+                w.write_size(b"n", *n)?;
+                w.write_size(b"k", *k)?;
             }
             Instruction::Log => write(Opcode::Log)?,
             Instruction::Eval => write(Opcode::Eval)?,
@@ -681,12 +747,59 @@ impl ExactSizeEncodable for Instruction {
             Instruction::Cloak(_, _) => 1 + 4 + 4,
             Instruction::Output(_) => 1 + 4,
             Instruction::Contract(_) => 1 + 4,
+            Instruction::Select(_, _) => 1 + 4 + 4,
             _ => 1,
         }
     }
 }
 
 impl Instruction {
+    /// Returns the code of the instruction's opcode.
+    /// Extension instructions return their unassigned code.
+    pub fn code(&self) -> u8 {
+        let opcode = match self {
+            Instruction::Push(_) => Opcode::Push,
+            Instruction::Program(_) => Opcode::Program,
+            Instruction::Drop => Opcode::Drop,
+            Instruction::Dup(_) => Opcode::Dup,
+            Instruction::Roll(_) => Opcode::Roll,
+            Instruction::Scalar => Opcode::Scalar,
+            Instruction::Commit => Opcode::Commit,
+            Instruction::Alloc(_) => Opcode::Alloc,
+            Instruction::Mintime => Opcode::Mintime,
+            Instruction::Maxtime => Opcode::Maxtime,
+            Instruction::Expr => Opcode::Expr,
+            Instruction::Neg => Opcode::Neg,
+            Instruction::Add => Opcode::Add,
+            Instruction::Mul => Opcode::Mul,
+            Instruction::Eq => Opcode::Eq,
+            Instruction::Range => Opcode::Range,
+            Instruction::And => Opcode::And,
+            Instruction::Or => Opcode::Or,
+            Instruction::Not => Opcode::Not,
+            Instruction::Verify => Opcode::Verify,
+            Instruction::Unblind => Opcode::Unblind,
+            Instruction::Issue => Opcode::Issue,
+            Instruction::Borrow => Opcode::Borrow,
+            Instruction::Retire => Opcode::Retire,
+            Instruction::Burn => Opcode::Burn,
+            Instruction::Cloak(_, _) => Opcode::Cloak,
+            Instruction::Fee => Opcode::Fee,
+            Instruction::Input => Opcode::Input,
+            Instruction::Output(_) => Opcode::Output,
+            Instruction::Contract(_) => Opcode::Contract,
+            Instruction::Select(_, _) => Opcode::Select,
+            Instruction::Log => Opcode::Log,
+            Instruction::Eval => Opcode::Eval,
+            Instruction::Call => Opcode::Call,
+            Instruction::Signtx => Opcode::Signtx,
+            Instruction::Signid => Opcode::Signid,
+            Instruction::Signtag => Opcode::Signtag,
+            Instruction::Ext(x) => return *x,
+        };
+        opcode.to_u8()
+    }
+
     /// Returns a parsed instruction from a subslice of the program string, modifying
     /// the subslice according to the bytes the instruction occupies
     /// E.g. a push instruction with 5-byte string occupies 1+4+5=10 bytes,
@@ -694,7 +807,38 @@ impl Instruction {
     ///
     /// Return `VMError::InvalidFormat` if there are not enough bytes to parse an
     /// instruction.
+    ///
+    /// The encoding is canonical: a parsed instruction encodes back into the same bytes.
+    /// Encodings that do not round-trip (e.g. `select:n:k` with `k >= n`) are rejected.
     pub fn parse(program: &mut impl Reader) -> Result<Self, VMError> {
+        Self::parse_with(
+            program,
+            |r, len| Ok(r.read_bytes(len)?.into()),
+            |r, len| r.read_bytes(len),
+        )
+    }
+
+    /// Parses an instruction like `parse` without copying the `push` payload:
+    /// the string shares the buffer of the program.
+    /// The buffer of the `program` payload is taken from the arena.
+    pub(crate) fn parse_shared(program: &mut Bytes, arena: &mut Arena) -> Result<Self, VMError> {
+        Self::parse_with(
+            program,
+            |r, len| {
+                if r.len() < len {
+                    return Err(ReadError::InsufficientBytes);
+                }
+                Ok(r.split_to(len))
+            },
+            |r, len| arena.read_bytes(r, len),
+        )
+    }
+
+    fn parse_with<R: Reader>(
+        program: &mut R,
+        read_string: impl FnOnce(&mut R, usize) -> Result<Bytes, ReadError>,
+        read_program: impl FnOnce(&mut R, usize) -> Result<Vec<u8>, ReadError>,
+    ) -> Result<Self, VMError> {
         let byte = program.read_u8()?;
 
         // Interpret the opcode. Unknown opcodes are extension opcodes.
@@ -708,12 +852,12 @@ impl Instruction {
         match opcode {
             Opcode::Push => {
                 let strlen = program.read_size()?;
-                let data = program.read_bytes(strlen)?;
+                let data = read_string(program, strlen)?;
                 Ok(Instruction::Push(String::Opaque(data)))
             }
             Opcode::Program => {
                 let strlen = program.read_size()?;
-                let data = program.read_bytes(strlen)?;
+                let data = read_program(program, strlen)?;
                 Ok(Instruction::Program(ProgramItem::Bytecode(data)))
             }
             Opcode::Drop => Ok(Instruction::Drop),
@@ -744,6 +888,7 @@ impl Instruction {
             Opcode::Issue => Ok(Instruction::Issue),
             Opcode::Borrow => Ok(Instruction::Borrow),
             Opcode::Retire => Ok(Instruction::Retire),
+            Opcode::Burn => Ok(Instruction::Burn),
             Opcode::Cloak => {
                 let m = program.read_size()?;
                 let n = program.read_size()?;
@@ -759,6 +904,14 @@ impl Instruction {
                 let k = program.read_size()?;
                 Ok(Instruction::Contract(k))
             }
+            Opcode::Select => {
+                let n = program.read_size()?;
+                let k = program.read_size()?;
+                if k >= n {
+                    return Err(VMError::InvalidFormat);
+                }
+                Ok(Instruction::Select(n, k))
+            }
             Opcode::Log => Ok(Instruction::Log),
             Opcode::Eval => Ok(Instruction::Eval),
             Opcode::Call => Ok(Instruction::Call),
@@ -768,3 +921,199 @@ impl Instruction {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Program;
+    use rand::Rng;
+
+    fn roundtrip(instr: &Instruction) {
+        let bytes = instr.encode_to_vec();
+        assert_eq!(bytes.len(), instr.encoded_size());
+        let parsed = Instruction::parse(&mut &bytes[..]).unwrap();
+        assert_eq!(parsed.encode_to_vec(), bytes);
+        assert_eq!(parsed.code(), instr.code());
+    }
+
+    fn sample_instructions() -> Vec<Instruction> {
+        let max = u32::max_value() as usize;
+        let mut list = vec![
+            Instruction::Push(String::Opaque(Bytes::new())),
+            Instruction::Push(String::Opaque(vec![0xff; 300].into())),
+            Instruction::Push(String::U64(u64::max_value())),
+            Instruction::Program(ProgramItem::Bytecode(vec![])),
+            Instruction::Program(ProgramItem::Program(Program::build(|p| {
+                p.drop().dup(1);
+            }))),
+            Instruction::Alloc(None),
+            Instruction::Alloc(Some(ScalarWitness::Integer(7u64.into()))),
+            Instruction::Select(1, 0),
+            Instruction::Select(max, max - 1),
+        ];
+        for &k in &[0, 1, 255, max] {
+            list.push(Instruction::Dup(k));
+            list.push(Instruction::Roll(k));
+            list.push(Instruction::Output(k));
+            list.push(Instruction::Contract(k));
+            list.push(Instruction::Cloak(k, max - k));
+        }
+        list.extend(vec![
+            Instruction::Drop,
+            Instruction::Scalar,
+            Instruction::Commit,
+            Instruction::Mintime,
+            Instruction::Maxtime,
+            Instruction::Expr,
+            Instruction::Neg,
+            Instruction::Add,
+            Instruction::Mul,
+            Instruction::Eq,
+            Instruction::Range,
+            Instruction::And,
+            Instruction::Or,
+            Instruction::Not,
+            Instruction::Verify,
+            Instruction::Unblind,
+            Instruction::Issue,
+            Instruction::Borrow,
+            Instruction::Retire,
+            Instruction::Burn,
+            Instruction::Fee,
+            Instruction::Input,
+            Instruction::Log,
+            Instruction::Eval,
+            Instruction::Call,
+            Instruction::Signtx,
+            Instruction::Signid,
+            Instruction::Signtag,
+        ]);
+        for code in 0..=u8::max_value() {
+            if Opcode::from_u8(code).is_none() {
+                list.push(Instruction::Ext(code));
+            }
+        }
+        list
+    }
+
+    #[test]
+    fn all_instructions_roundtrip() {
+        let list = sample_instructions();
+        let mut codes: Vec<u8> = list.iter().map(|i| i.code()).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), 256);
+
+        for instr in list.iter() {
+            roundtrip(instr);
+        }
+    }
+
+    #[test]
+    fn opcode_table_matches_codes() {
+        for code in 0..=u8::max_value() {
+            match Opcode::from_u8(code) {
+                Some(op) => {
+                    assert_eq!(op.to_u8(), code);
+                    assert!(Opcode::ALL.contains(&op));
+                }
+                None => assert!(Opcode::ALL.iter().all(|op| op.to_u8() != code)),
+            }
+        }
+        assert!(Opcode::ALL.windows(2).all(|w| w[0].to_u8() < w[1].to_u8()));
+    }
+
+    #[test]
+    fn instructions_parse_with_their_opcodes() {
+        for instr in sample_instructions() {
+            let bytes = instr.encode_to_vec();
+            assert_eq!(bytes[0], instr.code());
+            let parsed = Instruction::parse(&mut &bytes[..]).unwrap();
+            match Opcode::from_u8(bytes[0]) {
+                Some(op) => assert_eq!(parsed.code(), op.to_u8()),
+                None => assert_eq!(parsed, Instruction::Ext(bytes[0])),
+            }
+        }
+    }
+
+    #[test]
+    fn random_programs_roundtrip() {
+        let mut rng = rand::thread_rng();
+        let list = sample_instructions();
+        for _ in 0..100 {
+            let len = rng.gen_range(0, 20);
+            let program = Program::from_vec(
+                (0..len)
+                    .map(|_| list[rng.gen_range(0, list.len())].clone())
+                    .collect(),
+            );
+            let bytes = program.encode_to_vec();
+            let parsed = Program::parse(&bytes).unwrap();
+            assert_eq!(parsed.encode_to_vec(), bytes);
+        }
+    }
+
+    #[test]
+    fn random_bytecode_is_canonical() {
+        // Any bytecode is either rejected or parsed into the instructions
+        // that encode back into the same bytes.
+        let mut rng = rand::thread_rng();
+        for _ in 0..1000 {
+            let len = rng.gen_range(0, 16);
+            let bytes: Vec<u8> = (0..len).map(|_| rng.gen::<u8>()).collect();
+            if let Ok(program) = Program::parse(&bytes) {
+                assert_eq!(program.encode_to_vec(), bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn non_canonical_instructions_are_rejected() {
+        // Parser rejects the encodings that do not round-trip.
+        assert!(Program::parse(&[Opcode::Select.to_u8(), 1, 0, 0, 0, 1, 0, 0, 0]).is_err());
+        assert!(Program::parse(&[Opcode::Push.to_u8(), 2, 0, 0, 0, 1]).is_err());
+        assert!(Program::parse(&[Opcode::Dup.to_u8(), 1, 0, 0]).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn builder_rejects_out_of_range_select() {
+        Program::build(|p| {
+            p.select(2, 2);
+        });
+    }
+
+    #[test]
+    fn shared_parsing() {
+        let program = Program::build(|p| {
+            p.push(String::Opaque(vec![0xaa; 40].into()))
+                .program(Program::build(|p| {
+                    p.drop();
+                }))
+                .drop();
+        });
+        let bytes = Bytes::from(program.to_bytes());
+        let buffer = bytes.as_ptr() as usize..bytes.as_ptr() as usize + bytes.len();
+
+        let mut arena = Arena::new();
+        let mut reader = bytes.clone();
+        let mut parsed = Vec::new();
+        while !reader.is_empty() {
+            parsed.push(Instruction::parse_shared(&mut reader, &mut arena).unwrap());
+        }
+        assert_eq!(Program::from_vec(parsed.clone()).to_bytes(), &bytes[..]);
+
+        // The pushed string points into the program buffer.
+        match &parsed[0] {
+            Instruction::Push(String::Opaque(data)) => {
+                assert_eq!(&data[..], &[0xaa; 40][..]);
+                assert!(buffer.contains(&(data.as_ptr() as usize)));
+            }
+            other => panic!("unexpected instruction: {:?}", other),
+        }
+
+        // Truncated payload is rejected.
+        let mut truncated = bytes.slice(..20);
+        assert!(Instruction::parse_shared(&mut truncated, &mut arena).is_err());
+    }
+}
@@ -179,6 +179,20 @@ impl Predicate {
         }
     }
 
+    /// Creates a disjunction of predicates that can be replaced with any of them
+    /// using the `select` instruction.
+    /// The disjunction has no known discrete log and cannot be signed or used with `call`.
+    pub fn disjunction(predicates: &[Predicate]) -> Self {
+        let mut t = Transcript::new(b"ZkVM.select");
+        t.append_u64(b"n", predicates.len() as u64);
+        for p in predicates.iter() {
+            t.append_message(b"predicate", p.to_point().as_bytes());
+        }
+        let h = t.challenge_scalar(b"h");
+        let gens = PedersenGens::default();
+        Self::new(VerificationKey::from(gens.B_blinding + h * gens.B))
+    }
+
     fn commit_taproot(key: &VerificationKey, root: &Hash) -> Scalar {
         let mut t = Transcript::new(b"ZkVM.taproot");
         t.append_message(b"key", key.as_bytes());
@@ -267,6 +281,26 @@ impl PredicateTree {
         })
     }
 
+    /// Creates a refundable predicate tree for a payment.
+    ///
+    /// The key path pays the receiver: the funds are spent with a signature of the `payee`
+    /// predicate's key adjusted by the tree's adjustment factor. The only leaf (at index 0) refunds
+    /// the sender: once the minimum time bound of the transaction reaches `refund_after_ms`,
+    /// the funds can be spent with a signature of the `refund` predicate. The leaf program expects the value on the stack
+    /// and leaves it there for the refund transaction:
+    /// `mintime <refund_after_ms> scalar neg add range drop <refund> contract:1 signtx`.
+    pub fn refundable(
+        payee: Predicate,
+        refund: Predicate,
+        refund_after_ms: u64,
+        blinding_key: [u8; 32],
+    ) -> Result<Self, VMError> {
+        let refund_prog = Program::build(|p| {
+            p.lock_until(refund_after_ms).require_signature(refund, 1);
+        });
+        Self::new(Some(payee), vec![refund_prog], blinding_key)
+    }
+
     /// Returns the adjustment factor for signing
     // TODO: Instead, we would rather return a "key witness" object like musig::Multikey.
     // That would directly store the adjustment factor.
@@ -439,6 +473,31 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn refundable_tree() {
+        let payee = Predicate::new(VerificationKey::from_secret(&Scalar::from(1u64)));
+        let refund = Predicate::new(VerificationKey::from_secret(&Scalar::from(2u64)));
+        let blinding_key = rand::thread_rng().gen::<[u8; 32]>();
+        let tree =
+            PredicateTree::refundable(payee.clone(), refund.clone(), 1000, blinding_key).unwrap();
+        assert_eq!(tree.inner_predicate().to_point(), payee.to_point());
+        assert_ne!(tree.outer_key().into_point(), payee.to_point());
+
+        let tree_pred = Predicate::tree(tree.clone());
+        let (call_proof, prog) = tree.create_callproof(0).unwrap();
+        assert!(tree_pred
+            .verify_taproot(&ProgramItem::Program(prog), &call_proof)
+            .is_ok());
+        assert!(tree.create_callproof(1).is_err());
+
+        // Different refund time produces a different outer key.
+        let other = PredicateTree::refundable(payee, refund, 1001, blinding_key).unwrap();
+        assert_ne!(
+            other.outer_key().into_point(),
+            tree.outer_key().into_point()
+        );
+    }
+
     #[test]
     fn invalid_taproot() {
         let prog1 = Program::build(|p| {
@@ -0,0 +1,112 @@
+//! Instruction-level profile of the program execution.
+//!
+//! The profile records, per opcode, the number of executed instructions,
+//! the multipliers and constraints they add to the constraint system and the time spent.
+//! Contract authors use it to find the instructions that dominate the size of the proof.
+//! Time of the `call` and `eval` instructions does not include the nested program,
+//! which is profiled instruction by instruction.
+
+use core::fmt;
+use core::time::Duration;
+use std::collections::BTreeMap;
+
+use crate::ops::Opcode;
+
+/// Execution profile of a transaction.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    opcodes: BTreeMap<u8, OpcodeProfile>,
+}
+
+/// Counters collected for a single opcode.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct OpcodeProfile {
+    /// Number of the executed instructions.
+    pub count: usize,
+    /// Number of multipliers added to the constraint system.
+    pub multipliers: usize,
+    /// Number of constraints added to the constraint system.
+    pub constraints: usize,
+    /// Total time spent executing the instructions.
+    pub time: Duration,
+}
+
+impl Profile {
+    /// Returns the counters of a given opcode, if any instruction with it was executed.
+    pub fn get(&self, opcode: Opcode) -> Option<&OpcodeProfile> {
+        self.opcodes.get(&opcode.to_u8())
+    }
+
+    /// Iterates over the executed opcodes (including the extension opcodes) in the order of their codes.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &OpcodeProfile)> {
+        self.opcodes.iter().map(|(code, p)| (*code, p))
+    }
+
+    /// Returns the opcodes ordered by the number of multipliers they add, largest first.
+    pub fn hotspots(&self) -> Vec<(u8, OpcodeProfile)> {
+        let mut list: Vec<_> = self.opcodes.iter().map(|(code, p)| (*code, *p)).collect();
+        list.sort_by(|a, b| b.1.multipliers.cmp(&a.1.multipliers));
+        list
+    }
+
+    /// Returns the counters summed over all opcodes.
+    pub fn total(&self) -> OpcodeProfile {
+        self.opcodes
+            .values()
+            .fold(OpcodeProfile::default(), |total, p| OpcodeProfile {
+                count: total.count + p.count,
+                multipliers: total.multipliers + p.multipliers,
+                constraints: total.constraints + p.constraints,
+                time: total.time + p.time,
+            })
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        code: u8,
+        multipliers: usize,
+        constraints: usize,
+        time: Duration,
+    ) {
+        let p = self.opcodes.entry(code).or_default();
+        p.count += 1;
+        p.multipliers += multipliers;
+        p.constraints += constraints;
+        p.time += time;
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<10} {:>8} {:>12} {:>12} {:>12}",
+            "opcode", "count", "multipliers", "constraints", "time (µs)"
+        )?;
+        for (code, p) in self.hotspots() {
+            let name = match Opcode::from_u8(code) {
+                Some(op) => format!("{:?}", op).to_lowercase(),
+                None => format!("ext:{:#04x}", code),
+            };
+            writeln!(
+                f,
+                "{:<10} {:>8} {:>12} {:>12} {:>12}",
+                name,
+                p.count,
+                p.multipliers,
+                p.constraints,
+                p.time.as_micros()
+            )?;
+        }
+        let total = self.total();
+        write!(
+            f,
+            "{:<10} {:>8} {:>12} {:>12} {:>12}",
+            "total",
+            total.count,
+            total.multipliers,
+            total.constraints,
+            total.time.as_micros()
+        )
+    }
+}
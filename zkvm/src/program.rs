@@ -2,14 +2,16 @@ use crate::encoding::*;
 use crate::errors::VMError;
 use crate::merkle::MerkleItem;
 use crate::ops::Instruction;
-use crate::predicate::PredicateTree;
+use crate::predicate::{Predicate, PredicateTree};
 use crate::scalar_witness::ScalarWitness;
 use crate::types::String;
+use crate::vm::quantity_range;
 
 use alloc::vec;
 use core::borrow::Borrow;
 use merlin::Transcript;
 use serde::{Deserialize, Serialize};
+use spacesuit::{BitRange, CircuitSize};
 
 /// A builder type for assembling a sequence of `Instruction`s with chained method calls.
 ///
@@ -179,6 +181,7 @@ impl Program {
     def_op!(issue, Issue, "issue");
     def_op!(borrow, Borrow, "borrow");
     def_op!(retire, Retire, "retire");
+    def_op!(burn, Burn, "burn");
 
     def_op!(cloak, Cloak, usize, usize, "cloak:m:n");
     def_op!(fee, Fee, "fee");
@@ -186,6 +189,15 @@ impl Program {
     def_op!(output, Output, usize, "output:k");
     def_op!(contract, Contract, usize, "contract:k");
 
+    /// Adds a [`select:n:k`](crate::ops::Instruction::Select) instruction.
+    ///
+    /// Panics if `k` is not less than `n`: such instruction has no valid encoding.
+    pub fn select(&mut self, n: usize, k: usize) -> &mut Program {
+        assert!(k < n, "select:n:k requires k < n (got n={}, k={})", n, k);
+        self.0.push(Instruction::Select(n, k));
+        self
+    }
+
     def_op!(log, Log, "log");
     def_op!(eval, Eval, "eval");
     def_op!(call, Call, "call");
@@ -201,12 +213,46 @@ impl Program {
         prog_index: usize,
     ) -> Result<&mut Program, VMError> {
         let (call_proof, program) = pred_tree.create_callproof(prog_index)?;
-        self.push(String::Opaque(call_proof.to_bytes()))
+        self.push(String::Opaque(call_proof.to_bytes().into()))
             .program(program)
             .call();
         Ok(self)
     }
 
+    /// Adds instructions that fail unless the minimum time bound of the transaction
+    /// is at or after `time_ms`, so the funds remain locked until that time:
+    /// `mintime <time_ms> scalar neg add range drop`.
+    pub fn lock_until(&mut self, time_ms: u64) -> &mut Program {
+        self.mintime()
+            .push(time_ms)
+            .scalar()
+            .neg()
+            .add()
+            .range()
+            .drop()
+    }
+
+    /// Adds instructions that fail unless the maximum time bound of the transaction
+    /// is at or before `time_ms`, so the funds can no longer be spent after that time:
+    /// `<time_ms> scalar maxtime neg add range drop`.
+    pub fn expire_after(&mut self, time_ms: u64) -> &mut Program {
+        self.push(time_ms)
+            .scalar()
+            .maxtime()
+            .neg()
+            .add()
+            .range()
+            .drop()
+    }
+
+    /// Adds instructions that require the transaction to be signed with a given predicate
+    /// by wrapping the top `payload_len` items in a temporary contract:
+    /// `<pred> contract:k signtx`. The items are placed back on the stack.
+    /// Combined with `lock_until` or `expire_after`, makes a time-locked key predicate.
+    pub fn require_signature(&mut self, pred: Predicate, payload_len: usize) -> &mut Program {
+        self.push(pred).contract(payload_len).signtx()
+    }
+
     /// Serializes a Program into a byte array.
     pub fn to_bytes(&self) -> Vec<u8> {
         self.encode_to_vec()
@@ -216,6 +262,49 @@ impl Program {
     pub fn to_vec(self) -> Vec<Instruction> {
         self.0
     }
+
+    /// Estimates the number of R1CS multipliers and constraints allocated by the program,
+    /// including the nested programs pushed with the `program` instruction.
+    /// Programs stored in the input contracts are not accounted for.
+    /// Assumes the 64-bit quantities: use `estimated_circuit_size_for_version`
+    /// for the transactions with the wide quantities.
+    pub fn estimated_circuit_size(&self) -> CircuitSize {
+        self.estimated_circuit_size_in_range(BitRange::max())
+    }
+
+    /// Estimates the circuit size like `estimated_circuit_size`,
+    /// with the range proofs of the quantities in a transaction of a given version.
+    pub fn estimated_circuit_size_for_version(&self, version: u64) -> CircuitSize {
+        self.estimated_circuit_size_in_range(quantity_range(version))
+    }
+
+    fn estimated_circuit_size_in_range(&self, bitrange: BitRange) -> CircuitSize {
+        let range = spacesuit::range_proof_size(bitrange);
+        let mut size = CircuitSize::default();
+        for instr in self.0.iter() {
+            size += match instr {
+                Instruction::Alloc(_) | Instruction::Mul | Instruction::Or => {
+                    CircuitSize::new(1, 0)
+                }
+                Instruction::Not => CircuitSize::new(2, 4),
+                Instruction::Verify => CircuitSize::new(0, 1),
+                Instruction::Range | Instruction::Issue => range,
+                Instruction::Borrow => spacesuit::borrow_size(bitrange),
+                Instruction::Fee => CircuitSize::new(1, 2),
+                Instruction::Cloak(m, n) => {
+                    spacesuit::cloak_size_with_bitranges(*m, &vec![bitrange; *n])
+                }
+                Instruction::Program(ProgramItem::Program(prog)) => {
+                    prog.estimated_circuit_size_in_range(bitrange)
+                }
+                Instruction::Program(ProgramItem::Bytecode(bytes)) => Program::parse(bytes)
+                    .map(|prog| prog.estimated_circuit_size_in_range(bitrange))
+                    .unwrap_or_default(),
+                _ => CircuitSize::default(),
+            };
+        }
+        size
+    }
 }
 
 impl Encodable for ProgramItem {
@@ -1,18 +1,22 @@
+use alloc::collections::VecDeque;
 use bulletproofs::r1cs;
-use bulletproofs::r1cs::ConstraintSystem;
+use bulletproofs::r1cs::{ConstraintSystem, R1CSError, R1CSProof};
 use bulletproofs::{BulletproofGens, PedersenGens};
 use curve25519_dalek::ristretto::CompressedRistretto;
 use merlin::Transcript;
-use std::collections::VecDeque;
+use serde::{Deserialize, Serialize};
 
 use crate::constraints::Commitment;
-use crate::contract::ContractID;
-use crate::encoding::Encodable;
+use crate::contract::PortableItem;
+use crate::encoding::{Encodable, ExactSizeEncodable};
+use crate::entropy::{entropy_rng, EntropyRng};
 use crate::errors::VMError;
 use crate::ops::Instruction;
-use crate::predicate::Predicate;
+use crate::predicate::{Predicate, PredicateTree};
+use crate::profile::Profile;
 use crate::program::{Program, ProgramItem};
-use crate::tx::{TxHeader, UnsignedTx};
+use crate::tx::{SigningMessage, TxHeader, UnsignedTx};
+use crate::types::String;
 use crate::vm::{Delegate, VM};
 
 /// This is the entry point API for creating a transaction.
@@ -21,18 +25,52 @@ use crate::vm::{Delegate, VM};
 /// creates a R1CS proof and returns a complete `Tx` object that can be published.
 pub struct Prover<'g> {
     // TBD: use Multikey as a witness thing
-    signtx_items: Vec<(Predicate, ContractID)>,
+    signtx_items: Vec<(Predicate, SigningMessage)>,
     cs: r1cs::Prover<'g, Transcript>,
-    batch: musig::BatchVerifier<rand::rngs::ThreadRng>,
+    batch: musig::BatchVerifier<EntropyRng>,
+    // instruction queues of the finished runs, reused by the next runs
+    free_runs: Vec<VecDeque<Instruction>>,
+}
+
+/// Maximum number of free instruction queues kept for reuse.
+const MAX_FREE_RUNS: usize = 64;
+
+/// Generators and scratch buffers reused across many `build_tx` calls.
+/// Wallets building many transactions (e.g. offers on an exchange)
+/// should keep one context instead of creating the generators for each transaction.
+/// The points of the open commitments are computed with the Pedersen tables
+/// precomputed once per thread, with or without the context.
+pub struct ProverContext {
+    pc_gens: PedersenGens,
+    bp_gens: BulletproofGens,
+    free_runs: Vec<VecDeque<Instruction>>,
+}
+
+/// Serializable input for the prover that allows building the transaction
+/// on one machine and proving it on another.
+/// The program carries the commitment openings and scalar witnesses,
+/// but not the signing keys: predicate witnesses are never serialized.
+/// Predicate trees are carried separately, so the signing instructions
+/// of the resulting transaction can be matched to the trees by the signer.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WitnessBundle {
+    /// Header of the transaction.
+    pub header: TxHeader,
+
+    /// Program with all the witness data necessary to create the proof.
+    pub program: Program,
+
+    /// Predicate trees used in the program.
+    pub predicate_trees: Vec<PredicateTree>,
 }
 
 pub(crate) struct ProverRun {
-    program: VecDeque<Instruction>,
+    pub(crate) program: VecDeque<Instruction>,
 }
 
 impl<'t, 'g> Delegate<r1cs::Prover<'g, Transcript>> for Prover<'g> {
     type RunType = ProverRun;
-    type BatchVerifier = musig::BatchVerifier<rand::rngs::ThreadRng>;
+    type BatchVerifier = musig::BatchVerifier<EntropyRng>;
 
     fn commit_variable(
         &mut self,
@@ -45,9 +83,9 @@ impl<'t, 'g> Delegate<r1cs::Prover<'g, Transcript>> for Prover<'g> {
     fn process_tx_signature(
         &mut self,
         pred: Predicate,
-        contract_id: ContractID,
+        msg: SigningMessage,
     ) -> Result<(), VMError> {
-        self.signtx_items.push((pred, contract_id));
+        self.signtx_items.push((pred, msg));
         Ok(())
     }
 
@@ -58,10 +96,16 @@ impl<'t, 'g> Delegate<r1cs::Prover<'g, Transcript>> for Prover<'g> {
         Ok(run.program.pop_front())
     }
 
-    fn new_run(&self, data: ProgramItem) -> Result<Self::RunType, VMError> {
-        Ok(ProverRun {
-            program: data.to_program()?.to_vec().into(),
-        })
+    fn new_run(&mut self, data: ProgramItem) -> Result<Self::RunType, VMError> {
+        let program = data.to_program()?;
+        Ok(self.run_for(program))
+    }
+
+    fn recycle_run(&mut self, mut run: Self::RunType) {
+        if self.free_runs.len() < MAX_FREE_RUNS {
+            run.program.clear();
+            self.free_runs.push(run.program);
+        }
     }
 
     fn cs(&mut self) -> &mut r1cs::Prover<'g, Transcript> {
@@ -77,52 +121,244 @@ impl<'g> Prover<'g> {
     /// Builds a transaction with a given list of instructions and a `TxHeader`.
     /// Returns a transaction `Tx` along with its ID (`TxID`) and a transaction log (`TxLog`).
     /// Fails if the input program is malformed, or some witness data is missing.
+    /// The program and the ID depend only on the inputs, but the R1CS proof
+    /// is randomized by the bulletproofs prover, and is not committed to by the ID.
     pub fn build_tx(
         program: Program,
         header: TxHeader,
         bp_gens: &BulletproofGens,
     ) -> Result<UnsignedTx, VMError> {
+        Self::build_tx_with_gens(
+            program,
+            header,
+            &PedersenGens::default(),
+            &mut Vec::new(),
+            false,
+            |cs| cs.prove(bp_gens),
+        )
+        .map(|(utx, _)| utx)
+    }
+
+    /// Builds a transaction like `build_tx`, and returns the per-opcode execution profile
+    /// of its program: instruction counts, constraint system growth and time spent.
+    pub fn build_tx_with_profile(
+        program: Program,
+        header: TxHeader,
+        bp_gens: &BulletproofGens,
+    ) -> Result<(UnsignedTx, Profile), VMError> {
+        let (utx, profile) = Self::build_tx_with_gens(
+            program,
+            header,
+            &PedersenGens::default(),
+            &mut Vec::new(),
+            true,
+            |cs| cs.prove(bp_gens),
+        )?;
+        Ok((utx, profile.unwrap_or_default()))
+    }
+
+    /// Builds a transaction from a witness bundle, possibly received from another machine.
+    /// The predicates in the signing instructions are re-attached to the bundled predicate trees
+    /// with the matching keys, so the signer can compute the adjusted keys.
+    pub fn build_tx_from_bundle(
+        bundle: WitnessBundle,
+        bp_gens: &BulletproofGens,
+    ) -> Result<UnsignedTx, VMError> {
+        let mut utx = Self::build_tx(bundle.program.clone(), bundle.header, bp_gens)?;
+        bundle.attach_predicate_trees(&mut utx);
+        Ok(utx)
+    }
+
+    /// Runs the program and creates the R1CS proof with a given `prove` function.
+    /// The instruction queues of the runs are taken from `free_runs` and returned there.
+    fn build_tx_with_gens<'p, F>(
+        program: Program,
+        header: TxHeader,
+        pc_gens: &'p PedersenGens,
+        free_runs: &mut Vec<VecDeque<Instruction>>,
+        profiling: bool,
+        prove: F,
+    ) -> Result<(UnsignedTx, Option<Profile>), VMError>
+    where
+        F: FnOnce(r1cs::Prover<'p, Transcript>) -> Result<R1CSProof, R1CSError>,
+    {
         // Prepare the constraint system
-        let pc_gens = PedersenGens::default();
-        let cs = r1cs::Prover::new(&pc_gens, Transcript::new(b"ZkVM.r1cs"));
+        let cs = r1cs::Prover::new(pc_gens, Transcript::new(b"ZkVM.r1cs"));
 
         // Serialize the tx program
-        let mut bytecode = Vec::new();
+        let mut bytecode = Vec::with_capacity(program.encoded_size());
         program.encode(&mut bytecode)?;
 
         let mut prover = Prover {
             signtx_items: Vec::new(),
             cs: cs,
-            batch: musig::BatchVerifier::new(rand::thread_rng()),
+            batch: musig::BatchVerifier::new(entropy_rng()),
+            free_runs: core::mem::take(free_runs),
         };
 
-        let vm = VM::new(
-            header,
-            ProverRun {
-                program: program.to_vec().into(),
-            },
-            &mut prover,
-        );
+        let run = prover.run_for(program);
+        let vm = VM::new(header, run, &mut prover);
 
-        let (txid, txlog, _fee) = vm.run()?;
+        let (txid, txlog, _fee, profile) = if profiling {
+            let (txid, txlog, fee, profile) = vm.run_with_profile()?;
+            (txid, txlog, fee, Some(profile))
+        } else {
+            let (txid, txlog, fee) = vm.run()?;
+            (txid, txlog, fee, None)
+        };
+
+        let Prover {
+            signtx_items,
+            mut cs,
+            free_runs: used_runs,
+            ..
+        } = prover;
+        *free_runs = used_runs;
 
         // Commit txid so that the proof is bound to the entire transaction, not just the constraint system.
-        prover.cs.transcript().append_message(b"ZkVM.txid", &txid.0);
+        cs.transcript().append_message(b"ZkVM.txid", &txid.0);
 
         // Generate the R1CS proof
-        let proof = prover
-            .cs
-            .prove(bp_gens)
-            .map_err(|_| VMError::InvalidR1CSProof)?;
+        let proof = prove(cs).map_err(|e| match e {
+            R1CSError::InvalidGeneratorsLength => VMError::R1CSError(e),
+            _ => VMError::InvalidR1CSProof,
+        })?;
 
         // Defer signing of the transaction to the UnsignedTx API.
-        Ok(UnsignedTx {
+        let utx = UnsignedTx {
             header,
             program: bytecode,
             proof,
             txid,
             txlog,
-            signing_instructions: prover.signtx_items,
-        })
+            signing_instructions: signtx_items,
+        };
+        Ok((utx, profile))
+    }
+
+    /// Creates a run of the program, reusing a free instruction queue if there is one.
+    fn run_for(&mut self, program: Program) -> ProverRun {
+        let mut queue = self.free_runs.pop().unwrap_or_default();
+        queue.extend(program.to_vec());
+        ProverRun { program: queue }
+    }
+}
+
+impl WitnessBundle {
+    /// Creates a bundle for a given program, collecting the predicate trees
+    /// from the predicates and contracts pushed by the program and its nested programs.
+    pub fn new(program: Program, header: TxHeader) -> Self {
+        let mut predicate_trees = Vec::new();
+        collect_predicate_trees(&program, &mut predicate_trees);
+        WitnessBundle {
+            header,
+            program,
+            predicate_trees,
+        }
+    }
+
+    /// Re-attaches the bundled predicate trees to the predicates with the matching keys
+    /// in the signing instructions of the transaction.
+    /// Used by the client when the unsigned transaction is received from a remote prover.
+    pub fn attach_predicate_trees(&self, utx: &mut UnsignedTx) {
+        for (pred, _) in utx.signing_instructions.iter_mut() {
+            if let Some(tree) = self
+                .predicate_trees
+                .iter()
+                .find(|t| t.outer_key() == pred.verification_key())
+            {
+                *pred = Predicate::tree(tree.clone());
+            }
+        }
+    }
+}
+
+fn collect_predicate_trees(program: &Program, trees: &mut Vec<PredicateTree>) {
+    let mut add_tree = |pred: &Predicate| {
+        if let Some(tree) = pred.verification_key_witness::<PredicateTree>() {
+            if !trees.contains(tree) {
+                trees.push(tree.clone());
+            }
+        }
+    };
+    let mut nested = Vec::new();
+    for instr in program.iter() {
+        match instr {
+            Instruction::Push(String::Predicate(pred)) => add_tree(pred),
+            Instruction::Push(String::Output(contract)) => {
+                add_tree(&contract.predicate);
+                for item in contract.payload.iter() {
+                    if let PortableItem::Program(ProgramItem::Program(prog)) = item {
+                        nested.push(prog);
+                    }
+                }
+            }
+            Instruction::Program(ProgramItem::Program(prog)) => nested.push(prog),
+            _ => {}
+        }
+    }
+    for prog in nested {
+        collect_predicate_trees(prog, trees);
+    }
+}
+
+impl ProverContext {
+    /// Creates a context with the bulletproofs generators for a given number of multipliers.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_gens(BulletproofGens::new(capacity, 1))
+    }
+
+    /// Creates a context with the existing bulletproofs generators.
+    pub fn with_gens(bp_gens: BulletproofGens) -> Self {
+        ProverContext {
+            pc_gens: PedersenGens::default(),
+            bp_gens,
+            free_runs: Vec::new(),
+        }
+    }
+
+    /// Returns the bulletproofs generators that can also be used to verify the transactions.
+    pub fn bp_gens(&self) -> &BulletproofGens {
+        &self.bp_gens
+    }
+
+    /// Builds a transaction like `Prover::build_tx`, reusing the generators and the scratch buffers.
+    /// The generators grow to fit the circuit built by the program,
+    /// including the programs stored in the input contracts and the taproot branches.
+    pub fn build_tx(&mut self, program: Program, header: TxHeader) -> Result<UnsignedTx, VMError> {
+        let estimate = program
+            .estimated_circuit_size_for_version(header.version)
+            .multipliers;
+        loop {
+            let ProverContext {
+                pc_gens,
+                bp_gens,
+                free_runs,
+            } = self;
+            let result = Prover::build_tx_with_gens(
+                program.clone(),
+                header,
+                pc_gens,
+                free_runs,
+                false,
+                |cs| {
+                    let multipliers = cs.metrics().multipliers.max(estimate);
+                    let capacity = multipliers.next_power_of_two();
+                    if capacity > bp_gens.gens_capacity {
+                        bp_gens.increase_capacity(capacity);
+                    }
+                    cs.prove(bp_gens)
+                },
+            );
+            match result {
+                // The randomized constraints (e.g. the cloak shuffles) are allocated while proving,
+                // and are not counted in the metrics: grow the generators and try again.
+                Err(VMError::R1CSError(R1CSError::InvalidGeneratorsLength)) => {
+                    let capacity = 2 * self.bp_gens.gens_capacity;
+                    self.bp_gens.increase_capacity(capacity);
+                }
+                result => return result.map(|(utx, _)| utx),
+            }
+        }
     }
 }
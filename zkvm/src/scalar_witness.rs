@@ -1,18 +1,25 @@
 //! Arithmetic and conversion API for ScalarWitness.
+//!
+//! Witnesses are secret: comparisons of the witness values (`ConstantTimeEq`, `in_range`)
+//! run in constant time. With the `ct-review` feature, the constant-time comparisons
+//! are cross-checked against the plain ones in debug assertions and tests.
 
 use curve25519_dalek::scalar::Scalar;
 use serde::{Deserialize, Serialize};
-use spacesuit::SignedInteger;
+use spacesuit::{BitRange, SignedInteger};
+use subtle::{Choice, ConstantTimeEq};
 
 use crate::encoding::*;
 use crate::errors::VMError;
-use std::ops::{Add, Mul, Neg, Sub};
-use std::u64;
+use core::convert::TryFrom;
+use core::ops::{Add, Mul, Neg, Sub};
+use core::u64;
 
 /// Represents a concrete kind of a number represented by a scalar.
 #[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum ScalarWitness {
-    /// `ScalarKind::Integer` represents a signed integer with 64-bit absolute value (think "i65")
+    /// `ScalarKind::Integer` represents a signed integer with 64-bit absolute value (think "i65"),
+    /// or up to 127-bit absolute value for the wide quantities.
     Integer(SignedInteger),
     /// `ScalarKind::Scalar` represents a scalar modulo group order.
     Scalar(Scalar),
@@ -58,12 +65,111 @@ impl ScalarWitness {
 
     /// Returns true if the scalar fits in u64.
     pub fn in_range(self) -> bool {
+        self.ct_in_range().into()
+    }
+
+    /// Returns 1 if the scalar fits in u64, 0 otherwise.
+    /// Unlike `in_range`, does not branch on the value.
+    pub fn ct_in_range(self) -> Choice {
+        self.ct_fits_in_bytes(8)
+    }
+
+    /// Returns true if the scalar fits in the given range of quantities:
+    /// 64 bits, or 128 bits for the wide quantities.
+    pub fn in_quantity_range(self, range: BitRange) -> bool {
+        let bits: usize = range.into();
+        self.ct_fits_in_bytes(bits / 8).into()
+    }
+
+    fn ct_fits_in_bytes(self, n: usize) -> Choice {
         let scalar_bytes = self.to_scalar().to_bytes();
-        (&scalar_bytes[8..32]).iter().all(|v| v == &0)
+        let high = scalar_bytes[n..32].iter().fold(0u8, |acc, b| acc | b);
+        let result = high.ct_eq(&0);
+
+        #[cfg(feature = "ct-review")]
+        debug_assert_eq!(
+            bool::from(result),
+            scalar_bytes[n..32].iter().all(|v| v == &0)
+        );
+
+        result
+    }
+
+    /// Converts the witness to a u64 integer.
+    /// Scalars are converted if they fit in u64.
+    /// Returns `IntegerOverflow` error for negative integers and scalars out of range.
+    pub fn to_u64(self) -> Result<u64, VMError> {
+        match self {
+            ScalarWitness::Integer(i) => i.to_u64().ok_or(VMError::IntegerOverflow),
+            ScalarWitness::Scalar(s) => {
+                if !self.in_range() {
+                    return Err(VMError::IntegerOverflow);
+                }
+                let mut buf = [0u8; 8];
+                buf.copy_from_slice(&s.as_bytes()[0..8]);
+                Ok(u64::from_le_bytes(buf))
+            }
+        }
+    }
+
+    /// Converts the witness to a u128 integer.
+    /// Scalars are converted if they fit in u128.
+    /// Returns `IntegerOverflow` error for negative integers and scalars out of range.
+    pub fn to_u128(self) -> Result<u128, VMError> {
+        match self {
+            ScalarWitness::Integer(i) => i.to_u128().ok_or(VMError::IntegerOverflow),
+            ScalarWitness::Scalar(s) => {
+                if !bool::from(self.ct_fits_in_bytes(16)) {
+                    return Err(VMError::IntegerOverflow);
+                }
+                let mut buf = [0u8; 16];
+                buf.copy_from_slice(&s.as_bytes()[0..16]);
+                Ok(u128::from_le_bytes(buf))
+            }
+        }
+    }
+
+    /// Adds two witnesses.
+    /// The sum of two integers remains an integer or fails with `IntegerOverflow` error
+    /// instead of silently degrading to a scalar. If either operand is a scalar, the sum is a scalar.
+    pub fn checked_add(self, rhs: ScalarWitness) -> Result<ScalarWitness, VMError> {
+        match (self, rhs) {
+            (ScalarWitness::Integer(a), ScalarWitness::Integer(b)) => (a + b)
+                .map(ScalarWitness::Integer)
+                .ok_or(VMError::IntegerOverflow),
+            (a, b) => Ok(ScalarWitness::Scalar(a.to_scalar() + b.to_scalar())),
+        }
+    }
+
+    /// Subtracts one witness from another.
+    /// Follows the same rules as `checked_add`.
+    pub fn checked_sub(self, rhs: ScalarWitness) -> Result<ScalarWitness, VMError> {
+        self.checked_add(-rhs)
+    }
+
+    /// Multiplies two witnesses.
+    /// The product of two integers remains an integer or fails with `IntegerOverflow` error
+    /// instead of silently degrading to a scalar. If either operand is a scalar, the product is a scalar.
+    pub fn checked_mul(self, rhs: ScalarWitness) -> Result<ScalarWitness, VMError> {
+        match (self, rhs) {
+            (ScalarWitness::Integer(a), ScalarWitness::Integer(b)) => (a * b)
+                .map(ScalarWitness::Integer)
+                .ok_or(VMError::IntegerOverflow),
+            (a, b) => Ok(ScalarWitness::Scalar(a.to_scalar() * b.to_scalar())),
+        }
     }
 }
 
-// Implementing arithmetic operatons for ScalarWitness
+impl ConstantTimeEq for ScalarWitness {
+    /// Compares the witnesses as scalars, so an integer equals the scalar with the same value.
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.to_scalar().ct_eq(&other.to_scalar())
+    }
+}
+
+// Implementing arithmetic operatons for ScalarWitness.
+// Negation never overflows since integers have a symmetric range.
+// Addition and multiplication of integers degrade to scalars on overflow.
 
 impl Neg for ScalarWitness {
     type Output = ScalarWitness;
@@ -80,13 +186,8 @@ impl Add for ScalarWitness {
     type Output = ScalarWitness;
 
     fn add(self, rhs: ScalarWitness) -> ScalarWitness {
-        match (self, rhs) {
-            (ScalarWitness::Integer(a), ScalarWitness::Integer(b)) => match a + b {
-                Some(res) => ScalarWitness::Integer(res),
-                None => ScalarWitness::Scalar(a.to_scalar() + b.to_scalar()),
-            },
-            (a, b) => ScalarWitness::Scalar(a.to_scalar() + b.to_scalar()),
-        }
+        self.checked_add(rhs)
+            .unwrap_or_else(|_| ScalarWitness::Scalar(self.to_scalar() + rhs.to_scalar()))
     }
 }
 
@@ -94,7 +195,7 @@ impl Sub for ScalarWitness {
     type Output = ScalarWitness;
 
     fn sub(self, rhs: ScalarWitness) -> ScalarWitness {
-        -self + rhs
+        self + (-rhs)
     }
 }
 
@@ -102,13 +203,8 @@ impl Mul for ScalarWitness {
     type Output = ScalarWitness;
 
     fn mul(self, rhs: ScalarWitness) -> ScalarWitness {
-        match (self, rhs) {
-            (ScalarWitness::Integer(a), ScalarWitness::Integer(b)) => match a * b {
-                Some(res) => ScalarWitness::Integer(res),
-                None => ScalarWitness::Scalar(a.to_scalar() * b.to_scalar()),
-            },
-            (a, b) => ScalarWitness::Scalar(a.to_scalar() * b.to_scalar()),
-        }
+        self.checked_mul(rhs)
+            .unwrap_or_else(|_| ScalarWitness::Scalar(self.to_scalar() * rhs.to_scalar()))
     }
 }
 
@@ -126,7 +222,15 @@ impl From<Scalar> for ScalarWitness {
     }
 }
 
-// Converting scalar witness to an opaque Scalar.
+// Converting scalar witness to an integer or to an opaque Scalar.
+
+impl TryFrom<ScalarWitness> for u64 {
+    type Error = VMError;
+
+    fn try_from(x: ScalarWitness) -> Result<u64, VMError> {
+        x.to_u64()
+    }
+}
 
 impl Into<Scalar> for ScalarWitness {
     fn into(self) -> Scalar {
@@ -264,4 +368,131 @@ mod tests {
             ScalarWitness::from(-Scalar::from(u64::MAX) - Scalar::from(u64::MAX))
         );
     }
+
+    #[test]
+    fn sub() {
+        assert_eq!(
+            ScalarWitness::from(10u64) - ScalarWitness::from(3u64),
+            ScalarWitness::from(7u64)
+        );
+        assert_eq!(
+            ScalarWitness::from(3u64) - ScalarWitness::from(10u64),
+            -ScalarWitness::from(7u64)
+        );
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        assert_eq!(
+            ScalarWitness::from(2u64).checked_add(5u64.into()),
+            Ok(ScalarWitness::from(7u64))
+        );
+        assert_eq!(
+            ScalarWitness::from(2u64).checked_sub(5u64.into()),
+            Ok(-ScalarWitness::from(3u64))
+        );
+        assert_eq!(
+            ScalarWitness::from(5u64).checked_mul(6u64.into()),
+            Ok(ScalarWitness::from(30u64))
+        );
+
+        // integer overflow is reported instead of degrading to a scalar
+        assert_eq!(
+            ScalarWitness::from(u64::MAX).checked_add(u64::MAX.into()),
+            Err(VMError::IntegerOverflow)
+        );
+        assert_eq!(
+            ScalarWitness::from(u64::MAX).checked_mul(2u64.into()),
+            Err(VMError::IntegerOverflow)
+        );
+
+        // scalars are combined modulo group order
+        assert_eq!(
+            ScalarWitness::from(u64::MAX).checked_add(Scalar::from(u64::MAX).into()),
+            Ok(ScalarWitness::from(
+                Scalar::from(u64::MAX) + Scalar::from(u64::MAX)
+            ))
+        );
+    }
+
+    #[test]
+    fn ct_eq() {
+        let a = ScalarWitness::from(24u64);
+        assert!(bool::from(
+            a.ct_eq(&ScalarWitness::from(Scalar::from(24u64)))
+        ));
+        assert!(!bool::from(a.ct_eq(&-a)));
+        assert!(bool::from(
+            (-a).ct_eq(&ScalarWitness::from(-Scalar::from(24u64)))
+        ));
+    }
+
+    #[cfg(feature = "ct-review")]
+    #[test]
+    fn ct_review() {
+        let values = [0u64, 1, 2, 0xff, 1 << 32, u64::MAX - 1, u64::MAX];
+        let witnesses = values
+            .iter()
+            .flat_map(|&x| {
+                vec![
+                    ScalarWitness::from(x),
+                    -ScalarWitness::from(x),
+                    ScalarWitness::from(Scalar::from(x)),
+                    ScalarWitness::from(-Scalar::from(x)),
+                ]
+            })
+            .collect::<Vec<_>>();
+        for a in witnesses.iter() {
+            assert_eq!(
+                bool::from(a.ct_in_range()),
+                a.to_scalar().as_bytes()[8..].iter().all(|b| *b == 0)
+            );
+            for b in witnesses.iter() {
+                assert_eq!(bool::from(a.ct_eq(b)), a.to_scalar() == b.to_scalar());
+            }
+        }
+    }
+
+    #[test]
+    fn wide_quantities() {
+        let wide = |x: u128| ScalarWitness::Integer(SignedInteger::from_u128(x).unwrap());
+        let x = wide(1u128 << 64);
+        assert_eq!(x.to_u128(), Ok(1u128 << 64));
+        assert_eq!(x.to_u64(), Err(VMError::IntegerOverflow));
+        assert!(x.in_quantity_range(BitRange::wide()));
+        assert!(!x.in_quantity_range(BitRange::max()));
+        assert!(!x.in_range());
+        assert_eq!(
+            ScalarWitness::from(Scalar::from(1u128 << 64)).to_u128(),
+            Ok(1u128 << 64)
+        );
+        assert!(!ScalarWitness::from(-Scalar::one()).in_quantity_range(BitRange::wide()));
+
+        // integer arithmetic involving wide integers stays exact beyond 64 bits
+        assert_eq!(
+            x.checked_add(u64::MAX.into()),
+            Ok(wide((1u128 << 64) + u64::MAX as u128))
+        );
+        assert_eq!(
+            x.checked_sub(u64::MAX.into()),
+            Ok(ScalarWitness::from(1u64))
+        );
+        assert_eq!(x.checked_mul(2u64.into()), Ok(wide(1u128 << 65)));
+        assert_eq!(x.checked_mul(x), Err(VMError::IntegerOverflow));
+    }
+
+    #[test]
+    fn to_u64() {
+        assert_eq!(ScalarWitness::from(24u64).to_u64(), Ok(24));
+        assert_eq!(ScalarWitness::from(Scalar::from(24u64)).to_u64(), Ok(24));
+        assert_eq!(u64::try_from(ScalarWitness::from(u64::MAX)), Ok(u64::MAX));
+        assert_eq!(
+            (-ScalarWitness::from(24u64)).to_u64(),
+            Err(VMError::IntegerOverflow)
+        );
+        assert_eq!(
+            ScalarWitness::from(-Scalar::from(24u64)).to_u64(),
+            Err(VMError::IntegerOverflow)
+        );
+    }
 }
@@ -0,0 +1,155 @@
+//! Sealed contracts: contracts with auxiliary payload encrypted to the recipient.
+//!
+//! Contracts often carry data that is needed only by the owner (memos, invoice references,
+//! parameters of an off-chain agreement), but that is visible to everyone once the contract
+//! is published in the utxo set. `SealedContract` encrypts such data to the recipient's key
+//! and stores only the ciphertext in `String` payload items.
+//!
+//! Each sealed item is encoded as:
+//!
+//! ```ascii
+//! [nonce point] [ciphertext] [tag]
+//!  32 bytes      n bytes      16 bytes
+//! ```
+//!
+//! The key stream and the tag are derived from the Diffie-Hellman secret between
+//! the one-time nonce and the recipient's key, bound to the contract's predicate
+//! and the position of the item among the sealed items.
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_TABLE;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use merlin::Transcript;
+use rand::{CryptoRng, RngCore};
+use subtle::ConstantTimeEq;
+
+use crate::contract::{Anchor, Contract, PortableItem};
+use crate::predicate::Predicate;
+use crate::types::String;
+
+const NONCE_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+
+/// Contract with auxiliary data that is published only in encrypted form.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SealedContract {
+    /// Predicate that guards access to the contract’s payload.
+    pub predicate: Predicate,
+
+    /// Public payload items: values and programs that the VM operates on.
+    pub payload: Vec<PortableItem>,
+
+    /// Auxiliary data that is encrypted to the recipient.
+    pub sealed_data: Vec<Vec<u8>>,
+}
+
+impl SealedContract {
+    /// Creates a contract where the public payload is followed by the sealed items,
+    /// encrypted to the recipient's key.
+    pub fn seal<R: RngCore + CryptoRng>(
+        &self,
+        recipient_key: &RistrettoPoint,
+        anchor: Anchor,
+        rng: &mut R,
+    ) -> Contract {
+        let mut payload = self.payload.clone();
+        for (i, data) in self.sealed_data.iter().enumerate() {
+            let nonce_scalar = Scalar::random(rng);
+            let nonce_point = (&nonce_scalar * &RISTRETTO_BASEPOINT_TABLE).compress();
+            let dh = (nonce_scalar * recipient_key).compress();
+
+            let mut t = seal_transcript(&self.predicate, &dh, i);
+            let mut ct = data.clone();
+            apply_pad(&mut t, &mut ct);
+            let tag = compute_tag(&mut t, &ct);
+
+            let mut item = Vec::with_capacity(NONCE_LEN + ct.len() + TAG_LEN);
+            item.extend_from_slice(nonce_point.as_bytes());
+            item.extend_from_slice(&ct);
+            item.extend_from_slice(&tag);
+            payload.push(PortableItem::String(String::Opaque(item.into())));
+        }
+        Contract {
+            predicate: self.predicate.clone(),
+            payload,
+            anchor,
+        }
+    }
+
+    /// Attempts to decrypt the sealed items of the contract with the recipient's decryption key.
+    /// Items that do not decrypt are left in the public payload.
+    /// Returns `None` if none of the items could be decrypted, so wallets can call it
+    /// on every output they scan without any additional checks.
+    pub fn unseal(contract: &Contract, decryption_key: &Scalar) -> Option<SealedContract> {
+        let mut payload = Vec::new();
+        let mut sealed_data = Vec::new();
+        for item in contract.payload.iter() {
+            let decrypted = match item {
+                PortableItem::String(String::Opaque(bytes)) => open_item(
+                    &contract.predicate,
+                    bytes,
+                    decryption_key,
+                    sealed_data.len(),
+                ),
+                _ => None,
+            };
+            match decrypted {
+                Some(data) => sealed_data.push(data),
+                None => payload.push(item.clone()),
+            }
+        }
+        if sealed_data.is_empty() {
+            return None;
+        }
+        Some(SealedContract {
+            predicate: contract.predicate.clone(),
+            payload,
+            sealed_data,
+        })
+    }
+}
+
+fn open_item(
+    predicate: &Predicate,
+    bytes: &[u8],
+    decryption_key: &Scalar,
+    index: usize,
+) -> Option<Vec<u8>> {
+    if bytes.len() < NONCE_LEN + TAG_LEN {
+        return None;
+    }
+    let (nonce, rest) = bytes.split_at(NONCE_LEN);
+    let (ct, tag) = rest.split_at(rest.len() - TAG_LEN);
+    let nonce_point = CompressedRistretto::from_slice(nonce).decompress()?;
+    let dh = (decryption_key * nonce_point).compress();
+
+    let mut t = seal_transcript(predicate, &dh, index);
+    let mut pt = ct.to_vec();
+    apply_pad(&mut t, &mut pt);
+    if compute_tag(&mut t, ct).ct_eq(tag).unwrap_u8() == 0 {
+        return None;
+    }
+    Some(pt)
+}
+
+fn seal_transcript(predicate: &Predicate, dh: &CompressedRistretto, index: usize) -> Transcript {
+    let mut t = Transcript::new(b"ZkVM.sealed");
+    t.append_message(b"predicate", predicate.to_point().as_bytes());
+    t.append_message(b"dh", dh.as_bytes());
+    t.append_u64(b"index", index as u64);
+    t
+}
+
+fn apply_pad(t: &mut Transcript, data: &mut [u8]) {
+    let mut pad = vec![0u8; data.len()];
+    t.challenge_bytes(b"pad", &mut pad);
+    for (x, p) in data.iter_mut().zip(pad.iter()) {
+        *x ^= p;
+    }
+}
+
+fn compute_tag(t: &mut Transcript, ct: &[u8]) -> [u8; TAG_LEN] {
+    t.append_message(b"ct", ct);
+    let mut tag = [0u8; TAG_LEN];
+    t.challenge_bytes(b"tag", &mut tag);
+    tag
+}
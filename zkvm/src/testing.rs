@@ -0,0 +1,343 @@
+//! Harness for testing the contracts without creating the proofs.
+//!
+//! A test declares the initial contracts, the program that spends them,
+//! and the expected outcome: entries of the transaction log, output payloads or an error.
+//! The program is run with [`Verifier::verify_program_only`], so the secret constraints
+//! are checked against the witness data, but the signatures are not checked.
+//!
+//! Entries are compared by their text rendering, where the commitments are shown
+//! by the committed values without the blinding factors, and the outputs without the anchors.
+//! A mismatch is reported with a line diff of the expected and actual transaction logs.
+//!
+//! ```ignore
+//! ContractTest::new()
+//!     .input(contract)
+//!     .program(Program::build(|p| {
+//!         p.signtx().push(recipient.clone()).output(1);
+//!     }))
+//!     .expect_output(recipient, vec![PortableItem::Value(value)])
+//!     .run();
+//! ```
+
+use core::fmt::Write;
+
+use crate::constraints::Commitment;
+use crate::contract::{Contract, PortableItem};
+use crate::errors::VMError;
+use crate::predicate::Predicate;
+use crate::program::Program;
+use crate::tx::{TxEntry, TxHeader};
+use crate::types::String;
+use crate::verifier::{SimulatedTx, Verifier};
+use crate::vm::CURRENT_VERSION;
+
+/// Declaration of a contract test: the initial contracts, the program and the expected outcome.
+pub struct ContractTest {
+    header: TxHeader,
+    inputs: Vec<Contract>,
+    program: Program,
+    expectations: Vec<Expectation>,
+}
+
+enum Expectation {
+    /// Entire transaction log.
+    Log(Vec<TxEntry>),
+    /// Entry present in the transaction log.
+    Entry(TxEntry),
+    /// Output with a given predicate and payload.
+    Output(Predicate, Vec<PortableItem>),
+    /// Program fails with a given error.
+    Error(VMError),
+}
+
+impl ContractTest {
+    /// Creates a test with an empty program and no expectations
+    /// other than the successful execution.
+    pub fn new() -> Self {
+        ContractTest {
+            header: TxHeader {
+                version: CURRENT_VERSION,
+                mintime_ms: 0,
+                maxtime_ms: u64::max_value(),
+            },
+            inputs: Vec::new(),
+            program: Program::new(),
+            expectations: Vec::new(),
+        }
+    }
+
+    /// Sets the transaction header. By default, the header has the current version
+    /// and an unlimited time range.
+    pub fn set_header(mut self, header: TxHeader) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Adds an initial contract. The contracts are spent with `input` instruction
+    /// before the program runs, and are left on the stack in the order they were added.
+    pub fn input(mut self, contract: Contract) -> Self {
+        self.inputs.push(contract);
+        self
+    }
+
+    /// Sets the program that runs after the initial contracts are spent.
+    pub fn program(mut self, program: Program) -> Self {
+        self.program = program;
+        self
+    }
+
+    /// Expects the transaction log to consist of exactly the given entries.
+    pub fn expect_log(mut self, entries: Vec<TxEntry>) -> Self {
+        self.expectations.push(Expectation::Log(entries));
+        self
+    }
+
+    /// Expects the transaction log to contain a given entry.
+    pub fn expect_entry(mut self, entry: TxEntry) -> Self {
+        self.expectations.push(Expectation::Entry(entry));
+        self
+    }
+
+    /// Expects the transaction to create an output with a given predicate and payload.
+    pub fn expect_output(mut self, predicate: Predicate, payload: Vec<PortableItem>) -> Self {
+        self.expectations
+            .push(Expectation::Output(predicate, payload));
+        self
+    }
+
+    /// Expects the program to fail with a given error.
+    pub fn expect_error(mut self, error: VMError) -> Self {
+        self.expectations.push(Expectation::Error(error));
+        self
+    }
+
+    /// Runs the program and panics if the outcome does not match the expectations.
+    pub fn run(self) {
+        if let Err(report) = self.check() {
+            panic!("{}", report);
+        }
+    }
+
+    /// Runs the program and checks the outcome against the expectations.
+    /// Returns the report of the mismatches with the diffs of the transaction log.
+    pub fn check(self) -> Result<(), std::string::String> {
+        let mut program = Program::new();
+        for contract in self.inputs {
+            program.push(String::Output(Box::new(contract))).input();
+        }
+        let program = Program::from_vec(
+            program
+                .to_vec()
+                .into_iter()
+                .chain(self.program.to_vec())
+                .collect(),
+        );
+        let outcome = Verifier::verify_program_only(program, self.header);
+
+        let mut report = std::string::String::new();
+        let expected_error = self.expectations.iter().find_map(|e| match e {
+            Expectation::Error(err) => Some(err.clone()),
+            _ => None,
+        });
+        let stx = match (outcome, expected_error) {
+            (Ok(stx), None) => stx,
+            (Err(err), Some(expected)) if err == expected => return Ok(()),
+            (Err(err), Some(expected)) => {
+                return Err(format!(
+                    "Expected error: {}\n  Actual error: {}",
+                    expected, err
+                ))
+            }
+            (Err(err), None) => return Err(format!("Program failed: {}", err)),
+            (Ok(stx), Some(expected)) => {
+                return Err(format!(
+                    "Expected error: {}\nProgram succeeded with the log:\n{}",
+                    expected,
+                    render_log(&stx).join("\n")
+                ))
+            }
+        };
+
+        let actual = render_log(&stx);
+        for expectation in self.expectations.iter() {
+            let (title, expected) = match expectation {
+                Expectation::Log(entries) => {
+                    let expected: Vec<_> = entries.iter().map(render_entry).collect();
+                    if expected == actual {
+                        continue;
+                    }
+                    ("Transaction log does not match", expected)
+                }
+                Expectation::Entry(entry) => {
+                    let line = render_entry(entry);
+                    if actual.contains(&line) {
+                        continue;
+                    }
+                    ("Transaction log does not contain the entry", vec![line])
+                }
+                Expectation::Output(predicate, payload) => {
+                    let line = render_output(predicate, payload);
+                    if actual.contains(&line) {
+                        continue;
+                    }
+                    ("Transaction log does not contain the output", vec![line])
+                }
+                Expectation::Error(_) => continue,
+            };
+            let _ = writeln!(report, "{}:\n{}", title, diff(&expected, &actual));
+        }
+        if report.is_empty() {
+            Ok(())
+        } else {
+            Err(report)
+        }
+    }
+}
+
+fn render_log(stx: &SimulatedTx) -> Vec<std::string::String> {
+    stx.log.iter().map(render_entry).collect()
+}
+
+fn render_entry(entry: &TxEntry) -> std::string::String {
+    match entry {
+        TxEntry::Header(h) => format!("header v{} {}..{}", h.version, h.mintime_ms, h.maxtime_ms),
+        TxEntry::Issue(q, f) => format!(
+            "issue 0x{} 0x{}",
+            hex::encode(q.as_bytes()),
+            hex::encode(f.as_bytes())
+        ),
+        TxEntry::Retire(q, f) => format!(
+            "retire 0x{} 0x{}",
+            hex::encode(q.as_bytes()),
+            hex::encode(f.as_bytes())
+        ),
+        TxEntry::Burn(qty, flv) => format!("burn {} 0x{}", qty, hex::encode(flv.as_bytes())),
+        TxEntry::Input(id) => format!("input {}", id),
+        TxEntry::Output(contract) => render_output(&contract.predicate, &contract.payload),
+        TxEntry::Fee(fee) => format!("fee {}", fee),
+        TxEntry::Data(data) => format!("data 0x{}", hex::encode(data)),
+    }
+}
+
+fn render_output(predicate: &Predicate, payload: &[PortableItem]) -> std::string::String {
+    let items: Vec<_> = payload
+        .iter()
+        .map(|item| match item {
+            PortableItem::Value(v) => format!(
+                "Value{{{},{}}}",
+                render_commitment(&v.qty),
+                render_commitment(&v.flv)
+            ),
+            PortableItem::String(String::Commitment(c)) => render_commitment(c),
+            item => format!("{:?}", item),
+        })
+        .collect();
+    format!("output {:?} [{}]", predicate, items.join(", "))
+}
+
+/// Renders an open commitment by its value, so commitments with different blinding factors
+/// render the same. Values that fit in u64 are rendered as decimal integers.
+fn render_commitment(commitment: &Commitment) -> std::string::String {
+    match commitment.assignment() {
+        Some(value) => match value.to_u64() {
+            Ok(int) => format!("{}", int),
+            Err(_) => format!("0x{}", hex::encode(value.to_scalar().as_bytes())),
+        },
+        None => format!(
+            "Closed(0x{})",
+            hex::encode(commitment.to_point().as_bytes())
+        ),
+    }
+}
+
+/// Renders a line diff of the expected and actual lines:
+/// lines missing in the actual output are marked with `-`, unexpected lines with `+`.
+fn diff(expected: &[std::string::String], actual: &[std::string::String]) -> std::string::String {
+    // Lengths of the longest common subsequences of the suffixes.
+    let (n, m) = (expected.len(), actual.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = std::string::String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && expected[i] == actual[j] {
+            let _ = writeln!(out, "  {}", expected[i]);
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            let _ = writeln!(out, "- {}", expected[i]);
+            i += 1;
+        } else {
+            let _ = writeln!(out, "+ {}", actual[j]);
+            j += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::Anchor;
+    use crate::types::Value;
+    use curve25519_dalek::scalar::Scalar;
+    use musig::VerificationKey;
+
+    fn lines(s: &[&str]) -> Vec<std::string::String> {
+        s.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn line_diff() {
+        let expected = lines(&["input a", "output b", "fee 1"]);
+        let actual = lines(&["input a", "output c", "fee 1", "data 0x"]);
+        assert_eq!(
+            diff(&expected, &actual),
+            "  input a\n- output b\n+ output c\n  fee 1\n+ data 0x\n"
+        );
+    }
+
+    #[test]
+    fn expected_output() {
+        let pred = Predicate::new(VerificationKey::from_secret(&Scalar::from(1u64)));
+        let value = |qty: u64| Value {
+            qty: Commitment::blinded(qty),
+            flv: Commitment::blinded(Scalar::from(1u64)),
+        };
+        let contract = Contract {
+            predicate: pred.clone(),
+            payload: vec![PortableItem::Value(value(10))],
+            anchor: Anchor::from_raw_bytes([0u8; 32]),
+        };
+        let test = |expected_qty: u64| {
+            ContractTest::new()
+                .input(contract.clone())
+                .program(Program::build(|p| {
+                    p.signtx().push(pred.clone()).output(1);
+                }))
+                .expect_output(pred.clone(), vec![PortableItem::Value(value(expected_qty))])
+        };
+
+        assert_eq!(test(10).check(), Ok(()));
+
+        // blinding factors differ, but the values are compared
+        let report = test(9).check().unwrap_err();
+        assert!(report.contains("- output"));
+        assert!(report.contains("+ output"));
+
+        let report = test(10)
+            .expect_error(VMError::StackUnderflow)
+            .check()
+            .unwrap_err();
+        assert!(report.starts_with("Expected error"));
+    }
+}
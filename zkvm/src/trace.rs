@@ -0,0 +1,38 @@
+//! Step-by-step trace of the program execution.
+//!
+//! The trace records the VM stack after each executed instruction,
+//! so debuggers and explorers can show how the stack evolves through the program.
+//! Items are rendered in the human-readable format of the programs (see `Debug` for `Program`).
+//! Instructions of the nested programs (`call`, `eval`, `select`) are traced
+//! with the depth of the program that runs them.
+
+use serde::Serialize;
+
+use crate::errors::VMError;
+
+/// Trace of a transaction's program execution.
+#[derive(Clone, Debug, Default)]
+pub struct Trace {
+    /// Executed instructions with the stack after each of them.
+    pub steps: Vec<TraceStep>,
+
+    /// Error that stopped the execution, if any.
+    /// The failed instruction is not included in the steps.
+    pub error: Option<VMError>,
+}
+
+/// State of the VM after an executed instruction.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TraceStep {
+    /// Executed instruction.
+    pub instruction: String,
+
+    /// Number of the outer programs waiting for the current one to finish.
+    pub depth: usize,
+
+    /// Items on the stack, from the bottom to the top.
+    pub stack: Vec<String>,
+
+    /// Number of the entries in the transaction log.
+    pub log_len: usize,
+}
@@ -1,18 +1,24 @@
 use bulletproofs::r1cs::R1CSProof;
 use bulletproofs::BulletproofGens;
 use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
 use musig::Signature;
 use serde::{Deserialize, Serialize};
+use spacesuit::CircuitSize;
 
 use crate::contract::{Contract, ContractID};
 use crate::encoding::*;
 use crate::errors::VMError;
 use crate::fees::FeeRate;
-use crate::merkle::{Hash, MerkleItem, MerkleTree};
+use crate::merkle::{Hash, MerkleItem, MerkleRootBuilder, MerkleTree};
 use crate::predicate::Predicate;
+use crate::profile::Profile;
+use crate::program::Program;
+use crate::prover::{Prover, ProverContext, WitnessBundle};
+use crate::trace::Trace;
 use crate::transcript::TranscriptProtocol;
-use crate::verifier::Verifier;
+use crate::verifier::{DeferredVerification, Verifier};
 
 /// Transaction log, a list of all effects of a transaction called [entries](TxEntry).
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -20,9 +26,25 @@ use crate::verifier::Verifier;
 pub struct TxLog(Vec<TxEntry>);
 
 /// Transaction ID is a unique 32-byte identifier of a transaction effects represented by `TxLog`.
-#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(transparent)]
+/// It does not commit to the proof and signatures, so the same transaction
+/// may be relayed in several encodings with the same ID.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub struct TxID(pub Hash);
+bytes32_id!(TxID, Hash);
+
+/// Incremental builder of the [TxID](TxID): the entries are hashed as they are appended
+/// to the transaction log, so the ID does not require a second pass over the log.
+/// The intermediate root over the entries appended so far can be read at any time.
+pub struct TxIDBuilder {
+    builder: MerkleRootBuilder<TxEntry>,
+    len: usize,
+}
+
+/// Wire hash is a 32-byte hash of the serialized transaction, including the proof and signatures.
+/// Unlike `TxID`, it distinguishes between different encodings of the same transaction.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TxWireHash(pub Hash);
+bytes32_id!(TxWireHash, Hash);
 
 /// Entry in a transaction log. All entries are hashed into a [transaction ID](TxID).
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -34,6 +56,9 @@ pub enum TxEntry {
     Issue(CompressedRistretto, CompressedRistretto),
     /// Asset retirement entry that consists of a _flavor commitment_ and a _quantity commitment_.
     Retire(CompressedRistretto, CompressedRistretto),
+    /// Issuer-authorized retirement entry that consists of a cleartext quantity and a flavor,
+    /// created by [`burn`](crate::ops::Instruction::Burn) instruction.
+    Burn(u64, Scalar),
     /// Input entry that signals that a contract was spent. Contains the [ID](crate::contract::ContractID) of a contract.
     Input(ContractID),
     /// Output entry that signals that a contract was created. Contains the [Contract](crate::contract::Contract).
@@ -57,7 +82,19 @@ pub struct TxHeader {
     pub maxtime_ms: u64,
 }
 
-/// Instance of a transaction that is not signed yet.
+/// Transaction that is not proven yet: the header and the program
+/// with all the witness data (commitment openings, scalar witnesses and predicate witnesses).
+/// Proving it produces an `UnsignedTx` with the bytecode and the proof, but without the witnesses.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UnprovenTx {
+    /// Header metadata
+    pub header: TxHeader,
+
+    /// Program with the witness data
+    pub program: Program,
+}
+
+/// Instance of a transaction that is proven, but not signed yet.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct UnsignedTx {
     /// Header metadata
@@ -75,9 +112,20 @@ pub struct UnsignedTx {
     /// Log of tx entries
     pub txlog: TxLog,
 
-    /// List of (key,contractid) pairs for multi-message signature
+    /// List of (key,message) pairs for multi-message signature
     /// TBD: change to some key witness type
-    pub signing_instructions: Vec<(Predicate, ContractID)>,
+    pub signing_instructions: Vec<(Predicate, SigningMessage)>,
+}
+
+/// Message signed by a key in the aggregated transaction signature.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SigningMessage {
+    /// ID of the contract unlocked with `signtx`.
+    Contract(ContractID),
+
+    /// Digest of the `signid` or `signtag` statement with an empty signature string.
+    /// Since tx version 2 such statements are signed together with the transaction.
+    Statement([u8; 32]),
 }
 
 /// Instance of a transaction that contains all necessary data to validate it.
@@ -110,6 +158,9 @@ pub struct PrecomputedTx {
     /// Fee rate of the transaction
     pub feerate: FeeRate,
 
+    /// Consensus weight of the transaction (see `tx_weight`)
+    pub weight: u64,
+
     /// Verifier to continue verification of the transaction
     pub(crate) verifier: Verifier,
 
@@ -134,6 +185,9 @@ pub struct VerifiedTx {
 
     /// Fee rate of the transaction
     pub feerate: FeeRate,
+
+    /// Consensus weight of the transaction (see `tx_weight`)
+    pub weight: u64,
 }
 
 impl Encodable for TxHeader {
@@ -160,7 +214,53 @@ impl Decodable for TxHeader {
     }
 }
 
+impl AsRef<[u8]> for SigningMessage {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            SigningMessage::Contract(id) => id.as_ref(),
+            SigningMessage::Statement(digest) => &digest[..],
+        }
+    }
+}
+
+impl UnprovenTx {
+    /// Creates a transaction from a header and a program.
+    pub fn new(header: TxHeader, program: Program) -> Self {
+        UnprovenTx { header, program }
+    }
+
+    /// Creates the proof of the transaction, returning the transaction to be signed.
+    pub fn prove(self, bp_gens: &BulletproofGens) -> Result<UnsignedTx, VMError> {
+        Prover::build_tx(self.program, self.header, bp_gens)
+    }
+
+    /// Creates the proof of the transaction with the generators of the context.
+    pub fn prove_with_context(self, ctx: &mut ProverContext) -> Result<UnsignedTx, VMError> {
+        ctx.build_tx(self.program, self.header)
+    }
+
+    /// Packs the transaction for proving on another machine.
+    /// Predicate witnesses are not serialized, but the predicate trees are carried in the bundle.
+    pub fn into_witness_bundle(self) -> WitnessBundle {
+        WitnessBundle::new(self.program, self.header)
+    }
+}
+
+impl From<WitnessBundle> for UnprovenTx {
+    fn from(bundle: WitnessBundle) -> Self {
+        UnprovenTx::new(bundle.header, bundle.program)
+    }
+}
+
 impl UnsignedTx {
+    /// Returns the transcript for the aggregated transaction signature
+    /// over the messages in `signing_instructions`.
+    pub fn signing_transcript(&self) -> Transcript {
+        let mut t = Transcript::new(b"ZkVM.signtx");
+        t.append_message(b"txid", &self.txid.0);
+        t
+    }
+
     /// Attaches the signature to the transaction.
     pub fn sign(self, signature: Signature) -> Tx {
         Tx {
@@ -222,12 +322,60 @@ impl Tx {
         Verifier::precompute(self)
     }
 
+    /// Computes the TxID and TxLog like `precompute`,
+    /// and returns the per-opcode execution profile of the verifier.
+    pub fn precompute_with_profile(&self) -> Result<(PrecomputedTx, Profile), VMError> {
+        Verifier::precompute_with_profile(self)
+    }
+
+    /// Runs the program without verifying the transaction,
+    /// recording the stack after each instruction.
+    pub fn trace(&self) -> Trace {
+        Verifier::trace(self)
+    }
+
     /// Performs stateless verification of the transaction:
     /// logic, signatures and ZK R1CS proof.
     pub fn verify(&self, bp_gens: &BulletproofGens) -> Result<VerifiedTx, VMError> {
         self.precompute()?.verify(bp_gens)
     }
 
+    /// Performs stateless verification of the transaction,
+    /// deferring the signature checks to the `deferred` batch.
+    pub fn verify_deferred(
+        &self,
+        bp_gens: &BulletproofGens,
+        deferred: &mut DeferredVerification,
+    ) -> Result<VerifiedTx, VMError> {
+        self.precompute()?.verify_deferred(bp_gens, deferred)
+    }
+
+    /// Computes the consensus weight of the transaction (see `tx_weight`)
+    /// used for the block weight limit.
+    /// The program is executed to count the output payloads and the multipliers,
+    /// but the proofs are not verified.
+    pub fn weight(&self) -> Result<u64, VMError> {
+        Ok(self.precompute()?.weight())
+    }
+
+    /// Estimates the size of the constraint system verified with this transaction.
+    /// Use `CircuitSize::padded_multipliers` to pick the size of `BulletproofGens`
+    /// and to account for the verification cost when estimating the fees.
+    pub fn estimated_verification_cost(&self) -> Result<CircuitSize, VMError> {
+        Ok(Program::parse(&self.program)?.estimated_circuit_size_for_version(self.header.version))
+    }
+
+    /// Computes the hash of the serialized transaction.
+    /// Use `TxID` to identify the transaction effects, and the wire hash
+    /// to identify its particular encoding (e.g. for relay deduplication).
+    pub fn wire_hash(&self) -> TxWireHash {
+        let mut t = Transcript::new(b"ZkVM.tx_wire_hash");
+        t.append_message(b"tx", &self.encode_to_vec());
+        let mut result = [0u8; 32];
+        t.challenge_bytes(b"hash", &mut result);
+        TxWireHash(Hash(result))
+    }
+
     /// Serializes the tx into a byte array.
     pub fn to_bytes(&self) -> Vec<u8> {
         self.encode_to_vec()
@@ -244,6 +392,11 @@ impl Tx {
 }
 
 impl PrecomputedTx {
+    /// Returns the consensus weight of the transaction.
+    pub fn weight(&self) -> u64 {
+        self.weight
+    }
+
     /// Completes verification of the transaction,
     /// performing expensive checks of the R1CS proof, Schnorr signatures
     /// and other Ristretto255 operations.
@@ -251,6 +404,17 @@ impl PrecomputedTx {
         Verifier::verify_tx(self, bp_gens)
     }
 
+    /// Completes verification of the transaction, except for the Schnorr signatures
+    /// and other Ristretto255 operations that are added to the `deferred` batch.
+    /// The transaction is valid only if the batch verifies too.
+    pub fn verify_deferred(
+        self,
+        bp_gens: &BulletproofGens,
+        deferred: &mut DeferredVerification,
+    ) -> Result<VerifiedTx, VMError> {
+        Verifier::verify_tx_deferred(self, bp_gens, deferred)
+    }
+
     /// Verifies a batch of transactions, typically coming from a Block.
     pub fn verify_batch(
         txs: impl IntoIterator<Item = Self>,
@@ -262,6 +426,13 @@ impl PrecomputedTx {
     }
 }
 
+impl VerifiedTx {
+    /// Returns the consensus weight of the transaction.
+    pub fn weight(&self) -> u64 {
+        self.weight
+    }
+}
+
 impl TxEntry {
     /// Converts entry to the input and provides its contract ID.
     pub fn as_input(&self) -> Option<ContractID> {
@@ -347,13 +518,50 @@ impl MerkleItem for TxID {
 impl TxID {
     /// Computes TxID from a tx log
     pub fn from_log(list: &[TxEntry]) -> Self {
-        TxID(MerkleTree::root(b"ZkVM.txid", list))
+        let mut builder = TxIDBuilder::new();
+        for entry in list.iter() {
+            builder.append(entry);
+        }
+        builder.txid()
     }
 }
 
-impl AsRef<[u8]> for TxID {
-    fn as_ref(&self) -> &[u8] {
-        &self.0
+impl Default for TxIDBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TxIDBuilder {
+    /// Creates a builder for an empty log.
+    pub fn new() -> Self {
+        TxIDBuilder {
+            builder: MerkleTree::build_root(b"ZkVM.txid"),
+            len: 0,
+        }
+    }
+
+    /// Hashes the next entry of the log.
+    pub fn append(&mut self, entry: &TxEntry) {
+        self.builder.append(entry);
+        self.len += 1;
+    }
+
+    /// Number of entries appended so far.
+    pub fn entries_count(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the merkle root over the entries appended so far.
+    /// Equals the TxID of the log truncated to `entries_count()` entries,
+    /// so the streaming verifier can check the log as it arrives.
+    pub fn intermediate_root(&self) -> Hash {
+        self.builder.root()
+    }
+
+    /// Returns the TxID of the complete log.
+    pub fn txid(&self) -> TxID {
+        TxID(self.builder.root())
     }
 }
 
@@ -386,6 +594,10 @@ impl MerkleItem for TxEntry {
                 t.commit_point(b"retire.q", q);
                 t.commit_point(b"retire.f", f);
             }
+            TxEntry::Burn(q, f) => {
+                t.append_u64(b"burn.q", *q);
+                t.append_message(b"burn.f", f.as_bytes());
+            }
             TxEntry::Input(contract) => {
                 t.append_message(b"input", contract.as_bytes());
             }
@@ -447,4 +659,22 @@ mod tests {
         };
         assert!(path.verify_root(&txid.0, &entry, &hasher) == false);
     }
+
+    #[test]
+    fn incremental_txid() {
+        let entries = txlog_helper();
+        let mut builder = TxIDBuilder::new();
+        for (i, entry) in entries.iter().enumerate() {
+            builder.append(entry);
+            assert_eq!(builder.entries_count(), i + 1);
+            assert_eq!(
+                builder.intermediate_root(),
+                TxID::from_log(&entries[..=i]).0
+            );
+        }
+        assert_eq!(
+            builder.txid(),
+            TxID(MerkleTree::root(b"ZkVM.txid", &entries))
+        );
+    }
 }
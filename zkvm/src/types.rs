@@ -1,5 +1,6 @@
 //! Core ZkVM stack types: data, variables, values, contracts etc.
 
+use bytes::Bytes;
 use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
 use musig::VerificationKey;
@@ -76,7 +77,9 @@ pub enum DroppableItem {
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum String {
     /// Opaque data item.
-    Opaque(Vec<u8>),
+    /// The bytes are reference-counted: parsing a program slices the pushed strings
+    /// out of the program buffer, and clones of the item share the same buffer.
+    Opaque(Bytes),
 
     /// A predicate.
     Predicate(Box<Predicate>),
@@ -250,15 +253,25 @@ impl ExactSizeEncodable for String {
 
 impl String {
     /// Converts the String item into a vector of bytes.
-    /// Opaque item is converted without extra allocations,
+    /// Opaque item is copied out of its shared buffer,
     /// non-opaque item is encoded to a newly allocated buffer.
     pub fn to_bytes(self) -> Vec<u8> {
         match self {
-            String::Opaque(d) => d,
+            String::Opaque(d) => d.to_vec(),
             _ => self.encode_to_vec(),
         }
     }
 
+    /// Converts the String item into shared bytes.
+    /// Opaque item is converted without extra allocations,
+    /// non-opaque item is encoded to a newly allocated buffer.
+    pub fn to_opaque(self) -> Bytes {
+        match self {
+            String::Opaque(d) => d,
+            _ => self.encode_to_vec().into(),
+        }
+    }
+
     /// Downcast the data item to a `Predicate` type.
     pub fn to_predicate(self) -> Result<Predicate, VMError> {
         match self {
@@ -331,7 +344,7 @@ impl String {
 
 impl Default for String {
     fn default() -> Self {
-        String::Opaque(Vec::new())
+        String::Opaque(Bytes::new())
     }
 }
 
@@ -340,7 +353,7 @@ impl Value {
     pub fn issue_flavor(predicate: &Predicate, metadata: String) -> Scalar {
         let mut t = Transcript::new(b"ZkVM.issue");
         t.append_message(b"predicate", predicate.to_point().as_bytes());
-        t.append_message(b"metadata", &metadata.to_bytes());
+        t.append_message(b"metadata", &metadata.to_opaque());
         t.challenge_scalar(b"flavor")
     }
 
@@ -1,19 +1,26 @@
 use bulletproofs::r1cs;
 use bulletproofs::r1cs::ConstraintSystem;
 use bulletproofs::{BulletproofGens, PedersenGens};
+use bytes::Bytes;
 use curve25519_dalek::ristretto::CompressedRistretto;
-use merlin::Transcript;
+use curve25519_dalek::scalar::Scalar;
+use merlin::{Transcript, TranscriptRng};
 use musig::{Multisignature, VerificationKey};
+use spacesuit::BitRange;
 
-use crate::constraints::Commitment;
-use crate::contract::ContractID;
-use crate::encoding::{ExactSizeEncodable, Reader};
+use crate::arena::Arena;
+use crate::constraints::{Commitment, Constraint};
+use crate::encoding::ExactSizeEncodable;
+use crate::entropy::{entropy_rng, EntropyRng};
 use crate::errors::VMError;
-use crate::fees::FeeRate;
+use crate::fees::{tx_weight, CheckedFee, FeeRate};
 use crate::ops::Instruction;
 use crate::predicate::Predicate;
-use crate::program::ProgramItem;
-use crate::tx::{PrecomputedTx, Tx, VerifiedTx};
+use crate::profile::Profile;
+use crate::program::{Program, ProgramItem};
+use crate::prover::ProverRun;
+use crate::trace::Trace;
+use crate::tx::{PrecomputedTx, SigningMessage, Tx, TxHeader, TxID, TxLog, VerifiedTx};
 use crate::vm::{Delegate, VM};
 
 /// This is the entry point API for verifying a transaction.
@@ -21,21 +28,63 @@ use crate::vm::{Delegate, VM};
 /// verifies an aggregated transaction signature (see `signtx` instruction),
 /// verifies a R1CS proof and returns a `VerifiedTx` with the log of changes
 /// to be applied to the blockchain state.
+///
+/// All signature checks (`signtx`, `signid`, `signtag`), taproot `call` proofs
+/// and other deferred point operations are verified together in a single batch.
 pub struct Verifier {
-    signtx_items: Vec<(VerificationKey, ContractID)>,
+    signtx_items: Vec<(VerificationKey, SigningMessage)>,
     cs: r1cs::Verifier<Transcript>,
-    batch: musig::BatchVerifier<rand::rngs::ThreadRng>,
+    batch: musig::BatchVerifier<TranscriptRng>,
+    arena: Arena,
+    // hash of the transaction that binds the weight of its statements in the deferred batch
+    binding: [u8; 32],
+}
+
+/// Deferred point operations of the transactions verified with
+/// [`PrecomputedTx::verify_deferred`](crate::PrecomputedTx::verify_deferred):
+/// signatures, taproot proofs, unblinding and issuance checks.
+/// Statements of many transactions (e.g. across several blocks during the initial sync)
+/// are checked together in a single batch.
+/// The statements of each transaction are scaled by a weight bound to all the transactions
+/// added before it, so the transactions cannot be crafted to cancel each other out,
+/// even on targets without an entropy source.
+pub struct DeferredVerification {
+    transcript: Transcript,
+    batch: musig::BatchVerifier<TranscriptRng>,
+}
+
+/// Result of running a program with [`Verifier::verify_program_only`].
+#[derive(Clone, Debug)]
+pub struct SimulatedTx {
+    /// Transaction ID
+    pub id: TxID,
+
+    /// Transaction log: a list of changes to the blockchain state (UTXOs to delete/insert, etc.)
+    pub log: TxLog,
+
+    /// Total fee paid by the transaction
+    pub fee: CheckedFee,
+
+    /// Keys that have to sign the transaction (see `signtx` instruction).
+    pub signing_keys: Vec<VerificationKey>,
+}
+
+/// Delegate of [`Verifier::verify_program_only`]: runs the program with the witness data
+/// and checks the constraints against it instead of proving them.
+struct Simulator<'g> {
+    signing_keys: Vec<VerificationKey>,
+    cs: r1cs::Prover<'g, Transcript>,
+    batch: musig::BatchVerifier<EntropyRng>,
 }
 
 /// Verifier's implementation of the running state of the program.
 pub struct VerifierRun {
-    program: Vec<u8>,
-    offset: usize,
+    program: Bytes,
 }
 
 impl Delegate<r1cs::Verifier<Transcript>> for Verifier {
     type RunType = VerifierRun;
-    type BatchVerifier = musig::BatchVerifier<rand::rngs::ThreadRng>;
+    type BatchVerifier = musig::BatchVerifier<TranscriptRng>;
 
     fn commit_variable(
         &mut self,
@@ -49,30 +98,32 @@ impl Delegate<r1cs::Verifier<Transcript>> for Verifier {
     fn process_tx_signature(
         &mut self,
         pred: Predicate,
-        contract_id: ContractID,
+        msg: SigningMessage,
     ) -> Result<(), VMError> {
         // TBD: store predicate instead
         let key = pred.verification_key();
-        Ok(self.signtx_items.push((key, contract_id)))
+        Ok(self.signtx_items.push((key, msg)))
     }
 
     fn next_instruction(
         &mut self,
         run: &mut Self::RunType,
     ) -> Result<Option<Instruction>, VMError> {
-        if run.offset == run.program.len() {
+        if run.program.is_empty() {
             return Ok(None);
         }
-        let mut reader = &run.program[run.offset..];
-        let instr = Instruction::parse(&mut reader)?;
-        run.offset = run.program.len() - reader.remaining_bytes();
+        let instr = Instruction::parse_shared(&mut run.program, &mut self.arena)?;
         Ok(Some(instr))
     }
 
-    fn new_run(&self, prog: ProgramItem) -> Result<Self::RunType, VMError> {
+    fn new_run(&mut self, prog: ProgramItem) -> Result<Self::RunType, VMError> {
         Ok(VerifierRun::new(prog.to_bytecode()?))
     }
 
+    fn recycle_bytes(&mut self, bytes: Vec<u8>) {
+        self.arena.recycle(bytes);
+    }
+
     fn cs(&mut self) -> &mut r1cs::Verifier<Transcript> {
         &mut self.cs
     }
@@ -82,7 +133,124 @@ impl Delegate<r1cs::Verifier<Transcript>> for Verifier {
     }
 }
 
+impl<'g> Delegate<r1cs::Prover<'g, Transcript>> for Simulator<'g> {
+    type RunType = ProverRun;
+    type BatchVerifier = musig::BatchVerifier<EntropyRng>;
+
+    fn commit_variable(
+        &mut self,
+        com: &Commitment,
+    ) -> Result<(CompressedRistretto, r1cs::Variable), VMError> {
+        let (v, v_blinding) = com.witness().ok_or(VMError::WitnessMissing)?;
+        Ok(self.cs.commit(v.into(), v_blinding))
+    }
+
+    fn process_tx_signature(
+        &mut self,
+        pred: Predicate,
+        _msg: SigningMessage,
+    ) -> Result<(), VMError> {
+        self.signing_keys.push(pred.verification_key());
+        Ok(())
+    }
+
+    fn next_instruction(
+        &mut self,
+        run: &mut Self::RunType,
+    ) -> Result<Option<Instruction>, VMError> {
+        Ok(run.program.pop_front())
+    }
+
+    fn new_run(&mut self, data: ProgramItem) -> Result<Self::RunType, VMError> {
+        Ok(ProverRun {
+            program: data.to_program()?.to_vec().into(),
+        })
+    }
+
+    fn cs(&mut self) -> &mut r1cs::Prover<'g, Transcript> {
+        &mut self.cs
+    }
+
+    fn batch_verifier(&mut self) -> &mut Self::BatchVerifier {
+        &mut self.batch
+    }
+
+    fn check_constraint(&mut self, constraint: &Constraint) -> Result<(), VMError> {
+        match constraint.assignment() {
+            Some(true) => Ok(()),
+            Some(false) => Err(VMError::SecretConstraintFalse),
+            None => Err(VMError::WitnessMissing),
+        }
+    }
+
+    fn check_cloak(
+        &mut self,
+        inputs: &[spacesuit::AllocatedValue],
+        outputs: &[spacesuit::AllocatedValue],
+        range: BitRange,
+    ) -> Result<(), VMError> {
+        let bits: usize = range.into();
+        // Net quantity of each flavor: inputs minus outputs.
+        let mut balances: Vec<(Scalar, Scalar)> = Vec::new();
+        let mut add = |flv: Scalar, qty: Scalar| match balances.iter_mut().find(|(f, _)| *f == flv)
+        {
+            Some((_, net)) => *net += qty,
+            None => balances.push((flv, qty)),
+        };
+        for value in inputs.iter() {
+            let value = value.assignment.ok_or(VMError::WitnessMissing)?;
+            add(value.f, value.q.to_scalar());
+        }
+        for value in outputs.iter() {
+            let value = value.assignment.ok_or(VMError::WitnessMissing)?;
+            match value.q.to_u128() {
+                Some(qty) if qty.checked_shr(bits as u32).unwrap_or(0) == 0 => {}
+                _ => return Err(VMError::UnbalancedCloak),
+            }
+            add(value.f, -value.q.to_scalar());
+        }
+        if balances.iter().any(|(_, net)| *net != Scalar::zero()) {
+            return Err(VMError::UnbalancedCloak);
+        }
+        Ok(())
+    }
+}
+
 impl Verifier {
+    /// Runs the program with its witness data without creating and verifying the proofs,
+    /// so the contract logic can be tested before paying the proving costs.
+    /// The inputs are the contracts pushed by the program, they are not checked against the utxo set.
+    ///
+    /// Secret constraints added by `verify` and the balance of the `cloak` outputs
+    /// are checked against the witness data. Range proofs (`range`), signatures
+    /// (`signtx`, `signid`, `signtag`), taproot `call` proofs, `unblind` and `issue`
+    /// statements are not checked: the keys to sign the transaction are returned instead.
+    /// Fails with `VMError::WitnessMissing` if a committed value or a constraint lacks the witness.
+    pub fn verify_program_only(program: Program, header: TxHeader) -> Result<SimulatedTx, VMError> {
+        let pc_gens = PedersenGens::default();
+        let mut simulator = Simulator {
+            signing_keys: Vec::new(),
+            cs: r1cs::Prover::new(&pc_gens, Transcript::new(b"ZkVM.r1cs")),
+            batch: musig::BatchVerifier::new(entropy_rng()),
+        };
+
+        let vm = VM::new(
+            header,
+            ProverRun {
+                program: program.to_vec().into(),
+            },
+            &mut simulator,
+        );
+        let (id, log, fee) = vm.run()?;
+
+        Ok(SimulatedTx {
+            id,
+            log,
+            fee,
+            signing_keys: simulator.signing_keys,
+        })
+    }
+
     /// Precomputes the TxID and TxLog.
     /// This is a private API until we have a nicer composable API with precomputed tx.
     /// See public API `Tx::precompute() that wraps with method`
@@ -90,12 +258,43 @@ impl Verifier {
     /// only holds a &mut of the transcript that can only be parked in the lexical scope,
     /// but not in the struct. And we need CS instance both for building tx and for verifying.
     pub(crate) fn precompute(tx: &Tx) -> Result<PrecomputedTx, VMError> {
+        Self::run_vm(tx, false).map(|(ptx, _)| ptx)
+    }
+
+    /// Precomputes the TxID and TxLog, collecting the per-opcode execution profile.
+    pub(crate) fn precompute_with_profile(tx: &Tx) -> Result<(PrecomputedTx, Profile), VMError> {
+        let (ptx, profile) = Self::run_vm(tx, true)?;
+        Ok((ptx, profile.unwrap_or_default()))
+    }
+
+    /// Runs the program of the `Tx` object, recording the stack after each instruction.
+    pub(crate) fn trace(tx: &Tx) -> Trace {
+        let (rng, binding) = Self::batch_rng(tx);
+        let mut verifier = Verifier {
+            signtx_items: Vec::new(),
+            cs: r1cs::Verifier::new(Transcript::new(b"ZkVM.r1cs")),
+            batch: musig::BatchVerifier::new(rng),
+            arena: Arena::new(),
+            binding,
+        };
+        VM::new(
+            tx.header,
+            VerifierRun::new(tx.program.clone()),
+            &mut verifier,
+        )
+        .run_with_trace()
+    }
+
+    fn run_vm(tx: &Tx, profiling: bool) -> Result<(PrecomputedTx, Option<Profile>), VMError> {
         let cs = r1cs::Verifier::new(Transcript::new(b"ZkVM.r1cs"));
 
+        let (rng, binding) = Self::batch_rng(tx);
         let mut verifier = Verifier {
             signtx_items: Vec::new(),
             cs: cs,
-            batch: musig::BatchVerifier::new(rand::thread_rng()),
+            batch: musig::BatchVerifier::new(rng),
+            arena: Arena::new(),
+            binding,
         };
 
         let vm = VM::new(
@@ -104,17 +303,33 @@ impl Verifier {
             &mut verifier,
         );
 
-        let (id, log, fee) = vm.run()?;
+        let (id, log, fee, profile) = if profiling {
+            let (id, log, fee, profile) = vm.run_with_profile()?;
+            (id, log, fee, Some(profile))
+        } else {
+            let (id, log, fee) = vm.run()?;
+            (id, log, fee, None)
+        };
+
+        let payload_bytes = log
+            .outputs()
+            .flat_map(|contract| contract.payload.iter())
+            .map(|item| item.encoded_size())
+            .sum();
+        let multipliers = verifier.cs.metrics().multipliers;
+        let weight = tx_weight(tx.program.len(), payload_bytes, multipliers);
 
-        Ok(PrecomputedTx {
+        let ptx = PrecomputedTx {
             header: tx.header,
             id,
             log,
-            feerate: FeeRate::new(fee, tx.encoded_size()),
+            feerate: FeeRate::new(fee, weight as usize),
+            weight,
             signature: tx.signature.clone(),
             proof: tx.proof.clone(),
             verifier,
-        })
+        };
+        Ok((ptx, profile))
     }
 
     /// Verifies the `Tx` object by executing the VM and returns the `VerifiedTx`.
@@ -122,6 +337,20 @@ impl Verifier {
     pub fn verify_tx(
         verifiable_tx: PrecomputedTx,
         bp_gens: &BulletproofGens,
+    ) -> Result<VerifiedTx, VMError> {
+        let mut deferred = DeferredVerification::new();
+        let verified_tx = Self::verify_tx_deferred(verifiable_tx, bp_gens, &mut deferred)?;
+        deferred.verify()?;
+        Ok(verified_tx)
+    }
+
+    /// Verifies the R1CS proof of the `Tx` object and adds the signatures
+    /// and other deferred point operations to the `deferred` batch.
+    /// The transaction is valid only if the batch verifies too.
+    pub fn verify_tx_deferred(
+        verifiable_tx: PrecomputedTx,
+        bp_gens: &BulletproofGens,
+        deferred: &mut DeferredVerification,
     ) -> Result<VerifiedTx, VMError> {
         let pc_gens = PedersenGens::default();
 
@@ -130,6 +359,7 @@ impl Verifier {
             id,
             log,
             feerate,
+            weight,
             signature,
             proof,
             mut verifier,
@@ -156,23 +386,81 @@ impl Verifier {
             );
         }
 
-        // Verify all deferred crypto operations.
-        verifier
-            .batch
-            .verify()
-            .map_err(|_| VMError::BatchSignatureVerificationFailed)?;
+        // Defer all crypto operations to the caller's batch.
+        deferred.append_weighted(&verifier.binding, verifier.batch);
 
         Ok(VerifiedTx {
             header,
             id,
             log,
             feerate,
+            weight,
         })
     }
+
+    /// Creates an RNG for the batch weights bound to the entire transaction.
+    /// Each deferred statement (signatures, taproot proofs, unblinding and issuance checks)
+    /// is verified in a single batch with independent random weights.
+    /// Weights are derived from the tx bytes in addition to local entropy,
+    /// so they remain unpredictable to the author of the transaction even if the local RNG is weak,
+    /// and invalid statements cannot be crafted to cancel each other out.
+    /// Also returns the hash of the transaction that binds its weight in the deferred batch.
+    fn batch_rng(tx: &Tx) -> (TranscriptRng, [u8; 32]) {
+        let mut t = Transcript::new(b"ZkVM.batch");
+        t.append_message(b"tx", &tx.to_bytes());
+        let rng = t.build_rng().finalize(&mut entropy_rng());
+        let mut binding = [0u8; 32];
+        t.challenge_bytes(b"binding", &mut binding);
+        (rng, binding)
+    }
+}
+
+impl DeferredVerification {
+    /// Creates an empty batch.
+    pub fn new() -> Self {
+        let transcript = Transcript::new(b"ZkVM.deferred");
+        let rng = transcript.build_rng().finalize(&mut entropy_rng());
+        DeferredVerification {
+            transcript,
+            batch: musig::BatchVerifier::new(rng),
+        }
+    }
+
+    /// Adds all the statements collected by another batch.
+    pub fn append(&mut self, mut other: DeferredVerification) {
+        let mut binding = [0u8; 32];
+        other.transcript.challenge_bytes(b"binding", &mut binding);
+        self.append_weighted(&binding, other.batch);
+    }
+
+    /// Adds the statements of a transaction (or another batch) with a given binding hash,
+    /// scaled by a weight derived from the bindings of all the statements added so far.
+    fn append_weighted(&mut self, binding: &[u8; 32], batch: musig::BatchVerifier<TranscriptRng>) {
+        self.transcript.append_message(b"binding", binding);
+        let mut rng = self.transcript.build_rng().finalize(&mut entropy_rng());
+        self.batch
+            .append_batch_weighted(batch, Scalar::random(&mut rng));
+    }
+
+    /// Verifies all the deferred statements.
+    /// Returns an error if any of them is not valid, without telling which one.
+    pub fn verify(self) -> Result<(), VMError> {
+        self.batch
+            .verify()
+            .map_err(|_| VMError::BatchSignatureVerificationFailed)
+    }
+}
+
+impl Default for DeferredVerification {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VerifierRun {
     fn new(program: Vec<u8>) -> Self {
-        VerifierRun { program, offset: 0 }
+        VerifierRun {
+            program: program.into(),
+        }
     }
 }
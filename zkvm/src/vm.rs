@@ -1,28 +1,78 @@
 use bulletproofs::r1cs;
+use bulletproofs::r1cs::ConstraintSystem;
+use bytes::Bytes;
 use core::iter;
 use core::iter::FromIterator;
+use core::mem;
 use curve25519_dalek::ristretto::CompressedRistretto;
 use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
 use musig::{BatchVerification, Signature};
 use spacesuit;
 use spacesuit::BitRange;
-use std::mem;
+use std::collections::HashSet;
+use std::time::Instant;
 
 use crate::constraints::{Commitment, Constraint, Expression, Variable};
-use crate::contract::{Anchor, Contract, ContractID, PortableItem};
+use crate::contract::{Anchor, Contract, PortableItem};
 use crate::encoding::*;
 use crate::errors::VMError;
 use crate::fees::{fee_flavor, CheckedFee};
 use crate::ops::Instruction;
 use crate::predicate::{CallProof, Predicate};
+use crate::profile::Profile;
 use crate::program::ProgramItem;
 use crate::scalar_witness::ScalarWitness;
-use crate::tx::{TxEntry, TxHeader, TxID, TxLog};
+use crate::trace::{Trace, TraceStep};
+use crate::tx::{SigningMessage, TxEntry, TxHeader, TxID, TxIDBuilder, TxLog};
 use crate::types::*;
 
 /// Current tx version determines which extension opcodes are treated as noops (see VM.extension flag).
-pub const CURRENT_VERSION: u64 = 1;
+pub const CURRENT_VERSION: u64 = 4;
+
+/// Tx version since which `signid` and `signtag` statements with an empty signature
+/// are signed by the aggregated transaction signature.
+pub const AGGREGATED_SIGNATURES_VERSION: u64 = 2;
+
+/// Tx version since which the `select` instruction is enabled.
+/// Earlier versions fail on it, and future versions treat it as a no-op extension.
+pub const SELECT_VERSION: u64 = 3;
+
+/// Tx version since which the `burn` instruction is enabled.
+/// Earlier versions fail on it, and future versions treat it as a no-op extension.
+pub const BURN_VERSION: u64 = 3;
+
+/// Tx version since which the `eq` instruction produces a cleartext constraint
+/// when the variable terms of the compared expressions cancel out.
+pub const CLEARTEXT_FOLDING_VERSION: u64 = 3;
+
+/// Tx version since which a structurally identical secret constraint
+/// is added to the constraint system only once per transaction.
+pub const CONSTRAINT_DEDUP_VERSION: u64 = 3;
+
+/// Tx version since which the VM enforces `MAX_STACK_DEPTH`, `MAX_ITEM_SIZE` and `MAX_PAYLOAD_COUNT`.
+pub const RESOURCE_LIMITS_VERSION: u64 = 3;
+
+/// Tx version since which the quantities are proven to be in the 128-bit range instead of the 64-bit one.
+pub const WIDE_QUANTITY_VERSION: u64 = 4;
+
+/// Returns the range of the quantities in the transactions of a given version.
+pub fn quantity_range(version: u64) -> BitRange {
+    if version >= WIDE_QUANTITY_VERSION {
+        BitRange::wide()
+    } else {
+        BitRange::max()
+    }
+}
+
+/// Maximum number of items on the VM stack.
+pub const MAX_STACK_DEPTH: usize = 1024;
+
+/// Maximum size in bytes of a string or a program pushed on the stack.
+pub const MAX_ITEM_SIZE: usize = 64 * 1024;
+
+/// Maximum number of items in the contract payload.
+pub const MAX_PAYLOAD_COUNT: usize = 255;
 
 pub(crate) struct VM<'d, CS, D>
 where
@@ -32,10 +82,17 @@ where
     mintime_ms: u64,
     maxtime_ms: u64,
 
+    // version of the tx, enabling the instructions introduced in later versions
+    version: u64,
+
     // is true when tx version is in the future and
     // we allow treating unassigned opcodes as no-ops.
     extension: bool,
 
+    // is true when tx version permits signing `signid` and `signtag` statements
+    // with the aggregated transaction signature.
+    aggregated_signatures: bool,
+
     // updated by input/issue/contract/output instructions
     last_anchor: Option<Anchor>,
 
@@ -48,8 +105,20 @@ where
     run_stack: Vec<D::RunType>,
     txlog: TxLog,
 
+    // TxID computed incrementally as the entries are added to the txlog
+    txid_builder: TxIDBuilder,
+
     // collect the total fee to check for overflow
     total_fee: CheckedFee,
+
+    // structure of the secret constraints already added to the constraint system
+    verified_constraints: HashSet<Vec<u8>>,
+
+    // per-opcode counters, collected only when profiling is requested
+    profile: Option<Profile>,
+
+    // stack after each instruction, collected only when tracing is requested
+    trace: Option<Vec<TraceStep>>,
 }
 
 pub(crate) trait Delegate<CS: r1cs::RandomizableConstraintSystem> {
@@ -67,11 +136,8 @@ pub(crate) trait Delegate<CS: r1cs::RandomizableConstraintSystem> {
 
     /// Adds a key represented by Predicate to either verify or
     /// sign a transaction
-    fn process_tx_signature(
-        &mut self,
-        pred: Predicate,
-        contract_id: ContractID,
-    ) -> Result<(), VMError>;
+    fn process_tx_signature(&mut self, pred: Predicate, msg: SigningMessage)
+        -> Result<(), VMError>;
 
     /// Returns the delegate's underlying constraint system
     fn cs(&mut self) -> &mut CS;
@@ -86,7 +152,33 @@ pub(crate) trait Delegate<CS: r1cs::RandomizableConstraintSystem> {
     fn next_instruction(&mut self, run: &mut Self::RunType)
         -> Result<Option<Instruction>, VMError>;
 
-    fn new_run(&self, prog: ProgramItem) -> Result<Self::RunType, VMError>;
+    fn new_run(&mut self, prog: ProgramItem) -> Result<Self::RunType, VMError>;
+
+    /// Takes back the buffer of a consumed string or program for reuse.
+    /// Does nothing by default: the buffer is deallocated.
+    fn recycle_bytes(&mut self, _bytes: Vec<u8>) {}
+
+    /// Takes back a finished nested run for reuse.
+    /// Does nothing by default: the run is deallocated.
+    fn recycle_run(&mut self, _run: Self::RunType) {}
+
+    /// Checks the secret constraint against the witness data.
+    /// Does nothing by default: the constraint is enforced by the R1CS proof.
+    fn check_constraint(&mut self, _constraint: &Constraint) -> Result<(), VMError> {
+        Ok(())
+    }
+
+    /// Checks that the cloaked values are balanced and the outputs are in the given range,
+    /// using the witness data.
+    /// Does nothing by default: the balance and the ranges are enforced by the R1CS proof.
+    fn check_cloak(
+        &mut self,
+        _inputs: &[spacesuit::AllocatedValue],
+        _outputs: &[spacesuit::AllocatedValue],
+        _range: BitRange,
+    ) -> Result<(), VMError> {
+        Ok(())
+    }
 }
 
 impl<'d, CS, D> VM<'d, CS, D>
@@ -96,28 +188,74 @@ where
 {
     /// Instantiates a new VM instance.
     pub fn new(header: TxHeader, run: D::RunType, delegate: &'d mut D) -> Self {
-        VM {
+        let mut vm = VM {
             mintime_ms: header.mintime_ms,
             maxtime_ms: header.maxtime_ms,
+            version: header.version,
             extension: header.version > CURRENT_VERSION,
+            aggregated_signatures: header.version >= AGGREGATED_SIGNATURES_VERSION,
             last_anchor: None,
             delegate,
             stack: Vec::new(),
             current_run: run,
             run_stack: Vec::new(),
-            txlog: vec![TxEntry::Header(header)].into(),
+            txlog: Vec::new().into(),
+            txid_builder: TxIDBuilder::new(),
             total_fee: CheckedFee::zero(),
-        }
+            verified_constraints: HashSet::new(),
+            profile: None,
+            trace: None,
+        };
+        vm.append_log(TxEntry::Header(header));
+        vm
     }
 
     /// Runs through the entire program and nested programs until completion.
-    pub fn run(mut self) -> Result<(TxID, TxLog, CheckedFee), VMError> {
+    pub fn run(self) -> Result<(TxID, TxLog, CheckedFee), VMError> {
+        let (txid, txlog, fee, _) = self.execute()?;
+        Ok((txid, txlog, fee))
+    }
+
+    /// Runs the program like `run`, collecting the per-opcode execution profile.
+    pub fn run_with_profile(mut self) -> Result<(TxID, TxLog, CheckedFee, Profile), VMError> {
+        self.profile = Some(Profile::default());
+        let (txid, txlog, fee, profile) = self.execute()?;
+        Ok((txid, txlog, fee, profile.unwrap_or_default()))
+    }
+
+    /// Runs the program like `run`, recording the stack after each instruction.
+    /// The trace is returned even if the execution fails, together with the error.
+    pub fn run_with_trace(mut self) -> Trace {
+        self.trace = Some(Vec::new());
+        let error = loop {
+            match self.step() {
+                Ok(true) => continue,
+                Ok(false) => break self.check_finished().err(),
+                Err(err) => break Some(err),
+            }
+        };
+        Trace {
+            steps: self.trace.take().unwrap_or_default(),
+            error,
+        }
+    }
+
+    fn execute(mut self) -> Result<(TxID, TxLog, CheckedFee, Option<Profile>), VMError> {
         loop {
             if !self.step()? {
                 break;
             }
         }
 
+        self.check_finished()?;
+
+        let txid = self.txid_builder.txid();
+
+        Ok((txid, self.txlog, self.total_fee, self.profile))
+    }
+
+    /// Checks the state of the VM after all the programs finished.
+    fn check_finished(&self) -> Result<(), VMError> {
         if self.stack.len() > 0 {
             return Err(VMError::StackNotClean);
         }
@@ -125,17 +263,21 @@ where
         if self.last_anchor.is_none() {
             return Err(VMError::AnchorMissing);
         }
+        Ok(())
+    }
 
-        let txid = TxID::from_log(&self.txlog[..]);
-
-        Ok((txid, self.txlog, self.total_fee))
+    /// Adds an entry to the txlog and hashes it into the TxID.
+    fn append_log(&mut self, entry: TxEntry) {
+        self.txid_builder.append(&entry);
+        self.txlog.push(entry);
     }
 
     fn finish_run(&mut self) -> bool {
         // Do we have more programs to run?
         if let Some(run) = self.run_stack.pop() {
             // Continue with the previously remembered program
-            self.current_run = run;
+            let finished_run = mem::replace(&mut self.current_run, run);
+            self.delegate.recycle_run(finished_run);
             return true;
         }
         // Finish the execution
@@ -145,10 +287,20 @@ where
     /// Returns a flag indicating whether to continue the execution
     fn step(&mut self) -> Result<bool, VMError> {
         if let Some(instr) = self.delegate.next_instruction(&mut self.current_run)? {
+            let started = if self.profile.is_some() {
+                Some((instr.code(), self.delegate.cs().metrics(), Instant::now()))
+            } else {
+                None
+            };
+            let traced = if self.trace.is_some() {
+                Some((format!("{:?}", instr), self.run_stack.len()))
+            } else {
+                None
+            };
             // Attempt to read the next instruction and advance the program state
             match instr {
-                Instruction::Push(data) => self.pushdata(data),
-                Instruction::Program(prog) => self.pushprogram(prog),
+                Instruction::Push(data) => self.pushdata(data)?,
+                Instruction::Program(prog) => self.pushprogram(prog)?,
                 Instruction::Drop => self.drop()?,
                 Instruction::Dup(i) => self.dup(i)?,
                 Instruction::Roll(i) => self.roll(i)?,
@@ -171,11 +323,21 @@ where
                 Instruction::Issue => self.issue()?,
                 Instruction::Borrow => self.borrow()?,
                 Instruction::Retire => self.retire()?,
+                Instruction::Burn => {
+                    if self.enabled_since(BURN_VERSION)? {
+                        self.burn()?
+                    }
+                }
                 Instruction::Cloak(m, n) => self.cloak(m, n)?,
                 Instruction::Fee => self.fee()?,
                 Instruction::Input => self.input()?,
                 Instruction::Output(k) => self.output(k)?,
                 Instruction::Contract(k) => self.contract(k)?,
+                Instruction::Select(n, k) => {
+                    if self.enabled_since(SELECT_VERSION)? {
+                        self.select(n, k)?
+                    }
+                }
                 Instruction::Log => self.log()?,
                 Instruction::Eval => self.eval()?,
                 Instruction::Call => self.call()?,
@@ -184,6 +346,35 @@ where
                 Instruction::Signtag => self.signtag()?,
                 Instruction::Ext(opcode) => self.ext(opcode)?,
             }
+            if let Some((code, before, time)) = started {
+                let after = self.delegate.cs().metrics();
+                if let Some(profile) = self.profile.as_mut() {
+                    profile.record(
+                        code,
+                        after.multipliers - before.multipliers,
+                        after.constraints - before.constraints,
+                        time.elapsed(),
+                    );
+                }
+            }
+            if let Some((instruction, depth)) = traced {
+                let step = TraceStep {
+                    instruction,
+                    depth,
+                    stack: self
+                        .stack
+                        .iter()
+                        .map(|item| format!("{:?}", item))
+                        .collect(),
+                    log_len: self.txlog.len(),
+                };
+                if let Some(trace) = self.trace.as_mut() {
+                    trace.push(step);
+                }
+            }
+            if self.version >= RESOURCE_LIMITS_VERSION && self.stack.len() > MAX_STACK_DEPTH {
+                return Err(VMError::StackOverflow);
+            }
             return Ok(true);
         } else {
             // Reached the end of the current program
@@ -191,16 +382,28 @@ where
         }
     }
 
-    fn pushdata(&mut self, str: String) {
+    fn pushdata(&mut self, str: String) -> Result<(), VMError> {
+        if self.version >= RESOURCE_LIMITS_VERSION && str.encoded_size() > MAX_ITEM_SIZE {
+            return Err(VMError::ItemTooLarge);
+        }
         self.push_item(str);
+        Ok(())
     }
 
-    fn pushprogram(&mut self, prog: ProgramItem) {
+    fn pushprogram(&mut self, prog: ProgramItem) -> Result<(), VMError> {
+        if self.version >= RESOURCE_LIMITS_VERSION && prog.encoded_size() > MAX_ITEM_SIZE {
+            return Err(VMError::ItemTooLarge);
+        }
         self.push_item(prog);
+        Ok(())
     }
 
     fn drop(&mut self) -> Result<(), VMError> {
-        let _dropped = self.pop_item()?.to_droppable()?;
+        if let DroppableItem::Program(ProgramItem::Bytecode(data)) =
+            self.pop_item()?.to_droppable()?
+        {
+            self.delegate.recycle_bytes(data);
+        }
         Ok(())
     }
 
@@ -255,7 +458,11 @@ where
     fn eq(&mut self) -> Result<(), VMError> {
         let expr2 = self.pop_item()?.to_expression()?;
         let expr1 = self.pop_item()?.to_expression()?;
-        let constraint = Constraint::eq(expr1, expr2);
+        let constraint = if self.version >= CLEARTEXT_FOLDING_VERSION {
+            Constraint::eq_folded(expr1, expr2)
+        } else {
+            Constraint::eq(expr1, expr2)
+        };
         self.push_item(constraint);
         Ok(())
     }
@@ -292,6 +499,15 @@ where
 
     fn verify(&mut self) -> Result<(), VMError> {
         let constraint = self.pop_item()?.to_constraint()?;
+        // Skip the constraint if an identical one was already added within this tx.
+        if self.version >= CONSTRAINT_DEDUP_VERSION {
+            if let Some(key) = constraint.structural_key() {
+                if !self.verified_constraints.insert(key) {
+                    return Ok(());
+                }
+            }
+        }
+        self.delegate.check_constraint(&constraint)?;
         constraint.verify(self.delegate.cs())?;
         Ok(())
     }
@@ -308,7 +524,7 @@ where
         );
 
         // Push commitment item
-        self.push_item(String::Opaque(v_point.as_bytes().to_vec()));
+        self.push_item(String::Opaque(Bytes::copy_from_slice(v_point.as_bytes())));
         Ok(())
     }
 
@@ -348,7 +564,7 @@ where
 
     fn log(&mut self) -> Result<(), VMError> {
         let data = self.pop_item()?.to_string()?;
-        self.txlog.push(TxEntry::Data(data.to_bytes()));
+        self.append_log(TxEntry::Data(data.to_bytes()));
         Ok(())
     }
 
@@ -378,7 +594,7 @@ where
         let qty_expr = self.variable_to_expression(qty)?;
         self.add_range_proof(qty_expr)?;
 
-        self.txlog.push(TxEntry::Issue(qty_point, flv_point));
+        self.append_log(TxEntry::Issue(qty_point, flv_point));
 
         let payload = vec![PortableItem::Value(value)];
         let contract = self.make_contract(predicate, payload)?;
@@ -396,53 +612,64 @@ where
         let flv_assignment = flv.commitment.assignment().map(|sw| sw.to_scalar());
         let qty_assignment = ScalarWitness::option_to_integer(qty.commitment.assignment())?;
 
-        spacesuit::range_proof(
-            self.delegate.cs(),
-            qty_var.into(),
-            qty_assignment,
-            BitRange::max(),
-        )
-        .map_err(|_| VMError::R1CSInconsistency)?;
-
-        let neg_qty_assignment = qty_assignment.map(|q| -q);
-
-        let neg_qty_var = self
-            .delegate
-            .cs()
-            .allocate(neg_qty_assignment.map(|q| q.to_scalar()))
-            .map_err(|e| VMError::R1CSError(e))?;
-
-        self.delegate.cs().constrain(qty_var + neg_qty_var);
+        let borrowed = spacesuit::AllocatedValue {
+            q: qty_var,
+            f: flv_var,
+            assignment: match (qty_assignment, flv_assignment) {
+                (Some(q), Some(f)) => Some(spacesuit::Value { q, f }),
+                _ => None,
+            },
+        };
+        let debt = spacesuit::borrow(self.delegate.cs(), borrowed, quantity_range(self.version))
+            .map_err(|_| VMError::R1CSInconsistency)?;
 
         let value = Value {
             qty: qty.commitment.clone(),
             flv: flv.commitment,
         };
-        let wide_value = WideValue(spacesuit::AllocatedValue {
-            q: neg_qty_var,
-            f: flv_var,
-            assignment: match (neg_qty_assignment, flv_assignment) {
-                (Some(q), Some(f)) => Some(spacesuit::Value { q, f }),
-                _ => None,
-            },
-        });
-        self.push_item(wide_value);
+        self.push_item(WideValue(debt));
         self.push_item(value);
         Ok(())
     }
 
     fn retire(&mut self) -> Result<(), VMError> {
         let value = self.pop_item()?.to_value()?;
-        self.txlog
-            .push(TxEntry::Retire(value.qty.into(), value.flv.into()));
+        self.append_log(TxEntry::Retire(value.qty.into(), value.flv.into()));
+        Ok(())
+    }
+
+    /// _value qty data pred_ **burn** → _contract_
+    fn burn(&mut self) -> Result<(), VMError> {
+        let predicate = self.pop_item()?.to_string()?.to_predicate()?;
+        let metadata = self.pop_item()?.to_string()?;
+        let qty = self.pop_item()?.to_string()?.to_u64()?;
+        let value = self.pop_item()?.to_value()?;
+
+        let flv = Value::issue_flavor(&predicate, metadata);
+
+        // The value's commitments must open to the cleartext quantity and the issuer's flavor.
+        let (_, qty_var) = self.delegate.commit_variable(&value.qty)?;
+        let (_, flv_var) = self.delegate.commit_variable(&value.flv)?;
+        self.delegate.cs().constrain(qty_var - Scalar::from(qty));
+        self.delegate.cs().constrain(flv_var - flv);
+
+        self.append_log(TxEntry::Burn(qty, flv));
+
+        // The empty contract can only be unlocked by the issuer, which authorizes the burn.
+        let contract = self.make_contract(predicate, vec![])?;
+
+        self.push_item(contract);
         Ok(())
     }
 
     /// _input_ **input** → _contract_
     fn input(&mut self) -> Result<(), VMError> {
         let contract = self.pop_item()?.to_string()?.to_output()?;
+        if self.version >= RESOURCE_LIMITS_VERSION && contract.payload.len() > MAX_PAYLOAD_COUNT {
+            return Err(VMError::ItemTooLarge);
+        }
         let contract_id = contract.id();
-        self.txlog.push(TxEntry::Input(contract_id));
+        self.append_log(TxEntry::Input(contract_id));
         self.push_item(contract);
         self.last_anchor = Some(contract_id.to_anchor().ratchet());
         Ok(())
@@ -451,7 +678,7 @@ where
     /// _items... predicate_ **output:_k_** → ø
     fn output(&mut self, k: usize) -> Result<(), VMError> {
         let contract = self.pop_contract(k)?;
-        self.txlog.push(TxEntry::Output(contract));
+        self.append_log(TxEntry::Output(contract));
         Ok(())
     }
 
@@ -461,9 +688,37 @@ where
         Ok(())
     }
 
+    /// _contract pred(0) ... pred(n-1)_ **select:_n_:_k_** → _contract'_
+    fn select(&mut self, n: usize, k: usize) -> Result<(), VMError> {
+        if k >= n {
+            return Err(VMError::InvalidFormat);
+        }
+        if n > self.stack.len() {
+            return Err(VMError::StackUnderflow);
+        }
+        let predicates = self
+            .stack
+            .drain(self.stack.len() - n..)
+            .map(|item| item.to_string()?.to_predicate())
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut contract = self.pop_item()?.to_contract()?;
+        if contract.predicate != Predicate::disjunction(&predicates) {
+            return Err(VMError::InvalidDisjunction);
+        }
+        contract.predicate = predicates
+            .into_iter()
+            .nth(k)
+            .expect("k is less than the number of predicates");
+        self.push_item(contract);
+        Ok(())
+    }
+
     fn pop_contract(&mut self, k: usize) -> Result<Contract, VMError> {
         let predicate = self.pop_item()?.to_string()?.to_predicate()?;
 
+        if self.version >= RESOURCE_LIMITS_VERSION && k > MAX_PAYLOAD_COUNT {
+            return Err(VMError::ItemTooLarge);
+        }
         if k > self.stack.len() {
             return Err(VMError::StackUnderflow);
         }
@@ -520,7 +775,10 @@ where
             cloak_ins.insert(0, walue.0);
         }
 
-        spacesuit::cloak(self.delegate.cs(), cloak_ins, cloak_outs)
+        let range = quantity_range(self.version);
+        self.delegate.check_cloak(&cloak_ins, &cloak_outs, range)?;
+        let bitranges = vec![range; cloak_outs.len()];
+        spacesuit::cloak_with_bitranges(self.delegate.cs(), cloak_ins, cloak_outs, &bitranges)
             .map_err(|_| VMError::InvalidFormat)?;
 
         // Push in the same order.
@@ -551,7 +809,7 @@ where
 
         self.push_item(WideValue(av));
 
-        self.txlog.push(TxEntry::Fee(fee));
+        self.append_log(TxEntry::Fee(fee));
         Ok(())
     }
 
@@ -566,7 +824,7 @@ where
         let contract_id = contract.id();
 
         self.delegate
-            .process_tx_signature(contract.predicate, contract_id)?;
+            .process_tx_signature(contract.predicate, SigningMessage::Contract(contract_id))?;
         for item in contract.payload.into_iter() {
             self.push_item(item);
         }
@@ -585,7 +843,7 @@ where
     fn call(&mut self) -> Result<(), VMError> {
         // Pop program, call proof, and contract
         let program_item = self.pop_item()?.to_program()?;
-        let call_proof_bytes = self.pop_item()?.to_string()?.to_bytes();
+        let call_proof_bytes = self.pop_item()?.to_string()?.to_opaque();
         let call_proof = (&call_proof_bytes[..]).read_all(|r| CallProof::decode(r))?;
         let contract = self.pop_item()?.to_contract()?;
 
@@ -608,9 +866,7 @@ where
 
     fn signid(&mut self) -> Result<(), VMError> {
         // Signature
-        let sig = self.pop_item()?.to_string()?.to_bytes();
-        let signature = Signature::from_bytes((&sig[..]).read_all(|r| r.read_u8x64())?)
-            .map_err(|_| VMError::InvalidFormat)?;
+        let sig = self.pop_item()?.to_string()?.to_opaque();
 
         // Program
         let prog = self.pop_item()?.to_program()?;
@@ -623,14 +879,11 @@ where
             self.push_item(item);
         }
 
-        // Verification key from predicate
-        let verification_key = contract.predicate.verification_key();
-
-        // Verify signature using Verification key, over the message `program`
+        // Verify signature using the predicate, over the message `program`
         let mut t = Transcript::new(b"ZkVM.signid");
         t.append_message(b"contract", contract_id.as_ref());
         t.append_message(b"prog", &prog.to_bytes());
-        signature.verify_batched(&mut t, verification_key, self.delegate.batch_verifier());
+        self.process_statement_signature(sig, t, contract.predicate)?;
 
         // Replace current program with new program
         self.continue_with_program(prog)?;
@@ -639,9 +892,7 @@ where
 
     fn signtag(&mut self) -> Result<(), VMError> {
         // Signature
-        let sig = self.pop_item()?.to_string()?.to_bytes();
-        let signature = Signature::from_bytes((&sig[..]).read_all(|r| r.read_u8x64())?)
-            .map_err(|_| VMError::InvalidFormat)?;
+        let sig = self.pop_item()?.to_string()?.to_opaque();
 
         // Program
         let prog = self.pop_item()?.to_program()?;
@@ -658,20 +909,56 @@ where
         let tag = self.pop_item()?.to_string()?;
         self.push_item(tag.clone());
 
-        // Verification key from predicate
-        let verification_key = contract.predicate.verification_key();
-
-        // Verify signature using Verification key, over the message `program`
+        // Verify signature using the predicate, over the message `program`
         let mut t = Transcript::new(b"ZkVM.signtag");
-        t.append_message(b"tag", &tag.to_bytes());
+        t.append_message(b"tag", &tag.to_opaque());
         t.append_message(b"prog", &prog.to_bytes());
-        signature.verify_batched(&mut t, verification_key, self.delegate.batch_verifier());
+        self.process_statement_signature(sig, t, contract.predicate)?;
 
         // Replace current program with new program
         self.continue_with_program(prog)?;
         Ok(())
     }
 
+    /// Checks the signature of the `signid` or `signtag` statement committed to the transcript.
+    /// If the tx version permits, an empty signature defers the check
+    /// to the aggregated transaction signature over the statement's digest.
+    fn process_statement_signature(
+        &mut self,
+        sig: Bytes,
+        mut t: Transcript,
+        predicate: Predicate,
+    ) -> Result<(), VMError> {
+        if sig.is_empty() && self.aggregated_signatures {
+            let mut digest = [0u8; 32];
+            t.challenge_bytes(b"statement", &mut digest);
+            return self
+                .delegate
+                .process_tx_signature(predicate, SigningMessage::Statement(digest));
+        }
+        let signature = Signature::from_bytes((&sig[..]).read_all(|r| r.read_u8x64())?)
+            .map_err(|_| VMError::InvalidFormat)?;
+        signature.verify_batched(
+            &mut t,
+            predicate.verification_key(),
+            self.delegate.batch_verifier(),
+        );
+        Ok(())
+    }
+
+    /// Checks whether the instruction introduced in a given tx version is executed.
+    /// Returns `false` if the instruction is a no-op extension in a future tx version,
+    /// and fails if the instruction is not yet enabled in the tx version.
+    fn enabled_since(&self, version: u64) -> Result<bool, VMError> {
+        if self.extension {
+            Ok(false)
+        } else if self.version >= version {
+            Ok(true)
+        } else {
+            Err(VMError::ExtensionsNotAllowed)
+        }
+    }
+
     fn ext(&mut self, _: u8) -> Result<(), VMError> {
         if self.extension {
             // if extensions are allowed by tx version,
@@ -738,7 +1025,7 @@ where
     fn add_range_proof(&mut self, expr: Expression) -> Result<(), VMError> {
         match expr {
             Expression::Constant(x) => {
-                if x.in_range() {
+                if x.in_quantity_range(quantity_range(self.version)) {
                     Ok(())
                 } else {
                     Err(VMError::InvalidBitrange)
@@ -748,7 +1035,7 @@ where
                 self.delegate.cs(),
                 r1cs::LinearCombination::from_iter(terms),
                 ScalarWitness::option_to_integer(assignment)?,
-                BitRange::max(),
+                quantity_range(self.version),
             )
             .map_err(|_| VMError::R1CSInconsistency),
         }
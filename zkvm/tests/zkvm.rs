@@ -1,15 +1,22 @@
 use bulletproofs::BulletproofGens;
 use curve25519_dalek::scalar::Scalar;
 use curve25519_dalek::{
-    constants::RISTRETTO_BASEPOINT_COMPRESSED, ristretto::CompressedRistretto, traits::Identity,
+    constants::{RISTRETTO_BASEPOINT_COMPRESSED, RISTRETTO_BASEPOINT_POINT},
+    ristretto::CompressedRistretto,
+    traits::Identity,
 };
 use merlin::Transcript;
 use musig::{Multisignature, Signature};
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 use zkvm::{
-    Anchor, Commitment, Contract, PortableItem, Predicate, PredicateTree, Program, Prover, String,
-    TxHeader, TxID, TxLog, VMError, Value,
+    Anchor, Commitment, Contract, Opcode, PortableItem, Predicate, PredicateTree, Program,
+    ProgramItem, Prover, ProverContext, SealedContract, String, Tx, TxEntry, TxHeader, TxID, TxLog,
+    UnprovenTx, UnsignedTx, VMError, Value, Verifier, WitnessBundle, AGGREGATED_SIGNATURES_VERSION,
+    BURN_VERSION, CONSTRAINT_DEDUP_VERSION, CURRENT_VERSION, MAX_ITEM_SIZE, MAX_PAYLOAD_COUNT,
+    MAX_STACK_DEPTH, PROGRAM_BYTE_WEIGHT, RESOURCE_LIMITS_VERSION, SELECT_VERSION, TX_BASE_WEIGHT,
+    WIDE_QUANTITY_VERSION,
 };
 
 // TODO(vniu): move builder convenience functions into separate crate,
@@ -105,44 +112,7 @@ fn make_output(qty: u64, flv: Scalar, predicate: Predicate) -> Contract {
 }
 
 fn build_and_verify(program: Program) -> Result<(TxID, TxLog), VMError> {
-    let (txlog, tx) = {
-        // Build tx
-        let bp_gens = BulletproofGens::new(256, 1);
-        let header = TxHeader {
-            version: 0u64,
-            mintime_ms: 0u64,
-            maxtime_ms: 0u64,
-        };
-        let utx = Prover::build_tx(program, header, &bp_gens)?;
-
-        let sig = if utx.signing_instructions.len() == 0 {
-            Signature {
-                R: CompressedRistretto::identity(),
-                s: Scalar::zero(),
-            }
-        } else {
-            // find all the secret scalars for the pubkeys used in the VM
-            let privkeys: Vec<Scalar> = utx
-                .signing_instructions
-                .iter()
-                .map(|(predicate, _msg)| predicate_privkey(predicate))
-                .collect();
-
-            let mut signtx_transcript = Transcript::new(b"ZkVM.signtx");
-            signtx_transcript.append_message(b"txid", &utx.txid.0);
-            Signature::sign_multi(
-                privkeys,
-                utx.signing_instructions
-                    .iter()
-                    .map(|(p, m)| (p.verification_key(), m))
-                    .collect(),
-                &mut signtx_transcript,
-            )
-            .unwrap()
-        };
-
-        (utx.txlog.clone(), utx.sign(sig))
-    };
+    let (txlog, tx) = build_tx(program)?;
 
     // Verify tx
     let bp_gens = BulletproofGens::new(256, 1);
@@ -151,6 +121,63 @@ fn build_and_verify(program: Program) -> Result<(TxID, TxLog), VMError> {
     Ok((vtx.id, txlog))
 }
 
+fn build_and_verify_with_version(program: Program, version: u64) -> Result<(TxID, TxLog), VMError> {
+    let header = TxHeader {
+        version,
+        mintime_ms: 0u64,
+        maxtime_ms: 0u64,
+    };
+    let (txlog, tx) = build_tx_with_header(program, header)?;
+    let vtx = tx.verify(&BulletproofGens::new(256, 1))?;
+    Ok((vtx.id, txlog))
+}
+
+fn build_tx(program: Program) -> Result<(TxLog, Tx), VMError> {
+    let header = TxHeader {
+        version: 0u64,
+        mintime_ms: 0u64,
+        maxtime_ms: 0u64,
+    };
+    build_tx_with_header(program, header)
+}
+
+fn build_tx_with_header(program: Program, header: TxHeader) -> Result<(TxLog, Tx), VMError> {
+    // Build tx
+    let bp_gens = BulletproofGens::new(256, 1);
+    let utx = Prover::build_tx(program, header, &bp_gens)?;
+    Ok((utx.txlog.clone(), sign_tx(utx)))
+}
+
+fn sign_tx(utx: UnsignedTx) -> Tx {
+    let sig = if utx.signing_instructions.len() == 0 {
+        Signature {
+            R: CompressedRistretto::identity(),
+            s: Scalar::zero(),
+        }
+    } else {
+        // find all the secret scalars for the pubkeys used in the VM
+        let privkeys: Vec<Scalar> = utx
+            .signing_instructions
+            .iter()
+            .map(|(predicate, _msg)| predicate_privkey(predicate))
+            .collect();
+
+        let mut signtx_transcript = Transcript::new(b"ZkVM.signtx");
+        signtx_transcript.append_message(b"txid", &utx.txid.0);
+        Signature::sign_multi(
+            privkeys,
+            utx.signing_instructions
+                .iter()
+                .map(|(p, m)| (p.verification_key(), m))
+                .collect(),
+            &mut signtx_transcript,
+        )
+        .unwrap()
+    };
+
+    utx.sign(sig)
+}
+
 fn spend_1_1_contract(
     input: u64,
     output: u64,
@@ -198,6 +225,41 @@ fn spend_1_1() {
     }
 }
 
+#[test]
+fn tx_weight() {
+    let flavor = Scalar::from(1u64);
+    let program = spend_1_1_contract(
+        10u64,
+        10u64,
+        flavor,
+        generate_predicate(1),
+        generate_predicate(2),
+    );
+    let (_, tx) = build_tx(program).unwrap();
+    let weight = tx.weight().unwrap();
+    assert!(weight > TX_BASE_WEIGHT + PROGRAM_BYTE_WEIGHT * tx.program.len() as u64);
+
+    let bp_gens = BulletproofGens::new(256, 1);
+    let vtx = tx.verify(&bp_gens).unwrap();
+    assert_eq!(vtx.weight(), weight);
+    assert_eq!(vtx.feerate.size() as u64, weight);
+
+    // Dropped data adds only the weight of the program bytes.
+    let padded_program = Program::build(|p| {
+        p.push(String::Opaque(vec![0u8; 100].into()))
+            .drop()
+            .input_helper(10u64, flavor, generate_predicate(1))
+            .cloak_helper(1, vec![(10u64, flavor)])
+            .output_helper(generate_predicate(2));
+    });
+    let (_, padded_tx) = build_tx(padded_program).unwrap();
+    assert_eq!(padded_tx.program.len(), tx.program.len() + 1 + 4 + 100 + 1);
+    assert_eq!(
+        padded_tx.weight().unwrap(),
+        weight + PROGRAM_BYTE_WEIGHT * (1 + 4 + 100 + 1)
+    );
+}
+
 fn spend_1_2_contract(
     input: u64,
     output_1: u64,
@@ -395,6 +457,101 @@ fn spend_2_2() {
     }
 }
 
+#[test]
+fn estimated_verification_cost() {
+    let (_, flv) = make_flavor();
+    let program = spend_2_2_contract(
+        6,
+        4,
+        9,
+        1,
+        flv,
+        generate_predicate(1),
+        generate_predicate(2),
+        generate_predicate(3),
+        generate_predicate(4),
+    );
+    let (_, tx) = build_tx(program).unwrap();
+    let cost = tx.estimated_verification_cost().unwrap();
+
+    let bp_gens = BulletproofGens::new(cost.padded_multipliers(), 1);
+    assert!(tx.verify(&bp_gens).is_ok());
+
+    let bp_gens = BulletproofGens::new(cost.padded_multipliers() / 2, 1);
+    assert!(tx.verify(&bp_gens).is_err());
+}
+
+#[test]
+fn profile_counts_opcodes() {
+    let (issuance_pred, flavor) = make_flavor();
+    let program = issue_and_spend_contract(
+        4u64,
+        6u64,
+        9u64,
+        1u64,
+        flavor,
+        issuance_pred,
+        generate_predicate(1),
+        generate_predicate(2),
+        generate_predicate(3),
+    );
+    let header = TxHeader {
+        version: 0u64,
+        mintime_ms: 0u64,
+        maxtime_ms: 0u64,
+    };
+    let bp_gens = BulletproofGens::new(256, 1);
+    let (utx, profile) = Prover::build_tx_with_profile(program, header, &bp_gens).unwrap();
+
+    let output = profile.get(Opcode::Output).unwrap();
+    assert_eq!(output.count, 2);
+    assert_eq!(output.multipliers, 0);
+    let cloak = profile.get(Opcode::Cloak).unwrap();
+    assert_eq!(cloak.count, 1);
+    assert!(cloak.multipliers > 0);
+    assert!(profile.get(Opcode::Borrow).is_none());
+    assert_eq!(profile.hotspots()[0].0, Opcode::Cloak.to_u8());
+
+    // Verifier builds the same constraint system.
+    let tx = sign_tx(utx);
+    let (_, verifier_profile) = tx.precompute_with_profile().unwrap();
+    assert_eq!(
+        verifier_profile.total().multipliers,
+        profile.total().multipliers
+    );
+    assert_eq!(verifier_profile.total().count, profile.total().count);
+}
+
+#[test]
+fn trace_records_stack() {
+    let program = spend_1_1_contract(
+        10u64,
+        10u64,
+        Scalar::from(1u64),
+        generate_predicate(1),
+        generate_predicate(2),
+    );
+    let instructions = program.len();
+    let (_, tx) = build_tx(program).unwrap();
+
+    let trace = tx.trace();
+    assert!(trace.error.is_none());
+    assert_eq!(trace.steps.len(), instructions);
+    assert_eq!(trace.steps[0].stack.len(), 1);
+    assert_eq!(trace.steps[1].instruction, "input");
+    let last = trace.steps.last().unwrap();
+    assert!(last.instruction.starts_with("output"));
+    assert!(last.stack.is_empty());
+    assert_eq!(last.log_len, tx.precompute().unwrap().log.len());
+
+    // The trace stops at the failed instruction.
+    let mut tx = tx;
+    tx.program.truncate(tx.program.len() - 1);
+    let trace = tx.trace();
+    assert!(trace.error.is_some());
+    assert!(trace.steps.len() < instructions);
+}
+
 fn issue_and_spend_contract(
     issue_qty: u64,
     input_qty: u64,
@@ -457,6 +614,57 @@ fn issue_and_spend() {
     }
 }
 
+fn burn_contract(
+    input_qty: u64,
+    burn_qty: u64,
+    flv: Scalar,
+    issuance_pred: Predicate,
+    input_pred: Predicate,
+) -> Program {
+    Program::build(|p| {
+        p.input_helper(input_qty, flv, input_pred) // stack: input-val
+            .push(String::U64(burn_qty)) // stack: input-val, qty
+            .push(String::default()) // stack: input-val, qty, data
+            .push(issuance_pred) // stack: input-val, qty, data, pred
+            .burn() // stack: burn-contract
+            .signtx(); // stack: empty
+    })
+}
+
+#[test]
+fn burn_with_cleartext_qty() {
+    let pred1 = generate_predicate(1);
+    let (issuance_pred, flavor) = make_flavor();
+
+    let correct_program = burn_contract(6u64, 6u64, flavor, issuance_pred.clone(), pred1.clone());
+    let (_, txlog) = build_and_verify_with_version(correct_program.clone(), BURN_VERSION).unwrap();
+    assert!(txlog.iter().any(|entry| match entry {
+        TxEntry::Burn(qty, flv) => *qty == 6u64 && *flv == flavor,
+        _ => false,
+    }));
+
+    // Earlier tx versions do not know the instruction.
+    assert_eq!(
+        build_and_verify_with_version(correct_program.clone(), BURN_VERSION - 1).unwrap_err(),
+        VMError::ExtensionsNotAllowed
+    );
+    // Future tx versions treat it as a no-op, leaving the operands on the stack.
+    assert_eq!(
+        build_and_verify_with_version(correct_program, CURRENT_VERSION + 1).unwrap_err(),
+        VMError::TypeNotContract
+    );
+
+    let wrong_qty = burn_contract(6u64, 5u64, flavor, issuance_pred.clone(), pred1.clone());
+    if build_and_verify_with_version(wrong_qty, BURN_VERSION).is_ok() {
+        panic!("Burning $5 out of $6 should have failed but didn't");
+    }
+
+    let wrong_flavor = burn_contract(6u64, 6u64, Scalar::from(1u64), issuance_pred, pred1);
+    if build_and_verify_with_version(wrong_flavor, BURN_VERSION).is_ok() {
+        panic!("Burning a value of another flavor should have failed but didn't");
+    }
+}
+
 /// Program that spends an input on the stack unlocked with knowledge of a secret Scalar.
 fn spend_with_secret_scalar(qty: u64, flavor: Scalar, pred: Predicate, secret: Scalar) -> Program {
     Program::build(|p| {
@@ -486,6 +694,126 @@ fn taproot_happy_path() {
     build_and_verify(prog).unwrap();
 }
 
+#[test]
+fn prove_from_witness_bundle() {
+    let pred_tree = PredicateTree::new(Some(generate_predicate(1)), vec![], [0u8; 32]).unwrap();
+    let prev_output = make_output(101u64, Scalar::from(1u64), Predicate::tree(pred_tree));
+    let prog = Program::build(|p| {
+        p.push(prev_output)
+            .input()
+            .signtx()
+            .push(generate_predicate(2))
+            .output(1);
+    });
+    let header = TxHeader {
+        version: 0u64,
+        mintime_ms: 0u64,
+        maxtime_ms: 0u64,
+    };
+
+    // Bundle is transferred to the prover without the signing keys.
+    let bundle = WitnessBundle::new(prog, header);
+    assert_eq!(bundle.predicate_trees.len(), 1);
+    let json = serde_json::to_vec(&bundle).unwrap();
+    let bundle: WitnessBundle = serde_json::from_slice(&json).unwrap();
+
+    let bp_gens = BulletproofGens::new(256, 1);
+    let utx = Prover::build_tx_from_bundle(bundle, &bp_gens).unwrap();
+
+    // Signer uses the re-attached tree to adjust the key.
+    let (pred, contract_id) = &utx.signing_instructions[0];
+    let tree = pred.verification_key_witness::<PredicateTree>().unwrap();
+    assert!(tree
+        .inner_predicate()
+        .verification_key_witness::<Scalar>()
+        .is_none());
+    let privkey = Scalar::from(1u64) + tree.adjustment_factor();
+
+    let mut signtx_transcript = Transcript::new(b"ZkVM.signtx");
+    signtx_transcript.append_message(b"txid", &utx.txid.0);
+    let sig = Signature::sign_multi(
+        vec![privkey],
+        vec![(pred.verification_key(), contract_id)],
+        &mut signtx_transcript,
+    )
+    .unwrap();
+    utx.sign(sig).verify(&bp_gens).unwrap();
+}
+
+#[test]
+fn unproven_tx_lifecycle() {
+    let (_, flv) = make_flavor();
+    let program = spend_1_1_contract(10, 10, flv, generate_predicate(1), generate_predicate(2));
+    let header = TxHeader {
+        version: 0u64,
+        mintime_ms: 0u64,
+        maxtime_ms: 0u64,
+    };
+    let unproven = UnprovenTx::new(header, program);
+
+    // Witnesses survive serialization, except for the signing keys.
+    let json = serde_json::to_vec(&unproven).unwrap();
+    let unproven: UnprovenTx = serde_json::from_slice(&json).unwrap();
+
+    let bp_gens = BulletproofGens::new(256, 1);
+    let utx = unproven.prove(&bp_gens).unwrap();
+    let (pred, msg) = &utx.signing_instructions[0];
+    assert!(pred.verification_key_witness::<Scalar>().is_none());
+
+    // Signer supplies the key separately.
+    let sig = Signature::sign_multi(
+        vec![Scalar::from(1u64)],
+        vec![(pred.verification_key(), msg)],
+        &mut utx.signing_transcript(),
+    )
+    .unwrap();
+    utx.sign(sig).verify(&bp_gens).unwrap();
+}
+
+#[test]
+fn deterministic_build() {
+    // All the random draws go through the caller's RNG,
+    // so the same seed produces the same program and the same transaction.
+    let build = |seed: u64| {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (qty, flavor) = (101u64, Scalar::from(1u64));
+        let spend_prog = Program::build(|p| {
+            p.drop();
+        });
+        let tree =
+            PredicateTree::new(Some(generate_predicate(1)), vec![spend_prog], rng.gen()).unwrap();
+        let prev_output = Contract {
+            predicate: Predicate::tree(tree),
+            payload: vec![PortableItem::Value(Value {
+                qty: Commitment::blinded_with_rng(qty, &mut rng),
+                flv: Commitment::blinded_with_rng(flavor, &mut rng),
+            })],
+            anchor: Anchor::from_raw_bytes([0u8; 32]),
+        };
+        let program = Program::build(|p| {
+            p.push(prev_output)
+                .input()
+                .signtx()
+                .push(Commitment::blinded_with_rng(qty, &mut rng))
+                .push(Commitment::blinded_with_rng(flavor, &mut rng))
+                .cloak(1, 1)
+                .push(generate_predicate(2))
+                .output(1);
+        });
+        let header = TxHeader {
+            version: 0u64,
+            mintime_ms: 0u64,
+            maxtime_ms: 0u64,
+        };
+        Prover::build_tx(program, header, &BulletproofGens::new(256, 1)).unwrap()
+    };
+
+    let (utx1, utx2) = (build(1), build(1));
+    assert_eq!(utx1.program, utx2.program);
+    assert_eq!(utx1.txid, utx2.txid);
+    assert_ne!(utx1.txid, build(2).txid);
+}
+
 #[test]
 fn taproot_program_path() {
     let (qty, flavor) = (101u64, Scalar::from(1u64));
@@ -503,7 +831,7 @@ fn taproot_program_path() {
         p.push(secret_scalar)
             .push(prev_output.clone())
             .input()
-            .push(String::Opaque(call_proof.to_bytes().clone()))
+            .push(String::Opaque(call_proof.to_bytes().into()))
             .program(call_prog.clone())
             .call();
     });
@@ -513,7 +841,7 @@ fn taproot_program_path() {
         p.push(secret_scalar + Scalar::one())
             .push(prev_output.clone())
             .input()
-            .push(String::Opaque(call_proof.to_bytes().clone()))
+            .push(String::Opaque(call_proof.to_bytes().into()))
             .program(call_prog)
             .call();
     });
@@ -522,6 +850,107 @@ fn taproot_program_path() {
     }
 }
 
+#[test]
+fn timelocked_program_path() {
+    let (qty, flavor) = (101u64, Scalar::from(1u64));
+    let key_pred = generate_predicate(1);
+    let output_pred = generate_predicate(2);
+    let unlock_prog = Program::build(|p| {
+        p.lock_until(100)
+            .expire_after(200)
+            .require_signature(key_pred.clone(), 1);
+    });
+
+    let blinding_key = rand::thread_rng().gen::<[u8; 32]>();
+    let tree =
+        PredicateTree::new(Some(generate_predicate(3)), vec![unlock_prog], blinding_key).unwrap();
+    let (call_proof, call_prog) = tree.create_callproof(0).unwrap();
+    let prev_output = make_output(qty, flavor, Predicate::tree(tree));
+
+    let prog = Program::build(|p| {
+        p.push(prev_output.clone())
+            .input()
+            .push(String::Opaque(call_proof.to_bytes().into()))
+            .program(call_prog.clone())
+            .call()
+            .push(output_pred.clone())
+            .output(1);
+    });
+    let verify_at = |mintime_ms, maxtime_ms| {
+        let header = TxHeader {
+            version: 0u64,
+            mintime_ms,
+            maxtime_ms,
+        };
+        let (_, tx) = build_tx_with_header(prog.clone(), header)?;
+        tx.verify(&BulletproofGens::new(256, 1))
+    };
+
+    assert!(verify_at(150, 160).is_ok());
+    assert!(
+        verify_at(50, 160).is_err(),
+        "Spending before the lock time should have failed but didn't"
+    );
+    assert!(
+        verify_at(150, 250).is_err(),
+        "Spending after the expiration time should have failed but didn't"
+    );
+}
+
+#[test]
+fn refundable_payment() {
+    let (qty, flavor) = (101u64, Scalar::from(1u64));
+    let output_pred = generate_predicate(3);
+    let blinding_key = rand::thread_rng().gen::<[u8; 32]>();
+    let tree = PredicateTree::refundable(
+        generate_predicate(1),
+        generate_predicate(2),
+        100,
+        blinding_key,
+    )
+    .unwrap();
+    let prev_output = make_output(qty, flavor, Predicate::tree(tree.clone()));
+
+    // Receiver spends the payment with the adjusted key at any time.
+    let pay_prog = Program::build(|p| {
+        p.push(prev_output.clone())
+            .input()
+            .signtx()
+            .push(output_pred.clone())
+            .output(1);
+    });
+    let header = TxHeader {
+        version: 0u64,
+        mintime_ms: 50u64,
+        maxtime_ms: 60u64,
+    };
+    let (_, tx) = build_tx_with_header(pay_prog, header).unwrap();
+    tx.verify(&BulletproofGens::new(256, 1)).unwrap();
+
+    // Sender takes the payment back after the refund time.
+    let refund_prog = Program::build(|p| {
+        p.push(prev_output.clone()).input();
+        p.choose_call(tree.clone(), 0)
+            .unwrap()
+            .push(output_pred.clone())
+            .output(1);
+    });
+    let verify_at = |mintime_ms| {
+        let header = TxHeader {
+            version: 0u64,
+            mintime_ms,
+            maxtime_ms: mintime_ms + 10,
+        };
+        let (_, tx) = build_tx_with_header(refund_prog.clone(), header)?;
+        tx.verify(&BulletproofGens::new(256, 1))
+    };
+    assert!(verify_at(150).is_ok());
+    assert!(
+        verify_at(50).is_err(),
+        "Refund before the refund time should have failed but didn't"
+    );
+}
+
 #[test]
 fn programs_cannot_be_copied() {
     let prog = Program::build(|p| {
@@ -565,6 +994,159 @@ fn constraints_cannot_be_copied() {
     );
 }
 
+#[test]
+fn prover_context_reuses_generators() {
+    // Context starts with too few generators and grows them to fit the program.
+    let mut ctx = ProverContext::new(1);
+    let header = TxHeader {
+        version: 0u64,
+        mintime_ms: 0u64,
+        maxtime_ms: 0u64,
+    };
+    for i in 0..2 {
+        let program = spend_1_1_contract(
+            10u64,
+            10u64,
+            Scalar::from(1u64),
+            generate_predicate(1 + i),
+            generate_predicate(3 + i),
+        );
+        let tx = sign_tx(ctx.build_tx(program, header).unwrap());
+        tx.verify(ctx.bp_gens()).expect("should verify");
+    }
+    assert!(ctx.bp_gens().gens_capacity >= 64);
+}
+
+#[test]
+fn prover_context_grows_generators_for_stored_programs() {
+    // The range proofs are made by the program stored in the input contract,
+    // so the estimated circuit size of the tx program does not account for them.
+    let stored_prog = Program::build(|p| {
+        for i in 0..3u64 {
+            p.push(Commitment::blinded(i))
+                .commit()
+                .expr()
+                .range()
+                .drop();
+        }
+    });
+    let prev_output = Contract {
+        predicate: generate_predicate(1),
+        payload: vec![PortableItem::Program(ProgramItem::Program(stored_prog))],
+        anchor: Anchor::from_raw_bytes([0u8; 32]),
+    };
+    let program = Program::build(|p| {
+        p.push(prev_output).input().signtx().eval();
+    });
+    assert_eq!(program.estimated_circuit_size().multipliers, 0);
+
+    let mut ctx = ProverContext::new(1);
+    let header = TxHeader {
+        version: 0u64,
+        mintime_ms: 0u64,
+        maxtime_ms: 0u64,
+    };
+    let tx = sign_tx(ctx.build_tx(program, header).unwrap());
+    tx.verify(ctx.bp_gens()).expect("should verify");
+    assert!(ctx.bp_gens().gens_capacity >= 3 * 64);
+}
+
+#[test]
+fn stack_depth_is_limited() {
+    let prog = Program::build(|p| {
+        p.push(String::from(Scalar::from(1u64)));
+        for _ in 0..MAX_STACK_DEPTH {
+            p.dup(0);
+        }
+    });
+
+    assert_eq!(
+        build_and_verify_with_version(prog.clone(), RESOURCE_LIMITS_VERSION).unwrap_err(),
+        VMError::StackOverflow
+    );
+    // Earlier tx versions are not limited.
+    assert_ne!(
+        build_and_verify_with_version(prog, RESOURCE_LIMITS_VERSION - 1).unwrap_err(),
+        VMError::StackOverflow
+    );
+}
+
+#[test]
+fn item_size_is_limited() {
+    let prog = Program::build(|p| {
+        p.push(String::Opaque(vec![0u8; MAX_ITEM_SIZE + 1].into()));
+    });
+
+    assert_eq!(
+        build_and_verify_with_version(prog.clone(), RESOURCE_LIMITS_VERSION).unwrap_err(),
+        VMError::ItemTooLarge
+    );
+    assert_ne!(
+        build_and_verify_with_version(prog, RESOURCE_LIMITS_VERSION - 1).unwrap_err(),
+        VMError::ItemTooLarge
+    );
+
+    let prog = Program::build(|p| {
+        p.push(generate_predicate(1)).output(MAX_PAYLOAD_COUNT + 1);
+    });
+
+    assert_eq!(
+        build_and_verify_with_version(prog.clone(), RESOURCE_LIMITS_VERSION).unwrap_err(),
+        VMError::ItemTooLarge
+    );
+    assert_ne!(
+        build_and_verify_with_version(prog, RESOURCE_LIMITS_VERSION - 1).unwrap_err(),
+        VMError::ItemTooLarge
+    );
+}
+
+fn select_program(selected: usize, preds: Vec<Predicate>) -> Program {
+    let flv = Scalar::from(1u64);
+    let disjunction = Predicate::disjunction(&preds);
+    Program::build(|p| {
+        p.push(make_output(5, flv, disjunction)).input();
+        for pred in preds.iter() {
+            p.push(pred.clone());
+        }
+        p.select(preds.len(), selected)
+            .signtx()
+            .output_helper(generate_predicate(3));
+    })
+}
+
+#[test]
+fn select_predicate_from_disjunction() {
+    let preds = vec![generate_predicate(1), generate_predicate(2)];
+    build_and_verify_with_version(select_program(1, preds.clone()), SELECT_VERSION)
+        .expect("should succeed");
+    build_and_verify_with_version(select_program(0, preds.clone()), SELECT_VERSION)
+        .expect("should succeed");
+
+    // Earlier tx versions do not know the instruction.
+    assert_eq!(
+        build_and_verify_with_version(select_program(0, preds), SELECT_VERSION - 1).unwrap_err(),
+        VMError::ExtensionsNotAllowed
+    );
+
+    // Disjunction does not match the predicates on the stack.
+    let prog = Program::build(|p| {
+        p.push(make_output(
+            5,
+            Scalar::from(1u64),
+            Predicate::disjunction(&[generate_predicate(1)]),
+        ))
+        .input()
+        .push(generate_predicate(2))
+        .select(1, 0)
+        .signtx()
+        .output_helper(generate_predicate(3));
+    });
+    assert_eq!(
+        build_and_verify_with_version(prog, SELECT_VERSION).unwrap_err(),
+        VMError::InvalidDisjunction
+    );
+}
+
 #[test]
 fn eval_test() {
     let pred = generate_predicate(1);
@@ -593,6 +1175,96 @@ fn eval_test() {
     build_and_verify(prog).expect("should succeed");
 }
 
+#[test]
+fn duplicate_constraints_are_verified_once() {
+    let pred = generate_predicate(1);
+    let program = |x: u64| {
+        Program::build(|p| {
+            p.push(Commitment::blinded(5u64)).commit();
+            // the same constraint is verified twice, but added to the constraint system once
+            for _ in 0..2 {
+                p.dup(0)
+                    .expr()
+                    .push(String::from(Scalar::from(x)))
+                    .scalar()
+                    .eq()
+                    .verify();
+            }
+            p.drop();
+
+            // to make program finish we need to spend a dummy input
+            p.input_helper(0, Scalar::zero(), pred.clone());
+            p.output_helper(pred.clone());
+        })
+    };
+
+    // Earlier tx versions add both copies of the constraint.
+    for version in &[CONSTRAINT_DEDUP_VERSION - 1, CONSTRAINT_DEDUP_VERSION] {
+        build_and_verify_with_version(program(5), *version).expect("should succeed");
+        assert!(build_and_verify_with_version(program(6), *version).is_err());
+    }
+}
+
+#[test]
+fn verify_program_only() {
+    let header = TxHeader {
+        version: 0u64,
+        mintime_ms: 0u64,
+        maxtime_ms: 0u64,
+    };
+    let pred1 = generate_predicate(1);
+    let pred2 = generate_predicate(2);
+    let flavor = Scalar::from(1u64);
+
+    // simulation produces the same txid as the proven transaction
+    let program = spend_1_1_contract(10u64, 10u64, flavor, pred1.clone(), pred2.clone());
+    let (txid, _) = build_and_verify(program.clone()).unwrap();
+    let stx = Verifier::verify_program_only(program, header).unwrap();
+    assert_eq!(stx.id, txid);
+    assert_eq!(stx.signing_keys, vec![pred1.verification_key()]);
+
+    let unbalanced = spend_1_1_contract(5u64, 10u64, flavor, pred1.clone(), pred2.clone());
+    assert_eq!(
+        Verifier::verify_program_only(unbalanced, header).unwrap_err(),
+        VMError::UnbalancedCloak
+    );
+
+    let secret_constraint = |x: u64| {
+        Program::build(|p| {
+            p.push(Commitment::blinded(5u64))
+                .commit()
+                .expr()
+                .push(String::from(Scalar::from(x)))
+                .scalar()
+                .eq()
+                .verify();
+            p.input_helper(0, Scalar::zero(), pred1.clone());
+            p.output_helper(pred1.clone());
+        })
+    };
+    assert!(Verifier::verify_program_only(secret_constraint(5), header).is_ok());
+    assert_eq!(
+        Verifier::verify_program_only(secret_constraint(6), header).unwrap_err(),
+        VMError::SecretConstraintFalse
+    );
+
+    let closed = Program::build(|p| {
+        p.push(Commitment::Closed(Commitment::blinded(5u64).to_point()))
+            .commit()
+            .expr()
+            .push(String::from(Scalar::from(5u64)))
+            .scalar()
+            .eq()
+            .verify();
+        p.input_helper(0, Scalar::zero(), pred1.clone());
+        p.output_helper(pred1.clone());
+    });
+    assert_eq!(
+        Verifier::verify_program_only(closed, header).unwrap_err(),
+        VMError::WitnessMissing
+    );
+}
+
 #[test]
 fn borrow_output() {
     //inputs 10 units, borrows 5 units, outputs two (5 units)
@@ -613,3 +1285,195 @@ fn borrow_output() {
     });
     build_and_verify(borrow_prog).unwrap();
 }
+
+fn signid_and_signtx_program(delegate_key: Scalar) -> Program {
+    let flv = Scalar::from(1u64);
+    let prev_output = make_output(10, flv, generate_predicate(1));
+    let delegated_prog = Program::build(|p| {
+        p.cloak_helper(2, vec![(15u64, flv)])
+            .output_helper(generate_predicate(2));
+    });
+
+    let mut t = Transcript::new(b"ZkVM.signid");
+    t.append_message(b"contract", prev_output.id().as_ref());
+    t.append_message(b"prog", &delegated_prog.to_bytes());
+    let sig = Signature::sign(&mut t, delegate_key);
+
+    Program::build(|p| {
+        p.input_helper(5, flv, generate_predicate(3)) // stack: Value(5,1)
+            .push(prev_output) // stack: Value(5,1), input-data
+            .input() // stack: Value(5,1), input-contract
+            .program(delegated_prog) // stack: Value(5,1), input-contract, prog
+            .push(String::Opaque(sig.to_bytes().to_vec().into())) // stack: Value(5,1), input-contract, prog, sig
+            .signid(); // stack: Value(5,1), Value(10,1); outputs (15,1) via delegated program
+    })
+}
+
+#[test]
+fn signid_batched_with_signtx() {
+    build_and_verify(signid_and_signtx_program(Scalar::from(1u64))).expect("should succeed");
+
+    // Invalid delegate signature is caught by the tx-wide batch verification.
+    assert_eq!(
+        build_and_verify(signid_and_signtx_program(Scalar::from(42u64))).map(|_| ()),
+        Err(VMError::BatchSignatureVerificationFailed)
+    );
+}
+
+#[test]
+fn signid_aggregated_into_tx_signature() {
+    let flv = Scalar::from(1u64);
+    let prev_output = make_output(10, flv, generate_predicate(1));
+    let delegated_prog = Program::build(|p| {
+        p.cloak_helper(2, vec![(15u64, flv)])
+            .output_helper(generate_predicate(2));
+    });
+    // Empty signature string defers the check to the transaction signature.
+    let prog = Program::build(|p| {
+        p.input_helper(5, flv, generate_predicate(3))
+            .push(prev_output)
+            .input()
+            .program(delegated_prog)
+            .push(String::Opaque(Vec::new().into()))
+            .signid();
+    });
+    let header = |version| TxHeader {
+        version,
+        mintime_ms: 0u64,
+        maxtime_ms: 0u64,
+    };
+    let bp_gens = BulletproofGens::new(256, 1);
+
+    let utx = Prover::build_tx(
+        prog.clone(),
+        header(AGGREGATED_SIGNATURES_VERSION),
+        &bp_gens,
+    )
+    .unwrap();
+    // Both the `signtx` and the `signid` keys are signed by a single signature.
+    assert_eq!(utx.signing_instructions.len(), 2);
+    sign_tx(utx).verify(&bp_gens).expect("should succeed");
+
+    // Older tx versions require the standalone signature.
+    assert_eq!(
+        build_tx_with_header(prog, header(1)).map(|_| ()),
+        Err(VMError::InvalidFormat)
+    );
+}
+
+#[test]
+fn sealed_contract_roundtrip() {
+    let flv = Scalar::from(1u64);
+    let output = make_output(10, flv, generate_predicate(1));
+    let sealed = SealedContract {
+        predicate: output.predicate.clone(),
+        payload: output.payload.clone(),
+        sealed_data: vec![b"invoice #42".to_vec(), Vec::new()],
+    };
+    let deckey = Scalar::from(24u64);
+    let enckey = deckey * RISTRETTO_BASEPOINT_POINT;
+    let contract = sealed.seal(&enckey, output.anchor, &mut rand::thread_rng());
+
+    // Only ciphertext is published in the payload.
+    assert_eq!(contract.payload.len(), 3);
+    assert_eq!(contract.payload[0], output.payload[0]);
+
+    assert_eq!(SealedContract::unseal(&contract, &deckey), Some(sealed));
+    assert_eq!(
+        SealedContract::unseal(&contract, &Scalar::from(25u64)),
+        None
+    );
+    assert_eq!(SealedContract::unseal(&output, &deckey), None);
+
+    // Sealed items cannot be transplanted to a contract with another predicate.
+    let mut transplanted = contract.clone();
+    transplanted.predicate = generate_predicate(2);
+    assert_eq!(SealedContract::unseal(&transplanted, &deckey), None);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json_roundtrip() {
+    let (issuance_pred, flv) = make_flavor();
+    let program = issue_and_spend_contract(
+        4,
+        6,
+        9,
+        1,
+        flv,
+        issuance_pred,
+        generate_predicate(1),
+        generate_predicate(2),
+        generate_predicate(3),
+    );
+    let (txlog, tx) = build_tx(program).unwrap();
+
+    let tx_json = tx.to_json();
+    assert_eq!(tx_json["header"]["version"], 0);
+    assert_eq!(tx_json["program"], hex::encode(&tx.program));
+    assert_eq!(Tx::from_json(&tx_json).unwrap().to_bytes(), tx.to_bytes());
+
+    let txlog_json = txlog.to_json();
+    assert_eq!(txlog_json[0]["type"], "input");
+    assert_eq!(txlog_json[1]["type"], "issue");
+    assert_eq!(txlog_json[2]["type"], "output");
+    assert_eq!(TxLog::from_json(&txlog_json).unwrap().to_json(), txlog_json);
+
+    // Output contracts must match their declared IDs.
+    let mut tampered = txlog_json.clone();
+    tampered[2]["anchor"] = hex::encode([0u8; 32]).into();
+    assert!(TxLog::from_json(&tampered).is_err());
+}
+
+#[test]
+fn wide_quantities() {
+    use spacesuit::SignedInteger;
+    use zkvm::ScalarWitness;
+
+    let wide = |qty: u128| ScalarWitness::Integer(SignedInteger::from_u128(qty).unwrap());
+    let flavor = Scalar::from(1u64);
+    let wide_spend = |input: u128, output_1: u128, output_2: u64| {
+        let prev_output = Contract {
+            predicate: generate_predicate(1),
+            payload: vec![PortableItem::Value(Value {
+                qty: Commitment::blinded(wide(input)),
+                flv: Commitment::blinded(flavor),
+            })],
+            anchor: Anchor::from_raw_bytes([0u8; 32]),
+        };
+        Program::build(|p| {
+            p.push(prev_output)
+                .input()
+                .signtx()
+                .push(Commitment::blinded(wide(output_1)))
+                .push(Commitment::blinded(flavor))
+                .push(Commitment::blinded(output_2))
+                .push(Commitment::blinded(flavor))
+                .cloak(1, 2)
+                .output_helper(generate_predicate(2))
+                .output_helper(generate_predicate(3));
+        })
+    };
+    let build_and_verify_at = |program: Program, version: u64| {
+        let header = TxHeader {
+            version,
+            mintime_ms: 0u64,
+            maxtime_ms: 0u64,
+        };
+        // The 128-bit range proofs need twice as many generators.
+        let bp_gens = BulletproofGens::new(512, 1);
+        let utx = Prover::build_tx(program, header, &bp_gens)?;
+        sign_tx(utx).verify(&bp_gens)
+    };
+
+    // 2^64 + 5 is split into 2^64 and 5.
+    let program = wide_spend((1u128 << 64) + 5, 1u128 << 64, 5);
+    assert!(build_and_verify_at(program.clone(), WIDE_QUANTITY_VERSION).is_ok());
+
+    // Earlier versions prove the quantities in the 64-bit range.
+    assert!(build_and_verify_at(program, WIDE_QUANTITY_VERSION - 1).is_err());
+
+    // Quantities beyond 2^64 are balanced exactly.
+    let program = wide_spend((1u128 << 64) + 5, (1u128 << 64) + 1, 5);
+    assert!(build_and_verify_at(program, WIDE_QUANTITY_VERSION).is_err());
+}